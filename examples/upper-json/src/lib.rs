@@ -0,0 +1,94 @@
+//! A motya filter uppercasing the strings of a JSON request body, keys and values alike.
+//!
+//! Build it with `cargo build --release --target wasm32-wasip2`, then load
+//! `target/wasm32-wasip2/release/upper_json.wasm` as a plugin and give the filter access
+//! to the body:
+//!
+//! ```kdl
+//! definitions {
+//!     plugins {
+//!         plugin {
+//!             name "shout"
+//!             load path="./upper_json.wasm"
+//!         }
+//!     }
+//!     modifiers {
+//!         chain-filters "api" {
+//!             filter name="shout.upper-json" request-body=#true max-body-size="16KB"
+//!         }
+//!     }
+//! }
+//! ```
+
+wit_bindgen::generate!({
+    world: "app",
+    path: "../../source/motya/wit",
+});
+
+use exports::motya::proxy::filter_factory::{
+    Config, FilterInstance, FilterType, Guest, GuestFilterInstance,
+};
+use motya::proxy::request;
+
+struct Plugin;
+
+impl Guest for Plugin {
+    type FilterInstance = UpperJson;
+
+    fn create(
+        name: String,
+        _config: Config,
+    ) -> Result<Option<(FilterInstance, FilterType)>, String> {
+        match name.as_str() {
+            "upper-json" => Ok(Some((FilterInstance::new(UpperJson), FilterType::Request))),
+            _ => Ok(None),
+        }
+    }
+}
+
+struct UpperJson;
+
+impl GuestFilterInstance for UpperJson {
+    fn on_request(&self) -> Result<(), String> {
+        let body = request::read_body();
+        let json = std::str::from_utf8(&body).map_err(|err| err.to_string())?;
+        request::set_body(upper_strings(json).as_bytes());
+        Ok(())
+    }
+
+    fn on_response(&self) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn filter(&self) -> Result<bool, String> {
+        Ok(false)
+    }
+}
+
+/// Uppercases what's inside the strings of `json`, leaving the rest as it is so that
+/// `true`, `null` and escapes like `\n` keep their meaning.
+fn upper_strings(json: &str) -> String {
+    let mut out = String::with_capacity(json.len());
+    let (mut in_string, mut escaped) = (false, false);
+
+    for c in json.chars() {
+        if !in_string {
+            in_string = c == '"';
+            out.push(c);
+        } else if escaped {
+            escaped = false;
+            out.push(c);
+        } else {
+            match c {
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            out.extend(c.to_uppercase());
+        }
+    }
+
+    out
+}
+
+export!(Plugin);
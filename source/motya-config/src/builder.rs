@@ -0,0 +1,373 @@
+use std::net::SocketAddr;
+
+use http::{uri::PathAndQuery, Uri};
+use miette::Diagnostic;
+
+use crate::{
+    common_types::{
+        connectors::{
+            Connectors, HttpPeerConfig, RouteMatcher, UpstreamConfig, UpstreamContextConfig,
+            UpstreamScheme,
+        },
+        definitions::{Modificator, NamedFilterChain},
+        definitions_table::DefinitionsTable,
+        listeners::{ListenerConfig, ListenerKind, Listeners},
+    },
+    internal::ProxyConfig,
+    kdl::connectors::{resolve_proto_settings, ResolvedUpstream},
+};
+
+/// Builds a [`ProxyConfig`] in code, producing what the equivalent KDL service would.
+///
+/// ```ignore
+/// let proxy = ProxyConfigBuilder::new("Api")
+///     .listener("0.0.0.0:8080")
+///     .connector("/", "http://127.0.0.1:3000")
+///     .connector("/static", "http://127.0.0.1:4000/assets")
+///     .chain("auth")
+///     .definitions(table)
+///     .build()?;
+/// ```
+pub struct ProxyConfigBuilder {
+    name: String,
+    listeners: Vec<String>,
+    connectors: Vec<ConnectorSpec>,
+    chains: Vec<String>,
+    definitions: DefinitionsTable,
+}
+
+/// Everything wrong with a [`ProxyConfigBuilder`], one related diagnostic per problem.
+#[derive(thiserror::Error, Debug, Diagnostic)]
+#[error("Invalid configuration for proxy '{name}'")]
+pub struct BuildError {
+    pub name: String,
+    #[related]
+    pub problems: Vec<BuildProblem>,
+}
+
+#[derive(thiserror::Error, Debug, Diagnostic)]
+#[error("{0}")]
+pub struct BuildProblem(pub String);
+
+struct ConnectorSpec {
+    path: String,
+    url: String,
+    matcher: RouteMatcher,
+}
+
+impl ProxyConfigBuilder {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            listeners: Vec::new(),
+            connectors: Vec::new(),
+            chains: Vec::new(),
+            definitions: DefinitionsTable::default(),
+        }
+    }
+
+    /// A plain TCP listener, like `"ADDR"` in a `listeners` block.
+    pub fn listener(mut self, addr: impl Into<String>) -> Self {
+        self.listeners.push(addr.into());
+        self
+    }
+
+    /// Proxies requests for exactly `path`, like `section "PATH" { proxy "URL" }`.
+    ///
+    /// A `path` of `/` is the connector declared directly in the `connectors` block.
+    pub fn connector(mut self, path: impl Into<String>, url: impl Into<String>) -> Self {
+        self.connectors.push(ConnectorSpec {
+            path: path.into(),
+            url: url.into(),
+            matcher: RouteMatcher::Exact,
+        });
+        self
+    }
+
+    /// Proxies every request under `path`, like `section "PATH" as="prefix" { proxy "URL" }`.
+    pub fn prefix_connector(mut self, path: impl Into<String>, url: impl Into<String>) -> Self {
+        self.connectors.push(ConnectorSpec {
+            path: path.into(),
+            url: url.into(),
+            matcher: RouteMatcher::Prefix,
+        });
+        self
+    }
+
+    /// Applies a named chain to every connector, like `use-chain "NAME"` in the
+    /// `connectors` block.
+    pub fn chain(mut self, name: impl Into<String>) -> Self {
+        self.chains.push(name.into());
+        self
+    }
+
+    /// Table the chains are resolved against.
+    pub fn definitions(mut self, definitions: DefinitionsTable) -> Self {
+        self.definitions = definitions;
+        self
+    }
+
+    /// Assembles the config, reporting every problem found rather than only the first.
+    pub fn build(self) -> Result<ProxyConfig, BuildError> {
+        let mut problems = Vec::new();
+
+        if self.listeners.is_empty() {
+            problems.push("At least one listener is required".to_string());
+        }
+        if self.connectors.is_empty() {
+            problems.push("At least one connector is required".to_string());
+        }
+
+        let mut list_cfgs = Vec::new();
+        for addr in &self.listeners {
+            let Ok(addr) = addr.parse::<SocketAddr>() else {
+                problems.push(format!("'{addr}' is not a valid socket address"));
+                continue;
+            };
+            list_cfgs.push(ListenerConfig::new(ListenerKind::Tcp {
+                addr: addr.to_string(),
+                tls: None,
+                offer_h2: false,
+            }));
+        }
+
+        let mut chains = Vec::new();
+        for name in &self.chains {
+            match self.definitions.get_chain_by_name(name) {
+                Some(chain) => chains.push(Modificator::Chain(NamedFilterChain {
+                    chain,
+                    name: name.clone(),
+                })),
+                None => problems.push(format!("Chain '{name}' not found in definitions")),
+            }
+        }
+
+        let mut upstreams: Vec<UpstreamContextConfig> = Vec::new();
+        for spec in &self.connectors {
+            let peer = match spec.resolve() {
+                Ok(peer) => peer,
+                Err(problem) => {
+                    problems.push(problem);
+                    continue;
+                }
+            };
+
+            if upstreams
+                .iter()
+                .any(|existing| prefix_path(&existing.upstream) == Some(&peer.prefix_path))
+            {
+                problems.push(format!(
+                    "Duplicate connector for path '{}'",
+                    peer.prefix_path
+                ));
+                continue;
+            }
+
+            upstreams.push(UpstreamContextConfig {
+                chains: chains.clone(),
+                ..UpstreamContextConfig::new(UpstreamConfig::Service(peer))
+            });
+        }
+
+        if !problems.is_empty() {
+            return Err(BuildError {
+                name: self.name,
+                problems: problems.into_iter().map(BuildProblem).collect(),
+            });
+        }
+
+        Ok(ProxyConfig {
+            name: self.name,
+            listeners: Listeners { list_cfgs },
+            connectors: Connectors {
+                upstreams,
+                anonymous_definitions: DefinitionsTable::default(),
+            },
+            error_pages: Default::default(),
+            access_log: Default::default(),
+            routes: vec![],
+        })
+    }
+}
+
+impl ConnectorSpec {
+    /// The peer a `proxy "URL"` in a section for `path` would have, resolved and checked the
+    /// same way.
+    fn resolve(&self) -> Result<HttpPeerConfig, String> {
+        let prefix_path = if self.path.starts_with('/') {
+            self.path.clone()
+        } else {
+            format!("/{}", self.path)
+        };
+        let prefix_path = prefix_path
+            .parse::<PathAndQuery>()
+            .map_err(|err| format!("Bad path: {prefix_path}, error: {err}"))?;
+
+        let uri = self
+            .url
+            .parse::<Uri>()
+            .map_err(|err| format!("Invalid Uri '{}'. Reason: {err}", self.url))?;
+
+        let target = ResolvedUpstream::resolve(&uri, UpstreamScheme::default(), None, None)?;
+        let (tls, sni, alpn) = resolve_proto_settings(None, target.implied_sni().as_deref())?;
+
+        Ok(HttpPeerConfig {
+            peer_address: target.single_addr()?,
+            alpn,
+            tls,
+            sni,
+            prefix_path,
+            target_path: uri.path().parse().unwrap_or(PathAndQuery::from_static("/")),
+            matcher: self.matcher,
+            connect_timeout: None,
+        })
+    }
+}
+
+fn prefix_path(upstream: &UpstreamConfig) -> Option<&PathAndQuery> {
+    match upstream {
+        UpstreamConfig::Service(peer) => Some(&peer.prefix_path),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use kdl::KdlDocument;
+
+    use super::*;
+    use crate::{
+        assert_err_contains,
+        common_types::{
+            connectors::ConnectorGroups, definitions::FilterChain, section_parser::SectionParser,
+        },
+        kdl::{
+            parser::{
+                block::BlockParser,
+                ctx::{Current, ParseContext},
+            },
+            services::ServicesSection,
+        },
+    };
+
+    fn definitions() -> DefinitionsTable {
+        let mut table = DefinitionsTable::default();
+        table.insert_chain("auth", FilterChain { filters: vec![] });
+        table
+    }
+
+    fn parse_proxy(input: &str) -> ProxyConfig {
+        let doc: KdlDocument = input.parse().unwrap();
+        let table = definitions();
+        let groups = ConnectorGroups::default();
+
+        let ctx = ParseContext::new(&doc, Current::Document(&doc), "test");
+        let mut block = BlockParser::new(ctx).unwrap();
+
+        let mut services = block
+            .required("services", |ctx| {
+                ServicesSection::new(&table, &groups).parse_node(ctx)
+            })
+            .expect("Should parse services");
+        services.proxies.remove(0)
+    }
+
+    #[test]
+    fn test_builder_matches_kdl() {
+        let from_kdl = parse_proxy(
+            r#"
+            services {
+                Api {
+                    listeners { "127.0.0.1:8080" }
+                    connectors {
+                        use-chain "auth"
+                        proxy "http://127.0.0.1:3000"
+                        section "/static" as="prefix" {
+                            proxy "http://127.0.0.1:4000/assets"
+                        }
+                    }
+                }
+            }
+        "#,
+        );
+
+        let built = ProxyConfigBuilder::new("Api")
+            .listener("127.0.0.1:8080")
+            .connector("/", "http://127.0.0.1:3000")
+            .prefix_connector("/static", "http://127.0.0.1:4000/assets")
+            .chain("auth")
+            .definitions(definitions())
+            .build()
+            .expect("Should build proxy");
+
+        assert_eq!(built, from_kdl);
+    }
+
+    #[test]
+    fn test_builder_https_matches_kdl() {
+        let from_kdl = parse_proxy(
+            r#"
+            services {
+                Api {
+                    listeners { "127.0.0.1:8080" }
+                    connectors {
+                        proxy "https://127.0.0.1"
+                    }
+                }
+            }
+        "#,
+        );
+
+        let built = ProxyConfigBuilder::new("Api")
+            .listener("127.0.0.1:8080")
+            .connector("/", "https://127.0.0.1")
+            .build()
+            .expect("Should build proxy");
+
+        assert_eq!(built, from_kdl);
+        let UpstreamConfig::Service(peer) = &built.connectors.upstreams[0].upstream else {
+            panic!("Should be a single peer");
+        };
+        assert!(peer.tls);
+        assert_eq!(peer.peer_address, "127.0.0.1:443".parse().unwrap());
+    }
+
+    #[test]
+    fn test_builder_unknown_chain() {
+        let result = ProxyConfigBuilder::new("Api")
+            .listener("127.0.0.1:8080")
+            .connector("/", "http://127.0.0.1:3000")
+            .chain("missing")
+            .build();
+
+        let err = result.unwrap_err();
+        assert_eq!(err.problems.len(), 1);
+        assert_err_contains!(
+            err.problems[0].0,
+            "Chain 'missing' not found in definitions"
+        );
+    }
+
+    #[test]
+    fn test_builder_collects_missing_pieces() {
+        let err = ProxyConfigBuilder::new("Api")
+            .chain("missing")
+            .build()
+            .unwrap_err();
+
+        let problems = err
+            .problems
+            .iter()
+            .map(|problem| problem.0.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            problems,
+            vec![
+                "At least one listener is required",
+                "At least one connector is required",
+                "Chain 'missing' not found in definitions",
+            ]
+        );
+        assert_eq!(err.to_string(), "Invalid configuration for proxy 'Api'");
+    }
+}
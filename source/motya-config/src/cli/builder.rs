@@ -67,13 +67,11 @@ impl CliConfigBuilder {
     }
 
     pub fn build_routes(port: u16, routes: Vec<SyntheticRoute>) -> miette::Result<Config> {
-        let listener = ListenerConfig {
-            source: ListenerKind::Tcp {
-                addr: format!("0.0.0.0:{}", port),
-                tls: None,
-                offer_h2: false,
-            },
-        };
+        let listener = ListenerConfig::new(ListenerKind::Tcp {
+            addr: format!("0.0.0.0:{}", port),
+            tls: None,
+            offer_h2: false,
+        });
 
         let mut upstreams = Vec::new();
 
@@ -112,15 +110,12 @@ impl CliConfigBuilder {
                         prefix_path,
                         target_path: uri.path().parse().into_diagnostic()?,
                         matcher: route.route_match.match_type,
+                        connect_timeout: None,
                     })
                 }
             };
 
-            upstreams.push(UpstreamContextConfig {
-                upstream,
-                chains: vec![],
-                lb_options: None,
-            });
+            upstreams.push(UpstreamContextConfig::new(upstream));
         }
 
         let proxy_config = ProxyConfig {
@@ -132,15 +127,22 @@ impl CliConfigBuilder {
                 upstreams,
                 anonymous_definitions: Default::default(),
             },
+            error_pages: Default::default(),
+            access_log: Default::default(),
+            routes: vec![],
         };
 
         Ok(Config {
             validate_configs: false,
             threads_per_service: 1,
+            max_connections: None,
             daemonize: false,
             pid_file: None,
             upgrade_socket: None,
             upgrade: false,
+            status: None,
+            admin: None,
+            dns: None,
             basic_proxies: vec![proxy_config],
             file_servers: vec![],
         })
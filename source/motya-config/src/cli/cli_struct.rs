@@ -3,16 +3,30 @@
 use clap::{Parser, Subcommand};
 use std::path::PathBuf;
 
+use crate::kdl::parser::utils::parse_byte_size;
+
 #[derive(Parser, Debug)]
 pub struct Cli {
     /// Validate all configuration data and exit
     #[arg(long)]
     pub validate_configs: bool,
 
-    /// Path to the configuration file in KDL format
+    /// Print the effective configuration, after defaults and normalization, and exit
     #[arg(long)]
+    pub dump_config: bool,
+
+    /// Path to the configuration file in KDL format, `-` to read it from stdin
+    #[arg(long, visible_alias = "config")]
     pub config_entry: Option<PathBuf>,
 
+    /// Profile of the configuration to apply, `default` when omitted
+    #[arg(long)]
+    pub profile: Option<String>,
+
+    /// Largest configuration accepted, like `5MB`, counting every included file
+    #[arg(long, value_parser = parse_byte_size)]
+    pub max_config_size: Option<usize>,
+
     /// Number of threads used in the worker pool for EACH service
     #[arg(long)]
     pub threads_per_service: Option<usize>,
@@ -57,6 +71,28 @@ pub enum Commands {
         #[arg(short, long)]
         map: Vec<String>,
     },
+
+    /// Describe in plain words what the configuration does, then exit.
+    Explain,
+
+    /// Rewrite KDL configuration files in the canonical style.
+    Fmt {
+        /// Files to format
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+
+        /// Only report files that are not formatted, without changing them
+        #[arg(long)]
+        check: bool,
+    },
+
+    /// Compare the services of two configurations, ignoring formatting and key order.
+    Diff {
+        /// The configuration before the change
+        old: PathBuf,
+        /// The configuration after the change
+        new: PathBuf,
+    },
 }
 
 pub const BANNER: &str = r#"
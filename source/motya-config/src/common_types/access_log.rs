@@ -0,0 +1,74 @@
+use std::str::FromStr;
+
+use crate::common_types::headers::{parse_template, TemplatePart};
+
+/// Format the access record is written in when a service doesn't set `access-log`.
+pub const DEFAULT_ACCESS_LOG_FORMAT: &str = "$method $uri $status";
+
+/// A value of the finished request, written into its access record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessLogVariable {
+    Method,
+    Uri,
+    /// Status sent to the client.
+    Status,
+    /// IP address of the downstream client.
+    ClientIp,
+    /// Seconds from the request's arrival until its access record.
+    RequestTime,
+    /// Address of the backend that served the request, the last one tried on retries.
+    UpstreamAddr,
+    /// Seconds from picking the backend until its response was read, the time spent on
+    /// the upstream rather than on the whole request.
+    UpstreamResponseTime,
+    /// Name of the header a `431` answered for, never its value.
+    RejectedHeader,
+}
+
+const VARIABLES: &[(&str, AccessLogVariable)] = &[
+    ("method", AccessLogVariable::Method),
+    ("uri", AccessLogVariable::Uri),
+    ("status", AccessLogVariable::Status),
+    ("client_ip", AccessLogVariable::ClientIp),
+    ("request_time", AccessLogVariable::RequestTime),
+    ("upstream_addr", AccessLogVariable::UpstreamAddr),
+    (
+        "upstream_response_time",
+        AccessLogVariable::UpstreamResponseTime,
+    ),
+    ("rejected_header", AccessLogVariable::RejectedHeader),
+];
+
+impl AccessLogVariable {
+    pub fn name(&self) -> &'static str {
+        VARIABLES
+            .iter()
+            .find(|(_, var)| var == self)
+            .map(|(name, _)| *name)
+            .expect("every variable is listed in VARIABLES")
+    }
+}
+
+/// The `format` of a service's `access-log`, like `"$method $uri $status"`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccessLogFormat {
+    pub parts: Vec<TemplatePart<AccessLogVariable>>,
+}
+
+impl Default for AccessLogFormat {
+    fn default() -> Self {
+        DEFAULT_ACCESS_LOG_FORMAT
+            .parse()
+            .expect("the default format is valid")
+    }
+}
+
+impl FromStr for AccessLogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self {
+            parts: parse_template(s, VARIABLES)?,
+        })
+    }
+}
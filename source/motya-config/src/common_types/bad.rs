@@ -14,6 +14,32 @@ pub struct Bad {
     pub err_span: SourceSpan,
 }
 
+/// Every file of a multi-file config that failed to parse, reported together.
+#[derive(thiserror::Error, Debug, Diagnostic)]
+#[error("{} configuration file(s) failed to parse", .files.len())]
+pub struct DocumentErrors {
+    /// Names of the files at fault, in the order they were loaded.
+    pub files: Vec<String>,
+
+    #[related]
+    pub errors: Vec<Bad>,
+}
+
+/// A finding that doesn't fail the parse, such as a redundant setting.
+#[derive(thiserror::Error, Debug, Diagnostic)]
+#[error("Questionable configuration contents")]
+#[diagnostic(severity(Warning))]
+pub struct Warning {
+    #[help]
+    pub warning: String,
+
+    #[source_code]
+    pub src: NamedSource<String>,
+
+    #[label("here")]
+    pub span: SourceSpan,
+}
+
 pub trait OptExtParse {
     type Good;
 
@@ -57,3 +83,18 @@ impl Bad {
         }
     }
 }
+
+impl Warning {
+    pub fn docspan(
+        msg: impl Into<String>,
+        doc: &KdlDocument,
+        span: &SourceSpan,
+        source_name: impl AsRef<str>,
+    ) -> Self {
+        Self {
+            warning: msg.into(),
+            src: NamedSource::new(source_name, doc.to_string()),
+            span: span.to_owned(),
+        }
+    }
+}
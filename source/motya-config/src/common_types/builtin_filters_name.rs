@@ -4,6 +4,8 @@ macro_rules! define_builtin_filters {
         $callback! {
             actions: {
                 "motya.filters.block-cidr-range" => CidrRangeFilter,
+                "motya.filters.geo-block" => GeoBlockFilter,
+                "motya.filters.json-validate" => JsonValidateFilter,
             }
 
             requests: {
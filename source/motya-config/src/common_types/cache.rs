@@ -0,0 +1,22 @@
+use std::time::Duration;
+
+use http::Method;
+
+use crate::common_types::definitions::KeyTemplateConfig;
+
+/// Response caching for the upstreams of a section.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CacheConfig {
+    /// Key profile the cache key is computed with.
+    pub key: KeyTemplateConfig,
+    /// Freshness lifetime of an entry, unless the response's `Cache-Control` says otherwise.
+    pub ttl: Duration,
+    /// How long past `ttl` an entry may still be served while it's refreshed in the background.
+    pub stale_while_revalidate: Option<Duration>,
+    /// Upper bound on the total size of the cached bodies, in bytes.
+    pub max_size: usize,
+    /// Responses with a larger body are passed through without being stored, in bytes.
+    pub max_object_size: Option<usize>,
+    /// Request methods whose responses may be cached.
+    pub methods: Vec<Method>,
+}
@@ -0,0 +1,447 @@
+use std::{fmt, str::FromStr};
+
+use http::HeaderMap;
+
+/// A `when` clause deciding per request whether a filter runs, or a server of a pool is
+/// picked, such as `method == GET && path ^= /api`.
+///
+/// Comparisons combine with `&&`, binding tighter, and `||`, and can be grouped with
+/// parentheses. Values are bare words or `'quoted'` when they contain spaces.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    Compare {
+        field: Field,
+        op: Op,
+        value: String,
+    },
+    /// `header NAME exists`, or `header NAME <op> VALUE` against the first value of the header.
+    Header {
+        name: String,
+        test: Option<(Op, String)>,
+    },
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+}
+
+/// The part of the request a comparison looks at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Method,
+    Path,
+    Host,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    /// `==`
+    Eq,
+    /// `!=`
+    Ne,
+    /// `^=`, starts with
+    Prefix,
+    /// `$=`, ends with
+    Suffix,
+}
+
+/// What a [`Condition`] is evaluated against.
+#[derive(Debug, Clone, Copy)]
+pub struct RequestFacts<'a> {
+    pub method: &'a str,
+    pub path: &'a str,
+    pub host: Option<&'a str>,
+    pub headers: &'a HeaderMap,
+}
+
+impl Condition {
+    pub fn matches(&self, request: &RequestFacts) -> bool {
+        match self {
+            Condition::And(lhs, rhs) => lhs.matches(request) && rhs.matches(request),
+            Condition::Or(lhs, rhs) => lhs.matches(request) || rhs.matches(request),
+            Condition::Compare { field, op, value } => {
+                let actual = match field {
+                    Field::Method => request.method,
+                    Field::Path => request.path,
+                    Field::Host => match request.host {
+                        Some(host) => host,
+                        // a request without a host matches no host comparison but `!=`
+                        None => return *op == Op::Ne,
+                    },
+                };
+                // methods and hosts are case-insensitive, paths are not
+                op.apply(*field != Field::Path, actual, value)
+            }
+            Condition::Header { name, test } => {
+                let actual = request
+                    .headers
+                    .get(name.as_str())
+                    .and_then(|value| value.to_str().ok());
+                match (test, actual) {
+                    (None, actual) => actual.is_some(),
+                    (Some((op, value)), Some(actual)) => op.apply(false, actual, value),
+                    // like a missing host, a missing header only matches `!=`
+                    (Some((op, _)), None) => *op == Op::Ne,
+                }
+            }
+        }
+    }
+}
+
+impl Op {
+    fn apply(self, ignore_case: bool, actual: &str, expected: &str) -> bool {
+        let (actual, expected) = if ignore_case {
+            (actual.to_ascii_lowercase(), expected.to_ascii_lowercase())
+        } else {
+            (actual.to_string(), expected.to_string())
+        };
+
+        match self {
+            Op::Eq => actual == expected,
+            Op::Ne => actual != expected,
+            Op::Prefix => actual.starts_with(&expected),
+            Op::Suffix => actual.ends_with(&expected),
+        }
+    }
+}
+
+impl FromStr for Condition {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            tokens: tokenize(input)?,
+            pos: 0,
+        };
+
+        let condition = parser.or()?;
+        match parser.tokens.get(parser.pos) {
+            None => Ok(condition),
+            Some((offset, token)) => Err(format!("unexpected {token} at offset {offset}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Op(Op),
+    And,
+    Or,
+    Open,
+    Close,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Word(word) => write!(f, "'{word}'"),
+            Token::Op(op) => write!(f, "'{op}'"),
+            Token::And => f.write_str("'&&'"),
+            Token::Or => f.write_str("'||'"),
+            Token::Open => f.write_str("'('"),
+            Token::Close => f.write_str("')'"),
+        }
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Op::Eq => "==",
+            Op::Ne => "!=",
+            Op::Prefix => "^=",
+            Op::Suffix => "$=",
+        })
+    }
+}
+
+const OPERATORS: &[(&str, Option<Op>)] = &[
+    ("==", Some(Op::Eq)),
+    ("!=", Some(Op::Ne)),
+    ("^=", Some(Op::Prefix)),
+    ("$=", Some(Op::Suffix)),
+    ("&&", None),
+    ("||", None),
+];
+
+/// Splits the input into tokens, each with its byte offset for error messages.
+fn tokenize(input: &str) -> Result<Vec<(usize, Token)>, String> {
+    let mut tokens = vec![];
+    let mut rest = input;
+
+    loop {
+        let trimmed = rest.trim_start();
+        let offset = input.len() - trimmed.len();
+        rest = trimmed;
+
+        let Some(c) = rest.chars().next() else {
+            return Ok(tokens);
+        };
+
+        if let Some((text, op)) = OPERATORS.iter().find(|(text, _)| rest.starts_with(text)) {
+            let token = match (op, *text) {
+                (Some(op), _) => Token::Op(*op),
+                (None, "&&") => Token::And,
+                _ => Token::Or,
+            };
+            tokens.push((offset, token));
+            rest = &rest[text.len()..];
+            continue;
+        }
+
+        match c {
+            '(' => {
+                tokens.push((offset, Token::Open));
+                rest = &rest[1..];
+            }
+            ')' => {
+                tokens.push((offset, Token::Close));
+                rest = &rest[1..];
+            }
+            '\'' => {
+                let end = rest[1..]
+                    .find('\'')
+                    .ok_or_else(|| format!("unclosed quote at offset {offset}"))?;
+                tokens.push((offset, Token::Word(rest[1..end + 1].to_string())));
+                rest = &rest[end + 2..];
+            }
+            _ => {
+                let end = rest
+                    .find(|c: char| c.is_whitespace() || "()'".contains(c))
+                    .unwrap_or(rest.len());
+                let word = &rest[..end];
+                if let Some(at) = OPERATORS.iter().filter_map(|(op, _)| word.find(op)).min() {
+                    return Err(format!(
+                        "expected spaces around the operator at offset {}",
+                        offset + at
+                    ));
+                }
+                tokens.push((offset, Token::Word(word.to_string())));
+                rest = &rest[end..];
+            }
+        }
+    }
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+}
+
+impl Parser {
+    fn or(&mut self) -> Result<Condition, String> {
+        let mut lhs = self.and()?;
+        while self.eat(&Token::Or) {
+            lhs = Condition::Or(Box::new(lhs), Box::new(self.and()?));
+        }
+        Ok(lhs)
+    }
+
+    fn and(&mut self) -> Result<Condition, String> {
+        let mut lhs = self.atom()?;
+        while self.eat(&Token::And) {
+            lhs = Condition::And(Box::new(lhs), Box::new(self.atom()?));
+        }
+        Ok(lhs)
+    }
+
+    fn atom(&mut self) -> Result<Condition, String> {
+        if self.eat(&Token::Open) {
+            let inner = self.or()?;
+            return match self.next("')'")? {
+                (_, Token::Close) => Ok(inner),
+                (offset, token) => Err(format!("expected ')' at offset {offset}, found {token}")),
+            };
+        }
+
+        let field = match self.next("'method', 'path', 'host' or 'header'")? {
+            (_, Token::Word(word)) if word == "method" => Field::Method,
+            (_, Token::Word(word)) if word == "path" => Field::Path,
+            (_, Token::Word(word)) if word == "host" => Field::Host,
+            (_, Token::Word(word)) if word == "header" => return self.header(),
+            (offset, token) => {
+                return Err(format!(
+                "expected 'method', 'path', 'host' or 'header' at offset {offset}, found {token}"
+            ))
+            }
+        };
+
+        let (op, value) = self.comparison()?;
+
+        Ok(Condition::Compare { field, op, value })
+    }
+
+    /// The rest of a `header NAME exists` or `header NAME <op> VALUE` test.
+    fn header(&mut self) -> Result<Condition, String> {
+        let name = match self.next("a header name")? {
+            (_, Token::Word(name)) => name.to_ascii_lowercase(),
+            (offset, token) => {
+                return Err(format!(
+                    "expected a header name at offset {offset}, found {token}"
+                ))
+            }
+        };
+
+        let test = match self.tokens.get(self.pos) {
+            Some((_, Token::Word(word))) if word == "exists" => {
+                self.pos += 1;
+                None
+            }
+            _ => Some(self.comparison()?),
+        };
+
+        Ok(Condition::Header { name, test })
+    }
+
+    fn comparison(&mut self) -> Result<(Op, String), String> {
+        let op = match self.next("an operator")? {
+            (_, Token::Op(op)) => op,
+            (offset, token) => {
+                return Err(format!(
+                    "expected '==', '!=', '^=' or '$=' at offset {offset}, found {token}"
+                ))
+            }
+        };
+
+        let value = match self.next("a value")? {
+            (_, Token::Word(value)) => value,
+            (offset, token) => {
+                return Err(format!(
+                    "expected a value at offset {offset}, found {token}"
+                ))
+            }
+        };
+
+        Ok((op, value))
+    }
+
+    fn eat(&mut self, expected: &Token) -> bool {
+        let found = self
+            .tokens
+            .get(self.pos)
+            .is_some_and(|(_, token)| token == expected);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn next(&mut self, expected: &str) -> Result<(usize, Token), String> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or_else(|| format!("expected {expected}, found the end of the condition"))?;
+        self.pos += 1;
+        Ok(token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::LazyLock;
+
+    use super::*;
+
+    static NO_HEADERS: LazyLock<HeaderMap> = LazyLock::new(HeaderMap::new);
+
+    fn request<'a>(method: &'a str, path: &'a str, host: Option<&'a str>) -> RequestFacts<'a> {
+        RequestFacts {
+            method,
+            path,
+            host,
+            headers: &NO_HEADERS,
+        }
+    }
+
+    #[test]
+    fn test_matching_and_non_matching() {
+        let condition: Condition = "method == GET && path ^= /api".parse().unwrap();
+
+        assert!(condition.matches(&request("GET", "/api/users", None)));
+        assert!(condition.matches(&request("get", "/api", None)));
+        assert!(!condition.matches(&request("POST", "/api/users", None)));
+        assert!(!condition.matches(&request("GET", "/static/app.js", None)));
+    }
+
+    #[test]
+    fn test_precedence_and_grouping() {
+        let condition: Condition = "host == example.com || method == POST && path $= .json"
+            .parse()
+            .unwrap();
+
+        assert!(condition.matches(&request("GET", "/", Some("EXAMPLE.com"))));
+        assert!(condition.matches(&request("POST", "/data.json", Some("other.org"))));
+        assert!(!condition.matches(&request("POST", "/data.xml", Some("other.org"))));
+
+        let grouped: Condition = "(host == example.com || method == POST) && path != '/health'"
+            .parse()
+            .unwrap();
+
+        assert!(grouped.matches(&request("POST", "/data", None)));
+        assert!(!grouped.matches(&request("POST", "/health", None)));
+    }
+
+    #[test]
+    fn test_header_tests() {
+        let exists: Condition = "header X-Debug exists".parse().unwrap();
+        let equals: Condition = "header x-debug == 'on' && path ^= /api".parse().unwrap();
+
+        let mut headers = HeaderMap::new();
+        let with_debug = RequestFacts {
+            headers: &headers,
+            ..request("GET", "/api/users", None)
+        };
+        assert!(!exists.matches(&with_debug));
+        assert!(!equals.matches(&with_debug));
+
+        headers.insert("X-Debug", "on".parse().unwrap());
+        let with_debug = RequestFacts {
+            headers: &headers,
+            ..request("GET", "/api/users", None)
+        };
+        assert!(exists.matches(&with_debug));
+        assert!(equals.matches(&with_debug));
+
+        headers.insert("X-Debug", "ON".parse().unwrap());
+        let with_debug = RequestFacts {
+            headers: &headers,
+            ..request("GET", "/api/users", None)
+        };
+        assert!(exists.matches(&with_debug));
+        assert!(!equals.matches(&with_debug));
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        let cases = [
+            (
+                "method == GET &&",
+                "expected 'method', 'path', 'host' or 'header', found the end",
+            ),
+            (
+                "method = GET",
+                "expected '==', '!=', '^=' or '$=' at offset 7, found '='",
+            ),
+            (
+                "status == 200",
+                "expected 'method', 'path', 'host' or 'header' at offset 0, found 'status'",
+            ),
+            ("path ^= /api )", "unexpected ')' at offset 13"),
+            ("(method == GET", "expected ')', found the end"),
+            (
+                "method==GET",
+                "expected spaces around the operator at offset 6",
+            ),
+            ("path == 'a b", "unclosed quote at offset 8"),
+            ("header X-Debug", "expected an operator, found the end"),
+        ];
+
+        for (input, message) in cases {
+            let err = input.parse::<Condition>().unwrap_err();
+            assert!(
+                err.contains(message),
+                "{input}: expected {message:?}, got {err:?}"
+            );
+        }
+    }
+}
@@ -1,14 +1,28 @@
+use std::collections::HashMap;
 use std::fmt::Debug;
 use std::net::SocketAddr;
+use std::str::FromStr;
+use std::time::Duration;
 
-use http::uri::PathAndQuery;
+use http::{uri::PathAndQuery, Method};
+use regex::Regex;
 
 use crate::common_types::{
-    definitions::Modificator, definitions_table::DefinitionsTable,
+    cache::CacheConfig,
+    condition::Condition,
+    definitions::Modificator,
+    definitions_table::DefinitionsTable,
+    headers::{ForwardedHeaders, HeaderRule, HeaderTemplate, UpstreamAcceptEncoding, UpstreamHost},
+    rate_limit::RateLimitConfig,
     simple_response_type::SimpleResponseConfig,
+    status_map::StatusMap,
 };
 use crate::internal::UpstreamOptions;
 
+/// Largest body `buffer-request-body` and `buffer-response-body` hold when
+/// `buffer-body-limit` isn't set.
+pub const DEFAULT_BUFFER_BODY_LIMIT: usize = 10 * 1024 * 1024;
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum ALPN {
     H1,
@@ -16,6 +30,102 @@ pub enum ALPN {
     H2H1,
 }
 
+/// Scheme assumed for upstream addresses that don't specify one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UpstreamScheme {
+    #[default]
+    Http,
+    Https,
+}
+
+impl UpstreamScheme {
+    /// Port used when neither the upstream nor `default-port` provide one.
+    pub fn default_port(self) -> u16 {
+        match self {
+            UpstreamScheme::Http => 80,
+            UpstreamScheme::Https => 443,
+        }
+    }
+}
+
+impl FromStr for UpstreamScheme {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "http" => Ok(UpstreamScheme::Http),
+            "https" => Ok(UpstreamScheme::Https),
+            other => Err(format!(
+                "unknown scheme '{other}', expected 'http' or 'https'"
+            )),
+        }
+    }
+}
+
+/// What the upstream of a connector speaks, set with `protocol`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UpstreamProtocol {
+    /// Plain HTTP, over whichever version the connector negotiates.
+    #[default]
+    Http,
+    /// gRPC: HTTP/2 to the upstream, with the response trailers forwarded.
+    Grpc,
+}
+
+impl FromStr for UpstreamProtocol {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "http" => Ok(UpstreamProtocol::Http),
+            "grpc" => Ok(UpstreamProtocol::Grpc),
+            other => Err(format!(
+                "unknown protocol '{other}', expected 'http' or 'grpc'"
+            )),
+        }
+    }
+}
+
+/// The HTTP version spoken to the upstream of a connector, set with `upstream-http-version`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpstreamHttpVersion {
+    /// Negotiated with ALPN over TLS, HTTP/1 over cleartext.
+    Auto,
+    /// HTTP/1.1 only.
+    Http1,
+    /// HTTP/2 only. Cleartext upstreams are only spoken to, with prior knowledge, when
+    /// `h2c` allows it.
+    Http2 { h2c: bool },
+}
+
+impl FromStr for UpstreamHttpVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(UpstreamHttpVersion::Auto),
+            "1.1" => Ok(UpstreamHttpVersion::Http1),
+            "2" => Ok(UpstreamHttpVersion::Http2 { h2c: false }),
+            other => Err(format!(
+                "unknown upstream HTTP version '{other}', expected '1.1', '2' or 'auto'"
+            )),
+        }
+    }
+}
+
+/// What happens to plaintext requests of a connector, set with the `require-tls` key of a
+/// section.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RequireTls {
+    /// Served like requests over TLS.
+    #[default]
+    No,
+    /// Refused with a 403.
+    Reject,
+    /// Redirected to the same URL over https.
+    Redirect,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum RouteMatcher {
     #[default]
@@ -32,6 +142,8 @@ pub struct HttpPeerConfig {
     pub prefix_path: PathAndQuery,
     pub target_path: PathAndQuery,
     pub matcher: RouteMatcher,
+    /// Bound on establishing the TCP connection, pingora's default when `None`.
+    pub connect_timeout: Option<Duration>,
 }
 
 #[allow(clippy::large_enum_variant)]
@@ -48,14 +160,50 @@ pub struct UpstreamServer {
     pub weight: usize,
 }
 
+/// A server of a pool that is only sent the requests matching its `when` clause.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PinnedServer {
+    pub address: std::net::SocketAddr,
+    pub when: Condition,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct MultiServerUpstreamConfig {
     pub servers: Vec<UpstreamServer>,
+    /// Checked in order before the pool is balanced over, the first match taking the request.
+    pub pinned: Vec<PinnedServer>,
     pub tls_sni: Option<String>,
     pub alpn: ALPN,
     pub prefix_path: PathAndQuery,
     pub target_path: PathAndQuery,
     pub matcher: RouteMatcher,
+    /// Bound on establishing the TCP connection to each server, pingora's default when `None`.
+    pub connect_timeout: Option<Duration>,
+}
+
+impl MultiServerUpstreamConfig {
+    /// Divides the server weights by their greatest common divisor, so `10` and `20`
+    /// are balanced as `1` and `2`, and a lone server always has a weight of `1`.
+    pub fn normalize_weights(&mut self) {
+        let divisor = self
+            .servers
+            .iter()
+            .fold(0, |divisor, server| gcd(divisor, server.weight));
+
+        if divisor > 1 {
+            for server in &mut self.servers {
+                server.weight /= divisor;
+            }
+        }
+    }
+}
+
+fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
 }
 
 #[allow(clippy::large_enum_variant)]
@@ -63,6 +211,25 @@ pub enum ConnectorsLeaf {
     Upstream(UpstreamConfig),
     Modificator(Modificator),
     LoadBalance(UpstreamOptions),
+    RequestHeaders(Vec<HeaderRule<HeaderTemplate>>),
+    ResponseHeaders(Vec<HeaderRule>),
+    Cache(CacheConfig),
+    PathRegex(PathRegex),
+    BufferRequestBody(bool),
+    BufferResponseBody(bool),
+    BufferBodyLimit(usize),
+    AllowUpgrades(bool),
+    UpstreamAcceptEncoding(UpstreamAcceptEncoding),
+    UpstreamHost(UpstreamHost),
+    UpstreamMethod(Method),
+    UpstreamHttpVersion(UpstreamHttpVersion),
+    ResponseStatusMap(StatusMap),
+    Protocol(UpstreamProtocol),
+    LatencyBudget(Duration),
+    RequestTimeout(Duration),
+    RateLimit(RateLimitConfig),
+    RequireTls(RequireTls),
+    ForwardedHeaders(ForwardedHeaders),
     Section(Vec<ConnectorsLeaf>),
 }
 
@@ -72,9 +239,140 @@ pub struct Connectors {
     pub anonymous_definitions: DefinitionsTable,
 }
 
+/// Top-level `connectors "name" { ... }` groups, referenced by services with `use-connectors`.
+pub type ConnectorGroups = HashMap<String, Connectors>;
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct UpstreamContextConfig {
     pub upstream: UpstreamConfig,
     pub chains: Vec<Modificator>,
     pub lb_options: Option<UpstreamOptions>,
+    /// Header rules applied to requests before forwarding, inherited from enclosing sections first.
+    pub request_headers: Vec<HeaderRule<HeaderTemplate>>,
+    /// Header rules applied to responses, inherited from enclosing sections first.
+    pub response_headers: Vec<HeaderRule>,
+    /// Response cache of the closest enclosing section that declares one.
+    pub cache: Option<CacheConfig>,
+    /// Pattern of the enclosing `path-regex` section, matched against the whole request path.
+    pub path_regex: Option<PathRegex>,
+    /// Whether the whole request body is read before it is forwarded, instead of streamed.
+    pub buffer_request_body: bool,
+    /// Whether the whole response body is read before it is sent downstream.
+    pub buffer_response_body: bool,
+    /// Largest body held by `buffer-request-body` or `buffer-response-body`, from the closest
+    /// enclosing section that sets `buffer-body-limit`.
+    pub buffer_body_limit: usize,
+    /// Whether `Upgrade` requests, like WebSocket handshakes, are tunneled to the upstream.
+    pub allow_upgrades: bool,
+    /// `Accept-Encoding` sent upstream, from the closest enclosing section that sets it.
+    pub upstream_accept_encoding: UpstreamAcceptEncoding,
+    /// `Host` sent upstream, from the closest enclosing section that sets `host-header`.
+    pub host_header: UpstreamHost,
+    /// Method sent upstream in place of the client's, from the closest enclosing section that
+    /// sets `upstream-method`.
+    pub upstream_method: Option<Method>,
+    /// Status codes rewritten in responses, inherited from enclosing sections first.
+    pub response_status_map: StatusMap,
+    /// `protocol` of the closest enclosing section that sets it.
+    pub protocol: UpstreamProtocol,
+    /// `upstream-http-version` of the closest enclosing section that sets it, the `proxy`
+    /// settings decide when `None`.
+    pub upstream_http_version: Option<UpstreamHttpVersion>,
+    /// `latency-budget` of the closest enclosing section that sets it, bounding the whole
+    /// request, retries included.
+    pub latency_budget: Option<Duration>,
+    /// `request-timeout` of the closest enclosing section that sets it, unless the `route`
+    /// the request went through sets its own.
+    pub request_timeout: Option<Duration>,
+    /// `rate-limit` of the closest enclosing section that has one.
+    pub rate_limit: Option<RateLimitConfig>,
+    /// `require-tls` of the closest enclosing section that sets it.
+    pub require_tls: RequireTls,
+    /// `forwarded-headers` of the closest enclosing section that sets it.
+    pub forwarded_headers: ForwardedHeaders,
+}
+
+impl UpstreamContextConfig {
+    /// `upstream` with every section key at its default, as a `proxy` directly in a
+    /// `connectors` block without any other key gives.
+    pub fn new(upstream: UpstreamConfig) -> Self {
+        Self {
+            upstream,
+            chains: vec![],
+            lb_options: None,
+            request_headers: vec![],
+            response_headers: vec![],
+            cache: None,
+            path_regex: None,
+            buffer_request_body: false,
+            buffer_response_body: false,
+            buffer_body_limit: DEFAULT_BUFFER_BODY_LIMIT,
+            allow_upgrades: true,
+            upstream_accept_encoding: Default::default(),
+            host_header: Default::default(),
+            upstream_method: None,
+            response_status_map: Default::default(),
+            protocol: Default::default(),
+            upstream_http_version: None,
+            latency_budget: None,
+            request_timeout: None,
+            rate_limit: None,
+            require_tls: Default::default(),
+            forwarded_headers: Default::default(),
+        }
+    }
+}
+
+/// A compiled `path-regex`, compared by its pattern.
+#[derive(Debug, Clone)]
+pub struct PathRegex(pub Regex);
+
+impl PartialEq for PathRegex {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_weights() {
+        let cases: [(&[usize], &[usize]); 5] = [
+            (&[10, 20, 30], &[1, 2, 3]),
+            (&[4, 6], &[2, 3]),
+            (&[3, 5], &[3, 5]),
+            (&[5], &[1]),
+            (&[1000, 1000, 0], &[1, 1, 0]),
+        ];
+
+        for (configured, effective) in cases {
+            let mut upstream = MultiServerUpstreamConfig {
+                servers: configured
+                    .iter()
+                    .enumerate()
+                    .map(|(i, &weight)| UpstreamServer {
+                        address: SocketAddr::from(([127, 0, 0, 1], 8000 + i as u16)),
+                        weight,
+                    })
+                    .collect(),
+                pinned: vec![],
+                tls_sni: None,
+                alpn: ALPN::H1,
+                prefix_path: PathAndQuery::from_static("/"),
+                target_path: PathAndQuery::from_static("/"),
+                matcher: RouteMatcher::Prefix,
+                connect_timeout: None,
+            };
+            upstream.normalize_weights();
+
+            let weights = upstream
+                .servers
+                .iter()
+                .map(|server| server.weight)
+                .collect::<Vec<_>>();
+            assert_eq!(weights, effective, "configured {configured:?}");
+        }
+    }
 }
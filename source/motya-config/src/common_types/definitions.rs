@@ -1,5 +1,7 @@
 use fqdn::FQDN;
-use std::{collections::HashMap, path::PathBuf};
+
+use crate::common_types::condition::Condition;
+use std::{collections::HashMap, fmt, path::PathBuf, str::FromStr};
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct FilterChain {
@@ -10,12 +12,54 @@ pub struct FilterChain {
 pub struct ConfiguredFilter {
     pub name: FQDN,
     pub args: HashMap<String, String>,
+    /// `enabled=#false` keeps the filter in the config but skips it at runtime.
+    pub enabled: bool,
+    /// `on-filter-error`, overriding the default of the filter's plugin.
+    pub on_error: Option<FilterErrorPolicy>,
+    /// `max-memory` in bytes, capping the linear memory of a WASM filter.
+    pub max_memory: Option<usize>,
+    /// `request-body`, giving a WASM filter the whole request body up to this many bytes,
+    /// at most 64KB since the body is replayed from pingora's retry buffer.
+    pub max_body_size: Option<usize>,
+    /// `when` clause; the filter only runs on the requests matching it.
+    pub when: Option<Condition>,
+    /// `priority`; the chain runs lower ones first, keeping the written order between equals.
+    pub priority: i32,
+}
+
+/// What happens to a request when a WASM filter traps or returns an error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FilterErrorPolicy {
+    /// Reject the request with a 403 (fail-closed).
+    #[default]
+    Block,
+    /// Carry on as if the filter had not run (fail-open).
+    Allow,
+    /// Reject the request with a 500.
+    InternalError,
+}
+
+impl FromStr for FilterErrorPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "block" => Ok(FilterErrorPolicy::Block),
+            "allow" => Ok(FilterErrorPolicy::Allow),
+            "500" => Ok(FilterErrorPolicy::InternalError),
+            other => Err(format!(
+                "unknown policy '{other}', expected 'block', 'allow' or '500'"
+            )),
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct PluginDefinition {
     pub name: FQDN,
     pub source: PluginSource,
+    /// `on-filter-error` for every filter of the plugin, unless a filter sets its own.
+    pub on_filter_error: Option<FilterErrorPolicy>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -38,12 +82,202 @@ pub struct HashAlgorithm {
     pub seed: Option<String>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Clone, PartialEq)]
 pub struct Transform {
     pub name: String,
     pub params: HashMap<String, String>,
 }
 
+// keeps secret params, like the key of `hmac`, out of `--dump-config` and logs
+impl fmt::Debug for Transform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let schema = TransformSchema::find(&self.name);
+        let params: HashMap<&str, &str> = self
+            .params
+            .iter()
+            .map(|(name, value)| {
+                let secret = schema
+                    .and_then(|schema| schema.param(name).ok())
+                    .is_some_and(|spec| spec.kind == ParamKind::Secret);
+                (name.as_str(), if secret { "REDACTED" } else { value })
+            })
+            .collect();
+        f.debug_struct("Transform")
+            .field("name", &self.name)
+            .field("params", &params)
+            .finish()
+    }
+}
+
+/// What a transform parameter value must look like.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParamKind {
+    /// An integer greater than zero.
+    PositiveInteger,
+    /// Any non-empty string; `${ENV:NAME}` references are resolved.
+    NonEmpty,
+    /// Like [`ParamKind::NonEmpty`], but redacted when the config is printed.
+    Secret,
+    /// One of the listed values.
+    OneOf(&'static [&'static str]),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ParamSpec {
+    pub name: &'static str,
+    pub kind: ParamKind,
+    pub required: bool,
+}
+
+impl ParamSpec {
+    /// Checks a value given to this parameter of `transform`.
+    pub fn check(&self, transform: &str, raw: &str) -> Result<(), String> {
+        let name = self.name;
+        match self.kind {
+            ParamKind::PositiveInteger if !raw.parse::<usize>().is_ok_and(|n| n > 0) => Err(format!(
+                "Parameter '{name}' of transform '{transform}' must be a positive integer, found '{raw}'"
+            )),
+            ParamKind::NonEmpty | ParamKind::Secret if raw.is_empty() => Err(format!(
+                "Parameter '{name}' of transform '{transform}' must not be empty"
+            )),
+            ParamKind::OneOf(allowed) if !allowed.contains(&raw) => Err(format!(
+                "Parameter '{name}' of transform '{transform}' must be one of {allowed:?}, found '{raw}'"
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn missing(&self, transform: &str) -> String {
+        format!(
+            "Transform '{transform}' requires the '{}' parameter",
+            self.name
+        )
+    }
+}
+
+/// Where a transform belongs in `transforms-order`. A step breaking one is still applied,
+/// the parser only warns about it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OrderConstraint {
+    /// No other transform may follow, as it would rework the output of this one.
+    Last,
+    /// The named transform, when present, should come before this one.
+    After(&'static str),
+}
+
+/// A transform supported in `transforms-order`, with the parameters it accepts.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TransformSchema {
+    pub name: &'static str,
+    pub params: &'static [ParamSpec],
+    pub order: &'static [OrderConstraint],
+}
+
+pub const TRANSFORM_SCHEMAS: &[TransformSchema] = &[
+    TransformSchema {
+        name: "lowercase",
+        params: &[],
+        order: &[],
+    },
+    TransformSchema {
+        name: "remove-query-params",
+        params: &[],
+        order: &[],
+    },
+    TransformSchema {
+        name: "strip-trailing-slash",
+        params: &[],
+        order: &[],
+    },
+    TransformSchema {
+        name: "truncate",
+        params: &[ParamSpec {
+            name: "length",
+            kind: ParamKind::PositiveInteger,
+            required: true,
+        }],
+        // cutting first would keep a different part of the key
+        order: &[
+            OrderConstraint::After("lowercase"),
+            OrderConstraint::After("remove-query-params"),
+            OrderConstraint::After("strip-trailing-slash"),
+        ],
+    },
+    TransformSchema {
+        name: "hmac",
+        params: &[
+            ParamSpec {
+                name: "key",
+                kind: ParamKind::Secret,
+                required: true,
+            },
+            ParamSpec {
+                name: "algorithm",
+                kind: ParamKind::OneOf(HMAC_ALGORITHMS),
+                required: false,
+            },
+        ],
+        // a transform applied to the digest undoes what the key was hashed for
+        order: &[OrderConstraint::Last],
+    },
+];
+
+/// Digests accepted by the `hmac` transform, the first one is the default.
+pub const HMAC_ALGORITHMS: &[&str] = &["sha256", "sha512"];
+
+impl TransformSchema {
+    pub fn find(name: &str) -> Option<&'static TransformSchema> {
+        TRANSFORM_SCHEMAS.iter().find(|schema| schema.name == name)
+    }
+
+    /// Like [`TransformSchema::find`], describing the supported transforms when there's none.
+    pub fn find_or_describe(name: &str) -> Result<&'static TransformSchema, String> {
+        Self::find(name).ok_or_else(|| {
+            let known: Vec<&str> = TRANSFORM_SCHEMAS.iter().map(|s| s.name).collect();
+            format!("Unknown transform '{name}'. Supported transforms are: {known:?}")
+        })
+    }
+
+    /// The parameter `name` of the transform, describing the supported ones when there's none.
+    pub fn param(&self, name: &str) -> Result<&'static ParamSpec, String> {
+        self.params.iter().find(|p| p.name == name).ok_or_else(|| {
+            let known: Vec<&str> = self.params.iter().map(|p| p.name).collect();
+            format!(
+                "Unknown parameter '{name}' of transform '{}'. Supported parameters are: {known:?}",
+                self.name
+            )
+        })
+    }
+}
+
+impl Transform {
+    /// Checks the params against the [`TransformSchema`] of the transform: every one must be
+    /// known and well-formed, and the required ones present.
+    pub fn validate_params(&self) -> Result<&'static TransformSchema, String> {
+        let schema = TransformSchema::find_or_describe(&self.name)?;
+
+        // the smallest name keeps the error the same between runs
+        if let Some(unknown) = self
+            .params
+            .keys()
+            .filter(|name| schema.param(name).is_err())
+            .min()
+        {
+            return Err(schema.param(unknown).unwrap_err());
+        }
+
+        for spec in schema.params {
+            match self.params.get(spec.name) {
+                Some(raw) => spec.check(&self.name, raw)?,
+                None if spec.required => return Err(spec.missing(&self.name)),
+                None => {}
+            }
+        }
+
+        Ok(schema)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct NamedFilterChain {
     pub name: String,
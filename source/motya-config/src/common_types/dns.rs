@@ -0,0 +1,448 @@
+use std::{
+    io::{self, Read, Write},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream, UdpSocket},
+    time::{Duration, Instant},
+};
+
+/// How long a `dns` server is waited on when the block doesn't set `timeout`.
+pub const DEFAULT_DNS_TIMEOUT: Duration = Duration::from_secs(5);
+
+const TYPE_A: u16 = 1;
+const TYPE_AAAA: u16 = 28;
+const CLASS_IN: u16 = 1;
+
+/// Length of the header every message starts with, the question follows.
+const HEADER_LEN: usize = 12;
+
+/// The top-level `dns { ... }` block: the servers upstream host names are resolved with,
+/// instead of the system resolver.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DnsConfig {
+    pub servers: Vec<SocketAddr>,
+    /// How long each server is waited on before the next one is asked.
+    pub timeout: Duration,
+}
+
+/// Resolves upstream host names against the servers of a [`DnsConfig`], trying them in order.
+///
+/// Only `A` and `AAAA` records are asked for, every address of the first server that has
+/// any is used.
+#[derive(Debug, Clone)]
+pub struct DnsResolver {
+    servers: Vec<SocketAddr>,
+    timeout: Duration,
+}
+
+impl DnsResolver {
+    pub fn new(config: &DnsConfig) -> Self {
+        Self {
+            servers: config.servers.clone(),
+            timeout: config.timeout,
+        }
+    }
+
+    pub fn servers(&self) -> &[SocketAddr] {
+        &self.servers
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// The addresses of `host`, IP literals are returned without asking the servers.
+    pub fn lookup(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+        if let Ok(ip) = host.parse::<IpAddr>() {
+            return Ok(vec![SocketAddr::new(ip, port)]);
+        }
+
+        let mut last_error = io::Error::new(io::ErrorKind::NotFound, "no DNS servers configured");
+        for server in &self.servers {
+            match self.query(*server, host) {
+                Ok(ips) if !ips.is_empty() => {
+                    return Ok(ips
+                        .into_iter()
+                        .map(|ip| SocketAddr::new(ip, port))
+                        .collect())
+                }
+                Ok(_) => {
+                    last_error = io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!("{server} has no address for '{host}'"),
+                    )
+                }
+                Err(err) => last_error = io::Error::new(err.kind(), format!("{server}: {err}")),
+            }
+        }
+
+        Err(last_error)
+    }
+
+    /// The `A` and `AAAA` addresses `server` has for `host`, asked for at once and both
+    /// answered within the timeout.
+    fn query(&self, server: SocketAddr, host: &str) -> io::Result<Vec<IpAddr>> {
+        let deadline = Instant::now() + self.timeout;
+        let queries = [Query::new(host, TYPE_A)?, Query::new(host, TYPE_AAAA)?];
+
+        let local: SocketAddr = match server {
+            SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+            SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+        };
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(server)?;
+        for query in &queries {
+            socket.send(&query.msg)?;
+        }
+
+        let mut answers: [Option<Vec<IpAddr>>; 2] = Default::default();
+        let mut buf = [0u8; 512];
+        while answers.iter().any(Option::is_none) {
+            socket.set_read_timeout(Some(remaining(deadline)?))?;
+            let len = match socket.recv(&mut buf) {
+                Ok(len) => len,
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => return Err(timed_out()),
+                Err(err) => return Err(err),
+            };
+
+            // anything else reaching the socket, late or forged, is not an answer
+            let Some(i) = queries
+                .iter()
+                .position(|query| query.is_answered_by(&buf[..len]))
+            else {
+                continue;
+            };
+            if answers[i].is_some() {
+                continue;
+            }
+            answers[i] = Some(
+                match decode_response(&queries[i], &buf[..len]).map_err(invalid)? {
+                    Response::Addrs(ips) => ips,
+                    Response::Truncated => query_tcp(server, &queries[i], deadline)?,
+                },
+            );
+        }
+
+        Ok(answers.into_iter().flatten().flatten().collect())
+    }
+}
+
+/// Asks `server` again over TCP, for an answer too long for a UDP datagram.
+fn query_tcp(server: SocketAddr, query: &Query, deadline: Instant) -> io::Result<Vec<IpAddr>> {
+    let mut stream = TcpStream::connect_timeout(&server, remaining(deadline)?)?;
+    stream.set_write_timeout(Some(remaining(deadline)?))?;
+
+    // over TCP every message is preceded by its length
+    let mut msg = Vec::with_capacity(2 + query.msg.len());
+    msg.extend_from_slice(&(query.msg.len() as u16).to_be_bytes());
+    msg.extend_from_slice(&query.msg);
+    stream.write_all(&msg)?;
+
+    stream.set_read_timeout(Some(remaining(deadline)?))?;
+    let mut len = [0u8; 2];
+    stream.read_exact(&mut len)?;
+    let mut response = vec![0u8; u16::from_be_bytes(len) as usize];
+    stream.read_exact(&mut response)?;
+
+    if !query.is_answered_by(&response) {
+        return Err(invalid("response doesn't answer the query".to_string()));
+    }
+    match decode_response(query, &response).map_err(invalid)? {
+        Response::Addrs(ips) => Ok(ips),
+        Response::Truncated => Err(invalid("truncated response over TCP".to_string())),
+    }
+}
+
+/// How long until `deadline`, or an error once it has passed.
+fn remaining(deadline: Instant) -> io::Result<Duration> {
+    Some(deadline.saturating_duration_since(Instant::now()))
+        .filter(|left| !left.is_zero())
+        .ok_or_else(timed_out)
+}
+
+fn timed_out() -> io::Error {
+    io::Error::new(io::ErrorKind::TimedOut, "no answer within the timeout")
+}
+
+fn invalid(err: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+/// A question for the `qtype` records of a name, under a random id so that answers
+/// can't be guessed by someone who only sees the name being resolved.
+struct Query {
+    qtype: u16,
+    msg: Vec<u8>,
+}
+
+impl Query {
+    fn new(host: &str, qtype: u16) -> io::Result<Self> {
+        let mut id = [0u8; 2];
+        getrandom::fill(&mut id).map_err(|err| io::Error::other(err.to_string()))?;
+        let id = u16::from_be_bytes(id);
+
+        Ok(Self {
+            qtype,
+            msg: encode_query(id, host, qtype)?,
+        })
+    }
+
+    /// Whether `msg` is a response to this query: one with its id that echoes its
+    /// question, the name compared without regard to case.
+    fn is_answered_by(&self, msg: &[u8]) -> bool {
+        let question = &self.msg[HEADER_LEN..];
+        msg.len() >= HEADER_LEN + question.len()
+            && msg[..2] == self.msg[..2]
+            && msg[2] & 0x80 != 0
+            && msg[4..6] == [0, 1]
+            && msg[HEADER_LEN..HEADER_LEN + question.len()].eq_ignore_ascii_case(question)
+    }
+}
+
+fn encode_query(id: u16, host: &str, qtype: u16) -> io::Result<Vec<u8>> {
+    let mut query = Vec::with_capacity(17 + host.len());
+    query.extend_from_slice(&id.to_be_bytes());
+    // recursion desired, one question
+    query.extend_from_slice(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+
+    for label in host.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("'{host}' is not a valid host name"),
+            ));
+        }
+        query.push(label.len() as u8);
+        query.extend_from_slice(label.as_bytes());
+    }
+    query.push(0);
+    query.extend_from_slice(&qtype.to_be_bytes());
+    query.extend_from_slice(&CLASS_IN.to_be_bytes());
+
+    Ok(query)
+}
+
+/// What a server answered to a query.
+#[derive(Debug, PartialEq)]
+enum Response {
+    /// Every address of the queried type, none when the name has none.
+    Addrs(Vec<IpAddr>),
+    /// The answer didn't fit the datagram, and has to be asked for over TCP.
+    Truncated,
+}
+
+/// The addresses answered to `query` by `msg`, which [`Query::is_answered_by`] has matched.
+fn decode_response(query: &Query, msg: &[u8]) -> Result<Response, String> {
+    let u16_at = |pos: usize| -> Result<u16, String> {
+        msg.get(pos..pos + 2)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]))
+            .ok_or_else(|| "truncated response".to_string())
+    };
+
+    let flags = u16_at(2)?;
+    if flags & 0x0200 != 0 {
+        return Ok(Response::Truncated);
+    }
+    match flags & 0x000f {
+        0 => {}
+        // the name doesn't exist
+        3 => return Ok(Response::Addrs(vec![])),
+        rcode => return Err(format!("server failed with response code {rcode}")),
+    }
+
+    let answers = u16_at(6)?;
+    // the single question, checked to be the one asked
+    let mut pos = query.msg.len();
+
+    let mut ips = vec![];
+    for _ in 0..answers {
+        pos = skip_name(msg, pos)?;
+        let rtype = u16_at(pos)?;
+        let class = u16_at(pos + 2)?;
+        let len = u16_at(pos + 8)? as usize;
+        let data = msg
+            .get(pos + 10..pos + 10 + len)
+            .ok_or_else(|| "truncated response".to_string())?;
+        pos += 10 + len;
+
+        if rtype != query.qtype || class != CLASS_IN {
+            // CNAMEs come before the records they point to
+            continue;
+        }
+        let ip = match (rtype, data.len()) {
+            (TYPE_A, 4) => IpAddr::from(<[u8; 4]>::try_from(data).unwrap()),
+            (TYPE_AAAA, 16) => IpAddr::from(<[u8; 16]>::try_from(data).unwrap()),
+            _ => return Err(format!("malformed record of type {rtype}")),
+        };
+        if !ips.contains(&ip) {
+            ips.push(ip);
+        }
+    }
+
+    Ok(Response::Addrs(ips))
+}
+
+/// The position right after the name at `pos`.
+fn skip_name(msg: &[u8], mut pos: usize) -> Result<usize, String> {
+    loop {
+        let len = *msg
+            .get(pos)
+            .ok_or_else(|| "truncated response".to_string())?;
+        match len {
+            0 => return Ok(pos + 1),
+            // a pointer to a name earlier in the message ends this one
+            len if len & 0xc0 == 0xc0 => return Ok(pos + 2),
+            len => pos += 1 + len as usize,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::TcpListener, thread};
+
+    use super::*;
+
+    /// A response to `query` with `flags`, answering with those of `ips` of the type
+    /// it asks for.
+    fn respond(query: &[u8], flags: u16, ips: &[IpAddr]) -> Vec<u8> {
+        let qtype = u16::from_be_bytes([query[query.len() - 4], query[query.len() - 3]]);
+        let records = ips
+            .iter()
+            .filter_map(|ip| match (qtype, ip) {
+                (TYPE_A, IpAddr::V4(ip)) => Some(ip.octets().to_vec()),
+                (TYPE_AAAA, IpAddr::V6(ip)) => Some(ip.octets().to_vec()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        let mut response = query.to_vec();
+        response[2..4].copy_from_slice(&flags.to_be_bytes());
+        response[6..8].copy_from_slice(&(records.len() as u16).to_be_bytes());
+        for data in records {
+            response.extend_from_slice(&[0xc0, 12]);
+            response.extend_from_slice(&qtype.to_be_bytes());
+            response.extend_from_slice(&CLASS_IN.to_be_bytes());
+            response.extend_from_slice(&[0, 0, 0, 60, 0, data.len() as u8]);
+            response.extend_from_slice(&data);
+        }
+        response
+    }
+
+    /// Answers every query with the datagrams `answer` makes of it, returning the
+    /// server's address.
+    fn udp_server(answer: impl Fn(&[u8]) -> Vec<Vec<u8>> + Send + 'static) -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = socket.local_addr().unwrap();
+
+        thread::spawn(move || {
+            let mut buf = [0u8; 512];
+            while let Ok((len, client)) = socket.recv_from(&mut buf) {
+                for response in answer(&buf[..len]) {
+                    socket.send_to(&response, client).unwrap();
+                }
+            }
+        });
+
+        addr
+    }
+
+    fn resolver(server: SocketAddr) -> DnsResolver {
+        DnsResolver::new(&DnsConfig {
+            servers: vec![server],
+            timeout: Duration::from_secs(2),
+        })
+    }
+
+    #[test]
+    fn test_lookup_asks_configured_server() {
+        let ips: [IpAddr; 3] = [
+            Ipv4Addr::new(10, 1, 2, 3).into(),
+            Ipv4Addr::new(10, 1, 2, 4).into(),
+            "fd00::1".parse().unwrap(),
+        ];
+        let server = udp_server(move |query| vec![respond(query, 0x8180, &ips)]);
+
+        assert_eq!(
+            resolver(server).lookup("backend.internal", 8080).unwrap(),
+            vec![
+                "10.1.2.3:8080".parse().unwrap(),
+                "10.1.2.4:8080".parse().unwrap(),
+                "[fd00::1]:8080".parse().unwrap(),
+            ]
+        );
+        // literals skip the servers
+        assert_eq!(
+            resolver(server).lookup("192.168.0.1", 80).unwrap(),
+            vec!["192.168.0.1:80".parse().unwrap()]
+        );
+    }
+
+    #[test]
+    fn test_lookup_ignores_forged_answers() {
+        let ip: IpAddr = Ipv4Addr::new(10, 1, 2, 3).into();
+        let forged: IpAddr = Ipv4Addr::new(6, 6, 6, 6).into();
+        let server = udp_server(move |query| {
+            let mut other_id = respond(query, 0x8180, &[forged]);
+            other_id[0] ^= 0xff;
+            let mut other_name = respond(query, 0x8180, &[forged]);
+            other_name[HEADER_LEN + 1] ^= 0xff;
+            // the query itself, without the response bit
+            let echo = query.to_vec();
+
+            vec![other_id, other_name, echo, respond(query, 0x8180, &[ip])]
+        });
+
+        assert_eq!(
+            resolver(server).lookup("backend.internal", 80).unwrap(),
+            vec![SocketAddr::new(ip, 80)]
+        );
+    }
+
+    #[test]
+    fn test_lookup_retries_truncated_over_tcp() {
+        let ip: IpAddr = Ipv4Addr::new(10, 1, 2, 3).into();
+        let server = udp_server(|query| vec![respond(query, 0x8380, &[])]);
+        let tcp = TcpListener::bind(server).unwrap();
+
+        thread::spawn(move || {
+            while let Ok((mut stream, _)) = tcp.accept() {
+                let mut len = [0u8; 2];
+                stream.read_exact(&mut len).unwrap();
+                let mut query = vec![0u8; u16::from_be_bytes(len) as usize];
+                stream.read_exact(&mut query).unwrap();
+
+                let response = respond(&query, 0x8180, &[ip]);
+                stream
+                    .write_all(&(response.len() as u16).to_be_bytes())
+                    .unwrap();
+                stream.write_all(&response).unwrap();
+            }
+        });
+
+        assert_eq!(
+            resolver(server).lookup("backend.internal", 80).unwrap(),
+            vec![SocketAddr::new(ip, 80)]
+        );
+    }
+
+    #[test]
+    fn test_lookup_times_out_without_answer() {
+        let server = udp_server(|_| vec![]);
+        let resolver = DnsResolver::new(&DnsConfig {
+            servers: vec![server],
+            timeout: Duration::from_millis(200),
+        });
+
+        let err = resolver.lookup("backend.internal", 80).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut, "{err}");
+    }
+
+    #[test]
+    fn test_query_ids_are_random() {
+        let ids = (0..8)
+            .map(|_| Query::new("example.com", TYPE_A).unwrap().msg[..2].to_vec())
+            .collect::<Vec<_>>();
+        assert!(ids.iter().any(|id| *id != ids[0]));
+
+        assert!(encode_query(7, "bad..name", TYPE_A).is_err());
+    }
+}
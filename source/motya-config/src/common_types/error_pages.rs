@@ -0,0 +1,20 @@
+use std::collections::BTreeMap;
+
+/// Body served in place of a response the proxy generates itself.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ErrorPage {
+    pub content_type: &'static str,
+    pub body: Vec<u8>,
+}
+
+/// Configured error pages of a service, keyed by status code.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ErrorPages {
+    pub pages: BTreeMap<u16, ErrorPage>,
+}
+
+impl ErrorPages {
+    pub fn get(&self, status: u16) -> Option<&ErrorPage> {
+        self.pages.get(&status)
+    }
+}
@@ -0,0 +1,359 @@
+use std::{fmt, net::IpAddr, str::FromStr};
+
+use cidr::IpCidr;
+use http::{uri::Authority, HeaderName};
+
+/// A single declarative header operation.
+///
+/// `V` is the value representation: plain strings for responses, and
+/// [`HeaderTemplate`] for requests, where values may reference request variables.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HeaderRule<V = String> {
+    /// Replaces every existing value of the header.
+    Set { name: HeaderName, value: V },
+    /// Appends a value, keeping the existing ones.
+    Add { name: HeaderName, value: V },
+    /// Drops the header entirely.
+    Remove { name: HeaderName },
+}
+
+/// A value known only once a request arrives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeaderVariable {
+    /// IP address of the downstream client.
+    ClientIp,
+    /// Host the client addressed the request to.
+    Host,
+}
+
+const VARIABLES: &[(&str, HeaderVariable)] = &[
+    ("client_ip", HeaderVariable::ClientIp),
+    ("host", HeaderVariable::Host),
+];
+
+impl HeaderVariable {
+    pub fn name(&self) -> &'static str {
+        VARIABLES
+            .iter()
+            .find(|(_, var)| var == self)
+            .map(|(name, _)| *name)
+            .expect("every variable is listed in VARIABLES")
+    }
+}
+
+/// A piece of a template: `V` lists the variables it may reference.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TemplatePart<V = HeaderVariable> {
+    Literal(String),
+    Variable(V),
+}
+
+/// Header value containing `$name` or `${name}` references to [`HeaderVariable`]s.
+///
+/// A `$` that isn't followed by a variable name is kept literally.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct HeaderTemplate {
+    pub parts: Vec<TemplatePart>,
+}
+
+impl HeaderTemplate {
+    /// The template with every variable left out, used to validate the literal text.
+    pub fn literal_text(&self) -> String {
+        self.parts
+            .iter()
+            .filter_map(|part| match part {
+                TemplatePart::Literal(s) => Some(s.as_str()),
+                TemplatePart::Variable(_) => None,
+            })
+            .collect()
+    }
+}
+
+impl FromStr for HeaderTemplate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self {
+            parts: parse_template(s, VARIABLES)?,
+        })
+    }
+}
+
+/// Splits `s` into literal text and `$name` or `${name}` references to `variables`.
+///
+/// A `$` that isn't followed by a variable name is kept literally.
+pub fn parse_template<V: Copy>(
+    s: &str,
+    variables: &[(&str, V)],
+) -> Result<Vec<TemplatePart<V>>, String> {
+    let mut parts = Vec::new();
+    let mut literal = String::new();
+    let mut rest = s;
+
+    while let Some(pos) = rest.find('$') {
+        literal.push_str(&rest[..pos]);
+        let after = &rest[pos + 1..];
+
+        let (name, tail) = if let Some(braced) = after.strip_prefix('{') {
+            let end = braced
+                .find('}')
+                .ok_or_else(|| format!("unclosed '${{' in '{s}'"))?;
+            (&braced[..end], &braced[end + 1..])
+        } else {
+            let end = if after.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+                after
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(after.len())
+            } else {
+                0
+            };
+            (&after[..end], &after[end..])
+        };
+
+        if name.is_empty() {
+            literal.push('$');
+            rest = after;
+            continue;
+        }
+
+        let var = variables
+            .iter()
+            .find(|(known, _)| *known == name)
+            .map(|(_, var)| *var)
+            .ok_or_else(|| {
+                let known = variables
+                    .iter()
+                    .map(|(known, _)| format!("${known}"))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!("unknown variable '${name}', expected one of {known}")
+            })?;
+
+        if !literal.is_empty() {
+            parts.push(TemplatePart::Literal(std::mem::take(&mut literal)));
+        }
+        parts.push(TemplatePart::Variable(var));
+        rest = tail;
+    }
+
+    literal.push_str(rest);
+    if !literal.is_empty() {
+        parts.push(TemplatePart::Literal(literal));
+    }
+
+    Ok(parts)
+}
+
+impl fmt::Display for HeaderTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for part in &self.parts {
+            match part {
+                TemplatePart::Literal(s) => f.write_str(s)?,
+                TemplatePart::Variable(var) => write!(f, "${{{}}}", var.name())?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What `Accept-Encoding` is sent upstream, set by `upstream-accept-encoding`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum UpstreamAcceptEncoding {
+    /// The client's header is forwarded as is.
+    #[default]
+    Passthrough,
+    /// Upstreams are asked for uncompressed responses.
+    Identity,
+    /// Upstreams are offered these codings, in order, like `gzip, br`.
+    Codings(Vec<String>),
+}
+
+const CODINGS: &[&str] = &["gzip", "br", "deflate", "zstd", "compress", "identity"];
+
+impl UpstreamAcceptEncoding {
+    /// The header value to send upstream, `None` to keep the client's.
+    pub fn header_value(&self) -> Option<String> {
+        match self {
+            Self::Passthrough => None,
+            Self::Identity => Some("identity".to_string()),
+            Self::Codings(codings) => Some(codings.join(", ")),
+        }
+    }
+}
+
+impl FromStr for UpstreamAcceptEncoding {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "passthrough" => return Ok(Self::Passthrough),
+            "identity" => return Ok(Self::Identity),
+            _ => {}
+        }
+
+        let mut codings = vec![];
+        for coding in value.split(',') {
+            let coding = coding.trim().to_ascii_lowercase();
+            if !CODINGS.contains(&coding.as_str()) {
+                return Err(format!(
+                    "unknown content coding '{coding}', expected 'identity', 'passthrough' or a list of {}",
+                    CODINGS.join(", ")
+                ));
+            }
+            codings.push(coding);
+        }
+
+        Ok(Self::Codings(codings))
+    }
+}
+
+/// What `Host` is sent upstream, set by `host-header`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum UpstreamHost {
+    /// The client's header is forwarded as is.
+    #[default]
+    Passthrough,
+    /// Upstreams see this host, with an optional port, like `backend.internal:8080`.
+    Override(String),
+}
+
+impl UpstreamHost {
+    /// The header value to send upstream, `None` to keep the client's.
+    pub fn header_value(&self) -> Option<&str> {
+        match self {
+            Self::Passthrough => None,
+            Self::Override(host) => Some(host),
+        }
+    }
+}
+
+impl FromStr for UpstreamHost {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if value == "passthrough" {
+            return Ok(Self::Passthrough);
+        }
+
+        let authority = value
+            .parse::<Authority>()
+            .map_err(|e| format!("'{value}' is not a valid host: {e}"))?;
+        if authority.host().is_empty() || authority.as_str().contains('@') {
+            return Err(format!(
+                "'{value}' is not a valid host, expected a host with an optional port"
+            ));
+        }
+
+        Ok(Self::Override(authority.to_string()))
+    }
+}
+
+/// `forwarded-headers`: what the upstream is told about the client and the original request.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForwardedHeaders {
+    /// Whether `X-Forwarded-For`, `X-Forwarded-Proto` and `X-Forwarded-Host` are set.
+    pub enabled: bool,
+    /// Whether the RFC 7239 `Forwarded` header is set too.
+    pub rfc7239: bool,
+    /// Peers whose forwarding headers are kept and appended to. Those of anyone else are
+    /// replaced, as a client can send whatever it likes in them.
+    pub trusted_proxies: Vec<IpCidr>,
+}
+
+impl Default for ForwardedHeaders {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            rfc7239: false,
+            trusted_proxies: vec![],
+        }
+    }
+}
+
+impl ForwardedHeaders {
+    pub fn is_trusted(&self, peer: IpAddr) -> bool {
+        self.trusted_proxies.iter().any(|cidr| cidr.contains(&peer))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_template() {
+        let template: HeaderTemplate = "for=$client_ip; host=${host}:8080 $5".parse().unwrap();
+
+        assert_eq!(
+            template.parts,
+            vec![
+                TemplatePart::Literal("for=".to_string()),
+                TemplatePart::Variable(HeaderVariable::ClientIp),
+                TemplatePart::Literal("; host=".to_string()),
+                TemplatePart::Variable(HeaderVariable::Host),
+                TemplatePart::Literal(":8080 $5".to_string()),
+            ]
+        );
+        assert_eq!(
+            template.to_string(),
+            "for=${client_ip}; host=${host}:8080 $5"
+        );
+    }
+
+    #[test]
+    fn test_unknown_variable() {
+        let err = "$remote_user".parse::<HeaderTemplate>().unwrap_err();
+
+        assert_eq!(
+            err,
+            "unknown variable '$remote_user', expected one of $client_ip, $host"
+        );
+    }
+
+    #[test]
+    fn test_parse_upstream_accept_encoding() {
+        assert_eq!(
+            "passthrough".parse::<UpstreamAcceptEncoding>(),
+            Ok(UpstreamAcceptEncoding::Passthrough)
+        );
+        assert_eq!(
+            "identity".parse::<UpstreamAcceptEncoding>(),
+            Ok(UpstreamAcceptEncoding::Identity)
+        );
+        assert_eq!(
+            "gzip, BR".parse::<UpstreamAcceptEncoding>(),
+            Ok(UpstreamAcceptEncoding::Codings(vec![
+                "gzip".to_string(),
+                "br".to_string()
+            ]))
+        );
+
+        let err = "gzip,,br".parse::<UpstreamAcceptEncoding>().unwrap_err();
+        assert!(err.starts_with("unknown content coding ''"), "{err}");
+    }
+
+    #[test]
+    fn test_parse_upstream_host() {
+        assert_eq!(
+            "passthrough".parse::<UpstreamHost>(),
+            Ok(UpstreamHost::Passthrough)
+        );
+        assert_eq!(
+            "backend.internal:8080".parse::<UpstreamHost>(),
+            Ok(UpstreamHost::Override("backend.internal:8080".to_string()))
+        );
+
+        for invalid in [
+            "",
+            "backend internal",
+            "user@backend.internal",
+            "backend/api",
+            ":80",
+        ] {
+            assert!(
+                invalid.parse::<UpstreamHost>().is_err(),
+                "'{invalid}' should be rejected"
+            );
+        }
+    }
+}
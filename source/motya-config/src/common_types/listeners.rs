@@ -1,9 +1,80 @@
-use std::path::PathBuf;
+use std::{fmt, path::PathBuf, str::FromStr, time::Duration};
+
+use url::Url;
+
+/// Longest request target accepted when a listener doesn't set `max-uri-length`.
+///
+/// 8KiB is what nginx and Apache allow for a request line by default, so clients that
+/// work behind them work behind motya too.
+pub const DEFAULT_MAX_URI_LENGTH: usize = 8 * 1024;
+
+/// Longest single request header, name and value, accepted when a listener doesn't set
+/// `max-header-size`. Like nginx, the same as the request line.
+pub const DEFAULT_MAX_HEADER_SIZE: usize = 8 * 1024;
+
+/// Length of a `ticket-key-file`: a 16 byte key name, a 32 byte HMAC secret and a 32 byte
+/// AES key, the layout OpenSSL expects.
+pub const TICKET_KEY_LEN: usize = 80;
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct TlsConfig {
     pub cert_path: PathBuf,
     pub key_path: PathBuf,
+    /// Whether clients are handed session tickets to resume later connections with.
+    pub session_tickets: bool,
+    /// Key the session tickets are encrypted with, from `ticket-key-file`. `None` leaves
+    /// each process a random key of its own, so only the process that issued a ticket
+    /// accepts it.
+    pub ticket_key: Option<TicketKey>,
+    /// Refuses clients that can't negotiate HTTP/2 during the handshake, set by
+    /// `http-versions "2"`.
+    pub h2_only: bool,
+    /// Stapling of OCSP responses to the handshake, set by `ocsp-staple`.
+    pub ocsp: Option<OcspConfig>,
+}
+
+/// Where the OCSP responses of a listener's certificate are fetched from.
+#[derive(Debug, PartialEq, Clone)]
+pub struct OcspConfig {
+    /// `ocsp-responder`, or `None` to ask the responder named in the certificate.
+    pub responder: Option<Url>,
+}
+
+/// An HTTP version named in a listener's `http-versions`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum HttpVersion {
+    Http1,
+    Http2,
+}
+
+impl FromStr for HttpVersion {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "1.1" => Ok(Self::Http1),
+            "2" => Ok(Self::Http2),
+            other => Err(format!(
+                "unknown HTTP version '{other}', expected '1.1' or '2'"
+            )),
+        }
+    }
+}
+
+/// The contents of a `ticket-key-file`, read when the configuration is.
+#[derive(PartialEq, Clone)]
+pub struct TicketKey {
+    pub path: PathBuf,
+    pub material: [u8; TICKET_KEY_LEN],
+}
+
+// keeps the key itself out of `--dump-config` and logs
+impl fmt::Debug for TicketKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TicketKey")
+            .field("path", &self.path)
+            .finish_non_exhaustive()
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -14,11 +85,79 @@ pub enum ListenerKind {
         offer_h2: bool,
     },
     Uds(PathBuf),
+    /// A listening socket inherited from the process that started motya, as with systemd
+    /// socket activation. Only supported on Linux.
+    InheritedFd {
+        fd: i32,
+        tls: Option<TlsConfig>,
+        offer_h2: bool,
+    },
+}
+
+impl ListenerKind {
+    /// TLS settings of the listener, `None` for plaintext ones.
+    pub fn tls(&self) -> Option<&TlsConfig> {
+        match self {
+            ListenerKind::Tcp { tls, .. } | ListenerKind::InheritedFd { tls, .. } => tls.as_ref(),
+            ListenerKind::Uds(_) => None,
+        }
+    }
+
+    /// The inherited fd the listener takes over, `None` for ones motya binds itself.
+    pub fn inherited_fd(&self) -> Option<i32> {
+        match self {
+            ListenerKind::InheritedFd { fd, .. } => Some(*fd),
+            ListenerKind::Tcp { .. } | ListenerKind::Uds(_) => None,
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct ListenerConfig {
     pub source: ListenerKind,
+    /// Maximum number of in-flight requests accepted on this listener.
+    /// `None` means unlimited.
+    pub max_concurrent: Option<usize>,
+    /// Longest wait for data from the client before the connection is dropped.
+    /// `None` disables the timeout.
+    pub client_read_timeout: Option<Duration>,
+    /// Longest wait for the client to accept written data. `None` disables the timeout.
+    pub client_write_timeout: Option<Duration>,
+    /// Turns off Nagle's algorithm on accepted connections.
+    pub tcp_nodelay: bool,
+    /// Backlog of pending TCP Fast Open requests. `None` leaves Fast Open off.
+    pub tcp_fastopen: Option<usize>,
+    /// Length of the queue of connections waiting to be accepted. `None` keeps the default.
+    /// Only set on listeners with `auto_tls`, the sockets of the others are listened on by
+    /// pingora.
+    pub backlog: Option<u32>,
+    /// Serves TLS and plaintext HTTP on the same port, telling them apart by the first
+    /// byte the client sends. Only set on listeners with TLS.
+    pub auto_tls: bool,
+    /// Longest request target, in bytes, before the request is answered with `414`.
+    pub max_uri_length: usize,
+    /// Longest request header, counting its name and value, before the request is
+    /// answered with `431`.
+    pub max_header_size: usize,
+}
+
+impl ListenerConfig {
+    /// A listener on `source` with every other key at its default, as a bare address in a
+    /// `listeners` block gives.
+    pub fn new(source: ListenerKind) -> Self {
+        Self {
+            source,
+            max_concurrent: None,
+            client_read_timeout: None,
+            client_write_timeout: None,
+            tcp_nodelay: false,
+            tcp_fastopen: None,
+            backlog: None,
+            auto_tls: false,
+            max_uri_length: DEFAULT_MAX_URI_LENGTH,
+            max_header_size: DEFAULT_MAX_HEADER_SIZE,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
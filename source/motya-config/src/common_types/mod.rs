@@ -1,13 +1,22 @@
+pub mod access_log;
 pub mod bad;
 pub mod builtin_filters_name;
+pub mod cache;
+pub mod condition;
 pub mod connectors;
 pub mod definitions;
 pub mod definitions_table;
+pub mod dns;
+pub mod error_pages;
 pub mod file_server;
+pub mod headers;
 pub mod listeners;
+pub mod rate_limit;
 pub mod rate_limiter;
+pub mod routes;
 pub mod section_parser;
 pub mod service;
 pub mod services;
 pub mod simple_response_type;
+pub mod status_map;
 pub mod system_data;
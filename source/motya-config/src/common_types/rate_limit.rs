@@ -0,0 +1,58 @@
+use std::{fmt, num::NonZeroUsize, time::Duration};
+
+use url::Url;
+
+use crate::common_types::definitions::KeyTemplateConfig;
+
+/// Rate limiting for the upstreams of a section, with a token bucket per key.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateLimitConfig {
+    /// Key profile the requests are counted by, its `fallback` used when the key is empty.
+    pub key: KeyTemplateConfig,
+    /// Tokens a bucket starts with and never exceeds, one is taken per request.
+    pub tokens_per_bucket: NonZeroUsize,
+    /// Tokens put back into a bucket every `refill_interval`.
+    pub refill_qty: NonZeroUsize,
+    pub refill_interval: Duration,
+    /// How many keys are remembered at once.
+    pub max_buckets: NonZeroUsize,
+    /// Independently locked parts the buckets are split into by key, a power of two.
+    pub shards: NonZeroUsize,
+    /// Where the buckets are kept.
+    pub backend: RateLimitBackend,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum RateLimitBackend {
+    /// In this process, each instance limiting on its own.
+    #[default]
+    Local,
+    /// In Redis, shared by every instance using the same server and prefix.
+    Redis(RedisBackend),
+}
+
+#[derive(Clone, PartialEq)]
+pub struct RedisBackend {
+    /// `redis[s]://[user:password@]host[:port][/db]`.
+    pub url: Url,
+    /// Put in front of every bucket key, keeping apart limits that share a server.
+    pub prefix: String,
+    /// Whether requests fall back to the local buckets while Redis is unreachable,
+    /// rather than being refused.
+    pub fail_open: bool,
+}
+
+// keeps the password out of `--dump-config` and logs
+impl fmt::Debug for RedisBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut url = self.url.clone();
+        if url.password().is_some() {
+            let _ = url.set_password(Some("REDACTED"));
+        }
+        f.debug_struct("RedisBackend")
+            .field("url", &url.as_str())
+            .field("prefix", &self.prefix)
+            .field("fail_open", &self.fail_open)
+            .finish()
+    }
+}
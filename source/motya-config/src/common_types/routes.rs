@@ -0,0 +1,161 @@
+use std::{fmt, str::FromStr, time::Duration};
+
+use http::HeaderName;
+
+use crate::common_types::connectors::Connectors;
+
+/// A `route` whose traffic is divided between named connector groups.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SplitRouteConfig {
+    /// Path prefix of the requests the route applies to.
+    pub path: String,
+    /// How each request picks its group.
+    pub selector: RouteSelector,
+    /// Query parameter the requests must carry for the route to apply.
+    pub query: Option<QueryMatch>,
+    /// `request-timeout` of the requests it routes, over the one of their connector.
+    pub request_timeout: Option<Duration>,
+}
+
+/// `query="name"` or `query="name=value"`, a query parameter a request must carry.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryMatch {
+    pub name: String,
+    /// Value the parameter must have, any value when `None`.
+    pub value: Option<String>,
+}
+
+impl QueryMatch {
+    /// Whether the request `query` has the parameter, compared after decoding, so
+    /// `version=%32` matches `version=2`.
+    pub fn matches(&self, query: Option<&str>) -> bool {
+        let Some(query) = query else {
+            return false;
+        };
+
+        url::form_urlencoded::parse(query.as_bytes())
+            .filter(|(name, _)| *name == self.name)
+            .any(|(_, value)| match &self.value {
+                Some(expected) => *expected == value,
+                None => true,
+            })
+    }
+}
+
+impl fmt::Display for QueryMatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.value {
+            Some(value) => write!(f, "{}={value}", self.name),
+            None => f.write_str(&self.name),
+        }
+    }
+}
+
+impl FromStr for QueryMatch {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.contains('&') {
+            return Err("a route matches a single query parameter".to_string());
+        }
+
+        let (name, value) = match s.split_once('=') {
+            Some((name, value)) => (name, Some(value.to_string())),
+            None => (s, None),
+        };
+        if name.is_empty() {
+            return Err("expected 'name' or 'name=value'".to_string());
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            value,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RouteSelector {
+    /// `split`, a share of the traffic per group.
+    Split {
+        /// Groups in declaration order; the weights sum to 100.
+        targets: Vec<SplitTarget>,
+        /// Where to read the value that pins a client to one group.
+        sticky: Option<StickyKey>,
+    },
+    /// `match-header`, the group named for the value of a request header.
+    MatchHeader(HeaderMatch),
+}
+
+impl RouteSelector {
+    /// The connectors of every group the route can pick.
+    pub fn connectors(&self) -> Vec<&Connectors> {
+        match self {
+            RouteSelector::Split { targets, .. } => {
+                targets.iter().map(|target| &target.connectors).collect()
+            }
+            RouteSelector::MatchHeader(header_match) => header_match
+                .cases
+                .iter()
+                .map(|(_, group)| &group.connectors)
+                .chain([&header_match.default.connectors])
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HeaderMatch {
+    pub header: HeaderName,
+    /// Header values with the group each selects, in declaration order.
+    pub cases: Vec<(String, RouteGroup)>,
+    /// Group of the requests without the header, or with a value not listed.
+    pub default: RouteGroup,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RouteGroup {
+    pub group: String,
+    pub connectors: Connectors,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SplitTarget {
+    pub group: String,
+    /// Percentage of the route's traffic sent to this group.
+    pub weight: u8,
+    pub connectors: Connectors,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StickyKey {
+    Header(HeaderName),
+    Cookie(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_query_match() {
+        let presence = "beta".parse::<QueryMatch>().unwrap();
+        assert!(presence.matches(Some("beta")));
+        assert!(presence.matches(Some("page=1&beta=")));
+        assert!(!presence.matches(Some("betas=1")));
+        assert!(!presence.matches(None));
+
+        let value = "version=2".parse::<QueryMatch>().unwrap();
+        assert!(value.matches(Some("page=1&version=2")));
+        assert!(value.matches(Some("version=%32")));
+        assert!(!value.matches(Some("version=3")));
+        assert!(!value.matches(Some("version")));
+
+        let spaced = "q=a b".parse::<QueryMatch>().unwrap();
+        assert!(spaced.matches(Some("q=a+b")));
+
+        assert!("=2".parse::<QueryMatch>().is_err());
+        assert!("".parse::<QueryMatch>().is_err());
+        assert!("a=1&b=2".parse::<QueryMatch>().is_err());
+    }
+}
@@ -36,6 +36,9 @@ where
             name: self.name.to_string(),
             listeners,
             connectors,
+            error_pages: Default::default(),
+            access_log: Default::default(),
+            routes: vec![],
         })
     }
 }
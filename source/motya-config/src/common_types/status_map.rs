@@ -0,0 +1,24 @@
+use std::collections::BTreeMap;
+
+/// Status codes of upstream responses replaced before they reach the client, set by
+/// `response-status-map`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct StatusMap {
+    pub codes: BTreeMap<u16, u16>,
+}
+
+impl StatusMap {
+    /// The status to send in place of `status`, `None` to keep it.
+    pub fn get(&self, status: u16) -> Option<u16> {
+        self.codes.get(&status).copied()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.codes.is_empty()
+    }
+
+    /// Adds the codes of a nested section, replacing the mappings of the same codes.
+    pub fn extend(&mut self, other: StatusMap) {
+        self.codes.extend(other.codes);
+    }
+}
@@ -1,4 +1,4 @@
-use std::{net::SocketAddr, path::PathBuf};
+use std::{fmt, net::SocketAddr, path::PathBuf};
 
 use http::uri::PathAndQuery;
 
@@ -30,23 +30,56 @@ pub struct HttpProviderConfig {
     pub persist: bool,
 }
 
+/// The `status` endpoint, reporting the backends of every proxy service as JSON.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatusConfig {
+    pub addr: SocketAddr,
+    pub path: PathAndQuery,
+    /// Where the same listener serves metrics in the Prometheus text format.
+    pub metrics_path: PathAndQuery,
+}
+
+/// The `admin` endpoint, reloading the configuration on `POST /reload`.
+#[derive(Clone, PartialEq)]
+pub struct AdminConfig {
+    pub addr: SocketAddr,
+    /// Bearer token a reload request must present.
+    pub reload_token: String,
+}
+
+// keeps the token out of `--dump-config` and logs
+impl fmt::Debug for AdminConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AdminConfig")
+            .field("addr", &self.addr)
+            .finish_non_exhaustive()
+    }
+}
+
 #[derive(Debug)]
 pub struct SystemData {
     pub threads_per_service: usize,
+    /// Process-wide cap on simultaneous connections, across every listener.
+    pub max_connections: Option<usize>,
     pub daemonize: bool,
     pub upgrade_socket: Option<PathBuf>,
     pub pid_file: Option<PathBuf>,
     pub provider: Option<ConfigProvider>,
+    pub status: Option<StatusConfig>,
+    pub admin: Option<AdminConfig>,
 }
 
 impl Default for SystemData {
     fn default() -> Self {
         Self {
             threads_per_service: 8,
+            max_connections: None,
             daemonize: false,
             upgrade_socket: None,
             pid_file: None,
             provider: None,
+            status: None,
+            admin: None,
         }
     }
 }
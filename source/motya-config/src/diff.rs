@@ -0,0 +1,271 @@
+//! Structural differences between two versions of a service, for `motya diff`.
+
+use std::{collections::BTreeMap, fmt};
+
+use crate::{
+    common_types::{
+        connectors::{RouteMatcher, UpstreamConfig, UpstreamContextConfig},
+        definitions::{ConfiguredFilter, Modificator},
+        listeners::{ListenerConfig, ListenerKind},
+    },
+    internal::ProxyConfig,
+};
+
+/// Part of a service a [`DiffEntry`] is about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Section {
+    Listener,
+    Connector,
+    Filter,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    Added,
+    Removed,
+    Changed,
+}
+
+/// One listener, connector or filter that differs between the two versions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiffEntry {
+    pub section: Section,
+    /// What the item is known by in both versions, like the address of a listener.
+    pub key: String,
+    pub change: Change,
+}
+
+/// Differences between two versions of a service, see [`diff`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConfigDiff {
+    pub entries: Vec<DiffEntry>,
+}
+
+impl ConfigDiff {
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl fmt::Display for Section {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Section::Listener => "listener",
+            Section::Connector => "connector",
+            Section::Filter => "filter",
+        })
+    }
+}
+
+impl fmt::Display for DiffEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = match self.change {
+            Change::Added => '+',
+            Change::Removed => '-',
+            Change::Changed => '~',
+        };
+        write!(f, "{sign} {} {}", self.section, self.key)
+    }
+}
+
+/// Compares two versions of a service as parsed, so formatting and the order of keys
+/// make no difference.
+///
+/// Listeners are matched by their address, connectors by the paths they route and
+/// filters by their connector, chain and name. A connector whose filters changed is
+/// only reported through its filters.
+pub fn diff(old: &ProxyConfig, new: &ProxyConfig) -> ConfigDiff {
+    let mut entries = vec![];
+
+    diff_keyed(
+        Section::Listener,
+        listeners(old),
+        listeners(new),
+        &mut entries,
+    );
+    diff_keyed(
+        Section::Connector,
+        connectors(old),
+        connectors(new),
+        &mut entries,
+    );
+    diff_keyed(Section::Filter, filters(old), filters(new), &mut entries);
+
+    ConfigDiff { entries }
+}
+
+fn diff_keyed<T: PartialEq>(
+    section: Section,
+    old: BTreeMap<String, T>,
+    mut new: BTreeMap<String, T>,
+    entries: &mut Vec<DiffEntry>,
+) {
+    let entry = |key: String, change| DiffEntry {
+        section,
+        key,
+        change,
+    };
+
+    for (key, old_value) in old {
+        match new.remove(&key) {
+            None => entries.push(entry(key, Change::Removed)),
+            Some(new_value) if new_value != old_value => entries.push(entry(key, Change::Changed)),
+            Some(_) => {}
+        }
+    }
+    entries.extend(new.into_keys().map(|key| entry(key, Change::Added)));
+}
+
+/// Collects keyed `items`, numbering the repeats of a key in order, as `key #2`.
+fn keyed<T>(items: impl IntoIterator<Item = (String, T)>) -> BTreeMap<String, T> {
+    let mut map = BTreeMap::new();
+
+    for (key, item) in items {
+        let mut unique = key.clone();
+        let mut n = 1;
+        while map.contains_key(&unique) {
+            n += 1;
+            unique = format!("{key} #{n}");
+        }
+        map.insert(unique, item);
+    }
+
+    map
+}
+
+fn listeners(proxy: &ProxyConfig) -> BTreeMap<String, &ListenerConfig> {
+    keyed(proxy.listeners.list_cfgs.iter().map(|cfg| {
+        let key = match &cfg.source {
+            ListenerKind::Tcp { addr, .. } => addr.clone(),
+            ListenerKind::Uds(path) => format!("unix socket {}", path.display()),
+            ListenerKind::InheritedFd { fd, .. } => format!("inherited fd {fd}"),
+        };
+        (key, cfg)
+    }))
+}
+
+/// Connectors without their chains, whose filters are compared one by one.
+fn connectors(proxy: &ProxyConfig) -> BTreeMap<String, UpstreamContextConfig> {
+    keyed(proxy.connectors.upstreams.iter().map(|upstream| {
+        let connector = UpstreamContextConfig {
+            chains: vec![],
+            ..upstream.clone()
+        };
+        (connector_key(upstream), connector)
+    }))
+}
+
+fn filters(proxy: &ProxyConfig) -> BTreeMap<String, &ConfiguredFilter> {
+    keyed(proxy.connectors.upstreams.iter().flat_map(|upstream| {
+        let connector = connector_key(upstream);
+        upstream
+            .chains
+            .iter()
+            .flat_map(move |Modificator::Chain(named)| {
+                let connector = connector.clone();
+                named.chain.filters.iter().map(move |filter| {
+                    let key = format!("{connector} chain '{}' {}", named.name, filter.name);
+                    (key, filter)
+                })
+            })
+    }))
+}
+
+fn connector_key(upstream: &UpstreamContextConfig) -> String {
+    let (prefix_path, matcher) = match &upstream.upstream {
+        UpstreamConfig::Service(peer) => (&peer.prefix_path, peer.matcher),
+        UpstreamConfig::MultiServer(pool) => (&pool.prefix_path, pool.matcher),
+        UpstreamConfig::Static(response) => (&response.prefix_path, RouteMatcher::Exact),
+    };
+
+    match (&upstream.path_regex, matcher) {
+        (Some(regex), _) => format!("path-regex '{}'", regex.0.as_str()),
+        (None, RouteMatcher::Prefix) => format!("prefix {}", prefix_path.path()),
+        (None, RouteMatcher::Exact) => format!("exact {}", prefix_path.path()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use kdl::KdlDocument;
+
+    use super::*;
+    use crate::{common_types::definitions_table::DefinitionsTable, kdl::compiler::ConfigCompiler};
+
+    fn compile(listener: &str, filter: &str) -> ProxyConfig {
+        let kdl_input = format!(
+            r#"
+            system {{ }}
+            definitions {{
+                modifiers {{
+                    chain-filters "auth" {{
+                        {filter}
+                    }}
+                }}
+            }}
+            services {{
+                Api {{
+                    listeners {{
+                        {listener}
+                        "0.0.0.0:80"
+                    }}
+                    connectors {{
+                        section "/api" as="prefix" {{
+                            use-chain "auth"
+                            proxy "127.0.0.1:3001"
+                        }}
+                        proxy "127.0.0.1:3000"
+                    }}
+                }}
+            }}
+            "#
+        );
+        let doc: KdlDocument = kdl_input.parse().unwrap();
+        let mut config = ConfigCompiler::new(vec![(doc, "test".to_string())])
+            .compile(&mut DefinitionsTable::new_with_global())
+            .expect("Should compile");
+        config.basic_proxies.remove(0)
+    }
+
+    #[test]
+    fn test_reordered_keys_have_no_diff() {
+        let old = compile(
+            r#""0.0.0.0:443" cert-path="/etc/motya/cert.pem" key-path="/etc/motya/key.pem""#,
+            r#"filter name="motya.request.upsert-header" key="X-Auth" value="1""#,
+        );
+        let new = compile(
+            r#""0.0.0.0:443"   key-path="/etc/motya/key.pem" cert-path="/etc/motya/cert.pem""#,
+            r#"filter value="1" name="motya.request.upsert-header"  key="X-Auth""#,
+        );
+
+        assert_eq!(diff(&old, &new), ConfigDiff::default());
+    }
+
+    #[test]
+    fn test_changed_entries() {
+        let filter = r#"filter name="motya.request.upsert-header" key="X-Auth" value="1""#;
+        let old = compile(
+            r#""0.0.0.0:443" cert-path="/etc/motya/cert.pem" key-path="/etc/motya/key.pem""#,
+            filter,
+        );
+        let new = compile(
+            r#""0.0.0.0:443" cert-path="/etc/motya/new-cert.pem" key-path="/etc/motya/key.pem""#,
+            &format!(
+                "{filter}\nfilter name=\"motya.response.upsert-header\" key=\"X-Seen\" value=\"1\""
+            ),
+        );
+
+        let found = diff(&old, &new)
+            .entries
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            vec![
+                "~ listener 0.0.0.0:443",
+                "+ filter prefix /api chain 'auth' motya.response.upsert-header",
+            ]
+        );
+    }
+}
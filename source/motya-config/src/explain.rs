@@ -0,0 +1,243 @@
+//! Plain-words description of a service, for `motya explain`.
+
+use crate::{
+    common_types::{
+        connectors::{RouteMatcher, UpstreamConfig, UpstreamContextConfig},
+        definitions::Modificator,
+        listeners::{ListenerKind, Listeners},
+        routes::RouteSelector,
+    },
+    internal::{ProxyConfig, SelectionKind},
+};
+
+/// Describes what a service does, one sentence per line:
+///
+/// ```text
+/// Service 'Api':
+///   Listens on 0.0.0.0:443 (TLS, h2).
+///   Routes /api to a pool of 3 upstreams (round-robin), applying 2 filters.
+/// ```
+///
+/// The description follows the resolved configuration, so defaults such as the
+/// round-robin selection of a pool without `load-balance` are spelled out.
+pub fn explain(proxy: &ProxyConfig) -> String {
+    let mut lines = vec![
+        format!("Service '{}':", proxy.name),
+        format!("Listens on {}.", describe_listeners(&proxy.listeners)),
+    ];
+
+    for route in &proxy.routes {
+        let scope = match &route.query {
+            Some(query) => format!("{} with '{query}' in their query", route.path),
+            None => route.path.clone(),
+        };
+        lines.push(match &route.selector {
+            RouteSelector::Split { targets, .. } => {
+                let groups = targets
+                    .iter()
+                    .map(|target| format!("{}% to '{}'", target.weight, target.group))
+                    .collect::<Vec<_>>();
+                format!(
+                    "Splits requests under {scope} between groups: {}.",
+                    groups.join(", ")
+                )
+            }
+            RouteSelector::MatchHeader(header_match) => {
+                let cases = header_match
+                    .cases
+                    .iter()
+                    .map(|(value, target)| format!("'{value}' to '{}'", target.group))
+                    .collect::<Vec<_>>();
+                format!(
+                    "Sends requests under {scope} by their {} header: {}, others to '{}'.",
+                    header_match.header,
+                    cases.join(", "),
+                    header_match.default.group
+                )
+            }
+        });
+    }
+
+    for upstream in &proxy.connectors.upstreams {
+        lines.push(describe_upstream(upstream));
+    }
+
+    let mut text = lines.join("\n  ");
+    text.push('\n');
+    text
+}
+
+fn describe_listeners(listeners: &Listeners) -> String {
+    let listeners = listeners
+        .list_cfgs
+        .iter()
+        .map(|cfg| match &cfg.source {
+            ListenerKind::Tcp {
+                addr,
+                tls: Some(_),
+                offer_h2: true,
+            } => format!("{addr} (TLS, h2)"),
+            ListenerKind::Tcp {
+                addr, tls: Some(_), ..
+            } => format!("{addr} (TLS)"),
+            ListenerKind::Tcp {
+                addr, tls: None, ..
+            } => addr.clone(),
+            ListenerKind::Uds(path) => format!("unix socket {}", path.display()),
+            ListenerKind::InheritedFd {
+                fd,
+                tls: Some(_),
+                offer_h2: true,
+            } => format!("inherited fd {fd} (TLS, h2)"),
+            ListenerKind::InheritedFd {
+                fd, tls: Some(_), ..
+            } => {
+                format!("inherited fd {fd} (TLS)")
+            }
+            ListenerKind::InheritedFd { fd, tls: None, .. } => format!("inherited fd {fd}"),
+        })
+        .collect::<Vec<_>>();
+
+    match listeners.split_last() {
+        Some((last, [])) => last.clone(),
+        Some((last, rest)) => format!("{} and {last}", rest.join(", ")),
+        None => "nothing".to_string(),
+    }
+}
+
+fn describe_upstream(upstream: &UpstreamContextConfig) -> String {
+    let (prefix_path, matcher) = match &upstream.upstream {
+        UpstreamConfig::Service(peer) => (&peer.prefix_path, peer.matcher),
+        UpstreamConfig::MultiServer(pool) => (&pool.prefix_path, pool.matcher),
+        UpstreamConfig::Static(response) => (&response.prefix_path, RouteMatcher::Exact),
+    };
+    let path = match (&upstream.path_regex, matcher) {
+        (Some(regex), _) => format!("paths matching '{}'", regex.0.as_str()),
+        (None, RouteMatcher::Prefix) => format!("requests under {}", prefix_path.path()),
+        (None, RouteMatcher::Exact) => prefix_path.path().to_string(),
+    };
+
+    let mut sentence = match &upstream.upstream {
+        UpstreamConfig::Service(peer) if peer.tls => {
+            format!(
+                "Routes {path} to {} (TLS, SNI '{}')",
+                peer.peer_address, peer.sni
+            )
+        }
+        UpstreamConfig::Service(peer) => format!("Routes {path} to {}", peer.peer_address),
+        UpstreamConfig::MultiServer(pool) => {
+            let selection = upstream
+                .lb_options
+                .as_ref()
+                .map(|options| &options.selection)
+                .unwrap_or(&SelectionKind::RoundRobin);
+            let mut sentence = format!(
+                "Routes {path} to a pool of {} upstreams ({})",
+                pool.servers.len(),
+                describe_selection(selection)
+            );
+            match pool.pinned.len() {
+                0 => {}
+                1 => sentence.push_str(" or 1 upstream pinned by 'when'"),
+                n => sentence.push_str(&format!(" or {n} upstreams pinned by 'when'")),
+            }
+            sentence
+        }
+        UpstreamConfig::Static(response) => format!(
+            "Answers {path} with a static {} response",
+            response.http_code.as_u16()
+        ),
+    };
+
+    let filters = upstream
+        .chains
+        .iter()
+        .map(|Modificator::Chain(named)| {
+            let chain = &named.chain.filters;
+            chain.iter().filter(|filter| filter.enabled).count()
+        })
+        .sum::<usize>();
+    match filters {
+        0 => {}
+        1 => sentence.push_str(", applying 1 filter"),
+        n => sentence.push_str(&format!(", applying {n} filters")),
+    }
+    if upstream.cache.is_some() {
+        sentence.push_str(", caching responses");
+    }
+
+    sentence.push('.');
+    sentence
+}
+
+fn describe_selection(selection: &SelectionKind) -> &'static str {
+    match selection {
+        SelectionKind::RoundRobin => "round-robin",
+        SelectionKind::Random => "random",
+        SelectionKind::FvnHash => "FNV hashing",
+        SelectionKind::KetamaHashing => "Ketama hashing",
+        SelectionKind::WeightedRandom => "weighted random",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use kdl::KdlDocument;
+
+    use super::*;
+    use crate::{common_types::definitions_table::DefinitionsTable, kdl::compiler::ConfigCompiler};
+
+    #[test]
+    fn test_explain_service() {
+        let kdl_input = r#"
+            system { }
+            definitions {
+                modifiers {
+                    chain-filters "auth" {
+                        filter name="motya.request.upsert-header" key="X-Auth" value="1"
+                        filter name="motya.request.upsert-header" key="X-Off" value="1" enabled=#false
+                        filter name="motya.response.upsert-header" key="X-Seen" value="1"
+                    }
+                }
+            }
+            services {
+                Api {
+                    listeners {
+                        "0.0.0.0:443" cert-path="/etc/motya/cert.pem" key-path="/etc/motya/key.pem"
+                        "0.0.0.0:80"
+                    }
+                    connectors {
+                        section "/api" as="prefix" {
+                            use-chain "auth"
+                            proxy {
+                                server "127.0.0.1:3001"
+                                server "127.0.0.1:3002"
+                                server "127.0.0.1:3003"
+                            }
+                        }
+                        proxy "127.0.0.1:3000"
+                    }
+                }
+            }
+        "#;
+        let doc: KdlDocument = kdl_input.parse().unwrap();
+        let config = ConfigCompiler::new(vec![(doc, "test".to_string())])
+            .compile(&mut DefinitionsTable::new_with_global())
+            .expect("Should compile");
+
+        let text = explain(&config.basic_proxies[0]);
+
+        assert!(text.starts_with("Service 'Api':"), "{text}");
+        assert!(
+            text.contains("Listens on 0.0.0.0:443 (TLS, h2) and 0.0.0.0:80."),
+            "{text}"
+        );
+        assert!(
+            text.contains(
+                "Routes requests under /api to a pool of 3 upstreams (round-robin), applying 2 filters."
+            ),
+            "{text}"
+        );
+        assert!(text.contains("to 127.0.0.1:3000."), "{text}");
+    }
+}
@@ -1,8 +1,15 @@
-use std::{path::PathBuf, str::FromStr};
+use std::{path::PathBuf, str::FromStr, time::Duration};
 
 use crate::common_types::{
-    connectors::Connectors, definitions::KeyTemplateConfig, file_server::FileServerConfig,
+    access_log::AccessLogFormat,
+    connectors::Connectors,
+    definitions::KeyTemplateConfig,
+    dns::DnsConfig,
+    error_pages::ErrorPages,
+    file_server::FileServerConfig,
     listeners::Listeners,
+    routes::SplitRouteConfig,
+    system_data::{AdminConfig, StatusConfig},
 };
 
 use tracing::warn;
@@ -12,10 +19,18 @@ use tracing::warn;
 pub struct Config {
     pub validate_configs: bool,
     pub threads_per_service: usize,
+    /// Process-wide cap on simultaneous connections, shared by every listener.
+    pub max_connections: Option<usize>,
     pub daemonize: bool,
     pub pid_file: Option<PathBuf>,
     pub upgrade_socket: Option<PathBuf>,
     pub upgrade: bool,
+    /// Listener of the `status` endpoint, `None` when it's not served.
+    pub status: Option<StatusConfig>,
+    /// Listener of the `admin` endpoint, `None` when reloads can't be requested over HTTP.
+    pub admin: Option<AdminConfig>,
+    /// Servers upstream host names are resolved with, the system resolver when `None`.
+    pub dns: Option<DnsConfig>,
     pub basic_proxies: Vec<ProxyConfig>,
     pub file_servers: Vec<FileServerConfig>,
 }
@@ -66,6 +81,11 @@ pub struct ProxyConfig {
     pub name: String,
     pub listeners: Listeners,
     pub connectors: Connectors,
+    pub error_pages: ErrorPages,
+    /// Format of the access record written for each request.
+    pub access_log: AccessLogFormat,
+    /// `route` blocks, matched before the service's own connectors.
+    pub routes: Vec<SplitRouteConfig>,
     // pub rate_limiting: RateLimitingConfig,
 }
 
@@ -75,6 +95,18 @@ pub struct UpstreamOptions {
     pub template: Option<KeyTemplateConfig>,
     pub health_checks: HealthCheckKind,
     pub discovery: DiscoveryKind,
+    /// Interval over which a freshly healthy server ramps up to its configured weight.
+    pub slow_start: Option<Duration>,
+    pub outlier_detection: Option<OutlierDetection>,
+}
+
+/// Passive ejection of servers that keep failing.
+#[derive(Debug, PartialEq, Clone)]
+pub struct OutlierDetection {
+    /// Consecutive 5xx responses or connect errors that eject a server.
+    pub consecutive_errors: usize,
+    /// How long an ejected server is kept out of the pool.
+    pub ejection_time: Duration,
 }
 
 impl Default for UpstreamOptions {
@@ -84,6 +116,8 @@ impl Default for UpstreamOptions {
             template: None,
             health_checks: HealthCheckKind::None,
             discovery: DiscoveryKind::Static,
+            slow_start: None,
+            outlier_detection: None,
         }
     }
 }
@@ -94,6 +128,7 @@ pub enum SelectionKind {
     Random,
     FvnHash,
     KetamaHashing,
+    WeightedRandom,
 }
 
 impl FromStr for SelectionKind {
@@ -105,6 +140,7 @@ impl FromStr for SelectionKind {
             "Random" => Ok(SelectionKind::Random),
             "FNV" => Ok(SelectionKind::FvnHash),
             "Ketama" => Ok(SelectionKind::KetamaHashing),
+            "WeightedRandom" => Ok(SelectionKind::WeightedRandom),
             str => Err(format!("unknown selection kind, {str}")),
         }
     }
@@ -113,6 +149,8 @@ impl FromStr for SelectionKind {
 #[derive(Debug, PartialEq, Clone)]
 pub enum HealthCheckKind {
     None,
+    /// Servers are probed by opening a TCP connection to them every `interval`.
+    Tcp { interval: Duration },
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -129,12 +167,16 @@ impl Default for Config {
         Self {
             validate_configs: false,
             threads_per_service: 8,
+            max_connections: None,
             basic_proxies: vec![],
             file_servers: vec![],
             daemonize: false,
             pid_file: None,
             upgrade_socket: Some(PathBuf::from("/tmp/motya-upgrade.sock")),
             upgrade: false,
+            status: None,
+            admin: None,
+            dns: None,
         }
     }
 }
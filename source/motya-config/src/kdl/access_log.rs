@@ -0,0 +1,81 @@
+use motya_macro::validate;
+
+use crate::{
+    block_parser,
+    common_types::{access_log::AccessLogFormat, section_parser::SectionParser},
+    kdl::parser::{ctx::ParseContext, ensures::Rule},
+};
+
+/// Parses the `access-log { ... }` block of a proxy service.
+pub struct AccessLogSection;
+
+impl SectionParser<ParseContext<'_>, AccessLogFormat> for AccessLogSection {
+    #[validate(ensure_node_name = "access-log")]
+    fn parse_node(&self, ctx: ParseContext<'_>) -> miette::Result<AccessLogFormat> {
+        ctx.validate(&[Rule::ReqChildren, Rule::NoArgs])?;
+
+        block_parser!(ctx.enter_block()?,
+            format: optional("format") => |ctx| {
+                ctx.validate(&[Rule::NoChildren, Rule::ExactArgs(1), Rule::OnlyKeys(&[])])?;
+
+                let value = ctx.first()?;
+                value
+                    .as_str()?
+                    .parse::<AccessLogFormat>()
+                    .map_err(|err| value.error(format!("Invalid access log format: {err}")))
+            }
+        );
+
+        Ok(format.unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use kdl::KdlDocument;
+
+    use super::*;
+    use crate::{
+        assert_err_contains,
+        common_types::{access_log::AccessLogVariable, headers::TemplatePart},
+        kdl::parser::{block::BlockParser, ctx::Current},
+    };
+
+    fn parse_access_log(input: &str) -> miette::Result<AccessLogFormat> {
+        let doc: KdlDocument = input.parse().unwrap();
+        let ctx = ParseContext::new(&doc, Current::Document(&doc), "test");
+        let mut block = BlockParser::new(ctx)?;
+
+        block.required("access-log", |ctx| AccessLogSection.parse_node(ctx))
+    }
+
+    #[test]
+    fn test_parse_access_log() {
+        let format = parse_access_log(
+            r#"access-log { format "$status ${upstream_addr} in $upstream_response_time"; }"#,
+        )
+        .expect("Should parse access-log");
+
+        assert_eq!(
+            format.parts,
+            vec![
+                TemplatePart::Variable(AccessLogVariable::Status),
+                TemplatePart::Literal(" ".to_string()),
+                TemplatePart::Variable(AccessLogVariable::UpstreamAddr),
+                TemplatePart::Literal(" in ".to_string()),
+                TemplatePart::Variable(AccessLogVariable::UpstreamResponseTime),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_access_log_unknown_variable() {
+        let result = parse_access_log(r#"access-log { format "$status $upstream_time"; }"#);
+
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(
+            err_msg,
+            "Invalid access log format: unknown variable '$upstream_time'"
+        );
+    }
+}
@@ -0,0 +1,232 @@
+use http::Method;
+
+use crate::{
+    block_parser,
+    common_types::{cache::CacheConfig, definitions_table::DefinitionsTable},
+    kdl::parser::{ctx::ParseContext, ensures::Rule, typed_value::TypedValue},
+};
+
+/// Total size of the cached bodies when `max-size` is not given.
+const DEFAULT_MAX_SIZE: usize = 64 << 20;
+
+/// Parses a `cache { ... }` block of a connectors section.
+pub struct CacheParser<'a> {
+    table: &'a DefinitionsTable,
+}
+
+impl<'a> CacheParser<'a> {
+    pub fn new(table: &'a DefinitionsTable) -> Self {
+        Self { table }
+    }
+
+    pub fn parse(&self, ctx: ParseContext<'_>) -> miette::Result<CacheConfig> {
+        ctx.validate(&[Rule::ReqChildren, Rule::NoArgs])?;
+
+        let block_ctx = ctx.enter_block()?;
+
+        block_parser!(block_ctx,
+            key: required("key-profile") => |ctx| {
+                ctx.validate(&[Rule::NoChildren, Rule::ExactArgs(1), Rule::OnlyKeys(&[])])?;
+
+                let name = ctx.first()?.as_str()?;
+
+                self.table
+                    .get_key_templates()
+                    .get(&name)
+                    .cloned()
+                    .ok_or_else(|| ctx.error(format!("Key profile '{name}' not found")))
+            },
+
+            ttl: required("ttl") => |ctx| {
+                ctx.validate(&[Rule::NoChildren, Rule::ExactArgs(1), Rule::OnlyKeys(&[])])?;
+
+                let ttl = ctx.first()?.as_duration()?;
+
+                if ttl.is_zero() {
+                    return Err(ctx.error("'ttl' must be positive"));
+                }
+
+                Ok(ttl)
+            },
+
+            stale_while_revalidate: optional("stale-while-revalidate") => |ctx| {
+                ctx.validate(&[Rule::NoChildren, Rule::ExactArgs(1), Rule::OnlyKeys(&[])])?;
+
+                let window = ctx.first()?.as_duration()?;
+
+                if window.is_zero() {
+                    return Err(ctx.error("'stale-while-revalidate' must be positive"));
+                }
+
+                Ok((window, ctx.current_span()))
+            },
+
+            max_size: optional("max-size") => |ctx| {
+                ctx.validate(&[Rule::NoChildren, Rule::ExactArgs(1), Rule::OnlyKeys(&[])])?;
+
+                let max_size = ctx.first()?.as_byte_size()?;
+
+                if max_size == 0 {
+                    return Err(ctx.error("'max-size' must be positive"));
+                }
+
+                Ok(max_size)
+            },
+
+            max_object_size: optional("max-object-size") => |ctx| {
+                ctx.validate(&[Rule::NoChildren, Rule::ExactArgs(1), Rule::OnlyKeys(&[])])?;
+
+                let max_object_size = ctx.first()?.as_byte_size()?;
+
+                if max_object_size == 0 {
+                    return Err(ctx.error("'max-object-size' must be positive"));
+                }
+
+                Ok(max_object_size)
+            },
+
+            methods: optional("methods") => |ctx| {
+                ctx.validate(&[Rule::NoChildren, Rule::OnlyKeys(&[])])?;
+
+                let methods = ctx
+                    .args()?
+                    .iter()
+                    .map(|entry| {
+                        let value = TypedValue::new(&ctx, entry);
+                        let method = value.parse_as::<Method>()?;
+
+                        if method != Method::GET && method != Method::HEAD {
+                            return Err(value.error(format!(
+                                "Responses to '{method}' requests cannot be cached. Use 'GET' or 'HEAD'"
+                            )));
+                        }
+
+                        Ok(method)
+                    })
+                    .collect::<miette::Result<Vec<_>>>()?;
+
+                if methods.is_empty() {
+                    return Err(ctx.error("'methods' requires at least one method"));
+                }
+
+                Ok(methods)
+            }
+        );
+
+        let stale_while_revalidate = match stale_while_revalidate {
+            Some((window, span)) if window > ttl => {
+                return Err(block_ctx.error_with_span(
+                    format!(
+                        "'stale-while-revalidate' ({window:?}) must not be longer than 'ttl' ({ttl:?})"
+                    ),
+                    span,
+                ));
+            }
+            other => other.map(|(window, _)| window),
+        };
+
+        Ok(CacheConfig {
+            key,
+            ttl,
+            stale_while_revalidate,
+            max_size: max_size.unwrap_or(DEFAULT_MAX_SIZE),
+            max_object_size,
+            methods: methods.unwrap_or_else(|| vec![Method::GET, Method::HEAD]),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use kdl::KdlDocument;
+
+    use super::*;
+    use crate::{
+        assert_err_contains,
+        common_types::definitions::{HashAlgorithm, KeyTemplateConfig},
+        kdl::parser::{block::BlockParser, ctx::Current},
+    };
+
+    fn parse_cache(input: &str) -> miette::Result<CacheConfig> {
+        let mut table = DefinitionsTable::default();
+        table.insert_key_profile(
+            "page-key".to_string(),
+            KeyTemplateConfig {
+                source: "${uri-path}".to_string(),
+                fallback: None,
+                algorithm: HashAlgorithm {
+                    name: "xxhash64".to_string(),
+                    seed: None,
+                },
+                transforms: vec![],
+            },
+        );
+
+        let doc: KdlDocument = input.parse().unwrap();
+        let ctx = ParseContext::new(&doc, Current::Document(&doc), "test");
+        let mut block = BlockParser::new(ctx)?;
+
+        block.required("cache", |ctx| CacheParser::new(&table).parse(ctx))
+    }
+
+    #[test]
+    fn test_parse_cache() {
+        let cache = parse_cache(
+            r#"
+            cache {
+                key-profile "page-key"
+                ttl "60s"
+                max-size "100MB"
+                max-object-size "1MB"
+                methods "GET"
+            }
+        "#,
+        )
+        .expect("Should parse cache");
+
+        assert_eq!(cache.key.source, "${uri-path}");
+        assert_eq!(cache.ttl, Duration::from_secs(60));
+        assert_eq!(cache.stale_while_revalidate, None);
+        assert_eq!(cache.max_size, 100 * 1024 * 1024);
+        assert_eq!(cache.max_object_size, Some(1024 * 1024));
+        assert_eq!(cache.methods, vec![Method::GET]);
+    }
+
+    #[test]
+    fn test_unknown_key_profile() {
+        let result = parse_cache(r#"cache { key-profile "missing"; ttl "60s"; }"#);
+
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(err_msg, "Key profile 'missing' not found");
+    }
+
+    #[test]
+    fn test_invalid_size_and_method() {
+        let result =
+            parse_cache(r#"cache { key-profile "page-key"; ttl "60s"; max-size "lots"; }"#);
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(err_msg, "Invalid size 'lots'");
+
+        let result =
+            parse_cache(r#"cache { key-profile "page-key"; ttl "60s"; methods "GET" "POST"; }"#);
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(err_msg, "Responses to 'POST' requests cannot be cached");
+    }
+
+    #[test]
+    fn test_stale_while_revalidate() {
+        let cache = parse_cache(
+            r#"cache { key-profile "page-key"; ttl "60s"; stale-while-revalidate "30s"; }"#,
+        )
+        .expect("Should parse cache");
+        assert_eq!(cache.stale_while_revalidate, Some(Duration::from_secs(30)));
+
+        let result = parse_cache(
+            r#"cache { key-profile "page-key"; ttl "10s"; stale-while-revalidate "30s"; }"#,
+        );
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(err_msg, "must not be longer than 'ttl'");
+    }
+}
@@ -1,31 +1,122 @@
 use crate::{
-    common_types::definitions::{ConfiguredFilter, FilterChain},
-    kdl::parser::{block::BlockParser, ctx::ParseContext, ensures::Rule},
+    common_types::{
+        condition::Condition,
+        definitions::{ConfiguredFilter, FilterChain, FilterErrorPolicy},
+    },
+    kdl::parser::{
+        block::BlockParser,
+        ctx::ParseContext,
+        ensures::Rule,
+        utils::{OptionTypedValueExt, PrimitiveType},
+    },
 };
 use std::collections::HashMap;
 
+/// Size of a WASM memory page, the smallest `max-memory` that lets a module have memory.
+const WASM_PAGE_SIZE: usize = 64 << 10;
+
+/// Largest request body a filter can be given, the size of pingora's retry buffer the body
+/// is replayed from once the filter has read it. Also the default `max-body-size`.
+pub const MAX_FILTER_BODY_SIZE: usize = 64 << 10;
+
 pub struct ChainParser;
 
 impl ChainParser {
     pub fn parse(&self, ctx: ParseContext<'_>) -> miette::Result<FilterChain> {
         let mut block = BlockParser::new(ctx)?;
-        let filters = block.repeated("filter", |filter_ctx| {
-            filter_ctx.validate(&[Rule::NoChildren, Rule::NoPositionalArgs])?;
+        let mut filters = block.repeated("filter", |filter_ctx| {
+            filter_ctx.validate(&[
+                Rule::NoChildren,
+                Rule::NoPositionalArgs,
+                Rule::KeysTyped(&[
+                    ("enabled", PrimitiveType::Bool),
+                    ("on-filter-error", PrimitiveType::String),
+                    ("max-memory", PrimitiveType::String),
+                    ("request-body", PrimitiveType::Bool),
+                    ("max-body-size", PrimitiveType::String),
+                    ("when", PrimitiveType::String),
+                    ("priority", PrimitiveType::Integer),
+                ]),
+            ])?;
 
             let name = filter_ctx.prop("name")?.parse_as::<fqdn::FQDN>()?;
+            let enabled = filter_ctx.opt_prop("enabled")?.as_bool()?.unwrap_or(true);
+            let on_error = filter_ctx
+                .opt_prop("on-filter-error")?
+                .parse_as::<FilterErrorPolicy>()?;
+
+            let max_memory = match filter_ctx.opt_prop("max-memory")? {
+                Some(value) => {
+                    let size = value.as_byte_size()?;
+                    if size < WASM_PAGE_SIZE {
+                        return Err(value.error(
+                            "'max-memory' must be at least 64KB, the size of one WASM page",
+                        ));
+                    }
+                    Some(size)
+                }
+                None => None,
+            };
+
+            let request_body = filter_ctx
+                .opt_prop("request-body")?
+                .as_bool()?
+                .unwrap_or(false);
+            let max_body_size = match (request_body, filter_ctx.opt_prop("max-body-size")?) {
+                (false, Some(value)) => {
+                    return Err(value.error("'max-body-size' needs 'request-body=#true'"));
+                }
+                (false, None) => None,
+                (true, None) => Some(MAX_FILTER_BODY_SIZE),
+                (true, Some(value)) => {
+                    let size = value.as_byte_size()?;
+                    if size == 0 || size > MAX_FILTER_BODY_SIZE {
+                        return Err(value.error(
+                            "'max-body-size' must be between 1 byte and 64KB, the most the proxy \
+                             keeps of a body to replay it upstream",
+                        ));
+                    }
+                    Some(size)
+                }
+            };
+
+            let when = filter_ctx.opt_prop("when")?.parse_as::<Condition>()?;
+            let priority = filter_ctx.opt_prop("priority")?.as_i32()?.unwrap_or(0);
 
             let all_args = filter_ctx.args_map(1..)?;
 
             let args = all_args
                 .into_iter()
+                .filter(|(k, _)| {
+                    !matches!(
+                        *k,
+                        "on-filter-error"
+                            | "max-memory"
+                            | "request-body"
+                            | "max-body-size"
+                            | "when"
+                    )
+                })
                 .map(|(k, v)| (k.to_string(), v.to_string()))
                 .collect::<HashMap<_, _>>();
 
-            Ok(ConfiguredFilter { name, args })
+            Ok(ConfiguredFilter {
+                name,
+                args,
+                enabled,
+                on_error,
+                max_memory,
+                max_body_size,
+                when,
+                priority,
+            })
         })?;
 
         block.exhaust()?;
 
+        // stable, so filters of the same priority keep the order they are written in
+        filters.sort_by_key(|filter| filter.priority);
+
         Ok(FilterChain { filters })
     }
 }
@@ -53,6 +144,7 @@ mod tests {
         let f1 = &chain.filters[0];
         assert_eq!(f1.name.to_string(), "com.example.auth");
         assert!(f1.args.is_empty());
+        assert!(f1.enabled);
 
         let f2 = &chain.filters[1];
         assert_eq!(f2.name.to_string(), "com.example.logger");
@@ -60,6 +152,221 @@ mod tests {
         assert_eq!(f2.args.get("format").unwrap(), "json");
     }
 
+    #[test]
+    fn test_chain_parser_disabled_filter() {
+        let kdl_input = r#"
+            filter name="com.example.auth" enabled=#false realm="admin"
+            filter name="com.example.logger" enabled=#true
+        "#;
+        let doc: KdlDocument = kdl_input.parse().unwrap();
+
+        let ctx = ParseContext::new(&doc, Current::Document(&doc), "test");
+        let chain = ChainParser.parse(ctx).expect("Should parse valid chain");
+
+        assert!(!chain.filters[0].enabled);
+        assert_eq!(chain.filters[0].args.len(), 1);
+        assert_eq!(chain.filters[0].args.get("realm").unwrap(), "admin");
+        assert!(chain.filters[1].enabled);
+    }
+
+    #[test]
+    fn test_chain_parser_priority_order() {
+        let kdl_input = r#"
+            filter name="com.example.logger" priority=10
+            filter name="com.example.auth" priority=-1
+            filter name="com.example.cors"
+            filter name="com.example.rewrite"
+        "#;
+        let doc: KdlDocument = kdl_input.parse().unwrap();
+
+        let ctx = ParseContext::new(&doc, Current::Document(&doc), "test");
+        let chain = ChainParser.parse(ctx).expect("Should parse valid chain");
+
+        let order = chain
+            .filters
+            .iter()
+            .map(|f| (f.name.to_string(), f.priority))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            order,
+            vec![
+                ("com.example.auth".to_string(), -1),
+                ("com.example.cors".to_string(), 0),
+                ("com.example.rewrite".to_string(), 0),
+                ("com.example.logger".to_string(), 10),
+            ]
+        );
+        assert!(chain
+            .filters
+            .iter()
+            .all(|f| !f.args.contains_key("priority")));
+    }
+
+    #[test]
+    fn test_chain_parser_enabled_not_bool() {
+        let kdl_input = r#"
+            filter name="com.example.auth" enabled="no"
+        "#;
+        let doc: KdlDocument = kdl_input.parse().unwrap();
+
+        let ctx = ParseContext::new(&doc, Current::Document(&doc), "test");
+        let result = ChainParser.parse(ctx);
+        let msg_err = result.unwrap_err().help().unwrap().to_string();
+
+        crate::assert_err_contains!(
+            msg_err,
+            "Invalid type for key 'enabled'. Expected Boolean, found String"
+        );
+    }
+
+    #[test]
+    fn test_chain_parser_on_filter_error() {
+        let kdl_input = r#"
+            filter name="plugin.auth" on-filter-error="500" realm="admin"
+            filter name="plugin.logger" on-filter-error="allow"
+            filter name="plugin.audit"
+        "#;
+        let doc: KdlDocument = kdl_input.parse().unwrap();
+
+        let ctx = ParseContext::new(&doc, Current::Document(&doc), "test");
+        let chain = ChainParser.parse(ctx).expect("Should parse valid chain");
+
+        let policies = chain.filters.iter().map(|f| f.on_error).collect::<Vec<_>>();
+        assert_eq!(
+            policies,
+            vec![
+                Some(FilterErrorPolicy::InternalError),
+                Some(FilterErrorPolicy::Allow),
+                None
+            ]
+        );
+        // the policy is not handed to the filter as a setting
+        assert_eq!(chain.filters[0].args.len(), 1);
+    }
+
+    #[test]
+    fn test_chain_parser_unknown_on_filter_error() {
+        let kdl_input = r#"
+            filter name="plugin.auth" on-filter-error="ignore"
+        "#;
+        let doc: KdlDocument = kdl_input.parse().unwrap();
+
+        let ctx = ParseContext::new(&doc, Current::Document(&doc), "test");
+        let result = ChainParser.parse(ctx);
+        let msg_err = result.unwrap_err().help().unwrap().to_string();
+
+        crate::assert_err_contains!(
+            msg_err,
+            "unknown policy 'ignore', expected 'block', 'allow' or '500'"
+        );
+    }
+
+    #[test]
+    fn test_chain_parser_max_memory() {
+        let kdl_input = r#"
+            filter name="plugin.auth" max-memory="64MiB"
+            filter name="plugin.logger"
+        "#;
+        let doc: KdlDocument = kdl_input.parse().unwrap();
+
+        let ctx = ParseContext::new(&doc, Current::Document(&doc), "test");
+        let chain = ChainParser.parse(ctx).expect("Should parse valid chain");
+
+        assert_eq!(chain.filters[0].max_memory, Some(64 << 20));
+        assert!(chain.filters[0].args.is_empty());
+        assert_eq!(chain.filters[1].max_memory, None);
+    }
+
+    #[test]
+    fn test_chain_parser_max_memory_below_one_page() {
+        let kdl_input = r#"
+            filter name="plugin.auth" max-memory="32KB"
+        "#;
+        let doc: KdlDocument = kdl_input.parse().unwrap();
+
+        let ctx = ParseContext::new(&doc, Current::Document(&doc), "test");
+        let result = ChainParser.parse(ctx);
+        let msg_err = result.unwrap_err().help().unwrap().to_string();
+
+        crate::assert_err_contains!(msg_err, "'max-memory' must be at least 64KB");
+    }
+
+    #[test]
+    fn test_chain_parser_request_body() {
+        let kdl_input = r#"
+            filter name="plugin.upper" request-body=#true
+            filter name="plugin.sign" request-body=#true max-body-size="4KB"
+            filter name="plugin.logger"
+        "#;
+        let doc: KdlDocument = kdl_input.parse().unwrap();
+
+        let ctx = ParseContext::new(&doc, Current::Document(&doc), "test");
+        let chain = ChainParser.parse(ctx).expect("Should parse valid chain");
+
+        let sizes = chain
+            .filters
+            .iter()
+            .map(|f| f.max_body_size)
+            .collect::<Vec<_>>();
+        assert_eq!(sizes, vec![Some(MAX_FILTER_BODY_SIZE), Some(4 << 10), None]);
+        assert!(chain.filters[1].args.is_empty());
+
+        for (kdl_input, message) in [
+            (
+                r#"filter name="plugin.upper" request-body=#true max-body-size="1MB""#,
+                "'max-body-size' must be between 1 byte and 64KB",
+            ),
+            (
+                r#"filter name="plugin.upper" max-body-size="4KB""#,
+                "'max-body-size' needs 'request-body=#true'",
+            ),
+        ] {
+            let doc: KdlDocument = kdl_input.parse().unwrap();
+            let ctx = ParseContext::new(&doc, Current::Document(&doc), "test");
+            let msg_err = ChainParser
+                .parse(ctx)
+                .unwrap_err()
+                .help()
+                .unwrap()
+                .to_string();
+            crate::assert_err_contains!(msg_err, message);
+        }
+    }
+
+    #[test]
+    fn test_chain_parser_when() {
+        let kdl_input = r#"
+            filter name="plugin.auth" when="method == GET && path ^= /api"
+            filter name="plugin.logger"
+        "#;
+        let doc: KdlDocument = kdl_input.parse().unwrap();
+
+        let ctx = ParseContext::new(&doc, Current::Document(&doc), "test");
+        let chain = ChainParser.parse(ctx).expect("Should parse valid chain");
+
+        let expected: Condition = "method == GET && path ^= /api".parse().unwrap();
+        assert_eq!(chain.filters[0].when, Some(expected));
+        assert!(chain.filters[0].args.is_empty());
+        assert_eq!(chain.filters[1].when, None);
+    }
+
+    #[test]
+    fn test_chain_parser_when_syntax_error() {
+        let kdl_input = r#"
+            filter name="plugin.auth" when="method == GET &&"
+        "#;
+        let doc: KdlDocument = kdl_input.parse().unwrap();
+
+        let ctx = ParseContext::new(&doc, Current::Document(&doc), "test");
+        let result = ChainParser.parse(ctx);
+        let msg_err = result.unwrap_err().help().unwrap().to_string();
+
+        crate::assert_err_contains!(
+            msg_err,
+            "expected 'method', 'path' or 'host', found the end of the condition"
+        );
+    }
+
     #[test]
     fn test_chain_parser_empty_block() {
         let kdl_input = "";
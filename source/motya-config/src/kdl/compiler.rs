@@ -1,13 +1,17 @@
-use std::collections::HashSet;
+use std::{borrow::Cow, collections::HashSet};
 
-use crate::common_types::bad::Bad;
+use crate::common_types::bad::{Bad, Warning};
+use crate::common_types::connectors::ConnectorGroups;
 use crate::common_types::definitions_table::DefinitionsTable;
+use crate::common_types::dns::DnsResolver;
 use crate::common_types::section_parser::SectionParser;
 use crate::internal::Config;
 use crate::kdl::parser::block::BlockParser;
-use crate::kdl::parser::ctx::{Current, ParseContext};
+use crate::kdl::parser::ctx::{Current, ParseContext, Warnings};
+use crate::kdl::parser::ensures::Rule;
 use crate::kdl::{
-    definitions::DefinitionsSection, services::ServicesSection, system_data::SystemDataSection,
+    connectors::ConnectorsSection, definitions::DefinitionsSection, dns::DnsSection,
+    services::ServicesSection, system_data::SystemDataSection,
 };
 use kdl::KdlDocument;
 use miette::{miette, Result};
@@ -26,41 +30,92 @@ use miette::{miette, Result};
 /// 2. **Phase 1: Definitions & Plugins**:
 ///    Iterates through *all* loaded documents to collect and merge `definitions` blocks.
 ///    - Parses named filter chains, plugin definitions and key-profiles for load-balancer.
+///    - Then collects top-level `connectors "name" { ... }` groups, which may use them.
 ///
 /// 3. **Phase 2: System & Services**:
 ///    Iterates through the documents again to build the concrete configuration:
 ///    - **System Data**: Extracted *only* from the entry point document.
-///    - **Services**: Aggregated from *all* documents.
+///    - **Services**: Aggregated from *all* documents, resolving `use-connectors` references.
 ///      - During service parsing, anonymous chains and key templates are detected
 ///        and registered into the global definitions table with generated names.
-pub struct ConfigCompiler {
-    documents: Vec<(KdlDocument, String)>,
+///
+/// The sections of the selected `profile "name" { ... }` block are read in every phase as
+/// if they were written next to the block, the other profiles are skipped.
+pub struct ConfigCompiler<'src> {
+    documents: Vec<(Cow<'src, KdlDocument>, Cow<'src, str>)>,
+    profile: Option<String>,
+}
+
+/// Profile applied when none is selected.
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Sections a `profile` block may contain.
+const PROFILE_SECTIONS: [&str; 4] = ["services", "definitions", "system", "connectors"];
+
+/// A compiled configuration, with the warnings emitted along the way.
+#[derive(Debug)]
+pub struct ParseOutput {
+    pub config: Config,
+    pub warnings: Vec<Warning>,
 }
 
-impl ConfigCompiler {
+impl<'src> ConfigCompiler<'src> {
     pub fn new(documents: Vec<(KdlDocument, String)>) -> Self {
-        Self { documents }
+        Self {
+            documents: documents
+                .into_iter()
+                .map(|(doc, name)| (Cow::Owned(doc), Cow::Owned(name)))
+                .collect(),
+            profile: None,
+        }
+    }
+
+    /// Compiles a document parsed elsewhere, without copying it or collecting any files.
+    ///
+    /// Its `includes` are not followed, the document has to hold the whole configuration.
+    pub fn from_document(doc: &'src KdlDocument, source_name: &'src str) -> Self {
+        Self {
+            documents: vec![(Cow::Borrowed(doc), Cow::Borrowed(source_name))],
+            profile: None,
+        }
+    }
+
+    /// Selects the `profile` to apply, [`DEFAULT_PROFILE`] if there is one when `None`.
+    pub fn with_profile(self, profile: Option<String>) -> Self {
+        Self { profile, ..self }
     }
 
+    /// Like [`ConfigCompiler::compile_with_warnings`], logging the warnings.
     pub fn compile(self, global_definitions: &mut DefinitionsTable) -> Result<Config> {
+        let ParseOutput { config, warnings } = self.compile_with_warnings(global_definitions)?;
+
+        for warning in warnings {
+            tracing::warn!("{:?}", miette::Report::new(warning));
+        }
+
+        Ok(config)
+    }
+
+    pub fn compile_with_warnings(
+        self,
+        global_definitions: &mut DefinitionsTable,
+    ) -> Result<ParseOutput> {
         if self.documents.is_empty() {
             return Err(miette!("No configuration documents provided"));
         }
 
-        if !self
-            .documents
-            .iter()
-            .any(|(doc, _)| doc.get("definitions").is_some() || doc.get("services").is_some())
-        {
-            return Err(miette!(
-                "Configuration must contain at least one 'definitions' or 'services' section."
-            ));
-        }
-
-        let allowed_names: HashSet<&str> = ["services", "definitions", "includes", "system"]
-            .iter()
-            .cloned()
-            .collect();
+        let allowed_names: HashSet<&str> = [
+            "services",
+            "definitions",
+            "includes",
+            "system",
+            "connectors",
+            "profile",
+            "dns",
+        ]
+        .iter()
+        .cloned()
+        .collect();
 
         for (doc, source_name) in &self.documents {
             let present_names: HashSet<&str> =
@@ -77,7 +132,7 @@ impl ConfigCompiler {
                 {
                     let unknown = node.name().value();
                     return Err(Bad::docspan(
-                        format!("Unknown top-level section '{}' in '{}'. Allowed: services, definitions, includes, system.", unknown, source_name),
+                        format!("Unknown top-level section '{}' in '{}'. Allowed: services, definitions, includes, system, connectors, profile, dns.", unknown, source_name),
                         doc,
                         &node.span(),
                         source_name
@@ -87,16 +142,23 @@ impl ConfigCompiler {
         }
 
         let mut final_config = Config::default();
+        let warnings = Warnings::default();
+        let scopes = self.scopes(&warnings)?;
 
-        let sys_data = self
-            .documents
+        let mut has_content = false;
+        for ctx in &scopes {
+            has_content |= ctx.count_nodes("definitions")? + ctx.count_nodes("services")? > 0;
+        }
+        if !has_content {
+            return Err(miette!(
+                "Configuration must contain at least one 'definitions' or 'services' section."
+            ));
+        }
+
+        let sys_data = scopes
             .iter()
-            .try_fold(None, |acc, (doc, name)| {
-                let mut block = BlockParser::new(ParseContext::new(
-                    doc,
-                    Current::Document(doc),
-                    name,
-                ))?;
+            .try_fold(None, |acc, ctx| {
+                let mut block = BlockParser::new(ctx.clone())?;
 
                 let parsed = block.optional("system", |ctx| {
                     SystemDataSection.parse_node(ctx)
@@ -111,13 +173,27 @@ impl ConfigCompiler {
             .ok_or_else(|| miette!("Missing 'system' section in configuration"))?;
 
         final_config.threads_per_service = sys_data.threads_per_service;
+        final_config.max_connections = sys_data.max_connections;
         final_config.daemonize = sys_data.daemonize;
         final_config.upgrade_socket = sys_data.upgrade_socket;
         final_config.pid_file = sys_data.pid_file;
+        final_config.status = sys_data.status;
+        final_config.admin = sys_data.admin;
 
-        for (doc, name) in &self.documents {
-            let ctx = ParseContext::new(doc, Current::Document(doc), name);
-            let mut block = BlockParser::new(ctx)?;
+        for ctx in &scopes {
+            let mut block = BlockParser::new(ctx.clone())?;
+
+            if let Some(dns) = block.optional("dns", |ctx| DnsSection.parse_node(ctx))? {
+                if final_config.dns.is_some() {
+                    return Err(miette!("Multiple 'dns' sections found."));
+                }
+                final_config.dns = Some(dns);
+            }
+        }
+        let resolver = final_config.dns.as_ref().map(DnsResolver::new);
+
+        for ctx in &scopes {
+            let mut block = BlockParser::new(ctx.clone())?;
 
             let defs = block.optional("definitions", |ctx| DefinitionsSection.parse_node(ctx))?;
 
@@ -126,13 +202,36 @@ impl ConfigCompiler {
             }
         }
 
-        for (doc, name) in &self.documents {
-            let ctx = ParseContext::new(doc, Current::Document(doc), name);
-            let mut block = BlockParser::new(ctx)?;
+        let mut connector_groups = ConnectorGroups::default();
+
+        for ctx in &scopes {
+            let mut block = BlockParser::new(ctx.clone())?;
+
+            for group_ctx in block.repeated("connectors", Ok)? {
+                let (group_name, connectors) = ConnectorsSection::new(global_definitions)
+                    .with_resolver(resolver.as_ref())
+                    .parse_named(group_ctx.clone())?;
+
+                if connector_groups
+                    .insert(group_name.clone(), connectors)
+                    .is_some()
+                {
+                    return Err(group_ctx.error(format!(
+                        "Duplicate connector group definition: '{group_name}'"
+                    )));
+                }
+            }
+        }
+
+        // one parser for every block, so no two documents hand out the same inherited fd
+        let services = ServicesSection::new(global_definitions, &connector_groups)
+            .with_resolver(resolver.as_ref());
+        for ctx in &scopes {
+            let mut block = BlockParser::new(ctx.clone())?;
 
-            if let Some(services_config) = block.optional("services", |ctx| {
-                ServicesSection::new(global_definitions).parse_node(ctx)
-            })? {
+            if let Some(services_config) =
+                block.optional("services", |ctx| services.parse_node(ctx))?
+            {
                 final_config.basic_proxies.extend(services_config.proxies);
                 final_config
                     .file_servers
@@ -140,7 +239,79 @@ impl ConfigCompiler {
             }
         }
 
-        Ok(final_config)
+        Ok(ParseOutput {
+            config: final_config,
+            warnings: warnings.into_inner(),
+        })
+    }
+
+    /// The blocks the sections are read from: every document, each followed by its
+    /// selected `profile` block, if it declares it.
+    fn scopes<'a>(&'a self, warnings: &'a Warnings) -> Result<Vec<ParseContext<'a>>> {
+        let mut scopes = vec![];
+        // name, block, and where in `scopes` it goes if selected
+        let mut profiles: Vec<(String, ParseContext<'a>, usize)> = vec![];
+
+        for (doc, source_name) in &self.documents {
+            let root =
+                ParseContext::new(doc, Current::Document(doc), source_name).with_warnings(warnings);
+            scopes.push(root.clone());
+
+            for node in root.nodes()? {
+                if node.name()? != "profile" {
+                    continue;
+                }
+                node.validate(&[Rule::ReqChildren, Rule::ExactArgs(1), Rule::OnlyKeys(&[])])?;
+
+                let name = node.first()?.as_str()?;
+                if profiles.iter().any(|(existing, ..)| *existing == name) {
+                    return Err(node.error(format!("Duplicate profile '{name}'")));
+                }
+
+                let block = node.enter_block()?;
+                for section in block.nodes()? {
+                    let section_name = section.name()?;
+                    if !PROFILE_SECTIONS.contains(&section_name) {
+                        return Err(section.error(format!(
+                            "Unknown section '{section_name}' in profile '{name}'. Allowed: {}.",
+                            PROFILE_SECTIONS.join(", ")
+                        )));
+                    }
+                }
+
+                profiles.push((name, block, scopes.len()));
+            }
+        }
+
+        let selected = match &self.profile {
+            Some(name) => {
+                let position = profiles.iter().position(|(profile, ..)| profile == name);
+                if position.is_none() {
+                    let names = profiles
+                        .iter()
+                        .map(|(profile, ..)| format!("'{profile}'"))
+                        .collect::<Vec<_>>();
+                    let defined = match names.is_empty() {
+                        true => "no profiles".to_string(),
+                        false => names.join(", "),
+                    };
+                    return Err(miette!(
+                        "Profile '{name}' not found, the configuration has {defined}"
+                    ));
+                }
+                position
+            }
+            None => profiles
+                .iter()
+                .position(|(profile, ..)| profile == DEFAULT_PROFILE),
+        };
+
+        if let Some(index) = selected {
+            let (_, block, at) = profiles.swap_remove(index);
+            scopes.insert(at, block);
+        }
+
+        Ok(scopes)
     }
 }
 
@@ -149,6 +320,7 @@ mod tests {
 
     use super::*;
     use fqdn::fqdn;
+    use kdl::{KdlEntry, KdlNode, KdlValue};
     #[tokio::test]
     async fn test_namespace_merge_across_files() {
         const DEF_ONE: &str = r#"
@@ -334,6 +506,33 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_inherited_fd_used_across_files() {
+        const SERVICE: &str = r#"
+            services {
+                NAME {
+                    listeners { fd 3 }
+                    connectors {
+                        return code=200 response="OK"
+                    }
+                }
+            }
+        "#;
+
+        let files = ["First", "Second"]
+            .into_iter()
+            .map(|name| {
+                let doc: KdlDocument = SERVICE.replace("NAME", name).parse().unwrap();
+                (doc, format!("{name}.kdl"))
+            })
+            .collect();
+
+        let result = ConfigCompiler::new(files).compile(&mut DefinitionsTable::new_with_global());
+
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        crate::assert_err_contains!(err_msg, "Inherited fd 3 is already used by service 'First'");
+    }
+
     #[tokio::test]
     async fn test_include_logic() {
         const DEFINITIONS_FILE: &str = r#"
@@ -380,4 +579,196 @@ mod tests {
         assert_eq!(config.threads_per_service, 2);
         assert_eq!(config.basic_proxies.len(), 1);
     }
+
+    #[tokio::test]
+    async fn test_shared_connector_group() {
+        const GROUPS_FILE: &str = r#"
+            connectors "api-pool" {
+                proxy "http://127.0.0.1:3000"
+            }
+        "#;
+
+        const MAIN_FILE: &str = r#"
+            system { }
+
+            services {
+                Public {
+                    listeners { "127.0.0.1:8080" }
+                    use-connectors "api-pool"
+                }
+                Internal {
+                    listeners { "127.0.0.1:8081" }
+                    use-connectors "api-pool"
+                }
+            }
+        "#;
+
+        let groups: KdlDocument = GROUPS_FILE.parse().unwrap();
+        let main: KdlDocument = MAIN_FILE.parse().unwrap();
+
+        let files = vec![
+            (groups, "groups.kdl".to_string()),
+            (main, "main.kdl".to_string()),
+        ];
+
+        let mut def_table = DefinitionsTable::new_with_global();
+
+        let config = ConfigCompiler::new(files)
+            .compile(&mut def_table)
+            .expect("Config should load successfully");
+
+        assert_eq!(config.basic_proxies.len(), 2);
+        assert_eq!(
+            config.basic_proxies[0].connectors,
+            config.basic_proxies[1].connectors
+        );
+        assert_eq!(config.basic_proxies[0].connectors.upstreams.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_connector_group() {
+        const MAIN_FILE: &str = r#"
+            system { }
+
+            services {
+                Public {
+                    listeners { "127.0.0.1:8080" }
+                    use-connectors "missing-pool"
+                }
+            }
+        "#;
+
+        let main: KdlDocument = MAIN_FILE.parse().unwrap();
+        let files = vec![(main, "main.kdl".to_string())];
+
+        let mut def_table = DefinitionsTable::new_with_global();
+
+        let result = ConfigCompiler::new(files).compile(&mut def_table);
+
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        crate::assert_err_contains!(err_msg, "Connector group 'missing-pool' not found");
+    }
+    const PROFILES_FILE: &str = r#"
+        system { }
+
+        services {
+            Shared {
+                listeners { "127.0.0.1:8080" }
+                connectors {
+                    return code=200 response="OK"
+                }
+            }
+        }
+
+        profile "default" {
+            services {
+                Local {
+                    listeners { "127.0.0.1:9000" }
+                    connectors {
+                        return code=200 response="OK"
+                    }
+                }
+            }
+        }
+
+        profile "prod" {
+            services {
+                Api {
+                    listeners { "0.0.0.0:443" }
+                    connectors {
+                        proxy "http://10.0.0.1:3000"
+                    }
+                }
+            }
+        }
+    "#;
+
+    fn compile_profile(profile: Option<&str>) -> Result<Config> {
+        let main: KdlDocument = PROFILES_FILE.parse().unwrap();
+        let files = vec![(main, "main.kdl".to_string())];
+
+        ConfigCompiler::new(files)
+            .with_profile(profile.map(str::to_string))
+            .compile(&mut DefinitionsTable::new_with_global())
+    }
+
+    fn service_names(config: &Config) -> Vec<&str> {
+        config
+            .basic_proxies
+            .iter()
+            .map(|proxy| proxy.name.as_str())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_selected_profile_applies() {
+        let config = compile_profile(Some("prod")).expect("Config should load successfully");
+        assert_eq!(service_names(&config), ["Shared", "Api"]);
+
+        let config = compile_profile(None).expect("Config should load successfully");
+        assert_eq!(service_names(&config), ["Shared", "Local"]);
+    }
+
+    #[tokio::test]
+    async fn test_compile_constructed_document() {
+        fn node(name: &str, entries: Vec<KdlEntry>, children: Vec<KdlNode>) -> KdlNode {
+            let mut node = KdlNode::new(name);
+            node.entries_mut().extend(entries);
+            if !children.is_empty() {
+                let mut block = KdlDocument::new();
+                block.nodes_mut().extend(children);
+                node.set_children(block);
+            }
+            node
+        }
+
+        let threads = node(
+            "threads-per-service",
+            vec![KdlEntry::new(KdlValue::Integer(4))],
+            vec![],
+        );
+        let response = node(
+            "return",
+            vec![
+                KdlEntry::new_prop("code", KdlValue::Integer(200)),
+                KdlEntry::new_prop("response", "OK"),
+            ],
+            vec![],
+        );
+        let service = node(
+            "Api",
+            vec![],
+            vec![
+                node(
+                    "listeners",
+                    vec![],
+                    vec![node("127.0.0.1:8080", vec![], vec![])],
+                ),
+                node("connectors", vec![], vec![response]),
+            ],
+        );
+
+        let mut doc = KdlDocument::new();
+        doc.nodes_mut().extend([
+            node("system", vec![], vec![threads]),
+            node("services", vec![], vec![service]),
+        ]);
+
+        let config = ConfigCompiler::from_document(&doc, "generated")
+            .compile(&mut DefinitionsTable::new_with_global())
+            .expect("Config should load successfully");
+
+        assert_eq!(config.threads_per_service, 4);
+        assert_eq!(service_names(&config), ["Api"]);
+        assert_eq!(config.basic_proxies[0].listeners.list_cfgs.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_missing_profile() {
+        let err_msg = compile_profile(Some("staging")).unwrap_err().to_string();
+        crate::assert_err_contains!(
+            err_msg,
+            "Profile 'staging' not found, the configuration has 'default', 'prod'"
+        );
+    }
 }
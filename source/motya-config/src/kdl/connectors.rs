@@ -1,54 +1,220 @@
 use std::{
-    net::SocketAddr,
+    collections::BTreeMap,
+    net::{IpAddr, SocketAddr, ToSocketAddrs},
     sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
 };
 
-use http::{uri::PathAndQuery, StatusCode, Uri};
+use cidr::IpCidr;
+use http::{uri::PathAndQuery, Method, StatusCode, Uri};
 use motya_macro::validate;
+use regex::Regex;
 
 use crate::{
     block_parser,
     common_types::{
+        cache::CacheConfig,
+        condition::Condition,
         connectors::{
-            Connectors, ConnectorsLeaf, HttpPeerConfig, MultiServerUpstreamConfig, RouteMatcher,
-            UpstreamConfig, UpstreamContextConfig, UpstreamServer, ALPN,
+            Connectors, ConnectorsLeaf, HttpPeerConfig, MultiServerUpstreamConfig, PathRegex,
+            PinnedServer, RequireTls, RouteMatcher, UpstreamConfig, UpstreamContextConfig,
+            UpstreamHttpVersion, UpstreamProtocol, UpstreamScheme, UpstreamServer, ALPN,
+            DEFAULT_BUFFER_BODY_LIMIT,
         },
         definitions::{KeyTemplateConfig, Modificator, NamedFilterChain},
         definitions_table::DefinitionsTable,
+        dns::DnsResolver,
+        headers::{
+            ForwardedHeaders, HeaderRule, HeaderTemplate, UpstreamAcceptEncoding, UpstreamHost,
+        },
+        rate_limit::RateLimitConfig,
         section_parser::SectionParser,
         simple_response_type::SimpleResponseConfig,
+        status_map::StatusMap,
     },
-    internal::{DiscoveryKind, HealthCheckKind, SelectionKind, UpstreamOptions},
+    internal::{DiscoveryKind, HealthCheckKind, OutlierDetection, SelectionKind, UpstreamOptions},
     kdl::{
+        cache_parser::CacheParser,
         chain_parser::ChainParser,
+        header_rules_parser::HeaderRulesParser,
         key_profile_parser::KeyProfileParser,
         parser::{
             block::BlockParser,
             ctx::ParseContext,
             ensures::Rule,
+            typed_value::{Entry, TypedValue},
             utils::{OptionTypedValueExt, PrimitiveType},
         },
+        rate_limit_parser::RateLimitParser,
     },
 };
 
+/// Upper bound of `outlier-detection.consecutive-errors`.
+const MAX_CONSECUTIVE_ERRORS: usize = 1000;
+
+/// How often `health-check "TCP"` probes the servers when no `interval` is given.
+const DEFAULT_HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Methods `upstream-method` accepts: those of RFC 9110 and `PATCH`, but not `CONNECT`, which
+/// opens a tunnel instead of forwarding a request.
+const UPSTREAM_METHODS: [Method; 8] = [
+    Method::GET,
+    Method::HEAD,
+    Method::POST,
+    Method::PUT,
+    Method::DELETE,
+    Method::OPTIONS,
+    Method::TRACE,
+    Method::PATCH,
+];
+
+/// An upstream address, with the parts it omits filled from the connector defaults.
+pub(crate) struct ResolvedUpstream {
+    /// Every address the host resolves to, in the order of the resolver.
+    pub addrs: Vec<SocketAddr>,
+    pub scheme: UpstreamScheme,
+    /// The host, unless it is an IP address, which is never sent as SNI.
+    pub server_name: Option<String>,
+}
+
+impl ResolvedUpstream {
+    /// Resolves `[scheme://]host[:port]`, taking the scheme and port it omits from `scheme`
+    /// and `port`, with the `dns` servers of `resolver` or else the system resolver.
+    pub(crate) fn resolve(
+        uri: &Uri,
+        scheme: UpstreamScheme,
+        port: Option<u16>,
+        resolver: Option<&DnsResolver>,
+    ) -> Result<Self, String> {
+        let scheme = match uri.scheme_str() {
+            Some(scheme) => scheme.parse::<UpstreamScheme>()?,
+            None => scheme,
+        };
+
+        let host = uri
+            .host()
+            .ok_or("Not a valid socket address")?
+            .trim_start_matches('[')
+            .trim_end_matches(']');
+
+        let port = uri
+            .port_u16()
+            .or(port)
+            .unwrap_or_else(|| scheme.default_port());
+
+        let addrs = match resolver {
+            Some(resolver) => resolver.lookup(host, port).map_err(|err| {
+                format!("Failed to resolve '{host}:{port}' with the 'dns' servers: {err}")
+            })?,
+            None => {
+                let mut addrs = Vec::new();
+                for addr in (host, port).to_socket_addrs().into_iter().flatten() {
+                    if !addrs.contains(&addr) {
+                        addrs.push(addr);
+                    }
+                }
+                addrs
+            }
+        };
+        if addrs.is_empty() {
+            return Err(format!("Not a valid socket address: '{host}:{port}'"));
+        }
+
+        Ok(Self {
+            addrs,
+            scheme,
+            server_name: host.parse::<IpAddr>().is_err().then(|| host.to_string()),
+        })
+    }
+
+    /// The SNI an `https` upstream is reached with when no `tls-sni` is set: its host name,
+    /// or none at all for an IP address.
+    pub(crate) fn implied_sni(&self) -> Option<String> {
+        (self.scheme == UpstreamScheme::Https).then(|| self.server_name.clone().unwrap_or_default())
+    }
+
+    /// The address of an upstream that is a single peer, which cannot balance over the
+    /// several addresses of a name.
+    pub(crate) fn single_addr(&self) -> Result<SocketAddr, String> {
+        match self.addrs.as_slice() {
+            [addr] => Ok(*addr),
+            addrs => Err(format!(
+                "'{}' resolves to {} addresses, list it as a 'server' of a 'proxy' block to balance over them",
+                self.server_name.as_deref().unwrap_or_default(),
+                addrs.len()
+            )),
+        }
+    }
+}
+
+/// TLS, SNI and ALPN of a connector, from its `proto` and `tls-sni`.
+pub(crate) fn resolve_proto_settings(
+    proto: Option<&str>,
+    tls_sni: Option<&str>,
+) -> Result<(bool, String, ALPN), String> {
+    let alpn = match proto {
+        Some(p) => parse_proto_value(p)?,
+        None => None,
+    };
+
+    match (alpn, tls_sni) {
+        (None, None) | (Some(ALPN::H1), None) => Ok((false, String::new(), ALPN::H1)),
+        (None, Some(sni)) => Ok((true, sni.to_string(), ALPN::H2H1)),
+        (Some(_), None) => Err("'tls-sni' is required for HTTP2 support".to_string()),
+        (Some(p), Some(sni)) => Ok((true, sni.to_string(), p)),
+    }
+}
+
+/// The SNI of a pool without `tls-sni`, implied by its servers being `https` ones.
+///
+/// TLS and SNI are set for the whole pool, so its servers must agree on both.
+fn pool_implied_sni(
+    targets: &[(ParseContext<'_>, UpstreamScheme, Option<String>)],
+) -> miette::Result<Option<String>> {
+    let Some((_, scheme, server_name)) = targets.first() else {
+        return Ok(None);
+    };
+
+    for (ctx, other_scheme, other_name) in &targets[1..] {
+        if other_scheme != scheme {
+            return Err(
+                ctx.error("The servers of a 'proxy' block must all be 'http' or all be 'https'")
+            );
+        }
+        if *scheme == UpstreamScheme::Https && other_name != server_name {
+            return Err(ctx.error(
+                "'https' servers with different host names need a 'tls-sni' for the 'proxy' block",
+            ));
+        }
+    }
+
+    Ok((*scheme == UpstreamScheme::Https).then(|| server_name.clone().unwrap_or_default()))
+}
+
+/// Connector-level fallbacks for upstream addresses that omit the scheme or port, and
+/// the keys of `defaults { ... }` blocks, used by every `proxy` that doesn't set them itself.
+#[derive(Debug, Clone, Default)]
+struct UpstreamDefaults {
+    scheme: UpstreamScheme,
+    port: Option<u16>,
+    connect_timeout: Option<Duration>,
+    tls_sni: Option<String>,
+    proto: Option<String>,
+}
+
 pub struct ConnectorsSection<'a> {
     table: &'a DefinitionsTable,
     anon_counter: AtomicUsize,
+    /// Resolves upstream host names, the system resolver does when `None`.
+    resolver: Option<&'a DnsResolver>,
 }
 
 impl SectionParser<ParseContext<'_>, Connectors> for ConnectorsSection<'_> {
     #[validate(ensure_node_name = "connectors")]
     fn parse_node(&self, ctx: ParseContext) -> miette::Result<Connectors> {
-        let mut anonymous_definitions = DefinitionsTable::default();
+        ctx.validate(&[Rule::NoPositionalArgs])?;
 
-        let root_nodes = self.parse_connections_node(ctx, &mut anonymous_definitions)?;
-
-        let upstreams = flatten_nodes(root_nodes, &[])?;
-
-        Ok(Connectors {
-            upstreams,
-            anonymous_definitions,
-        })
+        self.parse_connectors(ctx)
     }
 }
 
@@ -57,39 +223,185 @@ impl<'a> ConnectorsSection<'a> {
         Self {
             table,
             anon_counter: AtomicUsize::new(0),
+            resolver: None,
         }
     }
 
+    /// Resolves upstream host names with the `dns` servers instead of the system resolver.
+    pub fn with_resolver(self, resolver: Option<&'a DnsResolver>) -> Self {
+        Self { resolver, ..self }
+    }
+
+    /// Parses a named group, `connectors "name" { ... }`, declared at the top level.
+    pub fn parse_named(&self, ctx: ParseContext<'_>) -> miette::Result<(String, Connectors)> {
+        ctx.validate(&[Rule::ExactArgs(1)])?;
+
+        let name = ctx.first()?.as_str()?;
+        let connectors = self.parse_connectors(ctx)?;
+
+        Ok((name, connectors))
+    }
+
+    /// Positional arguments of the `connectors` node are checked by the caller.
+    fn parse_connectors(&self, ctx: ParseContext<'_>) -> miette::Result<Connectors> {
+        let mut anonymous_definitions = DefinitionsTable::default();
+
+        let root_nodes = self.parse_connections_node(ctx, &mut anonymous_definitions)?;
+
+        let upstreams = flatten_nodes(root_nodes, &Inherited::default())?;
+
+        Ok(Connectors {
+            upstreams,
+            anonymous_definitions,
+        })
+    }
+
     pub fn parse_connections_node(
         &self,
         ctx: ParseContext<'_>,
         anonymous_definitions: &mut DefinitionsTable,
     ) -> miette::Result<Vec<ConnectorsLeaf>> {
+        let defaults = self.extract_upstream_defaults(&ctx)?;
+
         self.process_nodes_recursive(
             ctx,
             anonymous_definitions,
             "/".parse().unwrap(),
             RouteMatcher::Exact,
+            &defaults,
         )
     }
 
+    fn extract_upstream_defaults(
+        &self,
+        ctx: &ParseContext<'_>,
+    ) -> miette::Result<UpstreamDefaults> {
+        ctx.validate(&[
+            Rule::NoDuplicateKeys,
+            Rule::OnlyKeysTyped(&[
+                ("default-scheme", PrimitiveType::String),
+                ("default-port", PrimitiveType::Integer),
+            ]),
+            Rule::ValidPort(&["default-port"]),
+        ])?;
+
+        let [scheme_opt, port_opt] = ctx.props(["default-scheme", "default-port"])?;
+
+        Ok(UpstreamDefaults {
+            scheme: scheme_opt.parse_as::<UpstreamScheme>()?.unwrap_or_default(),
+            port: port_opt.as_u16()?,
+            ..Default::default()
+        })
+    }
+
+    /// A `defaults { ... }` block, its keys win over the ones of enclosing blocks.
+    fn extract_defaults(
+        &self,
+        ctx: ParseContext<'_>,
+        inherited: &UpstreamDefaults,
+    ) -> miette::Result<UpstreamDefaults> {
+        ctx.validate(&[Rule::ReqChildren, Rule::NoArgs])?;
+
+        let single_value = |ctx: &ParseContext<'_>| {
+            ctx.validate(&[Rule::NoChildren, Rule::ExactArgs(1), Rule::OnlyKeys(&[])])
+        };
+
+        block_parser!(ctx.enter_block()?,
+            connect_timeout: optional("connect-timeout") => |ctx| {
+                single_value(&ctx)?;
+                parse_connect_timeout(&ctx, ctx.first()?)
+            },
+            tls_sni: optional("tls-sni") => |ctx| {
+                single_value(&ctx)?;
+                ctx.first()?.as_str()
+            },
+            proto: optional("proto") => |ctx| {
+                single_value(&ctx)?;
+                let proto = ctx.first()?.as_str()?;
+                parse_proto_value(&proto).map_err(|msg| ctx.error(msg))?;
+                Ok(proto)
+            }
+        );
+
+        Ok(UpstreamDefaults {
+            connect_timeout: connect_timeout.or(inherited.connect_timeout),
+            tls_sni: tls_sni.or_else(|| inherited.tls_sni.clone()),
+            proto: proto.or_else(|| inherited.proto.clone()),
+            ..inherited.clone()
+        })
+    }
+
     fn process_nodes_recursive(
         &self,
         ctx: ParseContext<'a>,
         anon_definitions: &mut DefinitionsTable,
         base_path: PathAndQuery,
         matcher: RouteMatcher,
+        defaults: &UpstreamDefaults,
     ) -> miette::Result<Vec<ConnectorsLeaf>> {
+        // needed by the `proxy` and the sections next to it, whatever their order
+        let local_defaults;
+        let defaults = match ctx.child("defaults")? {
+            Some(defaults_ctx) => {
+                local_defaults = self.extract_defaults(defaults_ctx, defaults)?;
+                &local_defaults
+            }
+            None => defaults,
+        };
+
         block_parser!(
             ctx,
+            optional("defaults") => |_| Ok(()),
             leaf: optional_any(&["proxy", "return"]) => |ctx, name| match name {
                 "return" => self.extract_static_response(ctx, base_path.clone()),
-                "proxy" => self.extract_connector(ctx, base_path.clone(), matcher),
+                "proxy" => self.extract_connector(ctx, base_path.clone(), matcher, defaults),
                 _ => unreachable!("Guaranteed by BlockParser"),
             },
             lb: optional("load-balance") => |ctx| self.extract_load_balance(ctx, anon_definitions),
+            request_headers: optional("request-headers") => |ctx| {
+                ctx.validate(&[Rule::ReqChildren, Rule::NoArgs])?;
+                HeaderRulesParser.parse_request(ctx.enter_block()?)
+            },
+            response_headers: optional("response-headers") => |ctx| {
+                ctx.validate(&[Rule::ReqChildren, Rule::NoArgs])?;
+                HeaderRulesParser.parse_response(ctx.enter_block()?)
+            },
+            cache: optional("cache") => |ctx| CacheParser::new(self.table).parse(ctx),
+            rate_limit: optional("rate-limit") => |ctx| RateLimitParser::new(self.table).parse(ctx),
+            buffer_request_body: optional("buffer-request-body") => |ctx| self.extract_flag(ctx),
+            buffer_response_body: optional("buffer-response-body") => |ctx| self.extract_flag(ctx),
+            buffer_body_limit: optional("buffer-body-limit") => |ctx| {
+                ctx.validate(&[Rule::NoChildren, Rule::ExactArgs(1), Rule::OnlyKeys(&[])])?;
+                let limit = ctx.first()?.as_byte_size()?;
+                if limit == 0 {
+                    return Err(ctx.error("'buffer-body-limit' must be positive"));
+                }
+                Ok(limit)
+            },
+            allow_upgrades: optional("allow-upgrades") => |ctx| self.extract_flag(ctx),
+            accept_encoding: optional("upstream-accept-encoding") => |ctx| {
+                ctx.validate(&[Rule::NoChildren, Rule::ExactArgs(1), Rule::OnlyKeys(&[])])?;
+                ctx.first()?.parse_as::<UpstreamAcceptEncoding>()
+            },
+            host_header: optional("host-header") => |ctx| {
+                ctx.validate(&[Rule::NoChildren, Rule::ExactArgs(1), Rule::OnlyKeys(&[])])?;
+                ctx.first()?.parse_as::<UpstreamHost>()
+            },
+            upstream_method: optional("upstream-method") => |ctx| self.extract_upstream_method(ctx),
+            status_map: optional("response-status-map") => |ctx| self.extract_status_map(ctx),
+            protocol: optional("protocol") => |ctx| {
+                ctx.validate(&[Rule::NoChildren, Rule::ExactArgs(1), Rule::OnlyKeys(&[])])?;
+                ctx.first()?.parse_as::<UpstreamProtocol>()
+            },
+            http_version: optional("upstream-http-version") => |ctx| self.extract_http_version(ctx),
+            latency_budget: optional("latency-budget") => |ctx| {
+                ctx.validate(&[Rule::NoChildren, Rule::ExactArgs(1), Rule::OnlyKeys(&[])])?;
+                ctx.first()?.as_duration()
+            },
+            request_timeout: optional("request-timeout") => parse_request_timeout,
+            forwarded_headers: optional("forwarded-headers") => |ctx| self.extract_forwarded_headers(ctx),
             chains: repeated("use-chain") => |ctx| self.extract_chain_usage(ctx, anon_definitions, base_path.clone()),
-            sections: repeated("section") => |ctx| self.extract_section(ctx, anon_definitions, base_path.clone(), matcher)
+            sections: repeated("section") => |ctx| self.extract_section(ctx, anon_definitions, base_path.clone(), matcher, defaults)
         );
 
         let mut result = Vec::new();
@@ -100,6 +412,57 @@ impl<'a> ConnectorsSection<'a> {
         if let Some(l) = lb {
             result.push(l);
         }
+        if let Some(rules) = request_headers {
+            result.push(ConnectorsLeaf::RequestHeaders(rules));
+        }
+        if let Some(rules) = response_headers {
+            result.push(ConnectorsLeaf::ResponseHeaders(rules));
+        }
+        if let Some(cache) = cache {
+            result.push(ConnectorsLeaf::Cache(cache));
+        }
+        if let Some(limit) = rate_limit {
+            result.push(ConnectorsLeaf::RateLimit(limit));
+        }
+        if let Some(buffer) = buffer_request_body {
+            result.push(ConnectorsLeaf::BufferRequestBody(buffer));
+        }
+        if let Some(buffer) = buffer_response_body {
+            result.push(ConnectorsLeaf::BufferResponseBody(buffer));
+        }
+        if let Some(limit) = buffer_body_limit {
+            result.push(ConnectorsLeaf::BufferBodyLimit(limit));
+        }
+        if let Some(allow) = allow_upgrades {
+            result.push(ConnectorsLeaf::AllowUpgrades(allow));
+        }
+        if let Some(encoding) = accept_encoding {
+            result.push(ConnectorsLeaf::UpstreamAcceptEncoding(encoding));
+        }
+        if let Some(host) = host_header {
+            result.push(ConnectorsLeaf::UpstreamHost(host));
+        }
+        if let Some(method) = upstream_method {
+            result.push(ConnectorsLeaf::UpstreamMethod(method));
+        }
+        if let Some(map) = status_map {
+            result.push(ConnectorsLeaf::ResponseStatusMap(map));
+        }
+        if let Some(protocol) = protocol {
+            result.push(ConnectorsLeaf::Protocol(protocol));
+        }
+        if let Some(version) = http_version {
+            result.push(ConnectorsLeaf::UpstreamHttpVersion(version));
+        }
+        if let Some(budget) = latency_budget {
+            result.push(ConnectorsLeaf::LatencyBudget(budget));
+        }
+        if let Some(timeout) = request_timeout {
+            result.push(ConnectorsLeaf::RequestTimeout(timeout));
+        }
+        if let Some(forwarded) = forwarded_headers {
+            result.push(ConnectorsLeaf::ForwardedHeaders(forwarded));
+        }
 
         result.extend(chains);
         result.extend(sections);
@@ -107,6 +470,126 @@ impl<'a> ConnectorsSection<'a> {
         Ok(result)
     }
 
+    /// `response-status-map { "418" -> "400"; }`, status codes rewritten in responses.
+    fn extract_status_map(&self, ctx: ParseContext<'_>) -> miette::Result<StatusMap> {
+        ctx.validate(&[Rule::ReqChildren, Rule::NoArgs])?;
+
+        let mut codes = BTreeMap::new();
+
+        for entry_ctx in ctx.req_nodes()? {
+            entry_ctx.validate(&[Rule::NoChildren, Rule::ExactArgs(2), Rule::OnlyKeys(&[])])?;
+
+            let from = parse_status_code(&entry_ctx, entry_ctx.name()?)?;
+            let arrow = entry_ctx.arg(0)?;
+            if arrow.as_string_lossy()? != "->" {
+                return Err(
+                    entry_ctx.error(format!("Expected a mapping like '\"{from}\" -> \"400\"'"))
+                );
+            }
+            let to = parse_status_code(&entry_ctx, &entry_ctx.arg(1)?.as_string_lossy()?)?;
+
+            if codes.insert(from, to).is_some() {
+                return Err(entry_ctx.error(format!("Duplicate mapping for status {from}")));
+            }
+        }
+
+        Ok(StatusMap { codes })
+    }
+
+    /// `upstream-method "HEAD"`, one of the [`UPSTREAM_METHODS`].
+    fn extract_upstream_method(&self, ctx: ParseContext<'_>) -> miette::Result<Method> {
+        ctx.validate(&[Rule::NoChildren, Rule::ExactArgs(1), Rule::OnlyKeys(&[])])?;
+
+        let value = ctx.first()?;
+        let name = value.as_str()?;
+        UPSTREAM_METHODS
+            .iter()
+            .find(|method| method.as_str() == name)
+            .cloned()
+            .ok_or_else(|| {
+                let known = UPSTREAM_METHODS
+                    .iter()
+                    .map(Method::as_str)
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                value.error(format!("Unknown method '{name}', expected one of {known}"))
+            })
+    }
+
+    /// A directive switching a behavior on or off, like `buffer-request-body #true`.
+    fn extract_flag(&self, ctx: ParseContext<'_>) -> miette::Result<bool> {
+        ctx.validate(&[Rule::NoChildren, Rule::ExactArgs(1), Rule::OnlyKeys(&[])])?;
+        ctx.first()?.as_bool()
+    }
+
+    /// `upstream-http-version "1.1"|"2"|"auto"`, with `h2c=#true` allowing `"2"` over cleartext.
+    fn extract_http_version(&self, ctx: ParseContext<'_>) -> miette::Result<UpstreamHttpVersion> {
+        ctx.validate(&[
+            Rule::NoChildren,
+            Rule::ExactArgs(1),
+            Rule::OnlyKeysTyped(&[("h2c", PrimitiveType::Bool)]),
+        ])?;
+
+        let version = ctx.first()?.parse_as::<UpstreamHttpVersion>()?;
+        match (version, ctx.opt_prop("h2c")?) {
+            (UpstreamHttpVersion::Http2 { .. }, Some(h2c)) => Ok(UpstreamHttpVersion::Http2 {
+                h2c: h2c.as_bool()?,
+            }),
+            (_, Some(h2c)) => Err(h2c.error("'h2c' only applies to 'upstream-http-version \"2\"'")),
+            (version, None) => Ok(version),
+        }
+    }
+
+    /// The `require-tls` and `https-redirect` keys of a section, `None` when it inherits
+    /// the setting of the enclosing one.
+    fn extract_require_tls(&self, ctx: &ParseContext<'_>) -> miette::Result<Option<RequireTls>> {
+        let required = ctx
+            .opt_prop("require-tls")?
+            .map(|required| required.as_bool())
+            .transpose()?;
+        let redirect = ctx.opt_prop("https-redirect")?;
+
+        match (required, redirect) {
+            (Some(true), Some(redirect)) if redirect.as_bool()? => Ok(Some(RequireTls::Redirect)),
+            (Some(true), _) => Ok(Some(RequireTls::Reject)),
+            (_, Some(redirect)) => {
+                Err(redirect.error("'https-redirect' only applies to 'require-tls=#true'"))
+            }
+            (Some(false), None) => Ok(Some(RequireTls::No)),
+            (None, None) => Ok(None),
+        }
+    }
+
+    /// `forwarded-headers #true|#false [rfc7239=#true] [trusted-proxies="10.0.0.0/8, ..."]`.
+    fn extract_forwarded_headers(&self, ctx: ParseContext<'_>) -> miette::Result<ForwardedHeaders> {
+        ctx.validate(&[
+            Rule::NoChildren,
+            Rule::ExactArgs(1),
+            Rule::OnlyKeysTyped(&[
+                ("rfc7239", PrimitiveType::Bool),
+                ("trusted-proxies", PrimitiveType::String),
+            ]),
+        ])?;
+
+        let mut trusted_proxies = vec![];
+        if let Some(value) = ctx.opt_prop("trusted-proxies")? {
+            for addr in value.as_str()?.split(',').map(str::trim) {
+                let cidr = addr.parse::<IpCidr>().map_err(|err| {
+                    value.error(format!(
+                        "'{addr}' is not an IP address or CIDR range: {err}"
+                    ))
+                })?;
+                trusted_proxies.push(cidr);
+            }
+        }
+
+        Ok(ForwardedHeaders {
+            enabled: ctx.first()?.as_bool()?,
+            rfc7239: ctx.opt_prop("rfc7239")?.as_bool()?.unwrap_or(false),
+            trusted_proxies,
+        })
+    }
+
     fn extract_chain_usage(
         &self,
         ctx: ParseContext<'_>,
@@ -155,13 +638,34 @@ impl<'a> ConnectorsSection<'a> {
         anonymous_definitions: &mut DefinitionsTable,
         base_path: PathAndQuery,
         parent_matcher: RouteMatcher,
+        defaults: &UpstreamDefaults,
     ) -> miette::Result<ConnectorsLeaf> {
         ctx.validate(&[
             Rule::ReqChildren,
-            Rule::ExactArgs(1),
-            Rule::OnlyKeysTyped(&[("as", PrimitiveType::String)]),
+            Rule::NoDuplicateKeys,
+            Rule::OnlyKeysTyped(&[
+                ("as", PrimitiveType::String),
+                ("path-regex", PrimitiveType::String),
+                ("require-tls", PrimitiveType::Bool),
+                ("https-redirect", PrimitiveType::Bool),
+            ]),
         ])?;
 
+        let require_tls = self.extract_require_tls(&ctx)?;
+
+        if let Some(regex_value) = ctx.opt_prop("path-regex")? {
+            return self.extract_regex_section(
+                &ctx,
+                regex_value,
+                require_tls,
+                anonymous_definitions,
+                base_path,
+                defaults,
+            );
+        }
+
+        ctx.validate(&[Rule::ExactArgs(1)])?;
+
         let path_segment = ctx.arg(0)?.as_str()?;
         let mode_arg = ctx.opt_prop("as")?.as_str()?;
 
@@ -206,12 +710,66 @@ impl<'a> ConnectorsSection<'a> {
 
         let block_ctx = ctx.enter_block()?;
 
-        Ok(ConnectorsLeaf::Section(self.process_nodes_recursive(
+        let mut leaves: Vec<_> = require_tls
+            .map(ConnectorsLeaf::RequireTls)
+            .into_iter()
+            .collect();
+        leaves.extend(self.process_nodes_recursive(
             block_ctx,
             anonymous_definitions,
             path,
             next_matcher,
-        )?))
+            defaults,
+        )?);
+
+        Ok(ConnectorsLeaf::Section(leaves))
+    }
+
+    /// A `section path-regex="..." { ... }`, routed by matching the whole request path.
+    fn extract_regex_section(
+        &self,
+        ctx: &ParseContext<'_>,
+        regex_value: TypedValue<'_>,
+        require_tls: Option<RequireTls>,
+        anonymous_definitions: &mut DefinitionsTable,
+        base_path: PathAndQuery,
+        defaults: &UpstreamDefaults,
+    ) -> miette::Result<ConnectorsLeaf> {
+        for entry in ctx.entries()? {
+            match entry {
+                Entry::Positional(value) => {
+                    return Err(value.error("A section cannot set both a path and 'path-regex'"))
+                }
+                Entry::Named("as", value) => {
+                    return Err(value.error("'as' cannot be combined with 'path-regex'"))
+                }
+                Entry::Named(..) => {}
+            }
+        }
+
+        let pattern = regex_value.as_str()?;
+        let regex = Regex::new(&pattern)
+            .map_err(|err| regex_value.error(format!("Invalid 'path-regex' '{pattern}': {err}")))?;
+
+        for child in ctx.req_nodes()? {
+            if child.name()? == "section" {
+                return Err(
+                    child.error("A section with 'path-regex' cannot contain nested sections.")
+                );
+            }
+        }
+
+        let mut leaves = vec![ConnectorsLeaf::PathRegex(PathRegex(regex))];
+        leaves.extend(require_tls.map(ConnectorsLeaf::RequireTls));
+        leaves.extend(self.process_nodes_recursive(
+            ctx.enter_block()?,
+            anonymous_definitions,
+            base_path,
+            RouteMatcher::Exact,
+            defaults,
+        )?);
+
+        Ok(ConnectorsLeaf::Section(leaves))
     }
 
     fn extract_static_response(
@@ -248,6 +806,7 @@ impl<'a> ConnectorsSection<'a> {
         ctx: ParseContext<'_>,
         base_path: PathAndQuery,
         parent_matcher: RouteMatcher,
+        defaults: &UpstreamDefaults,
     ) -> miette::Result<ConnectorsLeaf> {
         if ctx.has_children_block()? {
             ctx.validate(&[Rule::NoArgs])?;
@@ -255,19 +814,51 @@ impl<'a> ConnectorsSection<'a> {
             let block_ctx = ctx.enter_block()?;
             let mut block = BlockParser::new(block_ctx)?;
 
+            let mut pinned = vec![];
+            let mut targets = vec![];
             let servers = block.required_repeated("server", |ctx| {
                 ctx.validate(&[
                     Rule::NoChildren,
                     Rule::ExactArgs(1),
-                    Rule::OnlyKeysTyped(&[("weight", PrimitiveType::Integer)]),
+                    Rule::OnlyKeysTyped(&[
+                        ("weight", PrimitiveType::Integer),
+                        ("when", PrimitiveType::String),
+                    ]),
                 ])?;
 
-                let address = ctx.first()?.parse_as::<SocketAddr>()?;
-
-                let weight = ctx.opt_prop("weight")?.as_usize()?.unwrap_or(1);
+                let target =
+                    self.resolve_upstream_addr(&ctx, &ctx.first()?.parse_as::<Uri>()?, defaults)?;
+                targets.push((ctx.clone(), target.scheme, target.server_name.clone()));
+
+                let weight = ctx.opt_prop("weight")?;
+                let when = ctx.opt_prop("when")?.parse_as::<Condition>()?;
+
+                let Some(when) = when else {
+                    // every address of a name is a server of its own
+                    let weight = weight.as_usize()?.unwrap_or(1);
+                    return Ok(target
+                        .addrs
+                        .into_iter()
+                        .map(|address| UpstreamServer { address, weight })
+                        .collect());
+                };
+                if weight.is_some() {
+                    return Err(ctx.error(
+                        "'weight' has no effect on a server with 'when', which takes every request it matches",
+                    ));
+                }
 
-                Ok(UpstreamServer { address, weight })
+                let address = target.single_addr().map_err(|err| ctx.error(err))?;
+                pinned.push(PinnedServer { address, when });
+                Ok(vec![])
             })?;
+            let servers = servers.into_iter().flatten().collect::<Vec<_>>();
+
+            if servers.is_empty() {
+                return Err(ctx.error(
+                    "A 'proxy' block needs at least one server without 'when' for the requests no 'when' matches",
+                ));
+            }
 
             let tls_sni = block.optional("tls-sni", |ctx| {
                 ctx.validate(&[Rule::NoChildren, Rule::ExactArgs(1), Rule::OnlyKeys(&[])])?;
@@ -279,22 +870,40 @@ impl<'a> ConnectorsSection<'a> {
                 ctx.first()?.as_str()
             })?;
 
+            let connect_timeout = block.optional("connect-timeout", |ctx| {
+                ctx.validate(&[Rule::NoChildren, Rule::ExactArgs(1), Rule::OnlyKeys(&[])])?;
+                parse_connect_timeout(&ctx, ctx.first()?)
+            })?;
+
             block.exhaust()?;
 
+            // keys set on the `proxy` itself win over `defaults`, which win over `https` servers
+            let tls_sni = match tls_sni.or_else(|| defaults.tls_sni.clone()) {
+                Some(sni) => Some(sni),
+                None => pool_implied_sni(&targets)?,
+            };
+            let proto_str = proto_str.or_else(|| defaults.proto.clone());
+
             let (tls, sni, alpn) =
                 self.resolve_proto_settings(&ctx, proto_str.as_deref(), tls_sni.as_deref())?;
 
-            let final_sni = if sni.is_empty() { None } else { Some(sni) };
+            // TLS without SNI is kept as an empty name, for `https` servers given by IP address
+            let final_sni = tls.then_some(sni);
+
+            let mut upstream = MultiServerUpstreamConfig {
+                servers,
+                pinned,
+                tls_sni: final_sni,
+                alpn,
+                prefix_path: base_path,
+                target_path: PathAndQuery::from_static("/"),
+                matcher: parent_matcher,
+                connect_timeout: connect_timeout.or(defaults.connect_timeout),
+            };
+            upstream.normalize_weights();
 
             Ok(ConnectorsLeaf::Upstream(UpstreamConfig::MultiServer(
-                MultiServerUpstreamConfig {
-                    servers,
-                    tls_sni: final_sni,
-                    alpn,
-                    prefix_path: base_path,
-                    target_path: PathAndQuery::from_static("/"),
-                    matcher: parent_matcher,
-                },
+                upstream,
             )))
         } else {
             ctx.validate(&[
@@ -302,23 +911,31 @@ impl<'a> ConnectorsSection<'a> {
                 Rule::OnlyKeysTyped(&[
                     ("tls-sni", PrimitiveType::String),
                     ("proto", PrimitiveType::String),
+                    ("connect-timeout", PrimitiveType::String),
                 ]),
             ])?;
 
             let uri = ctx.first()?.parse_as::<Uri>()?;
 
-            let host_addr = uri
-                .authority()
-                .and_then(|host| host.as_str().parse::<SocketAddr>().ok())
-                .ok_or(ctx.error("Not a valid socket address"))?;
+            let target = self.resolve_upstream_addr(&ctx, &uri, defaults)?;
+            let host_addr = target.single_addr().map_err(|err| ctx.error(err))?;
 
-            let [sni_opt, proto_opt] = ctx.props(["tls-sni", "proto"])?;
+            let [sni_opt, proto_opt, timeout_opt] =
+                ctx.props(["tls-sni", "proto", "connect-timeout"])?;
 
-            let (tls, sni, alpn) = self.resolve_proto_settings(
-                &ctx,
-                proto_opt.as_str()?.as_deref(),
-                sni_opt.as_str()?.as_deref(),
-            )?;
+            // keys set on the `proxy` itself win over `defaults`, which win over `https`
+            let sni = sni_opt
+                .as_str()?
+                .or_else(|| defaults.tls_sni.clone())
+                .or_else(|| target.implied_sni());
+            let proto = proto_opt.as_str()?.or_else(|| defaults.proto.clone());
+            let connect_timeout = match timeout_opt {
+                Some(value) => Some(parse_connect_timeout(&ctx, value)?),
+                None => defaults.connect_timeout,
+            };
+
+            let (tls, sni, alpn) =
+                self.resolve_proto_settings(&ctx, proto.as_deref(), sni.as_deref())?;
 
             Ok(ConnectorsLeaf::Upstream(UpstreamConfig::Service(
                 HttpPeerConfig {
@@ -329,6 +946,7 @@ impl<'a> ConnectorsSection<'a> {
                     prefix_path: base_path,
                     target_path: uri.path().parse().unwrap_or(PathAndQuery::from_static("/")),
                     matcher: parent_matcher,
+                    connect_timeout,
                 },
             )))
         }
@@ -347,11 +965,27 @@ impl<'a> ConnectorsSection<'a> {
             selection_data: optional("selection") => |ctx| self.parse_selection(ctx, anonymous_definitions),
 
             health_opt: optional("health-check") => |ctx| {
-                ctx.validate(&[Rule::NoChildren, Rule::ExactArgs(1), Rule::OnlyKeys(&[])])?;
+                ctx.validate(&[
+                    Rule::NoChildren,
+                    Rule::ExactArgs(1),
+                    Rule::OnlyKeys(&["interval"]),
+                ])?;
 
-                match ctx.arg(0)?.as_str()?.as_str() {
-                    "None" => Ok(HealthCheckKind::None),
-                    val => Err(ctx.error(format!("Unknown health-check kind: '{val}'"))),
+                let interval = ctx.opt_prop("interval")?.as_duration()?;
+
+                match (ctx.arg(0)?.as_str()?.as_str(), interval) {
+                    ("None", None) => Ok(HealthCheckKind::None),
+                    ("None", Some(_)) => Err(ctx.error(
+                        "'interval' requires a health-check kind other than 'None'",
+                    )),
+                    ("TCP", interval) => {
+                        let interval = interval.unwrap_or(DEFAULT_HEALTH_CHECK_INTERVAL);
+                        if interval.is_zero() {
+                            return Err(ctx.error("health-check 'interval' must be positive"));
+                        }
+                        Ok(HealthCheckKind::Tcp { interval })
+                    }
+                    (val, _) => Err(ctx.error(format!("Unknown health-check kind: '{val}'"))),
                 }
             },
 
@@ -362,7 +996,21 @@ impl<'a> ConnectorsSection<'a> {
                     "Static" => Ok(DiscoveryKind::Static),
                     val => Err(ctx.error(format!("Unknown discovery kind: '{val}'"))),
                 }
-            }
+            },
+
+            slow_start: optional("slow-start") => |ctx| {
+                ctx.validate(&[Rule::NoChildren, Rule::ExactArgs(1), Rule::OnlyKeys(&[])])?;
+
+                let interval = ctx.first()?.as_duration()?;
+
+                if interval.is_zero() {
+                    return Err(ctx.error("'slow-start' interval must be positive"));
+                }
+
+                Ok(interval)
+            },
+
+            outlier_detection: optional("outlier-detection") => |ctx| self.parse_outlier_detection(ctx)
         );
 
         let (selection, template) = selection_data.unwrap_or((SelectionKind::RoundRobin, None));
@@ -374,9 +1022,51 @@ impl<'a> ConnectorsSection<'a> {
             template,
             health_checks,
             discovery,
+            slow_start,
+            outlier_detection,
         }))
     }
 
+    fn parse_outlier_detection(&self, ctx: ParseContext<'_>) -> miette::Result<OutlierDetection> {
+        ctx.validate(&[Rule::ReqChildren, Rule::NoArgs])?;
+
+        let block_ctx = ctx.enter_block()?;
+
+        block_parser!(block_ctx,
+            consecutive_errors: required("consecutive-errors") => |ctx| {
+                ctx.validate(&[Rule::NoChildren, Rule::ExactArgs(1), Rule::OnlyKeys(&[])])?;
+
+                let value = ctx.first()?;
+                let count = value.as_usize()?;
+
+                if !(1..=MAX_CONSECUTIVE_ERRORS).contains(&count) {
+                    return Err(value.error(format!(
+                        "Value of 'consecutive-errors' must be between 1 and {MAX_CONSECUTIVE_ERRORS}, found {count}"
+                    )));
+                }
+
+                Ok(count)
+            },
+
+            ejection_time: required("ejection-time") => |ctx| {
+                ctx.validate(&[Rule::NoChildren, Rule::ExactArgs(1), Rule::OnlyKeys(&[])])?;
+
+                let time = ctx.first()?.as_duration()?;
+
+                if time.is_zero() {
+                    return Err(ctx.error("'ejection-time' must be positive"));
+                }
+
+                Ok(time)
+            }
+        );
+
+        Ok(OutlierDetection {
+            consecutive_errors,
+            ejection_time,
+        })
+    }
+
     fn parse_selection(
         &self,
         ctx: ParseContext<'_>,
@@ -402,23 +1092,24 @@ impl<'a> ConnectorsSection<'a> {
         )
     }
 
+    /// Resolves `[scheme://]host[:port]`, filling the missing parts from the connector defaults.
+    fn resolve_upstream_addr(
+        &self,
+        ctx: &ParseContext<'_>,
+        uri: &Uri,
+        defaults: &UpstreamDefaults,
+    ) -> miette::Result<ResolvedUpstream> {
+        ResolvedUpstream::resolve(uri, defaults.scheme, defaults.port, self.resolver)
+            .map_err(|err| ctx.error(err))
+    }
+
     fn resolve_proto_settings(
         &self,
         ctx: &ParseContext<'_>,
         proto: Option<&str>,
         tls_sni: Option<&str>,
     ) -> miette::Result<(bool, String, ALPN)> {
-        let alpn = match proto {
-            Some(p) => parse_proto_value(p).map_err(|msg| ctx.error(msg))?,
-            None => None,
-        };
-
-        match (alpn, tls_sni) {
-            (None, None) | (Some(ALPN::H1), None) => Ok((false, String::new(), ALPN::H1)),
-            (None, Some(sni)) => Ok((true, sni.to_string(), ALPN::H2H1)),
-            (Some(_), None) => Err(ctx.error("'tls-sni' is required for HTTP2 support")),
-            (Some(p), Some(sni)) => Ok((true, sni.to_string(), p)),
-        }
+        resolve_proto_settings(proto, tls_sni).map_err(|err| ctx.error(err))
     }
 
     fn validate_selection(
@@ -470,15 +1161,40 @@ impl<'a> ConnectorsSection<'a> {
     }
 }
 
-/// Recursive function to flatten the node tree
+/// Settings a section hands down to the sections nested in it.
+#[derive(Clone, Default)]
+struct Inherited {
+    chains: Vec<Modificator>,
+    request_headers: Vec<HeaderRule<HeaderTemplate>>,
+    response_headers: Vec<HeaderRule>,
+    cache: Option<CacheConfig>,
+    path_regex: Option<PathRegex>,
+    buffer_request_body: bool,
+    buffer_response_body: bool,
+    buffer_body_limit: Option<usize>,
+    allow_upgrades: Option<bool>,
+    upstream_accept_encoding: UpstreamAcceptEncoding,
+    host_header: UpstreamHost,
+    upstream_method: Option<Method>,
+    response_status_map: StatusMap,
+    protocol: UpstreamProtocol,
+    upstream_http_version: Option<UpstreamHttpVersion>,
+    latency_budget: Option<Duration>,
+    request_timeout: Option<Duration>,
+    rate_limit: Option<RateLimitConfig>,
+    require_tls: RequireTls,
+    forwarded_headers: ForwardedHeaders,
+}
+
+/// Recursive function to flatten the node tree
 fn flatten_nodes(
     nodes: Vec<ConnectorsLeaf>,
-    parent_chains: &[Modificator], // Chains inherited from parents
+    parent: &Inherited,
 ) -> miette::Result<Vec<UpstreamContextConfig>> {
     let mut results = Vec::new();
 
     // 1. Build context for the current level
-    let mut current_chains = parent_chains.to_vec();
+    let mut current = parent.clone();
     let mut local_lb_options: Option<UpstreamOptions> = None;
 
     // Separate configuration (chains, lb) from structure (upstreams, sections)
@@ -486,8 +1202,32 @@ fn flatten_nodes(
 
     for node in nodes {
         match node {
-            ConnectorsLeaf::Modificator(m) => current_chains.push(m),
+            ConnectorsLeaf::Modificator(m) => current.chains.push(m),
             ConnectorsLeaf::LoadBalance(lb) => local_lb_options = Some(lb),
+            ConnectorsLeaf::RequestHeaders(rules) => current.request_headers.extend(rules),
+            ConnectorsLeaf::ResponseHeaders(rules) => current.response_headers.extend(rules),
+            // the closest `cache` block wins
+            ConnectorsLeaf::Cache(cache) => current.cache = Some(cache),
+            ConnectorsLeaf::PathRegex(regex) => current.path_regex = Some(regex),
+            ConnectorsLeaf::BufferRequestBody(buffer) => current.buffer_request_body = buffer,
+            ConnectorsLeaf::BufferResponseBody(buffer) => current.buffer_response_body = buffer,
+            ConnectorsLeaf::BufferBodyLimit(limit) => current.buffer_body_limit = Some(limit),
+            ConnectorsLeaf::AllowUpgrades(allow) => current.allow_upgrades = Some(allow),
+            ConnectorsLeaf::UpstreamAcceptEncoding(encoding) => {
+                current.upstream_accept_encoding = encoding
+            }
+            ConnectorsLeaf::UpstreamHost(host) => current.host_header = host,
+            ConnectorsLeaf::UpstreamMethod(method) => current.upstream_method = Some(method),
+            ConnectorsLeaf::ResponseStatusMap(map) => current.response_status_map.extend(map),
+            ConnectorsLeaf::Protocol(protocol) => current.protocol = protocol,
+            ConnectorsLeaf::UpstreamHttpVersion(version) => {
+                current.upstream_http_version = Some(version)
+            }
+            ConnectorsLeaf::LatencyBudget(budget) => current.latency_budget = Some(budget),
+            ConnectorsLeaf::RequestTimeout(timeout) => current.request_timeout = Some(timeout),
+            ConnectorsLeaf::RateLimit(limit) => current.rate_limit = Some(limit),
+            ConnectorsLeaf::RequireTls(require) => current.require_tls = require,
+            ConnectorsLeaf::ForwardedHeaders(forwarded) => current.forwarded_headers = forwarded,
             s => structure.push(s),
         }
     }
@@ -495,22 +1235,65 @@ fn flatten_nodes(
     // 2. Traverse structural elements
     for node in structure {
         match node {
-            ConnectorsLeaf::Upstream(up) => {
+            ConnectorsLeaf::Upstream(mut up) => {
                 // VALIDATION: Check compatibility if LoadBalance is present
                 if local_lb_options.is_some() && !matches!(up, UpstreamConfig::MultiServer(_)) {
                     return Err(miette::miette!(
                         "The 'load-balance' directive can only be applied to 'proxy' blocks with multiple servers (MultiServer). Found incompatible upstream (Static or Single Service) in the same section."
                     ));
                 }
+                if let (Some(lb), UpstreamConfig::MultiServer(pool)) = (&local_lb_options, &up) {
+                    if lb.selection == SelectionKind::WeightedRandom
+                        && pool.servers.iter().all(|server| server.weight == 0)
+                    {
+                        return Err(miette::miette!(
+                            "'WeightedRandom' selection requires at least one server with a positive 'weight'"
+                        ));
+                    }
+                }
+                if let Some(version) = current.upstream_http_version {
+                    if current.protocol == UpstreamProtocol::Grpc
+                        && version == UpstreamHttpVersion::Http1
+                    {
+                        return Err(miette::miette!(
+                            "'protocol \"grpc\"' requires an HTTP/2 upstream, but 'upstream-http-version' is \"1.1\""
+                        ));
+                    }
+                    apply_http_version(&mut up, version)?;
+                }
+                if current.protocol == UpstreamProtocol::Grpc {
+                    apply_grpc(&mut up, &current)?;
+                }
 
                 results.push(UpstreamContextConfig {
                     upstream: up,
-                    chains: current_chains.clone(),
+                    chains: current.chains.clone(),
                     lb_options: local_lb_options.clone(),
+                    request_headers: current.request_headers.clone(),
+                    response_headers: current.response_headers.clone(),
+                    cache: current.cache.clone(),
+                    path_regex: current.path_regex.clone(),
+                    buffer_request_body: current.buffer_request_body,
+                    buffer_response_body: current.buffer_response_body,
+                    buffer_body_limit: current
+                        .buffer_body_limit
+                        .unwrap_or(DEFAULT_BUFFER_BODY_LIMIT),
+                    allow_upgrades: current.allow_upgrades.unwrap_or(true),
+                    upstream_accept_encoding: current.upstream_accept_encoding.clone(),
+                    host_header: current.host_header.clone(),
+                    upstream_method: current.upstream_method.clone(),
+                    response_status_map: current.response_status_map.clone(),
+                    protocol: current.protocol,
+                    upstream_http_version: current.upstream_http_version,
+                    latency_budget: current.latency_budget,
+                    request_timeout: current.request_timeout,
+                    rate_limit: current.rate_limit.clone(),
+                    require_tls: current.require_tls,
+                    forwarded_headers: current.forwarded_headers.clone(),
                 });
             }
             ConnectorsLeaf::Section(children) => {
-                let children_flat = flatten_nodes(children, &current_chains)?;
+                let children_flat = flatten_nodes(children, &current)?;
                 results.extend(children_flat);
             }
             _ => unreachable!(),
@@ -520,6 +1303,117 @@ fn flatten_nodes(
     Ok(results)
 }
 
+/// Sets the ALPN of a connector's upstream from its `upstream-http-version`, refusing the
+/// `proto` and TLS settings it contradicts.
+fn apply_http_version(
+    upstream: &mut UpstreamConfig,
+    version: UpstreamHttpVersion,
+) -> miette::Result<()> {
+    let (tls, alpn) = match upstream {
+        UpstreamConfig::Service(peer) => (peer.tls, &mut peer.alpn),
+        UpstreamConfig::MultiServer(pool) => (pool.tls_sni.is_some(), &mut pool.alpn),
+        // a `return` response has no upstream to talk to
+        UpstreamConfig::Static(_) => return Ok(()),
+    };
+
+    *alpn = match (version, tls, &*alpn) {
+        (UpstreamHttpVersion::Http1, _, ALPN::H2) => {
+            return Err(miette::miette!(
+                "'upstream-http-version \"1.1\"' contradicts the 'proto=\"h2-only\"' of the 'proxy'"
+            ));
+        }
+        (UpstreamHttpVersion::Http1, _, _) => ALPN::H1,
+        (UpstreamHttpVersion::Http2 { .. }, true, ALPN::H1) => {
+            return Err(miette::miette!(
+                "'upstream-http-version \"2\"' contradicts the 'proto=\"h1-only\"' of the 'proxy'"
+            ));
+        }
+        (UpstreamHttpVersion::Http2 { h2c: false }, false, _) => {
+            return Err(miette::miette!(
+                "'upstream-http-version \"2\"' on a cleartext upstream needs HTTP/2 with prior knowledge, allow it with 'h2c=#true' or set 'tls-sni' on the 'proxy'"
+            ));
+        }
+        (UpstreamHttpVersion::Http2 { .. }, _, _) => ALPN::H2,
+        // the `proto` of the `proxy` is negotiated, `h2-or-h1` unless it says otherwise
+        (UpstreamHttpVersion::Auto, true, alpn) => alpn.clone(),
+        (UpstreamHttpVersion::Auto, false, _) => ALPN::H1,
+    };
+    Ok(())
+}
+
+/// Makes a `protocol "grpc"` connector negotiate HTTP/2 with its upstream, refusing the
+/// settings that would lose the response trailers on the way.
+fn apply_grpc(upstream: &mut UpstreamConfig, current: &Inherited) -> miette::Result<()> {
+    let (tls, alpn) = match upstream {
+        UpstreamConfig::Service(peer) => (peer.tls, &mut peer.alpn),
+        UpstreamConfig::MultiServer(pool) => (pool.tls_sni.is_some(), &mut pool.alpn),
+        UpstreamConfig::Static(_) => {
+            return Err(miette::miette!(
+                "'protocol \"grpc\"' needs a 'proxy' connector, a 'return' response has no upstream to stream from"
+            ));
+        }
+    };
+
+    if !tls {
+        return Err(miette::miette!(
+            "'protocol \"grpc\"' requires an HTTP/2 upstream, set 'tls-sni' on the 'proxy'"
+        ));
+    }
+    if *alpn == ALPN::H1 {
+        return Err(miette::miette!(
+            "'protocol \"grpc\"' requires an HTTP/2 upstream, but the 'proxy' sets 'proto=\"h1-only\"'"
+        ));
+    }
+    if current.buffer_response_body {
+        return Err(miette::miette!(
+            "'protocol \"grpc\"' streams responses and can't be combined with 'buffer-response-body'"
+        ));
+    }
+    if current.cache.is_some() {
+        return Err(miette::miette!(
+            "'protocol \"grpc\"' can't be combined with 'cache', cached responses don't keep their trailers"
+        ));
+    }
+
+    *alpn = ALPN::H2;
+    Ok(())
+}
+
+/// A positive `connect-timeout`, like `"2s"`.
+fn parse_connect_timeout(
+    ctx: &ParseContext<'_>,
+    value: TypedValue<'_>,
+) -> miette::Result<Duration> {
+    let timeout = value.as_duration()?;
+    if timeout.is_zero() {
+        return Err(ctx.error("'connect-timeout' must be positive"));
+    }
+    Ok(timeout)
+}
+
+/// `request-timeout "60s"`, a positive duration.
+pub(crate) fn parse_request_timeout(ctx: ParseContext<'_>) -> miette::Result<Duration> {
+    ctx.validate(&[Rule::NoChildren, Rule::ExactArgs(1), Rule::OnlyKeys(&[])])?;
+
+    let timeout = ctx.first()?.as_duration()?;
+    if timeout.is_zero() {
+        return Err(ctx.error("'request-timeout' must be positive"));
+    }
+    Ok(timeout)
+}
+
+fn parse_status_code(ctx: &ParseContext<'_>, value: &str) -> miette::Result<u16> {
+    value
+        .parse::<u16>()
+        .ok()
+        .filter(|code| (100..=599).contains(code))
+        .ok_or_else(|| {
+            ctx.error(format!(
+                "Invalid status code '{value}': expected a number between 100 and 599"
+            ))
+        })
+}
+
 fn parse_proto_value(value: &str) -> Result<Option<ALPN>, String> {
     match value {
         "h1-only" => Ok(Some(ALPN::H1)),
@@ -604,6 +1498,1186 @@ mod tests {
         assert!(lb_options.template.is_none());
     }
 
+    const DEFAULT_SCHEME_AND_PORT: &str = r#"
+    connectors default-scheme="http" default-port=8080 {
+        section "/bare" {
+            proxy "127.0.0.1"
+        }
+        section "/explicit" {
+            proxy "127.0.0.1:443"
+        }
+        section "/multi" {
+            proxy {
+                server "127.0.0.2"
+                server "127.0.0.3:9000"
+            }
+        }
+    }
+    "#;
+
+    fn service_addr(connectors: &Connectors, prefix: &str) -> SocketAddr {
+        connectors
+            .upstreams
+            .iter()
+            .find_map(|u| match &u.upstream {
+                UpstreamConfig::Service(s) if s.prefix_path == prefix => Some(s.peer_address),
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn test_bare_host_gets_default_port() {
+        let connectors = parse_config(DEFAULT_SCHEME_AND_PORT).expect("Parsing failed");
+
+        assert_eq!(
+            service_addr(&connectors, "/bare"),
+            "127.0.0.1:8080".parse().unwrap()
+        );
+        assert_eq!(
+            service_addr(&connectors, "/explicit"),
+            "127.0.0.1:443".parse().unwrap()
+        );
+
+        let multi = connectors
+            .upstreams
+            .iter()
+            .find_map(|u| match &u.upstream {
+                UpstreamConfig::MultiServer(m) => Some(m),
+                _ => None,
+            })
+            .unwrap();
+
+        assert_eq!(multi.servers[0].address, "127.0.0.2:8080".parse().unwrap());
+        assert_eq!(multi.servers[1].address, "127.0.0.3:9000".parse().unwrap());
+    }
+
+    #[test]
+    fn test_connector_defaults() {
+        let connectors = parse_config(
+            r#"
+            connectors {
+                defaults {
+                    connect-timeout "2s"
+                }
+                section "/a" {
+                    proxy "127.0.0.1:8001"
+                }
+                section "/b" {
+                    proxy "127.0.0.1:8002" connect-timeout="500ms"
+                }
+                section "/c" {
+                    defaults {
+                        connect-timeout "10s"
+                    }
+                    proxy {
+                        server "127.0.0.1:8003"
+                    }
+                }
+            }
+            "#,
+        )
+        .expect("Parsing failed");
+
+        let timeouts = connectors
+            .upstreams
+            .iter()
+            .map(|u| match &u.upstream {
+                UpstreamConfig::Service(s) => (s.prefix_path.to_string(), s.connect_timeout),
+                UpstreamConfig::MultiServer(m) => (m.prefix_path.to_string(), m.connect_timeout),
+                UpstreamConfig::Static(_) => panic!("expected a proxy"),
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            timeouts,
+            vec![
+                ("/a".to_string(), Some(Duration::from_secs(2))),
+                // set on the proxy itself
+                ("/b".to_string(), Some(Duration::from_millis(500))),
+                // the section's own defaults win over the enclosing ones
+                ("/c".to_string(), Some(Duration::from_secs(10))),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_connector_defaults_tls() {
+        let connectors = parse_config(
+            r#"
+            connectors {
+                defaults {
+                    tls-sni "internal.example"
+                    proto "h2-only"
+                }
+                section "/a" {
+                    proxy "127.0.0.1:8443"
+                }
+                section "/b" {
+                    proxy "127.0.0.1:9443" proto="h1-only"
+                }
+            }
+            "#,
+        )
+        .expect("Parsing failed");
+
+        let peers = connectors
+            .upstreams
+            .iter()
+            .map(|u| match &u.upstream {
+                UpstreamConfig::Service(s) => (s.tls, s.sni.clone(), s.alpn.clone()),
+                _ => panic!("expected a single proxy"),
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            peers,
+            vec![
+                (true, "internal.example".to_string(), ALPN::H2),
+                (true, "internal.example".to_string(), ALPN::H1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_connector_defaults_invalid() {
+        let result = parse_config(
+            r#"
+            connectors {
+                defaults {
+                    read-timeout "2s"
+                }
+                proxy "127.0.0.1:8000"
+            }
+            "#,
+        );
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(err_msg, "Unknown directive: 'read-timeout'");
+
+        let result = parse_config(
+            r#"
+            connectors {
+                defaults {
+                    connect-timeout "0s"
+                }
+                proxy "127.0.0.1:8000"
+            }
+            "#,
+        );
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(err_msg, "'connect-timeout' must be positive");
+    }
+
+    #[test]
+    fn test_default_port_from_scheme() {
+        let connectors = parse_config(
+            r#"
+            connectors default-scheme="https" {
+                proxy "127.0.0.1"
+            }
+            "#,
+        )
+        .expect("Parsing failed");
+
+        assert_eq!(
+            service_addr(&connectors, "/"),
+            "127.0.0.1:443".parse().unwrap()
+        );
+
+        let connectors = parse_config(
+            r#"
+            connectors default-scheme="https" {
+                proxy "http://127.0.0.1"
+            }
+            "#,
+        )
+        .expect("Parsing failed");
+
+        assert_eq!(
+            service_addr(&connectors, "/"),
+            "127.0.0.1:80".parse().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_https_implies_tls() {
+        let connectors = parse_config(
+            r#"
+            connectors default-scheme="https" {
+                section "/service" {
+                    proxy "127.0.0.1"
+                }
+                section "/plain" {
+                    proxy "http://127.0.0.1"
+                }
+                section "/pool" {
+                    proxy {
+                        server "127.0.0.2"
+                        server "https://127.0.0.3:8443"
+                    }
+                }
+            }
+            "#,
+        )
+        .expect("Parsing failed");
+
+        let service = |prefix: &str| {
+            connectors
+                .upstreams
+                .iter()
+                .find_map(|u| match &u.upstream {
+                    UpstreamConfig::Service(s) if s.prefix_path == prefix => Some(s.clone()),
+                    _ => None,
+                })
+                .unwrap()
+        };
+        // an IP address is not sent as SNI
+        assert!(service("/service").tls);
+        assert_eq!(service("/service").sni, "");
+        assert!(!service("/plain").tls);
+
+        let pool = connectors
+            .upstreams
+            .iter()
+            .find_map(|u| match &u.upstream {
+                UpstreamConfig::MultiServer(m) => Some(m),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(pool.tls_sni.as_deref(), Some(""));
+    }
+
+    #[test]
+    fn test_https_server_name_is_sni() {
+        let target = ResolvedUpstream {
+            addrs: vec!["10.0.0.1:443".parse().unwrap()],
+            scheme: UpstreamScheme::Https,
+            server_name: Some("api.example.com".to_string()),
+        };
+        assert_eq!(target.implied_sni().as_deref(), Some("api.example.com"));
+
+        let plain = ResolvedUpstream {
+            scheme: UpstreamScheme::Http,
+            ..target
+        };
+        assert_eq!(plain.implied_sni(), None);
+    }
+
+    #[test]
+    fn test_error_name_with_several_addresses_for_single_peer() {
+        let target = ResolvedUpstream {
+            addrs: vec![
+                "10.0.0.1:80".parse().unwrap(),
+                "10.0.0.2:80".parse().unwrap(),
+            ],
+            scheme: UpstreamScheme::Http,
+            server_name: Some("api.example.com".to_string()),
+        };
+
+        let err_msg = target.single_addr().unwrap_err();
+        assert_err_contains!(err_msg, "'api.example.com' resolves to 2 addresses");
+    }
+
+    #[test]
+    fn test_error_pool_mixing_schemes() {
+        let result = parse_config(
+            r#"
+            connectors {
+                proxy {
+                    server "https://127.0.0.1"
+                    server "http://127.0.0.2"
+                }
+            }
+            "#,
+        );
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(
+            err_msg,
+            "The servers of a 'proxy' block must all be 'http' or all be 'https'"
+        );
+    }
+
+    #[test]
+    fn test_error_invalid_defaults() {
+        let result = parse_config(r#"connectors default-scheme="ftp" { proxy "127.0.0.1" }"#);
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(err_msg, "unknown scheme 'ftp'");
+
+        let result = parse_config(r#"connectors default-port=70000 { proxy "127.0.0.1" }"#);
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(
+            err_msg,
+            "Value of 'default-port' must be between 1 and 65535"
+        );
+    }
+
+    const RESPONSE_HEADERS_INHERITANCE: &str = r#"
+    connectors {
+        response-headers {
+            remove "Server"
+        }
+        section "/api" {
+            response-headers {
+                set "X-Frame-Options" "DENY"
+            }
+            proxy "http://127.0.0.1:8081"
+        }
+        section "/public" {
+            proxy "http://127.0.0.1:8082"
+        }
+    }
+    "#;
+
+    #[test]
+    fn test_response_headers_inheritance() {
+        let connectors = parse_config(RESPONSE_HEADERS_INHERITANCE).expect("Parsing failed");
+
+        let rules_for = |prefix: &str| {
+            connectors
+                .upstreams
+                .iter()
+                .find(|u| matches!(&u.upstream, UpstreamConfig::Service(s) if s.prefix_path == prefix))
+                .map(|u| u.response_headers.clone())
+                .unwrap()
+        };
+
+        let server = http::HeaderName::from_static("server");
+
+        assert_eq!(
+            rules_for("/api"),
+            vec![
+                HeaderRule::Remove {
+                    name: server.clone()
+                },
+                HeaderRule::Set {
+                    name: http::HeaderName::from_static("x-frame-options"),
+                    value: "DENY".to_string(),
+                },
+            ]
+        );
+        assert_eq!(
+            rules_for("/public"),
+            vec![HeaderRule::Remove { name: server }]
+        );
+    }
+
+    #[test]
+    fn test_request_headers_unknown_variable() {
+        let result = parse_config(
+            r#"
+        connectors {
+            request-headers {
+                set "X-Real-IP" "$remote_addr"
+            }
+            proxy "http://127.0.0.1:8081"
+        }
+        "#,
+        );
+
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(err_msg, "unknown variable '$remote_addr'");
+    }
+
+    const CACHE_INHERITANCE: &str = r#"
+    connectors {
+        cache {
+            key-profile "ip-profile"
+            ttl "60s"
+        }
+        section "/static" {
+            proxy "http://127.0.0.1:8081"
+        }
+        section "/api" {
+            cache {
+                key-profile "ip-profile"
+                ttl "5s"
+            }
+            proxy "http://127.0.0.1:8082"
+        }
+    }
+    "#;
+
+    #[test]
+    fn test_cache_closest_block_wins() {
+        let connectors =
+            parse_config_with_defs(DEFS_KEY_PROFILE, CACHE_INHERITANCE).expect("Parsing failed");
+
+        let ttl_for = |prefix: &str| {
+            connectors
+                .upstreams
+                .iter()
+                .find(|u| matches!(&u.upstream, UpstreamConfig::Service(s) if s.prefix_path == prefix))
+                .and_then(|u| u.cache.as_ref())
+                .map(|c| c.ttl)
+        };
+
+        assert_eq!(ttl_for("/static"), Some(std::time::Duration::from_secs(60)));
+        assert_eq!(ttl_for("/api"), Some(std::time::Duration::from_secs(5)));
+    }
+
+    const PATH_REGEX_SECTION: &str = r#"
+    connectors {
+        section path-regex="^/users/\\d+$" {
+            proxy "http://127.0.0.1:8000"
+        }
+        section "/" as="prefix" {
+            proxy "http://127.0.0.1:8001"
+        }
+    }
+    "#;
+
+    #[test]
+    fn test_path_regex_section() {
+        let connectors = parse_config(PATH_REGEX_SECTION).expect("Parsing failed");
+
+        let regex = connectors.upstreams[0]
+            .path_regex
+            .as_ref()
+            .expect("first section should be routed by regex");
+        assert!(regex.0.is_match("/users/42"));
+        assert!(!regex.0.is_match("/users/me"));
+
+        assert!(connectors.upstreams[1].path_regex.is_none());
+    }
+
+    #[test]
+    fn test_error_path_regex_invalid() {
+        let result = parse_config(
+            r#"connectors { section path-regex="^/users/(\\d+$" { proxy "http://127.0.0.1:8000"; } }"#,
+        );
+
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(err_msg, "Invalid 'path-regex'");
+    }
+
+    #[test]
+    fn test_error_path_and_path_regex() {
+        let result = parse_config(
+            r#"connectors { section "/users" path-regex="^/users/\\d+$" { proxy "http://127.0.0.1:8000"; } }"#,
+        );
+
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(err_msg, "A section cannot set both a path and 'path-regex'");
+    }
+
+    const BODY_BUFFERING: &str = r#"
+    connectors {
+        buffer-request-body #true
+        section "/upload" {
+            buffer-response-body #true
+            proxy "http://127.0.0.1:8000"
+        }
+        section "/stream" {
+            buffer-request-body #false
+            buffer-body-limit "64KB"
+            proxy "http://127.0.0.1:8001"
+        }
+    }
+    "#;
+
+    #[test]
+    fn test_body_buffering_inheritance() {
+        let connectors = parse_config(BODY_BUFFERING).expect("Parsing failed");
+
+        let buffering = connectors
+            .upstreams
+            .iter()
+            .map(|u| {
+                (
+                    u.buffer_request_body,
+                    u.buffer_response_body,
+                    u.buffer_body_limit,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            buffering,
+            vec![
+                (true, true, DEFAULT_BUFFER_BODY_LIMIT),
+                (false, false, 64 * 1024)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_error_buffer_body_limit_zero() {
+        let result = parse_config(
+            r#"connectors { buffer-body-limit "0B"; proxy "http://127.0.0.1:8000"; }"#,
+        );
+
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(err_msg, "'buffer-body-limit' must be positive");
+    }
+
+    const UPGRADES: &str = r#"
+    connectors {
+        section "/ws" {
+            proxy "http://127.0.0.1:8000"
+        }
+        section "/api" {
+            allow-upgrades #false
+            proxy "http://127.0.0.1:8001"
+            section "/live" {
+                allow-upgrades #true
+                proxy "http://127.0.0.1:8002"
+            }
+        }
+    }
+    "#;
+
+    #[test]
+    fn test_allow_upgrades_inheritance() {
+        let connectors = parse_config(UPGRADES).expect("Parsing failed");
+
+        let allowed = connectors
+            .upstreams
+            .iter()
+            .map(|u| u.allow_upgrades)
+            .collect::<Vec<_>>();
+
+        assert_eq!(allowed, vec![true, false, true]);
+    }
+
+    #[test]
+    fn test_error_body_buffering_not_bool() {
+        let result = parse_config(
+            r#"connectors { buffer-request-body "yes"; proxy "http://127.0.0.1:8000"; }"#,
+        );
+
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(err_msg, "Expected a boolean");
+    }
+
+    #[test]
+    fn test_upstream_accept_encoding() {
+        let connectors = parse_config(
+            r#"
+            connectors {
+                upstream-accept-encoding "identity"
+                section "/raw" {
+                    upstream-accept-encoding "passthrough"
+                    proxy "http://127.0.0.1:8000"
+                }
+                proxy "http://127.0.0.1:8001"
+            }
+            "#,
+        )
+        .expect("Parsing failed");
+
+        let encodings = connectors
+            .upstreams
+            .iter()
+            .map(|u| u.upstream_accept_encoding.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            encodings,
+            vec![
+                UpstreamAcceptEncoding::Identity,
+                UpstreamAcceptEncoding::Passthrough
+            ]
+        );
+
+        let result = parse_config(
+            r#"connectors { upstream-accept-encoding "gzip, lzma"; proxy "http://127.0.0.1:8000"; }"#,
+        );
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(err_msg, "unknown content coding 'lzma'");
+    }
+
+    #[test]
+    fn test_host_header() {
+        let connectors = parse_config(
+            r#"
+            connectors {
+                section "/legacy" as="prefix" {
+                    host-header "backend.internal"
+                    section "/public" {
+                        host-header "passthrough"
+                        proxy "http://127.0.0.1:8000"
+                    }
+                    proxy "http://127.0.0.1:8001"
+                }
+                proxy "http://127.0.0.1:8002"
+            }
+            "#,
+        )
+        .expect("Parsing failed");
+
+        let hosts = connectors
+            .upstreams
+            .iter()
+            .map(|u| u.host_header.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            hosts,
+            vec![
+                UpstreamHost::Passthrough,
+                UpstreamHost::Override("backend.internal".to_string()),
+                UpstreamHost::Passthrough,
+            ]
+        );
+
+        let result = parse_config(
+            r#"connectors { host-header "backend internal"; proxy "http://127.0.0.1:8000"; }"#,
+        );
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(err_msg, "'backend internal' is not a valid host");
+    }
+
+    #[test]
+    fn test_upstream_method() {
+        let connectors = parse_config(
+            r#"
+            connectors {
+                section "/probe" as="prefix" {
+                    upstream-method "HEAD"
+                    section "/probe/full" {
+                        upstream-method "GET"
+                        proxy "http://127.0.0.1:8000"
+                    }
+                    proxy "http://127.0.0.1:8001"
+                }
+                proxy "http://127.0.0.1:8002"
+            }
+            "#,
+        )
+        .expect("Parsing failed");
+
+        let methods = connectors
+            .upstreams
+            .iter()
+            .map(|u| u.upstream_method.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(methods, vec![None, Some(Method::HEAD), Some(Method::GET)]);
+
+        for (method, expected) in [
+            ("FETCH", "Unknown method 'FETCH'"),
+            ("head", "Unknown method 'head'"),
+            ("CONNECT", "Unknown method 'CONNECT'"),
+        ] {
+            let result = parse_config(&format!(
+                r#"connectors {{ upstream-method "{method}"; proxy "http://127.0.0.1:8000"; }}"#
+            ));
+            let err_msg = result.unwrap_err().help().unwrap().to_string();
+            assert_err_contains!(err_msg, expected);
+        }
+    }
+
+    #[test]
+    fn test_require_tls() {
+        let connectors = parse_config(
+            r#"
+            connectors {
+                section "/admin" as="prefix" require-tls=#true {
+                    section "/login" require-tls=#true https-redirect=#true {
+                        proxy "http://127.0.0.1:8000"
+                    }
+                    section "/health" require-tls=#false {
+                        proxy "http://127.0.0.1:8003"
+                    }
+                    proxy "http://127.0.0.1:8001"
+                }
+                section path-regex="^/internal/.*$" require-tls=#true {
+                    proxy "http://127.0.0.1:8004"
+                }
+                proxy "http://127.0.0.1:8002"
+            }
+            "#,
+        )
+        .expect("Parsing failed");
+
+        let required = connectors
+            .upstreams
+            .iter()
+            .map(|u| u.require_tls)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            required,
+            vec![
+                RequireTls::No,
+                RequireTls::Reject,
+                RequireTls::Redirect,
+                RequireTls::No,
+                RequireTls::Reject
+            ]
+        );
+
+        for (keys, message) in [
+            (r#"require-tls="yes""#, "Invalid type for key 'require-tls'"),
+            (
+                r#"require-tls=#false https-redirect=#true"#,
+                "'https-redirect' only applies to 'require-tls=#true'",
+            ),
+            (
+                r#"https-redirect=#true"#,
+                "'https-redirect' only applies to 'require-tls=#true'",
+            ),
+        ] {
+            let result = parse_config(&format!(
+                r#"connectors {{ section "/admin" {keys} {{ proxy "http://127.0.0.1:8000"; }}; }}"#
+            ));
+            let err_msg = result.unwrap_err().help().unwrap().to_string();
+            assert_err_contains!(err_msg, message);
+        }
+
+        // the directive form is gone, the key goes on the section it guards
+        let result =
+            parse_config(r#"connectors { require-tls #true; proxy "http://127.0.0.1:8000"; }"#);
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(err_msg, "Unknown directive: 'require-tls'");
+    }
+
+    #[test]
+    fn test_forwarded_headers() {
+        let connectors = parse_config(
+            r#"
+            connectors {
+                forwarded-headers #true rfc7239=#true trusted-proxies="10.0.0.0/8, 127.0.0.1"
+                section "/internal" {
+                    forwarded-headers #false
+                    proxy "http://127.0.0.1:8000"
+                }
+                proxy "http://127.0.0.1:8001"
+            }
+            "#,
+        )
+        .expect("Parsing failed");
+
+        let internal = &connectors.upstreams[0].forwarded_headers;
+        assert!(!internal.enabled);
+        assert!(internal.trusted_proxies.is_empty());
+
+        let root = &connectors.upstreams[1].forwarded_headers;
+        assert!(root.enabled && root.rfc7239);
+        assert!(root.is_trusted("10.1.2.3".parse().unwrap()));
+        assert!(root.is_trusted("127.0.0.1".parse().unwrap()));
+        assert!(!root.is_trusted("203.0.113.7".parse().unwrap()));
+
+        let defaults = parse_config(r#"connectors { proxy "http://127.0.0.1:8000"; }"#).unwrap();
+        assert_eq!(
+            defaults.upstreams[0].forwarded_headers,
+            ForwardedHeaders::default()
+        );
+
+        let result = parse_config(
+            r#"connectors { forwarded-headers #true trusted-proxies="10.0.0.0/33"; proxy "http://127.0.0.1:8000"; }"#,
+        );
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(err_msg, "'10.0.0.0/33' is not an IP address or CIDR range");
+    }
+
+    #[test]
+    fn test_latency_budget() {
+        let connectors = parse_config(
+            r#"
+            connectors {
+                latency-budget "2s"
+                section "/reports" as="prefix" {
+                    latency-budget "30s"
+                    proxy "http://127.0.0.1:8000"
+                }
+                proxy "http://127.0.0.1:8001"
+            }
+            "#,
+        )
+        .expect("Parsing failed");
+
+        let budgets = connectors
+            .upstreams
+            .iter()
+            .map(|u| u.latency_budget)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            budgets,
+            vec![Some(Duration::from_secs(2)), Some(Duration::from_secs(30))]
+        );
+
+        let result =
+            parse_config(r#"connectors { latency-budget "soon"; proxy "http://127.0.0.1:8000"; }"#);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_request_timeout() {
+        let connectors = parse_config(
+            r#"
+            connectors {
+                request-timeout "10s"
+                section "/reports" as="prefix" {
+                    request-timeout "60s"
+                    proxy "http://127.0.0.1:8000"
+                }
+                proxy "http://127.0.0.1:8001"
+            }
+            "#,
+        )
+        .expect("Parsing failed");
+
+        let timeouts = connectors
+            .upstreams
+            .iter()
+            .map(|u| u.request_timeout)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            timeouts,
+            vec![Some(Duration::from_secs(10)), Some(Duration::from_secs(60))]
+        );
+
+        let result =
+            parse_config(r#"connectors { request-timeout "0s"; proxy "http://127.0.0.1:8000"; }"#);
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(err_msg, "'request-timeout' must be positive");
+    }
+
+    #[test]
+    fn test_grpc_protocol() {
+        let connectors = parse_config(
+            r#"
+            connectors {
+                section "/helloworld.Greeter" as="prefix" {
+                    protocol "grpc"
+                    proxy "https://127.0.0.1:50051" tls-sni="grpc.internal"
+                }
+                proxy "http://127.0.0.1:8000"
+            }
+            "#,
+        )
+        .expect("Parsing failed");
+
+        let grpc = &connectors.upstreams[0];
+        assert_eq!(grpc.protocol, UpstreamProtocol::Grpc);
+        let UpstreamConfig::Service(peer) = &grpc.upstream else {
+            panic!("Expected a proxy connector");
+        };
+        // negotiated as h2 even though the proxy didn't set 'proto'
+        assert_eq!(peer.alpn, ALPN::H2);
+        assert_eq!(connectors.upstreams[1].protocol, UpstreamProtocol::Http);
+
+        let cases = [
+            (
+                r#"connectors { protocol "grpc"; proxy "http://127.0.0.1:50051"; }"#,
+                "set 'tls-sni' on the 'proxy'",
+            ),
+            (
+                r#"connectors { protocol "grpc"; proxy "https://127.0.0.1:50051" tls-sni="grpc.internal" proto="h1-only"; }"#,
+                "'proto=\"h1-only\"'",
+            ),
+            (
+                r#"connectors { protocol "grpc"; buffer-response-body #true; proxy "https://127.0.0.1:50051" tls-sni="grpc.internal"; }"#,
+                "can't be combined with 'buffer-response-body'",
+            ),
+            (
+                r#"connectors { protocol "grpc"; return code=200 response="OK"; }"#,
+                "needs a 'proxy' connector",
+            ),
+        ];
+        for (input, expected) in cases {
+            let err = parse_config(input).unwrap_err();
+            assert_err_contains!(err.to_string(), expected);
+        }
+
+        let result =
+            parse_config(r#"connectors { protocol "websocket"; proxy "http://127.0.0.1:8000"; }"#);
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(err_msg, "unknown protocol 'websocket'");
+    }
+
+    #[test]
+    fn test_upstream_http_version() {
+        let connectors = parse_config(
+            r#"
+            connectors {
+                section "/h1" as="prefix" {
+                    upstream-http-version "1.1"
+                    proxy "https://127.0.0.1:8443" tls-sni="h1.internal"
+                }
+                section "/h2" as="prefix" {
+                    upstream-http-version "2"
+                    proxy "https://127.0.0.1:8444" tls-sni="h2.internal"
+                }
+                section "/h2c" as="prefix" {
+                    upstream-http-version "2" h2c=#true
+                    proxy "http://127.0.0.1:8000"
+                }
+                section "/auto" as="prefix" {
+                    upstream-http-version "auto"
+                    section "/tls" {
+                        proxy "https://127.0.0.1:8445" tls-sni="auto.internal"
+                    }
+                    proxy "http://127.0.0.1:8001"
+                }
+            }
+            "#,
+        )
+        .expect("Parsing failed");
+
+        let versions = connectors
+            .upstreams
+            .iter()
+            .map(|u| {
+                let UpstreamConfig::Service(peer) = &u.upstream else {
+                    panic!("Expected a proxy connector");
+                };
+                (u.upstream_http_version, peer.alpn.clone())
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            versions,
+            vec![
+                (Some(UpstreamHttpVersion::Http1), ALPN::H1),
+                (Some(UpstreamHttpVersion::Http2 { h2c: false }), ALPN::H2),
+                (Some(UpstreamHttpVersion::Http2 { h2c: true }), ALPN::H2),
+                (Some(UpstreamHttpVersion::Auto), ALPN::H1),
+                (Some(UpstreamHttpVersion::Auto), ALPN::H2H1),
+            ]
+        );
+
+        let cases = [
+            (
+                r#"connectors { upstream-http-version "2"; proxy "http://127.0.0.1:8000"; }"#,
+                "allow it with 'h2c=#true'",
+            ),
+            (
+                r#"connectors { upstream-http-version "2"; proxy "https://127.0.0.1:8443" tls-sni="a.internal" proto="h1-only"; }"#,
+                "contradicts the 'proto=\"h1-only\"'",
+            ),
+            (
+                r#"connectors { upstream-http-version "1.1"; proxy "https://127.0.0.1:8443" tls-sni="a.internal" proto="h2-only"; }"#,
+                "contradicts the 'proto=\"h2-only\"'",
+            ),
+            (
+                r#"connectors { protocol "grpc"; upstream-http-version "1.1"; proxy "https://127.0.0.1:50051" tls-sni="grpc.internal"; }"#,
+                "but 'upstream-http-version' is \"1.1\"",
+            ),
+        ];
+        for (input, expected) in cases {
+            let err = parse_config(input).unwrap_err();
+            assert_err_contains!(err.to_string(), expected);
+        }
+
+        for (input, expected) in [
+            (
+                r#"connectors { upstream-http-version "3"; proxy "http://127.0.0.1:8000"; }"#,
+                "unknown upstream HTTP version '3'",
+            ),
+            (
+                r#"connectors { upstream-http-version "1.1" h2c=#true; proxy "http://127.0.0.1:8000"; }"#,
+                "'h2c' only applies to 'upstream-http-version \"2\"'",
+            ),
+        ] {
+            let err_msg = parse_config(input).unwrap_err().help().unwrap().to_string();
+            assert_err_contains!(err_msg, expected);
+        }
+    }
+
+    #[test]
+    fn test_response_status_map() {
+        let connectors = parse_config(
+            r#"
+            connectors {
+                response-status-map {
+                    "418" -> "400"
+                    "503" -> "502"
+                }
+                section "/legacy" as="prefix" {
+                    response-status-map { "503" -> 500; }
+                    proxy "http://127.0.0.1:8000"
+                }
+                proxy "http://127.0.0.1:8001"
+            }
+            "#,
+        )
+        .expect("Parsing failed");
+
+        let maps = connectors
+            .upstreams
+            .iter()
+            .map(|u| {
+                u.response_status_map
+                    .codes
+                    .clone()
+                    .into_iter()
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            maps,
+            vec![vec![(418, 400), (503, 502)], vec![(418, 400), (503, 500)]]
+        );
+
+        let cases = [
+            (
+                r#""418" -> "600""#,
+                "Invalid status code '600': expected a number between 100 and 599",
+            ),
+            (
+                r#""99" -> "400""#,
+                "Invalid status code '99': expected a number between 100 and 599",
+            ),
+            (
+                r#""418" to "400""#,
+                r#"Expected a mapping like '"418" -> "400"'"#,
+            ),
+            (
+                r#""418" -> "400"; "418" -> "404""#,
+                "Duplicate mapping for status 418",
+            ),
+        ];
+        for (entries, message) in cases {
+            let result = parse_config(&format!(
+                r#"connectors {{ response-status-map {{ {entries}; }}; proxy "http://127.0.0.1:8000"; }}"#
+            ));
+            let err_msg = result.unwrap_err().help().unwrap().to_string();
+            assert_err_contains!(err_msg, message);
+        }
+    }
+
+    const LOAD_BALANCE_SLOW_START: &str = r#"
+    connectors {
+        load-balance {
+            selection "RoundRobin"
+            slow-start "30s"
+        }
+        proxy {
+            server "127.0.0.1:8080"
+        }
+    }
+    "#;
+
+    #[test]
+    fn test_load_balance_slow_start() {
+        let connectors = parse_config(LOAD_BALANCE_SLOW_START).expect("Parsing failed");
+
+        let lb_options = connectors.upstreams[0].lb_options.clone().unwrap();
+        assert_eq!(
+            lb_options.slow_start,
+            Some(std::time::Duration::from_secs(30))
+        );
+    }
+
+    #[test]
+    fn test_error_slow_start_not_positive() {
+        let result = parse_config(&LOAD_BALANCE_SLOW_START.replace("30s", "0s"));
+
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(err_msg, "'slow-start' interval must be positive");
+    }
+
+    #[test]
+    fn test_load_balance_tcp_health_check() {
+        let config = LOAD_BALANCE_SLOW_START.replace(
+            r#"slow-start "30s""#,
+            r#"slow-start "30s"; health-check "TCP" interval="2s""#,
+        );
+        let connectors = parse_config(&config).expect("Parsing failed");
+
+        let lb_options = connectors.upstreams[0].lb_options.clone().unwrap();
+        assert_eq!(
+            lb_options.health_checks,
+            HealthCheckKind::Tcp {
+                interval: Duration::from_secs(2)
+            }
+        );
+
+        let config =
+            LOAD_BALANCE_SLOW_START.replace("selection", r#"health-check "TCP"; selection"#);
+        let connectors = parse_config(&config).expect("Parsing failed");
+
+        let lb_options = connectors.upstreams[0].lb_options.clone().unwrap();
+        assert_eq!(
+            lb_options.health_checks,
+            HealthCheckKind::Tcp {
+                interval: DEFAULT_HEALTH_CHECK_INTERVAL
+            }
+        );
+    }
+
+    #[test]
+    fn test_error_health_check_interval() {
+        let cases = [
+            (
+                r#"health-check "TCP" interval="0s""#,
+                "health-check 'interval' must be positive",
+            ),
+            (
+                r#"health-check "None" interval="5s""#,
+                "'interval' requires a health-check kind other than 'None'",
+            ),
+            (
+                r#"health-check "HTTP""#,
+                "Unknown health-check kind: 'HTTP'",
+            ),
+        ];
+        for (node, message) in cases {
+            let config = LOAD_BALANCE_SLOW_START.replace(r#"slow-start "30s""#, node);
+            let err_msg = parse_config(&config)
+                .unwrap_err()
+                .help()
+                .unwrap()
+                .to_string();
+            assert_err_contains!(err_msg, message);
+        }
+    }
+
+    #[test]
+    fn test_error_slow_start_bad_duration() {
+        let result = parse_config(&LOAD_BALANCE_SLOW_START.replace("30s", "soon"));
+
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(err_msg, "Invalid duration 'soon'");
+    }
+
+    const LOAD_BALANCE_OUTLIER_DETECTION: &str = r#"
+    connectors {
+        load-balance {
+            outlier-detection {
+                consecutive-errors 5
+                ejection-time "30s"
+            }
+        }
+        proxy {
+            server "127.0.0.1:8080"
+        }
+    }
+    "#;
+
+    #[test]
+    fn test_load_balance_outlier_detection() {
+        let connectors = parse_config(LOAD_BALANCE_OUTLIER_DETECTION).expect("Parsing failed");
+
+        let lb_options = connectors.upstreams[0].lb_options.clone().unwrap();
+        assert_eq!(
+            lb_options.outlier_detection,
+            Some(OutlierDetection {
+                consecutive_errors: 5,
+                ejection_time: std::time::Duration::from_secs(30),
+            })
+        );
+    }
+
+    #[test]
+    fn test_error_outlier_detection_consecutive_errors_range() {
+        let result = parse_config(
+            &LOAD_BALANCE_OUTLIER_DETECTION.replace("consecutive-errors 5", "consecutive-errors 0"),
+        );
+
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(
+            err_msg,
+            "Value of 'consecutive-errors' must be between 1 and 1000, found 0"
+        );
+    }
+
+    #[test]
+    fn test_error_outlier_detection_missing_ejection_time() {
+        let result =
+            parse_config(&LOAD_BALANCE_OUTLIER_DETECTION.replace(r#"ejection-time "30s""#, ""));
+
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(err_msg, "Missing required directive 'ejection-time'");
+    }
+
     const LOAD_BALANCE_ALL_SELECTION_TYPES: &str = r#"
     connectors {
         load-balance {
@@ -624,6 +2698,86 @@ mod tests {
         assert_eq!(lb_options.selection, SelectionKind::Random);
     }
 
+    #[test]
+    fn test_load_balance_weighted_random_selection() {
+        let connectors = parse_config(
+            r#"
+            connectors {
+                load-balance {
+                    selection "WeightedRandom"
+                }
+                proxy {
+                    server "127.0.0.1:8080" weight=3
+                    server "127.0.0.1:8081"
+                }
+            }
+            "#,
+        )
+        .expect("Parsing failed");
+
+        let lb_options = connectors.upstreams[0].lb_options.clone().unwrap();
+        assert_eq!(lb_options.selection, SelectionKind::WeightedRandom);
+
+        let result = parse_config(
+            r#"
+            connectors {
+                load-balance {
+                    selection "WeightedRandom"
+                }
+                proxy {
+                    server "127.0.0.1:8080" weight=0
+                }
+            }
+            "#,
+        );
+        assert_err_contains!(
+            result.unwrap_err().to_string(),
+            "requires at least one server with a positive 'weight'"
+        );
+    }
+
+    #[test]
+    fn test_pinned_servers() {
+        let connectors = parse_config(
+            r#"
+            connectors {
+                proxy {
+                    server "127.0.0.1:8080"
+                    server "127.0.0.1:8089" when="header X-Debug exists"
+                }
+            }
+            "#,
+        )
+        .expect("Parsing failed");
+
+        let UpstreamConfig::MultiServer(pool) = &connectors.upstreams[0].upstream else {
+            panic!("Expected a pool");
+        };
+        assert_eq!(pool.servers.len(), 1);
+        assert_eq!(pool.pinned.len(), 1);
+        assert_eq!(pool.pinned[0].address, "127.0.0.1:8089".parse().unwrap());
+
+        let cases = [
+            (
+                r#"server "127.0.0.1:8089" when="header X-Debug exists""#,
+                "needs at least one server without 'when'",
+            ),
+            (
+                r#"server "127.0.0.1:8080"; server "127.0.0.1:8089" weight=2 when="method == GET""#,
+                "'weight' has no effect on a server with 'when'",
+            ),
+            (
+                r#"server "127.0.0.1:8080"; server "127.0.0.1:8089" when="header""#,
+                "expected a header name",
+            ),
+        ];
+        for (servers, message) in cases {
+            let result = parse_config(&format!("connectors {{ proxy {{ {servers}; }} }}"));
+            let err_msg = result.unwrap_err().help().unwrap().to_string();
+            assert_err_contains!(err_msg, message);
+        }
+    }
+
     const LOAD_BALANCE_FNV_HASH: &str = r#"
     connectors {
         load-balance {
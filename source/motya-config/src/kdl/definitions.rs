@@ -6,7 +6,7 @@ use motya_macro::validate;
 use crate::{
     block_parser,
     common_types::{
-        definitions::{PluginDefinition, PluginSource},
+        definitions::{FilterErrorPolicy, PluginDefinition, PluginSource},
         definitions_table::DefinitionsTable,
         section_parser::SectionParser,
     },
@@ -169,10 +169,19 @@ impl DefinitionsSection {
                     (Some(_), Some(_)) => Err(ctx.error("Duplicate source: provide either 'path' or 'url', not both")),
                     (None, None) => Err(ctx.error("'load' must provide either 'path' or 'url'")),
                 }
+            },
+
+            on_filter_error: optional("on-filter-error") => |ctx| {
+                ctx.validate(&[Rule::NoChildren, Rule::ExactArgs(1), Rule::OnlyKeys(&[])])?;
+                ctx.first()?.parse_as::<FilterErrorPolicy>()
             }
         );
 
-        Ok(PluginDefinition { name, source })
+        Ok(PluginDefinition {
+            name,
+            source,
+            on_filter_error,
+        })
     }
 
     fn parse_namespace_recursive(
@@ -285,6 +294,38 @@ mod tests {
         })
     }
 
+    #[test]
+    fn test_plugin_on_filter_error() {
+        let doc: KdlDocument = r#"
+        definitions {
+            plugins {
+                plugin {
+                    name "strict"
+                    load path="./assets/filter.wasm"
+                }
+                plugin {
+                    name "lenient"
+                    load path="./assets/filter.wasm"
+                    on-filter-error "allow"
+                }
+            }
+        }
+        "#
+        .parse()
+        .unwrap();
+        let ctx = ParseContext::new(&doc, Current::Document(&doc), "test");
+        let mut block = BlockParser::new(ctx).unwrap();
+
+        let table = block
+            .required("definitions", |ctx| DefinitionsSection.parse_node(ctx))
+            .expect("Parsing failed");
+
+        let policy =
+            |name: &str| table.get_plugins()[&FQDN::from_str(name).unwrap()].on_filter_error;
+        assert_eq!(policy("strict"), None);
+        assert_eq!(policy("lenient"), Some(FilterErrorPolicy::Allow));
+    }
+
     const LOAD_BALANCE_BASIC: &str = r#"
     connectors {
         load-balance {
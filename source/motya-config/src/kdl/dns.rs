@@ -0,0 +1,96 @@
+use motya_macro::validate;
+
+use crate::{
+    block_parser,
+    common_types::{
+        dns::{DnsConfig, DEFAULT_DNS_TIMEOUT},
+        section_parser::SectionParser,
+    },
+    kdl::parser::{ctx::ParseContext, ensures::Rule},
+};
+
+/// Parses the top-level `dns { ... }` block.
+pub struct DnsSection;
+
+impl SectionParser<ParseContext<'_>, DnsConfig> for DnsSection {
+    #[validate(ensure_node_name = "dns")]
+    fn parse_node(&self, ctx: ParseContext<'_>) -> miette::Result<DnsConfig> {
+        ctx.validate(&[Rule::ReqChildren, Rule::NoArgs])?;
+
+        block_parser!(ctx.enter_block()?,
+            servers: required("servers") => |ctx| {
+                ctx.validate(&[Rule::NoChildren, Rule::OnlyKeys(&[])])?;
+
+                let servers = ctx.parse_socket_addr_list()?;
+                if servers.is_empty() {
+                    return Err(ctx.error("'servers' of 'dns' needs at least one 'IP:PORT' address"));
+                }
+                Ok(servers)
+            },
+            timeout: optional("timeout") => |ctx| {
+                ctx.validate(&[Rule::NoChildren, Rule::ExactArgs(1), Rule::OnlyKeys(&[])])?;
+
+                let timeout = ctx.first()?.as_duration()?;
+                if timeout.is_zero() {
+                    return Err(ctx.error("'timeout' of 'dns' must be positive"));
+                }
+                Ok(timeout)
+            }
+        );
+
+        Ok(DnsConfig {
+            servers,
+            timeout: timeout.unwrap_or(DEFAULT_DNS_TIMEOUT),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use kdl::KdlDocument;
+
+    use super::*;
+    use crate::{
+        assert_err_contains,
+        common_types::dns::DnsResolver,
+        kdl::parser::{block::BlockParser, ctx::Current},
+    };
+
+    fn parse_dns(input: &str) -> miette::Result<DnsConfig> {
+        let doc: KdlDocument = input.parse().unwrap();
+        let ctx = ParseContext::new(&doc, Current::Document(&doc), "test");
+        let mut block = BlockParser::new(ctx)?;
+
+        block.required("dns", |ctx| DnsSection.parse_node(ctx))
+    }
+
+    #[test]
+    fn test_parse_dns() {
+        let config = parse_dns(r#"dns { servers "1.1.1.1:53" "8.8.8.8:53"; timeout "2s"; }"#)
+            .expect("Should parse dns");
+
+        let servers = vec!["1.1.1.1:53".parse().unwrap(), "8.8.8.8:53".parse().unwrap()];
+        assert_eq!(config.servers, servers);
+        assert_eq!(config.timeout, Duration::from_secs(2));
+
+        let resolver = DnsResolver::new(&config);
+        assert_eq!(resolver.servers(), servers.as_slice());
+        assert_eq!(resolver.timeout(), Duration::from_secs(2));
+
+        let config = parse_dns(r#"dns { servers "[2606:4700:4700::1111]:53"; }"#).unwrap();
+        assert_eq!(config.timeout, DEFAULT_DNS_TIMEOUT);
+    }
+
+    #[test]
+    fn test_dns_invalid_server() {
+        let result = parse_dns(r#"dns { servers "1.1.1.1"; }"#);
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(err_msg, "'1.1.1.1' is not a valid socket address");
+
+        let result = parse_dns(r#"dns { servers; }"#);
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(err_msg, "'servers' of 'dns' needs at least one");
+    }
+}
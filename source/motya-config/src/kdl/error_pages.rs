@@ -0,0 +1,133 @@
+use std::{collections::BTreeMap, path::Path};
+
+use motya_macro::validate;
+
+use crate::{
+    common_types::{
+        error_pages::{ErrorPage, ErrorPages},
+        section_parser::SectionParser,
+    },
+    kdl::parser::{ctx::ParseContext, ensures::Rule},
+};
+
+pub struct ErrorPagesSection;
+
+impl SectionParser<ParseContext<'_>, ErrorPages> for ErrorPagesSection {
+    #[validate(ensure_node_name = "error-pages")]
+    fn parse_node(&self, ctx: ParseContext<'_>) -> miette::Result<ErrorPages> {
+        ctx.validate(&[Rule::ReqChildren, Rule::NoArgs])?;
+
+        let mut pages = BTreeMap::new();
+
+        for node_ctx in ctx.req_nodes()? {
+            let (status, page) = self.extract_page(&node_ctx)?;
+
+            if pages.insert(status, page).is_some() {
+                return Err(node_ctx.error(format!("Duplicate error page for status {status}")));
+            }
+        }
+
+        Ok(ErrorPages { pages })
+    }
+}
+
+impl ErrorPagesSection {
+    fn extract_page(&self, ctx: &ParseContext<'_>) -> miette::Result<(u16, ErrorPage)> {
+        ctx.validate(&[Rule::NoChildren, Rule::ExactArgs(1), Rule::OnlyKeys(&[])])?;
+
+        let name = ctx.name()?;
+        let status = name
+            .parse::<u16>()
+            .ok()
+            .filter(|code| (100..=599).contains(code))
+            .ok_or_else(|| {
+                ctx.error(format!(
+                    "Invalid status code '{name}': expected a number between 100 and 599"
+                ))
+            })?;
+
+        let path = ctx.first()?.as_str()?;
+        let body = std::fs::read(&path)
+            .map_err(|e| ctx.error(format!("Failed to read error page '{path}': {e}")))?;
+
+        Ok((
+            status,
+            ErrorPage {
+                content_type: content_type_for(Path::new(&path)),
+                body,
+            },
+        ))
+    }
+}
+
+fn content_type_for(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_ascii_lowercase);
+
+    match extension.as_deref() {
+        Some("html" | "htm") => "text/html; charset=utf-8",
+        Some("json") => "application/json",
+        Some("txt") => "text/plain; charset=utf-8",
+        Some("xml") => "application/xml",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use kdl::KdlDocument;
+    use tempfile::NamedTempFile;
+
+    use super::*;
+    use crate::{
+        assert_err_contains,
+        kdl::parser::{block::BlockParser, ctx::Current},
+    };
+
+    fn parse_error_pages(input: &str) -> miette::Result<ErrorPages> {
+        let doc: KdlDocument = input.parse().unwrap();
+        let ctx = ParseContext::new(&doc, Current::Document(&doc), "test");
+        let mut block = BlockParser::new(ctx)?;
+
+        block.required("error-pages", |ctx| ErrorPagesSection.parse_node(ctx))
+    }
+
+    #[test]
+    fn test_pages_are_loaded() {
+        let mut page = tempfile::Builder::new().suffix(".html").tempfile().unwrap();
+        write!(page, "<h1>Bad gateway</h1>").unwrap();
+
+        let input = format!(r#"error-pages {{ "502" "{}"; }}"#, page.path().display());
+        let pages = parse_error_pages(&input).expect("Should parse error pages");
+
+        let page = pages.get(502).expect("page for 502");
+        assert_eq!(page.content_type, "text/html; charset=utf-8");
+        assert_eq!(page.body, b"<h1>Bad gateway</h1>");
+        assert!(pages.get(503).is_none());
+    }
+
+    #[test]
+    fn test_invalid_status_code() {
+        let page = NamedTempFile::new().unwrap();
+        let input = format!(r#"error-pages {{ "700" "{}"; }}"#, page.path().display());
+
+        let err_msg = parse_error_pages(&input)
+            .unwrap_err()
+            .help()
+            .unwrap()
+            .to_string();
+        assert_err_contains!(err_msg, "Invalid status code '700'");
+    }
+
+    #[test]
+    fn test_missing_file() {
+        let result = parse_error_pages(r#"error-pages { "503" "./does-not-exist.html"; }"#);
+
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(err_msg, "Failed to read error page './does-not-exist.html'");
+    }
+}
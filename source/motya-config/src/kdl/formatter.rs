@@ -0,0 +1,285 @@
+use kdl::{KdlDocument, KdlEntry, KdlNode, KdlValue};
+use miette::IntoDiagnostic;
+
+use crate::kdl::parser::comments::{lex_trivia, Trivia};
+
+const INDENT: &str = "    ";
+
+/// Re-emits `doc` in the canonical style used by `motya fmt`.
+///
+/// Every node goes on its own line, indented by four spaces per level, with its
+/// positional arguments before its properties and every string quoted. Properties keep
+/// their order, since some directives such as `split` read them in source order.
+/// Comments and slashdashed nodes are kept, as is a single blank line wherever the
+/// source had blank lines between nodes.
+///
+/// Formatting its own output returns it unchanged.
+pub fn format_document(doc: &KdlDocument) -> String {
+    let mut out = String::new();
+    write_block(&mut out, doc, 0);
+    out
+}
+
+/// Parses and formats KDL source text, as `motya fmt` does with each file.
+pub fn format_source(source: &str) -> miette::Result<String> {
+    let doc: KdlDocument = source.parse().into_diagnostic()?;
+    Ok(format_document(&doc))
+}
+
+fn write_block(out: &mut String, block: &KdlDocument, depth: usize) {
+    let (leading, trailing) = block
+        .format()
+        .map(|format| (format.leading.as_str(), format.trailing.as_str()))
+        .unwrap_or_default();
+
+    let mut gap = 2;
+    for (i, node) in block.nodes().iter().enumerate() {
+        let mut trivia = String::new();
+        if i == 0 {
+            trivia.push_str(leading);
+        }
+        if let Some(format) = node.format() {
+            trivia.push_str(&format.leading);
+        }
+
+        if write_trivia(out, &trivia, depth, i > 0, gap) {
+            out.push('\n');
+        }
+        write_node(out, node, depth);
+
+        // a line break ending the previous node is not part of the next one's trivia
+        gap = match node.format() {
+            Some(format) if format.terminator.ends_with('\n') => 1,
+            _ => 2,
+        };
+    }
+
+    let trivia = if block.nodes().is_empty() {
+        format!("{leading}{trailing}")
+    } else {
+        trailing.to_string()
+    };
+    write_trivia(out, &trivia, depth, !block.nodes().is_empty(), gap);
+}
+
+/// Writes the comments in `trivia` on their own lines, keeping one blank line wherever
+/// the source had any. `gap` is how many line breaks make the first whitespace a blank line.
+///
+/// Returns whether a blank line separates the comments from what follows.
+fn write_trivia(out: &mut String, trivia: &str, depth: usize, started: bool, gap: usize) -> bool {
+    let mut started = started;
+    let mut gap = gap;
+    let mut blank = false;
+
+    for item in lex_trivia(trivia) {
+        let text = match item {
+            Trivia::Space(lines) => {
+                blank |= lines >= gap;
+                gap = 2;
+                continue;
+            }
+            Trivia::Line(text) | Trivia::Block(text) | Trivia::Slashdash(text) => text,
+        };
+
+        if blank && started {
+            out.push('\n');
+        }
+        push_indent(out, depth);
+        out.push_str(text);
+        out.push('\n');
+
+        started = true;
+        blank = false;
+        gap = 2;
+    }
+
+    blank && started
+}
+
+fn write_node(out: &mut String, node: &KdlNode, depth: usize) {
+    push_indent(out, depth);
+    if let Some(ty) = node.ty() {
+        out.push('(');
+        write_ident(out, ty.value());
+        out.push(')');
+    }
+    write_ident(out, node.name().value());
+
+    let (args, props): (Vec<&KdlEntry>, Vec<&KdlEntry>) = node
+        .entries()
+        .iter()
+        .partition(|entry| entry.name().is_none());
+    for entry in args.into_iter().chain(props) {
+        out.push(' ');
+        write_entry(out, entry);
+    }
+
+    if let Some(children) = node.children() {
+        let mut block = String::new();
+        write_block(&mut block, children, depth + 1);
+
+        if block.is_empty() {
+            out.push_str(" {}");
+        } else {
+            out.push_str(" {\n");
+            out.push_str(&block);
+            push_indent(out, depth);
+            out.push('}');
+        }
+    }
+
+    if let Some(comment) = node
+        .format()
+        .map(|format| format.terminator.trim())
+        .filter(|terminator| terminator.starts_with("//"))
+    {
+        out.push(' ');
+        out.push_str(comment);
+    }
+    out.push('\n');
+}
+
+fn write_entry(out: &mut String, entry: &KdlEntry) {
+    if let Some(ty) = entry.ty() {
+        out.push('(');
+        write_ident(out, ty.value());
+        out.push(')');
+    }
+    if let Some(name) = entry.name() {
+        write_ident(out, name.value());
+        out.push('=');
+    }
+
+    match entry.value() {
+        KdlValue::String(value) => write_string(out, value),
+        KdlValue::Integer(value) => out.push_str(&value.to_string()),
+        KdlValue::Float(value) if value.is_nan() => out.push_str("#nan"),
+        KdlValue::Float(value) if value.is_infinite() => {
+            out.push_str(if *value > 0.0 { "#inf" } else { "#-inf" })
+        }
+        // `Debug` keeps the fraction of whole numbers, `1.0` rather than `1`
+        KdlValue::Float(value) => out.push_str(&format!("{value:?}")),
+        KdlValue::Bool(true) => out.push_str("#true"),
+        KdlValue::Bool(false) => out.push_str("#false"),
+        KdlValue::Null => out.push_str("#null"),
+    }
+}
+
+/// Node names, property keys and type annotations stay bare when KDL allows it.
+fn write_ident(out: &mut String, ident: &str) {
+    if is_bare_ident(ident) {
+        out.push_str(ident);
+    } else {
+        write_string(out, ident);
+    }
+}
+
+fn is_bare_ident(ident: &str) -> bool {
+    const RESERVED: &[&str] = &["true", "false", "null", "inf", "-inf", "nan"];
+
+    let Some(first) = ident.chars().next() else {
+        return false;
+    };
+
+    let starts_like_number = first.is_ascii_digit()
+        || (matches!(first, '+' | '-' | '.')
+            && ident[1..]
+                .trim_start_matches('.')
+                .starts_with(|c: char| c.is_ascii_digit()));
+
+    !starts_like_number
+        && !RESERVED.contains(&ident)
+        && ident
+            .chars()
+            .all(|c| !c.is_whitespace() && !c.is_control() && !"\\/(){};[]\"#=".contains(c))
+}
+
+fn write_string(out: &mut String, value: &str) {
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => out.push_str(&format!("\\u{{{:x}}}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+fn push_indent(out: &mut String, depth: usize) {
+    for _ in 0..depth {
+        out.push_str(INDENT);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MESSY: &str = r#"
+// edge service
+services{
+  Api   {
+     listeners {   "127.0.0.1:8080" ;  "0.0.0.0:443" cert-path=cert.pem offer-h2=#true }
+
+
+     // upstreams
+     connectors { load-balance { selection RoundRobin } ; proxy    "http://127.0.0.1:3000" }
+     route path="/" {
+        split stable=90 canary=10
+     }
+  }
+}
+rule key="a"  "first" 2 weight=1.0
+"#;
+
+    const CANONICAL: &str = r#"// edge service
+services {
+    Api {
+        listeners {
+            "127.0.0.1:8080"
+            "0.0.0.0:443" cert-path="cert.pem" offer-h2=#true
+        }
+
+        // upstreams
+        connectors {
+            load-balance {
+                selection "RoundRobin"
+            }
+            proxy "http://127.0.0.1:3000"
+        }
+        route path="/" {
+            split stable=90 canary=10
+        }
+    }
+}
+rule "first" 2 key="a" weight=1.0
+"#;
+
+    #[test]
+    fn test_format_messy_config() {
+        let doc: KdlDocument = MESSY.parse().unwrap();
+
+        let formatted = format_document(&doc);
+        assert_eq!(formatted, CANONICAL);
+
+        let reparsed: KdlDocument = formatted.parse().unwrap();
+        assert_eq!(format_document(&reparsed), formatted);
+    }
+
+    #[test]
+    fn test_quotes_only_where_needed() {
+        let doc: KdlDocument = r#""0.0.0.0:80" "key with space"=#null (u8)n=-1 "true"=0.5"#
+            .parse()
+            .unwrap();
+
+        assert_eq!(
+            format_document(&doc),
+            "\"0.0.0.0:80\" \"key with space\"=#null (u8)n=-1 \"true\"=0.5\n"
+        );
+    }
+}
@@ -1,3 +1,4 @@
+use crate::common_types::bad::{Bad, DocumentErrors};
 use crate::common_types::section_parser::SectionParser;
 use crate::config_source::ConfigSource;
 use crate::kdl::includes::IncludesSection;
@@ -5,15 +6,34 @@ use crate::kdl::parser::block::BlockParser;
 use crate::kdl::parser::ctx::{Current, ParseContext};
 use async_recursion::async_recursion;
 use kdl::KdlDocument;
-use miette::{miette, Context, IntoDiagnostic, Result};
+use miette::{miette, Context, IntoDiagnostic, NamedSource, Result};
 use std::collections::HashSet;
 use std::future::Future;
+use std::io::Read;
 use std::marker::PhantomData;
 use std::path::{Path, PathBuf};
 
+/// The entry path meaning the configuration is piped to stdin, as in `--config -`.
+pub const STDIN_ENTRY: &str = "-";
+
+/// Source name of the document read from stdin, shown in diagnostics.
+pub const STDIN_SOURCE_NAME: &str = "<stdin>";
+
 pub trait AsyncFs: Send + Sync + Clone + Default {
     fn canonicalize(path: &Path) -> impl Future<Output = Result<PathBuf>> + Send;
     fn read_to_string(path: &Path) -> impl Future<Output = Result<String>> + Send;
+    /// Paths of the entries of the directory at `path`, in no particular order.
+    fn read_dir(path: &Path) -> impl Future<Output = Result<Vec<PathBuf>>> + Send;
+}
+
+/// What collecting a glob entry path, like `conf.d/*.kdl`, does when no file matches it.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum GlobNoMatch {
+    /// Fails, as a typo in the pattern would otherwise start motya without configuration.
+    #[default]
+    Error,
+    /// Collects no documents.
+    Empty,
 }
 
 #[derive(Default, Clone)]
@@ -21,6 +41,12 @@ pub struct FileCollector<F: AsyncFs> {
     fs: PhantomData<F>,
     documents: Vec<(KdlDocument, String)>,
     visited_paths: HashSet<PathBuf>,
+    report_all: bool,
+    failed: Option<DocumentErrors>,
+    max_size: Option<usize>,
+    /// Bytes of every file read so far.
+    total_size: usize,
+    glob_no_match: GlobNoMatch,
 }
 
 impl<F: AsyncFs> ConfigSource for FileCollector<F> {
@@ -30,14 +56,54 @@ impl<F: AsyncFs> ConfigSource for FileCollector<F> {
 }
 
 impl<Fs: AsyncFs> FileCollector<Fs> {
+    /// Keeps loading the other files when one fails to parse, then reports the syntax errors of
+    /// every file at once instead of stopping at the first.
+    ///
+    /// The includes of a file that failed to parse are not followed.
+    pub fn report_all_errors(mut self) -> Self {
+        self.report_all = true;
+        self
+    }
+
+    /// Refuses to parse a configuration larger than `max_size` bytes, counting the files it
+    /// includes. No limit when `None`.
+    pub fn max_config_size(mut self, max_size: Option<usize>) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Sets what collecting a glob entry path does when no file matches it, an error by
+    /// default.
+    pub fn on_glob_no_match(mut self, policy: GlobNoMatch) -> Self {
+        self.glob_no_match = policy;
+        self
+    }
+
+    /// Parses the file at `entry_path` and the files it includes.
+    ///
+    /// An entry path whose file name is a glob, like `/etc/motya/conf.d/*.kdl`, collects
+    /// every matching file of the directory in the order of their names, so files
+    /// numbered like `10-base.kdl` and `20-api.kdl` are always merged the same way.
     pub async fn collect(mut self, entry_path: PathBuf) -> Result<Vec<(KdlDocument, String)>> {
-        let root_path = Fs::canonicalize(&entry_path)
-            .await
-            .context("Failed to resolve entry point")?;
+        if entry_path.as_os_str() == STDIN_ENTRY {
+            return read_piped(std::io::stdin().lock(), self.max_size);
+        }
 
-        self.load_recursive(root_path).await?;
+        let root_paths = match glob_pattern(&entry_path) {
+            Some(pattern) => self.expand_glob(&entry_path, pattern).await?,
+            None => vec![Fs::canonicalize(&entry_path)
+                .await
+                .context("Failed to resolve entry point")?],
+        };
 
-        Ok(self.documents)
+        for root_path in root_paths {
+            self.load_recursive(root_path).await?;
+        }
+
+        match self.failed {
+            Some(errors) => Err(errors.into()),
+            None => Ok(self.documents),
+        }
     }
 
     #[async_recursion]
@@ -50,16 +116,36 @@ impl<Fs: AsyncFs> FileCollector<Fs> {
         let content = Fs::read_to_string(&path)
             .await
             .wrap_err_with(|| format!("Failed to read file: {:?}", path))?;
+        self.check_size(&path, content.len())?;
 
-        let doc: KdlDocument = content
-            .parse()
-            .into_diagnostic()
-            .wrap_err_with(|| format!("Failed to parse KDL: {:?}", path))?;
+        if path.file_name().is_none() {
+            return Err(miette!("It's not a file: {:?}", path));
+        }
+        // the full path, so values such as `${FILE:...}` can be resolved next to the file
+        let name = path.to_string_lossy();
 
-        let name = path
-            .file_name()
-            .map(|s| s.to_string_lossy())
-            .ok_or_else(|| miette!("It's not a file: {:?}", path))?;
+        let doc: KdlDocument = match content.parse::<KdlDocument>() {
+            Ok(doc) => doc,
+            Err(err) if self.report_all => {
+                let failed = self.failed.get_or_insert_with(|| DocumentErrors {
+                    files: vec![],
+                    errors: vec![],
+                });
+                failed.files.push(name.to_string());
+                let errors = err.diagnostics.into_iter().map(|diag| Bad {
+                    error: diag.message.unwrap_or_else(|| "Invalid KDL".to_string()),
+                    src: NamedSource::new(name.as_ref(), content.clone()),
+                    err_span: diag.span,
+                });
+                failed.errors.extend(errors);
+                return Ok(());
+            }
+            Err(err) => {
+                return Err(err)
+                    .into_diagnostic()
+                    .wrap_err_with(|| format!("Failed to parse KDL: {:?}", path))
+            }
+        };
 
         let mut block = BlockParser::new(ParseContext::new(&doc, Current::Document(&doc), &name))?;
 
@@ -76,4 +162,343 @@ impl<Fs: AsyncFs> FileCollector<Fs> {
         self.documents.push((doc, name.to_string()));
         Ok(())
     }
+
+    /// The files of the directory of `entry_path` whose name matches `pattern`, sorted.
+    async fn expand_glob(&self, entry_path: &Path, pattern: &str) -> Result<Vec<PathBuf>> {
+        let dir = match entry_path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let dir = Fs::canonicalize(dir)
+            .await
+            .wrap_err_with(|| format!("Failed to resolve the directory of {entry_path:?}"))?;
+
+        let mut matched = Fs::read_dir(&dir)
+            .await
+            .wrap_err_with(|| format!("Failed to list {dir:?}"))?
+            .into_iter()
+            .filter(|path| {
+                path.file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| glob_matches(pattern, name))
+            })
+            .collect::<Vec<_>>();
+        matched.sort();
+
+        if matched.is_empty() && self.glob_no_match == GlobNoMatch::Error {
+            return Err(miette!("No configuration file matches {entry_path:?}"));
+        }
+        Ok(matched)
+    }
+
+    /// Counts the `len` bytes of the file at `path`, failing once over `max_size`.
+    fn check_size(&mut self, path: &Path, len: usize) -> Result<()> {
+        self.total_size += len;
+        let Some(max) = self.max_size else {
+            return Ok(());
+        };
+
+        if len > max {
+            return Err(miette!(
+                "Configuration file {path:?} is {len} bytes, over the max-config-size of {max} bytes"
+            ));
+        }
+        if self.total_size > max {
+            return Err(miette!(
+                "Configuration file {path:?} of {len} bytes brings the configuration and its \
+                 includes to {} bytes, over the max-config-size of {max} bytes",
+                self.total_size
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// The file name of `entry_path`, when it's a glob pattern rather than a file.
+fn glob_pattern(entry_path: &Path) -> Option<&str> {
+    entry_path
+        .file_name()?
+        .to_str()
+        .filter(|name| name.contains(['*', '?']))
+}
+
+/// The path to watch for changes of the configuration at `entry_path`: the directory
+/// of a glob, as files matching it may be added later.
+pub fn watched_path(entry_path: &Path) -> &Path {
+    match (glob_pattern(entry_path), entry_path.parent()) {
+        (Some(_), Some(dir)) if !dir.as_os_str().is_empty() => dir,
+        (Some(_), _) => Path::new("."),
+        (None, _) => entry_path,
+    }
+}
+
+/// Whether the file `name` matches `pattern`, where `*` stands for any run of characters
+/// and `?` for a single one. Like in a shell, wildcards don't match a leading `.`, so
+/// hidden files and editor backups are left out.
+fn glob_matches(pattern: &str, name: &str) -> bool {
+    if name.starts_with('.') && !pattern.starts_with('.') {
+        return false;
+    }
+
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let name = name.chars().collect::<Vec<_>>();
+    // the pattern and name positions to go back to when a `*` has to take one more character
+    let mut backtrack = None;
+    let (mut p, mut n) = (0, 0);
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some('?') => (p, n) = (p + 1, n + 1),
+            Some(c) if *c == name[n] => (p, n) = (p + 1, n + 1),
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    backtrack = Some((star, matched + 1));
+                    (p, n) = (star + 1, matched + 1);
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Parses a configuration piped in as a single document, without following its `includes`,
+/// as there is no directory to resolve them from.
+///
+/// With `max_size`, no more than one byte past it is read before the input is refused.
+pub fn read_piped(
+    reader: impl Read,
+    max_size: Option<usize>,
+) -> Result<Vec<(KdlDocument, String)>> {
+    let mut content = vec![];
+    let limit = max_size.map_or(u64::MAX, |max| max as u64 + 1);
+    reader
+        .take(limit)
+        .read_to_end(&mut content)
+        .into_diagnostic()
+        .wrap_err("Failed to read the configuration from stdin")?;
+    if let Some(max) = max_size.filter(|max| content.len() > *max) {
+        return Err(miette!(
+            "The configuration piped to stdin is over the max-config-size of {max} bytes"
+        ));
+    }
+    let content = String::from_utf8(content)
+        .into_diagnostic()
+        .wrap_err("Failed to read the configuration from stdin")?;
+
+    if content.trim().is_empty() {
+        return Err(miette!(
+            "No configuration was piped to stdin, expected a KDL document before the end of input"
+        ));
+    }
+
+    let doc: KdlDocument = content.parse().map_err(|err: kdl::KdlError| {
+        let errors = err
+            .diagnostics
+            .into_iter()
+            .map(|diag| Bad {
+                error: diag.message.unwrap_or_else(|| "Invalid KDL".to_string()),
+                src: NamedSource::new(STDIN_SOURCE_NAME, content.clone()),
+                err_span: diag.span,
+            })
+            .collect();
+        DocumentErrors {
+            files: vec![STDIN_SOURCE_NAME.to_string()],
+            errors,
+        }
+    })?;
+
+    if let Some(includes) = doc.get("includes") {
+        let ctx = ParseContext::new(&doc, Current::Document(&doc), STDIN_SOURCE_NAME);
+        return Err(ctx.error_with_span(
+            "'includes' can't be used in a configuration read from stdin, there is no directory to resolve them from",
+            includes.span(),
+        ));
+    }
+
+    Ok(vec![(doc, STDIN_SOURCE_NAME.to_string())])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default, Clone)]
+    struct TestFs;
+
+    impl AsyncFs for TestFs {
+        async fn canonicalize(path: &Path) -> Result<PathBuf> {
+            tokio::fs::canonicalize(path).await.into_diagnostic()
+        }
+
+        async fn read_to_string(path: &Path) -> Result<String> {
+            tokio::fs::read_to_string(path).await.into_diagnostic()
+        }
+
+        async fn read_dir(path: &Path) -> Result<Vec<PathBuf>> {
+            let mut entries = tokio::fs::read_dir(path).await.into_diagnostic()?;
+            let mut paths = vec![];
+            while let Some(entry) = entries.next_entry().await.into_diagnostic()? {
+                paths.push(entry.path());
+            }
+            Ok(paths)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_glob_entry_path() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("20-api.kdl"), "services { }\n").unwrap();
+        std::fs::write(dir.path().join("10-base.kdl"), "system { }\n").unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "not configuration\n").unwrap();
+        std::fs::write(dir.path().join(".30-draft.kdl"), "system {\n").unwrap();
+
+        let documents = FileCollector::<TestFs>::default()
+            .collect(dir.path().join("*.kdl"))
+            .await
+            .expect("Should collect the matching files");
+
+        let names = documents
+            .iter()
+            .map(|(_, name)| Path::new(name).file_name().unwrap().to_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["10-base.kdl", "20-api.kdl"]);
+        assert!(documents[0].0.get("system").is_some());
+
+        let err = FileCollector::<TestFs>::default()
+            .collect(dir.path().join("*.conf"))
+            .await
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("No configuration file matches"),
+            "{err}"
+        );
+
+        let documents = FileCollector::<TestFs>::default()
+            .on_glob_no_match(GlobNoMatch::Empty)
+            .collect(dir.path().join("*.conf"))
+            .await
+            .expect("no match is not an error with GlobNoMatch::Empty");
+        assert!(documents.is_empty());
+    }
+
+    #[test]
+    fn test_glob_matches() {
+        assert!(glob_matches("*.kdl", "main.kdl"));
+        assert!(!glob_matches("*.kdl", ".draft.kdl"));
+        assert!(glob_matches("??-*.kdl", "10-base.kdl"));
+        assert!(glob_matches("*base*", "10-base.kdl"));
+        assert!(!glob_matches("*.kdl", "main.kdl.bak"));
+        assert!(!glob_matches("?.kdl", "10.kdl"));
+        assert!(glob_matches(".*", ".hidden"));
+
+        assert_eq!(
+            watched_path(Path::new("/etc/motya/conf.d/*.kdl")),
+            Path::new("/etc/motya/conf.d")
+        );
+        assert_eq!(
+            watched_path(Path::new("/etc/motya/main.kdl")),
+            Path::new("/etc/motya/main.kdl")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_errors_of_every_file_are_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("main.kdl"),
+            "includes {\n    \"a.kdl\"\n    \"b.kdl\"\n}\n",
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("a.kdl"), "system {\n").unwrap();
+        std::fs::write(dir.path().join("b.kdl"), "services { \"unclosed }\n").unwrap();
+
+        let err = FileCollector::<TestFs>::default()
+            .report_all_errors()
+            .collect(dir.path().join("main.kdl"))
+            .await
+            .unwrap_err();
+
+        let errors = err
+            .downcast_ref::<DocumentErrors>()
+            .expect("collected errors");
+        assert_eq!(errors.files.len(), 2);
+        assert!(errors.files[0].ends_with("a.kdl"));
+        assert!(errors.files[1].ends_with("b.kdl"));
+        assert!(!errors.errors.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_max_config_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let main = "includes {\n    \"big.kdl\"\n}\n";
+        std::fs::write(dir.path().join("main.kdl"), main).unwrap();
+        let big = format!("system {{\n{}}}\n", "    // padding\n".repeat(100));
+        std::fs::write(dir.path().join("big.kdl"), &big).unwrap();
+        let total = main.len() + big.len();
+
+        let collect = |max_size| {
+            FileCollector::<TestFs>::default()
+                .max_config_size(Some(max_size))
+                .collect(dir.path().join("main.kdl"))
+        };
+
+        let documents = collect(total)
+            .await
+            .expect("a configuration at the limit parses");
+        assert_eq!(documents.len(), 2);
+
+        let err = collect(total - 1).await.unwrap_err().to_string();
+        assert!(err.contains("big.kdl"), "{err}");
+        assert!(
+            err.contains(&format!("of {} bytes brings", big.len())),
+            "{err}"
+        );
+
+        let err = collect(64).await.unwrap_err().to_string();
+        assert!(
+            err.contains(&format!(
+                "is {} bytes, over the max-config-size of 64",
+                big.len()
+            )),
+            "{err}"
+        );
+
+        let err = read_piped(big.as_bytes(), Some(64))
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("over the max-config-size of 64 bytes"),
+            "{err}"
+        );
+        assert!(read_piped(big.as_bytes(), Some(big.len())).is_ok());
+    }
+
+    #[test]
+    fn test_read_piped() {
+        let documents = read_piped("system { threads-per-service 2 }\n".as_bytes(), None).unwrap();
+        assert_eq!(documents.len(), 1);
+        assert_eq!(documents[0].1, STDIN_SOURCE_NAME);
+        assert!(documents[0].0.get("system").is_some());
+
+        let err = read_piped("".as_bytes(), None).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("No configuration was piped to stdin"),
+            "{err}"
+        );
+
+        let err = read_piped("system {".as_bytes(), None).unwrap_err();
+        let errors = err.downcast_ref::<DocumentErrors>().expect("syntax errors");
+        assert_eq!(errors.files, vec![STDIN_SOURCE_NAME.to_string()]);
+
+        let err = read_piped("includes { \"a.kdl\" }".as_bytes(), None).unwrap_err();
+        let msg = err.help().unwrap().to_string();
+        assert!(msg.contains("'includes' can't be used"), "{msg}");
+    }
 }
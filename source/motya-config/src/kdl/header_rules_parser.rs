@@ -0,0 +1,175 @@
+use http::{HeaderName, HeaderValue};
+
+use crate::{
+    common_types::headers::{HeaderRule, HeaderTemplate},
+    kdl::parser::{ctx::ParseContext, ensures::Rule},
+};
+
+/// Parses the body of a `response-headers { ... }` or `request-headers { ... }` block.
+///
+/// Unlike most blocks, the order of the directives is significant, so the
+/// children are walked directly instead of through a `BlockParser`.
+pub struct HeaderRulesParser;
+
+impl HeaderRulesParser {
+    pub fn parse_response(&self, ctx: ParseContext<'_>) -> miette::Result<Vec<HeaderRule>> {
+        self.parse(ctx, |ctx, value| {
+            HeaderValue::from_str(&value)
+                .map_err(|e| ctx.error(format!("Invalid header value '{value}': {e}")))?;
+            Ok(value)
+        })
+    }
+
+    /// Request rules may reference request variables such as `$client_ip`,
+    /// which are checked here and substituted when a request is forwarded.
+    pub fn parse_request(
+        &self,
+        ctx: ParseContext<'_>,
+    ) -> miette::Result<Vec<HeaderRule<HeaderTemplate>>> {
+        self.parse(ctx, |ctx, value| {
+            let template = value.parse::<HeaderTemplate>().map_err(|e| ctx.error(e))?;
+            HeaderValue::from_str(&template.literal_text())
+                .map_err(|e| ctx.error(format!("Invalid header value '{value}': {e}")))?;
+            Ok(template)
+        })
+    }
+
+    fn parse<V>(
+        &self,
+        ctx: ParseContext<'_>,
+        parse_value: impl Fn(&ParseContext<'_>, String) -> miette::Result<V>,
+    ) -> miette::Result<Vec<HeaderRule<V>>> {
+        ctx.nodes()?
+            .into_iter()
+            .map(|rule_ctx| self.parse_rule(rule_ctx, &parse_value))
+            .collect()
+    }
+
+    fn parse_rule<V>(
+        &self,
+        ctx: ParseContext<'_>,
+        parse_value: &impl Fn(&ParseContext<'_>, String) -> miette::Result<V>,
+    ) -> miette::Result<HeaderRule<V>> {
+        match ctx.name()? {
+            "set" | "add" => {
+                ctx.validate(&[Rule::NoChildren, Rule::ExactArgs(2), Rule::OnlyKeys(&[])])?;
+
+                let name = ctx.arg(0)?.parse_as::<HeaderName>()?;
+                let value = parse_value(&ctx, ctx.arg(1)?.as_str()?)?;
+
+                if ctx.name()? == "set" {
+                    Ok(HeaderRule::Set { name, value })
+                } else {
+                    Ok(HeaderRule::Add { name, value })
+                }
+            }
+            "remove" => {
+                ctx.validate(&[Rule::NoChildren, Rule::ExactArgs(1), Rule::OnlyKeys(&[])])?;
+
+                let name = ctx.arg(0)?.parse_as::<HeaderName>()?;
+
+                Ok(HeaderRule::Remove { name })
+            }
+            other => Err(ctx.error(format!(
+                "Unknown header operation '{other}'. Use 'set', 'add' or 'remove'"
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{assert_err_contains, kdl::parser::ctx::Current};
+    use kdl::KdlDocument;
+
+    fn parse_rules(input: &str) -> miette::Result<Vec<HeaderRule>> {
+        let doc: KdlDocument = input.parse().unwrap();
+        let ctx = ParseContext::new(&doc, Current::Document(&doc), "test");
+        HeaderRulesParser.parse_response(ctx)
+    }
+
+    #[test]
+    fn test_rules_keep_order() {
+        let rules = parse_rules(
+            r#"
+            set "X-Frame-Options" "DENY"
+            remove "Server"
+            add "Set-Cookie" "a=b"
+        "#,
+        )
+        .expect("Should parse rules");
+
+        assert_eq!(
+            rules,
+            vec![
+                HeaderRule::Set {
+                    name: HeaderName::from_static("x-frame-options"),
+                    value: "DENY".to_string(),
+                },
+                HeaderRule::Remove {
+                    name: HeaderName::from_static("server"),
+                },
+                HeaderRule::Add {
+                    name: HeaderName::from_static("set-cookie"),
+                    value: "a=b".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_header_name() {
+        let result = parse_rules(r#"set "X Frame" "DENY""#);
+
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(err_msg, "Invalid HeaderName 'X Frame'");
+    }
+
+    #[test]
+    fn test_request_rules_with_variables() {
+        let doc: KdlDocument = r#"
+            set "X-Real-IP" "$client_ip"
+            remove "X-Internal"
+        "#
+        .parse()
+        .unwrap();
+        let ctx = ParseContext::new(&doc, Current::Document(&doc), "test");
+
+        let rules = HeaderRulesParser
+            .parse_request(ctx)
+            .expect("Should parse rules");
+
+        assert_eq!(
+            rules,
+            vec![
+                HeaderRule::Set {
+                    name: HeaderName::from_static("x-real-ip"),
+                    value: "$client_ip".parse().unwrap(),
+                },
+                HeaderRule::Remove {
+                    name: HeaderName::from_static("x-internal"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_request_unknown_variable() {
+        let doc: KdlDocument = r#"set "X-User" "$remote_user""#.parse().unwrap();
+        let ctx = ParseContext::new(&doc, Current::Document(&doc), "test");
+
+        let result = HeaderRulesParser.parse_request(ctx);
+
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(err_msg, "unknown variable '$remote_user'");
+    }
+
+    #[test]
+    fn test_unknown_operation() {
+        let result = parse_rules(r#"replace "Server" "motya""#);
+
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(err_msg, "Unknown header operation 'replace'");
+    }
+}
@@ -1,12 +1,18 @@
+use std::collections::HashMap;
+
 use crate::{
-    common_types::definitions::{HashAlgorithm, KeyTemplateConfig, Transform},
-    kdl::parser::{block::BlockParser, ctx::ParseContext},
+    common_types::definitions::{
+        HashAlgorithm, KeyTemplateConfig, OrderConstraint, ParamKind, Transform, TransformSchema,
+    },
+    kdl::parser::{block::BlockParser, ctx::ParseContext, ensures::Rule},
 };
 
 pub struct KeyProfileParser;
 
 impl KeyProfileParser {
     pub fn parse(&self, ctx: ParseContext<'_>) -> miette::Result<KeyTemplateConfig> {
+        ctx.req_exactly_one("key")?;
+
         let mut block = BlockParser::new(ctx)?;
 
         let (source, fallback) = block.required("key", |ctx| {
@@ -36,18 +42,14 @@ impl KeyProfileParser {
 
         let transforms = block
             .optional("transforms-order", |c| {
-                let mut steps = Vec::new();
-                for step_ctx in c.nodes()? {
-                    let name = step_ctx.name().unwrap_or("").to_string();
-                    let params = step_ctx
-                        .args_map(..)?
-                        .into_iter()
-                        .map(|(k, v)| (k.to_string(), v.to_string()))
-                        .collect();
-
-                    steps.push(Transform { name, params });
-                }
-                Ok(steps)
+                let steps = c.nodes()?;
+                let transforms = steps
+                    .iter()
+                    .map(|step_ctx| self.parse_transform(step_ctx.clone()))
+                    .collect::<miette::Result<Vec<_>>>()?;
+
+                self.check_order(&steps, &transforms);
+                Ok(transforms)
             })?
             .unwrap_or_default();
 
@@ -60,14 +62,88 @@ impl KeyProfileParser {
             transforms,
         })
     }
+
+    /// Warns about the steps breaking the [`OrderConstraint`]s of their transform.
+    fn check_order(&self, steps: &[ParseContext<'_>], transforms: &[Transform]) {
+        for (i, (step_ctx, transform)) in steps.iter().zip(transforms).enumerate() {
+            let Some(schema) = TransformSchema::find(&transform.name) else {
+                continue;
+            };
+            let following = &transforms[i + 1..];
+
+            for constraint in schema.order {
+                match constraint {
+                    OrderConstraint::Last => {
+                        if let Some(next) = following.first() {
+                            step_ctx.warn(format!(
+                                "Transform '{}' should be the last one, '{}' after it changes its result",
+                                transform.name, next.name
+                            ));
+                        }
+                    }
+                    OrderConstraint::After(before) => {
+                        if following.iter().any(|t| t.name == *before) {
+                            step_ctx.warn(format!(
+                                "Transform '{}' should come after '{before}'",
+                                transform.name
+                            ));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn parse_transform(&self, ctx: ParseContext<'_>) -> miette::Result<Transform> {
+        let name = ctx.name()?;
+
+        let schema = TransformSchema::find_or_describe(name).map_err(|e| ctx.error(e))?;
+
+        ctx.validate(&[
+            Rule::NoChildren,
+            Rule::NoPositionalArgs,
+            Rule::NoDuplicateKeys,
+        ])?;
+
+        for entry in ctx.entries()? {
+            if let Some(key) = entry.name() {
+                schema.param(key).map_err(|e| entry.value().error(e))?;
+            }
+        }
+
+        let mut params = HashMap::new();
+
+        for spec in schema.params {
+            let Some(value) = ctx.opt_prop(spec.name)? else {
+                if spec.required {
+                    return Err(ctx.error(spec.missing(name)));
+                }
+                continue;
+            };
+
+            let raw = match spec.kind {
+                ParamKind::NonEmpty | ParamKind::Secret => value.as_str_interpolated()?,
+                _ => value.as_string_lossy()?,
+            };
+            spec.check(name, &raw).map_err(|e| value.error(e))?;
+
+            params.insert(spec.name.to_string(), raw);
+        }
+
+        Ok(Transform {
+            name: name.to_string(),
+            params,
+        })
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::kdl::parser::ctx::Current;
+    use crate::kdl::parser::ctx::{Current, Warnings};
 
     use super::*;
     use kdl::KdlDocument;
+    use miette::Diagnostic;
 
     #[test]
     fn test_parse_key_profile() {
@@ -130,4 +206,199 @@ mod tests {
         let msg_err = result.unwrap_err().help().unwrap().to_string();
         crate::assert_err_contains!(msg_err, "Missing required directive 'key'");
     }
+
+    #[test]
+    fn test_duplicate_key_error() {
+        let kdl_input = r#"
+            key "${uri_path}"
+            key "${client_ip}"
+        "#;
+        let doc: KdlDocument = kdl_input.parse().unwrap();
+
+        let ctx = ParseContext::new(&doc, Current::Document(&doc), "test");
+        let result = KeyProfileParser.parse(ctx);
+
+        let msg_err = result.unwrap_err().help().unwrap().to_string();
+        crate::assert_err_contains!(msg_err, "Directive 'key' must appear exactly once, found 2");
+    }
+
+    fn parse_transforms(transforms: &str) -> miette::Result<KeyTemplateConfig> {
+        let kdl_input = format!(
+            r#"
+            key "${{uri_path}}"
+            transforms-order {{
+                {transforms}
+            }}
+        "#
+        );
+        let doc: KdlDocument = kdl_input.parse().unwrap();
+
+        let ctx = ParseContext::new(&doc, Current::Document(&doc), "test");
+        KeyProfileParser.parse(ctx)
+    }
+
+    #[test]
+    fn test_transform_order_warnings() {
+        let kdl_input = r#"
+            key "${uri_path}"
+            transforms-order {
+                hmac key="secret"
+                lowercase
+            }
+        "#;
+        let doc: KdlDocument = kdl_input.parse().unwrap();
+        let warnings = Warnings::default();
+
+        let ctx = ParseContext::new(&doc, Current::Document(&doc), "test").with_warnings(&warnings);
+        let template = KeyProfileParser
+            .parse(ctx)
+            .expect("Should parse despite the order");
+        assert_eq!(template.transforms.len(), 2);
+
+        let warnings = warnings.into_inner();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(
+            warnings[0].help().unwrap().to_string(),
+            "Transform 'hmac' should be the last one, 'lowercase' after it changes its result"
+        );
+
+        let warnings = Warnings::default();
+        let doc: KdlDocument = r#"
+            key "${uri_path}"
+            transforms-order {
+                lowercase
+                truncate length=64
+                hmac key="secret"
+            }
+        "#
+        .parse()
+        .unwrap();
+        let ctx = ParseContext::new(&doc, Current::Document(&doc), "test").with_warnings(&warnings);
+        KeyProfileParser.parse(ctx).unwrap();
+        assert!(warnings.into_inner().is_empty());
+    }
+
+    #[test]
+    fn test_truncate_invalid_length() {
+        let result = parse_transforms(r#"truncate length="abc""#);
+
+        let msg_err = result.unwrap_err().help().unwrap().to_string();
+        crate::assert_err_contains!(
+            msg_err,
+            "Parameter 'length' of transform 'truncate' must be a positive integer, found 'abc'"
+        );
+
+        let result = parse_transforms("truncate length=0");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_truncate_missing_length() {
+        let result = parse_transforms("truncate");
+
+        let msg_err = result.unwrap_err().help().unwrap().to_string();
+        crate::assert_err_contains!(
+            msg_err,
+            "Transform 'truncate' requires the 'length' parameter"
+        );
+    }
+
+    #[test]
+    fn test_transform_missing_required_param() {
+        let result = parse_transforms(r#"hmac algorithm="sha512""#);
+
+        let msg_err = result.unwrap_err().help().unwrap().to_string();
+        crate::assert_err_contains!(msg_err, "Transform 'hmac' requires the 'key' parameter");
+    }
+
+    #[test]
+    fn test_transform_unknown_param() {
+        let input = r#"
+            key "${uri_path}"
+            transforms-order {
+                truncate length=64 size=8
+            }
+        "#;
+        let doc: KdlDocument = input.parse().unwrap();
+
+        let ctx = ParseContext::new(&doc, Current::Document(&doc), "test");
+        let err = KeyProfileParser.parse(ctx).unwrap_err();
+
+        crate::assert_err_contains!(
+            err.help().unwrap().to_string(),
+            "Unknown parameter 'size' of transform 'truncate'. Supported parameters are: [\"length\"]"
+        );
+        // anchored at the parameter rather than the whole step
+        let span = err.labels().unwrap().next().unwrap();
+        let labeled = &input[span.offset()..span.offset() + span.len()];
+        assert_eq!(labeled.trim(), "size=8");
+
+        let result = parse_transforms("lowercase mode=\"ascii\"");
+        let msg_err = result.unwrap_err().help().unwrap().to_string();
+        crate::assert_err_contains!(
+            msg_err,
+            "Unknown parameter 'mode' of transform 'lowercase'. Supported parameters are: []"
+        );
+    }
+
+    #[test]
+    fn test_unknown_transform() {
+        let result = parse_transforms("reverse");
+
+        let msg_err = result.unwrap_err().help().unwrap().to_string();
+        crate::assert_err_contains!(msg_err, "Unknown transform 'reverse'");
+    }
+
+    #[test]
+    fn test_integer_length_is_accepted() {
+        let template = parse_transforms("truncate length=64").unwrap();
+
+        assert_eq!(
+            template.transforms[0].params.get("length"),
+            Some(&"64".to_string())
+        );
+    }
+
+    #[test]
+    fn test_hmac_params() {
+        let result = parse_transforms(r#"hmac key="${ENV:MOTYA_TEST_HMAC_KEY_UNSET}""#);
+        let msg_err = result.unwrap_err().help().unwrap().to_string();
+        crate::assert_err_contains!(
+            msg_err,
+            "environment variable 'MOTYA_TEST_HMAC_KEY_UNSET' is not set"
+        );
+
+        let result = parse_transforms(r#"hmac key="secret" algorithm="md5""#);
+        let msg_err = result.unwrap_err().help().unwrap().to_string();
+        crate::assert_err_contains!(
+            msg_err,
+            "Parameter 'algorithm' of transform 'hmac' must be one of"
+        );
+
+        let result = parse_transforms(r#"hmac key="""#);
+        let msg_err = result.unwrap_err().help().unwrap().to_string();
+        crate::assert_err_contains!(
+            msg_err,
+            "Parameter 'key' of transform 'hmac' must not be empty"
+        );
+
+        let template = parse_transforms(r#"hmac key="secret" algorithm="sha512""#).unwrap();
+        assert_eq!(
+            template.transforms[0].params.get("algorithm"),
+            Some(&"sha512".to_string())
+        );
+    }
+
+    #[test]
+    fn test_hmac_key_redacted() {
+        let template = parse_transforms(r#"hmac key="hmac-secret-value""#).unwrap();
+
+        let dump = format!("{template:#?}");
+        assert!(!dump.contains("hmac-secret-value"));
+        assert!(dump.contains("REDACTED"));
+        assert_eq!(
+            template.transforms[0].params.get("key"),
+            Some(&"hmac-secret-value".to_string())
+        );
+    }
 }
@@ -1,16 +1,22 @@
-use std::net::SocketAddr;
+use std::{net::SocketAddr, path::PathBuf, time::Duration};
 
 use motya_macro::validate;
+use url::Url;
 
 use crate::{
     common_types::{
-        listeners::{ListenerConfig, ListenerKind, Listeners, TlsConfig},
+        listeners::{
+            HttpVersion, ListenerConfig, ListenerKind, Listeners, OcspConfig, TicketKey, TlsConfig,
+            DEFAULT_MAX_HEADER_SIZE, DEFAULT_MAX_URI_LENGTH, TICKET_KEY_LEN,
+        },
         section_parser::SectionParser,
     },
     kdl::parser::{
+        block::BlockParser,
         ctx::ParseContext,
         ensures::{NamePredicate, Rule},
-        utils::{OptionTypedValueExt, PrimitiveType},
+        typed_value::{Entry, TypedValue},
+        utils::PrimitiveType,
     },
 };
 
@@ -21,39 +27,284 @@ impl SectionParser<ParseContext<'_>, Listeners> for ListenersSection {
     fn parse_node(&self, ctx: ParseContext<'_>) -> miette::Result<Listeners> {
         let nodes = ctx.req_nodes()?;
 
-        let list_cfgs = nodes
+        let (defaults, listeners): (Vec<_>, Vec<_>) = nodes
             .into_iter()
-            .map(|node_ctx| self.extract_listener(node_ctx))
-            .collect::<miette::Result<Vec<_>>>()?;
+            .partition(|node_ctx| node_ctx.name().is_ok_and(|name| name == "defaults"));
+
+        if let Some(duplicate) = defaults.get(1) {
+            return Err(duplicate.error("Only one 'defaults' block is allowed in 'listeners'"));
+        }
+        if listeners.is_empty() {
+            return Err(ctx.error("'listeners' must contain at least one listener"));
+        }
+
+        let defaults = match defaults.into_iter().next() {
+            Some(defaults_ctx) => self.extract_defaults(defaults_ctx)?,
+            None => ListenerKeys::default(),
+        };
+
+        let mut list_cfgs: Vec<ListenerConfig> = vec![];
+        for node_ctx in listeners {
+            let cfg = self.extract_listener(node_ctx.clone(), &defaults)?;
+            if let ListenerKind::InheritedFd { fd, .. } = cfg.source {
+                if list_cfgs
+                    .iter()
+                    .any(|other| other.source.inherited_fd() == Some(fd))
+                {
+                    return Err(node_ctx.error(format!("Duplicate listener on inherited fd {fd}")));
+                }
+            }
+            list_cfgs.push(cfg);
+        }
 
         Ok(Listeners { list_cfgs })
     }
 }
 
+/// Every key a listener node takes, all of which can also go in `defaults`.
+const LISTENER_KEYS: &[(&str, PrimitiveType)] = &[
+    ("cert-path", PrimitiveType::String),
+    ("key-path", PrimitiveType::String),
+    ("offer-h2", PrimitiveType::Bool),
+    ("http-versions", PrimitiveType::String),
+    ("session-tickets", PrimitiveType::Bool),
+    ("ticket-key-file", PrimitiveType::String),
+    ("max-concurrent", PrimitiveType::Integer),
+    ("client-read-timeout", PrimitiveType::String),
+    ("client-write-timeout", PrimitiveType::String),
+    ("tcp-nodelay", PrimitiveType::Bool),
+    ("tcp-fastopen", PrimitiveType::Integer),
+    ("backlog", PrimitiveType::Integer),
+    ("auto-tls", PrimitiveType::Bool),
+    ("max-uri-length", PrimitiveType::String),
+    ("max-header-size", PrimitiveType::String),
+    ("ocsp-staple", PrimitiveType::Bool),
+    ("ocsp-responder", PrimitiveType::String),
+];
+
+/// The keys set on a listener node, or in the `defaults { ... }` block used by every
+/// listener that doesn't set them itself.
+#[derive(Default)]
+struct ListenerKeys {
+    cert_path: Option<String>,
+    key_path: Option<String>,
+    offer_h2: Option<bool>,
+    http_versions: Option<Vec<HttpVersion>>,
+    session_tickets: Option<bool>,
+    ticket_key_file: Option<String>,
+    max_concurrent: Option<usize>,
+    client_read_timeout: Option<Duration>,
+    client_write_timeout: Option<Duration>,
+    tcp_nodelay: Option<bool>,
+    tcp_fastopen: Option<usize>,
+    backlog: Option<u32>,
+    auto_tls: Option<bool>,
+    max_uri_length: Option<usize>,
+    max_header_size: Option<usize>,
+    ocsp_staple: Option<bool>,
+    ocsp_responder: Option<Url>,
+}
+
+impl ListenerKeys {
+    /// Parses the value of one of the [`LISTENER_KEYS`], wherever it was written.
+    fn parse(&mut self, name: &str, value: TypedValue<'_>) -> miette::Result<()> {
+        let non_empty = |value: TypedValue<'_>| -> miette::Result<String> {
+            let path = value.as_str()?;
+            if path.trim().is_empty() {
+                return Err(value.error(format!("Value of '{name}' must not be empty")));
+            }
+            Ok(path)
+        };
+        let non_zero_size = |value: TypedValue<'_>| -> miette::Result<usize> {
+            let size = value.as_byte_size()?;
+            if size == 0 {
+                return Err(value.error(format!("'{name}' must be at least 1 byte")));
+            }
+            Ok(size)
+        };
+
+        match name {
+            "cert-path" => self.cert_path = Some(non_empty(value)?),
+            "key-path" => self.key_path = Some(non_empty(value)?),
+            "offer-h2" => self.offer_h2 = Some(value.as_bool()?),
+            "http-versions" => self.http_versions = Some(parse_http_versions(value)?),
+            "session-tickets" => self.session_tickets = Some(value.as_bool()?),
+            "ticket-key-file" => self.ticket_key_file = Some(non_empty(value)?),
+            "max-concurrent" => {
+                self.max_concurrent =
+                    Some(value.as_int_in_range(name, 1, u32::MAX as i128)? as usize)
+            }
+            "client-read-timeout" => self.client_read_timeout = Some(value.as_duration()?),
+            "client-write-timeout" => self.client_write_timeout = Some(value.as_duration()?),
+            "tcp-nodelay" => self.tcp_nodelay = Some(value.as_bool()?),
+            "tcp-fastopen" => {
+                self.tcp_fastopen = Some(value.as_int_in_range(name, 0, u32::MAX as i128)? as usize)
+            }
+            "backlog" => {
+                self.backlog = Some(value.as_int_in_range(name, 1, i32::MAX as i128)? as u32)
+            }
+            "auto-tls" => self.auto_tls = Some(value.as_bool()?),
+            "max-uri-length" => self.max_uri_length = Some(non_zero_size(value)?),
+            "max-header-size" => self.max_header_size = Some(non_zero_size(value)?),
+            "ocsp-staple" => self.ocsp_staple = Some(value.as_bool()?),
+            "ocsp-responder" => self.ocsp_responder = Some(value.as_url(name, &["http", "https"])?),
+            _ => return Err(value.error(format!("Unknown listener key '{name}'"))),
+        }
+        Ok(())
+    }
+}
+
 impl ListenersSection {
-    fn extract_listener(&self, ctx: ParseContext<'_>) -> miette::Result<ListenerConfig> {
+    fn extract_defaults(&self, ctx: ParseContext<'_>) -> miette::Result<ListenerKeys> {
+        ctx.validate(&[Rule::ReqChildren, Rule::NoArgs])?;
+
+        let mut defaults = ListenerKeys::default();
+        let mut block = BlockParser::new(ctx.enter_block()?)?;
+        for (name, _) in LISTENER_KEYS {
+            block.optional(name, |ctx| {
+                ctx.validate(&[Rule::NoChildren, Rule::ExactArgs(1), Rule::OnlyKeys(&[])])?;
+                defaults.parse(name, ctx.first()?)
+            })?;
+        }
+        block.exhaust()?;
+
+        Ok(defaults)
+    }
+
+    fn extract_listener(
+        &self,
+        ctx: ParseContext<'_>,
+        defaults: &ListenerKeys,
+    ) -> miette::Result<ListenerConfig> {
+        // `fd 3` takes over an inherited socket, any other name is the address to bind
+        let inherited = ctx.name()? == "fd";
+        if inherited {
+            ctx.validate(&[Rule::ExactArgs(1)])?;
+        } else {
+            ctx.validate(&[
+                Rule::NoPositionalArgs,
+                Rule::Name(NamePredicate::SocketAddr),
+            ])?;
+        }
         ctx.validate(&[
             Rule::NoChildren,
-            Rule::NoPositionalArgs,
-            Rule::OnlyKeysTyped(&[
-                ("cert-path", PrimitiveType::String),
-                ("key-path", PrimitiveType::String),
-                ("offer-h2", PrimitiveType::Bool),
-            ]),
-            Rule::Name(NamePredicate::SocketAddr),
+            Rule::NoDuplicateKeys,
+            Rule::OnlyKeysTyped(LISTENER_KEYS),
         ])?;
 
-        let addr = ctx.validated_name()?.as_socket_addr()?;
+        let mut keys = ListenerKeys::default();
+        for entry in ctx.entries()? {
+            if let Entry::Named(name, value) = entry {
+                keys.parse(name, value)?;
+            }
+        }
 
-        let [cert_opt, key_opt, h2_opt] = ctx.props(["cert-path", "key-path", "offer-h2"])?;
+        // keys set on the listener itself win over `defaults`
+        let cert_path = keys.cert_path.or_else(|| defaults.cert_path.clone());
+        let key_path = keys.key_path.or_else(|| defaults.key_path.clone());
+        // a default `offer-h2` only concerns the listeners that have TLS
+        let offer_h2 = keys
+            .offer_h2
+            .or(defaults.offer_h2.filter(|_| cert_path.is_some()));
+        let http_versions = keys.http_versions.or_else(|| {
+            defaults
+                .http_versions
+                .clone()
+                .filter(|_| cert_path.is_some())
+        });
+        let (offer_h2, h2_only) =
+            resolve_http_versions(&ctx, http_versions, offer_h2, cert_path.is_some())?;
+        let session_tickets = keys
+            .session_tickets
+            .or(defaults.session_tickets.filter(|_| cert_path.is_some()));
+        // a default key file is not used by the listeners that turn tickets off
+        let ticket_key_file = keys.ticket_key_file.or_else(|| {
+            defaults
+                .ticket_key_file
+                .clone()
+                .filter(|_| cert_path.is_some() && session_tickets != Some(false))
+        });
 
-        self.resolve_tcp_listener(
-            &ctx,
-            addr,
-            cert_opt.as_str()?,
-            key_opt.as_str()?,
-            h2_opt.as_bool()?,
-        )
+        let source = if inherited {
+            self.resolve_inherited_fd(&ctx, cert_path, key_path, offer_h2)?
+        } else {
+            let addr = ctx.validated_name()?.as_socket_addr()?;
+            self.resolve_tcp_listener(&ctx, addr, cert_path, key_path, offer_h2)?
+        };
+        let mut source =
+            self.resolve_session_tickets(&ctx, source, session_tickets, ticket_key_file)?;
+        if let ListenerKind::Tcp { tls: Some(tls), .. }
+        | ListenerKind::InheritedFd { tls: Some(tls), .. } = &mut source
+        {
+            tls.h2_only = h2_only;
+        }
+        let ocsp_staple = keys
+            .ocsp_staple
+            .or(defaults.ocsp_staple.filter(|_| source.tls().is_some()))
+            .unwrap_or(false);
+        // a default responder is not used by the listeners that don't staple
+        let ocsp_responder = keys
+            .ocsp_responder
+            .or_else(|| defaults.ocsp_responder.clone().filter(|_| ocsp_staple));
+        let source = self.resolve_ocsp(&ctx, source, ocsp_staple, ocsp_responder)?;
+
+        // a default only applies to the listeners it can be used on
+        let auto_tls = keys
+            .auto_tls
+            .or(defaults
+                .auto_tls
+                .filter(|_| source.tls().is_some() && !inherited))
+            .unwrap_or(false);
+        if auto_tls && source.tls().is_none() {
+            return Err(ctx.error("'auto-tls' requires TLS, specify 'cert-path' and 'key-path'"));
+        }
+        // the process handing the socket over has already listened on it
+        if inherited && auto_tls {
+            return Err(ctx.error("'auto-tls' can't be used on an inherited 'fd' listener"));
+        }
+        if keys.backlog.is_some() && !auto_tls {
+            let msg = if inherited {
+                "'backlog' can't be set on an inherited 'fd' listener, it is set by the process that opened the socket"
+            } else {
+                // pingora listens on the other sockets itself, with a backlog of its own
+                "'backlog' only applies to listeners with 'auto-tls=#true', the others are listened on by pingora with a fixed backlog"
+            };
+            return Err(match ctx.span_of_prop("backlog") {
+                Some(span) => ctx.error_with_span(msg, span),
+                None => ctx.error(msg),
+            });
+        }
+
+        Ok(ListenerConfig {
+            source,
+            max_concurrent: keys.max_concurrent.or(defaults.max_concurrent),
+            // "0s" turns the timeout off
+            client_read_timeout: keys
+                .client_read_timeout
+                .or(defaults.client_read_timeout)
+                .filter(|t| !t.is_zero()),
+            client_write_timeout: keys
+                .client_write_timeout
+                .or(defaults.client_write_timeout)
+                .filter(|t| !t.is_zero()),
+            tcp_nodelay: keys.tcp_nodelay.or(defaults.tcp_nodelay).unwrap_or(false),
+            // a backlog of 0 leaves Fast Open off
+            tcp_fastopen: keys
+                .tcp_fastopen
+                .or(defaults.tcp_fastopen)
+                .filter(|backlog| *backlog > 0),
+            // the socket of an `fd` listener is already listening
+            backlog: keys.backlog.or(defaults.backlog.filter(|_| auto_tls)),
+            auto_tls,
+            max_uri_length: keys
+                .max_uri_length
+                .or(defaults.max_uri_length)
+                .unwrap_or(DEFAULT_MAX_URI_LENGTH),
+            max_header_size: keys
+                .max_header_size
+                .or(defaults.max_header_size)
+                .unwrap_or(DEFAULT_MAX_HEADER_SIZE),
+        })
     }
 
     fn resolve_tcp_listener(
@@ -63,36 +314,1085 @@ impl ListenersSection {
         cert_path: Option<String>,
         key_path: Option<String>,
         offer_h2: Option<bool>,
-    ) -> miette::Result<ListenerConfig> {
+    ) -> miette::Result<ListenerKind> {
+        let (tls, offer_h2) = self.resolve_tls(ctx, cert_path, key_path, offer_h2)?;
+        Ok(ListenerKind::Tcp {
+            addr: addr.to_string(),
+            tls,
+            offer_h2,
+        })
+    }
+
+    /// The TLS settings of a listener and whether it offers h2.
+    fn resolve_tls(
+        &self,
+        ctx: &ParseContext<'_>,
+        cert_path: Option<String>,
+        key_path: Option<String>,
+        offer_h2: Option<bool>,
+    ) -> miette::Result<(Option<TlsConfig>, bool)> {
         match (cert_path, key_path, offer_h2) {
 
-            (None, None, None) => Ok(ListenerConfig {
-                source: ListenerKind::Tcp {
-                    addr: addr.to_string(),
-                    tls: None,
-                    offer_h2: false,
-                },
-            }),
+            (None, None, None) => Ok((None, false)),
 
             (None, Some(_), _) | (Some(_), None, _) => Err(ctx.error(
                 "'cert-path' and 'key-path' must either BOTH be present, or NEITHER should be present",
             )),
 
-            (None, None, Some(_)) => Err(ctx.error(
-                "'offer-h2' requires TLS, specify 'cert-path' and 'key-path'",
+            (None, None, Some(_)) => {
+                let msg = "'offer-h2' requires TLS, specify 'cert-path' and 'key-path'";
+                Err(match ctx.span_of_prop("offer-h2") {
+                    Some(span) => ctx.error_with_span(msg, span),
+                    None => ctx.error(msg),
+                })
+            }
+
+            (Some(cpath), Some(kpath), offer_h2) => Ok((
+                Some(TlsConfig {
+                    cert_path: cpath.into(),
+                    key_path: kpath.into(),
+                    session_tickets: true,
+                    ticket_key: None,
+                    h2_only: false,
+                    ocsp: None,
+                }),
+                offer_h2.unwrap_or(true),
             )),
+        }
+    }
 
-            (Some(cpath), Some(kpath), offer_h2) => Ok(ListenerConfig {
-                source: ListenerKind::Tcp {
-                    addr: addr.to_string(),
-                    tls: Some(TlsConfig {
-                        cert_path: cpath.into(),
-                        key_path: kpath.into(),
-                    }),
+    /// `fd N`, a socket inherited from the process that started motya.
+    fn resolve_inherited_fd(
+        &self,
+        ctx: &ParseContext<'_>,
+        cert_path: Option<String>,
+        key_path: Option<String>,
+        offer_h2: Option<bool>,
+    ) -> miette::Result<ListenerKind> {
+        let value = ctx.first()?;
+        let fd = value.as_i32()?;
+        if fd < 1 {
+            return Err(value.error(format!(
+                "'fd' must be a positive file descriptor, found {fd}"
+            )));
+        }
 
-                    offer_h2: offer_h2.unwrap_or(true),
-                },
-            }),
+        let (tls, offer_h2) = self.resolve_tls(ctx, cert_path, key_path, offer_h2)?;
+        Ok(ListenerKind::InheritedFd { fd, tls, offer_h2 })
+    }
+
+    /// Sets up session resumption on a TLS listener, reading its `ticket-key-file`.
+    fn resolve_session_tickets(
+        &self,
+        ctx: &ParseContext<'_>,
+        mut source: ListenerKind,
+        session_tickets: Option<bool>,
+        ticket_key_file: Option<String>,
+    ) -> miette::Result<ListenerKind> {
+        match &mut source {
+            ListenerKind::Tcp { tls: Some(tls), .. }
+            | ListenerKind::InheritedFd { tls: Some(tls), .. } => {
+                tls.session_tickets = session_tickets.unwrap_or(true);
+
+                if let Some(path) = ticket_key_file {
+                    if !tls.session_tickets {
+                        return Err(ctx.error(
+                            "'ticket-key-file' has no effect with 'session-tickets #false'",
+                        ));
+                    }
+                    tls.ticket_key = Some(load_ticket_key(ctx, path)?);
+                }
+            }
+            _ if session_tickets.is_some() => {
+                return Err(
+                    ctx.error("'session-tickets' requires TLS, specify 'cert-path' and 'key-path'")
+                )
+            }
+            _ if ticket_key_file.is_some() => {
+                return Err(
+                    ctx.error("'ticket-key-file' requires TLS, specify 'cert-path' and 'key-path'")
+                )
+            }
+            _ => {}
         }
+
+        Ok(source)
+    }
+
+    /// Turns on OCSP stapling for a TLS listener with `ocsp-staple #true`.
+    fn resolve_ocsp(
+        &self,
+        ctx: &ParseContext<'_>,
+        mut source: ListenerKind,
+        staple: bool,
+        responder: Option<Url>,
+    ) -> miette::Result<ListenerKind> {
+        if !staple {
+            if responder.is_some() {
+                return Err(ctx.error("'ocsp-responder' has no effect without 'ocsp-staple #true'"));
+            }
+            return Ok(source);
+        }
+
+        match &mut source {
+            ListenerKind::Tcp { tls: Some(tls), .. }
+            | ListenerKind::InheritedFd { tls: Some(tls), .. } => {
+                tls.ocsp = Some(OcspConfig { responder });
+                Ok(source)
+            }
+            _ => Err(ctx.error("'ocsp-staple' requires TLS, specify 'cert-path' and 'key-path'")),
+        }
+    }
+}
+
+/// Parses `http-versions`, a comma separated list like `"1.1, 2"`.
+fn parse_http_versions(value: TypedValue<'_>) -> miette::Result<Vec<HttpVersion>> {
+    value
+        .as_str()?
+        .split(',')
+        .map(|version| {
+            version
+                .trim()
+                .parse::<HttpVersion>()
+                .map_err(|err| value.error(err))
+        })
+        .collect()
+}
+
+/// Checks the `http-versions` of a listener against its `offer-h2` and TLS, returning the
+/// `offer-h2` they amount to and whether HTTP/1 is refused.
+fn resolve_http_versions(
+    ctx: &ParseContext<'_>,
+    http_versions: Option<Vec<HttpVersion>>,
+    offer_h2: Option<bool>,
+    tls: bool,
+) -> miette::Result<(Option<bool>, bool)> {
+    let Some(versions) = http_versions else {
+        return Ok((offer_h2, false));
+    };
+    let http1 = versions.contains(&HttpVersion::Http1);
+    let http2 = versions.contains(&HttpVersion::Http2);
+
+    if !tls {
+        // plaintext listeners only speak HTTP/1, there is no h2c
+        if !http1 {
+            return Err(ctx.error(
+                "'http-versions \"2\"' requires TLS, HTTP/2 is not served over plaintext. \
+                 Specify 'cert-path' and 'key-path'",
+            ));
+        }
+        return Ok((offer_h2, false));
+    }
+
+    match offer_h2 {
+        Some(offer_h2) if offer_h2 != http2 => Err(ctx.error(format!(
+            "'offer-h2=#{offer_h2}' contradicts 'http-versions', which {} HTTP/2",
+            if http2 { "includes" } else { "leaves out" }
+        ))),
+        _ => Ok((Some(http2), !http1)),
+    }
+}
+
+fn load_ticket_key(ctx: &ParseContext<'_>, path: String) -> miette::Result<TicketKey> {
+    let contents = std::fs::read(&path)
+        .map_err(|e| ctx.error(format!("Failed to read ticket key file '{path}': {e}")))?;
+
+    let material = <[u8; TICKET_KEY_LEN]>::try_from(contents.as_slice()).map_err(|_| {
+        ctx.error(format!(
+            "Ticket key file '{path}' must hold exactly {TICKET_KEY_LEN} bytes, found {}. Generate one with 'openssl rand {TICKET_KEY_LEN} > {path}'",
+            contents.len()
+        ))
+    })?;
+
+    Ok(TicketKey {
+        path: PathBuf::from(path),
+        material,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{
+        assert_err_contains,
+        kdl::parser::{block::BlockParser, ctx::Current},
+    };
+    use kdl::KdlDocument;
+
+    fn parse_listeners(input: &str) -> miette::Result<Listeners> {
+        let doc: KdlDocument = input.parse().unwrap();
+        let ctx = ParseContext::new(&doc, Current::Document(&doc), "test");
+        let mut block = BlockParser::new(ctx)?;
+
+        block.required("listeners", |ctx| ListenersSection.parse_node(ctx))
+    }
+
+    #[test]
+    fn test_defaults() {
+        let listeners = parse_listeners(
+            r#"
+            listeners {
+                defaults {
+                    offer-h2 #false
+                    max-concurrent 100
+                    client-read-timeout "10s"
+                }
+                "0.0.0.0:443" cert-path="a.crt" key-path="a.key"
+                "0.0.0.0:8443" cert-path="b.crt" key-path="b.key" offer-h2=#true max-concurrent=5
+                "0.0.0.0:80" client-read-timeout="0s"
+            }
+        "#,
+        )
+        .expect("Should parse listeners");
+
+        let shape = listeners
+            .list_cfgs
+            .iter()
+            .map(|cfg| {
+                let ListenerKind::Tcp { offer_h2, .. } = &cfg.source else {
+                    panic!("expected a TCP listener");
+                };
+                (*offer_h2, cfg.max_concurrent, cfg.client_read_timeout)
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            shape,
+            vec![
+                (false, Some(100), Some(Duration::from_secs(10))),
+                (true, Some(5), Some(Duration::from_secs(10))),
+                // the plain listener is not affected by `offer-h2`
+                (false, Some(100), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_defaults_unknown_key() {
+        let result = parse_listeners(
+            r#"
+            listeners {
+                defaults {
+                    offer-h3 #true
+                }
+                "0.0.0.0:80"
+            }
+        "#,
+        );
+
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(err_msg, "Unknown directive: 'offer-h3'");
+    }
+
+    #[test]
+    fn test_max_concurrent() {
+        let listeners = parse_listeners(
+            r#"
+            listeners {
+                "0.0.0.0:80" max-concurrent=1000
+                "0.0.0.0:81"
+            }
+        "#,
+        )
+        .expect("Should parse listeners");
+
+        assert_eq!(listeners.list_cfgs[0].max_concurrent, Some(1000));
+        assert_eq!(listeners.list_cfgs[1].max_concurrent, None);
+    }
+
+    #[test]
+    fn test_max_concurrent_out_of_range() {
+        let result = parse_listeners(
+            r#"
+            listeners {
+                "0.0.0.0:80" max-concurrent=0
+            }
+        "#,
+        );
+
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(err_msg, "Value of 'max-concurrent' must be between 1 and");
+    }
+
+    #[test]
+    fn test_client_timeouts() {
+        let listeners = parse_listeners(
+            r#"
+            listeners {
+                "0.0.0.0:80" client-read-timeout="10s" client-write-timeout="0s"
+                "0.0.0.0:81"
+            }
+        "#,
+        )
+        .expect("Should parse listeners");
+
+        let first = &listeners.list_cfgs[0];
+        assert_eq!(first.client_read_timeout, Some(Duration::from_secs(10)));
+        assert_eq!(first.client_write_timeout, None);
+        assert_eq!(listeners.list_cfgs[1].client_read_timeout, None);
+    }
+
+    #[test]
+    fn test_client_timeout_invalid() {
+        let result = parse_listeners(
+            r#"
+            listeners {
+                "0.0.0.0:80" client-read-timeout="soon"
+            }
+        "#,
+        );
+
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(err_msg, "Invalid duration 'soon'");
+    }
+
+    #[test]
+    fn test_tcp_options() {
+        let listeners = parse_listeners(
+            r#"
+            listeners {
+                "0.0.0.0:80" tcp-nodelay=#true tcp-fastopen=256
+                "0.0.0.0:81" tcp-fastopen=0
+                "0.0.0.0:82"
+            }
+        "#,
+        )
+        .expect("Should parse listeners");
+
+        let options = listeners
+            .list_cfgs
+            .iter()
+            .map(|cfg| (cfg.tcp_nodelay, cfg.tcp_fastopen))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            options,
+            vec![(true, Some(256)), (false, None), (false, None)]
+        );
+    }
+
+    #[test]
+    fn test_tcp_options_defaults() {
+        let listeners = parse_listeners(
+            r#"
+            listeners {
+                defaults {
+                    tcp-nodelay #true
+                    tcp-fastopen 256
+                }
+                "0.0.0.0:80"
+                "0.0.0.0:81" tcp-nodelay=#false tcp-fastopen=0
+            }
+        "#,
+        )
+        .expect("Should parse listeners");
+
+        let options = listeners
+            .list_cfgs
+            .iter()
+            .map(|cfg| (cfg.tcp_nodelay, cfg.tcp_fastopen))
+            .collect::<Vec<_>>();
+        assert_eq!(options, vec![(true, Some(256)), (false, None)]);
+    }
+
+    #[test]
+    fn test_auto_tls_defaults() {
+        let listeners = parse_listeners(
+            r#"
+            listeners {
+                defaults {
+                    auto-tls #true
+                }
+                "0.0.0.0:443" cert-path="a.crt" key-path="a.key"
+                "0.0.0.0:8443" cert-path="a.crt" key-path="a.key" auto-tls=#false
+                "0.0.0.0:80"
+                fd 3 cert-path="a.crt" key-path="a.key"
+            }
+        "#,
+        )
+        .expect("Should parse listeners");
+
+        let auto_tls = listeners
+            .list_cfgs
+            .iter()
+            .map(|cfg| cfg.auto_tls)
+            .collect::<Vec<_>>();
+        assert_eq!(auto_tls, vec![true, false, false, false]);
+    }
+
+    #[test]
+    fn test_tcp_options_invalid() {
+        let result = parse_listeners(
+            r#"
+            listeners {
+                "0.0.0.0:80" tcp-nodelay="yes"
+            }
+        "#,
+        );
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(
+            err_msg,
+            "Invalid type for key 'tcp-nodelay'. Expected Boolean, found String"
+        );
+
+        let result = parse_listeners(
+            r#"
+            listeners {
+                "0.0.0.0:80" tcp-fastopen=-1
+            }
+        "#,
+        );
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(
+            err_msg,
+            "Value of 'tcp-fastopen' must be between 0 and 4294967295, found -1"
+        );
+    }
+
+    #[test]
+    fn test_backlog() {
+        let listeners = parse_listeners(
+            r#"
+            listeners {
+                "0.0.0.0:443" cert-path="a.crt" key-path="a.key" auto-tls=#true backlog=1024
+                "0.0.0.0:444" cert-path="a.crt" key-path="a.key" auto-tls=#true
+            }
+        "#,
+        )
+        .expect("Should parse listeners");
+
+        let backlogs = listeners
+            .list_cfgs
+            .iter()
+            .map(|cfg| cfg.backlog)
+            .collect::<Vec<_>>();
+        assert_eq!(backlogs, vec![Some(1024), None]);
+
+        let result = parse_listeners(
+            r#"
+            listeners {
+                "0.0.0.0:443" cert-path="a.crt" key-path="a.key" auto-tls=#true backlog=0
+            }
+        "#,
+        );
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(
+            err_msg,
+            "Value of 'backlog' must be between 1 and 2147483647, found 0"
+        );
+
+        // pingora listens on these itself, the key would do nothing
+        for listener in [
+            r#""0.0.0.0:80" backlog=1024"#,
+            r#""0.0.0.0:443" cert-path="a.crt" key-path="a.key" backlog=1024"#,
+        ] {
+            let result = parse_listeners(&format!("listeners {{ {listener}; }}"));
+            let err = result.unwrap_err();
+            let bad = err.downcast_ref::<crate::common_types::bad::Bad>().unwrap();
+            assert_err_contains!(
+                err.help().unwrap().to_string(),
+                "'backlog' only applies to listeners with 'auto-tls=#true'"
+            );
+
+            let text = bad.src.inner();
+            let label = &text[bad.err_span.offset()..bad.err_span.offset() + bad.err_span.len()];
+            assert_eq!(label.trim(), "backlog=1024");
+        }
+    }
+
+    #[test]
+    fn test_backlog_defaults() {
+        let listeners = parse_listeners(
+            r#"
+            listeners {
+                defaults {
+                    backlog 4096
+                }
+                "0.0.0.0:443" cert-path="a.crt" key-path="a.key" auto-tls=#true
+                "0.0.0.0:444" cert-path="a.crt" key-path="a.key" auto-tls=#true backlog=128
+                "0.0.0.0:80"
+                fd 3
+            }
+        "#,
+        )
+        .expect("Should parse listeners");
+
+        // only the listeners motya listens on itself take the default
+        let backlogs = listeners
+            .list_cfgs
+            .iter()
+            .map(|cfg| cfg.backlog)
+            .collect::<Vec<_>>();
+        assert_eq!(backlogs, vec![Some(4096), Some(128), None, None]);
+
+        let result = parse_listeners(
+            r#"
+            listeners {
+                defaults {
+                    backlog 0
+                }
+                "0.0.0.0:80"
+            }
+        "#,
+        );
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(
+            err_msg,
+            "Value of 'backlog' must be between 1 and 2147483647, found 0"
+        );
+    }
+
+    #[test]
+    fn test_inherited_fd() {
+        let listeners = parse_listeners(
+            r#"
+            listeners {
+                fd 3 cert-path="a.crt" key-path="a.key" offer-h2=#true
+                fd 4 max-concurrent=10
+            }
+        "#,
+        )
+        .expect("Should parse listeners");
+
+        let tls = listeners.list_cfgs[0].source.tls().expect("fd 3 has TLS");
+        assert_eq!(tls.cert_path, PathBuf::from("a.crt"));
+        assert!(matches!(
+            listeners.list_cfgs[0].source,
+            ListenerKind::InheritedFd {
+                fd: 3,
+                offer_h2: true,
+                ..
+            }
+        ));
+        assert_eq!(
+            listeners.list_cfgs[1].source,
+            ListenerKind::InheritedFd {
+                fd: 4,
+                tls: None,
+                offer_h2: false,
+            }
+        );
+        assert_eq!(listeners.list_cfgs[1].max_concurrent, Some(10));
+
+        let result = parse_listeners(
+            r#"
+            listeners {
+                fd -1
+            }
+        "#,
+        );
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(err_msg, "'fd' must be a positive file descriptor, found -1");
+
+        let result = parse_listeners(
+            r#"
+            listeners {
+                fd 3 cert-path="a.crt" key-path="a.key" auto-tls=#true
+            }
+        "#,
+        );
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(
+            err_msg,
+            "'auto-tls' can't be used on an inherited 'fd' listener"
+        );
+
+        let result = parse_listeners(
+            r#"
+            listeners {
+                fd 3
+                fd 3 max-concurrent=10
+            }
+        "#,
+        );
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(err_msg, "Duplicate listener on inherited fd 3");
+    }
+
+    #[test]
+    fn test_auto_tls() {
+        let listeners = parse_listeners(
+            r#"
+            listeners {
+                "0.0.0.0:443" cert-path="a.crt" key-path="a.key" auto-tls=#true
+                "0.0.0.0:8443" cert-path="b.crt" key-path="b.key"
+            }
+        "#,
+        )
+        .expect("Should parse listeners");
+
+        let auto_tls = listeners
+            .list_cfgs
+            .iter()
+            .map(|cfg| cfg.auto_tls)
+            .collect::<Vec<_>>();
+        assert_eq!(auto_tls, vec![true, false]);
+
+        let result = parse_listeners(
+            r#"
+            listeners {
+                "0.0.0.0:80" auto-tls=#true
+            }
+        "#,
+        );
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(
+            err_msg,
+            "'auto-tls' requires TLS, specify 'cert-path' and 'key-path'"
+        );
+    }
+
+    fn tls_of(cfg: &ListenerConfig) -> Option<&TlsConfig> {
+        cfg.source.tls()
+    }
+
+    #[test]
+    fn test_ocsp_staple() {
+        let listeners = parse_listeners(
+            r#"
+            listeners {
+                "0.0.0.0:443" cert-path="a.crt" key-path="a.key" ocsp-staple=#true
+                "0.0.0.0:8443" cert-path="b.crt" key-path="b.key" ocsp-staple=#true ocsp-responder="http://ocsp.example.com"
+                "0.0.0.0:9443" cert-path="c.crt" key-path="c.key"
+            }
+        "#,
+        )
+        .expect("Should parse listeners");
+
+        let ocsp = listeners
+            .list_cfgs
+            .iter()
+            .map(|cfg| tls_of(cfg).unwrap().ocsp.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ocsp,
+            vec![
+                Some(OcspConfig { responder: None }),
+                Some(OcspConfig {
+                    responder: Some(Url::parse("http://ocsp.example.com").unwrap()),
+                }),
+                None,
+            ]
+        );
+
+        for (input, expected) in [
+            (
+                r#""0.0.0.0:80" ocsp-staple=#true"#,
+                "'ocsp-staple' requires TLS, specify 'cert-path' and 'key-path'",
+            ),
+            (
+                r#""0.0.0.0:443" cert-path="a.crt" key-path="a.key" ocsp-responder="http://ocsp.example.com""#,
+                "'ocsp-responder' has no effect without 'ocsp-staple #true'",
+            ),
+        ] {
+            let result = parse_listeners(&format!("listeners {{\n{input}\n}}"));
+            let err_msg = result.unwrap_err().help().unwrap().to_string();
+            assert_err_contains!(err_msg, expected);
+        }
+
+        let result = parse_listeners(
+            r#"
+            listeners {
+                "0.0.0.0:443" cert-path="a.crt" key-path="a.key" ocsp-staple=#true ocsp-responder="ldap://ocsp.example.com"
+            }
+        "#,
+        );
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(err_msg, "Scheme 'ldap' is not allowed for 'ocsp-responder'");
+    }
+
+    #[test]
+    fn test_ocsp_defaults() {
+        let listeners = parse_listeners(
+            r#"
+            listeners {
+                defaults {
+                    ocsp-staple #true
+                    ocsp-responder "http://ocsp.example.com"
+                }
+                "0.0.0.0:443" cert-path="a.crt" key-path="a.key"
+                "0.0.0.0:8443" cert-path="b.crt" key-path="b.key" ocsp-responder="https://other.example.com"
+                "0.0.0.0:9443" cert-path="c.crt" key-path="c.key" ocsp-staple=#false
+                "0.0.0.0:80"
+            }
+        "#,
+        )
+        .expect("Should parse listeners");
+
+        let ocsp = listeners
+            .list_cfgs
+            .iter()
+            .map(|cfg| tls_of(cfg).map(|tls| tls.ocsp.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ocsp,
+            vec![
+                Some(Some(OcspConfig {
+                    responder: Some(Url::parse("http://ocsp.example.com").unwrap()),
+                })),
+                Some(Some(OcspConfig {
+                    responder: Some(Url::parse("https://other.example.com").unwrap()),
+                })),
+                Some(None),
+                None,
+            ]
+        );
+
+        let result = parse_listeners(
+            r#"
+            listeners {
+                defaults {
+                    ocsp-responder "ldap://ocsp.example.com"
+                }
+                "0.0.0.0:443" cert-path="a.crt" key-path="a.key"
+            }
+        "#,
+        );
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(err_msg, "Scheme 'ldap' is not allowed for 'ocsp-responder'");
+    }
+
+    #[test]
+    fn test_ticket_key_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let key_path = dir.path().join("ticket.key");
+        let material: [u8; TICKET_KEY_LEN] = std::array::from_fn(|i| i as u8);
+        std::fs::write(&key_path, material).unwrap();
+
+        let listeners = parse_listeners(&format!(
+            r#"
+            listeners {{
+                defaults {{
+                    ticket-key-file "{}"
+                }}
+                "0.0.0.0:443" cert-path="a.crt" key-path="a.key"
+                "0.0.0.0:8443" cert-path="b.crt" key-path="b.key" session-tickets=#false
+                "0.0.0.0:80"
+            }}
+        "#,
+            key_path.display()
+        ))
+        .expect("Should parse listeners");
+
+        let shared = tls_of(&listeners.list_cfgs[0]).unwrap();
+        assert!(shared.session_tickets);
+        let key = shared
+            .ticket_key
+            .as_ref()
+            .expect("Should load the ticket key");
+        assert_eq!(key.material, material);
+        assert_eq!(key.path, key_path);
+        // the key is never printed, only where it came from
+        assert!(!format!("{key:?}").contains("material"));
+
+        let no_tickets = tls_of(&listeners.list_cfgs[1]).unwrap();
+        assert!(!no_tickets.session_tickets);
+        assert_eq!(no_tickets.ticket_key, None);
+
+        assert_eq!(tls_of(&listeners.list_cfgs[2]), None);
+    }
+
+    #[test]
+    fn test_ticket_key_file_length() {
+        let dir = tempfile::tempdir().unwrap();
+        let key_path = dir.path().join("ticket.key");
+        // the 48 byte layout of OpenSSL 1.0
+        std::fs::write(&key_path, [0u8; 48]).unwrap();
+
+        let result = parse_listeners(&format!(
+            r#"
+            listeners {{
+                "0.0.0.0:443" cert-path="a.crt" key-path="a.key" ticket-key-file="{}"
+            }}
+        "#,
+            key_path.display()
+        ));
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(err_msg, "must hold exactly 80 bytes, found 48");
+
+        let result = parse_listeners(
+            r#"
+            listeners {
+                "0.0.0.0:80" ticket-key-file="ticket.key"
+            }
+        "#,
+        );
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(
+            err_msg,
+            "'ticket-key-file' requires TLS, specify 'cert-path' and 'key-path'"
+        );
+    }
+
+    #[test]
+    fn test_max_uri_length() {
+        let listeners = parse_listeners(
+            r#"
+            listeners {
+                "0.0.0.0:80" max-uri-length="16KiB"
+                "0.0.0.0:81"
+            }
+        "#,
+        )
+        .expect("Should parse listeners");
+
+        let limits = listeners
+            .list_cfgs
+            .iter()
+            .map(|cfg| cfg.max_uri_length)
+            .collect::<Vec<_>>();
+        assert_eq!(limits, vec![16 * 1024, DEFAULT_MAX_URI_LENGTH]);
+
+        let result = parse_listeners(
+            r#"
+            listeners {
+                "0.0.0.0:80" max-uri-length="8 lines"
+            }
+        "#,
+        );
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(err_msg, "Invalid size '8 lines'");
+
+        let result = parse_listeners(
+            r#"
+            listeners {
+                "0.0.0.0:80" max-uri-length="0B"
+            }
+        "#,
+        );
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(err_msg, "'max-uri-length' must be at least 1 byte");
+    }
+
+    #[test]
+    fn test_max_uri_length_defaults() {
+        let listeners = parse_listeners(
+            r#"
+            listeners {
+                defaults {
+                    max-uri-length "16KiB"
+                }
+                "0.0.0.0:80"
+                "0.0.0.0:81" max-uri-length="4KiB"
+            }
+        "#,
+        )
+        .expect("Should parse listeners");
+
+        let limits = listeners
+            .list_cfgs
+            .iter()
+            .map(|cfg| cfg.max_uri_length)
+            .collect::<Vec<_>>();
+        assert_eq!(limits, vec![16 * 1024, 4 * 1024]);
+
+        let result = parse_listeners(
+            r#"
+            listeners {
+                defaults {
+                    max-uri-length "0B"
+                }
+                "0.0.0.0:80"
+            }
+        "#,
+        );
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(err_msg, "'max-uri-length' must be at least 1 byte");
+    }
+
+    #[test]
+    fn test_max_header_size() {
+        let listeners = parse_listeners(
+            r#"
+            listeners {
+                "0.0.0.0:80" max-header-size="1KiB"
+                "0.0.0.0:81"
+            }
+        "#,
+        )
+        .expect("Should parse listeners");
+
+        let limits = listeners
+            .list_cfgs
+            .iter()
+            .map(|cfg| cfg.max_header_size)
+            .collect::<Vec<_>>();
+        assert_eq!(limits, vec![1024, DEFAULT_MAX_HEADER_SIZE]);
+
+        let result = parse_listeners(
+            r#"
+            listeners {
+                "0.0.0.0:80" max-header-size="0B"
+            }
+        "#,
+        );
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(err_msg, "'max-header-size' must be at least 1 byte");
+    }
+
+    #[test]
+    fn test_max_header_size_defaults() {
+        let listeners = parse_listeners(
+            r#"
+            listeners {
+                defaults {
+                    max-header-size "16KiB"
+                }
+                "0.0.0.0:80"
+                "0.0.0.0:81" max-header-size="1KiB"
+            }
+        "#,
+        )
+        .expect("Should parse listeners");
+
+        let limits = listeners
+            .list_cfgs
+            .iter()
+            .map(|cfg| cfg.max_header_size)
+            .collect::<Vec<_>>();
+        assert_eq!(limits, vec![16 * 1024, 1024]);
+
+        let result = parse_listeners(
+            r#"
+            listeners {
+                defaults {
+                    max-header-size "0B"
+                }
+                "0.0.0.0:80"
+            }
+        "#,
+        );
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(err_msg, "'max-header-size' must be at least 1 byte");
+    }
+
+    #[test]
+    fn test_http_versions() {
+        let listeners = parse_listeners(
+            r#"
+            listeners {
+                "0.0.0.0:443" cert-path="a.crt" key-path="a.key" http-versions="2"
+                "0.0.0.0:8443" cert-path="a.crt" key-path="a.key" http-versions="1.1"
+                "0.0.0.0:9443" cert-path="a.crt" key-path="a.key" http-versions="1.1, 2"
+                "0.0.0.0:80" http-versions="1.1"
+            }
+        "#,
+        )
+        .expect("Should parse listeners");
+
+        let shape = listeners
+            .list_cfgs
+            .iter()
+            .map(|cfg| match &cfg.source {
+                ListenerKind::Tcp { tls, offer_h2, .. } => {
+                    (*offer_h2, tls.as_ref().is_some_and(|tls| tls.h2_only))
+                }
+                ListenerKind::Uds(_) => panic!("expected a TCP listener"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            shape,
+            vec![(true, true), (false, false), (true, false), (false, false)]
+        );
+    }
+
+    #[test]
+    fn test_http_versions_inconsistent() {
+        let result = parse_listeners(
+            r#"
+            listeners {
+                "0.0.0.0:80" http-versions="2"
+            }
+        "#,
+        );
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(err_msg, "'http-versions \"2\"' requires TLS");
+
+        let result = parse_listeners(
+            r#"
+            listeners {
+                "0.0.0.0:443" cert-path="a.crt" key-path="a.key" offer-h2=#true http-versions="1.1"
+            }
+        "#,
+        );
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(
+            err_msg,
+            "'offer-h2=#true' contradicts 'http-versions', which leaves out HTTP/2"
+        );
+
+        let result = parse_listeners(
+            r#"
+            listeners {
+                "0.0.0.0:443" cert-path="a.crt" key-path="a.key" http-versions="1.0"
+            }
+        "#,
+        );
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(err_msg, "unknown HTTP version '1.0', expected '1.1' or '2'");
+    }
+
+    #[test]
+    fn test_duplicate_offer_h2() {
+        let result = parse_listeners(
+            r#"
+            listeners {
+                "0.0.0.0:443" cert-path="a.crt" key-path="a.key" offer-h2=#true offer-h2=#false
+            }
+        "#,
+        );
+
+        let err = result.unwrap_err();
+        let bad = err.downcast_ref::<crate::common_types::bad::Bad>().unwrap();
+        let err_msg = err.help().unwrap().to_string();
+
+        assert_err_contains!(err_msg, "Duplicate configuration key: 'offer-h2'");
+
+        let text = bad.src.inner();
+        let label = &text[bad.err_span.offset()..bad.err_span.offset() + bad.err_span.len()];
+        assert_err_contains!(label, "offer-h2=#false");
+    }
+
+    #[test]
+    fn test_offer_h2_without_tls_points_at_offer_h2() {
+        let result = parse_listeners(
+            r#"
+            listeners {
+                "0.0.0.0:80" offer-h2=#true tcp-nodelay=#true
+            }
+        "#,
+        );
+
+        let err = result.unwrap_err();
+        let bad = err.downcast_ref::<crate::common_types::bad::Bad>().unwrap();
+        let err_msg = err.help().unwrap().to_string();
+
+        assert_err_contains!(err_msg, "'offer-h2' requires TLS");
+
+        let text = bad.src.inner();
+        let label = &text[bad.err_span.offset()..bad.err_span.offset() + bad.err_span.len()];
+        assert_eq!(label.trim(), "offer-h2=#true");
+    }
+
+    #[test]
+    fn test_empty_cert_path() {
+        let result = parse_listeners(
+            r#"
+            listeners {
+                "0.0.0.0:443" cert-path="" key-path="a.key"
+            }
+        "#,
+        );
+
+        let err = result.unwrap_err();
+        let bad = err.downcast_ref::<crate::common_types::bad::Bad>().unwrap();
+        let err_msg = err.help().unwrap().to_string();
+
+        assert_err_contains!(err_msg, "Value of 'cert-path' must not be empty");
+
+        let text = bad.src.inner();
+        let label = &text[bad.err_span.offset()..bad.err_span.offset() + bad.err_span.len()];
+        assert_err_contains!(label, "cert-path=\"\"");
     }
 }
@@ -1,13 +1,20 @@
+pub mod access_log;
+pub mod cache_parser;
 pub mod chain_parser;
 pub mod compiler;
 pub mod connectors;
 pub mod definitions;
+pub mod dns;
+pub mod error_pages;
 pub mod file_server;
 pub mod fs_loader;
+pub mod header_rules_parser;
 pub mod includes;
 pub mod key_profile_parser;
 pub mod listeners;
 pub mod parser;
+pub mod rate_limit_parser;
 pub mod rate_limiter;
+pub mod routes;
 pub mod services;
 pub mod system_data;
@@ -0,0 +1,191 @@
+use kdl::{KdlDocument, KdlNode};
+
+use crate::kdl::parser::ctx::{Current, ParseContext};
+
+impl ParseContext<'_> {
+    /// Text of the `//` and `/* */` comments written directly above the current node,
+    /// one entry per comment, so tooling can show what a node is documented as.
+    ///
+    /// A blank line detaches the comments before it. Returns an empty list for the
+    /// document root and for nodes without comments.
+    pub fn comments(&self) -> Vec<String> {
+        let Current::Node(node, _) = self.current else {
+            return vec![];
+        };
+
+        let mut leading = String::new();
+        // the parser keeps the comments before the first node of a block on the block itself
+        if let Some(block) = containing_block(self.doc, node) {
+            if is_first(block, node) {
+                if let Some(format) = block.format() {
+                    leading.push_str(&format.leading);
+                }
+            }
+        }
+        if let Some(format) = node.format() {
+            leading.push_str(&format.leading);
+        }
+
+        parse_comments(&leading)
+    }
+}
+
+fn is_first(block: &KdlDocument, node: &KdlNode) -> bool {
+    block
+        .nodes()
+        .first()
+        .is_some_and(|first| std::ptr::eq(first, node))
+}
+
+fn containing_block<'a>(doc: &'a KdlDocument, node: &KdlNode) -> Option<&'a KdlDocument> {
+    if doc.nodes().iter().any(|child| std::ptr::eq(child, node)) {
+        return Some(doc);
+    }
+
+    doc.nodes()
+        .iter()
+        .filter_map(|child| child.children())
+        .find_map(|children| containing_block(children, node))
+}
+
+/// A piece of the whitespace and comments KDL keeps around nodes.
+pub(crate) enum Trivia<'a> {
+    /// Whitespace, with the number of line breaks in it.
+    Space(usize),
+    /// A `//` comment, without its line break.
+    Line(&'a str),
+    /// A `/* */` comment.
+    Block(&'a str),
+    /// A slashdashed node and everything after it.
+    Slashdash(&'a str),
+}
+
+pub(crate) fn lex_trivia(text: &str) -> Vec<Trivia<'_>> {
+    let mut items = Vec::new();
+    let mut rest = text;
+
+    while !rest.is_empty() {
+        let trimmed = rest.trim_start();
+        if trimmed.len() < rest.len() {
+            let space = &rest[..rest.len() - trimmed.len()];
+            items.push(Trivia::Space(space.matches('\n').count()));
+            rest = trimmed;
+        } else if rest.starts_with("//") {
+            let end = rest.find('\n').unwrap_or(rest.len());
+            items.push(Trivia::Line(rest[..end].trim_end()));
+            rest = &rest[end..];
+        } else if let Some(body) = rest.strip_prefix("/*") {
+            let Some(end) = block_comment_end(body) else {
+                break;
+            };
+            let len = end + 4;
+            items.push(Trivia::Block(&rest[..len]));
+            rest = &rest[len..];
+        } else if rest.starts_with("/-") {
+            items.push(Trivia::Slashdash(rest.trim_end()));
+            break;
+        } else {
+            break;
+        }
+    }
+
+    items
+}
+
+/// Splits the whitespace and comments preceding a node into comment texts.
+fn parse_comments(leading: &str) -> Vec<String> {
+    let mut comments = Vec::new();
+
+    for item in lex_trivia(leading) {
+        match item {
+            Trivia::Space(lines) if lines > 1 => comments.clear(),
+            Trivia::Space(_) => {}
+            Trivia::Line(raw) => comments.push(raw[2..].trim().to_string()),
+            Trivia::Block(raw) => comments.push(raw[2..raw.len() - 2].trim().to_string()),
+            // slashdashed nodes stand between the comments and the node
+            Trivia::Slashdash(_) => comments.clear(),
+        }
+    }
+
+    comments
+}
+
+/// Offset of the `*/` closing a block comment, honoring nested `/* */`.
+fn block_comment_end(body: &str) -> Option<usize> {
+    let bytes = body.as_bytes();
+    let mut depth = 0;
+    let mut i = 0;
+
+    while i + 1 < bytes.len() {
+        match (bytes[i], bytes[i + 1]) {
+            (b'/', b'*') => {
+                depth += 1;
+                i += 2;
+            }
+            (b'*', b'/') if depth == 0 => return Some(i),
+            (b'*', b'/') => {
+                depth -= 1;
+                i += 2;
+            }
+            _ => i += 1,
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_comments_carried_to_nodes() {
+        let doc: KdlDocument = r#"
+            // edge listeners
+            listeners {
+                // this listener is for the public API
+                "0.0.0.0:443"
+
+                // detached
+
+                /* internal
+                   only */
+                "127.0.0.1:8080"
+                "127.0.0.1:9090"
+            }
+        "#
+        .parse()
+        .unwrap();
+        let ctx = ParseContext::new(&doc, Current::Document(&doc), "test");
+
+        let listeners = ctx.nodes().unwrap().remove(0);
+        assert_eq!(listeners.comments(), vec!["edge listeners"]);
+
+        let block = listeners.enter_block().unwrap();
+        let comments = block
+            .nodes()
+            .unwrap()
+            .iter()
+            .map(|node| node.comments())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            comments,
+            vec![
+                vec!["this listener is for the public API".to_string()],
+                vec!["internal\n                   only".to_string()],
+                vec![],
+            ]
+        );
+        assert!(ctx.comments().is_empty());
+    }
+
+    #[test]
+    fn test_parse_comments() {
+        assert_eq!(
+            parse_comments("\n  // one\n  /* two /* nested */ */\n  "),
+            vec!["one", "two /* nested */"]
+        );
+        assert!(parse_comments("\n  // stale\n\n  ").is_empty());
+    }
+}
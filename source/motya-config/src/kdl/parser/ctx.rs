@@ -6,16 +6,36 @@ use std::{
     fmt::Debug,
     ops::{Range, RangeFrom, RangeFull, RangeTo},
     str::FromStr,
+    sync::{Arc, Mutex, OnceLock},
     vec::IntoIter,
 };
+use url::Url;
 
-use crate::{common_types::bad::Bad, kdl::parser::typed_value::TypedValue};
+use crate::{
+    common_types::bad::{Bad, Warning},
+    kdl::parser::typed_value::TypedValue,
+};
 
 #[derive(Debug, Clone)]
 pub struct ParseContext<'a> {
     pub doc: &'a KdlDocument,
     pub source_name: &'a str,
     pub current: Current<'a>,
+    /// Where [`ParseContext::warn`] records warnings, shared by every context derived from this one.
+    pub warnings: Option<&'a Warnings>,
+    /// The text of `doc`, rendered on the first [`ParseContext::source_text`] call and shared
+    /// by every context derived from this one.
+    source: Arc<OnceLock<String>>,
+}
+
+/// Collects the warnings emitted while parsing.
+#[derive(Debug, Default)]
+pub struct Warnings(Mutex<Vec<Warning>>);
+
+impl Warnings {
+    pub fn into_inner(self) -> Vec<Warning> {
+        self.0.into_inner().unwrap_or_else(|e| e.into_inner())
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -31,24 +51,34 @@ impl<'a> ParseContext<'a> {
             doc,
             source_name,
             current,
+            warnings: None,
+            source: Arc::default(),
+        }
+    }
+
+    /// Makes [`ParseContext::warn`] record into `warnings`, for this context and its children.
+    pub fn with_warnings(self, warnings: &'a Warnings) -> Self {
+        Self {
+            warnings: Some(warnings),
+            ..self
         }
     }
 
     /// Creates a new context for the child block's content.
     /// Returns an error if the block does not exist.
     pub fn enter_block(&self) -> Result<ParseContext<'a>> {
+        self.try_enter_block()?
+            .ok_or_else(|| self.error("Expected a children block { ... }, but none found"))
+    }
+
+    /// Like [`Self::enter_block`], but for blocks that may be omitted.
+    /// Returns `Ok(None)` if the node has no children block.
+    pub fn try_enter_block(&self) -> Result<Option<ParseContext<'a>>> {
         match &self.current {
-            Current::Node(node, _) => {
-                let children = node.children().ok_or_else(|| {
-                    self.error("Expected a children block { ... }, but none found")
-                })?;
-
-                Ok(ParseContext::new(
-                    self.doc,
-                    Current::Document(children),
-                    self.source_name,
-                ))
-            }
+            Current::Node(node, _) => Ok(node.children().map(|children| ParseContext {
+                current: Current::Document(children),
+                ..self.clone()
+            })),
             Current::Document(_) => {
                 Err(self.error("Cannot enter block: current context is already a document root"))
             }
@@ -72,6 +102,22 @@ impl<'a> ParseContext<'a> {
         Bad::docspan(msg.into(), self.doc, &self.current_span(), self.source_name).into()
     }
 
+    /// Records a warning pointing to the current span, without failing the parse.
+    ///
+    /// Without a collector, see [`ParseContext::with_warnings`], the warning is only logged.
+    pub fn warn(&self, msg: impl Into<String>) {
+        let warning = Warning::docspan(msg, self.doc, &self.current_span(), self.source_name);
+
+        match self.warnings {
+            Some(warnings) => warnings
+                .0
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .push(warning),
+            None => tracing::warn!("{}: {}", self.source_name, warning.warning),
+        }
+    }
+
     /// Returns the source span of the current element (Node or Document).
     pub fn current_span(&self) -> SourceSpan {
         match &self.current {
@@ -80,6 +126,37 @@ impl<'a> ParseContext<'a> {
         }
     }
 
+    /// Returns the source span of the property `name` of the current node, like
+    /// `offer-h2=#true`, to point an error at it with [`ParseContext::error_with_span`].
+    pub fn span_of_prop(&self, name: &str) -> Option<SourceSpan> {
+        self.args()
+            .ok()?
+            .iter()
+            .find(|entry| entry.name().map(|n| n.value()) == Some(name))
+            .map(KdlEntry::span)
+    }
+
+    /// The source text of the current node, like `server "localhost"`, or the whole source
+    /// at the document root.
+    ///
+    /// The whitespace around a node is left out. Documents built in code rather than parsed
+    /// have no source positions, so their nodes yield an empty string.
+    pub fn source_text(&self) -> &str {
+        let source = self.source.get_or_init(|| self.doc.to_string());
+
+        if let Current::Document(doc) = &self.current {
+            if std::ptr::eq(*doc, self.doc) {
+                return source;
+            }
+        }
+
+        let span = self.current_span();
+        source
+            .get(span.offset()..span.offset() + span.len())
+            .unwrap_or_default()
+            .trim()
+    }
+
     /// Returns the name of the current node (e.g., "server" in `server "localhost"`).
     /// Returns an error if the context is the Document root.
     pub fn name(&self) -> Result<&str> {
@@ -120,6 +197,82 @@ impl<'a> ParseContext<'a> {
             .collect())
     }
 
+    /// Counts the child nodes with the given `name`.
+    pub fn count_nodes(&self, name: &str) -> Result<usize> {
+        let nodes = self.nodes()?;
+        let mut count = 0;
+        for node in &nodes {
+            if node.name()? == name {
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    /// Returns the single child node with the given `name`.
+    /// Errors, pointing at the block, if it is missing or repeated.
+    pub fn req_exactly_one<'b>(&self, name: &str) -> Result<ParseContext<'b>>
+    where
+        'a: 'b,
+    {
+        let mut matching = self.all_children(name)?;
+
+        match matching.len() {
+            1 => Ok(matching.pop().unwrap()),
+            0 => Err(self.error(format!("Missing required directive '{name}'"))),
+            count => Err(self.error(format!(
+                "Directive '{name}' must appear exactly once, found {count}"
+            ))),
+        }
+    }
+
+    /// Returns the first child node with the given `name`, or `None` if there is none.
+    /// Any further ones are ignored, with a warning pointing at each.
+    pub fn child<'b>(&self, name: &str) -> Result<Option<ParseContext<'b>>>
+    where
+        'a: 'b,
+    {
+        let mut first = None;
+        for node in self.nodes()? {
+            if node.name()? != name {
+                continue;
+            }
+            if first.is_none() {
+                first = Some(node);
+            } else {
+                node.warn(format!(
+                    "Directive '{name}' is repeated, only the first one is used"
+                ));
+            }
+        }
+
+        Ok(first)
+    }
+
+    /// Every child node with the given `name`, in source order, for checks like
+    /// "at most 3 of these" that [`Self::child`] and `BlockParser::repeated` don't make.
+    pub fn all_children<'b>(&self, name: &str) -> Result<Vec<ParseContext<'b>>>
+    where
+        'a: 'b,
+    {
+        let mut matching = Vec::new();
+        for node in self.nodes()? {
+            if node.name()? == name {
+                matching.push(node);
+            }
+        }
+        Ok(matching)
+    }
+
+    /// Like [`Self::child`], but errors, pointing at the block, if the child is missing.
+    pub fn req_child<'b>(&self, name: &str) -> Result<ParseContext<'b>>
+    where
+        'a: 'b,
+    {
+        self.child(name)?
+            .ok_or_else(|| self.error(format!("Missing required directive '{name}'")))
+    }
+
     /// Asserts that the current node has a specific name.
     pub fn expect_name(&self, expected: &str) -> Result<()> {
         match &self.current {
@@ -139,6 +292,24 @@ impl<'a> ParseContext<'a> {
         }
     }
 
+    /// Asserts that the current node is named one of `alternatives`, returning the name it has.
+    /// Meant for a directive that goes by several names, like a renamed one and its old name.
+    pub fn expect_one_of(&self, alternatives: &[&str]) -> Result<&str> {
+        let name = self.name()?;
+        if alternatives.contains(&name) {
+            return Ok(name);
+        }
+
+        let expected = alternatives
+            .iter()
+            .map(|alternative| format!("'{alternative}'"))
+            .collect::<Vec<_>>();
+        Err(self.error(format!(
+            "Expected one of {}, found '{name}'",
+            expected.join(", ")
+        )))
+    }
+
     /// Returns the raw slice of arguments/entries for the current node.
     pub fn args(&self) -> Result<&[KdlEntry]> {
         match &self.current {
@@ -169,6 +340,8 @@ impl<'a> ParseContext<'a> {
     where
         R: SliceRange<[KdlEntry]>,
     {
+        self.ensure_no_duplicate_keys()?;
+
         let args = self.args()?;
         let sliced = range
             .slice(args)
@@ -199,6 +372,14 @@ impl<'a> ParseContext<'a> {
         FQDN::from_str(&str).map_err(|err| self.error(format!("Invalid FQDN '{str}': {err}")))
     }
 
+    /// Retrieves a required named property and parses it as a URL whose scheme is one of
+    /// `allowed_schemes`, like `&["http", "https"]`.
+    ///
+    /// Errors point at the property.
+    pub fn parse_url_arg(&self, name: &str, allowed_schemes: &[&str]) -> Result<Url> {
+        self.prop(name)?.as_url(name, allowed_schemes)
+    }
+
     /// Checks if the current node has an attached children block (e.g., `{ ... }`).
     pub fn has_children_block(&self) -> Result<bool> {
         match &self.current {
@@ -301,3 +482,607 @@ impl<V> HashMapValidationExt for HashMap<&str, V> {
         Ok(self)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        assert_err_contains,
+        kdl::parser::{ensures::Rule, typed_value::Entry, utils::OptionTypedValueExt},
+    };
+    use miette::Diagnostic;
+
+    fn doc(input: &str) -> KdlDocument {
+        input.parse().unwrap()
+    }
+
+    #[test]
+    fn test_count_nodes() {
+        let doc = doc(r#"
+            key "a"
+            key "b"
+            algorithm name="xxhash64"
+        "#);
+        let ctx = ParseContext::new(&doc, Current::Document(&doc), "test");
+
+        assert_eq!(ctx.count_nodes("key").unwrap(), 2);
+        assert_eq!(ctx.count_nodes("algorithm").unwrap(), 1);
+        assert_eq!(ctx.count_nodes("transforms-order").unwrap(), 0);
+    }
+
+    #[test]
+    fn test_source_text() {
+        let input = r#"
+services {
+    Api {
+        listeners { "127.0.0.1:8080"; }
+        connectors { proxy "http://127.0.0.1:8000"; }
+    }
+}
+"#;
+        let doc = doc(input);
+        let ctx = ParseContext::new(&doc, Current::Document(&doc), "test");
+        assert_eq!(ctx.source_text(), input);
+
+        let services = ctx.req_exactly_one("services").unwrap();
+        let api = services
+            .enter_block()
+            .unwrap()
+            .req_exactly_one("Api")
+            .unwrap();
+        let connectors = api.enter_block().unwrap().req_child("connectors").unwrap();
+
+        assert_eq!(
+            connectors.source_text(),
+            r#"connectors { proxy "http://127.0.0.1:8000"; }"#
+        );
+        assert_eq!(
+            api.source_text(),
+            r#"Api {
+        listeners { "127.0.0.1:8080"; }
+        connectors { proxy "http://127.0.0.1:8000"; }
+    }"#
+        );
+    }
+
+    #[test]
+    fn test_req_exactly_one_zero() {
+        let doc = doc(r#"algorithm name="xxhash64""#);
+        let ctx = ParseContext::new(&doc, Current::Document(&doc), "test");
+
+        let err_msg = ctx
+            .req_exactly_one("key")
+            .unwrap_err()
+            .help()
+            .unwrap()
+            .to_string();
+        assert_err_contains!(err_msg, "Missing required directive 'key'");
+    }
+
+    #[test]
+    fn test_req_exactly_one_one() {
+        let doc = doc(r#"key "a""#);
+        let ctx = ParseContext::new(&doc, Current::Document(&doc), "test");
+
+        let key = ctx.req_exactly_one("key").unwrap();
+        assert_eq!(key.first().unwrap().as_str().unwrap(), "a");
+    }
+
+    #[test]
+    fn test_req_exactly_one_two() {
+        let doc = doc(r#"
+            key "a"
+            key "b"
+        "#);
+        let ctx = ParseContext::new(&doc, Current::Document(&doc), "test");
+
+        let err_msg = ctx
+            .req_exactly_one("key")
+            .unwrap_err()
+            .help()
+            .unwrap()
+            .to_string();
+        assert_err_contains!(err_msg, "Directive 'key' must appear exactly once, found 2");
+    }
+
+    #[test]
+    fn test_expect_one_of() {
+        let doc = doc(r#"
+            key-source "${uri_path}"
+            algorithm name="xxhash64"
+        "#);
+        let ctx = ParseContext::new(&doc, Current::Document(&doc), "test");
+        let nodes = ctx.nodes().unwrap();
+
+        assert_eq!(
+            nodes[0].expect_one_of(&["key", "key-source"]).unwrap(),
+            "key-source"
+        );
+
+        let err = nodes[1].expect_one_of(&["key", "key-source"]).unwrap_err();
+        assert_err_contains!(
+            err.help().unwrap().to_string(),
+            "Expected one of 'key', 'key-source', found 'algorithm'"
+        );
+        let span = err.labels().unwrap().next().unwrap();
+        assert_eq!(span.offset(), nodes[1].current_span().offset());
+    }
+
+    #[test]
+    fn test_span_of_prop() {
+        let input = r#"
+            listener "0.0.0.0:80" offer-h2=#true backlog=128
+        "#;
+        let doc = doc(input);
+        let ctx = ParseContext::new(&doc, Current::Document(&doc), "test");
+        let node = &ctx.nodes().unwrap()[0];
+
+        let span = node
+            .span_of_prop("offer-h2")
+            .expect("the property is present");
+        assert_eq!(
+            input[span.offset()..span.offset() + span.len()].trim(),
+            "offer-h2=#true"
+        );
+        assert_eq!(node.span_of_prop("cert-path"), None);
+        // arguments have no name
+        assert_eq!(node.span_of_prop("0.0.0.0:80"), None);
+    }
+
+    #[test]
+    fn test_child_present() {
+        let doc = doc(r#"
+            key "a"
+            algorithm name="xxhash64"
+        "#);
+        let ctx = ParseContext::new(&doc, Current::Document(&doc), "test");
+
+        let key = ctx.child("key").unwrap().expect("Should find 'key'");
+        assert_eq!(key.first().unwrap().as_str().unwrap(), "a");
+
+        let key = ctx.req_child("key").unwrap();
+        assert_eq!(key.first().unwrap().as_str().unwrap(), "a");
+    }
+
+    #[test]
+    fn test_child_absent() {
+        let doc = doc(r#"algorithm name="xxhash64""#);
+        let ctx = ParseContext::new(&doc, Current::Document(&doc), "test");
+
+        assert!(ctx.child("key").unwrap().is_none());
+
+        let err_msg = ctx
+            .req_child("key")
+            .unwrap_err()
+            .help()
+            .unwrap()
+            .to_string();
+        assert_err_contains!(err_msg, "Missing required directive 'key'");
+    }
+
+    #[test]
+    fn test_all_children_in_order() {
+        let doc = doc(r#"
+            server "a"
+            weight 10
+            server "b"
+            server "c"
+        "#);
+        let ctx = ParseContext::new(&doc, Current::Document(&doc), "test");
+
+        let servers = ctx.all_children("server").unwrap();
+        let values: Vec<String> = servers
+            .iter()
+            .map(|s| s.first().unwrap().as_str().unwrap())
+            .collect();
+        assert_eq!(values, ["a", "b", "c"]);
+
+        assert!(ctx.all_children("missing").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_child_duplicate_takes_first() {
+        let input = r#"
+            key "a"
+            key "b"
+        "#;
+        let doc = doc(input);
+        let warnings = Warnings::default();
+        let ctx = ParseContext::new(&doc, Current::Document(&doc), "test").with_warnings(&warnings);
+
+        let key = ctx.child("key").unwrap().expect("Should find 'key'");
+        assert_eq!(key.first().unwrap().as_str().unwrap(), "a");
+
+        let warnings = warnings.into_inner();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(
+            warnings[0].help().unwrap().to_string(),
+            "Directive 'key' is repeated, only the first one is used"
+        );
+        let span = warnings[0].labels().unwrap().next().unwrap();
+        let labeled = &input[span.offset()..span.offset() + span.len()];
+        assert_eq!(labeled.trim(), r#"key "b""#);
+    }
+
+    #[test]
+    fn test_args_map_rejects_duplicate_keys() {
+        let doc = doc(r#"algorithm name="xxhash64" name="xxhash32""#);
+        let ctx = ParseContext::new(&doc, Current::Document(&doc), "test");
+        let node = ctx.req_exactly_one("algorithm").unwrap();
+
+        let err_msg = node.args_map(..).unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(err_msg, "Duplicate configuration key: 'name'");
+    }
+
+    #[test]
+    fn test_prop_rejects_repeated_name() {
+        let input = r#"upstream-method to="POST" to="GET""#;
+        let doc = doc(input);
+        let ctx = ParseContext::new(&doc, Current::Document(&doc), "test");
+        let node = ctx.req_exactly_one("upstream-method").unwrap();
+
+        for err in [
+            node.prop("to").err().unwrap(),
+            node.opt_prop("to").err().unwrap(),
+        ] {
+            assert_err_contains!(
+                err.help().unwrap().to_string(),
+                "Duplicate configuration key: 'to'"
+            );
+            let span = err.labels().unwrap().next().unwrap();
+            assert_eq!(
+                input[span.offset()..span.offset() + span.len()].trim(),
+                r#"to="GET""#
+            );
+        }
+    }
+
+    #[test]
+    fn test_try_enter_block_present() {
+        let doc = doc(r#"tls { sni "example.com"; }"#);
+        let ctx = ParseContext::new(&doc, Current::Document(&doc), "test");
+        let node = ctx.req_exactly_one("tls").unwrap();
+
+        let block = node.try_enter_block().unwrap().expect("block should exist");
+        assert_eq!(block.count_nodes("sni").unwrap(), 1);
+    }
+
+    #[test]
+    fn test_try_enter_block_absent() {
+        let doc = doc(r#"tls"#);
+        let ctx = ParseContext::new(&doc, Current::Document(&doc), "test");
+        let node = ctx.req_exactly_one("tls").unwrap();
+
+        assert!(node.try_enter_block().unwrap().is_none());
+
+        let err_msg = node.enter_block().unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(err_msg, "Expected a children block");
+    }
+
+    #[test]
+    fn test_try_enter_block_on_document() {
+        let doc = doc(r#"tls"#);
+        let ctx = ParseContext::new(&doc, Current::Document(&doc), "test");
+
+        let err_msg = ctx
+            .try_enter_block()
+            .unwrap_err()
+            .help()
+            .unwrap()
+            .to_string();
+        assert_err_contains!(err_msg, "current context is already a document root");
+    }
+
+    #[test]
+    fn test_first_opt() {
+        let doc = doc(r#"
+            truncate "256"
+            truncate length="256"
+        "#);
+        let ctx = ParseContext::new(&doc, Current::Document(&doc), "test");
+        let nodes = ctx.nodes().unwrap();
+
+        let positional = nodes[0].first_opt().unwrap().expect("positional arg");
+        assert_eq!(positional.as_str().unwrap(), "256");
+
+        assert!(nodes[1].first_opt().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_valid_port() {
+        let doc = doc(r#"
+            health-check port=8080
+            health-check port=0
+            health-check port=70000
+        "#);
+        let ctx = ParseContext::new(&doc, Current::Document(&doc), "test");
+        let nodes = ctx.nodes().unwrap();
+        let validate = |node: &ParseContext<'_>| node.validate(&[Rule::ValidPort(&["port"])]);
+
+        assert!(validate(&nodes[0]).is_ok());
+
+        for (node, value) in [(&nodes[1], "0"), (&nodes[2], "70000")] {
+            let err = validate(node).unwrap_err();
+            assert_err_contains!(
+                err.help().unwrap().to_string(),
+                format!("Value of 'port' must be between 1 and 65535, found {value}").as_str()
+            );
+
+            let span = err.labels().unwrap().next().unwrap();
+            let text = doc.to_string();
+            assert_eq!(
+                text[span.offset()..span.offset() + span.len()].trim(),
+                format!("port={value}")
+            );
+        }
+    }
+
+    #[test]
+    fn test_as_u16() {
+        let doc = doc(r#"
+            health-check port=8080
+            health-check port=70000
+            health-check port=-1
+        "#);
+        let ctx = ParseContext::new(&doc, Current::Document(&doc), "test");
+        let nodes = ctx.nodes().unwrap();
+
+        assert_eq!(nodes[0].prop("port").unwrap().as_u16().unwrap(), 8080);
+
+        for (node, value) in [(&nodes[1], "70000"), (&nodes[2], "-1")] {
+            let err = node.prop("port").unwrap().as_u16().unwrap_err();
+            assert_err_contains!(
+                err.help().unwrap().to_string(),
+                format!("value {value} out of range for u16").as_str()
+            );
+
+            let span = err.labels().unwrap().next().unwrap();
+            let text = doc.to_string();
+            assert_eq!(
+                text[span.offset()..span.offset() + span.len()].trim(),
+                format!("port={value}")
+            );
+        }
+    }
+
+    #[test]
+    fn test_as_u32() {
+        let doc = doc(r#"
+            limit max=4294967295
+            limit max=4294967296
+            limit
+        "#);
+        let ctx = ParseContext::new(&doc, Current::Document(&doc), "test");
+        let nodes = ctx.nodes().unwrap();
+
+        assert_eq!(nodes[0].prop("max").unwrap().as_u32().unwrap(), u32::MAX);
+
+        let err = nodes[1].prop("max").unwrap().as_u32().unwrap_err();
+        assert_err_contains!(
+            err.help().unwrap().to_string(),
+            "value 4294967296 out of range for u32"
+        );
+
+        assert_eq!(nodes[2].opt_prop("max").unwrap().as_u32().unwrap(), None);
+    }
+
+    #[test]
+    fn test_parse_url_arg() {
+        let doc = doc(r#"webhook target="https://hooks.example.com:8443/notify?x=1""#);
+        let ctx = ParseContext::new(&doc, Current::Document(&doc), "test");
+        let node = ctx.req_exactly_one("webhook").unwrap();
+
+        let url = node.parse_url_arg("target", &["http", "https"]).unwrap();
+        assert_eq!(url.scheme(), "https");
+        assert_eq!(url.host_str(), Some("hooks.example.com"));
+        assert_eq!(url.port(), Some(8443));
+        assert_eq!(url.path(), "/notify");
+    }
+
+    #[test]
+    fn test_parse_url_arg_errors() {
+        let cases = [
+            (
+                r#"webhook name="a" target="ftp://files.example.com""#,
+                "Scheme 'ftp' is not allowed for 'target', expected one of: http, https",
+            ),
+            (
+                r#"webhook name="a" target="not a url""#,
+                "Invalid URL 'not a url' for 'target'",
+            ),
+        ];
+
+        for (input, message) in cases {
+            let doc = doc(input);
+            let ctx = ParseContext::new(&doc, Current::Document(&doc), "test");
+            let node = ctx.req_exactly_one("webhook").unwrap();
+
+            let err = node
+                .parse_url_arg("target", &["http", "https"])
+                .unwrap_err();
+            assert_err_contains!(err.help().unwrap().to_string(), message);
+
+            // the label covers the property, not the whole node
+            let span = err.labels().unwrap().next().unwrap();
+            let labeled = &input[span.offset()..span.offset() + span.len()];
+            assert!(labeled.trim().starts_with("target="), "labeled {labeled:?}");
+        }
+    }
+
+    #[test]
+    fn test_parse_socket_addr_list() {
+        let doc = doc(r#"listen "127.0.0.1:8080" "[::1]:443""#);
+        let ctx = ParseContext::new(&doc, Current::Document(&doc), "test");
+        let node = ctx.req_exactly_one("listen").unwrap();
+
+        assert_eq!(
+            node.parse_socket_addr_list().unwrap(),
+            vec![
+                "127.0.0.1:8080".parse().unwrap(),
+                "[::1]:443".parse().unwrap()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_socket_addr_list_errors() {
+        let cases = [
+            (
+                r#"listen "127.0.0.1:8080" "127.0.0.1" "0.0.0.0:80""#,
+                "'127.0.0.1' is not a valid socket address",
+                r#""127.0.0.1:8080""#,
+                r#""127.0.0.1""#,
+            ),
+            (
+                r#"listen "127.0.0.1:8080" "0.0.0.0:80" "127.0.0.1:8080""#,
+                "Duplicate address '127.0.0.1:8080'",
+                r#""0.0.0.0:80""#,
+                r#""127.0.0.1:8080""#,
+            ),
+        ];
+
+        for (input, message, before, entry) in cases {
+            let doc = doc(input);
+            let ctx = ParseContext::new(&doc, Current::Document(&doc), "test");
+            let node = ctx.req_exactly_one("listen").unwrap();
+
+            let err = node.parse_socket_addr_list().unwrap_err();
+            assert_err_contains!(err.help().unwrap().to_string(), message);
+
+            // the label covers the offending entry, not the whole node
+            let span = err.labels().unwrap().next().unwrap();
+            let labeled = &input[span.offset()..span.offset() + span.len()];
+            assert_eq!(labeled.trim(), entry);
+            assert!(span.offset() >= input.find(before).unwrap() + before.len());
+        }
+    }
+
+    #[test]
+    fn test_interpolate_secret_files() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("token.txt"), "  s3cret\n").unwrap();
+        let absolute = dir.path().join("token.txt");
+        let source_name = dir.path().join("motya.kdl").to_string_lossy().to_string();
+
+        let input = format!(
+            r#"secret relative="${{FILE:token.txt}}" absolute="key=${{FILE:{}}}""#,
+            absolute.display()
+        );
+        let doc = doc(&input);
+        let ctx = ParseContext::new(&doc, Current::Document(&doc), &source_name);
+        let node = ctx.req_exactly_one("secret").unwrap();
+
+        let relative = node
+            .prop("relative")
+            .unwrap()
+            .as_str_interpolated()
+            .unwrap();
+        assert_eq!(relative, "s3cret");
+        let absolute = node
+            .prop("absolute")
+            .unwrap()
+            .as_str_interpolated()
+            .unwrap();
+        assert_eq!(absolute, "key=s3cret");
+    }
+
+    #[test]
+    fn test_interpolated_env_value_is_not_rescanned() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("token.txt"), "s3cret").unwrap();
+        let source_name = dir.path().join("motya.kdl").to_string_lossy().to_string();
+        std::env::set_var("MOTYA_TEST_INTERPOLATED_REFERENCE", "${FILE:token.txt}");
+
+        let input = r#"secret value="${ENV:MOTYA_TEST_INTERPOLATED_REFERENCE}""#;
+        let doc = doc(input);
+        let ctx = ParseContext::new(&doc, Current::Document(&doc), &source_name);
+        let node = ctx.req_exactly_one("secret").unwrap();
+
+        let value = node.prop("value").unwrap().as_str_interpolated().unwrap();
+        assert_eq!(value, "${FILE:token.txt}");
+    }
+
+    #[test]
+    fn test_interpolate_missing_secret_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let source_name = dir.path().join("motya.kdl").to_string_lossy().to_string();
+
+        let input = r#"secret name="api" value="${FILE:missing.txt}""#;
+        let doc = doc(input);
+        let ctx = ParseContext::new(&doc, Current::Document(&doc), &source_name);
+        let node = ctx.req_exactly_one("secret").unwrap();
+
+        let err = node
+            .prop("value")
+            .unwrap()
+            .as_str_interpolated()
+            .unwrap_err();
+        assert_err_contains!(
+            err.help().unwrap().to_string(),
+            "failed to read secret file"
+        );
+        assert_err_contains!(err.help().unwrap().to_string(), "missing.txt");
+
+        let span = err.labels().unwrap().next().unwrap();
+        let labeled = &input[span.offset()..span.offset() + span.len()];
+        assert_eq!(labeled.trim(), r#"value="${FILE:missing.txt}""#);
+    }
+
+    #[test]
+    fn test_warn_is_recorded_without_failing() {
+        let input = r#"
+            listeners {
+                "0.0.0.0:443" offer-h2=#true
+            }
+        "#;
+        let doc = doc(input);
+        let warnings = Warnings::default();
+        let ctx = ParseContext::new(&doc, Current::Document(&doc), "test").with_warnings(&warnings);
+
+        let parse = |ctx: &ParseContext| -> Result<usize> {
+            let listeners = ctx.req_exactly_one("listeners")?.enter_block()?;
+            let listener = listeners.nodes()?.remove(0);
+            listener.warn("offer-h2 is redundant with alpn listing h2");
+            Ok(listeners.nodes()?.len())
+        };
+
+        assert_eq!(parse(&ctx).unwrap(), 1);
+
+        let warnings = warnings.into_inner();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(
+            warnings[0].help().unwrap().to_string(),
+            "offer-h2 is redundant with alpn listing h2"
+        );
+        assert_eq!(warnings[0].severity(), Some(miette::Severity::Warning));
+
+        let span = warnings[0].labels().unwrap().next().unwrap();
+        let labeled = &input[span.offset()..span.offset() + span.len()];
+        assert_eq!(labeled.trim(), r#""0.0.0.0:443" offer-h2=#true"#);
+    }
+
+    #[test]
+    fn test_entries_keep_source_order() {
+        let doc = doc(r#"rule "first" key="a" 2 weight=10"#);
+        let ctx = ParseContext::new(&doc, Current::Document(&doc), "test");
+        let node = ctx.req_exactly_one("rule").unwrap();
+
+        let entries = node.entries().unwrap();
+        let shape = entries
+            .iter()
+            .map(|entry| (entry.name(), entry.value().as_string_lossy().unwrap()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            shape,
+            vec![
+                (None, "first".to_string()),
+                (Some("key"), "a".to_string()),
+                (None, "2".to_string()),
+                (Some("weight"), "10".to_string()),
+            ]
+        );
+        assert!(matches!(entries[0], Entry::Positional(_)));
+        assert!(matches!(entries[1], Entry::Named("key", _)));
+    }
+}
@@ -1,11 +1,11 @@
-use std::{net::SocketAddr, str::FromStr};
+use std::str::FromStr;
 
-use kdl::KdlValue;
+use kdl::{KdlEntry, KdlValue};
 use miette::Result;
 
 use crate::kdl::parser::{
     ctx::ParseContext,
-    utils::{get_kdl_type_name, PrimitiveType},
+    utils::{get_kdl_type_name, parse_socket_addr, PrimitiveType},
 };
 
 /// Defines validation constraints that can be applied to a KDL node.
@@ -22,6 +22,20 @@ pub enum Rule<'a> {
     ExactArgs(usize),
     Name(NamePredicate),
     OnlyKeysTyped(&'a [(&'a str, PrimitiveType)]),
+    /// A named property may appear at most once.
+    NoDuplicateKeys,
+    /// If the named property is present, it must be an integer within `min..=max`.
+    IntRange {
+        key: &'a str,
+        min: i128,
+        max: i128,
+    },
+    /// The named string properties, when present, must not be empty or whitespace-only.
+    NonEmptyString(&'a [&'a str]),
+    /// Like [`Rule::OnlyKeysTyped`], but other keys are allowed and left unchecked.
+    KeysTyped(&'a [(&'a str, PrimitiveType)]),
+    /// The named integer properties, when present, must be a port in `1..=65535`.
+    ValidPort(&'a [&'a str]),
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -44,6 +58,11 @@ impl<'a> ParseContext<'a> {
                 Rule::OnlyKeysTyped(schema) => self.ensure_only_keys_typed(schema)?,
                 Rule::ExactArgs(n) => self.ensure_positional_args(*n, *n)?,
                 Rule::ReqChildren => self.ensure_req_children()?,
+                Rule::NoDuplicateKeys => self.ensure_no_duplicate_keys()?,
+                Rule::IntRange { key, min, max } => self.ensure_int_range(key, *min, *max)?,
+                Rule::NonEmptyString(keys) => self.ensure_non_empty_strings(keys)?,
+                Rule::KeysTyped(schema) => self.ensure_keys_typed(schema)?,
+                Rule::ValidPort(keys) => self.ensure_valid_ports(keys)?,
             }
         }
         Ok(())
@@ -64,24 +83,152 @@ impl<'a> ParseContext<'a> {
                             allowed_keys
                         )));
                     }
-                    Some((_, expected_type)) => {
-                        let value = arg.value();
-                        let is_valid = matches!(
-                            (expected_type, value),
-                            (PrimitiveType::String, KdlValue::String(_))
-                                | (PrimitiveType::Integer, KdlValue::Integer(_))
-                                | (PrimitiveType::Float, KdlValue::Float(_))
-                                | (PrimitiveType::Bool, KdlValue::Bool(_))
-                                | (PrimitiveType::Null, KdlValue::Null)
-                        );
-
-                        if !is_valid {
-                            let actual_type = get_kdl_type_name(value);
-                            return Err(self.error(format!(
-                                "Invalid type for key '{key}'. Expected {expected_type}, found {actual_type}"
-                            )));
-                        }
-                    }
+                    Some((_, expected_type)) => self.ensure_entry_type(key, arg, expected_type)?,
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Enforces the types of the properties in `schema` that are present.
+    pub fn ensure_keys_typed(&self, schema: &[(&str, PrimitiveType)]) -> Result<()> {
+        for arg in self.args()? {
+            let Some(key) = arg.name().map(|n| n.value()) else {
+                continue;
+            };
+
+            if let Some((_, expected_type)) = schema.iter().find(|(k, _)| *k == key) {
+                self.ensure_entry_type(key, arg, expected_type)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn ensure_entry_type(
+        &self,
+        key: &str,
+        arg: &KdlEntry,
+        expected_type: &PrimitiveType,
+    ) -> Result<()> {
+        let value = arg.value();
+        let is_valid = matches!(
+            (expected_type, value),
+            (PrimitiveType::String, KdlValue::String(_))
+                | (PrimitiveType::Integer, KdlValue::Integer(_))
+                | (PrimitiveType::Float, KdlValue::Float(_))
+                | (PrimitiveType::Bool, KdlValue::Bool(_))
+                | (PrimitiveType::Null, KdlValue::Null)
+        );
+
+        if !is_valid {
+            let actual_type = get_kdl_type_name(value);
+            return Err(self.error(format!(
+                "Invalid type for key '{key}'. Expected {expected_type}, found {actual_type}"
+            )));
+        }
+        Ok(())
+    }
+
+    /// Enforces that no named property is repeated, pointing at the duplicate.
+    pub fn ensure_no_duplicate_keys(&self) -> Result<()> {
+        let args = self.args()?;
+
+        for (i, arg) in args.iter().enumerate() {
+            let Some(key) = arg.name().map(|n| n.value()) else {
+                continue;
+            };
+
+            if args[..i]
+                .iter()
+                .any(|prev| prev.name().map(|n| n.value()) == Some(key))
+            {
+                return Err(self
+                    .error_with_span(format!("Duplicate configuration key: '{key}'"), arg.span()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Enforces that the property `key`, when present, is an integer in `min..=max`.
+    pub fn ensure_int_range(&self, key: &str, min: i128, max: i128) -> Result<()> {
+        let Some(entry) = self
+            .args()?
+            .iter()
+            .find(|e| e.name().map(|n| n.value()) == Some(key))
+        else {
+            return Ok(());
+        };
+
+        match entry.value() {
+            KdlValue::Integer(value) if (min..=max).contains(value) => Ok(()),
+            KdlValue::Integer(value) => Err(self.error_with_span(
+                format!("Value of '{key}' must be between {min} and {max}, found {value}"),
+                entry.span(),
+            )),
+            other => Err(self.error_with_span(
+                format!(
+                    "Invalid type for key '{key}'. Expected {}, found {}",
+                    PrimitiveType::Integer,
+                    get_kdl_type_name(other)
+                ),
+                entry.span(),
+            )),
+        }
+    }
+
+    /// Enforces that the integer properties `keys`, when present, are usable port numbers.
+    pub fn ensure_valid_ports(&self, keys: &[&str]) -> Result<()> {
+        for entry in self.args()? {
+            let Some(key) = entry.name().map(|n| n.value()) else {
+                continue;
+            };
+
+            if !keys.contains(&key) {
+                continue;
+            }
+
+            match entry.value() {
+                KdlValue::Integer(value) if (1..=u16::MAX as i128).contains(value) => {}
+                KdlValue::Integer(value) => {
+                    return Err(self.error_with_span(
+                        format!(
+                            "Value of '{key}' must be between 1 and 65535, found {value}, which is not a valid port"
+                        ),
+                        entry.span(),
+                    ))
+                }
+                other => {
+                    return Err(self.error_with_span(
+                        format!(
+                            "Invalid type for key '{key}'. Expected {}, found {}",
+                            PrimitiveType::Integer,
+                            get_kdl_type_name(other)
+                        ),
+                        entry.span(),
+                    ))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Enforces that the string properties `keys`, when present, have visible content.
+    pub fn ensure_non_empty_strings(&self, keys: &[&str]) -> Result<()> {
+        for entry in self.args()? {
+            let Some(key) = entry.name().map(|n| n.value()) else {
+                continue;
+            };
+
+            if !keys.contains(&key) {
+                continue;
+            }
+
+            if let KdlValue::String(value) = entry.value() {
+                if value.trim().is_empty() {
+                    return Err(self.error_with_span(
+                        format!("Value of '{key}' must not be empty"),
+                        entry.span(),
+                    ));
                 }
             }
         }
@@ -93,11 +240,7 @@ impl<'a> ParseContext<'a> {
 
         match predicate {
             NamePredicate::SocketAddr => {
-                if name.parse::<SocketAddr>().is_err() {
-                    return Err(self.error(format!(
-                        "'{name}' is not a valid socket address. Expected format: 'IP:PORT' (e.g., '127.0.0.1:8080')"
-                    )));
-                }
+                parse_socket_addr(name).map_err(|err| self.error(err))?;
             }
             NamePredicate::FQDN => {
                 if fqdn::FQDN::from_str(name).is_err() {
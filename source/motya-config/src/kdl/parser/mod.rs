@@ -1,4 +1,5 @@
 pub mod block;
+pub mod comments;
 pub mod ctx;
 pub mod ensures;
 pub mod typed_name;
@@ -1,9 +1,15 @@
-use std::{fmt::Display, str::FromStr};
+use std::{fmt::Display, net::SocketAddr, path::Path, str::FromStr, time::Duration};
 
 use kdl::{KdlEntry, KdlValue};
 use miette::Result;
+use url::Url;
 
-use crate::kdl::parser::{ctx::ParseContext, utils::get_simple_type_name};
+use crate::kdl::parser::{
+    ctx::ParseContext,
+    utils::{
+        get_simple_type_name, interpolate, parse_byte_size, parse_duration, parse_socket_addr,
+    },
+};
 
 #[derive(Clone, Copy)]
 pub struct TypedValue<'a> {
@@ -29,6 +35,19 @@ impl<'a> TypedValue<'a> {
             })
     }
 
+    /// Like [`TypedValue::as_str`], with `${ENV:NAME}` references replaced by
+    /// the value of the environment variable and `${FILE:PATH}` references by the
+    /// contents of the file. Relative paths start from the config file's directory.
+    pub fn as_str_interpolated(self) -> Result<String> {
+        let raw_str = self.as_str()?;
+        let base = Path::new(self.ctx.source_name)
+            .parent()
+            .unwrap_or_else(|| Path::new(""));
+
+        interpolate(&raw_str, base, |name| std::env::var(name).ok())
+            .map_err(|e| self.ctx.error_with_span(e, self.entry.span()))
+    }
+
     pub fn as_usize(self) -> Result<usize> {
         self.entry
             .value()
@@ -45,6 +64,51 @@ impl<'a> TypedValue<'a> {
             })
     }
 
+    /// An integer that fits in a `u16`, such as a port.
+    pub fn as_u16(self) -> Result<u16> {
+        self.as_bounded_int("u16")
+    }
+
+    /// An integer that fits in a `u32`.
+    pub fn as_u32(self) -> Result<u32> {
+        self.as_bounded_int("u32")
+    }
+
+    /// An integer that fits in an `i32`, negative ones included.
+    pub fn as_i32(self) -> Result<i32> {
+        self.as_bounded_int("i32")
+    }
+
+    fn as_bounded_int<T: TryFrom<i128>>(self, type_name: &str) -> Result<T> {
+        let Some(value) = self.entry.value().as_integer() else {
+            return Err(self.ctx.error_with_span(
+                format!("Expected an integer, found {:?}", self.entry.value()),
+                self.entry.span(),
+            ));
+        };
+
+        T::try_from(value).map_err(|_| {
+            self.ctx.error_with_span(
+                format!("value {value} out of range for {type_name}"),
+                self.entry.span(),
+            )
+        })
+    }
+
+    /// An integer within `min..=max`, `key` being the name the error reports it under.
+    pub fn as_int_in_range(self, key: &str, min: i128, max: i128) -> Result<i128> {
+        match self.entry.value().as_integer() {
+            Some(value) if (min..=max).contains(&value) => Ok(value),
+            Some(value) => Err(self.error(format!(
+                "Value of '{key}' must be between {min} and {max}, found {value}"
+            ))),
+            None => Err(self.error(format!(
+                "Expected an integer, found {:?}",
+                self.entry.value()
+            ))),
+        }
+    }
+
     pub fn as_bool(self) -> Result<bool> {
         self.entry.value().as_bool().ok_or_else(|| {
             self.ctx.error_with_span(
@@ -54,6 +118,49 @@ impl<'a> TypedValue<'a> {
         })
     }
 
+    pub fn as_duration(self) -> Result<Duration> {
+        let raw_str = self.as_str()?;
+        parse_duration(&raw_str).map_err(|e| {
+            self.ctx.error_with_span(
+                format!("Invalid duration '{raw_str}'. Reason: {e}"),
+                self.entry.span(),
+            )
+        })
+    }
+
+    pub fn as_byte_size(self) -> Result<usize> {
+        let raw_str = self.as_str()?;
+        parse_byte_size(&raw_str).map_err(|e| {
+            self.ctx.error_with_span(
+                format!("Invalid size '{raw_str}'. Reason: {e}"),
+                self.entry.span(),
+            )
+        })
+    }
+
+    pub fn as_socket_addr(self) -> Result<SocketAddr> {
+        let raw_str = self.as_str()?;
+        parse_socket_addr(&raw_str).map_err(|e| self.ctx.error_with_span(e, self.entry.span()))
+    }
+
+    /// A URL with one of the `allowed_schemes`, `name` being the key the error reports it under.
+    pub fn as_url(self, name: &str, allowed_schemes: &[&str]) -> Result<Url> {
+        let raw = self.as_str()?;
+
+        let url = Url::parse(&raw)
+            .map_err(|err| self.error(format!("Invalid URL '{raw}' for '{name}': {err}")))?;
+
+        if !allowed_schemes.contains(&url.scheme()) {
+            return Err(self.error(format!(
+                "Scheme '{}' is not allowed for '{name}', expected one of: {}",
+                url.scheme(),
+                allowed_schemes.join(", ")
+            )));
+        }
+
+        Ok(url)
+    }
+
     pub fn parse_as<T>(self) -> Result<T>
     where
         T: FromStr,
@@ -69,6 +176,11 @@ impl<'a> TypedValue<'a> {
         })
     }
 
+    /// Generates an error pointing at this value.
+    pub fn error(self, msg: impl Into<String>) -> miette::Error {
+        self.ctx.error_with_span(msg, self.entry.span())
+    }
+
     pub fn as_string_lossy(self) -> Result<String> {
         match self.entry.value() {
             KdlValue::String(s) => Ok(s.clone()),
@@ -84,7 +196,48 @@ impl<'a> TypedValue<'a> {
     }
 }
 
+/// A single argument of a node, either positional or a named property.
+#[derive(Clone, Copy)]
+pub enum Entry<'a> {
+    Positional(TypedValue<'a>),
+    Named(&'a str, TypedValue<'a>),
+}
+
+impl<'a> Entry<'a> {
+    /// The property name, or `None` for a positional argument.
+    pub fn name(&self) -> Option<&'a str> {
+        match self {
+            Entry::Positional(_) => None,
+            Entry::Named(name, _) => Some(name),
+        }
+    }
+
+    pub fn value(&self) -> TypedValue<'a> {
+        match self {
+            Entry::Positional(value) | Entry::Named(_, value) => *value,
+        }
+    }
+}
+
 impl<'a> ParseContext<'a> {
+    /// Every argument of the current node, positional and named, in source order.
+    pub fn entries<'b>(&'a self) -> Result<Vec<Entry<'b>>>
+    where
+        'a: 'b,
+    {
+        Ok(self
+            .args()?
+            .iter()
+            .map(|entry| {
+                let value = TypedValue::new(self, entry);
+                match entry.name() {
+                    Some(name) => Entry::Named(name.value(), value),
+                    None => Entry::Positional(value),
+                }
+            })
+            .collect())
+    }
+
     pub fn first<'b>(&'a self) -> Result<TypedValue<'b>>
     where
         'a: 'b,
@@ -97,6 +250,37 @@ impl<'a> ParseContext<'a> {
         Ok(TypedValue::new(self, entry))
     }
 
+    /// The first positional argument, or `None` when the node only has named ones.
+    pub fn first_opt<'b>(&'a self) -> Result<Option<TypedValue<'b>>>
+    where
+        'a: 'b,
+    {
+        Ok(self
+            .args()?
+            .iter()
+            .find(|e| e.name().is_none())
+            .map(|entry| TypedValue::new(self, entry)))
+    }
+
+    /// Every positional argument parsed as a socket address, rejecting repeated ones.
+    ///
+    /// Errors point at the offending entry.
+    pub fn parse_socket_addr_list(&'a self) -> Result<Vec<SocketAddr>> {
+        let mut addrs: Vec<SocketAddr> = Vec::new();
+
+        for entry in self.args()?.iter().filter(|e| e.name().is_none()) {
+            let value = TypedValue::new(self, entry);
+            let addr = value.as_socket_addr()?;
+
+            if addrs.contains(&addr) {
+                return Err(value.error(format!("Duplicate address '{addr}'")));
+            }
+            addrs.push(addr);
+        }
+
+        Ok(addrs)
+    }
+
     pub fn arg<'b>(&'a self, index: usize) -> Result<TypedValue<'b>>
     where
         'a: 'b,
@@ -121,9 +305,7 @@ impl<'a> ParseContext<'a> {
         'a: 'b,
     {
         let entry = self
-            .args()?
-            .iter()
-            .find(|e| e.name().map(|n| n.value()) == Some(key))
+            .find_prop(key)?
             .ok_or_else(|| self.error(format!("Missing required property '{}'", key)))?;
 
         Ok(TypedValue::new(self, entry))
@@ -133,11 +315,27 @@ impl<'a> ParseContext<'a> {
     where
         'a: 'b,
     {
-        let entry = self
+        let entry = self.find_prop(key)?;
+
+        Ok(entry.map(|e| TypedValue::new(self, e)))
+    }
+
+    /// The property `key` of the node, an error pointing at its second occurrence when it
+    /// is given more than once.
+    fn find_prop(&'a self, key: &str) -> Result<Option<&'a KdlEntry>> {
+        let mut entries = self
             .args()?
             .iter()
-            .find(|e| e.name().map(|n| n.value()) == Some(key));
+            .filter(|e| e.name().map(|n| n.value()) == Some(key));
 
-        Ok(entry.map(|e| TypedValue::new(self, e)))
+        let first = entries.next();
+        if let Some(repeated) = entries.next() {
+            return Err(self.error_with_span(
+                format!("Duplicate configuration key: '{key}'"),
+                repeated.span(),
+            ));
+        }
+
+        Ok(first)
     }
 }
@@ -1,7 +1,15 @@
-use std::{any::type_name, fmt::Display, str::FromStr};
+use std::{
+    any::type_name,
+    fmt::Display,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
 
 use kdl::KdlValue;
 use miette::Result;
+use path_clean::PathClean;
 
 use crate::kdl::parser::typed_value::TypedValue;
 
@@ -10,6 +18,11 @@ pub trait OptionTypedValueExt {
     fn as_str(self) -> Result<Option<String>>;
     fn as_bool(self) -> Result<Option<bool>>;
     fn as_usize(self) -> Result<Option<usize>>;
+    fn as_u16(self) -> Result<Option<u16>>;
+    fn as_u32(self) -> Result<Option<u32>>;
+    fn as_i32(self) -> Result<Option<i32>>;
+    fn as_duration(self) -> Result<Option<Duration>>;
+    fn as_byte_size(self) -> Result<Option<usize>>;
     fn parse_as<T>(self) -> Result<Option<T>>
     where
         T: FromStr,
@@ -37,6 +50,42 @@ impl<'a> OptionTypedValueExt for Option<TypedValue<'a>> {
             None => Ok(None),
         }
     }
+
+    fn as_u16(self) -> Result<Option<u16>> {
+        match self {
+            Some(v) => Ok(Some(v.as_u16()?)),
+            None => Ok(None),
+        }
+    }
+
+    fn as_u32(self) -> Result<Option<u32>> {
+        match self {
+            Some(v) => Ok(Some(v.as_u32()?)),
+            None => Ok(None),
+        }
+    }
+
+    fn as_i32(self) -> Result<Option<i32>> {
+        match self {
+            Some(v) => Ok(Some(v.as_i32()?)),
+            None => Ok(None),
+        }
+    }
+
+    fn as_duration(self) -> Result<Option<Duration>> {
+        match self {
+            Some(v) => Ok(Some(v.as_duration()?)),
+            None => Ok(None),
+        }
+    }
+
+    fn as_byte_size(self) -> Result<Option<usize>> {
+        match self {
+            Some(v) => Ok(Some(v.as_byte_size()?)),
+            None => Ok(None),
+        }
+    }
+
     fn parse_as<T>(self) -> Result<Option<T>>
     where
         T: FromStr,
@@ -86,3 +135,126 @@ pub fn get_kdl_type_name(val: &KdlValue) -> &'static str {
         KdlValue::Null => "Null",
     }
 }
+
+/// Parses an `IP:PORT` socket address such as `"127.0.0.1:8080"` or `"[::1]:443"`.
+pub fn parse_socket_addr(value: &str) -> std::result::Result<SocketAddr, String> {
+    value.parse().map_err(|_| {
+        format!(
+            "'{value}' is not a valid socket address. Expected format: 'IP:PORT' (e.g., '127.0.0.1:8080')"
+        )
+    })
+}
+
+/// Parses a duration such as `"500ms"`, `"30s"`, `"5m"` or `"1h"`.
+pub fn parse_duration(value: &str) -> std::result::Result<Duration, String> {
+    let value = value.trim();
+    let split_at = value
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(|| "missing unit, expected one of 'ms', 's', 'm', 'h'".to_string())?;
+
+    let (amount, unit) = value.split_at(split_at);
+
+    let amount: u64 = amount
+        .parse()
+        .map_err(|_| "expected a number followed by a unit (e.g. '30s')".to_string())?;
+
+    match unit {
+        "ms" => Ok(Duration::from_millis(amount)),
+        "s" => Ok(Duration::from_secs(amount)),
+        "m" => Ok(Duration::from_secs(amount * 60)),
+        "h" => Ok(Duration::from_secs(amount * 60 * 60)),
+        other => Err(format!(
+            "unknown unit '{other}', expected one of 'ms', 's', 'm', 'h'"
+        )),
+    }
+}
+
+/// Parses a size such as `"512B"`, `"64KB"`, `"100MB"` or `"1GB"` into bytes.
+///
+/// Units are binary multiples, so `"1KB"` is 1024 bytes. `KiB`, `MiB` and `GiB` are
+/// accepted as the same units.
+pub fn parse_byte_size(value: &str) -> std::result::Result<usize, String> {
+    let value = value.trim();
+    let split_at = value
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(|| "missing unit, expected one of 'B', 'KB', 'MB', 'GB'".to_string())?;
+
+    let (amount, unit) = value.split_at(split_at);
+
+    let amount: usize = amount
+        .parse()
+        .map_err(|_| "expected a number followed by a unit (e.g. '100MB')".to_string())?;
+
+    let multiplier: usize = match unit.to_ascii_uppercase().as_str() {
+        "B" => 1,
+        "KB" | "KIB" => 1 << 10,
+        "MB" | "MIB" => 1 << 20,
+        "GB" | "GIB" => 1 << 30,
+        other => {
+            return Err(format!(
+                "unknown unit '{other}', expected one of 'B', 'KB', 'MB', 'GB'"
+            ))
+        }
+    };
+
+    amount
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("'{value}' is too large"))
+}
+
+const ENV_PREFIX: &str = "${ENV:";
+const FILE_PREFIX: &str = "${FILE:";
+
+/// Replaces every `${ENV:NAME}` in `value` with the result of `lookup("NAME")`, and every
+/// `${FILE:PATH}` with the trimmed contents of the file, so secrets can live outside the
+/// config. Relative paths are resolved against `base`.
+///
+/// Both are replaced in a single pass, so substituted text is never scanned again: an
+/// environment variable holding `${FILE:...}` is taken as is. Other `${...}` sequences are
+/// left untouched, they belong to key templates.
+pub fn interpolate(
+    value: &str,
+    base: &Path,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> std::result::Result<String, String> {
+    let mut out = String::with_capacity(value.len());
+    let mut rest = value;
+
+    while let Some(start) = rest.find("${") {
+        out.push_str(&rest[..start]);
+        let reference = &rest[start..];
+
+        let Some((prefix, after)) = [ENV_PREFIX, FILE_PREFIX]
+            .into_iter()
+            .find_map(|prefix| Some((prefix, reference.strip_prefix(prefix)?)))
+        else {
+            out.push_str("${");
+            rest = &reference[2..];
+            continue;
+        };
+
+        let end = after
+            .find('}')
+            .ok_or_else(|| format!("unclosed '{prefix}' in '{value}'"))?;
+        let name = &after[..end];
+
+        let resolved = if prefix == ENV_PREFIX {
+            lookup(name).ok_or_else(|| format!("environment variable '{name}' is not set"))?
+        } else {
+            let path = normalize_path(base, name);
+            std::fs::read_to_string(&path)
+                .map(|contents| contents.trim().to_string())
+                .map_err(|err| format!("failed to read secret file '{}': {err}", path.display()))?
+        };
+        out.push_str(&resolved);
+
+        rest = &after[end + 1..];
+    }
+
+    out.push_str(rest);
+    Ok(out)
+}
+
+pub fn normalize_path(base: &Path, relative: &str) -> PathBuf {
+    base.join(relative).clean()
+}
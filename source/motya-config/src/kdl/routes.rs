@@ -0,0 +1,367 @@
+use http::HeaderName;
+use motya_macro::validate;
+
+use crate::{
+    block_parser,
+    common_types::{
+        connectors::ConnectorGroups,
+        routes::{
+            HeaderMatch, QueryMatch, RouteGroup, RouteSelector, SplitRouteConfig, SplitTarget,
+            StickyKey,
+        },
+        section_parser::SectionParser,
+    },
+    kdl::{
+        connectors::parse_request_timeout,
+        parser::{ctx::ParseContext, ensures::Rule, typed_value::Entry, utils::PrimitiveType},
+    },
+};
+
+/// Parses a `route path="..." { split ... }` or `route path="..." { match-header ... }`
+/// block of a service.
+pub struct RouteSection<'a> {
+    connector_groups: &'a ConnectorGroups,
+}
+
+impl SectionParser<ParseContext<'_>, SplitRouteConfig> for RouteSection<'_> {
+    #[validate(ensure_node_name = "route")]
+    fn parse_node(&self, ctx: ParseContext<'_>) -> miette::Result<SplitRouteConfig> {
+        ctx.validate(&[
+            Rule::ReqChildren,
+            Rule::NoPositionalArgs,
+            Rule::OnlyKeysTyped(&[
+                ("path", PrimitiveType::String),
+                ("query", PrimitiveType::String),
+            ]),
+        ])?;
+
+        let path_value = ctx.prop("path")?;
+        let path = path_value.as_str()?;
+
+        if !path.starts_with('/') {
+            return Err(path_value.error(format!("Route path '{path}' must start with '/'")));
+        }
+
+        let query = ctx
+            .opt_prop("query")?
+            .map(|query| query.parse_as::<QueryMatch>())
+            .transpose()?;
+
+        let block_ctx = ctx.enter_block()?;
+
+        block_parser!(block_ctx,
+            targets: optional("split") => |ctx| self.parse_split(ctx),
+            sticky: optional("sticky") => |ctx| self.parse_sticky(ctx),
+            match_header: optional("match-header") => |ctx| self.parse_match_header(ctx),
+            request_timeout: optional("request-timeout") => parse_request_timeout
+        );
+
+        let selector = match (targets, sticky, match_header) {
+            (Some(targets), sticky, None) => RouteSelector::Split { targets, sticky },
+            (None, None, Some(header_match)) => RouteSelector::MatchHeader(header_match),
+            (Some(_), _, Some(_)) => {
+                return Err(ctx.error("'route' accepts either 'split' or 'match-header', not both"))
+            }
+            (None, Some(_), Some(_)) => {
+                return Err(ctx.error(
+                    "'sticky' only applies to 'split', 'match-header' is always deterministic",
+                ))
+            }
+            (None, _, None) => {
+                return Err(ctx.error("'route' requires either 'split' or 'match-header'"))
+            }
+        };
+
+        Ok(SplitRouteConfig {
+            path,
+            selector,
+            query,
+            request_timeout,
+        })
+    }
+}
+
+impl<'a> RouteSection<'a> {
+    pub fn new(connector_groups: &'a ConnectorGroups) -> Self {
+        Self { connector_groups }
+    }
+
+    fn parse_split(&self, ctx: ParseContext<'_>) -> miette::Result<Vec<SplitTarget>> {
+        ctx.validate(&[
+            Rule::NoChildren,
+            Rule::NoPositionalArgs,
+            Rule::NoDuplicateKeys,
+        ])?;
+
+        let mut targets = Vec::new();
+        let mut total = 0;
+
+        for entry in ctx.entries()? {
+            let Entry::Named(group, value) = entry else {
+                continue;
+            };
+
+            let weight = value.as_usize()?;
+            if weight > 100 {
+                return Err(value.error(format!(
+                    "Weight of '{group}' must be between 0 and 100, found {weight}"
+                )));
+            }
+
+            let connectors = self
+                .connector_groups
+                .get(group)
+                .cloned()
+                .ok_or_else(|| value.error(format!("Connector group '{group}' not found")))?;
+
+            total += weight;
+            targets.push(SplitTarget {
+                group: group.to_string(),
+                weight: weight as u8,
+                connectors,
+            });
+        }
+
+        if total != 100 {
+            return Err(ctx.error(format!("Split weights must sum to 100, found {total}")));
+        }
+
+        Ok(targets)
+    }
+
+    fn parse_match_header(&self, ctx: ParseContext<'_>) -> miette::Result<HeaderMatch> {
+        ctx.validate(&[Rule::ReqChildren, Rule::ExactArgs(1), Rule::OnlyKeys(&[])])?;
+
+        let header = ctx.first()?.parse_as::<HeaderName>()?;
+
+        let mut cases: Vec<(String, RouteGroup)> = Vec::new();
+        let mut default = None;
+
+        for case_ctx in ctx.req_nodes()? {
+            case_ctx.validate(&[
+                Rule::NoChildren,
+                Rule::NoPositionalArgs,
+                Rule::OnlyKeysTyped(&[("connector", PrimitiveType::String)]),
+            ])?;
+
+            let connector = case_ctx.prop("connector")?;
+            let group = connector.as_str()?;
+            let route_group = RouteGroup {
+                connectors: self.connector_groups.get(&group).cloned().ok_or_else(|| {
+                    connector.error(format!("Connector group '{group}' not found"))
+                })?,
+                group,
+            };
+
+            let value = case_ctx.name()?;
+            if value == "default" {
+                if default.replace(route_group).is_some() {
+                    return Err(
+                        case_ctx.error("Only one 'default' case is allowed in 'match-header'")
+                    );
+                }
+            } else if cases.iter().any(|(known, _)| known == value) {
+                return Err(case_ctx.error(format!(
+                    "Duplicate header value '{value}' in 'match-header'"
+                )));
+            } else {
+                cases.push((value.to_string(), route_group));
+            }
+        }
+
+        let default = default.ok_or_else(|| {
+            ctx.error("'match-header' requires a 'default' case for unmatched requests")
+        })?;
+
+        Ok(HeaderMatch {
+            header,
+            cases,
+            default,
+        })
+    }
+
+    fn parse_sticky(&self, ctx: ParseContext<'_>) -> miette::Result<StickyKey> {
+        ctx.validate(&[
+            Rule::NoChildren,
+            Rule::NoPositionalArgs,
+            Rule::OnlyKeysTyped(&[
+                ("header", PrimitiveType::String),
+                ("cookie", PrimitiveType::String),
+            ]),
+        ])?;
+
+        match (ctx.opt_prop("header")?, ctx.opt_prop("cookie")?) {
+            (Some(header), None) => Ok(StickyKey::Header(header.parse_as::<HeaderName>()?)),
+            (None, Some(cookie)) => Ok(StickyKey::Cookie(cookie.as_str()?)),
+            _ => Err(ctx.error("'sticky' requires exactly one of 'header' or 'cookie'")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use kdl::KdlDocument;
+
+    use super::*;
+    use crate::{
+        assert_err_contains,
+        common_types::{connectors::Connectors, definitions_table::DefinitionsTable},
+        kdl::parser::{block::BlockParser, ctx::Current},
+    };
+
+    fn parse_route(input: &str) -> miette::Result<SplitRouteConfig> {
+        let mut groups = ConnectorGroups::default();
+        for name in ["stable", "canary"] {
+            groups.insert(
+                name.to_string(),
+                Connectors {
+                    upstreams: vec![],
+                    anonymous_definitions: DefinitionsTable::default(),
+                },
+            );
+        }
+
+        let doc: KdlDocument = input.parse().unwrap();
+        let ctx = ParseContext::new(&doc, Current::Document(&doc), "test");
+        let mut block = BlockParser::new(ctx)?;
+
+        block.required("route", |ctx| RouteSection::new(&groups).parse_node(ctx))
+    }
+
+    #[test]
+    fn test_parse_route() {
+        let route = parse_route(
+            r#"
+            route path="/" {
+                split stable=90 canary=10
+                sticky cookie="uid"
+            }
+        "#,
+        )
+        .expect("Should parse route");
+
+        assert_eq!(route.path, "/");
+        let RouteSelector::Split { targets, sticky } = route.selector else {
+            panic!("expected a split");
+        };
+        let targets = targets
+            .iter()
+            .map(|t| (t.group.as_str(), t.weight))
+            .collect::<Vec<_>>();
+        assert_eq!(targets, vec![("stable", 90), ("canary", 10)]);
+        assert_eq!(sticky, Some(StickyKey::Cookie("uid".to_string())));
+    }
+
+    #[test]
+    fn test_request_timeout() {
+        let route = parse_route(
+            r#"
+            route path="/reports" {
+                split stable=100
+                request-timeout "60s"
+            }
+        "#,
+        )
+        .expect("Should parse route");
+        assert_eq!(route.request_timeout, Some(Duration::from_secs(60)));
+
+        let route = parse_route(r#"route path="/" { split stable=100; }"#).unwrap();
+        assert_eq!(route.request_timeout, None);
+
+        let result = parse_route(r#"route path="/" { split stable=100; request-timeout "0s"; }"#);
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(err_msg, "'request-timeout' must be positive");
+    }
+
+    #[test]
+    fn test_query() {
+        let route = parse_route(r#"route path="/api" query="version=2" { split canary=100; }"#)
+            .expect("Should parse route");
+        assert_eq!(
+            route.query,
+            Some(QueryMatch {
+                name: "version".to_string(),
+                value: Some("2".to_string()),
+            })
+        );
+
+        let route = parse_route(r#"route path="/api" query="beta" { split canary=100; }"#)
+            .expect("Should parse route");
+        assert_eq!(
+            route.query,
+            Some(QueryMatch {
+                name: "beta".to_string(),
+                value: None,
+            })
+        );
+
+        let result = parse_route(r#"route path="/api" query="=2" { split canary=100; }"#);
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(err_msg, "expected 'name' or 'name=value'");
+    }
+
+    #[test]
+    fn test_error_weights_sum() {
+        let result = parse_route(r#"route path="/" { split stable=90 canary=20; }"#);
+
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(err_msg, "Split weights must sum to 100, found 110");
+    }
+
+    #[test]
+    fn test_error_unknown_group() {
+        let result = parse_route(r#"route path="/" { split stable=90 beta=10; }"#);
+
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(err_msg, "Connector group 'beta' not found");
+    }
+
+    #[test]
+    fn test_parse_match_header() {
+        let route = parse_route(
+            r#"
+            route path="/" {
+                match-header "X-Variant" {
+                    "beta" connector="canary"
+                    default connector="stable"
+                }
+            }
+        "#,
+        )
+        .expect("Should parse route");
+
+        let RouteSelector::MatchHeader(header_match) = route.selector else {
+            panic!("expected a header match");
+        };
+        assert_eq!(header_match.header, "x-variant");
+        let cases = header_match
+            .cases
+            .iter()
+            .map(|(value, target)| (value.as_str(), target.group.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(cases, vec![("beta", "canary")]);
+        assert_eq!(header_match.default.group, "stable");
+    }
+
+    #[test]
+    fn test_error_match_header_without_default() {
+        let result = parse_route(
+            r#"route path="/" { match-header "X-Variant" { "beta" connector="canary"; }; }"#,
+        );
+
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(err_msg, "'match-header' requires a 'default' case");
+    }
+
+    #[test]
+    fn test_error_match_header_unknown_group() {
+        let result = parse_route(
+            r#"route path="/" { match-header "X-Variant" { "beta" connector="beta"; default connector="stable"; }; }"#,
+        );
+
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(err_msg, "Connector group 'beta' not found");
+    }
+}
@@ -1,16 +1,29 @@
+use std::{collections::HashMap, sync::Mutex};
+
 use motya_macro::validate;
 
 use crate::common_types::{
-    definitions_table::DefinitionsTable, file_server::FileServerConfig, listeners::Listeners,
-    section_parser::SectionParser, services::ServicesConfig,
+    access_log::AccessLogFormat,
+    connectors::{ConnectorGroups, Connectors, UpstreamProtocol},
+    definitions_table::DefinitionsTable,
+    dns::DnsResolver,
+    error_pages::ErrorPages,
+    file_server::FileServerConfig,
+    listeners::{ListenerKind, Listeners},
+    routes::SplitRouteConfig,
+    section_parser::SectionParser,
+    services::ServicesConfig,
 };
 use crate::{
     internal::ProxyConfig,
     kdl::{
+        access_log::AccessLogSection,
         connectors::ConnectorsSection,
+        error_pages::ErrorPagesSection,
         file_server::FileServerSection,
         listeners::ListenersSection,
-        parser::{block::BlockParser, ctx::ParseContext},
+        parser::{block::BlockParser, ctx::ParseContext, ensures::Rule},
+        routes::RouteSection,
     },
 };
 
@@ -22,6 +35,11 @@ pub enum ServiceConfig {
 
 pub struct ServicesSection<'a> {
     global_definitions: &'a DefinitionsTable,
+    connector_groups: &'a ConnectorGroups,
+    resolver: Option<&'a DnsResolver>,
+    /// Services by the inherited fd they listen on, kept across every `services` block
+    /// parsed, as only one listener can own an fd.
+    fd_owners: Mutex<HashMap<i32, String>>,
 }
 
 impl SectionParser<ParseContext<'_>, ServicesConfig> for ServicesSection<'_> {
@@ -32,8 +50,21 @@ impl SectionParser<ParseContext<'_>, ServicesConfig> for ServicesSection<'_> {
 }
 
 impl<'a> ServicesSection<'a> {
-    pub fn new(global_definitions: &'a DefinitionsTable) -> Self {
-        Self { global_definitions }
+    pub fn new(
+        global_definitions: &'a DefinitionsTable,
+        connector_groups: &'a ConnectorGroups,
+    ) -> Self {
+        Self {
+            global_definitions,
+            connector_groups,
+            resolver: None,
+            fd_owners: Mutex::default(),
+        }
+    }
+
+    /// Resolves the upstream host names of the services with the `dns` servers.
+    pub fn with_resolver(self, resolver: Option<&'a DnsResolver>) -> Self {
+        Self { resolver, ..self }
     }
 
     pub fn parse(&self, ctx: ParseContext) -> miette::Result<ServicesConfig> {
@@ -41,7 +72,24 @@ impl<'a> ServicesSection<'a> {
         let mut file_servers: Vec<FileServerConfig> = vec![];
 
         for node in ctx.nodes()? {
-            match self.parse_service(node)? {
+            let service = self.parse_service(node.clone())?;
+            let (name, listeners) = match &service {
+                ServiceConfig::Proxy(proxy) => (&proxy.name, &proxy.listeners),
+                ServiceConfig::FileServer(fs) => (&fs.name, &fs.listeners),
+            };
+            for fd in listeners
+                .list_cfgs
+                .iter()
+                .filter_map(|cfg| cfg.source.inherited_fd())
+            {
+                if let Some(owner) = self.fd_owners.lock().unwrap().insert(fd, name.clone()) {
+                    return Err(node.error(format!(
+                        "Inherited fd {fd} is already used by service '{owner}'"
+                    )));
+                }
+            }
+
+            match service {
                 ServiceConfig::FileServer(fs) => file_servers.push(fs),
                 ServiceConfig::Proxy(proxy) => proxies.push(proxy),
             }
@@ -58,31 +106,127 @@ impl<'a> ServicesSection<'a> {
         let mut block = BlockParser::new(service_ctx.clone())?;
 
         let listeners = block.required("listeners", |ctx| ListenersSection.parse_node(ctx))?;
-
-        let service_type =
-            block.required_any(&["connectors", "file-server"], |ctx, name| match name {
-                "connectors" => self.parse_proxy(ctx, listeners, &service_name),
-                "file-server" => self.parse_file_server(ctx, listeners, &service_name),
+        let error_pages = block.optional("error-pages", |ctx| {
+            Ok((ErrorPagesSection.parse_node(ctx.clone())?, ctx))
+        })?;
+        let access_log = block.optional("access-log", |ctx| {
+            Ok((AccessLogSection.parse_node(ctx.clone())?, ctx))
+        })?;
+        let routes = block.repeated("route", |ctx| {
+            Ok((
+                RouteSection::new(self.connector_groups).parse_node(ctx.clone())?,
+                ctx,
+            ))
+        })?;
+
+        let service_type = block.required_any(
+            &["connectors", "use-connectors", "file-server"],
+            |ctx, name| match name {
+                "connectors" | "use-connectors" => {
+                    let connectors = if name == "connectors" {
+                        ConnectorsSection::new(self.global_definitions)
+                            .with_resolver(self.resolver)
+                            .parse_node(ctx)?
+                    } else {
+                        self.resolve_connectors(ctx)?
+                    };
+                    let error_pages = error_pages.map(|(pages, _)| pages).unwrap_or_default();
+                    let access_log = access_log.map(|(format, _)| format).unwrap_or_default();
+                    let routes = routes.into_iter().map(|(route, _)| route).collect();
+                    self.parse_proxy(
+                        &service_ctx,
+                        connectors,
+                        listeners,
+                        error_pages,
+                        access_log,
+                        routes,
+                        &service_name,
+                    )
+                }
+                "file-server" => {
+                    if let Some((_, pages_ctx)) = error_pages {
+                        return Err(
+                            pages_ctx.error("'error-pages' is only supported by proxy services")
+                        );
+                    }
+                    if let Some((_, log_ctx)) = access_log {
+                        return Err(
+                            log_ctx.error("'access-log' is only supported by proxy services")
+                        );
+                    }
+                    if let Some((_, route_ctx)) = routes.first() {
+                        return Err(route_ctx.error("'route' is only supported by proxy services"));
+                    }
+                    self.parse_file_server(ctx, listeners, &service_name)
+                }
                 _ => unreachable!("Guaranteed by BlockParser"),
-            })?;
+            },
+        )?;
 
         block.exhaust()?;
 
         Ok(service_type)
     }
 
+    /// Looks up the group named by `use-connectors "name"`.
+    fn resolve_connectors(&self, ctx: ParseContext<'_>) -> miette::Result<Connectors> {
+        ctx.validate(&[Rule::NoChildren, Rule::ExactArgs(1), Rule::OnlyKeys(&[])])?;
+
+        let name = ctx.first()?.as_str()?;
+
+        self.connector_groups
+            .get(&name)
+            .cloned()
+            .ok_or_else(|| ctx.error(format!("Connector group '{name}' not found")))
+    }
+
     fn parse_proxy(
         &self,
-        ctx: ParseContext<'_>,
+        ctx: &ParseContext<'_>,
+        connectors: Connectors,
         listeners: Listeners,
+        error_pages: ErrorPages,
+        access_log: AccessLogFormat,
+        routes: Vec<SplitRouteConfig>,
         service_name: &str,
     ) -> miette::Result<ServiceConfig> {
-        let connectors = ConnectorsSection::new(self.global_definitions).parse_node(ctx)?;
+        let route_upstreams = routes
+            .iter()
+            .flat_map(|route| route.selector.connectors())
+            .flat_map(|connectors| &connectors.upstreams);
+        let has_grpc = connectors
+            .upstreams
+            .iter()
+            .chain(route_upstreams)
+            .any(|upstream| upstream.protocol == UpstreamProtocol::Grpc);
+        let offers_h2 = listeners.list_cfgs.iter().any(|cfg| {
+            matches!(
+                cfg.source,
+                ListenerKind::Tcp {
+                    tls: Some(_),
+                    offer_h2: true,
+                    ..
+                } | ListenerKind::InheritedFd {
+                    tls: Some(_),
+                    offer_h2: true,
+                    ..
+                }
+            )
+        });
+        // trailers only reach the client over HTTP/2
+        if has_grpc && !offers_h2 {
+            return Err(ctx.error(format!(
+                "Service '{service_name}' has 'protocol \"grpc\"' connectors but no listener offering h2, add a TLS listener with 'offer-h2'"
+            )));
+        }
 
         Ok(ServiceConfig::Proxy(ProxyConfig {
             name: service_name.to_string(),
             listeners,
             connectors,
+            error_pages,
+            access_log,
+            routes,
         }))
     }
 
@@ -113,12 +257,13 @@ mod tests {
         let doc: KdlDocument = input.parse().unwrap();
 
         let table = DefinitionsTable::default();
+        let groups = ConnectorGroups::default();
 
         let ctx = ParseContext::new(&doc, Current::Document(&doc), "test");
         let mut block = BlockParser::new(ctx)?;
 
         block.required("services", |ctx| {
-            ServicesSection::new(&table).parse_node(ctx)
+            ServicesSection::new(&table, &groups).parse_node(ctx)
         })
     }
 
@@ -229,7 +374,80 @@ mod tests {
         let err_msg = result.unwrap_err().help().unwrap().to_string();
         assert_err_contains!(
             err_msg,
-            "Block must contain exactly one of: [\"connectors\", \"file-server\"]"
+            "Block must contain exactly one of: [\"connectors\", \"use-connectors\", \"file-server\"]"
+        );
+    }
+
+    #[test]
+    fn test_error_pages_only_for_proxies() {
+        let page = tempfile::NamedTempFile::new().unwrap();
+        let input = format!(
+            r#"
+            services {{
+                StaticFiles {{
+                    listeners {{ "127.0.0.1:8080" }}
+                    error-pages {{ "404" "{}"; }}
+                    file-server base-path="/var/www"
+                }}
+            }}
+        "#,
+            page.path().display()
         );
+
+        let result = parse_services(&input);
+
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(err_msg, "'error-pages' is only supported by proxy services");
+    }
+
+    #[test]
+    fn test_grpc_needs_h2_listener() {
+        let service = |listener: &str| {
+            format!(
+                r#"
+                services {{
+                    Grpc {{
+                        listeners {{ {listener} }}
+                        connectors {{
+                            protocol "grpc"
+                            proxy "https://127.0.0.1:50051" tls-sni="grpc.internal"
+                        }}
+                    }}
+                }}
+            "#
+            )
+        };
+
+        let config = parse_services(&service(
+            r#""0.0.0.0:443" cert-path="/etc/motya/cert.pem" key-path="/etc/motya/key.pem""#,
+        ))
+        .expect("Should parse a gRPC service behind an h2 listener");
+        assert_eq!(config.proxies.len(), 1);
+
+        let result = parse_services(&service(r#""0.0.0.0:8080""#));
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(err_msg, "no listener offering h2");
+    }
+
+    #[test]
+    fn test_inherited_fd_used_once() {
+        let result = parse_services(
+            r#"
+            services {
+                Proxy {
+                    listeners { fd 3 }
+                    connectors {
+                        return code=200 response="OK"
+                    }
+                }
+                Files {
+                    listeners { fd 3 }
+                    file-server base-path="."
+                }
+            }
+        "#,
+        );
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(err_msg, "Inherited fd 3 is already used by service 'Proxy'");
     }
 }
@@ -1,5 +1,5 @@
 use crate::block_parser;
-use crate::common_types::system_data::HttpProviderConfig;
+use crate::common_types::system_data::{AdminConfig, HttpProviderConfig, StatusConfig};
 use crate::common_types::{
     section_parser::SectionParser,
     system_data::{ConfigProvider, FilesProviderConfig, S3ProviderConfig, SystemData},
@@ -24,20 +24,35 @@ impl SectionParser<ParseContext<'_>, Option<SystemData>> for SystemDataSection {
 impl SystemDataSection {
     fn extract_system_data(&self, ctx: ParseContext) -> miette::Result<Option<SystemData>> {
         block_parser!(
-            ctx,
+            ctx.clone(),
             tps: optional("threads-per-service") => |ctx| self.parse_threads_per_service(ctx),
+            max_connections: optional("max-connections") => |ctx| self.parse_max_connections(ctx),
             daemonize: optional("daemonize") => |ctx| self.parse_daemonize(ctx),
             upgrade: optional("upgrade-socket") => |ctx| self.parse_upgrade_socket(ctx),
             pid: optional("pid-file") => |ctx| self.parse_pid_file(ctx),
-            provider: optional("providers") => |ctx| self.parse_providers(ctx)
+            provider: optional("providers") => |ctx| self.parse_providers(ctx),
+            status: optional("status") => |ctx| self.parse_status(ctx),
+            admin: optional("admin") => |ctx| self.parse_admin(ctx)
         );
 
+        if let (Some(status), Some(admin)) = (&status, &admin) {
+            if status.addr == admin.addr {
+                return Err(ctx.error(format!(
+                    "'status' and 'admin' must listen on different addresses, both use '{}'",
+                    admin.addr
+                )));
+            }
+        }
+
         Ok(Some(SystemData {
             threads_per_service: tps.unwrap_or(8),
+            max_connections,
             daemonize: daemonize.unwrap_or(false),
             upgrade_socket: upgrade,
             pid_file: pid,
             provider,
+            status,
+            admin,
         }))
     }
 
@@ -48,6 +63,19 @@ impl SystemDataSection {
         ctx.first()?.as_usize()
     }
 
+    fn parse_max_connections(&self, ctx: ParseContext<'_>) -> miette::Result<usize> {
+        ctx.validate(&[Rule::NoChildren, Rule::ExactArgs(1), Rule::OnlyKeys(&[])])?;
+
+        let max = ctx.first()?.as_usize()?;
+        if !(1..=u32::MAX as usize).contains(&max) {
+            return Err(ctx.error(format!(
+                "Value of 'max-connections' must be between 1 and {}, found {max}",
+                u32::MAX
+            )));
+        }
+        Ok(max)
+    }
+
     fn parse_daemonize(&self, ctx: ParseContext<'_>) -> miette::Result<bool> {
         ctx.validate(&[Rule::NoChildren, Rule::ExactArgs(1)])?;
         ctx.first()?.as_bool()
@@ -65,6 +93,84 @@ impl SystemDataSection {
         ctx.first()?.parse_as::<PathBuf>()
     }
 
+    fn parse_status(&self, ctx: ParseContext<'_>) -> miette::Result<StatusConfig> {
+        ctx.validate(&[Rule::ReqChildren, Rule::NoArgs])?;
+
+        let single_value = |ctx: &ParseContext<'_>| {
+            ctx.validate(&[Rule::NoChildren, Rule::ExactArgs(1), Rule::OnlyKeys(&[])])
+        };
+
+        block_parser!(
+            ctx.enter_block()?,
+            addr: required("addr") => |ctx| {
+                single_value(&ctx)?;
+                ctx.first()?.as_socket_addr()
+            },
+            path: optional("path") => |ctx| {
+                single_value(&ctx)?;
+                self.parse_status_path(&ctx, "path")
+            },
+            metrics_path: optional("metrics-path") => |ctx| {
+                single_value(&ctx)?;
+                self.parse_status_path(&ctx, "metrics-path")
+            }
+        );
+
+        let path = path.unwrap_or_else(|| PathAndQuery::from_static("/status"));
+        let metrics_path = metrics_path.unwrap_or_else(|| PathAndQuery::from_static("/metrics"));
+        if path == metrics_path {
+            return Err(ctx.error(format!(
+                "'path' and 'metrics-path' of 'status' must differ, both are '{path}'"
+            )));
+        }
+
+        Ok(StatusConfig {
+            addr,
+            path,
+            metrics_path,
+        })
+    }
+
+    fn parse_admin(&self, ctx: ParseContext<'_>) -> miette::Result<AdminConfig> {
+        ctx.validate(&[Rule::ReqChildren, Rule::NoArgs])?;
+
+        let single_value = |ctx: &ParseContext<'_>| {
+            ctx.validate(&[Rule::NoChildren, Rule::ExactArgs(1), Rule::OnlyKeys(&[])])
+        };
+
+        block_parser!(
+            ctx.enter_block()?,
+            addr: required("addr") => |ctx| {
+                single_value(&ctx)?;
+                ctx.first()?.as_socket_addr()
+            },
+            reload_token: required("reload-token") => |ctx| {
+                single_value(&ctx)?;
+                let token = ctx.first()?.as_str()?;
+                if token.trim().is_empty() {
+                    return Err(ctx.error("'reload-token' of 'admin' must not be empty"));
+                }
+                Ok(token)
+            }
+        );
+
+        Ok(AdminConfig { addr, reload_token })
+    }
+
+    fn parse_status_path(
+        &self,
+        ctx: &ParseContext<'_>,
+        name: &str,
+    ) -> miette::Result<PathAndQuery> {
+        let path = ctx.first()?.parse_as::<PathAndQuery>()?;
+        if !path.path().starts_with('/') || path.query().is_some() {
+            return Err(ctx.error(format!(
+                "'{name}' of 'status' must be an absolute path without a query, found '{path}'"
+            )));
+        }
+        Ok(path)
+    }
+
     fn parse_providers(&self, providers_ctx: ParseContext<'_>) -> miette::Result<ConfigProvider> {
         providers_ctx.validate(&[Rule::ReqChildren, Rule::NoArgs])?;
 
@@ -117,6 +223,10 @@ impl SystemDataSection {
             .unwrap_or_else(|| "60s".to_string());
 
         let endpoint = ctx.opt_prop("endpoint")?.as_str()?;
+        if endpoint.is_some() {
+            // only checked, the endpoint is kept as written
+            ctx.parse_url_arg("endpoint", &["http", "https"])?;
+        }
 
         Ok(ConfigProvider::S3(S3ProviderConfig {
             bucket,
@@ -235,6 +345,20 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_s3_provider_endpoint_scheme() {
+        let input = r#"
+        system {
+            providers {
+                s3 bucket="configs" key="prod.kdl" region="r" endpoint="ftp://minio:9000"
+            }
+        }
+        "#;
+
+        let err_msg = parse_system(input).unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(err_msg, "Scheme 'ftp' is not allowed for 'endpoint'");
+    }
+
     #[test]
     fn test_http_provider_persist() {
         let input = r#"
@@ -276,6 +400,99 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_status() {
+        let input = r#"
+        system {
+            status {
+                addr "127.0.0.1:9000"
+                path "/health/status"
+            }
+        }
+        "#;
+
+        let data = parse_system(input).expect("Should parse status");
+        let status = data.status.expect("status should be set");
+        assert_eq!(status.addr, "127.0.0.1:9000".parse().unwrap());
+        assert_eq!(status.path, "/health/status");
+
+        let data = parse_system(r#"system { status { addr "[::1]:9000"; }; }"#)
+            .expect("Should parse status without a path");
+        assert_eq!(data.status.unwrap().path, "/status");
+    }
+
+    #[test]
+    fn test_status_metrics_path() {
+        let data = parse_system(r#"system { status { addr "127.0.0.1:9000"; }; }"#).unwrap();
+        assert_eq!(data.status.unwrap().metrics_path, "/metrics");
+
+        let data =
+            parse_system(r#"system { status { addr "127.0.0.1:9000"; metrics-path "/prom"; }; }"#)
+                .unwrap();
+        assert_eq!(data.status.unwrap().metrics_path, "/prom");
+
+        let err_msg = parse_system(
+            r#"system { status { addr "127.0.0.1:9000"; path "/m"; metrics-path "/m"; }; }"#,
+        )
+        .unwrap_err()
+        .help()
+        .unwrap()
+        .to_string();
+        assert_err_contains!(err_msg, "'path' and 'metrics-path' of 'status' must differ");
+    }
+
+    #[test]
+    fn test_status_invalid_addr() {
+        let result = parse_system(r#"system { status { addr "localhost:9000"; }; }"#);
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(err_msg, "'localhost:9000' is not a valid socket address");
+
+        let result = parse_system(r#"system { status { path "/status"; }; }"#);
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(err_msg, "Missing required directive 'addr'");
+    }
+
+    #[test]
+    fn test_admin() {
+        let input = r#"
+        system {
+            admin {
+                addr "127.0.0.1:9001"
+                reload-token "secret"
+            }
+        }
+        "#;
+
+        let data = parse_system(input).expect("Should parse admin");
+        let admin = data.admin.expect("admin should be set");
+        assert_eq!(admin.addr, "127.0.0.1:9001".parse().unwrap());
+        assert_eq!(admin.reload_token, "secret");
+        // the token stays out of the config dump
+        let dump = format!("{admin:?}");
+        assert!(!dump.contains("secret"), "{dump}");
+
+        let result = parse_system(r#"system { admin { addr "127.0.0.1:9001"; }; }"#);
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(err_msg, "Missing required directive 'reload-token'");
+
+        let result =
+            parse_system(r#"system { admin { addr "127.0.0.1:9001"; reload-token " "; }; }"#);
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(err_msg, "'reload-token' of 'admin' must not be empty");
+
+        let result = parse_system(
+            r#"system {
+                status { addr "127.0.0.1:9000"; }
+                admin { addr "127.0.0.1:9000"; reload-token "secret"; }
+            }"#,
+        );
+        let err_msg = result.unwrap_err().help().unwrap().to_string();
+        assert_err_contains!(
+            err_msg,
+            "'status' and 'admin' must listen on different addresses"
+        );
+    }
+
     #[test]
     fn test_conflict_providers() {
         let input = r#"
@@ -297,4 +514,20 @@ mod tests {
             "Directive 'http' conflicts with 's3' (mutually exclusive)"
         );
     }
+
+    #[test]
+    fn test_max_connections() {
+        let data = parse_system("system { max-connections 10000 }").unwrap();
+        assert_eq!(data.max_connections, Some(10000));
+
+        let data = parse_system("system { threads-per-service 2 }").unwrap();
+        assert_eq!(data.max_connections, None);
+
+        let err_msg = parse_system("system { max-connections 0 }")
+            .unwrap_err()
+            .help()
+            .unwrap()
+            .to_string();
+        assert_err_contains!(err_msg, "Value of 'max-connections' must be between 1 and");
+    }
 }
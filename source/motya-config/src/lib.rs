@@ -1,8 +1,12 @@
+pub mod builder;
 pub mod cli;
 pub mod common_types;
 pub mod config_source;
+pub mod diff;
+pub mod explain;
 pub mod internal;
 pub mod kdl;
 pub mod legacy;
+pub mod lint;
 pub mod loader;
 pub mod utils;
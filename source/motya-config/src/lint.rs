@@ -0,0 +1,304 @@
+//! Warnings about valid configurations that are likely mistakes.
+
+use std::fmt;
+
+use crate::{
+    common_types::{
+        connectors::{RequireTls, UpstreamConfig, UpstreamContextConfig},
+        definitions::Modificator,
+    },
+    internal::ProxyConfig,
+};
+
+/// A suspicious pattern found in a service, a warning rather than an error.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    /// Name of the rule that raised it, like `single-server-balance`.
+    pub rule: &'static str,
+    pub service: String,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[{}] service '{}': {}",
+            self.rule, self.service, self.message
+        )
+    }
+}
+
+/// A lint rule, returning what it finds in one service.
+pub type LintRule = fn(&ProxyConfig) -> Vec<Diagnostic>;
+
+/// Every rule [`lint`] runs.
+pub const RULES: &[LintRule] = &[
+    require_tls_without_tls_listener,
+    unfiltered_admin_section,
+    shared_rate_limit_bucket,
+    single_server_balance,
+];
+
+/// Runs every rule of [`RULES`] on the service.
+pub fn lint(proxy: &ProxyConfig) -> Vec<Diagnostic> {
+    RULES.iter().flat_map(|rule| rule(proxy)).collect()
+}
+
+/// `require-tls` in a service without a TLS listener, refusing every request of the section.
+pub fn require_tls_without_tls_listener(proxy: &ProxyConfig) -> Vec<Diagnostic> {
+    let has_tls = proxy
+        .listeners
+        .list_cfgs
+        .iter()
+        .any(|cfg| cfg.source.tls().is_some());
+    if has_tls {
+        return vec![];
+    }
+
+    proxy
+        .connectors
+        .upstreams
+        .iter()
+        .filter(|upstream| upstream.require_tls != RequireTls::No)
+        .map(|upstream| {
+            diagnostic(
+                "require-tls-without-tls-listener",
+                proxy,
+                format!(
+                    "{} requires TLS, but the service has no TLS listener",
+                    path_of(upstream)
+                ),
+            )
+        })
+        .collect()
+}
+
+/// A section under `/admin` that proxies without a single enabled filter.
+pub fn unfiltered_admin_section(proxy: &ProxyConfig) -> Vec<Diagnostic> {
+    proxy
+        .connectors
+        .upstreams
+        .iter()
+        .filter(|upstream| !matches!(upstream.upstream, UpstreamConfig::Static(_)))
+        .filter(|upstream| {
+            let path = path_of(upstream);
+            path == "/admin" || path.starts_with("/admin/")
+        })
+        .filter(|upstream| {
+            !upstream.chains.iter().any(|Modificator::Chain(named)| {
+                named.chain.filters.iter().any(|filter| filter.enabled)
+            })
+        })
+        .map(|upstream| {
+            diagnostic(
+                "unfiltered-admin-section",
+                proxy,
+                format!("{} is proxied without any filter", path_of(upstream)),
+            )
+        })
+        .collect()
+}
+
+/// A `rate-limit` whose key profile has no variables, so all clients share one bucket.
+pub fn shared_rate_limit_bucket(proxy: &ProxyConfig) -> Vec<Diagnostic> {
+    proxy
+        .connectors
+        .upstreams
+        .iter()
+        .filter(|upstream| {
+            upstream
+                .rate_limit
+                .as_ref()
+                .is_some_and(|limit| !limit.key.source.contains("${"))
+        })
+        .map(|upstream| {
+            diagnostic(
+                "shared-rate-limit-bucket",
+                proxy,
+                format!(
+                    "the rate limit of {} has a constant key, so every client shares one bucket",
+                    path_of(upstream)
+                ),
+            )
+        })
+        .collect()
+}
+
+/// `load-balance` on a pool with a single server, where there is nothing to choose.
+pub fn single_server_balance(proxy: &ProxyConfig) -> Vec<Diagnostic> {
+    proxy
+        .connectors
+        .upstreams
+        .iter()
+        .filter(|upstream| upstream.lb_options.is_some())
+        .filter(|upstream| {
+            matches!(&upstream.upstream, UpstreamConfig::MultiServer(pool) if pool.servers.len() == 1)
+        })
+        .map(|upstream| {
+            diagnostic(
+                "single-server-balance",
+                proxy,
+                format!(
+                    "{} sets load-balance on a pool of one server",
+                    path_of(upstream)
+                ),
+            )
+        })
+        .collect()
+}
+
+fn diagnostic(rule: &'static str, proxy: &ProxyConfig, message: String) -> Diagnostic {
+    Diagnostic {
+        rule,
+        service: proxy.name.clone(),
+        message,
+    }
+}
+
+fn path_of(upstream: &UpstreamContextConfig) -> &str {
+    match &upstream.upstream {
+        UpstreamConfig::Service(peer) => peer.prefix_path.path(),
+        UpstreamConfig::MultiServer(pool) => pool.prefix_path.path(),
+        UpstreamConfig::Static(response) => response.prefix_path.path(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use kdl::KdlDocument;
+
+    use super::*;
+    use crate::{common_types::definitions_table::DefinitionsTable, kdl::compiler::ConfigCompiler};
+
+    fn compile(services: &str) -> ProxyConfig {
+        let kdl_input = format!(
+            r#"
+            system {{ }}
+            definitions {{
+                key-profiles {{
+                    template "everyone" {{
+                        key "global"
+                    }}
+                }}
+                modifiers {{
+                    chain-filters "auth" {{
+                        filter name="motya.request.upsert-header" key="X-Auth" value="1"
+                    }}
+                }}
+            }}
+            services {{
+                {services}
+            }}
+            "#
+        );
+        let doc: KdlDocument = kdl_input.parse().unwrap();
+        let mut config = ConfigCompiler::new(vec![(doc, "test".to_string())])
+            .compile(&mut DefinitionsTable::new_with_global())
+            .expect("Should compile");
+        config.basic_proxies.remove(0)
+    }
+
+    fn rules(diagnostics: &[Diagnostic]) -> Vec<&'static str> {
+        diagnostics.iter().map(|d| d.rule).collect()
+    }
+
+    #[test]
+    fn test_clean_config() {
+        let proxy = compile(
+            r#"
+            Api {
+                listeners { "0.0.0.0:443" cert-path="/etc/motya/cert.pem" key-path="/etc/motya/key.pem"; }
+                connectors {
+                    section "/admin" as="prefix" require-tls=#true {
+                        use-chain "auth"
+                        proxy "127.0.0.1:3001"
+                    }
+                    load-balance { selection "RoundRobin"; }
+                    proxy {
+                        server "127.0.0.1:3002"
+                        server "127.0.0.1:3003"
+                    }
+                }
+            }
+            "#,
+        );
+
+        assert_eq!(lint(&proxy), vec![]);
+    }
+
+    #[test]
+    fn test_unfiltered_admin_section() {
+        let proxy = compile(
+            r#"
+            Api {
+                listeners { "0.0.0.0:80"; }
+                connectors {
+                    section "/admin" as="prefix" {
+                        proxy "127.0.0.1:3001"
+                    }
+                    proxy "127.0.0.1:3000"
+                }
+            }
+            "#,
+        );
+
+        let found = unfiltered_admin_section(&proxy);
+        assert_eq!(rules(&found), vec!["unfiltered-admin-section"]);
+        assert_eq!(found[0].service, "Api");
+        assert!(found[0].message.contains("/admin"), "{}", found[0]);
+    }
+
+    #[test]
+    fn test_single_server_balance() {
+        let proxy = compile(
+            r#"
+            Api {
+                listeners { "0.0.0.0:80"; }
+                connectors {
+                    load-balance { selection "Random"; }
+                    proxy {
+                        server "127.0.0.1:3000"
+                    }
+                }
+            }
+            "#,
+        );
+
+        assert_eq!(
+            rules(&single_server_balance(&proxy)),
+            vec!["single-server-balance"]
+        );
+        assert_eq!(rules(&lint(&proxy)), vec!["single-server-balance"]);
+    }
+
+    #[test]
+    fn test_require_tls_and_shared_bucket() {
+        let proxy = compile(
+            r#"
+            Api {
+                listeners { "0.0.0.0:80"; }
+                connectors {
+                    rate-limit {
+                        key-profile "everyone"
+                        tokens-per-bucket 10
+                        refill-qty 1
+                        refill-interval "1s"
+                    }
+                    section "/" as="prefix" require-tls=#true {
+                        proxy "127.0.0.1:3000"
+                    }
+                }
+            }
+            "#,
+        );
+
+        assert_eq!(
+            rules(&lint(&proxy)),
+            vec![
+                "require-tls-without-tls-listener",
+                "shared-rate-limit-bucket"
+            ]
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use miette::Result;
+use miette::{IntoDiagnostic, Result};
 use std::path::PathBuf;
 
 use crate::common_types::definitions_table::DefinitionsTable;
@@ -18,6 +18,7 @@ pub trait FileConfigLoaderProvider {
 #[derive(Clone)]
 pub struct ConfigLoader<S: ConfigSource> {
     source: S,
+    profile: Option<String>,
 }
 
 impl<S: ConfigSource> FileConfigLoaderProvider for ConfigLoader<S> {
@@ -28,10 +29,19 @@ impl<S: ConfigSource> FileConfigLoaderProvider for ConfigLoader<S> {
     ) -> Result<Option<Config>> {
         if let Some(path) = path {
             let documents = self.source.collect(path).await?;
+            let compiler = ConfigCompiler::new(documents).with_profile(self.profile);
 
-            let config = ConfigCompiler::new(documents).compile(global_definitions)?;
+            // compiling resolves the upstream host names, which blocks
+            let mut definitions = std::mem::take(global_definitions);
+            let (config, definitions) = tokio::task::spawn_blocking(move || {
+                let config = compiler.compile(&mut definitions);
+                (config, definitions)
+            })
+            .await
+            .into_diagnostic()?;
+            *global_definitions = definitions;
 
-            Ok(Some(config))
+            Ok(Some(config?))
         } else {
             Ok(None)
         }
@@ -40,6 +50,14 @@ impl<S: ConfigSource> FileConfigLoaderProvider for ConfigLoader<S> {
 
 impl<S: ConfigSource> ConfigLoader<S> {
     pub fn new(source: S) -> Self {
-        Self { source }
+        Self {
+            source,
+            profile: None,
+        }
+    }
+
+    /// Applies the `profile` block named `profile`, see [`ConfigCompiler::with_profile`].
+    pub fn with_profile(self, profile: Option<String>) -> Self {
+        Self { profile, ..self }
     }
 }
@@ -9,4 +9,19 @@ pub struct DiagnosticError {
     pub end_offset: usize,
 }
 
+/// A `transforms-order` step offered for completion.
+#[derive(Serialize, Debug)]
+pub struct TransformCompletion {
+    pub name: String,
+    pub params: Vec<TransformParamCompletion>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct TransformParamCompletion {
+    pub name: String,
+    pub required: bool,
+    /// The values the parameter accepts, empty when it isn't limited to a list.
+    pub values: Vec<String>,
+}
+
 pub type Snapshot = std::collections::HashMap<String, String>;
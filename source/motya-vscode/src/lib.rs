@@ -3,10 +3,11 @@ mod dto;
 mod utils;
 
 use crate::adapter::map_collector::MapCollector;
-use crate::dto::{DiagnosticError, Snapshot};
+use crate::dto::{DiagnosticError, Snapshot, TransformCompletion, TransformParamCompletion};
 use miette::Diagnostic;
 use miette::Report;
 use motya_config::common_types::bad::Bad;
+use motya_config::common_types::definitions::{ParamKind, TRANSFORM_SCHEMAS};
 use motya_config::common_types::definitions_table::DefinitionsTable;
 use motya_config::loader::{ConfigLoader, FileConfigLoaderProvider};
 use std::path::PathBuf;
@@ -36,6 +37,31 @@ pub async fn validate_workspace(entry_point: String, snapshot_js: JsValue) -> Js
     }
 }
 
+/// The transforms `transforms-order` accepts with their parameters, for completion.
+#[wasm_bindgen]
+pub fn transform_completions() -> JsValue {
+    let completions: Vec<TransformCompletion> = TRANSFORM_SCHEMAS
+        .iter()
+        .map(|schema| TransformCompletion {
+            name: schema.name.to_string(),
+            params: schema
+                .params
+                .iter()
+                .map(|param| TransformParamCompletion {
+                    name: param.name.to_string(),
+                    required: param.required,
+                    values: match param.kind {
+                        ParamKind::OneOf(values) => values.iter().map(|v| v.to_string()).collect(),
+                        _ => vec![],
+                    },
+                })
+                .collect(),
+        })
+        .collect();
+
+    serde_wasm_bindgen::to_value(&completions).unwrap()
+}
+
 fn return_single_error(file: &str, msg: &str) -> JsValue {
     let err = DiagnosticError {
         file_path: file.to_string(),
@@ -1,12 +1,20 @@
-use std::{path::PathBuf, sync::Arc};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use crate::{
     files::motya_file_server,
     fs_adapter::TokioFs,
     proxy::{
+        admin::admin_service,
+        connection_limit::ConnectionLimit,
         filters::{chain_resolver::ChainResolver, generate_registry},
         motya_proxy_service,
+        ocsp::ocsp_staplers,
         plugins::store::WasmPluginStore,
+        populate_listeners::check_privileged_ports,
+        status::status_service,
         upstream_factory::UpstreamFactory,
         watcher::file_watcher::ConfigWatcher,
     },
@@ -29,7 +37,7 @@ use pingora::{
         configuration::{Opt as PingoraOpt, ServerConf as PingoraServerConf},
         Server,
     },
-    services::Service,
+    services::{background::background_service, Service},
 };
 
 use tokio::sync::Mutex;
@@ -79,7 +87,12 @@ impl AppContext {
             global_definitions,
             config_path,
             UpstreamFactory::new(resolver.clone()),
-            ConfigLoader::new(FileCollector::default()),
+            ConfigLoader::new(
+                FileCollector::default()
+                    .report_all_errors()
+                    .max_config_size(cli_args.max_config_size),
+            )
+            .with_profile(cli_args.profile.clone()),
         );
 
         // 6. Prepare Server instance (Pingora)
@@ -99,30 +112,77 @@ impl AppContext {
 
         tracing::info!("Configuring Basic Proxies...");
 
+        let connections = ConnectionLimit::new(self.config.max_connections);
+        let mut proxy_states = vec![];
+
         for proxy_conf in &self.config.basic_proxies {
             tracing::info!("Configuring Basic Proxy: {}", proxy_conf.name);
 
-            let (motya_service, shared_state) =
-                motya_proxy_service(proxy_conf.clone(), self.resolver.clone(), &self.server)
-                    .await
-                    .map_err(|e| {
-                        miette::miette!("Failed create service {}: {}", proxy_conf.name, e)
-                    })?;
+            check_privileged_ports(&proxy_conf.listeners)?;
+
+            for stapler in ocsp_staplers(&proxy_conf.listeners) {
+                services.push(Box::new(background_service("ocsp-stapling", stapler)));
+            }
+
+            let (motya_services, shared_state) = motya_proxy_service(
+                proxy_conf.clone(),
+                self.resolver.clone(),
+                connections.clone(),
+                &self.server,
+            )
+            .await
+            .map_err(|e| miette::miette!("Failed create service {}: {}", proxy_conf.name, e))?;
 
+            proxy_states.push((proxy_conf.name.clone(), shared_state.clone()));
             self.watcher
-                .insert_proxy_state(motya_service.name().to_string(), shared_state);
-            services.push(motya_service);
+                .insert_proxy_state(motya_services[0].name().to_string(), shared_state);
+            services.extend(motya_services);
+        }
+
+        if let Some(status) = &self.config.status {
+            tracing::info!("Serving status on {}{}", status.addr, status.path);
+            services.push(status_service(status, proxy_states));
+        }
+
+        if let Some(admin) = &self.config.admin {
+            tracing::info!("Serving admin on {}", admin.addr);
+            services.push(admin_service(admin, self.watcher.reload_handle()));
         }
 
         for fs_conf in &self.config.file_servers {
             tracing::info!("Configuring File Server: {}", fs_conf.name);
-            let service = motya_file_server(fs_conf.clone(), &self.server);
-            services.push(service);
+            check_privileged_ports(&fs_conf.listeners)?;
+
+            for stapler in ocsp_staplers(&fs_conf.listeners) {
+                services.push(Box::new(background_service("ocsp-stapling", stapler)));
+            }
+
+            services.extend(motya_file_server(fs_conf.clone(), &self.server)?);
         }
 
         Ok(services)
     }
 
+    /// Loads the configuration at `path` as it would be served, without starting anything.
+    pub async fn load_file(cli_args: &Cli, path: &Path) -> miette::Result<Config> {
+        let mut global_definitions = DefinitionsTable::default();
+        generate_registry::load_registry(&mut global_definitions);
+
+        ConfigLoader::new(
+            FileCollector::<TokioFs>::default()
+                .report_all_errors()
+                .max_config_size(cli_args.max_config_size),
+        )
+        .with_profile(cli_args.profile.clone())
+        .load_entry_point(Some(path.into()), &mut global_definitions)
+        .await?
+        .ok_or_else(|| miette::miette!("No configuration found at {path:?}"))
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
     pub fn ready(self) -> (Server, ConfigWatcher) {
         (self.server, self.watcher)
     }
@@ -155,8 +215,16 @@ impl AppContext {
 
                 CliConfigBuilder::build_routes(*port, routes)?
             }
-            None => {
-                let loader = ConfigLoader::new(FileCollector::<TokioFs>::default());
+            Some(Commands::Fmt { .. } | Commands::Diff { .. }) => {
+                unreachable!("`fmt` and `diff` exit before bootstrap")
+            }
+            None | Some(Commands::Explain) => {
+                let loader = ConfigLoader::new(
+                    FileCollector::<TokioFs>::default()
+                        .report_all_errors()
+                        .max_config_size(cli_args.max_config_size),
+                )
+                .with_profile(cli_args.profile.clone());
                 loader
                     .load_entry_point(Some(config_path.into()), global_definitions)
                     .await?
@@ -185,8 +253,11 @@ impl AppContext {
 fn apply_cli(conf: &mut Config, cli: &Cli) {
     let Cli {
         validate_configs,
+        dump_config: _,
         threads_per_service,
         config_entry: _,
+        profile: _,
+        max_config_size: _,
         daemonize,
         upgrade,
         pidfile,
@@ -1,20 +1,33 @@
 //! File Serving
 
-use std::ops::{Deref, DerefMut};
+use std::{
+    ops::{Deref, DerefMut},
+    sync::Arc,
+};
 
 use async_trait::async_trait;
 use motya_config::common_types::file_server::FileServerConfig;
 use pandora_module_utils::{pingora::SessionWrapper, RequestFilter, RequestFilterResult};
-use pingora::{server::Server, upstreams::peer::HttpPeer, Result};
+use pingora::{
+    server::Server,
+    services::{background::background_service, listening::Service},
+    upstreams::peer::HttpPeer,
+    Result,
+};
 use pingora_proxy::{ProxyHttp, Session};
 use static_files_module::{StaticFilesConf, StaticFilesHandler};
 
-use crate::proxy::populate_listeners::populate_listners;
+use crate::proxy::{
+    accept::SharedApp, auto_tls::auto_tls_listeners, inherited_fd::inherited_fd_listeners,
+    populate_listeners::populate_listners,
+};
 
+/// Create a file server, followed by the listeners that accept on their own and have to
+/// run next to it.
 pub fn motya_file_server(
     conf: FileServerConfig,
     server: &Server,
-) -> Box<dyn pingora::services::Service> {
+) -> miette::Result<Vec<Box<dyn pingora::services::Service>>> {
     let fsconf = StaticFilesConf {
         root: conf.base_path,
         canonicalize_uri: true,
@@ -27,12 +40,23 @@ pub fn motya_file_server(
         server: StaticFilesHandler::try_from(fsconf)
             .expect("Creation of a Static File Service should not fail"),
     };
-    let mut my_proxy =
-        pingora_proxy::http_proxy_service_with_name(&server.configuration, file_server, &conf.name);
+    let app = Arc::new(pingora_proxy::http_proxy(
+        &server.configuration,
+        file_server,
+    ));
+    let mut my_proxy = Service::new(conf.name.clone(), SharedApp(app.clone()));
 
     populate_listners(&conf.listeners, &mut my_proxy);
 
-    Box::new(my_proxy)
+    let mut services: Vec<Box<dyn pingora::services::Service>> = vec![Box::new(my_proxy)];
+    for listener in auto_tls_listeners(&conf.listeners, &app)? {
+        services.push(Box::new(background_service("auto-tls", listener)));
+    }
+    for listener in inherited_fd_listeners(&conf.listeners, &app)? {
+        services.push(Box::new(background_service("inherited-fd", listener)));
+    }
+
+    Ok(services)
 }
 
 pub struct FileServer {
@@ -3,10 +3,20 @@ mod files;
 pub mod fs_adapter;
 mod proxy;
 
-use std::process;
+use std::{
+    path::{Path, PathBuf},
+    process,
+};
 
 use clap::{CommandFactory, FromArgMatches};
-use motya_config::cli::cli_struct::{Cli, BANNER};
+use miette::{miette, Context, IntoDiagnostic};
+use motya_config::{
+    cli::cli_struct::{Cli, Commands, BANNER},
+    diff::diff,
+    explain::explain,
+    kdl::formatter::format_source,
+    lint::lint,
+};
 use tokio::runtime::Runtime;
 
 use crate::app_context::AppContext;
@@ -21,8 +31,35 @@ fn main() -> miette::Result<()> {
         .get_matches();
     let cli_args = Cli::from_arg_matches(&command).expect("Failed to parse args");
 
+    if let Some(Commands::Fmt { files, check }) = &cli_args.command {
+        return format_files(files, *check);
+    }
+    if let Some(Commands::Diff { old, new }) = &cli_args.command {
+        return rt.block_on(diff_files(&cli_args, old, new));
+    }
+
+    let dump_config = cli_args.dump_config;
+    let explain_config = matches!(cli_args.command, Some(Commands::Explain));
     let mut ctx = rt.block_on(AppContext::bootstrap(cli_args))?;
 
+    if dump_config {
+        println!("{:#?}", ctx.config());
+        return Ok(());
+    }
+    if explain_config {
+        for proxy in &ctx.config().basic_proxies {
+            println!("{}", explain(proxy));
+            for diagnostic in lint(proxy) {
+                println!("warning: {diagnostic}");
+            }
+        }
+        return Ok(());
+    }
+
+    for diagnostic in ctx.config().basic_proxies.iter().flat_map(lint) {
+        tracing::warn!("{diagnostic}");
+    }
+
     let services = rt.block_on(ctx.build_services())?;
 
     tracing::info!("Server running (PID: {})", process::id());
@@ -38,3 +75,81 @@ fn main() -> miette::Result<()> {
 
     server.run_forever();
 }
+
+/// `motya diff`: prints what differs between the services of two configurations.
+async fn diff_files(cli_args: &Cli, old: &Path, new: &Path) -> miette::Result<()> {
+    let old = AppContext::load_file(cli_args, old).await?;
+    let new = AppContext::load_file(cli_args, new).await?;
+    let mut identical = true;
+
+    for proxy in &old.basic_proxies {
+        if !new
+            .basic_proxies
+            .iter()
+            .any(|other| other.name == proxy.name)
+        {
+            println!("- service '{}'", proxy.name);
+            identical = false;
+        }
+    }
+    for proxy in &new.basic_proxies {
+        let Some(before) = old
+            .basic_proxies
+            .iter()
+            .find(|other| other.name == proxy.name)
+        else {
+            println!("+ service '{}'", proxy.name);
+            identical = false;
+            continue;
+        };
+
+        let changes = diff(before, proxy);
+        if changes.is_empty() {
+            continue;
+        }
+        identical = false;
+        println!("Service '{}':", proxy.name);
+        for entry in &changes.entries {
+            println!("  {entry}");
+        }
+    }
+
+    if identical {
+        println!("No differences");
+    }
+    Ok(())
+}
+
+/// `motya fmt`: rewrites each file in the canonical style, or with `check` only
+/// reports the ones that would change.
+fn format_files(files: &[PathBuf], check: bool) -> miette::Result<()> {
+    let mut unformatted = vec![];
+
+    for path in files {
+        let source = std::fs::read_to_string(path)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("Failed to read file: {:?}", path))?;
+        let formatted =
+            format_source(&source).wrap_err_with(|| format!("Failed to parse KDL: {:?}", path))?;
+
+        if formatted == source {
+            continue;
+        }
+
+        if check {
+            tracing::warn!("Not formatted: {:?}", path);
+            unformatted.push(path);
+        } else {
+            std::fs::write(path, formatted)
+                .into_diagnostic()
+                .wrap_err_with(|| format!("Failed to write file: {:?}", path))?;
+            tracing::info!("Formatted {:?}", path);
+        }
+    }
+
+    if !unformatted.is_empty() {
+        return Err(miette!("{} file(s) are not formatted", unformatted.len()));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,64 @@
+use std::{os::fd::AsRawFd, sync::Arc};
+
+use async_trait::async_trait;
+use pingora::{
+    apps::ServerApp,
+    protocols::{
+        l4::{socket::SocketDigest, stream::Stream as L4Stream},
+        tls::server::handshake,
+        GetSocketDigest, Stream,
+    },
+    server::ShutdownWatch,
+    tls::ssl::SslAcceptor,
+};
+use tokio::net::TcpStream;
+
+/// An app served by pingora's listeners and by the listeners motya accepts on itself,
+/// which hand their connections to the same instance.
+pub struct SharedApp<A>(pub Arc<A>);
+
+#[async_trait]
+impl<A> ServerApp for SharedApp<A>
+where
+    A: ServerApp + Send + Sync + 'static,
+{
+    async fn process_new(
+        self: &Arc<Self>,
+        stream: Stream,
+        shutdown: &ShutdownWatch,
+    ) -> Option<Stream> {
+        self.0.process_new(stream, shutdown).await
+    }
+
+    async fn cleanup(&self) {
+        self.0.cleanup().await
+    }
+}
+
+/// Serves a connection accepted by motya with `app`, after a TLS handshake with `tls`
+/// if given, the way pingora serves the connections it accepts.
+///
+/// The connection keeps its own socket, so the app sees the client's address and the
+/// listening address the client connected to.
+pub async fn serve<A>(
+    app: &Arc<A>,
+    tcp: TcpStream,
+    tls: Option<&SslAcceptor>,
+    shutdown: &ShutdownWatch,
+) -> pingora::Result<()>
+where
+    A: ServerApp + Send + Sync + 'static,
+{
+    let digest = SocketDigest::from_raw_fd(tcp.as_raw_fd());
+    let mut stream = L4Stream::from(tcp);
+    stream.set_socket_digest(digest);
+
+    let mut stream: Stream = match tls {
+        Some(acceptor) => Box::new(handshake(acceptor, stream).await?),
+        None => Box::new(stream),
+    };
+    while let Some(reused) = app.process_new(stream, shutdown).await {
+        stream = reused;
+    }
+    Ok(())
+}
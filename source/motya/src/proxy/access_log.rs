@@ -0,0 +1,92 @@
+use std::{fmt::Write, net::IpAddr, time::Duration};
+
+use motya_config::common_types::{
+    access_log::{AccessLogFormat, AccessLogVariable},
+    headers::TemplatePart,
+};
+
+/// What the access record of a finished request is rendered from.
+#[derive(Debug)]
+pub struct AccessRecord<'a> {
+    pub method: &'a str,
+    pub uri: String,
+    pub status: u16,
+    pub client_ip: Option<IpAddr>,
+    pub request_time: Duration,
+    /// `None` when no backend was picked, like for cache hits and filter responses.
+    pub upstream_addr: Option<&'a str>,
+    /// `None` when no backend response was read.
+    pub upstream_response_time: Option<Duration>,
+    /// Name of the oversized header of a request answered with `431`.
+    pub rejected_header: Option<&'a str>,
+}
+
+impl AccessRecord<'_> {
+    /// Writes the record in `format`. Values the request doesn't have become `-`, and
+    /// durations are in seconds with millisecond precision, like `0.012`.
+    pub fn render(&self, format: &AccessLogFormat) -> String {
+        let mut out = String::new();
+        for part in &format.parts {
+            let _ = match part {
+                TemplatePart::Literal(s) => out.write_str(s),
+                TemplatePart::Variable(var) => match var {
+                    AccessLogVariable::Method => out.write_str(self.method),
+                    AccessLogVariable::Uri => out.write_str(&self.uri),
+                    AccessLogVariable::Status => write!(out, "{}", self.status),
+                    AccessLogVariable::ClientIp => match self.client_ip {
+                        Some(ip) => write!(out, "{ip}"),
+                        None => out.write_str("-"),
+                    },
+                    AccessLogVariable::RequestTime => write_seconds(&mut out, self.request_time),
+                    AccessLogVariable::UpstreamAddr => {
+                        out.write_str(self.upstream_addr.unwrap_or("-"))
+                    }
+                    AccessLogVariable::UpstreamResponseTime => match self.upstream_response_time {
+                        Some(time) => write_seconds(&mut out, time),
+                        None => out.write_str("-"),
+                    },
+                    AccessLogVariable::RejectedHeader => {
+                        out.write_str(self.rejected_header.unwrap_or("-"))
+                    }
+                },
+            };
+        }
+        out
+    }
+}
+
+fn write_seconds(out: &mut String, time: Duration) -> std::fmt::Result {
+    write!(out, "{:.3}", time.as_secs_f64())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_upstream_variables() {
+        let format = "$method $uri $status $upstream_addr $upstream_response_time/$request_time"
+            .parse::<AccessLogFormat>()
+            .unwrap();
+        let mut record = AccessRecord {
+            method: "GET",
+            uri: "/api?page=2".to_string(),
+            status: 200,
+            client_ip: None,
+            request_time: Duration::from_millis(1250),
+            upstream_addr: Some("10.0.0.7:8080"),
+            upstream_response_time: Some(Duration::from_micros(1_200_400)),
+            rejected_header: None,
+        };
+
+        assert_eq!(
+            record.render(&format),
+            "GET /api?page=2 200 10.0.0.7:8080 1.200/1.250"
+        );
+
+        // answered without a backend, like a cache hit
+        record.upstream_addr = None;
+        record.upstream_response_time = None;
+        assert_eq!(record.render(&format), "GET /api?page=2 200 - -/1.250");
+    }
+}
@@ -0,0 +1,125 @@
+use async_trait::async_trait;
+use http::{header, Method, Response, StatusCode};
+use pingora::{
+    apps::http_app::{HttpServer, ServeHttp},
+    protocols::http::ServerSession,
+    services::listening::Service,
+};
+use serde_json::{json, Value};
+
+use motya_config::common_types::system_data::AdminConfig;
+
+use crate::proxy::watcher::file_watcher::ReloadHandle;
+
+/// Path a reload is requested at.
+const RELOAD_PATH: &str = "/reload";
+
+/// Serves the `admin` endpoint: `POST /reload` with the `reload-token` reloads the
+/// configuration, answering with the diagnostics when the new one is refused.
+pub struct AdminApp {
+    reload_token: String,
+    reloads: ReloadHandle,
+}
+
+pub fn admin_service(
+    config: &AdminConfig,
+    reloads: ReloadHandle,
+) -> Box<dyn pingora::services::Service> {
+    let app = AdminApp {
+        reload_token: config.reload_token.clone(),
+        reloads,
+    };
+
+    let mut service = Service::new("motya-admin".to_string(), HttpServer::new_app(app));
+    service.add_tcp(&config.addr.to_string());
+    Box::new(service)
+}
+
+impl AdminApp {
+    async fn reload(&self, method: &Method, authorization: Option<&str>) -> (StatusCode, Value) {
+        if method != Method::POST {
+            return (
+                StatusCode::METHOD_NOT_ALLOWED,
+                json!({ "error": "reloads are requested with POST" }),
+            );
+        }
+        let presented = authorization.and_then(|value| value.strip_prefix("Bearer "));
+        if !presented.is_some_and(|token| token_matches(&self.reload_token, token)) {
+            return (
+                StatusCode::UNAUTHORIZED,
+                json!({ "error": "a valid 'Authorization: Bearer' token is required" }),
+            );
+        }
+
+        match self.reloads.reload().await {
+            Ok(()) => {
+                tracing::info!("Configuration reloaded on request");
+                (StatusCode::OK, json!({ "reloaded": true }))
+            }
+            Err(err) => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                json!({ "reloaded": false, "diagnostics": format!("{err:?}") }),
+            ),
+        }
+    }
+}
+
+/// Compares the tokens without stopping at the first differing byte, so the time taken
+/// doesn't tell how much of a guess was right.
+fn token_matches(expected: &str, presented: &str) -> bool {
+    expected.len() == presented.len()
+        && expected
+            .bytes()
+            .zip(presented.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+#[async_trait]
+impl ServeHttp for AdminApp {
+    async fn response(&self, session: &mut ServerSession) -> Response<Vec<u8>> {
+        let req = session.req_header();
+        // owned, the session is not held across the reload
+        let reload = (req.uri.path() == RELOAD_PATH).then(|| {
+            let authorization = req
+                .headers
+                .get(header::AUTHORIZATION)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string);
+            (req.method.clone(), authorization)
+        });
+
+        let (status, body) = match reload {
+            Some((method, authorization)) => self.reload(&method, authorization.as_deref()).await,
+            None => (StatusCode::NOT_FOUND, json!({ "error": "not found" })),
+        };
+
+        let mut response = Response::builder().status(status);
+        if status == StatusCode::UNAUTHORIZED {
+            response = response.header(header::WWW_AUTHENTICATE, "Bearer");
+        }
+        if status == StatusCode::METHOD_NOT_ALLOWED {
+            response = response.header(header::ALLOW, "POST");
+        }
+
+        let body = body.to_string().into_bytes();
+        response
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(header::CONTENT_LENGTH, body.len())
+            .body(body)
+            .expect("admin response is valid")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_matches() {
+        assert!(token_matches("secret", "secret"));
+        assert!(!token_matches("secret", "secreT"));
+        assert!(!token_matches("secret", "secret2"));
+        assert!(!token_matches("secret", ""));
+    }
+}
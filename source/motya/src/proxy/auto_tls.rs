@@ -0,0 +1,311 @@
+use std::{
+    io,
+    net::{SocketAddr, TcpListener as StdTcpListener},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use miette::miette;
+use pingora::{
+    apps::ServerApp, server::ShutdownWatch, services::background::BackgroundService,
+    tls::ssl::SslAcceptor, ErrorType, OrErr,
+};
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    time::timeout,
+};
+
+use motya_config::common_types::listeners::{ListenerKind, Listeners};
+
+use crate::proxy::{
+    accept::serve,
+    populate_listeners::{privileged_bind_error, tls_acceptor},
+};
+
+/// How long a new connection may stay silent before it is dropped, as nothing can be
+/// routed until the client sends its first byte.
+const FIRST_BYTE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Backlog of a listener without `backlog`, the one tokio listens with.
+const DEFAULT_BACKLOG: u32 = 1024;
+
+/// Whether the first bytes of a connection are a TLS handshake record.
+///
+/// A TLS connection opens with a record of content type 22 (handshake), which is not a
+/// printable character and so never starts an HTTP/1 request line or the HTTP/2 preface.
+/// The first byte alone decides, so that a client sending its bytes one at a time is
+/// routed as soon as the first one arrives.
+pub fn looks_like_tls(prefix: &[u8]) -> bool {
+    prefix.first() == Some(&0x16)
+}
+
+/// A listener with `auto-tls`, accepting on the configured address and handing each
+/// connection to the service's app, with a TLS handshake first for those that open with
+/// one.
+pub struct AutoTlsListener<A> {
+    public: SocketAddr,
+    /// Bound when the service is built, taken out when it starts.
+    listener: Mutex<Option<StdTcpListener>>,
+    tls: SslAcceptor,
+    app: Arc<A>,
+}
+
+/// A listener for every `auto-tls` listener, to run next to the service serving `app`.
+///
+/// The listeners keep their configuration in `listeners`, as the connections are
+/// accepted on the configured address and limits and timeouts keyed by it apply to them,
+/// while `populate_listners` leaves them out. Their sockets are bound here, so that an
+/// address that can't be listened on stops the startup.
+pub fn auto_tls_listeners<A>(
+    listeners: &Listeners,
+    app: &Arc<A>,
+) -> miette::Result<Vec<AutoTlsListener<A>>> {
+    let mut auto_tls = vec![];
+
+    for cfg in &listeners.list_cfgs {
+        let ListenerKind::Tcp {
+            addr,
+            tls: Some(tls),
+            offer_h2,
+        } = &cfg.source
+        else {
+            continue;
+        };
+        if !cfg.auto_tls {
+            continue;
+        }
+
+        let public = addr
+            .parse::<SocketAddr>()
+            .expect("Listener address must be valid after parsing the configuration");
+        let listener = bind(public, cfg.backlog).map_err(|err| {
+            privileged_bind_error(public, &err)
+                .unwrap_or_else(|| miette!("Failed to bind the auto-tls listener {public}: {err}"))
+        })?;
+
+        auto_tls.push(AutoTlsListener {
+            public,
+            listener: Mutex::new(Some(listener)),
+            tls: tls_acceptor(tls, *offer_h2)?,
+            app: app.clone(),
+        });
+    }
+
+    Ok(auto_tls)
+}
+
+#[async_trait]
+impl<A> BackgroundService for AutoTlsListener<A>
+where
+    A: ServerApp + Send + Sync + 'static,
+{
+    async fn start(&self, mut shutdown: ShutdownWatch) {
+        let Some(listener) = self.listener.lock().unwrap().take() else {
+            return;
+        };
+        // registered with the runtime running the service, which is only there once the
+        // server has started, after daemonizing
+        let listener = match TcpListener::from_std(listener) {
+            Ok(listener) => listener,
+            Err(err) => {
+                tracing::error!(
+                    "Failed to listen on auto-tls listener {}: {err}",
+                    self.public
+                );
+                return;
+            }
+        };
+
+        loop {
+            tokio::select! {
+                _ = shutdown.changed() => return,
+                accepted = listener.accept() => match accepted {
+                    Ok((stream, _)) => {
+                        let (app, tls, shutdown) = (self.app.clone(), self.tls.clone(), shutdown.clone());
+                        tokio::spawn(async move {
+                            if let Err(err) = dispatch(&app, stream, &tls, &shutdown).await {
+                                tracing::debug!("auto-tls connection ended with an error: {err}");
+                            }
+                        });
+                    }
+                    Err(err) => tracing::warn!("Failed to accept on {}: {err}", self.public),
+                },
+            }
+        }
+    }
+}
+
+/// Binds `addr` for a non-blocking listener, with [`DEFAULT_BACKLOG`] when `backlog` is
+/// not set.
+fn bind(addr: SocketAddr, backlog: Option<u32>) -> io::Result<StdTcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    socket.set_reuse_address(true)?;
+    socket.bind(&addr.into())?;
+    // the configuration keeps `backlog` within `i32`
+    socket.listen(backlog.unwrap_or(DEFAULT_BACKLOG) as i32)?;
+    socket.set_nonblocking(true)?;
+    Ok(socket.into())
+}
+
+/// Serves `client` with or without TLS, depending on its first byte.
+async fn dispatch<A>(
+    app: &Arc<A>,
+    client: TcpStream,
+    tls: &SslAcceptor,
+    shutdown: &ShutdownWatch,
+) -> pingora::Result<()>
+where
+    A: ServerApp + Send + Sync + 'static,
+{
+    let mut prefix = [0u8; 1];
+    let read = timeout(FIRST_BYTE_TIMEOUT, client.peek(&mut prefix))
+        .await
+        .or_err(ErrorType::ReadTimedout, "waiting for the first byte")?
+        .or_err(ErrorType::ReadError, "peeking the first byte")?;
+    if read == 0 {
+        return Ok(());
+    }
+
+    let tls = looks_like_tls(&prefix).then_some(tls);
+    serve(app, client, tls, shutdown).await
+}
+
+#[cfg(test)]
+mod tests {
+    use motya_config::common_types::listeners::{ListenerConfig, TlsConfig};
+    use pingora::{
+        protocols::{GetSocketDigest, Stream},
+        tls::ssl::SslMethod,
+    };
+    use tokio::{io::AsyncWriteExt, sync::mpsc};
+
+    use super::*;
+
+    /// The start of a ClientHello sent by curl: a handshake record, TLS 1.0 record
+    /// version, the record length, then handshake type 1 and the TLS 1.2 client version.
+    const CLIENT_HELLO: &[u8] = &[
+        0x16, 0x03, 0x01, 0x02, 0x00, 0x01, 0x00, 0x01, 0xfc, 0x03, 0x03, 0x5a, 0x1b,
+    ];
+
+    #[test]
+    fn test_detects_client_hello() {
+        assert!(looks_like_tls(CLIENT_HELLO));
+        assert!(looks_like_tls(&CLIENT_HELLO[..1]));
+
+        assert!(!looks_like_tls(
+            b"GET / HTTP/1.1\r\nHost: example.com\r\n\r\n"
+        ));
+        assert!(!looks_like_tls(b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n"));
+        assert!(!looks_like_tls(b""));
+    }
+
+    /// Reports the client address each connection it's handed comes from.
+    struct PeerApp(mpsc::UnboundedSender<Option<SocketAddr>>);
+
+    #[async_trait]
+    impl ServerApp for PeerApp {
+        async fn process_new(
+            self: &Arc<Self>,
+            stream: Stream,
+            _shutdown: &ShutdownWatch,
+        ) -> Option<Stream> {
+            let peer = stream
+                .get_socket_digest()
+                .and_then(|digest| digest.peer_addr()?.as_inet().copied());
+            self.0.send(peer).unwrap();
+            None
+        }
+    }
+
+    fn auto_tls_listener(addr: &str, auto_tls: bool) -> ListenerConfig {
+        ListenerConfig {
+            backlog: Some(1024),
+            auto_tls,
+            ..ListenerConfig::new(ListenerKind::Tcp {
+                addr: addr.to_string(),
+                tls: Some(TlsConfig {
+                    cert_path: "./assets/test.crt".into(),
+                    key_path: "./assets/test.key".into(),
+                    session_tickets: true,
+                    ticket_key: None,
+                    h2_only: false,
+                    ocsp: None,
+                }),
+                offer_h2: true,
+            })
+        }
+    }
+
+    #[test]
+    fn test_listeners_for_auto_tls_only() {
+        let (tx, _rx) = mpsc::unbounded_channel();
+
+        let auto_tls = auto_tls_listeners(
+            &Listeners {
+                list_cfgs: vec![
+                    auto_tls_listener("127.0.0.1:0", true),
+                    auto_tls_listener("0.0.0.0:8443", false),
+                ],
+            },
+            &Arc::new(PeerApp(tx)),
+        )
+        .unwrap();
+
+        assert_eq!(auto_tls.len(), 1);
+        assert_eq!(auto_tls[0].public, "127.0.0.1:0".parse().unwrap());
+        let bound = auto_tls[0].listener.lock().unwrap();
+        assert_ne!(bound.as_ref().unwrap().local_addr().unwrap().port(), 0);
+    }
+
+    #[test]
+    fn test_bind_failure_stops_startup() {
+        let taken = StdTcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = taken.local_addr().unwrap().to_string();
+        let (tx, _rx) = mpsc::unbounded_channel();
+
+        let Err(err) = auto_tls_listeners(
+            &Listeners {
+                list_cfgs: vec![auto_tls_listener(&addr, true)],
+            },
+            &Arc::new(PeerApp(tx)),
+        ) else {
+            panic!("an address in use can't be listened on");
+        };
+        assert!(err
+            .to_string()
+            .contains(&format!("Failed to bind the auto-tls listener {addr}")));
+    }
+
+    #[tokio::test]
+    async fn test_plain_connection_keeps_client_address() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        client.write_all(b"GET / HTTP/1.1\r\n").await.unwrap();
+        let (accepted, _) = listener.accept().await.unwrap();
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let app = Arc::new(PeerApp(tx));
+        let tls = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls())
+            .unwrap()
+            .build();
+        let (_shutdown_tx, shutdown) = tokio::sync::watch::channel(false);
+        dispatch(&app, accepted, &tls, &shutdown).await.unwrap();
+
+        assert_eq!(rx.recv().await.unwrap(), Some(client.local_addr().unwrap()));
+    }
+
+    #[tokio::test]
+    async fn test_bind_with_backlog() {
+        let listener = bind("127.0.0.1:0".parse().unwrap(), Some(16)).unwrap();
+        let listener = TcpListener::from_std(listener).unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let client = TcpStream::connect(addr).await.unwrap();
+        let (accepted, _) = listener.accept().await.unwrap();
+        assert_eq!(accepted.peer_addr().unwrap(), client.local_addr().unwrap());
+    }
+}
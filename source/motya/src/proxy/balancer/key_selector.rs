@@ -1,15 +1,24 @@
+use hmac::{digest::KeyInit, Hmac, Mac};
 use http::uri::PathAndQuery;
 use pingora_load_balancing::{
     prelude::RoundRobin,
     selection::{consistent::KetamaHashing, FNVHash, Random},
-    Backend, LoadBalancer,
+    Backend, Backends, LoadBalancer,
 };
+use sha2::{Sha256, Sha512};
 use std::hash::Hasher;
-use std::{io::Cursor, net::IpAddr};
+
+use crate::proxy::balancer::{
+    outlier::OutlierDetector, slow_start::SlowStart, weighted_random::WeightedRandom,
+};
+use std::{io::Cursor, net::IpAddr, sync::Arc};
 
 pub struct Balancer {
     pub selector: Option<KeySelector>,
-    pub balancer_type: BalancerType,
+    /// Shared with the health-check task, which stops once the balancer is dropped.
+    pub balancer_type: Arc<BalancerType>,
+    pub slow_start: Option<Arc<SlowStart>>,
+    pub outlier_detection: Option<OutlierDetector>,
 }
 
 pub trait KeySourceContext {
@@ -26,18 +35,47 @@ pub struct KeySelector {
     pub hasher: HashOp,
 }
 
+/// A request's key as left by the transforms, with its hash.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EvaluatedKey {
+    pub key: String,
+    pub hash: u64,
+}
+
 impl KeySelector {
+    /// Evaluates the key profile for a request: its `source` resolved, or its `fallback`
+    /// when none of the `source` placeholders has a value, run through the `transforms` in
+    /// order and hashed with its `algorithm`.
+    pub fn evaluate<C: KeySourceContext>(&self, ctx: &C) -> Option<EvaluatedKey> {
+        let mut buffer = Vec::new();
+        let hash = self.select(ctx, &mut buffer)?;
+
+        Some(EvaluatedKey {
+            key: String::from_utf8_lossy(&buffer).into_owned(),
+            hash,
+        })
+    }
+
+    /// Like [`KeySelector::evaluate`], leaving the key in `buffer` and returning its hash.
     pub fn select<C: KeySourceContext>(&self, ctx: &C, buffer: &mut Vec<u8>) -> Option<u64> {
         buffer.clear();
         let mut extracted = false;
 
         for strategy in &self.extraction_strategies {
             let start_len = buffer.len();
+            // a template with placeholders yields nothing when none of them has a value,
+            // whatever literal text surrounds them
+            let mut resolved = strategy
+                .parts
+                .iter()
+                .all(|part| matches!(part, KeyPart::Literal(_)));
 
             for part in &strategy.parts {
+                let before = buffer.len();
                 match part {
                     KeyPart::Literal(s) => {
                         buffer.extend_from_slice(s.as_bytes());
+                        continue;
                     }
                     KeyPart::Header(name) => {
                         if let Some(val) = ctx.get_header(name) {
@@ -84,9 +122,10 @@ impl KeySelector {
                         }
                     }
                 }
+                resolved |= buffer.len() > before;
             }
 
-            if buffer.len() > start_len {
+            if resolved && buffer.len() > start_len {
                 extracted = true;
                 break;
             } else {
@@ -123,7 +162,23 @@ fn apply_transform(op: &TransformOp, buf: &mut Vec<u8>) {
                 buf.truncate(pos);
             }
         }
-        _ => {}
+        TransformOp::StripTrailingSlash => {
+            // the root path keeps its slash
+            while buf.len() > 1 && buf.ends_with(b"/") {
+                buf.pop();
+            }
+        }
+        TransformOp::Hmac { algorithm, key } => {
+            let digest = match algorithm {
+                HmacAlgorithm::Sha256 => hmac_digest::<Hmac<Sha256>>(key, buf),
+                HmacAlgorithm::Sha512 => hmac_digest::<Hmac<Sha512>>(key, buf),
+            };
+
+            buf.clear();
+            for byte in digest {
+                buf.extend_from_slice(format!("{byte:02x}").as_bytes());
+            }
+        }
     }
 }
 
@@ -166,11 +221,33 @@ impl Balancer {
     }
 
     fn select(&self, key: &[u8]) -> Option<Backend> {
-        match &self.balancer_type {
+        if self.slow_start.is_some() || self.outlier_detection.is_some() {
+            let accept = |backend: &Backend, healthy: bool| {
+                healthy
+                    && self.outlier_detection.iter().all(|o| o.accept(backend))
+                    && self.slow_start.iter().all(|s| s.accept(backend))
+            };
+
+            let accepted = match self.balancer_type.as_ref() {
+                BalancerType::FNVHash(b) => b.select_with(key, 256, accept),
+                BalancerType::Random(b) => b.select_with(key, 256, accept),
+                BalancerType::KetamaHashing(b) => b.select_with(key, 256, accept),
+                BalancerType::RoundRobin(b) => b.select_with(key, 256, accept),
+                BalancerType::WeightedRandom(b) => b.select_with(key, 256, accept),
+            };
+
+            if accepted.is_some() {
+                return accepted;
+            }
+            // every candidate is ejected or still ramping up, serve the request anyway
+        }
+
+        match self.balancer_type.as_ref() {
             BalancerType::FNVHash(b) => b.select(key, 256),
             BalancerType::Random(b) => b.select(key, 256),
             BalancerType::KetamaHashing(b) => b.select(key, 256),
             BalancerType::RoundRobin(b) => b.select(key, 256),
+            BalancerType::WeightedRandom(b) => b.select(key, 256),
         }
     }
 }
@@ -180,6 +257,26 @@ pub enum BalancerType {
     Random(LoadBalancer<Random>),
     FNVHash(LoadBalancer<FNVHash>),
     KetamaHashing(LoadBalancer<KetamaHashing>),
+    WeightedRandom(LoadBalancer<WeightedRandom>),
+}
+
+impl BalancerType {
+    /// The backends balanced over, along with their health.
+    pub fn backends(&self) -> &Backends {
+        match self {
+            BalancerType::FNVHash(b) => b.backends(),
+            BalancerType::Random(b) => b.backends(),
+            BalancerType::KetamaHashing(b) => b.backends(),
+            BalancerType::RoundRobin(b) => b.backends(),
+            BalancerType::WeightedRandom(b) => b.backends(),
+        }
+    }
+}
+
+fn hmac_digest<M: Mac + KeyInit>(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = <M as KeyInit>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -204,7 +301,19 @@ pub enum TransformOp {
     Lowercase,
     RemoveQueryParams,
     StripTrailingSlash,
-    Truncate { length: usize },
+    Truncate {
+        length: usize,
+    },
+    Hmac {
+        algorithm: HmacAlgorithm,
+        key: Vec<u8>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HmacAlgorithm {
+    Sha256,
+    Sha512,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -387,6 +496,70 @@ mod execution_tests {
         assert_eq!(String::from_utf8(buf).unwrap(), "my-cookie-id");
     }
 
+    #[test]
+    fn test_fallback_when_placeholders_are_empty() {
+        let mut conf = make_config("user:${header-x-user}", vec![]);
+        conf.fallback = Some("ip:${client-ip}".to_string());
+        let selector = KeySelector::try_from(conf).unwrap();
+
+        // the literal prefix alone doesn't count as a key
+        let key = selector.evaluate(&MockContext::new()).unwrap();
+        assert_eq!(key.key, "ip:127.0.0.1");
+
+        let key = selector
+            .evaluate(&MockContext::new().with_header("x-user", "alice"))
+            .unwrap();
+        assert_eq!(key.key, "user:alice");
+
+        // without a fallback, such a request has no key
+        let selector = KeySelector::try_from(make_config("user:${header-x-user}", vec![])).unwrap();
+        assert_eq!(selector.evaluate(&MockContext::new()), None);
+    }
+
+    #[test]
+    fn test_evaluate_transforms_then_hashes() {
+        let mut conf = make_config(
+            "${uri-path}",
+            vec!["remove-query-params", "strip-trailing-slash", "lowercase"],
+        );
+        conf.algorithm = HashAlgorithm {
+            name: "xxhash64".to_string(),
+            seed: Some("42".to_string()),
+        };
+        let selector = KeySelector::try_from(conf).unwrap();
+
+        let ctx = MockContext::new().with_path(PathAndQuery::from_static("/Docs/Intro//?page=2"));
+        let key = selector.evaluate(&ctx).unwrap();
+
+        assert_eq!(key.key, "/docs/intro");
+        assert_eq!(key.hash, xxhash_rust::xxh64::xxh64(b"/docs/intro", 42));
+
+        let root = MockContext::new().with_path(PathAndQuery::from_static("/"));
+        assert_eq!(selector.evaluate(&root).unwrap().key, "/");
+    }
+
+    #[test]
+    fn test_transform_hmac_sha256_vector() {
+        // RFC 4231, test case 2
+        let selector = build_manual_selector(
+            vec![KeyPart::Header("x-data".to_string())],
+            vec![TransformOp::Hmac {
+                algorithm: HmacAlgorithm::Sha256,
+                key: b"Jefe".to_vec(),
+            }],
+        );
+
+        let ctx = MockContext::new().with_header("x-data", "what do ya want for nothing?");
+
+        let mut buf = Vec::new();
+        selector.select(&ctx, &mut buf).unwrap();
+
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
     #[test]
     fn test_transform_lowercase_and_stability() {
         let conf = make_config("${header-x-key}", vec!["lowercase"]);
@@ -4,7 +4,7 @@ use std::sync::OnceLock;
 use std::{convert::TryFrom, str::FromStr};
 
 use crate::proxy::balancer::key_selector::{
-    ExtractionChain, HashOp, KeyPart, KeySelector, TransformOp,
+    ExtractionChain, HashOp, HmacAlgorithm, KeyPart, KeySelector, TransformOp,
 };
 
 fn variable_regex() -> &'static Regex {
@@ -83,6 +83,9 @@ fn parse_template_string(template: &str) -> Result<ExtractionChain, String> {
 }
 
 fn parse_transform(t: &Transform) -> Result<TransformOp, String> {
+    // configurations built without the parser haven't been checked yet
+    t.validate_params()?;
+
     match t.name.as_str() {
         "lowercase" => Ok(TransformOp::Lowercase),
         "remove-query-params" => Ok(TransformOp::RemoveQueryParams),
@@ -95,6 +98,18 @@ fn parse_transform(t: &Transform) -> Result<TransformOp, String> {
             let length = len_str.parse::<usize>().map_err(|_| "Invalid length")?;
             Ok(TransformOp::Truncate { length })
         }
+        "hmac" => {
+            let key = t.params.get("key").ok_or("Missing key param for hmac")?;
+            let algorithm = match t.params.get("algorithm").map(String::as_str) {
+                None | Some("sha256") => HmacAlgorithm::Sha256,
+                Some("sha512") => HmacAlgorithm::Sha512,
+                Some(other) => return Err(format!("Unknown hmac algorithm: {other}")),
+            };
+            Ok(TransformOp::Hmac {
+                algorithm,
+                key: key.as_bytes().to_vec(),
+            })
+        }
         _ => Err(format!("Unknown transform: {}", t.name)),
     }
 }
@@ -323,4 +338,22 @@ mod tests {
         assert!(res.is_err());
         assert!(res.unwrap_err().contains("Unknown transform"));
     }
+
+    #[test]
+    fn test_transform_params_checked_against_schema() {
+        let params = HashMap::from([
+            ("length".to_string(), "3".to_string()),
+            ("size".to_string(), "8".to_string()),
+        ]);
+        let t = vec![Transform {
+            name: "truncate".to_string(),
+            params,
+        }];
+        let conf = create_config("k", None, t, "xxhash32", None);
+
+        assert_eq!(
+            KeySelector::try_from(conf).err().unwrap(),
+            "Unknown parameter 'size' of transform 'truncate'. Supported parameters are: [\"length\"]"
+        );
+    }
 }
@@ -1,2 +1,5 @@
 pub mod key_selector;
 pub mod key_selector_builder;
+pub mod outlier;
+pub mod slow_start;
+pub mod weighted_random;
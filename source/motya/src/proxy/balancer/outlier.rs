@@ -0,0 +1,283 @@
+use std::{
+    collections::HashMap,
+    sync::RwLock,
+    time::{Duration, Instant},
+};
+
+use motya_config::internal::OutlierDetection;
+use pingora_load_balancing::Backend;
+use tracing::Level;
+
+use crate::proxy::log_sink::{self, LogRecord, LogSource};
+
+/// Passively ejects backends that keep failing, based on the outcome of proxied requests.
+///
+/// After `consecutive_errors` failures in a row a backend is left out of selection for
+/// `ejection_time`; when the window is over it is admitted again with a clean count.
+/// Both are reported to the log sinks as [`LogSource::Upstream`] records.
+pub struct OutlierDetector {
+    consecutive_errors: usize,
+    ejection_time: Duration,
+    states: RwLock<HashMap<String, OutlierState>>,
+}
+
+#[derive(Debug, Default)]
+struct OutlierState {
+    failures: usize,
+    ejected_until: Option<Instant>,
+}
+
+impl OutlierDetector {
+    pub fn new(config: OutlierDetection) -> Self {
+        Self {
+            consecutive_errors: config.consecutive_errors,
+            ejection_time: config.ejection_time,
+            states: RwLock::default(),
+        }
+    }
+
+    /// A 5xx response or a connect error from the backend at `addr`, described by `error`.
+    pub fn record_failure(&self, addr: &str, error: &str) {
+        self.record_failure_at(addr, error, Instant::now());
+    }
+
+    /// A response that does not count as a failure; breaks the run of errors.
+    pub fn record_success(&self, addr: &str) {
+        let mut states = self.states.write().expect("outlier lock poisoned");
+
+        if states
+            .get(addr)
+            .is_some_and(|state| state.ejected_until.is_none())
+        {
+            states.remove(addr);
+        }
+    }
+
+    /// Whether a pick of `backend` by the underlying balancer should be kept.
+    pub fn accept(&self, backend: &Backend) -> bool {
+        self.is_admitted(&backend.addr.to_string(), Instant::now())
+    }
+
+    /// Whether the backend at `addr` is currently left out of selection, without
+    /// re-admitting it when its window is over; the next pick does that.
+    pub fn is_ejected(&self, addr: &str) -> bool {
+        let states = self.states.read().expect("outlier lock poisoned");
+        states
+            .get(addr)
+            .and_then(|state| state.ejected_until)
+            .is_some_and(|until| Instant::now() < until)
+    }
+
+    fn record_failure_at(&self, addr: &str, error: &str, now: Instant) {
+        let mut states = self.states.write().expect("outlier lock poisoned");
+        let state = states.entry(addr.to_string()).or_default();
+
+        let readmitted = match state.ejected_until {
+            // requests that were in flight when the backend got ejected
+            Some(until) if now < until => return,
+            Some(_) => {
+                state.ejected_until = None;
+                true
+            }
+            None => false,
+        };
+
+        state.failures += 1;
+        let ejected = state.failures >= self.consecutive_errors;
+        if ejected {
+            state.failures = 0;
+            state.ejected_until = Some(now + self.ejection_time);
+        }
+        drop(states);
+
+        // sinks run synchronously, so only once the lock is released
+        if readmitted {
+            self.report_readmission(addr);
+        }
+        if ejected {
+            self.report_ejection(addr, error);
+        }
+    }
+
+    fn report_ejection(&self, addr: &str, error: &str) {
+        log_sink::emit(LogRecord {
+            level: Level::WARN,
+            source: LogSource::Upstream,
+            message: format!(
+                "Ejected backend {addr} for {:?} after {} consecutive errors, the last one: {error}",
+                self.ejection_time, self.consecutive_errors
+            ),
+            fields: vec![
+                ("event", "ejected".to_string()),
+                ("backend", addr.to_string()),
+                ("consecutive_errors", self.consecutive_errors.to_string()),
+                ("last_error", error.to_string()),
+                ("ejection_time", format!("{:?}", self.ejection_time)),
+            ],
+        });
+    }
+
+    fn report_readmission(&self, addr: &str) {
+        log_sink::emit(LogRecord {
+            level: Level::INFO,
+            source: LogSource::Upstream,
+            message: format!(
+                "Re-admitted backend {addr} after an ejection of {:?}",
+                self.ejection_time
+            ),
+            fields: vec![
+                ("event", "readmitted".to_string()),
+                ("backend", addr.to_string()),
+                ("ejection_time", format!("{:?}", self.ejection_time)),
+            ],
+        });
+    }
+
+    fn is_admitted(&self, addr: &str, now: Instant) -> bool {
+        {
+            let states = self.states.read().expect("outlier lock poisoned");
+            match states.get(addr).and_then(|state| state.ejected_until) {
+                None => return true,
+                Some(until) if now < until => return false,
+                Some(_) => {}
+            }
+        }
+
+        // the ejection window is over, unless a concurrent pick got here first
+        let removed = self
+            .states
+            .write()
+            .expect("outlier lock poisoned")
+            .remove(addr)
+            .is_some();
+        if removed {
+            self.report_readmission(addr);
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::proxy::log_sink::{register_sink, LogSink};
+
+    use super::*;
+
+    const ADDR: &str = "127.0.0.1:8080";
+    const EJECTION: Duration = Duration::from_secs(30);
+    const ERROR: &str = "status 503";
+
+    fn detector() -> OutlierDetector {
+        OutlierDetector::new(OutlierDetection {
+            consecutive_errors: 3,
+            ejection_time: EJECTION,
+        })
+    }
+
+    #[test]
+    fn test_ejection_state_machine() {
+        let detector = detector();
+        let start = Instant::now();
+
+        // a success in between breaks the run
+        detector.record_failure_at(ADDR, ERROR, start);
+        detector.record_failure_at(ADDR, ERROR, start);
+        detector.record_success(ADDR);
+        detector.record_failure_at(ADDR, ERROR, start);
+        detector.record_failure_at(ADDR, ERROR, start);
+        assert!(detector.is_admitted(ADDR, start));
+
+        detector.record_failure_at(ADDR, ERROR, start);
+        assert!(!detector.is_admitted(ADDR, start));
+        assert!(!detector.is_admitted(ADDR, start + EJECTION / 2));
+
+        // late results of in-flight requests neither extend nor lift the ejection
+        detector.record_failure_at(ADDR, ERROR, start + EJECTION / 2);
+        detector.record_success(ADDR);
+        assert!(!detector.is_admitted(ADDR, start + EJECTION - Duration::from_millis(1)));
+
+        let readmitted = start + EJECTION;
+        assert!(detector.is_admitted(ADDR, readmitted));
+
+        // the count starts over after re-admission
+        detector.record_failure_at(ADDR, ERROR, readmitted);
+        detector.record_failure_at(ADDR, ERROR, readmitted);
+        assert!(detector.is_admitted(ADDR, readmitted));
+        detector.record_failure_at(ADDR, ERROR, readmitted);
+        assert!(!detector.is_admitted(ADDR, readmitted));
+    }
+
+    #[test]
+    fn test_other_backends_unaffected() {
+        let detector = detector();
+        let start = Instant::now();
+
+        for _ in 0..3 {
+            detector.record_failure_at(ADDR, ERROR, start);
+        }
+
+        assert!(!detector.is_admitted(ADDR, start));
+        assert!(detector.is_admitted("127.0.0.1:8081", start));
+    }
+
+    #[derive(Default)]
+    struct MemorySink(Mutex<Vec<LogRecord>>);
+
+    impl LogSink for MemorySink {
+        fn emit(&self, record: LogRecord) {
+            if record.source == LogSource::Upstream {
+                self.0.lock().unwrap().push(record);
+            }
+        }
+    }
+
+    #[test]
+    fn test_ejection_reported() {
+        // other tests eject backends through the same sinks, this one is only used here
+        const REPORTED: &str = "127.0.0.1:9301";
+
+        let sink = Arc::new(MemorySink::default());
+        register_sink(sink.clone());
+
+        let detector = detector();
+        let start = Instant::now();
+        detector.record_failure_at(REPORTED, "status 502", start);
+        detector.record_failure_at(REPORTED, "status 502", start);
+        detector.record_failure_at(REPORTED, "connection refused", start);
+        assert!(detector.is_admitted(REPORTED, start + EJECTION));
+
+        let events = sink
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|record| record.fields.contains(&("backend", REPORTED.to_string())))
+            .cloned()
+            .collect::<Vec<_>>();
+        assert_eq!(events.len(), 2, "{events:?}");
+
+        assert_eq!(events[0].level, Level::WARN);
+        assert_eq!(
+            events[0].fields,
+            vec![
+                ("event", "ejected".to_string()),
+                ("backend", REPORTED.to_string()),
+                ("consecutive_errors", "3".to_string()),
+                ("last_error", "connection refused".to_string()),
+                ("ejection_time", "30s".to_string()),
+            ]
+        );
+
+        assert_eq!(events[1].level, Level::INFO);
+        assert_eq!(
+            events[1].fields,
+            vec![
+                ("event", "readmitted".to_string()),
+                ("backend", REPORTED.to_string()),
+                ("ejection_time", "30s".to_string()),
+            ]
+        );
+    }
+}
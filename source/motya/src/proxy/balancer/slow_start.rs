@@ -0,0 +1,164 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, RwLock,
+    },
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use pingora_load_balancing::{health_check::HealthObserve, Backend};
+use tracing::Level;
+
+use crate::proxy::log_sink::{self, LogRecord, LogSource};
+
+/// Resolution of the acceptance ratio used while a backend is ramping up.
+const RAMP_STEPS: u64 = 1000;
+
+/// Linearly ramps the share of traffic a backend receives after it becomes healthy.
+///
+/// The balancer still picks backends by their configured weight; while a backend is
+/// ramping, only a `elapsed / window` fraction of the picks landing on it is accepted,
+/// the rest fall through to the next candidate. This gives it an effective weight of
+/// `weight * elapsed / window` without rebuilding the selection tables.
+pub struct SlowStart {
+    window: Duration,
+    recovered_at: RwLock<HashMap<String, Instant>>,
+    ticks: AtomicU64,
+}
+
+impl SlowStart {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            recovered_at: RwLock::default(),
+            ticks: AtomicU64::new(0),
+        }
+    }
+
+    /// Health-check observer hook: a backend turning healthy starts its ramp,
+    /// turning unhealthy forgets it. The change is reported to the log sinks.
+    pub fn on_health_changed(&self, backend: &Backend, healthy: bool) {
+        let key = backend.addr.to_string();
+        {
+            let mut recovered_at = self.recovered_at.write().expect("slow-start lock poisoned");
+            if healthy {
+                recovered_at.insert(key.clone(), Instant::now());
+            } else {
+                recovered_at.remove(&key);
+            }
+        }
+
+        let (level, event) = match healthy {
+            true => (Level::INFO, "healthy"),
+            false => (Level::WARN, "unhealthy"),
+        };
+        log_sink::emit(LogRecord {
+            level,
+            source: LogSource::Upstream,
+            message: format!("Health check marked backend {key} {event}"),
+            fields: vec![("event", event.to_string()), ("backend", key)],
+        });
+    }
+
+    /// Whether a pick of `backend` by the underlying balancer should be kept.
+    pub fn accept(&self, backend: &Backend) -> bool {
+        let ratio = {
+            let recovered_at = self.recovered_at.read().expect("slow-start lock poisoned");
+            match recovered_at.get(&backend.addr.to_string()) {
+                None => return true,
+                Some(since) => ramp_ratio(since.elapsed(), self.window),
+            }
+        };
+
+        if ratio >= 1.0 {
+            self.recovered_at
+                .write()
+                .expect("slow-start lock poisoned")
+                .remove(&backend.addr.to_string());
+            return true;
+        }
+
+        let tick = self.ticks.fetch_add(1, Ordering::Relaxed) % RAMP_STEPS;
+        (tick as f64) < ratio * RAMP_STEPS as f64
+    }
+}
+
+/// Forwards the transitions seen by a pool's health check to its [`SlowStart`].
+pub struct SlowStartObserver(pub Arc<SlowStart>);
+
+#[async_trait]
+impl HealthObserve for SlowStartObserver {
+    async fn observe(&self, target: &Backend, healthy: bool) {
+        self.0.on_health_changed(target, healthy);
+    }
+}
+
+/// Fraction of the configured weight a backend has after being healthy for `elapsed`.
+///
+/// Starts slightly above zero so a recovered backend is never fully starved.
+pub fn ramp_ratio(elapsed: Duration, window: Duration) -> f64 {
+    if window.is_zero() || elapsed >= window {
+        return 1.0;
+    }
+
+    let min = 1.0 / RAMP_STEPS as f64;
+    (elapsed.as_secs_f64() / window.as_secs_f64()).max(min)
+}
+
+/// Effective weight of a backend configured with `weight`, after being healthy for `elapsed`.
+pub fn effective_weight(weight: usize, elapsed: Duration, window: Duration) -> f64 {
+    weight as f64 * ramp_ratio(elapsed, window)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WINDOW: Duration = Duration::from_secs(30);
+
+    #[test]
+    fn test_ramp_weight_over_time() {
+        let at = |secs: f64| effective_weight(10, Duration::from_secs_f64(secs), WINDOW);
+
+        assert!(at(0.0) > 0.0 && at(0.0) < 0.1);
+        assert!((at(7.5) - 2.5).abs() < 1e-9);
+        assert!((at(15.0) - 5.0).abs() < 1e-9);
+        assert!((at(22.5) - 7.5).abs() < 1e-9);
+        assert_eq!(at(30.0), 10.0);
+        assert_eq!(at(120.0), 10.0);
+    }
+
+    #[test]
+    fn test_ramp_is_monotonic() {
+        let mut prev = 0.0;
+        for secs in 0..=30 {
+            let ratio = ramp_ratio(Duration::from_secs(secs), WINDOW);
+            assert!(ratio >= prev);
+            prev = ratio;
+        }
+    }
+
+    #[test]
+    fn test_accept_ratio_follows_ramp() {
+        let slow_start = SlowStart::new(WINDOW);
+        let backend = Backend::new("127.0.0.1:8080").unwrap();
+        let other = Backend::new("127.0.0.1:8081").unwrap();
+
+        assert!(slow_start.accept(&backend));
+
+        slow_start.on_health_changed(&backend, true);
+
+        let accepted = (0..RAMP_STEPS)
+            .filter(|_| slow_start.accept(&backend))
+            .count();
+        assert!(accepted < (RAMP_STEPS / 100) as usize);
+
+        // backends that never went through recovery are not throttled
+        assert!((0..100).all(|_| slow_start.accept(&other)));
+
+        slow_start.on_health_changed(&backend, false);
+        assert!(slow_start.accept(&backend));
+    }
+}
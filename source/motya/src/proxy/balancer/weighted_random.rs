@@ -0,0 +1,142 @@
+use std::{
+    collections::BTreeSet,
+    hash::{BuildHasher, Hasher, RandomState},
+    sync::Arc,
+};
+
+use pingora_load_balancing::{
+    selection::{BackendIter, BackendSelection},
+    Backend,
+};
+
+/// Picks a backend at random, each with a probability proportional to its weight.
+///
+/// The weights are laid out as a cumulative distribution once, when the backends are
+/// updated, so a pick is a binary search over it.
+pub struct WeightedRandom {
+    backends: Box<[Backend]>,
+    /// Running total of the weights, `cumulative[i]` covers the backends up to `i`.
+    cumulative: Box<[u64]>,
+}
+
+impl WeightedRandom {
+    /// Index of the backend whose share of the distribution contains `point`.
+    fn index_of(&self, point: u64) -> usize {
+        self.cumulative.partition_point(|&upper| upper <= point)
+    }
+
+    fn total(&self) -> u64 {
+        self.cumulative.last().copied().unwrap_or(0)
+    }
+}
+
+impl BackendSelection for WeightedRandom {
+    type Iter = WeightedRandomIter;
+    type Config = ();
+
+    fn build(backends: &BTreeSet<Backend>) -> Self {
+        // a server with no weight is never picked
+        let backends = backends
+            .iter()
+            .filter(|backend| backend.weight > 0)
+            .cloned()
+            .collect::<Box<[_]>>();
+
+        let mut total = 0;
+        let cumulative = backends
+            .iter()
+            .map(|backend| {
+                total += backend.weight as u64;
+                total
+            })
+            .collect();
+
+        Self {
+            backends,
+            cumulative,
+        }
+    }
+
+    fn iter(self: &Arc<Self>, _key: &[u8]) -> Self::Iter {
+        WeightedRandomIter {
+            selection: self.clone(),
+        }
+    }
+}
+
+/// Endless independent draws, the caller stops once it accepts a backend.
+pub struct WeightedRandomIter {
+    selection: Arc<WeightedRandom>,
+}
+
+impl BackendIter for WeightedRandomIter {
+    fn next(&mut self) -> Option<&Backend> {
+        let total = self.selection.total();
+        if total == 0 {
+            return None;
+        }
+
+        let point = random_u64() % total;
+        Some(&self.selection.backends[self.selection.index_of(point)])
+    }
+}
+
+/// A random number from the std hasher, whose keys are seeded randomly and differ
+/// for every `RandomState`.
+fn random_u64() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn selection(weights: &[usize]) -> Arc<WeightedRandom> {
+        let backends = weights
+            .iter()
+            .enumerate()
+            .map(|(i, weight)| {
+                Backend::new_with_weight(&format!("127.0.0.1:{}", 3000 + i), *weight).unwrap()
+            })
+            .collect::<BTreeSet<_>>();
+
+        Arc::new(WeightedRandom::build(&backends))
+    }
+
+    #[test]
+    fn test_cumulative_distribution() {
+        let selection = selection(&[1, 0, 3]);
+
+        assert_eq!(&*selection.cumulative, &[1, 4]);
+        assert_eq!(selection.index_of(0), 0);
+        assert_eq!(selection.index_of(1), 1);
+        assert_eq!(selection.index_of(3), 1);
+
+        assert!(selection(&[0]).iter(b"").next().is_none());
+    }
+
+    #[test]
+    fn test_frequencies_follow_weights() {
+        let weights = [1, 2, 7];
+        let selection = selection(&weights);
+        let draws = 100_000;
+
+        let mut counts = [0usize; 3];
+        let mut iter = selection.iter(b"");
+        for _ in 0..draws {
+            let backend = iter.next().unwrap();
+            counts[usize::from(backend.addr.as_inet().unwrap().port() - 3000)] += 1;
+        }
+
+        let total_weight = weights.iter().sum::<usize>() as f64;
+        for (count, weight) in counts.iter().zip(weights) {
+            let expected = weight as f64 / total_weight;
+            let observed = *count as f64 / draws as f64;
+            // about ten standard deviations at this many draws
+            assert!(
+                (observed - expected).abs() < 0.015,
+                "weight {weight}: expected {expected:.3}, observed {observed:.3}"
+            );
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use bytes::{Bytes, BytesMut};
+
+/// Holds back the chunks of a body, so it's passed on in one piece once complete.
+///
+/// Used for connectors with `buffer-request-body` or `buffer-response-body` turned on.
+#[derive(Debug, Default)]
+pub struct BodyBuffer {
+    chunks: BytesMut,
+}
+
+/// A body grew past the `buffer-body-limit` it was held under.
+#[derive(Debug, PartialEq, Eq)]
+pub struct BodyTooLarge;
+
+impl BodyBuffer {
+    /// Takes the chunk out of `body`, putting the whole body back on the last one.
+    ///
+    /// Fails once the held body would grow past `limit` bytes, dropping what was held.
+    pub fn hold(
+        &mut self,
+        body: &mut Option<Bytes>,
+        end_of_stream: bool,
+        limit: usize,
+    ) -> Result<(), BodyTooLarge> {
+        if let Some(chunk) = body.take() {
+            if self.chunks.len() + chunk.len() > limit {
+                self.chunks = BytesMut::new();
+                return Err(BodyTooLarge);
+            }
+            self.chunks.extend_from_slice(&chunk);
+        }
+        if end_of_stream && !self.chunks.is_empty() {
+            *body = Some(self.chunks.split().freeze());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_body_released_at_end_of_stream() {
+        let mut buffer = BodyBuffer::default();
+
+        let mut body = Some(Bytes::from_static(b"hello "));
+        buffer.hold(&mut body, false, 1024).unwrap();
+        assert_eq!(body, None);
+
+        let mut body = Some(Bytes::from_static(b"world"));
+        buffer.hold(&mut body, true, 1024).unwrap();
+        assert_eq!(body, Some(Bytes::from_static(b"hello world")));
+    }
+
+    #[test]
+    fn test_empty_body_stays_empty() {
+        let mut buffer = BodyBuffer::default();
+
+        let mut body = None;
+        buffer.hold(&mut body, true, 1024).unwrap();
+        assert_eq!(body, None);
+    }
+
+    #[test]
+    fn test_body_over_limit() {
+        let mut buffer = BodyBuffer::default();
+
+        let mut body = Some(Bytes::from_static(b"hello "));
+        buffer.hold(&mut body, false, 8).unwrap();
+
+        let mut body = Some(Bytes::from_static(b"world"));
+        assert_eq!(buffer.hold(&mut body, true, 8), Err(BodyTooLarge));
+        assert_eq!(body, None);
+    }
+}
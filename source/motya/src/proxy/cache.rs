@@ -0,0 +1,760 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use bytes::{Bytes, BytesMut};
+use http::Method;
+use pingora::{connectors::http::Connector, prelude::HttpPeer, Error, ErrorType};
+use pingora_http::{RequestHeader, ResponseHeader};
+
+use motya_config::common_types::cache::CacheConfig;
+
+use crate::proxy::balancer::key_selector::{KeyPart, KeySelector, KeySourceContext};
+
+/// Statuses whose responses can be stored, per RFC 9111 "heuristically cacheable" codes.
+const CACHEABLE_STATUSES: &[u16] = &[200, 203, 204, 300, 301, 308, 404, 405, 410, 414, 501];
+
+/// Headers that only concern one connection, see RFC 9110 section 7.6.1. A stored response
+/// is served over other connections, with framing of its own.
+const HOP_BY_HOP: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    method: Method,
+    hash: u64,
+}
+
+#[derive(Debug)]
+pub struct CachedResponse {
+    pub header: ResponseHeader,
+    pub body: Bytes,
+    stored_at: Instant,
+    expires_at: Instant,
+    /// End of the `stale-while-revalidate` window, equal to `expires_at` without one.
+    stale_until: Instant,
+}
+
+impl CachedResponse {
+    pub fn age(&self) -> Duration {
+        self.stored_at.elapsed()
+    }
+
+    fn is_fresh(&self) -> bool {
+        Instant::now() < self.expires_at
+    }
+
+    fn is_servable(&self) -> bool {
+        Instant::now() < self.stale_until
+    }
+}
+
+/// Outcome of a cache lookup.
+#[derive(Debug)]
+pub enum Lookup {
+    Fresh(Arc<CachedResponse>),
+    /// Expired, but still within the `stale-while-revalidate` window.
+    Stale(Arc<CachedResponse>),
+    Miss,
+}
+
+/// A response being read from the upstream, stored once its body is complete.
+pub struct CacheFill {
+    key: CacheKey,
+    header: ResponseHeader,
+    ttl: Duration,
+    body: BytesMut,
+}
+
+#[derive(Default)]
+struct Store {
+    entries: HashMap<CacheKey, Arc<CachedResponse>>,
+    /// Insertion order, oldest first, used for eviction.
+    order: VecDeque<CacheKey>,
+    size: usize,
+}
+
+impl Store {
+    fn remove(&mut self, key: &CacheKey) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.size -= entry.body.len();
+            self.order.retain(|k| k != key);
+        }
+    }
+}
+
+/// In-memory response cache of one route, keyed by a key profile.
+pub struct ResponseCache {
+    selector: KeySelector,
+    ttl: Duration,
+    stale_while_revalidate: Duration,
+    max_size: usize,
+    /// Largest body a single entry may have, at most `max_size`.
+    max_object_size: usize,
+    methods: Vec<Method>,
+    store: Mutex<Store>,
+    /// Keys with a background revalidation in flight.
+    revalidating: Mutex<HashSet<CacheKey>>,
+    /// Pool of upstream connections the revalidations are sent over.
+    connector: Connector,
+}
+
+impl ResponseCache {
+    pub fn new(config: CacheConfig) -> Result<Self, String> {
+        Ok(Self {
+            selector: KeySelector::try_from(config.key)?,
+            ttl: config.ttl,
+            stale_while_revalidate: config.stale_while_revalidate.unwrap_or_default(),
+            max_size: config.max_size,
+            max_object_size: config
+                .max_object_size
+                .map_or(config.max_size, |size| size.min(config.max_size)),
+            methods: config.methods,
+            store: Mutex::default(),
+            revalidating: Mutex::default(),
+            connector: Connector::new(None),
+        })
+    }
+
+    /// Computes the key of a request, or `None` if the request can't be served from cache.
+    pub fn key<C: KeySourceContext>(&self, method: &Method, ctx: &C) -> Option<CacheKey> {
+        if !self.methods.contains(method) {
+            return None;
+        }
+
+        Some(CacheKey {
+            method: method.clone(),
+            hash: self.selector.evaluate(ctx)?.hash,
+        })
+    }
+
+    /// Returns the entry stored under `key`, dropping it once it can't be served even stale.
+    pub fn lookup(&self, key: &CacheKey) -> Lookup {
+        let mut store = self.store.lock().expect("cache lock poisoned");
+
+        let Some(entry) = store.entries.get(key).cloned() else {
+            return Lookup::Miss;
+        };
+
+        if entry.is_fresh() {
+            Lookup::Fresh(entry)
+        } else if entry.is_servable() {
+            Lookup::Stale(entry)
+        } else {
+            store.remove(key);
+            Lookup::Miss
+        }
+    }
+
+    /// Claims the revalidation of a stale entry.
+    ///
+    /// Returns `None` if another request is already revalidating `key`, so at most
+    /// one refresh per key reaches the upstream at a time. The claim is released
+    /// when the returned [`Revalidation`] is dropped.
+    pub fn claim_revalidation(self: &Arc<Self>, key: &CacheKey) -> Option<Revalidation> {
+        let mut revalidating = self.revalidating.lock().expect("cache lock poisoned");
+
+        if !revalidating.insert(key.clone()) {
+            return None;
+        }
+
+        Some(Revalidation {
+            cache: self.clone(),
+            key: key.clone(),
+        })
+    }
+
+    /// Starts storing the upstream response to `request` if it's cacheable.
+    pub fn begin_fill(
+        &self,
+        key: CacheKey,
+        request: &RequestHeader,
+        header: &ResponseHeader,
+    ) -> Option<CacheFill> {
+        let ttl = self.ttl_for(request, header)?;
+
+        let content_length = header
+            .headers
+            .get(http::header::CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<usize>().ok());
+        if content_length.is_some_and(|length| length > self.max_object_size) {
+            return None;
+        }
+
+        let mut header = header.clone();
+        strip_hop_by_hop(&mut header);
+
+        Some(CacheFill {
+            key,
+            header,
+            ttl,
+            body: BytesMut::new(),
+        })
+    }
+
+    /// Appends a body chunk to `fill`, giving up on responses over `max-object-size`,
+    /// whose remaining chunks are then only passed through.
+    pub fn extend_fill(&self, fill: &mut Option<CacheFill>, chunk: &[u8]) {
+        if let Some(f) = fill {
+            if f.body.len() + chunk.len() > self.max_object_size {
+                *fill = None;
+            } else {
+                f.body.extend_from_slice(chunk);
+            }
+        }
+    }
+
+    /// Stores a completely read response, evicting the oldest entries to make room.
+    pub fn finish_fill(&self, fill: CacheFill) {
+        let body = fill.body.freeze();
+        let now = Instant::now();
+
+        // a response that came chunked is served with the length of its whole body
+        let mut header = fill.header;
+        let framed = fill.key.method == Method::HEAD
+            || header.status.as_u16() == 204
+            || header.headers.contains_key(http::header::CONTENT_LENGTH);
+        if !framed {
+            if let Err(e) = header.insert_header(http::header::CONTENT_LENGTH, body.len()) {
+                tracing::warn!("Failed to set the Content-Length of a cached response: {e}");
+            }
+        }
+
+        let mut store = self.store.lock().expect("cache lock poisoned");
+        store.remove(&fill.key);
+
+        while store.size + body.len() > self.max_size {
+            let Some(oldest) = store.order.pop_front() else {
+                break;
+            };
+            if let Some(entry) = store.entries.remove(&oldest) {
+                store.size -= entry.body.len();
+            }
+        }
+
+        store.size += body.len();
+        store.order.push_back(fill.key.clone());
+        store.entries.insert(
+            fill.key,
+            Arc::new(CachedResponse {
+                header,
+                body,
+                stored_at: now,
+                expires_at: now + fill.ttl,
+                stale_until: now + fill.ttl + self.stale_while_revalidate,
+            }),
+        );
+    }
+
+    /// Freshness lifetime of the response to `request`, or `None` if it must not be stored.
+    ///
+    /// `s-maxage` and `max-age` in `Cache-Control` take precedence over the configured `ttl`.
+    /// As the cache is shared by every client, a response setting cookies or answering an
+    /// `Authorization` request is only stored when marked `public` or given an `s-maxage`,
+    /// and one that `Vary`s is only stored when the key covers the headers it varies on.
+    fn ttl_for(&self, request: &RequestHeader, header: &ResponseHeader) -> Option<Duration> {
+        if !CACHEABLE_STATUSES.contains(&header.status.as_u16()) {
+            return None;
+        }
+
+        let mut max_age = None;
+        let mut s_maxage = None;
+        let mut public = false;
+
+        for value in header.headers.get_all(http::header::CACHE_CONTROL) {
+            let value = value.to_str().ok()?;
+
+            for directive in value.split(',').map(str::trim) {
+                let (name, arg) = directive
+                    .split_once('=')
+                    .map_or((directive, None), |(n, a)| (n, Some(a.trim_matches('"'))));
+
+                match name.to_ascii_lowercase().as_str() {
+                    "no-store" | "no-cache" | "private" => return None,
+                    "public" => public = true,
+                    "max-age" => max_age = arg.and_then(|a| a.parse::<u64>().ok()),
+                    "s-maxage" => s_maxage = arg.and_then(|a| a.parse::<u64>().ok()),
+                    _ => {}
+                }
+            }
+        }
+
+        let personal = request.headers.contains_key(http::header::AUTHORIZATION)
+            || header.headers.contains_key(http::header::SET_COOKIE);
+        if personal && !public && s_maxage.is_none() {
+            return None;
+        }
+        if !self.keyed_on_vary(header) {
+            return None;
+        }
+
+        match s_maxage.or(max_age) {
+            Some(0) => None,
+            Some(secs) => Some(Duration::from_secs(secs)),
+            None => Some(self.ttl),
+        }
+    }
+
+    /// Whether every header the response `Vary`s on is part of the key, so that the entry
+    /// is only served to requests sending the same values. `Vary: *` never is.
+    fn keyed_on_vary(&self, header: &ResponseHeader) -> bool {
+        header
+            .headers
+            .get_all(http::header::VARY)
+            .iter()
+            .all(|value| {
+                value.to_str().is_ok_and(|value| {
+                    value
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|name| !name.is_empty())
+                        .all(|name| self.keyed_on(name))
+                })
+            })
+    }
+
+    /// Whether every way of building the key includes the header `name`.
+    fn keyed_on(&self, name: &str) -> bool {
+        self.selector.extraction_strategies.iter().all(|strategy| {
+            strategy.parts.iter().any(|part| match part {
+                KeyPart::Header(header) => header.eq_ignore_ascii_case(name),
+                KeyPart::UserAgent => name.eq_ignore_ascii_case("user-agent"),
+                _ => false,
+            })
+        })
+    }
+}
+
+/// Removes the hop-by-hop headers of a response, including those its `Connection` names.
+fn strip_hop_by_hop(header: &mut ResponseHeader) {
+    let named = header
+        .headers
+        .get_all(http::header::CONNECTION)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|name| name.trim().to_ascii_lowercase())
+        .filter(|name| !name.is_empty())
+        .collect::<Vec<_>>();
+
+    for name in HOP_BY_HOP
+        .iter()
+        .copied()
+        .chain(named.iter().map(String::as_str))
+    {
+        header.remove_header(name);
+    }
+}
+
+/// An in-flight refresh of a stale entry, see [`ResponseCache::claim_revalidation`].
+pub struct Revalidation {
+    cache: Arc<ResponseCache>,
+    key: CacheKey,
+}
+
+impl Revalidation {
+    /// Fetches the request again from `peer` and stores the response if it's cacheable.
+    ///
+    /// On failure the stale entry is kept, to be served until its window ends.
+    pub async fn run(self, peer: HttpPeer, request: RequestHeader) {
+        let fetched = fetch(
+            &self.cache.connector,
+            &peer,
+            &request,
+            self.cache.max_object_size,
+        );
+        match fetched.await {
+            Ok((header, body)) => self.finish(&request, &header, &body),
+            Err(err) => tracing::warn!("Failed to revalidate {}: {err}", request.uri),
+        }
+    }
+
+    fn finish(&self, request: &RequestHeader, header: &ResponseHeader, body: &[u8]) {
+        let mut fill = self.cache.begin_fill(self.key.clone(), request, header);
+        self.cache.extend_fill(&mut fill, body);
+
+        if let Some(fill) = fill {
+            self.cache.finish_fill(fill);
+        }
+    }
+}
+
+impl Drop for Revalidation {
+    fn drop(&mut self) {
+        self.cache
+            .revalidating
+            .lock()
+            .expect("cache lock poisoned")
+            .remove(&self.key);
+    }
+}
+
+/// Sends `request` to `peer` outside of any downstream session, with the TLS settings of
+/// the peer and over a connection of `connector`, which the next revalidation can reuse.
+///
+/// A body over `max_body` is not read to its end, it couldn't be stored anyway.
+async fn fetch(
+    connector: &Connector,
+    peer: &HttpPeer,
+    request: &RequestHeader,
+    max_body: usize,
+) -> pingora::Result<(ResponseHeader, Bytes)> {
+    let (mut session, _reused) = connector.get_http_session(peer).await?;
+    session
+        .write_request_header(Box::new(request.clone()))
+        .await?;
+    session.finish_request_body().await?;
+    session.read_response_header().await?;
+
+    // the body is stored whole, `begin_fill` drops the framing it came with
+    let mut header = session
+        .response_header()
+        .cloned()
+        .ok_or_else(|| Error::explain(ErrorType::InvalidHTTPHeader, "no response header"))?;
+
+    let mut body = BytesMut::new();
+    while let Some(chunk) = session.read_response_body().await? {
+        if body.len() + chunk.len() > max_body {
+            return Err(Error::explain(
+                ErrorType::InternalError,
+                "response is larger than the cache's 'max-object-size'",
+            ));
+        }
+        body.extend_from_slice(&chunk);
+    }
+    connector.release_http_session(session, peer, None).await;
+
+    header.insert_header(http::header::CONTENT_LENGTH, body.len())?;
+
+    Ok((header, body.freeze()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+
+    use http::uri::PathAndQuery;
+    use motya_config::common_types::definitions::{HashAlgorithm, KeyTemplateConfig};
+
+    use super::*;
+
+    struct PathOnly(PathAndQuery);
+
+    impl KeySourceContext for PathOnly {
+        fn get_header(&self, _name: &str) -> Option<&str> {
+            None
+        }
+        fn get_cookie(&self, _name: &str) -> Option<&str> {
+            None
+        }
+        fn get_ip(&self) -> Option<IpAddr> {
+            None
+        }
+        fn get_path(&self) -> &PathAndQuery {
+            &self.0
+        }
+    }
+
+    fn cache(max_size: usize) -> ResponseCache {
+        cache_with_stale(max_size, None)
+    }
+
+    fn cache_with_stale(max_size: usize, stale: Option<Duration>) -> ResponseCache {
+        cache_with(max_size, None, stale)
+    }
+
+    fn cache_with(
+        max_size: usize,
+        max_object_size: Option<usize>,
+        stale: Option<Duration>,
+    ) -> ResponseCache {
+        cache_keyed("${uri-path}", max_size, max_object_size, stale)
+    }
+
+    fn cache_keyed(
+        source: &str,
+        max_size: usize,
+        max_object_size: Option<usize>,
+        stale: Option<Duration>,
+    ) -> ResponseCache {
+        ResponseCache::new(CacheConfig {
+            key: KeyTemplateConfig {
+                source: source.to_string(),
+                fallback: None,
+                algorithm: HashAlgorithm {
+                    name: "xxhash64".to_string(),
+                    seed: None,
+                },
+                transforms: vec![],
+            },
+            ttl: Duration::from_secs(60),
+            stale_while_revalidate: stale,
+            max_size,
+            max_object_size,
+            methods: vec![Method::GET],
+        })
+        .unwrap()
+    }
+
+    fn key(cache: &ResponseCache, path: &'static str) -> CacheKey {
+        cache
+            .key(&Method::GET, &PathOnly(PathAndQuery::from_static(path)))
+            .unwrap()
+    }
+
+    fn get() -> RequestHeader {
+        RequestHeader::build("GET", b"/page", None).unwrap()
+    }
+
+    fn fill(cache: &ResponseCache, key: CacheKey, header: &ResponseHeader, body: &[u8]) {
+        let mut fill = cache.begin_fill(key, &get(), header);
+        cache.extend_fill(&mut fill, body);
+        cache.finish_fill(fill.expect("response should be cacheable"));
+    }
+
+    #[test]
+    fn test_miss_populates_and_hit_returns_body() {
+        let cache = cache(1024);
+        let page = key(&cache, "/page");
+
+        assert!(matches!(cache.lookup(&page), Lookup::Miss));
+
+        let header = ResponseHeader::build(200, None).unwrap();
+        fill(&cache, page.clone(), &header, b"hello");
+
+        let Lookup::Fresh(hit) = cache.lookup(&page) else {
+            panic!("should be a hit");
+        };
+        assert_eq!(hit.body, Bytes::from_static(b"hello"));
+        assert_eq!(hit.header.status, 200);
+
+        assert!(matches!(cache.lookup(&key(&cache, "/other")), Lookup::Miss));
+    }
+
+    #[test]
+    fn test_method_not_cached() {
+        let cache = cache(1024);
+        let ctx = PathOnly(PathAndQuery::from_static("/page"));
+
+        assert!(cache.key(&Method::POST, &ctx).is_none());
+    }
+
+    #[test]
+    fn test_cache_control_overrides_ttl() {
+        let cache = cache(1024);
+
+        let mut header = ResponseHeader::build(200, None).unwrap();
+        header
+            .insert_header("Cache-Control", "public, max-age=5")
+            .unwrap();
+        assert_eq!(cache.ttl_for(&get(), &header), Some(Duration::from_secs(5)));
+
+        header
+            .insert_header("Cache-Control", "max-age=5, s-maxage=10")
+            .unwrap();
+        assert_eq!(
+            cache.ttl_for(&get(), &header),
+            Some(Duration::from_secs(10))
+        );
+
+        header.insert_header("Cache-Control", "no-store").unwrap();
+        assert_eq!(cache.ttl_for(&get(), &header), None);
+
+        let header = ResponseHeader::build(500, None).unwrap();
+        assert_eq!(cache.ttl_for(&get(), &header), None);
+    }
+
+    #[test]
+    fn test_personal_responses_not_shared() {
+        let cache = cache(1024);
+        let mut authorized = get();
+        authorized
+            .insert_header("Authorization", "Bearer secret")
+            .unwrap();
+        let plain = ResponseHeader::build(200, None).unwrap();
+        let mut cookie = ResponseHeader::build(200, None).unwrap();
+        cookie.insert_header("Set-Cookie", "session=abc").unwrap();
+
+        assert_eq!(cache.ttl_for(&authorized, &plain), None);
+        assert_eq!(cache.ttl_for(&get(), &cookie), None);
+
+        // unless the upstream says they can be shared
+        let mut public = plain.clone();
+        public.insert_header("Cache-Control", "public").unwrap();
+        assert_eq!(
+            cache.ttl_for(&authorized, &public),
+            Some(Duration::from_secs(60))
+        );
+        cookie
+            .insert_header("Cache-Control", "s-maxage=10")
+            .unwrap();
+        assert_eq!(
+            cache.ttl_for(&get(), &cookie),
+            Some(Duration::from_secs(10))
+        );
+    }
+
+    #[test]
+    fn test_vary_only_within_key() {
+        let by_path = cache(1024);
+        let by_encoding = cache_keyed("${uri-path}:${header-accept-encoding}", 1024, None, None);
+
+        let mut header = ResponseHeader::build(200, None).unwrap();
+        header.insert_header("Vary", "Accept-Encoding").unwrap();
+        assert_eq!(by_path.ttl_for(&get(), &header), None);
+        assert_eq!(
+            by_encoding.ttl_for(&get(), &header),
+            Some(Duration::from_secs(60))
+        );
+
+        header.insert_header("Vary", "*").unwrap();
+        assert_eq!(by_encoding.ttl_for(&get(), &header), None);
+    }
+
+    #[test]
+    fn test_hop_by_hop_headers_not_stored() {
+        let cache = cache(1024);
+        let page = key(&cache, "/page");
+
+        let mut header = ResponseHeader::build(200, None).unwrap();
+        header
+            .insert_header("Transfer-Encoding", "chunked")
+            .unwrap();
+        header
+            .insert_header("Connection", "keep-alive, x-hop")
+            .unwrap();
+        header.insert_header("X-Hop", "1").unwrap();
+        header.insert_header("X-Kept", "1").unwrap();
+        fill(&cache, page.clone(), &header, b"hello");
+
+        let Lookup::Fresh(hit) = cache.lookup(&page) else {
+            panic!("should be a hit");
+        };
+        for name in ["transfer-encoding", "connection", "x-hop"] {
+            assert!(!hit.header.headers.contains_key(name), "{name} was stored");
+        }
+        assert!(hit.header.headers.contains_key("x-kept"));
+        // the chunked body is served with its whole length instead
+        assert_eq!(hit.header.headers["content-length"], "5");
+    }
+
+    #[test]
+    fn test_oldest_entries_are_evicted() {
+        let cache = cache(8);
+        let header = ResponseHeader::build(200, None).unwrap();
+        let first = key(&cache, "/first");
+        let second = key(&cache, "/second");
+
+        fill(&cache, first.clone(), &header, b"12345");
+        fill(&cache, second.clone(), &header, b"67890");
+
+        assert!(matches!(cache.lookup(&first), Lookup::Miss));
+        assert!(matches!(cache.lookup(&second), Lookup::Fresh(_)));
+
+        let mut too_big = cache.begin_fill(key(&cache, "/big"), &get(), &header);
+        cache.extend_fill(&mut too_big, b"123456789");
+        assert!(too_big.is_none());
+    }
+
+    #[test]
+    fn test_max_object_size() {
+        let cache = cache_with(1024, Some(8), None);
+        let header = ResponseHeader::build(200, None).unwrap();
+        let small = key(&cache, "/small");
+        let large = key(&cache, "/large");
+
+        fill(&cache, small.clone(), &header, b"1234");
+        assert!(matches!(cache.lookup(&small), Lookup::Fresh(_)));
+
+        // the limit is only hit mid-stream, after the first chunk was taken
+        let mut fill = cache.begin_fill(large.clone(), &get(), &header);
+        cache.extend_fill(&mut fill, b"12345");
+        assert!(fill.is_some());
+        cache.extend_fill(&mut fill, b"67890");
+        assert!(fill.is_none());
+        assert!(matches!(cache.lookup(&large), Lookup::Miss));
+
+        // a declared length over the limit is never collected
+        let mut header = ResponseHeader::build(200, None).unwrap();
+        header.insert_header("Content-Length", "9").unwrap();
+        assert!(cache.begin_fill(large, &get(), &header).is_none());
+    }
+
+    #[test]
+    fn test_expired_entry_is_stale_within_window() {
+        let cache = cache_with_stale(1024, Some(Duration::from_secs(30)));
+        let page = key(&cache, "/page");
+
+        let mut header = ResponseHeader::build(200, None).unwrap();
+        header.insert_header("Cache-Control", "max-age=1").unwrap();
+        fill(&cache, page.clone(), &header, b"old");
+
+        let no_window = cache_with_stale(1024, None);
+        fill(&no_window, page.clone(), &header, b"old");
+
+        std::thread::sleep(Duration::from_millis(1100));
+
+        let Lookup::Stale(hit) = cache.lookup(&page) else {
+            panic!("should be served stale");
+        };
+        assert_eq!(hit.body, Bytes::from_static(b"old"));
+
+        assert!(matches!(no_window.lookup(&page), Lookup::Miss));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_single_revalidation_per_key() {
+        let cache = Arc::new(cache_with_stale(1024, Some(Duration::from_secs(30))));
+        let page = key(&cache, "/page");
+        let barrier = Arc::new(tokio::sync::Barrier::new(16));
+
+        let tasks = (0..16)
+            .map(|_| {
+                let cache = cache.clone();
+                let page = page.clone();
+                let barrier = barrier.clone();
+                tokio::spawn(async move {
+                    barrier.wait().await;
+                    let claim = cache.claim_revalidation(&page);
+                    // hold the claim until every task has tried
+                    barrier.wait().await;
+                    claim.is_some()
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut claimed = 0;
+        for task in tasks {
+            if task.await.unwrap() {
+                claimed += 1;
+            }
+        }
+        assert_eq!(claimed, 1);
+
+        // the claim is released once the revalidation is done
+        let claim = cache
+            .claim_revalidation(&page)
+            .expect("claim should be free");
+        assert!(cache.claim_revalidation(&page).is_none());
+
+        let header = ResponseHeader::build(200, None).unwrap();
+        claim.finish(&get(), &header, b"new");
+        drop(claim);
+
+        let Lookup::Fresh(hit) = cache.lookup(&page) else {
+            panic!("revalidated entry should be fresh");
+        };
+        assert_eq!(hit.body, Bytes::from_static(b"new"));
+        assert!(cache.claim_revalidation(&page).is_some());
+    }
+}
@@ -0,0 +1,156 @@
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use pingora::{
+    apps::{HttpServerApp, HttpServerOptions, ReusedHttpStream},
+    protocols::http::{v2::server::H2Options, ServerSession},
+    server::ShutdownWatch,
+};
+
+use motya_config::common_types::listeners::{ListenerKind, Listeners};
+
+use crate::proxy::concurrency::find_listener;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Timeouts {
+    read: Option<Duration>,
+    write: Option<Duration>,
+}
+
+/// Per-listener `client-read-timeout` and `client-write-timeout` settings.
+#[derive(Debug, Clone, Default)]
+pub struct ClientTimeouts {
+    listeners: Vec<(SocketAddr, Timeouts)>,
+}
+
+impl ClientTimeouts {
+    pub fn from_listeners(listeners: &Listeners) -> Self {
+        let listeners = listeners
+            .list_cfgs
+            .iter()
+            .filter_map(|cfg| {
+                let timeouts = Timeouts {
+                    read: cfg.client_read_timeout,
+                    write: cfg.client_write_timeout,
+                };
+
+                match &cfg.source {
+                    ListenerKind::Tcp { addr, .. }
+                        if timeouts.read.is_some() || timeouts.write.is_some() =>
+                    {
+                        let addr = addr.parse::<SocketAddr>().expect(
+                            "Listener address must be valid after parsing the configuration",
+                        );
+                        Some((addr, timeouts))
+                    }
+                    _ => None,
+                }
+            })
+            .collect();
+
+        Self { listeners }
+    }
+
+    /// Applies the timeouts of the listener that accepted the connection to its session.
+    ///
+    /// A stalled read or write then fails the request and the connection is dropped.
+    fn apply(&self, session: &mut ServerSession) {
+        let local_addr = session
+            .server_addr()
+            .and_then(|addr| addr.as_inet())
+            .copied();
+        let Some(timeouts) = local_addr.and_then(|addr| self.find(&addr)) else {
+            return;
+        };
+
+        if timeouts.read.is_some() {
+            session.set_read_timeout(timeouts.read);
+        }
+        if timeouts.write.is_some() {
+            session.set_write_timeout(timeouts.write);
+        }
+    }
+
+    fn find(&self, local_addr: &SocketAddr) -> Option<&Timeouts> {
+        find_listener(&self.listeners, local_addr)
+    }
+}
+
+/// Wraps an HTTP app, setting the client timeouts on each session before the app reads its
+/// request header, so that the header read is bounded too.
+pub struct ClientTimeoutsApp<A> {
+    inner: Arc<A>,
+    timeouts: ClientTimeouts,
+}
+
+impl<A> ClientTimeoutsApp<A> {
+    pub fn new(inner: A, timeouts: ClientTimeouts) -> Self {
+        Self {
+            inner: Arc::new(inner),
+            timeouts,
+        }
+    }
+}
+
+#[async_trait]
+impl<A> HttpServerApp for ClientTimeoutsApp<A>
+where
+    A: HttpServerApp + Send + Sync + 'static,
+{
+    async fn process_new_http(
+        self: &Arc<Self>,
+        mut session: ServerSession,
+        shutdown: &ShutdownWatch,
+    ) -> Option<ReusedHttpStream> {
+        self.timeouts.apply(&mut session);
+        self.inner.process_new_http(session, shutdown).await
+    }
+
+    fn h2_options(&self) -> Option<H2Options> {
+        self.inner.h2_options()
+    }
+
+    fn server_options(&self) -> Option<&HttpServerOptions> {
+        self.inner.server_options()
+    }
+
+    async fn http_cleanup(&self) {
+        self.inner.http_cleanup().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use motya_config::common_types::listeners::ListenerConfig;
+
+    use super::*;
+
+    fn listener(addr: &str, read: Option<Duration>) -> ListenerConfig {
+        ListenerConfig {
+            client_read_timeout: read,
+            ..ListenerConfig::new(ListenerKind::Tcp {
+                addr: addr.to_string(),
+                tls: None,
+                offer_h2: false,
+            })
+        }
+    }
+
+    #[test]
+    fn test_timeouts_follow_listener() {
+        let timeouts = ClientTimeouts::from_listeners(&Listeners {
+            list_cfgs: vec![
+                listener("0.0.0.0:8080", Some(Duration::from_secs(5))),
+                listener("0.0.0.0:9090", None),
+            ],
+        });
+
+        let local: SocketAddr = "10.0.0.5:8080".parse().unwrap();
+        let found = timeouts.find(&local).unwrap();
+        assert_eq!(found.read, Some(Duration::from_secs(5)));
+        assert_eq!(found.write, None);
+
+        let other: SocketAddr = "10.0.0.5:9090".parse().unwrap();
+        assert!(timeouts.find(&other).is_none());
+    }
+}
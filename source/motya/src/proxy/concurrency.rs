@@ -0,0 +1,166 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use motya_config::common_types::listeners::{ListenerKind, Listeners};
+
+/// Outcome of trying to admit a request through a [`ConcurrencyGate`].
+#[derive(Debug)]
+pub enum Admission {
+    /// The listener has no `max-concurrent` limit.
+    Unlimited,
+    /// The request holds a slot until the permit is dropped.
+    Admitted(OwnedSemaphorePermit),
+    /// All slots of the listener are taken.
+    Rejected,
+}
+
+/// Per-listener cap on simultaneous in-flight requests.
+///
+/// A permit is stored in the request context, so it's released when the
+/// context is dropped at the end of the request, on success and error paths alike.
+#[derive(Debug, Clone, Default)]
+pub struct ConcurrencyGate {
+    limits: Vec<(SocketAddr, Arc<Semaphore>)>,
+}
+
+impl ConcurrencyGate {
+    pub fn from_listeners(listeners: &Listeners) -> Self {
+        let limits = listeners
+            .list_cfgs
+            .iter()
+            .filter_map(|cfg| match (&cfg.source, cfg.max_concurrent) {
+                (ListenerKind::Tcp { addr, .. }, Some(max)) => {
+                    let addr = addr
+                        .parse::<SocketAddr>()
+                        .expect("Listener address must be valid after parsing the configuration");
+                    Some((addr, Arc::new(Semaphore::new(max))))
+                }
+                _ => None,
+            })
+            .collect();
+
+        Self { limits }
+    }
+
+    /// Tries to take a slot on the listener that accepted the connection on `local_addr`.
+    pub fn try_admit(&self, local_addr: Option<&SocketAddr>) -> Admission {
+        let Some(semaphore) = local_addr.and_then(|addr| self.find(addr)) else {
+            return Admission::Unlimited;
+        };
+
+        match semaphore.clone().try_acquire_owned() {
+            Ok(permit) => Admission::Admitted(permit),
+            Err(_) => Admission::Rejected,
+        }
+    }
+
+    fn find(&self, local_addr: &SocketAddr) -> Option<&Arc<Semaphore>> {
+        find_listener(&self.limits, local_addr)
+    }
+}
+
+/// Finds the entry of the listener that accepted a connection on `local_addr`.
+pub(crate) fn find_listener<'a, T>(
+    entries: &'a [(SocketAddr, T)],
+    local_addr: &SocketAddr,
+) -> Option<&'a T> {
+    entries
+        .iter()
+        .find(|(addr, _)| addr == local_addr)
+        .or_else(|| {
+            // A wildcard listener ("0.0.0.0:80") sees the concrete local address.
+            entries
+                .iter()
+                .find(|(addr, _)| addr.ip().is_unspecified() && addr.port() == local_addr.port())
+        })
+        .map(|(_, entry)| entry)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use motya_config::common_types::listeners::ListenerConfig;
+    use tokio::sync::Barrier;
+
+    use super::*;
+
+    fn gate(addr: &str, max_concurrent: Option<usize>) -> ConcurrencyGate {
+        ConcurrencyGate::from_listeners(&Listeners {
+            list_cfgs: vec![ListenerConfig {
+                max_concurrent,
+                ..ListenerConfig::new(ListenerKind::Tcp {
+                    addr: addr.to_string(),
+                    tls: None,
+                    offer_h2: false,
+                })
+            }],
+        })
+    }
+
+    #[test]
+    fn test_unlimited_listener() {
+        let gate = gate("127.0.0.1:8080", None);
+        let local: SocketAddr = "127.0.0.1:8080".parse().unwrap();
+
+        assert!(matches!(gate.try_admit(Some(&local)), Admission::Unlimited));
+    }
+
+    #[test]
+    fn test_wildcard_listener_matches_concrete_addr() {
+        let gate = gate("0.0.0.0:8080", Some(1));
+        let local: SocketAddr = "10.0.0.5:8080".parse().unwrap();
+        let other_port: SocketAddr = "10.0.0.5:9090".parse().unwrap();
+
+        assert!(matches!(
+            gate.try_admit(Some(&local)),
+            Admission::Admitted(_)
+        ));
+        assert!(matches!(
+            gate.try_admit(Some(&other_port)),
+            Admission::Unlimited
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_cap_is_honored_under_concurrency() {
+        const CAP: usize = 4;
+        const REQUESTS: usize = 16;
+
+        let gate = gate("127.0.0.1:8080", Some(CAP));
+        let local: SocketAddr = "127.0.0.1:8080".parse().unwrap();
+        let barrier = Arc::new(Barrier::new(REQUESTS));
+
+        let handles = (0..REQUESTS)
+            .map(|_| {
+                let gate = gate.clone();
+                let barrier = barrier.clone();
+                tokio::spawn(async move {
+                    barrier.wait().await;
+                    let admission = gate.try_admit(Some(&local));
+                    let admitted = matches!(admission, Admission::Admitted(_));
+                    // hold the permit as an in-flight request would
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    drop(admission);
+                    admitted
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut admitted = 0;
+        for handle in handles {
+            if handle.await.unwrap() {
+                admitted += 1;
+            }
+        }
+
+        assert_eq!(admitted, CAP);
+
+        // every permit was released when the requests completed
+        assert!(matches!(
+            gate.try_admit(Some(&local)),
+            Admission::Admitted(_)
+        ));
+    }
+}
@@ -0,0 +1,185 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use async_trait::async_trait;
+use pingora::{apps::ServerApp, protocols::Stream, server::ShutdownWatch};
+
+/// Process-wide cap on simultaneous connections, set by `system.max-connections`.
+///
+/// Unlike the per-listener [`ConcurrencyGate`](crate::proxy::concurrency::ConcurrencyGate),
+/// one limit is shared by every listener of every service. Over the limit, the connection
+/// is closed as soon as it's accepted, without a response rather than answered with a 503.
+#[derive(Debug, Clone, Default)]
+pub struct ConnectionLimit {
+    open: Arc<AtomicUsize>,
+    max: Option<usize>,
+}
+
+/// Counts as an open connection until dropped.
+#[derive(Debug)]
+pub struct ConnectionGuard {
+    open: Arc<AtomicUsize>,
+}
+
+impl ConnectionLimit {
+    pub fn new(max: Option<usize>) -> Self {
+        Self {
+            open: Arc::default(),
+            max,
+        }
+    }
+
+    /// Counts a new connection, or returns `None` when the limit is reached.
+    pub fn try_open(&self) -> Option<ConnectionGuard> {
+        let Some(max) = self.max else {
+            self.open.fetch_add(1, Ordering::AcqRel);
+            return Some(ConnectionGuard {
+                open: self.open.clone(),
+            });
+        };
+
+        self.open
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |open| {
+                (open < max).then_some(open + 1)
+            })
+            .ok()
+            .map(|_| ConnectionGuard {
+                open: self.open.clone(),
+            })
+    }
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.open.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// Wraps an app, counting each connection it's handed against a [`ConnectionLimit`] until
+/// the connection is closed, and closing those over the limit right away.
+pub struct ConnectionLimitApp<A> {
+    inner: Arc<A>,
+    limit: ConnectionLimit,
+}
+
+impl<A> ConnectionLimitApp<A> {
+    pub fn new(inner: A, limit: ConnectionLimit) -> Self {
+        Self {
+            inner: Arc::new(inner),
+            limit,
+        }
+    }
+}
+
+#[async_trait]
+impl<A> ServerApp for ConnectionLimitApp<A>
+where
+    A: ServerApp + Send + Sync + 'static,
+{
+    async fn process_new(
+        self: &Arc<Self>,
+        stream: Stream,
+        shutdown: &ShutdownWatch,
+    ) -> Option<Stream> {
+        let Some(_connection) = self.limit.try_open() else {
+            tracing::trace!("Dropping connection due to the global connection limit");
+            // not answered at all, the client sees the connection closed
+            return None;
+        };
+
+        // the connection is reused here rather than handed back, so it's counted until closed
+        let mut stream = Some(stream);
+        while let Some(reused) = stream {
+            stream = self.inner.process_new(reused, shutdown).await;
+        }
+        None
+    }
+
+    async fn cleanup(&self) {
+        self.inner.cleanup().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::{
+        net::{TcpListener, TcpStream},
+        sync::{watch, Notify},
+    };
+
+    use super::*;
+
+    /// Serves each connection until released.
+    #[derive(Default)]
+    struct HoldingApp {
+        served: AtomicUsize,
+        release: Notify,
+    }
+
+    #[async_trait]
+    impl ServerApp for HoldingApp {
+        async fn process_new(
+            self: &Arc<Self>,
+            _stream: Stream,
+            _shutdown: &ShutdownWatch,
+        ) -> Option<Stream> {
+            self.served.fetch_add(1, Ordering::AcqRel);
+            self.release.notified().await;
+            None
+        }
+    }
+
+    async fn accept(listener: &TcpListener) -> Stream {
+        let _client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+        Box::new(pingora::protocols::l4::stream::Stream::from(stream))
+    }
+
+    #[tokio::test]
+    async fn test_connection_over_limit_closed_at_accept() {
+        let app = Arc::new(ConnectionLimitApp::new(
+            HoldingApp::default(),
+            ConnectionLimit::new(Some(1)),
+        ));
+        let (_shutdown_tx, shutdown) = watch::channel(false);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+
+        let first = accept(&listener).await;
+        let serving = tokio::spawn({
+            let app = app.clone();
+            let shutdown = shutdown.clone();
+            async move { app.process_new(first, &shutdown).await }
+        });
+        while app.inner.served.load(Ordering::Acquire) == 0 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        // the first connection holds the only slot, so the app never sees the second
+        let second = accept(&listener).await;
+        assert!(app.process_new(second, &shutdown).await.is_none());
+        assert_eq!(app.inner.served.load(Ordering::Acquire), 1);
+
+        // closing the first frees the slot
+        app.inner.release.notify_one();
+        serving.await.unwrap();
+
+        let third = accept(&listener).await;
+        app.inner.release.notify_one();
+        app.process_new(third, &shutdown).await;
+        assert_eq!(app.inner.served.load(Ordering::Acquire), 2);
+    }
+
+    #[test]
+    fn test_unlimited() {
+        let limit = ConnectionLimit::default();
+
+        let guards = (0..100).map(|_| limit.try_open()).collect::<Vec<_>>();
+        assert!(guards.iter().all(Option::is_some));
+    }
+}
@@ -0,0 +1,280 @@
+use std::{
+    any::Any,
+    collections::HashMap,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, OnceLock, Weak,
+    },
+};
+
+/// Upper bounds of the requests-per-connection histogram buckets, `+Inf` comes last.
+const BUCKETS: [u64; 8] = [1, 2, 5, 10, 20, 50, 100, 500];
+
+/// Independently locked parts of the open connections, so that requests on different
+/// connections rarely wait on each other.
+const SHARDS: usize = 32;
+
+/// Keep-alive efficiency of downstream connections: how many requests came on a new
+/// connection versus one that was already open, and how many requests each connection
+/// carried before it closed.
+///
+/// A connection is identified by a handle that lives exactly as long as it does, the
+/// socket digest of pingora's stream. The tracker only keeps a weak reference to it, so
+/// a closed connection is noticed once the handle is gone, and its request count lands
+/// in the histogram on the next sweep. Connections are spread over [`SHARDS`] maps by
+/// handle, whose histograms are added up when the metrics are read.
+#[derive(Debug, Default)]
+pub struct ConnectionReuse {
+    shards: [Mutex<OpenConnections>; SHARDS],
+    new: AtomicU64,
+    reused: AtomicU64,
+}
+
+#[derive(Debug, Default)]
+struct OpenConnections {
+    by_handle: HashMap<usize, OpenConnection>,
+    /// Closed connections aren't looked for until this many are tracked.
+    sweep_at: usize,
+    closed: RequestsPerConnection,
+}
+
+#[derive(Debug)]
+struct OpenConnection {
+    handle: Weak<dyn Any + Send + Sync>,
+    requests: u64,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RequestsPerConnection {
+    /// Count of connections per bucket of [`BUCKETS`], not cumulative, plus `+Inf`.
+    buckets: [u64; BUCKETS.len() + 1],
+    sum: u64,
+    count: u64,
+}
+
+/// The tracker shared by every proxy service.
+pub fn connection_reuse() -> &'static ConnectionReuse {
+    static REUSE: OnceLock<ConnectionReuse> = OnceLock::new();
+    REUSE.get_or_init(ConnectionReuse::default)
+}
+
+impl ConnectionReuse {
+    /// Counts a request arriving on `connection`.
+    pub fn on_request<C: Any + Send + Sync>(&self, connection: &Arc<C>) {
+        let key = Arc::as_ptr(connection) as *const () as usize;
+        let mut open = self.shard(key).lock().unwrap_or_else(|p| p.into_inner());
+
+        // the weak handle keeps the allocation, so a live key is always the same connection
+        match open.by_handle.get_mut(&key) {
+            Some(conn) if conn.handle.strong_count() > 0 => {
+                conn.requests += 1;
+                self.reused.fetch_add(1, Ordering::Relaxed);
+            }
+            _ => {
+                if open.by_handle.len() >= open.sweep_at {
+                    open.sweep();
+                }
+                let handle: Weak<C> = Arc::downgrade(connection);
+                if let Some(closed) = open.by_handle.insert(
+                    key,
+                    OpenConnection {
+                        handle: handle as Weak<dyn Any + Send + Sync>,
+                        requests: 1,
+                    },
+                ) {
+                    open.closed.observe(closed.requests);
+                }
+                self.new.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn shard(&self, key: usize) -> &Mutex<OpenConnections> {
+        // allocations are aligned, the low bits of an address say little about it
+        let mixed = (key as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+        &self.shards[(mixed >> 32) as usize % SHARDS]
+    }
+
+    pub fn new_connections(&self) -> u64 {
+        self.new.load(Ordering::Relaxed)
+    }
+
+    pub fn reused_connections(&self) -> u64 {
+        self.reused.load(Ordering::Relaxed)
+    }
+
+    /// Requests carried by the connections that closed so far.
+    pub fn requests_per_connection(&self) -> RequestsPerConnection {
+        let mut histogram = RequestsPerConnection::default();
+        for shard in &self.shards {
+            let mut open = shard.lock().unwrap_or_else(|p| p.into_inner());
+            open.sweep();
+            histogram.merge(&open.closed);
+        }
+        histogram
+    }
+
+    /// The metrics in the Prometheus text exposition format.
+    pub fn render_prometheus(&self) -> String {
+        let histogram = self.requests_per_connection();
+        let mut out = String::new();
+
+        let _ = writeln!(
+            out,
+            "# HELP motya_connection_requests_total Requests by whether their downstream connection was new or reused."
+        );
+        let _ = writeln!(out, "# TYPE motya_connection_requests_total counter");
+        let _ = writeln!(
+            out,
+            "motya_connection_requests_total{{connection=\"new\"}} {}",
+            self.new_connections()
+        );
+        let _ = writeln!(
+            out,
+            "motya_connection_requests_total{{connection=\"reused\"}} {}",
+            self.reused_connections()
+        );
+
+        let _ = writeln!(
+            out,
+            "# HELP motya_requests_per_connection Requests served on each closed downstream connection."
+        );
+        let _ = writeln!(out, "# TYPE motya_requests_per_connection histogram");
+        let mut cumulative = 0;
+        for (bound, count) in BUCKETS.iter().zip(&histogram.buckets) {
+            cumulative += count;
+            let _ = writeln!(
+                out,
+                "motya_requests_per_connection_bucket{{le=\"{bound}\"}} {cumulative}"
+            );
+        }
+        let _ = writeln!(
+            out,
+            "motya_requests_per_connection_bucket{{le=\"+Inf\"}} {}",
+            histogram.count
+        );
+        let _ = writeln!(out, "motya_requests_per_connection_sum {}", histogram.sum);
+        let _ = writeln!(
+            out,
+            "motya_requests_per_connection_count {}",
+            histogram.count
+        );
+
+        out
+    }
+}
+
+impl OpenConnections {
+    /// Moves the connections whose handle is gone into the histogram.
+    fn sweep(&mut self) {
+        let closed = &mut self.closed;
+        self.by_handle.retain(|_, conn| {
+            let open = conn.handle.strong_count() > 0;
+            if !open {
+                closed.observe(conn.requests);
+            }
+            open
+        });
+        self.sweep_at = (self.by_handle.len() * 2).max(64);
+    }
+}
+
+impl RequestsPerConnection {
+    fn observe(&mut self, requests: u64) {
+        let bucket = BUCKETS
+            .iter()
+            .position(|bound| requests <= *bound)
+            .unwrap_or(BUCKETS.len());
+        self.buckets[bucket] += 1;
+        self.sum += requests;
+        self.count += 1;
+    }
+
+    fn merge(&mut self, other: &RequestsPerConnection) {
+        for (bucket, count) in self.buckets.iter_mut().zip(&other.buckets) {
+            *bucket += count;
+        }
+        self.sum += other.sum;
+        self.count += other.count;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn sum(&self) -> u64 {
+        self.sum
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_requests_on_one_connection_reuse_it() {
+        let reuse = ConnectionReuse::default();
+        let connection = Arc::new(());
+
+        for _ in 0..5 {
+            reuse.on_request(&connection);
+        }
+        assert_eq!(reuse.new_connections(), 1);
+        assert_eq!(reuse.reused_connections(), 4);
+        // still open, nothing to report yet
+        assert_eq!(reuse.requests_per_connection().count(), 0);
+
+        drop(connection);
+        let histogram = reuse.requests_per_connection();
+        assert_eq!(histogram.count(), 1);
+        assert_eq!(histogram.sum(), 5);
+
+        let other = Arc::new(());
+        reuse.on_request(&other);
+        assert_eq!(reuse.new_connections(), 2);
+        assert_eq!(reuse.reused_connections(), 4);
+    }
+
+    #[test]
+    fn test_histogram_adds_up_shards() {
+        let reuse = ConnectionReuse::default();
+        let connections: Vec<_> = (0..200).map(Arc::new).collect();
+
+        for connection in &connections {
+            reuse.on_request(connection);
+            reuse.on_request(connection);
+        }
+        let used = reuse
+            .shards
+            .iter()
+            .filter(|shard| !shard.lock().unwrap().by_handle.is_empty())
+            .count();
+        assert!(used > 1, "connections spread over {used} shard");
+
+        drop(connections);
+        let histogram = reuse.requests_per_connection();
+        assert_eq!(histogram.count(), 200);
+        assert_eq!(histogram.sum(), 400);
+        assert_eq!(reuse.new_connections(), 200);
+        assert_eq!(reuse.reused_connections(), 200);
+    }
+
+    #[test]
+    fn test_render_prometheus() {
+        let reuse = ConnectionReuse::default();
+        let connection = Arc::new(());
+        reuse.on_request(&connection);
+        reuse.on_request(&connection);
+        reuse.on_request(&connection);
+        drop(connection);
+
+        let text = reuse.render_prometheus();
+        assert!(text.contains("motya_connection_requests_total{connection=\"new\"} 1\n"));
+        assert!(text.contains("motya_connection_requests_total{connection=\"reused\"} 2\n"));
+        assert!(text.contains("motya_requests_per_connection_bucket{le=\"2\"} 0\n"));
+        assert!(text.contains("motya_requests_per_connection_bucket{le=\"5\"} 1\n"));
+        assert!(text.contains("motya_requests_per_connection_bucket{le=\"+Inf\"} 1\n"));
+        assert!(text.contains("motya_requests_per_connection_sum 3\n"));
+    }
+}
@@ -1,4 +1,4 @@
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, net::IpAddr};
 
 use async_trait::async_trait;
 use cidr::IpCidr;
@@ -37,6 +37,11 @@ impl CidrRangeFilter {
 
         Ok(Self { blocks })
     }
+
+    /// Whether `ip` lies in one of the blocked ranges.
+    pub fn is_blocked(&self, ip: &IpAddr) -> bool {
+        self.blocks.iter().any(|b| b.contains(ip))
+    }
 }
 
 #[async_trait]
@@ -51,9 +56,7 @@ impl RequestFilterMod for CidrRangeFilter {
             // CIDR filters don't apply to UDS
             return Ok(false);
         };
-        let ip_addr = addr.ip();
-
-        if self.blocks.iter().any(|b| b.contains(&ip_addr)) {
+        if self.is_blocked(&addr.ip()) {
             session.downstream_session.respond_error(401).await?;
             Ok(true)
         } else {
@@ -0,0 +1,161 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    net::IpAddr,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock},
+    time::SystemTime,
+};
+
+use async_trait::async_trait;
+use maxminddb::{geoip2, Reader};
+use pingora::{protocols::l4::socket::SocketAddr, Error, ErrorType, Result};
+use pingora_proxy::Session;
+
+use crate::proxy::{
+    filters::{
+        builtin::helpers::{ensure_empty, extract_val},
+        types::RequestFilterMod,
+    },
+    MotyaContext,
+};
+
+type CountryDb = Arc<Reader<Vec<u8>>>;
+
+/// Readers opened so far, keyed by path and modification time so a replaced
+/// database is picked up on reload.
+static DATABASES: OnceLock<Mutex<HashMap<(PathBuf, Option<SystemTime>), CountryDb>>> =
+    OnceLock::new();
+
+pub struct GeoBlockFilter {
+    db: CountryDb,
+    deny: Vec<String>,
+}
+
+impl GeoBlockFilter {
+    /// Create from the settings field
+    pub fn from_settings(mut settings: BTreeMap<String, String>) -> Result<Self> {
+        let db_path = extract_val("db", &mut settings)?;
+        let deny = extract_val("deny", &mut settings)?;
+        ensure_empty(&settings)?;
+
+        let mut codes = vec![];
+        for code in deny.split(',') {
+            let code = code.trim();
+            if code.len() != 2 || !code.chars().all(|c| c.is_ascii_alphabetic()) {
+                tracing::error!("'{code}' is not a two-letter country code");
+                return Err(Error::new(ErrorType::Custom("Invalid configuration")));
+            }
+            codes.push(code.to_ascii_uppercase());
+        }
+
+        Ok(Self {
+            db: open_database(Path::new(&db_path))?,
+            deny: codes,
+        })
+    }
+
+    fn is_denied(&self, ip: IpAddr) -> bool {
+        let Ok(country) = self.db.lookup::<geoip2::Country>(ip) else {
+            // addresses missing from the database are let through
+            return false;
+        };
+
+        country
+            .country
+            .and_then(|country| country.iso_code)
+            .is_some_and(|code| self.deny.iter().any(|denied| denied == code))
+    }
+}
+
+fn open_database(path: &Path) -> Result<CountryDb> {
+    let modified = std::fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok();
+    let key = (path.to_path_buf(), modified);
+
+    let mut databases = DATABASES
+        .get_or_init(Mutex::default)
+        .lock()
+        .expect("geo database cache poisoned");
+
+    if let Some(db) = databases.get(&key) {
+        return Ok(db.clone());
+    }
+
+    let db = Reader::open_readfile(path).map_err(|err| {
+        tracing::error!(
+            "Failed to open country database '{}': {err}",
+            path.display()
+        );
+        Error::new(ErrorType::Custom("Invalid configuration"))
+    })?;
+
+    let db = Arc::new(db);
+    databases.insert(key, db.clone());
+    Ok(db)
+}
+
+#[async_trait]
+impl RequestFilterMod for GeoBlockFilter {
+    async fn request_filter(&self, session: &mut Session, _ctx: &mut MotyaContext) -> Result<bool> {
+        let Some(addr) = session.downstream_session.client_addr() else {
+            // Unable to determine source address, assuming it should be blocked
+            session.downstream_session.respond_error(403).await?;
+            return Ok(true);
+        };
+        let SocketAddr::Inet(addr) = addr else {
+            // Geo filters don't apply to UDS
+            return Ok(false);
+        };
+
+        if self.is_denied(addr.ip()) {
+            session.downstream_session.respond_error(403).await?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Maps 1.2.3.0/24 to CN and 8.8.8.0/24 to US.
+    const FIXTURE_DB: &str = "./assets/test-country.mmdb";
+
+    fn settings(db: &str, deny: &str) -> BTreeMap<String, String> {
+        BTreeMap::from([
+            ("db".to_string(), db.to_string()),
+            ("deny".to_string(), deny.to_string()),
+        ])
+    }
+
+    #[test]
+    fn test_denies_listed_countries() {
+        let filter = GeoBlockFilter::from_settings(settings(FIXTURE_DB, "cn, RU"))
+            .expect("Should successfully create filter");
+
+        assert!(filter.is_denied("1.2.3.4".parse().unwrap()));
+        assert!(!filter.is_denied("8.8.8.8".parse().unwrap()));
+        assert!(!filter.is_denied("9.9.9.9".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_invalid_country_code() {
+        let result = GeoBlockFilter::from_settings(settings(FIXTURE_DB, "CN, CHN"));
+
+        let err = result.err().unwrap();
+        assert!(format!("{:?}", err).contains("Invalid configuration"));
+    }
+
+    #[test]
+    fn test_missing_or_corrupt_database() {
+        for db in ["./assets/missing.mmdb", "./assets/test.crt"] {
+            let result = GeoBlockFilter::from_settings(settings(db, "CN"));
+
+            let err = result.err().unwrap();
+            assert!(format!("{:?}", err).contains("Invalid configuration"));
+        }
+    }
+}
@@ -0,0 +1,242 @@
+use std::{collections::BTreeMap, path::Path};
+
+use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+use jsonschema::Validator;
+use motya_config::kdl::parser::utils::parse_byte_size;
+use pingora::{Error, ErrorType, Result};
+use pingora_http::{RequestHeader, ResponseHeader};
+use pingora_proxy::Session;
+
+use crate::proxy::{
+    filters::{
+        builtin::helpers::{ensure_empty, extract_val},
+        types::RequestFilterMod,
+    },
+    MotyaContext,
+};
+
+/// Largest body validated when the filter doesn't set `max-body-size`.
+const DEFAULT_MAX_BODY_SIZE: usize = 1024 * 1024;
+
+/// Rejects requests whose JSON body doesn't follow a JSON Schema.
+pub struct JsonValidateFilter {
+    validator: Validator,
+    max_body_size: usize,
+}
+
+impl JsonValidateFilter {
+    /// Create from the settings field
+    pub fn from_settings(mut settings: BTreeMap<String, String>) -> Result<Self> {
+        let schema_path = extract_val("schema", &mut settings)?;
+        let max_body_size = match settings.remove("max-body-size") {
+            Some(size) => parse_byte_size(&size).map_err(|err| {
+                tracing::error!("Invalid 'max-body-size' '{size}': {err}");
+                Error::new(ErrorType::Custom("Invalid configuration"))
+            })?,
+            None => DEFAULT_MAX_BODY_SIZE,
+        };
+        ensure_empty(&settings)?;
+
+        Ok(Self {
+            validator: load_schema(Path::new(&schema_path))?,
+            max_body_size,
+        })
+    }
+
+    /// The first way `body` breaks the schema, as `/path/in/body: reason`.
+    fn validate(&self, body: &[u8]) -> std::result::Result<(), String> {
+        let instance = serde_json::from_slice::<serde_json::Value>(body)
+            .map_err(|err| format!("the body is not valid JSON: {err}"))?;
+
+        let Some(err) = self.validator.iter_errors(&instance).next() else {
+            return Ok(());
+        };
+        let path = err.instance_path.to_string();
+        let path = if path.is_empty() { "/" } else { &path };
+        Err(format!("{path}: {err}"))
+    }
+}
+
+fn load_schema(path: &Path) -> Result<Validator> {
+    let invalid = |reason: String| {
+        tracing::error!("Failed to load JSON schema '{}': {reason}", path.display());
+        Error::new(ErrorType::Custom("Invalid configuration"))
+    };
+
+    let content = std::fs::read(path).map_err(|err| invalid(err.to_string()))?;
+    let schema = serde_json::from_slice::<serde_json::Value>(&content)
+        .map_err(|err| invalid(err.to_string()))?;
+
+    jsonschema::validator_for(&schema).map_err(|err| invalid(err.to_string()))
+}
+
+/// Whether the request declares a JSON body, like `application/json` or
+/// `application/problem+json`.
+fn is_json(header: &RequestHeader) -> bool {
+    let Some(content_type) = header
+        .headers
+        .get(http::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+    else {
+        return false;
+    };
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+
+    mime == "application/json" || (mime.starts_with("application/") && mime.ends_with("+json"))
+}
+
+/// Whether the request comes with a body at all.
+fn has_body(header: &RequestHeader) -> bool {
+    let length = header
+        .headers
+        .get(http::header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+
+    length.is_some_and(|length| length > 0)
+        || header.headers.contains_key(http::header::TRANSFER_ENCODING)
+}
+
+/// Answers with `status` and `message` as a plain text body.
+async fn reject(session: &mut Session, status: u16, message: String) -> Result<()> {
+    let mut header = ResponseHeader::build(status, Some(2))?;
+    header.insert_header(http::header::CONTENT_TYPE, "text/plain; charset=utf-8")?;
+    header.insert_header(http::header::CONTENT_LENGTH, message.len())?;
+
+    session
+        .downstream_session
+        .write_response_header(Box::new(header))
+        .await?;
+    session
+        .downstream_session
+        .write_response_body(Bytes::from(message), true)
+        .await?;
+    Ok(())
+}
+
+#[async_trait]
+impl RequestFilterMod for JsonValidateFilter {
+    async fn request_filter(&self, session: &mut Session, ctx: &mut MotyaContext) -> Result<bool> {
+        if !has_body(session.req_header()) {
+            return Ok(false);
+        }
+        if !is_json(session.req_header()) {
+            reject(
+                session,
+                415,
+                "the request body must be JSON, with a 'Content-Type: application/json' header"
+                    .to_string(),
+            )
+            .await?;
+            return Ok(true);
+        }
+
+        let body = match &ctx.wasm_request_body {
+            // read whole by an earlier filter
+            Some(body) => body.clone(),
+            None => {
+                // the body is read here, and sent upstream from `wasm_request_body`
+                session.enable_retry_buffering();
+                let mut body = BytesMut::new();
+                while let Some(chunk) = session.read_request_body().await? {
+                    if body.len() + chunk.len() > self.max_body_size {
+                        return Err(Error::new(ErrorType::HTTPStatus(413)));
+                    }
+                    body.extend_from_slice(&chunk);
+                }
+                let body = body.freeze();
+                ctx.wasm_request_body = Some(body.clone());
+                body
+            }
+        };
+        if body.len() > self.max_body_size {
+            return Err(Error::new(ErrorType::HTTPStatus(413)));
+        }
+
+        match self.validate(&body) {
+            Ok(()) => Ok(false),
+            Err(reason) => {
+                reject(session, 400, format!("invalid request body, {reason}\n")).await?;
+                Ok(true)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An object with a string `name` and an optional non-negative integer `age`.
+    const FIXTURE_SCHEMA: &str = "./assets/test-request.schema.json";
+
+    fn filter(schema: &str) -> Result<JsonValidateFilter> {
+        JsonValidateFilter::from_settings(BTreeMap::from([(
+            "schema".to_string(),
+            schema.to_string(),
+        )]))
+    }
+
+    #[test]
+    fn test_valid_body() {
+        let filter = filter(FIXTURE_SCHEMA).expect("Should successfully create filter");
+
+        assert_eq!(filter.validate(br#"{"name":"motya"}"#), Ok(()));
+        assert_eq!(filter.validate(br#"{"name":"motya","age":3}"#), Ok(()));
+    }
+
+    #[test]
+    fn test_invalid_body() {
+        let filter = filter(FIXTURE_SCHEMA).expect("Should successfully create filter");
+
+        let err = filter
+            .validate(br#"{"name":"motya","age":-1}"#)
+            .unwrap_err();
+        assert!(err.starts_with("/age: "), "{err}");
+
+        let err = filter.validate(br#"{"age":3}"#).unwrap_err();
+        assert!(err.starts_with("/: "), "{err}");
+        assert!(err.contains("\"name\" is a required property"), "{err}");
+
+        let err = filter.validate(b"{\"name\":").unwrap_err();
+        assert!(err.contains("not valid JSON"), "{err}");
+    }
+
+    #[test]
+    fn test_unreadable_or_invalid_schema() {
+        // a missing file, a file that is not JSON and a schema with an unknown type
+        for schema in [
+            "./assets/missing.schema.json",
+            "./assets/test.crt",
+            "./assets/test-invalid.schema.json",
+        ] {
+            let err = filter(schema).err().unwrap();
+            assert!(format!("{err:?}").contains("Invalid configuration"));
+        }
+    }
+
+    #[test]
+    fn test_json_content_types() {
+        let request = |content_type: Option<&str>| {
+            let mut header = RequestHeader::build("POST", b"/api", None).unwrap();
+            if let Some(content_type) = content_type {
+                header
+                    .insert_header(http::header::CONTENT_TYPE, content_type)
+                    .unwrap();
+            }
+            header
+        };
+
+        assert!(is_json(&request(Some("application/json"))));
+        assert!(is_json(&request(Some("Application/JSON; charset=utf-8"))));
+        assert!(is_json(&request(Some("application/merge-patch+json"))));
+        assert!(!is_json(&request(Some("text/plain"))));
+        assert!(!is_json(&request(None)));
+    }
+}
@@ -1,5 +1,7 @@
 pub mod cidr_range;
+pub mod geo_block;
 pub mod helpers;
+pub mod json_validate;
 pub mod request;
 pub mod response;
 pub mod simple_response;
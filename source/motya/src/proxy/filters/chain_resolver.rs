@@ -1,13 +1,17 @@
 use crate::proxy::{
     filters::{
+        conditional::Conditional,
         registry::{FilterInstance, FilterRegistry, RegistryFilterContainer},
         types::{RequestFilterMod, RequestModifyMod, ResponseModifyMod},
     },
     plugins::module::{FilterType, WasmInvoker},
 };
 use miette::{miette, Context, IntoDiagnostic, Result};
-use motya_config::common_types::{definitions::FilterChain, definitions_table::DefinitionsTable};
+use motya_config::common_types::{
+    condition::Condition, definitions::FilterChain, definitions_table::DefinitionsTable,
+};
 use std::collections::BTreeMap;
+use std::str::FromStr;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
@@ -18,6 +22,29 @@ pub struct RuntimeChain {
     pub res_mods: Vec<Box<dyn ResponseModifyMod>>,
 }
 
+impl RuntimeChain {
+    fn push_action(&mut self, filter: Box<dyn RequestFilterMod>, when: Option<Condition>) {
+        self.actions.push(match when {
+            Some(condition) => Box::new(Conditional::new(condition, filter)),
+            None => filter,
+        });
+    }
+
+    fn push_req_mod(&mut self, filter: Box<dyn RequestModifyMod>, when: Option<Condition>) {
+        self.req_mods.push(match when {
+            Some(condition) => Box::new(Conditional::new(condition, filter)),
+            None => filter,
+        });
+    }
+
+    fn push_res_mod(&mut self, filter: Box<dyn ResponseModifyMod>, when: Option<Condition>) {
+        self.res_mods.push(match when {
+            Some(condition) => Box::new(Conditional::new(condition, filter)),
+            None => filter,
+        });
+    }
+}
+
 #[derive(Clone, Default)]
 pub struct ChainResolver {
     table: DefinitionsTable,
@@ -70,7 +97,7 @@ impl ChainResolver {
     async fn build_chain(&self, chain: &FilterChain, context_name: &str) -> Result<RuntimeChain> {
         let mut runtime_chain = RuntimeChain::default();
 
-        for filter_cfg in &chain.filters {
+        for filter_cfg in chain.filters.iter().filter(|filter| filter.enabled) {
             let settings: BTreeMap<String, String> = filter_cfg
                 .args
                 .iter()
@@ -88,14 +115,16 @@ impl ChainResolver {
                     )
                 })?;
 
+            let when = filter_cfg.when.clone();
+
             match container {
                 RegistryFilterContainer::Builtin(builtin) => match builtin {
-                    FilterInstance::Action(f) => runtime_chain.actions.push(f),
-                    FilterInstance::Request(f) => runtime_chain.req_mods.push(f),
-                    FilterInstance::Response(f) => runtime_chain.res_mods.push(f),
+                    FilterInstance::Action(f) => runtime_chain.push_action(f, when),
+                    FilterInstance::Request(f) => runtime_chain.push_req_mod(f, when),
+                    FilterInstance::Response(f) => runtime_chain.push_res_mod(f, when),
                 },
                 RegistryFilterContainer::Plugin(plugin) => {
-                    let (_plugin_name, filter_name) = filter_cfg
+                    let (plugin_name, filter_name) = filter_cfg
                         .name
                         .as_c_str()
                         .to_str()
@@ -108,13 +137,26 @@ impl ChainResolver {
                             )
                         })?;
 
-                    let invoker = WasmInvoker::new(plugin, filter_name.to_string(), settings);
+                    let plugin_default = fqdn::FQDN::from_str(plugin_name)
+                        .ok()
+                        .and_then(|name| self.table.get_plugins().get(&name))
+                        .and_then(|def| def.on_filter_error);
+                    let on_error = filter_cfg.on_error.or(plugin_default).unwrap_or_default();
+
+                    let invoker = WasmInvoker::new(plugin, filter_name.to_string(), settings)
+                        .with_on_error(on_error)
+                        .with_max_memory(filter_cfg.max_memory)
+                        .with_max_body_size(filter_cfg.max_body_size);
 
                     match invoker.get_filter_type()? {
-                        FilterType::Filter => Box::new(invoker),
-                        FilterType::OnRequest => Box::new(invoker),
-                        FilterType::OnResponse => Box::new(invoker),
-                    };
+                        FilterType::Filter => runtime_chain.push_action(Box::new(invoker), when),
+                        FilterType::OnRequest => {
+                            runtime_chain.push_req_mod(Box::new(invoker), when)
+                        }
+                        FilterType::OnResponse => {
+                            runtime_chain.push_res_mod(Box::new(invoker), when)
+                        }
+                    }
                 }
             }
         }
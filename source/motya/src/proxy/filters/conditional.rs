@@ -0,0 +1,76 @@
+use async_trait::async_trait;
+use pingora::Result;
+use pingora_http::{RequestHeader, ResponseHeader};
+use pingora_proxy::Session;
+
+use motya_config::common_types::condition::{Condition, RequestFacts};
+
+use crate::proxy::{
+    filters::types::{RequestFilterMod, RequestModifyMod, ResponseModifyMod},
+    headers::RequestVariables,
+    MotyaContext,
+};
+
+/// A filter with a `when` clause, only run on the requests matching it.
+pub struct Conditional<F: ?Sized> {
+    condition: Condition,
+    inner: Box<F>,
+}
+
+impl<F: ?Sized> Conditional<F> {
+    pub fn new(condition: Condition, inner: Box<F>) -> Self {
+        Self { condition, inner }
+    }
+
+    fn applies(&self, session: &Session) -> bool {
+        let req = session.req_header();
+        let host = RequestVariables::from_session(session).host;
+
+        self.condition.matches(&RequestFacts {
+            method: req.method.as_str(),
+            path: req.uri.path(),
+            host: host.as_deref(),
+            headers: &req.headers,
+        })
+    }
+}
+
+#[async_trait]
+impl RequestFilterMod for Conditional<dyn RequestFilterMod> {
+    async fn request_filter(&self, session: &mut Session, ctx: &mut MotyaContext) -> Result<bool> {
+        if !self.applies(session) {
+            return Ok(false);
+        }
+        self.inner.request_filter(session, ctx).await
+    }
+}
+
+#[async_trait]
+impl RequestModifyMod for Conditional<dyn RequestModifyMod> {
+    async fn upstream_request_filter(
+        &self,
+        session: &mut Session,
+        header: &mut RequestHeader,
+        ctx: &mut MotyaContext,
+    ) -> Result<()> {
+        if !self.applies(session) {
+            return Ok(());
+        }
+        self.inner
+            .upstream_request_filter(session, header, ctx)
+            .await
+    }
+}
+
+impl ResponseModifyMod for Conditional<dyn ResponseModifyMod> {
+    fn upstream_response_filter(
+        &self,
+        session: &mut Session,
+        header: &mut ResponseHeader,
+        ctx: &mut MotyaContext,
+    ) {
+        if self.applies(session) {
+            self.inner.upstream_response_filter(session, header, ctx);
+        }
+    }
+}
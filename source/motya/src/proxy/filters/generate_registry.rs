@@ -1,5 +1,7 @@
 use crate::proxy::filters::builtin::{
     cidr_range::CidrRangeFilter,
+    geo_block::GeoBlockFilter,
+    json_validate::JsonValidateFilter,
     request::{
         remove_headers::RemoveHeaderKeyRegex as RequestRemoveHeaderKeyRegex,
         rewrite_path::RewritePathRegex, strip_prefix::StripPrefix,
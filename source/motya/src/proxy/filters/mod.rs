@@ -1,5 +1,6 @@
 pub mod builtin;
 pub mod chain_resolver;
+pub mod conditional;
 pub mod generate_registry;
 pub mod registry;
 pub mod types;
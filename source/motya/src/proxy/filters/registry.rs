@@ -191,10 +191,22 @@ mod tests {
             ConfiguredFilter {
                 name: FQDN::from_str("motya.sec.block").unwrap(),
                 args: HashMap::new(),
+                enabled: true,
+                on_error: None,
+                max_memory: None,
+                max_body_size: None,
+                when: None,
+                priority: 0,
             },
             ConfiguredFilter {
                 name: FQDN::from_str("motya.req.add_header").unwrap(),
                 args: header_args,
+                enabled: true,
+                on_error: None,
+                max_memory: None,
+                max_body_size: None,
+                when: None,
+                priority: 0,
             },
         ];
 
@@ -264,6 +276,12 @@ mod tests {
                 filters: vec![ConfiguredFilter {
                     name: FQDN::from_str("motya.always_fail").unwrap(),
                     args: HashMap::new(),
+                    enabled: true,
+                    on_error: None,
+                    max_memory: None,
+                    max_body_size: None,
+                    when: None,
+                    priority: 0,
                 }],
             },
         );
@@ -277,4 +295,44 @@ mod tests {
             .to_string()
             .contains("Failed to build filter 'motya.always_fail' in chain 'test'"));
     }
+
+    #[tokio::test]
+    async fn test_disabled_filter_is_skipped() {
+        let mut reg = FilterRegistry::new();
+        reg.register_factory(
+            FQDN::from_str("motya.always_fail").unwrap(),
+            Box::new(|_| {
+                Err(pingora::Error::new(pingora::ErrorType::Custom(
+                    "Init failed",
+                )))
+            }),
+        );
+
+        let mut table = DefinitionsTable::default();
+        table.insert_filter(FQDN::from_str("motya.always_fail").unwrap());
+        table.insert_chain(
+            "test",
+            FilterChain {
+                filters: vec![ConfiguredFilter {
+                    name: FQDN::from_str("motya.always_fail").unwrap(),
+                    args: HashMap::new(),
+                    enabled: false,
+                    on_error: None,
+                    max_memory: None,
+                    max_body_size: None,
+                    when: None,
+                    priority: 0,
+                }],
+            },
+        );
+
+        let resolver = ChainResolver::new(table, Arc::new(reg.into()))
+            .await
+            .expect("Disabled filters are still validated against the registry");
+        let chain = resolver.resolve("test").await.unwrap();
+
+        assert!(chain.actions.is_empty());
+        assert!(chain.req_mods.is_empty());
+        assert!(chain.res_mods.is_empty());
+    }
 }
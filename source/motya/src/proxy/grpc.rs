@@ -0,0 +1,78 @@
+use http::HeaderMap;
+use pingora::{prelude::HttpPeer, protocols::ALPN};
+use pingora_http::RequestHeader;
+
+/// Fields tied to a single connection, which HTTP/2 forbids. A trailer block carrying one
+/// is refused by the client's HTTP/2 stack, and the `grpc-status` next to it is lost.
+const CONNECTION_FIELDS: [&str; 5] = [
+    "connection",
+    "keep-alive",
+    "transfer-encoding",
+    "upgrade",
+    "te",
+];
+
+/// Makes the connection to a `protocol "grpc"` upstream speak HTTP/2 only.
+pub fn negotiate_h2(peer: &mut HttpPeer) {
+    peer.options.alpn = ALPN::H2;
+}
+
+/// Marks the upstream request of a `protocol "grpc"` connector as accepting trailers,
+/// which gRPC servers require before answering.
+pub fn prepare_request(header: &mut RequestHeader) {
+    if let Err(e) = header.insert_header(http::header::TE, "trailers") {
+        tracing::warn!("Failed to set TE on a gRPC request: {e}");
+    }
+}
+
+/// Readies the trailers of a `protocol "grpc"` response for the client, dropping the
+/// fields an HTTP/1 upstream hop may have left but HTTP/2 can't carry.
+pub fn forward_trailers(trailers: &mut HeaderMap) {
+    for name in CONNECTION_FIELDS {
+        trailers.remove(name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http::{header, HeaderValue};
+
+    use super::*;
+
+    #[test]
+    fn test_trailers_forwarded() {
+        let mut trailers = HeaderMap::new();
+        trailers.insert("grpc-status", HeaderValue::from_static("5"));
+        trailers.insert(
+            "grpc-message",
+            HeaderValue::from_static("user%20not%20found"),
+        );
+        trailers.insert(header::CONNECTION, HeaderValue::from_static("close"));
+        trailers.insert("keep-alive", HeaderValue::from_static("timeout=5"));
+
+        forward_trailers(&mut trailers);
+
+        assert_eq!(trailers.len(), 2);
+        assert_eq!(trailers["grpc-status"], "5");
+        assert_eq!(trailers["grpc-message"], "user%20not%20found");
+    }
+
+    #[test]
+    fn test_request_accepts_trailers() {
+        let mut req = RequestHeader::build("POST", b"/helloworld.Greeter/SayHello", None).unwrap();
+        req.insert_header(header::TE, "gzip").unwrap();
+
+        prepare_request(&mut req);
+
+        assert_eq!(req.headers[header::TE], "trailers");
+    }
+
+    #[test]
+    fn test_peer_negotiates_h2() {
+        let mut peer = HttpPeer::new("127.0.0.1:50051", true, "grpc.internal".to_string());
+
+        negotiate_h2(&mut peer);
+
+        assert_eq!(peer.options.alpn, ALPN::H2);
+    }
+}
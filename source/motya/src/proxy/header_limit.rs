@@ -0,0 +1,106 @@
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
+
+use http::{HeaderMap, HeaderName};
+use pingora_proxy::Session;
+
+use motya_config::common_types::listeners::{ListenerKind, Listeners, DEFAULT_MAX_HEADER_SIZE};
+
+use crate::proxy::concurrency::find_listener;
+
+/// Header naming the oversized request header in a `431` response.
+pub const REJECTED_HEADER: &str = "X-Rejected-Header";
+
+/// Per-listener `max-header-size` settings.
+#[derive(Debug, Clone, Default)]
+pub struct HeaderLimits {
+    tcp: Vec<(SocketAddr, usize)>,
+    uds: Vec<(PathBuf, usize)>,
+}
+
+impl HeaderLimits {
+    pub fn from_listeners(listeners: &Listeners) -> Self {
+        let mut limits = Self::default();
+
+        for cfg in &listeners.list_cfgs {
+            match &cfg.source {
+                ListenerKind::Tcp { addr, .. } => {
+                    let addr = addr
+                        .parse::<SocketAddr>()
+                        .expect("Listener address must be valid after parsing the configuration");
+                    limits.tcp.push((addr, cfg.max_header_size));
+                }
+                ListenerKind::Uds(path) => limits.uds.push((path.clone(), cfg.max_header_size)),
+                // given the address of its socket by `with_inherited_addrs` beforehand
+                ListenerKind::InheritedFd { .. } => {}
+            }
+        }
+
+        limits
+    }
+
+    /// Name of the largest request header, when it's larger than the listener that
+    /// accepted the request allows.
+    pub fn exceeded(&self, session: &Session) -> Option<HeaderName> {
+        let limit = session
+            .server_addr()
+            .and_then(|addr| match (addr.as_inet(), addr.as_unix()) {
+                (Some(inet), _) => self.for_tcp(inet),
+                (None, Some(unix)) => self.for_uds(unix.as_pathname()?),
+                (None, None) => None,
+            })
+            .unwrap_or(DEFAULT_MAX_HEADER_SIZE);
+
+        oversized_header(&session.req_header().headers, limit).cloned()
+    }
+
+    fn for_tcp(&self, local_addr: &SocketAddr) -> Option<usize> {
+        find_listener(&self.tcp, local_addr).copied()
+    }
+
+    fn for_uds(&self, path: &Path) -> Option<usize> {
+        self.uds
+            .iter()
+            .find(|(listener, _)| listener == path)
+            .map(|(_, limit)| *limit)
+    }
+}
+
+/// The largest header of `headers`, counting its name and value, if it's over `limit`.
+///
+/// Only the name is handed out, the value may hold credentials and is never logged.
+fn oversized_header(headers: &HeaderMap, limit: usize) -> Option<&HeaderName> {
+    headers
+        .iter()
+        .map(|(name, value)| (name, name.as_str().len() + value.len()))
+        .filter(|(_, size)| *size > limit)
+        .max_by_key(|(_, size)| *size)
+        .map(|(name, _)| name)
+}
+
+#[cfg(test)]
+mod tests {
+    use http::HeaderValue;
+
+    use super::*;
+
+    #[test]
+    fn test_oversized_header_is_named() {
+        let mut headers = HeaderMap::new();
+        headers.insert("host", HeaderValue::from_static("example.com"));
+        headers.insert("x-small", HeaderValue::from_static("1"));
+        assert_eq!(oversized_header(&headers, 64), None);
+
+        let token = "a".repeat(100);
+        headers.insert("authorization", HeaderValue::from_str(&token).unwrap());
+        let cookie = "b".repeat(80);
+        headers.insert("cookie", HeaderValue::from_str(&cookie).unwrap());
+
+        // the largest one of the two over the limit
+        let name = oversized_header(&headers, 64).expect("a header is over the limit");
+        assert_eq!(name, "authorization");
+        assert!(!name.as_str().contains(&token));
+    }
+}
@@ -0,0 +1,523 @@
+use std::net::IpAddr;
+
+use http::{uri::Authority, Method};
+use pingora_http::{RequestHeader, ResponseHeader};
+use pingora_proxy::Session;
+
+use motya_config::common_types::headers::{
+    ForwardedHeaders, HeaderRule, HeaderTemplate, HeaderVariable, TemplatePart,
+    UpstreamAcceptEncoding, UpstreamHost,
+};
+
+/// Values of the [`HeaderVariable`]s for one request.
+#[derive(Debug, Default)]
+pub struct RequestVariables {
+    pub client_ip: Option<IpAddr>,
+    pub host: Option<String>,
+}
+
+impl RequestVariables {
+    pub fn from_session(session: &Session) -> Self {
+        Self {
+            client_ip: session
+                .client_addr()
+                .and_then(|addr| addr.as_inet())
+                .map(|addr| addr.ip()),
+            host: request_host(session.req_header()),
+        }
+    }
+
+    /// Substitutes the variables of `template`; unknown values become empty.
+    pub fn render(&self, template: &HeaderTemplate) -> String {
+        let mut out = String::new();
+        for part in &template.parts {
+            match part {
+                TemplatePart::Literal(s) => out.push_str(s),
+                TemplatePart::Variable(HeaderVariable::ClientIp) => {
+                    if let Some(ip) = self.client_ip {
+                        out.push_str(&ip.to_string());
+                    }
+                }
+                TemplatePart::Variable(HeaderVariable::Host) => {
+                    if let Some(host) = &self.host {
+                        out.push_str(host);
+                    }
+                }
+            }
+        }
+        out
+    }
+}
+
+/// Host the request is for, from its URI or else its `Host` header, without the port.
+pub fn request_host(req: &RequestHeader) -> Option<String> {
+    req.uri.host().map(str::to_string).or_else(|| {
+        req.headers
+            .get(http::header::HOST)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<Authority>().ok())
+            .map(|authority| authority.host().to_string())
+    })
+}
+
+/// Applies `request-headers` rules to the request sent upstream.
+///
+/// Every `remove` runs first, so a header that is both removed and set always
+/// ends up with the configured value, regardless of declaration order.
+pub fn apply_request_rules(
+    rules: &[HeaderRule<HeaderTemplate>],
+    vars: &RequestVariables,
+    header: &mut RequestHeader,
+) {
+    for rule in rules {
+        if let HeaderRule::Remove { name } = rule {
+            header.remove_header(name);
+        }
+    }
+
+    for rule in rules {
+        let result = match rule {
+            HeaderRule::Set { name, value } => {
+                header.insert_header(name.clone(), vars.render(value))
+            }
+            HeaderRule::Add { name, value } => {
+                header.append_header(name.clone(), vars.render(value))
+            }
+            HeaderRule::Remove { .. } => continue,
+        };
+
+        // a variable may expand to something that isn't a valid header value
+        if let Err(e) = result {
+            tracing::warn!("Failed to apply header rule {rule:?}: {e}");
+        }
+    }
+}
+
+/// Replaces the `Accept-Encoding` of the request sent upstream, as `upstream-accept-encoding` asks.
+pub fn apply_accept_encoding(encoding: &UpstreamAcceptEncoding, header: &mut RequestHeader) {
+    let Some(value) = encoding.header_value() else {
+        return;
+    };
+
+    if let Err(e) = header.insert_header(http::header::ACCEPT_ENCODING, value) {
+        tracing::warn!("Failed to set the upstream Accept-Encoding: {e}");
+    }
+}
+
+/// Replaces the `Host` of the request sent upstream, as `host-header` asks.
+pub fn apply_upstream_host(host: &UpstreamHost, header: &mut RequestHeader) {
+    let Some(value) = host.header_value() else {
+        return;
+    };
+
+    if let Err(e) = header.insert_header(http::header::HOST, value) {
+        tracing::warn!("Failed to set the upstream Host: {e}");
+    }
+}
+
+/// Replaces the method of the request sent upstream, as `upstream-method` asks.
+///
+/// Only the upstream request changes, the client's method is still the one logged and
+/// cached on. The body is forwarded as the client sent it.
+pub fn apply_upstream_method(method: Option<&Method>, header: &mut RequestHeader) {
+    if let Some(method) = method {
+        header.set_method(method.clone());
+    }
+}
+
+/// Sets the headers of `forwarded-headers` on the request sent upstream.
+///
+/// `X-Forwarded-For` and `Forwarded` get the peer appended when it's a trusted proxy, and
+/// are replaced otherwise. The proto and host of a trusted proxy are kept as it sent them.
+pub fn apply_forwarded_headers(
+    forwarded: &ForwardedHeaders,
+    peer: Option<IpAddr>,
+    tls: bool,
+    header: &mut RequestHeader,
+) {
+    let trusted = peer.is_some_and(|peer| forwarded.is_trusted(peer));
+    let proto = if tls { "https" } else { "http" };
+    let host = header
+        .headers
+        .get(http::header::HOST)
+        .and_then(|host| host.to_str().ok())
+        .map(str::to_string)
+        .or_else(|| header.uri.authority().map(|a| a.to_string()));
+
+    if forwarded.enabled {
+        let chain = trusted.then(|| joined(header, "x-forwarded-for")).flatten();
+        let xff = match (chain, peer) {
+            (Some(chain), Some(peer)) => Some(format!("{chain}, {peer}")),
+            (chain, peer) => chain.or(peer.map(|peer| peer.to_string())),
+        };
+        set_forwarded(header, "x-forwarded-for", xff);
+
+        if !(trusted && header.headers.contains_key("x-forwarded-proto")) {
+            set_forwarded(header, "x-forwarded-proto", Some(proto.to_string()));
+        }
+        if !(trusted && header.headers.contains_key("x-forwarded-host")) {
+            set_forwarded(header, "x-forwarded-host", host.clone());
+        }
+    }
+
+    if forwarded.rfc7239 {
+        let mut element = match peer {
+            // IPv6 addresses contain colons, so they are bracketed and quoted
+            Some(IpAddr::V6(peer)) => format!("for=\"[{peer}]\""),
+            Some(IpAddr::V4(peer)) => format!("for={peer}"),
+            None => "for=unknown".to_string(),
+        };
+        element.push_str(&format!(";proto={proto}"));
+        if let Some(host) = &host {
+            element.push_str(&format!(";host=\"{host}\""));
+        }
+
+        let value = match trusted.then(|| joined(header, "forwarded")).flatten() {
+            Some(chain) => format!("{chain}, {element}"),
+            None => element,
+        };
+        set_forwarded(header, "forwarded", Some(value));
+    }
+}
+
+/// Replaces every value of the header, or removes it when there is nothing to send.
+fn set_forwarded(header: &mut RequestHeader, name: &'static str, value: Option<String>) {
+    let result = match value {
+        Some(value) => header.insert_header(name, value),
+        None => {
+            header.remove_header(name);
+            Ok(())
+        }
+    };
+    if let Err(e) = result {
+        tracing::warn!("Failed to set {name}: {e}");
+    }
+}
+
+/// Every value of the header in one comma-separated list, `None` if it's missing.
+fn joined(header: &RequestHeader, name: &str) -> Option<String> {
+    let values = header
+        .headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .collect::<Vec<_>>();
+    (!values.is_empty()).then(|| values.join(", "))
+}
+
+/// Applies `response-headers` rules to an outgoing response, in declaration order.
+pub fn apply_response_rules(rules: &[HeaderRule], header: &mut ResponseHeader) {
+    for rule in rules {
+        let result = match rule {
+            HeaderRule::Set { name, value } => header.insert_header(name.clone(), value.as_str()),
+            HeaderRule::Add { name, value } => header.append_header(name.clone(), value.as_str()),
+            HeaderRule::Remove { name } => {
+                header.remove_header(name);
+                Ok(())
+            }
+        };
+
+        // values are validated while parsing the configuration
+        if let Err(e) = result {
+            tracing::warn!("Failed to apply header rule {rule:?}: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http::HeaderName;
+
+    use super::*;
+
+    fn response() -> ResponseHeader {
+        let mut header = ResponseHeader::build(200, None).unwrap();
+        header.append_header("Server", "upstream").unwrap();
+        header.append_header("Set-Cookie", "session=1").unwrap();
+        header
+            .append_header("X-Frame-Options", "SAMEORIGIN")
+            .unwrap();
+        header
+    }
+
+    fn values(header: &ResponseHeader, name: &str) -> Vec<String> {
+        header
+            .headers
+            .get_all(name)
+            .iter()
+            .map(|v| v.to_str().unwrap().to_string())
+            .collect()
+    }
+
+    fn request_rules(rules: &[(&str, &str, Option<&str>)]) -> Vec<HeaderRule<HeaderTemplate>> {
+        rules
+            .iter()
+            .map(|(op, name, value)| {
+                let name = HeaderName::from_bytes(name.as_bytes()).unwrap();
+                match (*op, value) {
+                    ("set", Some(v)) => HeaderRule::Set {
+                        name,
+                        value: v.parse().unwrap(),
+                    },
+                    ("add", Some(v)) => HeaderRule::Add {
+                        name,
+                        value: v.parse().unwrap(),
+                    },
+                    _ => HeaderRule::Remove { name },
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_request_variables_are_resolved() {
+        let mut header = RequestHeader::build("GET", b"/", None).unwrap();
+        let vars = RequestVariables {
+            client_ip: Some("10.1.2.3".parse().unwrap()),
+            host: Some("example.com".to_string()),
+        };
+
+        apply_request_rules(
+            &request_rules(&[
+                ("set", "X-Real-IP", Some("$client_ip")),
+                ("set", "X-Forwarded-Host", Some("${host}:443")),
+            ]),
+            &vars,
+            &mut header,
+        );
+
+        assert_eq!(header.headers.get("x-real-ip").unwrap(), "10.1.2.3");
+        assert_eq!(
+            header.headers.get("x-forwarded-host").unwrap(),
+            "example.com:443"
+        );
+    }
+
+    #[test]
+    fn test_request_remove_runs_before_set() {
+        let mut header = RequestHeader::build("GET", b"/", None).unwrap();
+        header.append_header("X-Real-IP", "spoofed").unwrap();
+        header.append_header("X-Internal", "secret").unwrap();
+        let vars = RequestVariables {
+            client_ip: Some("10.1.2.3".parse().unwrap()),
+            host: None,
+        };
+
+        apply_request_rules(
+            &request_rules(&[
+                ("set", "X-Real-IP", Some("$client_ip")),
+                ("remove", "X-Real-IP", None),
+                ("remove", "X-Internal", None),
+            ]),
+            &vars,
+            &mut header,
+        );
+
+        let real_ip = header
+            .headers
+            .get_all("x-real-ip")
+            .iter()
+            .collect::<Vec<_>>();
+        assert_eq!(real_ip, vec!["10.1.2.3"]);
+        assert!(header.headers.get("x-internal").is_none());
+    }
+
+    #[test]
+    fn test_identity_replaces_client_accept_encoding() {
+        let request = || {
+            let mut header = RequestHeader::build("GET", b"/", None).unwrap();
+            header.append_header("Accept-Encoding", "gzip, br").unwrap();
+            header
+        };
+        let accept_encoding = |header: &RequestHeader| {
+            header
+                .headers
+                .get_all("accept-encoding")
+                .iter()
+                .map(|v| v.to_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        let mut header = request();
+        apply_accept_encoding(&UpstreamAcceptEncoding::Identity, &mut header);
+        assert_eq!(accept_encoding(&header), vec!["identity"]);
+
+        let mut header = request();
+        apply_accept_encoding(&UpstreamAcceptEncoding::Passthrough, &mut header);
+        assert_eq!(accept_encoding(&header), vec!["gzip, br"]);
+    }
+
+    #[test]
+    fn test_upstream_host() {
+        let request = || {
+            let mut header = RequestHeader::build("GET", b"/", None).unwrap();
+            header.append_header("Host", "example.com").unwrap();
+            header
+        };
+        let host = |header: &RequestHeader| {
+            header
+                .headers
+                .get_all("host")
+                .iter()
+                .map(|v| v.to_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        let mut header = request();
+        apply_upstream_host(&UpstreamHost::Passthrough, &mut header);
+        assert_eq!(host(&header), vec!["example.com"]);
+
+        let mut header = request();
+        apply_upstream_host(
+            &UpstreamHost::Override("backend.internal".to_string()),
+            &mut header,
+        );
+        assert_eq!(host(&header), vec!["backend.internal"]);
+    }
+
+    #[test]
+    fn test_upstream_method() {
+        let mut header = RequestHeader::build("GET", b"/probe", None).unwrap();
+        apply_upstream_method(None, &mut header);
+        assert_eq!(header.method, Method::GET);
+
+        apply_upstream_method(Some(&Method::HEAD), &mut header);
+        assert_eq!(header.method, Method::HEAD);
+        assert_eq!(header.uri.path(), "/probe");
+    }
+
+    #[test]
+    fn test_set_replaces_existing_values() {
+        let mut header = response();
+
+        apply_response_rules(
+            &[HeaderRule::Set {
+                name: HeaderName::from_static("x-frame-options"),
+                value: "DENY".to_string(),
+            }],
+            &mut header,
+        );
+
+        assert_eq!(values(&header, "X-Frame-Options"), vec!["DENY"]);
+    }
+
+    #[test]
+    fn test_add_keeps_existing_values() {
+        let mut header = response();
+
+        apply_response_rules(
+            &[HeaderRule::Add {
+                name: HeaderName::from_static("set-cookie"),
+                value: "theme=dark".to_string(),
+            }],
+            &mut header,
+        );
+
+        assert_eq!(
+            values(&header, "Set-Cookie"),
+            vec!["session=1", "theme=dark"]
+        );
+    }
+
+    #[test]
+    fn test_remove_drops_header() {
+        let mut header = response();
+
+        apply_response_rules(
+            &[HeaderRule::Remove {
+                name: HeaderName::from_static("server"),
+            }],
+            &mut header,
+        );
+
+        assert!(values(&header, "Server").is_empty());
+        assert_eq!(values(&header, "Set-Cookie"), vec!["session=1"]);
+    }
+
+    fn forwarded(enabled: bool, rfc7239: bool, trusted: &[&str]) -> ForwardedHeaders {
+        ForwardedHeaders {
+            enabled,
+            rfc7239,
+            trusted_proxies: trusted.iter().map(|cidr| cidr.parse().unwrap()).collect(),
+        }
+    }
+
+    fn request_values(header: &RequestHeader, name: &str) -> Vec<String> {
+        header
+            .headers
+            .get_all(name)
+            .iter()
+            .map(|v| v.to_str().unwrap().to_string())
+            .collect()
+    }
+
+    fn spoofed_request() -> RequestHeader {
+        let mut header = RequestHeader::build("GET", b"/", None).unwrap();
+        header.append_header("Host", "example.com").unwrap();
+        header.append_header("X-Forwarded-For", "1.1.1.1").unwrap();
+        header.append_header("X-Forwarded-For", "2.2.2.2").unwrap();
+        header.append_header("X-Forwarded-Proto", "https").unwrap();
+        header
+    }
+
+    #[test]
+    fn test_forwarded_replaces_headers_of_direct_client() {
+        let mut header = spoofed_request();
+
+        apply_forwarded_headers(
+            &forwarded(true, false, &["10.0.0.0/8"]),
+            Some("203.0.113.7".parse().unwrap()),
+            false,
+            &mut header,
+        );
+
+        assert_eq!(
+            request_values(&header, "X-Forwarded-For"),
+            vec!["203.0.113.7"]
+        );
+        assert_eq!(request_values(&header, "X-Forwarded-Proto"), vec!["http"]);
+        assert_eq!(
+            request_values(&header, "X-Forwarded-Host"),
+            vec!["example.com"]
+        );
+        assert!(header.headers.get("forwarded").is_none());
+    }
+
+    #[test]
+    fn test_forwarded_appends_to_trusted_proxy() {
+        let mut header = spoofed_request();
+
+        apply_forwarded_headers(
+            &forwarded(true, false, &["10.0.0.0/8"]),
+            Some("10.1.2.3".parse().unwrap()),
+            false,
+            &mut header,
+        );
+
+        assert_eq!(
+            request_values(&header, "X-Forwarded-For"),
+            vec!["1.1.1.1, 2.2.2.2, 10.1.2.3"]
+        );
+        assert_eq!(request_values(&header, "X-Forwarded-Proto"), vec!["https"]);
+    }
+
+    #[test]
+    fn test_forwarded_rfc7239() {
+        let mut header = RequestHeader::build("GET", b"/", None).unwrap();
+        header.append_header("Host", "example.com").unwrap();
+        header.append_header("Forwarded", "for=1.1.1.1").unwrap();
+
+        apply_forwarded_headers(
+            &forwarded(false, true, &[]),
+            Some("::1".parse().unwrap()),
+            true,
+            &mut header,
+        );
+
+        assert_eq!(
+            request_values(&header, "Forwarded"),
+            vec![r#"for="[::1]";proto=https;host="example.com""#]
+        );
+        assert!(header.headers.get("x-forwarded-for").is_none());
+    }
+}
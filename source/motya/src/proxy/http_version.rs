@@ -0,0 +1,43 @@
+use pingora::{prelude::HttpPeer, protocols::ALPN};
+
+use motya_config::common_types::connectors::UpstreamHttpVersion;
+
+/// Makes the connection to the upstream speak the `upstream-http-version` of its connector.
+///
+/// HTTP/2 over a cleartext peer is spoken with prior knowledge, the configuration only
+/// allows it with `h2c=#true`.
+pub fn negotiate_http_version(peer: &mut HttpPeer, version: UpstreamHttpVersion) {
+    peer.options.alpn = match version {
+        UpstreamHttpVersion::Http1 => ALPN::H1,
+        UpstreamHttpVersion::Http2 { .. } => ALPN::H2,
+        UpstreamHttpVersion::Auto if peer.is_tls() => ALPN::H2H1,
+        UpstreamHttpVersion::Auto => ALPN::H1,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_negotiated_alpn() {
+        let tls = || HttpPeer::new("127.0.0.1:8443", true, "backend.internal".to_string());
+        let cleartext = || HttpPeer::new("127.0.0.1:8000", false, String::new());
+
+        let cases = [
+            (tls(), UpstreamHttpVersion::Auto, ALPN::H2H1),
+            (cleartext(), UpstreamHttpVersion::Auto, ALPN::H1),
+            (tls(), UpstreamHttpVersion::Http1, ALPN::H1),
+            (tls(), UpstreamHttpVersion::Http2 { h2c: false }, ALPN::H2),
+            (
+                cleartext(),
+                UpstreamHttpVersion::Http2 { h2c: true },
+                ALPN::H2,
+            ),
+        ];
+        for (mut peer, version, alpn) in cases {
+            negotiate_http_version(&mut peer, version);
+            assert_eq!(peer.options.alpn, alpn, "{version:?}");
+        }
+    }
+}
@@ -0,0 +1,246 @@
+use std::{
+    mem::ManuallyDrop,
+    net::{SocketAddr, TcpListener as StdTcpListener},
+    os::fd::{FromRawFd, IntoRawFd},
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use miette::{miette, IntoDiagnostic};
+use pingora::{
+    apps::ServerApp, server::ShutdownWatch, services::background::BackgroundService,
+    tls::ssl::SslAcceptor,
+};
+use tokio::net::TcpListener;
+
+use motya_config::common_types::listeners::{ListenerConfig, ListenerKind, Listeners};
+
+use crate::proxy::{accept::serve, populate_listeners::tls_acceptor};
+
+/// A listener on a socket inherited from the process that started motya, handing each
+/// connection to the service's app, with a TLS handshake first if the listener has TLS,
+/// as pingora only accepts on the addresses it binds itself.
+pub struct InheritedFdListener<A> {
+    fd: i32,
+    /// Taken out when the service starts.
+    listener: Mutex<Option<StdTcpListener>>,
+    tls: Option<SslAcceptor>,
+    app: Arc<A>,
+}
+
+/// A listener for every `fd` listener, to run next to the service serving `app`.
+///
+/// `populate_listners` leaves these listeners out, and the settings looked up by the
+/// address a connection was accepted on find them through [`with_inherited_addrs`].
+pub fn inherited_fd_listeners<A>(
+    listeners: &Listeners,
+    app: &Arc<A>,
+) -> miette::Result<Vec<InheritedFdListener<A>>> {
+    let mut inherited = vec![];
+
+    for cfg in &listeners.list_cfgs {
+        let ListenerKind::InheritedFd { fd, tls, offer_h2 } = &cfg.source else {
+            continue;
+        };
+        if cfg!(not(target_os = "linux")) {
+            return Err(miette!(
+                "Inherited 'fd' listeners are only supported on Linux, found fd {fd}"
+            ));
+        }
+
+        let tls = tls
+            .as_ref()
+            .map(|tls| tls_acceptor(tls, *offer_h2))
+            .transpose()?;
+        inherited.push(InheritedFdListener {
+            fd: *fd,
+            listener: Mutex::new(Some(take_listener(*fd)?)),
+            tls,
+            app: app.clone(),
+        });
+    }
+
+    Ok(inherited)
+}
+
+/// The listeners with every `fd` listener standing for the address its socket listens on,
+/// which is the local address of the connections accepted on it.
+pub fn with_inherited_addrs(listeners: &Listeners) -> miette::Result<Listeners> {
+    let list_cfgs = listeners
+        .list_cfgs
+        .iter()
+        .map(|cfg| {
+            let ListenerKind::InheritedFd { fd, tls, offer_h2 } = &cfg.source else {
+                return Ok(cfg.clone());
+            };
+            Ok(ListenerConfig {
+                source: ListenerKind::Tcp {
+                    addr: local_addr(*fd)?.to_string(),
+                    tls: tls.clone(),
+                    offer_h2: *offer_h2,
+                },
+                ..cfg.clone()
+            })
+        })
+        .collect::<miette::Result<_>>()?;
+
+    Ok(Listeners { list_cfgs })
+}
+
+/// Address the listening socket `fd` is bound to, leaving the socket open.
+fn local_addr(fd: i32) -> miette::Result<SocketAddr> {
+    // SAFETY: the configuration hands `fd` over to motya, and the listener is only
+    // borrowed here, never closing the fd.
+    let listener = ManuallyDrop::new(unsafe { StdTcpListener::from_raw_fd(fd) });
+
+    listener
+        .local_addr()
+        .map_err(|err| miette!("Inherited fd {fd} is not a listening socket: {err}"))
+}
+
+/// Takes over the listening socket `fd`, left open if it turns out not to be one.
+fn take_listener(fd: i32) -> miette::Result<StdTcpListener> {
+    local_addr(fd)?;
+
+    // SAFETY: the configuration hands `fd` over to motya, and a listener is the only
+    // user of its fd.
+    let listener = unsafe { StdTcpListener::from_raw_fd(fd) };
+    if let Err(err) = listener.set_nonblocking(true) {
+        // not ours to close
+        let _ = listener.into_raw_fd();
+        return Err(err).into_diagnostic();
+    }
+    Ok(listener)
+}
+
+#[async_trait]
+impl<A> BackgroundService for InheritedFdListener<A>
+where
+    A: ServerApp + Send + Sync + 'static,
+{
+    async fn start(&self, mut shutdown: ShutdownWatch) {
+        let Some(listener) = self.listener.lock().unwrap().take() else {
+            return;
+        };
+        // the socket was checked when the service was built, it is only registered with
+        // the runtime running the service here, which `bootstrap` starts after daemonizing
+        let listener = match TcpListener::from_std(listener) {
+            Ok(listener) => listener,
+            Err(err) => {
+                tracing::error!("Failed to listen on inherited fd {}: {err}", self.fd);
+                return;
+            }
+        };
+
+        loop {
+            tokio::select! {
+                _ = shutdown.changed() => return,
+                accepted = listener.accept() => match accepted {
+                    Ok((stream, _)) => {
+                        let (app, tls, shutdown) = (self.app.clone(), self.tls.clone(), shutdown.clone());
+                        tokio::spawn(async move {
+                            if let Err(err) = serve(&app, stream, tls.as_ref(), &shutdown).await {
+                                tracing::debug!("inherited fd connection ended with an error: {err}");
+                            }
+                        });
+                    }
+                    Err(err) => tracing::warn!("Failed to accept on inherited fd {}: {err}", self.fd),
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pingora::protocols::{GetSocketDigest, Stream};
+    use tokio::{io::AsyncWriteExt, net::TcpStream, sync::mpsc};
+
+    use super::*;
+
+    fn listener(source: ListenerKind) -> ListenerConfig {
+        ListenerConfig {
+            max_concurrent: Some(10),
+            ..ListenerConfig::new(source)
+        }
+    }
+
+    /// Reports the client and local addresses of each connection it's handed.
+    struct PeerApp(mpsc::UnboundedSender<(Option<SocketAddr>, Option<SocketAddr>)>);
+
+    #[async_trait]
+    impl ServerApp for PeerApp {
+        async fn process_new(
+            self: &Arc<Self>,
+            stream: Stream,
+            _shutdown: &ShutdownWatch,
+        ) -> Option<Stream> {
+            let digest = stream.get_socket_digest();
+            let addrs = digest.map_or((None, None), |digest| {
+                (
+                    digest.peer_addr().and_then(|addr| addr.as_inet().copied()),
+                    digest.local_addr().and_then(|addr| addr.as_inet().copied()),
+                )
+            });
+            self.0.send(addrs).unwrap();
+            None
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_serves_inherited_socket() {
+        let inherited = StdTcpListener::bind("127.0.0.1:0").unwrap();
+        let public = inherited.local_addr().unwrap();
+        let listeners = Listeners {
+            list_cfgs: vec![listener(ListenerKind::InheritedFd {
+                fd: inherited.into_raw_fd(),
+                tls: None,
+                offer_h2: false,
+            })],
+        };
+
+        let by_addr = with_inherited_addrs(&listeners).unwrap();
+        assert_eq!(
+            by_addr.list_cfgs[0].source,
+            ListenerKind::Tcp {
+                addr: public.to_string(),
+                tls: None,
+                offer_h2: false,
+            }
+        );
+        assert_eq!(by_addr.list_cfgs[0].max_concurrent, Some(10));
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let mut inherited = inherited_fd_listeners(&listeners, &Arc::new(PeerApp(tx))).unwrap();
+        assert_eq!(inherited.len(), 1);
+        let inherited = inherited.remove(0);
+        let (_shutdown_tx, shutdown) = tokio::sync::watch::channel(false);
+        tokio::spawn(async move { inherited.start(shutdown).await });
+
+        let mut client = TcpStream::connect(public).await.unwrap();
+        client.write_all(b"GET / HTTP/1.1\r\n").await.unwrap();
+
+        // the app gets the client's own connection, not one relayed over loopback
+        let (peer, local) = rx.recv().await.unwrap();
+        assert_eq!(peer, Some(client.local_addr().unwrap()));
+        assert_eq!(local, Some(public));
+    }
+
+    #[test]
+    fn test_rejects_fd_that_is_not_a_socket() {
+        let file = tempfile::tempfile().unwrap();
+        let fd = file.into_raw_fd();
+
+        let err = take_listener(fd).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains(&format!("Inherited fd {fd} is not a listening socket")),
+            "{err}"
+        );
+
+        // the fd was left open for its owner
+        // SAFETY: the fd came from `into_raw_fd` above and was not closed
+        drop(unsafe { std::fs::File::from_raw_fd(fd) });
+    }
+}
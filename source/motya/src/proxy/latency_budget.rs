@@ -0,0 +1,129 @@
+use std::{
+    fmt::Write,
+    time::{Duration, Instant},
+};
+
+use pingora::prelude::HttpPeer;
+
+/// The `latency-budget` of a request, with the time spent so far in each phase.
+///
+/// Each phase boundary is charged the time since the previous one, so the phases always
+/// add up to the total. The budget is over once the total passes it, whether the time went
+/// to the filters, to failed connects or to waiting on the upstream.
+#[derive(Debug, Clone)]
+pub struct LatencyBudget {
+    budget: Duration,
+    started: Instant,
+    last_boundary: Instant,
+    phases: Vec<(&'static str, Duration)>,
+}
+
+impl LatencyBudget {
+    pub fn new(budget: Duration, started: Instant) -> Self {
+        Self {
+            budget,
+            started,
+            last_boundary: started,
+            phases: vec![],
+        }
+    }
+
+    /// Ends `phase` at `now`, returning `false` if the request is over its budget.
+    pub fn boundary(&mut self, phase: &'static str, now: Instant) -> bool {
+        self.phases
+            .push((phase, now.saturating_duration_since(self.last_boundary)));
+        self.last_boundary = now;
+        !self.exceeded(now)
+    }
+
+    pub fn exceeded(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.started) > self.budget
+    }
+
+    /// Time left at `now`, zero once the budget is over.
+    pub fn remaining(&self, now: Instant) -> Duration {
+        self.budget
+            .saturating_sub(now.saturating_duration_since(self.started))
+    }
+
+    /// Keeps the connect and the wait for the upstream within what is left of the budget,
+    /// so a slow upstream fails the request on time instead of at the next boundary.
+    pub fn bound_peer(&self, peer: &mut HttpPeer, now: Instant) {
+        let remaining = self.remaining(now);
+        let bound =
+            |timeout: Option<Duration>| Some(timeout.map_or(remaining, |t| t.min(remaining)));
+
+        peer.options.total_connection_timeout = bound(peer.options.total_connection_timeout);
+        peer.options.read_timeout = bound(peer.options.read_timeout);
+        peer.options.write_timeout = bound(peer.options.write_timeout);
+    }
+
+    /// Where the time went, like `2s spent of a 1.5s budget: request filters 3ms, ...`.
+    pub fn breakdown(&self, now: Instant) -> String {
+        let mut out = format!(
+            "{:?} spent of a {:?} budget",
+            now.saturating_duration_since(self.started),
+            self.budget
+        );
+
+        let mut separator = ": ";
+        for (phase, spent) in &self.phases {
+            let _ = write!(out, "{separator}{phase} {spent:?}");
+            separator = ", ";
+        }
+        let unaccounted = now.saturating_duration_since(self.last_boundary);
+        if !unaccounted.is_zero() {
+            let _ = write!(out, "{separator}in progress {unaccounted:?}");
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exceeded_across_retries() {
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+        let mut budget = LatencyBudget::new(Duration::from_secs(2), start);
+
+        assert!(budget.boundary("request filters", at(50)));
+        // the first attempt and the first retry fail to connect
+        assert!(budget.boundary("connect failed", at(950)));
+        assert!(budget.boundary("connect failed", at(1850)));
+        assert_eq!(budget.remaining(at(1850)), Duration::from_millis(150));
+        // the second retry connects, but the upstream answers too late
+        assert!(budget.boundary("connect", at(1900)));
+        assert!(!budget.boundary("upstream response", at(2400)));
+
+        assert_eq!(budget.remaining(at(2400)), Duration::ZERO);
+        assert_eq!(
+            budget.breakdown(at(2400)),
+            "2.4s spent of a 2s budget: request filters 50ms, connect failed 900ms, \
+             connect failed 900ms, connect 50ms, upstream response 500ms"
+        );
+    }
+
+    #[test]
+    fn test_peer_bound_by_remaining() {
+        let start = Instant::now();
+        let budget = LatencyBudget::new(Duration::from_secs(2), start);
+
+        let mut peer = HttpPeer::new("127.0.0.1:8000", false, String::new());
+        peer.options.read_timeout = Some(Duration::from_millis(100));
+        budget.bound_peer(&mut peer, start + Duration::from_millis(500));
+
+        assert_eq!(
+            peer.options.total_connection_timeout,
+            Some(Duration::from_millis(1500))
+        );
+        assert_eq!(peer.options.read_timeout, Some(Duration::from_millis(100)));
+        assert_eq!(
+            peer.options.write_timeout,
+            Some(Duration::from_millis(1500))
+        );
+    }
+}
@@ -0,0 +1,129 @@
+use std::{
+    panic::{catch_unwind, AssertUnwindSafe},
+    sync::{Arc, OnceLock, RwLock},
+};
+
+use tracing::Level;
+
+/// Where a [`LogRecord`] comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogSource {
+    /// A WASM filter calling `logger::info`, `logger::error` or `logger::debug`.
+    Plugin,
+    /// One line per finished request.
+    Access,
+    /// A backend ejected or re-admitted by outlier detection, or changing health.
+    Upstream,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogRecord {
+    pub level: Level,
+    pub source: LogSource,
+    pub message: String,
+    /// Details of the event for sinks that index them, also written out in `message`.
+    pub fields: Vec<(&'static str, String)>,
+}
+
+/// A destination for motya's logs, for hosts embedding motya that want them somewhere
+/// other than the `tracing` subscriber.
+///
+/// Sinks are called synchronously, on the thread handling the request, in the order they
+/// were registered, so `emit` should hand slow work off rather than block. A sink that
+/// panics is reported and skipped: the other sinks still get the record and the request
+/// goes on.
+pub trait LogSink: Send + Sync {
+    fn emit(&self, record: LogRecord);
+}
+
+/// The default sink, writing to the `tracing` subscriber.
+pub struct TracingSink;
+
+impl LogSink for TracingSink {
+    fn emit(&self, record: LogRecord) {
+        let prefix = match record.source {
+            LogSource::Plugin => "WASM LOG",
+            LogSource::Access => "ACCESS",
+            LogSource::Upstream => "UPSTREAM",
+        };
+
+        match record.level {
+            Level::ERROR => tracing::error!("{prefix}: {}", record.message),
+            Level::WARN => tracing::warn!("{prefix}: {}", record.message),
+            Level::INFO => tracing::info!("{prefix}: {}", record.message),
+            Level::DEBUG => tracing::debug!("{prefix}: {}", record.message),
+            Level::TRACE => tracing::trace!("{prefix}: {}", record.message),
+        }
+    }
+}
+
+fn sinks() -> &'static RwLock<Vec<Arc<dyn LogSink>>> {
+    static SINKS: OnceLock<RwLock<Vec<Arc<dyn LogSink>>>> = OnceLock::new();
+    SINKS.get_or_init(|| RwLock::new(vec![Arc::new(TracingSink)]))
+}
+
+/// Adds a sink after the ones already registered, the first being [`TracingSink`].
+pub fn register_sink(sink: Arc<dyn LogSink>) {
+    sinks()
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .push(sink);
+}
+
+/// Hands the record to every registered sink.
+pub fn emit(record: LogRecord) {
+    let sinks = sinks()
+        .read()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .clone();
+
+    for sink in sinks {
+        let record = record.clone();
+        if catch_unwind(AssertUnwindSafe(|| sink.emit(record))).is_err() {
+            tracing::warn!("A log sink panicked, the record was not delivered to it");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct MemorySink(Mutex<Vec<LogRecord>>);
+
+    impl LogSink for MemorySink {
+        fn emit(&self, record: LogRecord) {
+            self.0.lock().unwrap().push(record);
+        }
+    }
+
+    struct PanickingSink;
+
+    impl LogSink for PanickingSink {
+        fn emit(&self, _record: LogRecord) {
+            panic!("sink is broken");
+        }
+    }
+
+    #[test]
+    fn test_records_reach_every_sink() {
+        let memory = Arc::new(MemorySink::default());
+        register_sink(Arc::new(PanickingSink));
+        register_sink(memory.clone());
+
+        let record = LogRecord {
+            level: Level::INFO,
+            source: LogSource::Plugin,
+            message: "test_records_reach_every_sink".to_string(),
+            fields: vec![],
+        };
+        emit(record.clone());
+
+        // other tests may log concurrently through the same sinks
+        let captured = memory.0.lock().unwrap();
+        assert!(captured.contains(&record));
+    }
+}
@@ -1,40 +1,102 @@
-use std::sync::Arc;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use arc_swap::ArcSwap;
 use async_trait::async_trait;
-use futures_util::future::try_join_all;
-use http::uri::PathAndQuery;
-use pingora::{prelude::HttpPeer, server::Server, Result};
+use bytes::Bytes;
+use http::{uri::PathAndQuery, HeaderName};
+use pingora::{
+    prelude::HttpPeer,
+    server::Server,
+    services::{background::background_service, listening::Service},
+    upstreams::peer::Peer,
+    Result,
+};
 use pingora_http::{RequestHeader, ResponseHeader};
-use pingora_proxy::{ProxyHttp, Session};
+use pingora_proxy::{FailToProxy, ProxyHttp, Session};
 use uuid::Uuid;
 
 use crate::proxy::{
+    accept::SharedApp,
+    access_log::AccessRecord,
+    auto_tls::auto_tls_listeners,
+    balancer::outlier::OutlierDetector,
+    body_buffer::BodyBuffer,
+    cache::{CacheFill, CacheKey, CachedResponse, Lookup, Revalidation},
+    client_timeouts::{ClientTimeouts, ClientTimeoutsApp},
+    concurrency::{Admission, ConcurrencyGate},
+    connection_limit::{ConnectionLimit, ConnectionLimitApp},
+    connection_reuse::connection_reuse,
     context::{ContextInfo, SessionInfo},
     filters::builtin::simple_response::SimpleResponse,
     filters::{
         chain_resolver::ChainResolver,
         types::{RequestFilterMod, RequestModifyMod, ResponseModifyMod},
     },
+    header_limit::{HeaderLimits, REJECTED_HEADER},
+    headers::{
+        apply_accept_encoding, apply_forwarded_headers, apply_request_rules, apply_response_rules,
+        apply_upstream_host, apply_upstream_method, RequestVariables,
+    },
+    inherited_fd::{inherited_fd_listeners, with_inherited_addrs},
+    latency_budget::LatencyBudget,
+    log_sink::{self, LogRecord, LogSource},
+    plugins::host::unix_millis,
     populate_listeners::populate_listners,
+    require_tls::{self, TlsCheck},
+    status::InFlightGuard,
+    tcp_nodelay::TcpNoDelay,
     upstream_factory::UpstreamFactory,
     upstream_router::{UpstreamContext, UpstreamRouter},
+    uri_limit::UriLimits,
 };
 use motya_config::{
     common_types::{
-        connectors::{UpstreamConfig, UpstreamContextConfig},
+        access_log::AccessLogFormat,
+        connectors::{UpstreamConfig, UpstreamContextConfig, UpstreamProtocol},
+        error_pages::ErrorPages,
         listeners::Listeners,
+        routes::SplitRouteConfig,
     },
     internal::ProxyConfig,
 };
 
+pub mod accept;
+pub mod access_log;
+pub mod admin;
+pub mod auto_tls;
 pub mod balancer;
+pub mod body_buffer;
+pub mod cache;
+pub mod client_timeouts;
+pub mod concurrency;
+pub mod connection_limit;
+pub mod connection_reuse;
 pub mod context;
 pub mod filters;
+pub mod grpc;
+pub mod header_limit;
+pub mod headers;
+pub mod http_version;
+pub mod inherited_fd;
+pub mod latency_budget;
+pub mod log_sink;
+pub mod ocsp;
 pub mod plugins;
 pub mod populate_listeners;
+pub mod rate_limit;
+pub mod redis_rate_limit;
+pub mod require_tls;
+pub mod simulate;
+pub mod split;
+pub mod status;
+pub mod tcp_nodelay;
+pub mod upgrade;
 pub mod upstream_factory;
 pub mod upstream_router;
+pub mod uri_limit;
 pub mod watcher;
 
 // pub struct RateLimiters {
@@ -47,37 +109,56 @@ pub type SharedProxyState = Arc<ArcSwap<UpstreamRouter<UpstreamContext>>>;
 pub struct MotyaProxyService {
     // pub rate_limiters: RateLimiters,
     pub state: SharedProxyState,
+    pub concurrency: ConcurrencyGate,
+    pub tcp_nodelay: TcpNoDelay,
+    pub uri_limits: UriLimits,
+    pub header_limits: HeaderLimits,
+    pub error_pages: ErrorPages,
+    pub access_log: AccessLogFormat,
 }
 
 /// Create a proxy service, with the type parameters chosen based on the config file
+///
+/// `connections` is the process-wide connection limit, shared by all services.
 pub async fn motya_proxy_service(
     conf: ProxyConfig,
     chain_resolver: ChainResolver,
+    connections: ConnectionLimit,
     server: &Server,
-) -> miette::Result<(Box<dyn pingora::services::Service>, SharedProxyState)> {
+) -> miette::Result<(Vec<Box<dyn pingora::services::Service>>, SharedProxyState)> {
     let factory = UpstreamFactory::new(chain_resolver);
 
-    MotyaProxyService::from_basic_conf(conf.connectors.upstreams, &conf.listeners, factory, server)
-        .await
+    MotyaProxyService::from_basic_conf(
+        conf.connectors.upstreams,
+        conf.routes,
+        &conf.listeners,
+        conf.error_pages,
+        conf.access_log,
+        factory,
+        connections,
+        server,
+    )
+    .await
 }
 
 impl MotyaProxyService {
     /// Create a new [MotyaProxyService] from the given [ProxyConfig]
+    ///
+    /// Returns the proxy service, followed by the listeners that accept on their own and
+    /// have to run next to it.
     pub async fn from_basic_conf(
         upstream_configs: Vec<UpstreamContextConfig>,
+        routes: Vec<SplitRouteConfig>,
         listeners: &Listeners,
+        error_pages: ErrorPages,
+        access_log: AccessLogFormat,
         upstream_factory: UpstreamFactory,
+        connections: ConnectionLimit,
         server: &Server,
-    ) -> miette::Result<(Box<dyn pingora::services::Service>, SharedProxyState)> {
-        let upstream_ctx = try_join_all(
-            upstream_configs
-                .into_iter()
-                .map(|cfg| upstream_factory.create_context(cfg)),
-        )
-        .await?;
-
-        let router = UpstreamRouter::build(upstream_ctx)
-            .expect("Paths must be valid after parsing the configuration");
+    ) -> miette::Result<(Vec<Box<dyn pingora::services::Service>>, SharedProxyState)> {
+        let router = upstream_factory
+            .create_router(upstream_configs, routes)
+            .await?;
 
         // let mut request_filter_stage_multi = vec![];
         // let mut request_filter_stage_single = vec![];
@@ -95,23 +176,192 @@ impl MotyaProxyService {
         //     }
         // }
 
+        // settings are looked up by the address a connection was accepted on
+        let by_addr = with_inherited_addrs(listeners)?;
         let shared_state = Arc::new(ArcSwap::from_pointee(router));
-        let mut my_proxy = pingora_proxy::http_proxy_service_with_name(
+        let proxy = pingora_proxy::http_proxy(
             &server.configuration,
             Self {
                 state: shared_state.clone(),
+                concurrency: ConcurrencyGate::from_listeners(&by_addr),
+                tcp_nodelay: TcpNoDelay::from_listeners(&by_addr),
+                uri_limits: UriLimits::from_listeners(&by_addr),
+                header_limits: HeaderLimits::from_listeners(&by_addr),
+                error_pages,
+                access_log,
             },
-            "motya-proxy",
         );
+        // the timeouts are set ahead of the proxy, which reads the request header first thing
+        let app = ClientTimeoutsApp::new(proxy, ClientTimeouts::from_listeners(&by_addr));
+        let app = Arc::new(ConnectionLimitApp::new(app, connections));
+        let mut my_proxy = Service::new("motya-proxy".to_string(), SharedApp(app.clone()));
 
         populate_listners(listeners, &mut my_proxy);
 
-        Ok((Box::new(my_proxy), shared_state))
+        let mut services: Vec<Box<dyn pingora::services::Service>> = vec![Box::new(my_proxy)];
+        for listener in auto_tls_listeners(listeners, &app)? {
+            services.push(Box::new(background_service("auto-tls", listener)));
+        }
+        for listener in inherited_fd_listeners(listeners, &app)? {
+            services.push(Box::new(background_service("inherited-fd", listener)));
+        }
+
+        Ok((services, shared_state))
+    }
+
+    /// Responds with the configured error page for `status`, or a bodiless default one.
+    async fn respond_error(&self, session: &mut Session, status: u16) -> Result<()> {
+        self.respond_error_with(session, status, None).await
+    }
+
+    /// Like [`Self::respond_error`], adding the `extra` header to the response.
+    async fn respond_error_with(
+        &self,
+        session: &mut Session,
+        status: u16,
+        extra: Option<(&'static str, &str)>,
+    ) -> Result<()> {
+        let page = self.error_pages.get(status);
+        if page.is_none() && extra.is_none() {
+            return session.respond_error(status).await;
+        }
+
+        let mut header = ResponseHeader::build(status, Some(3))?;
+        if let Some((name, value)) = extra {
+            header.insert_header(name, value)?;
+        }
+        let body = match page {
+            Some(page) => {
+                header.insert_header(http::header::CONTENT_TYPE, page.content_type)?;
+                Bytes::copy_from_slice(&page.body)
+            }
+            None => Bytes::new(),
+        };
+        header.insert_header(http::header::CONTENT_LENGTH, body.len())?;
+
+        session
+            .downstream_session
+            .write_response_header(Box::new(header))
+            .await?;
+        let body = match session.req_header().method {
+            http::Method::HEAD => Bytes::new(),
+            _ => body,
+        };
+        session
+            .downstream_session
+            .write_response_body(body, true)
+            .await?;
+
+        Ok(())
     }
 }
 
 pub struct MotyaContext {
     router: Arc<UpstreamRouter<UpstreamContext>>,
+    /// Held for the whole request; dropping the context frees the listener slot.
+    _admission: Option<Admission>,
+    /// Key of a cache miss, the upstream response is stored under it.
+    cache_key: Option<CacheKey>,
+    cache_fill: Option<CacheFill>,
+    /// Address of the picked backend, for outlier detection and WASM filters.
+    selected_upstream: Option<String>,
+    /// When the backend was picked, the last one on retries.
+    upstream_started: Option<Instant>,
+    /// Time from picking the backend until its response was read, for the access log.
+    upstream_response_time: Option<Duration>,
+    /// Counts the request against the picked backend for the `status` endpoint.
+    _in_flight: Option<InFlightGuard>,
+    request_body: BodyBuffer,
+    response_body: BodyBuffer,
+    /// The request body read whole for WASM filters with `request-body`, as they left it.
+    /// Sent upstream in place of the downstream body, which they consumed.
+    wasm_request_body: Option<Bytes>,
+    /// Wall clock sample handed to every WASM filter of the request, taken by the first.
+    wasm_now_millis: Option<u64>,
+    /// When the request arrived, the start of its `latency-budget`.
+    started: Instant,
+    latency_budget: Option<LatencyBudget>,
+    /// `request-timeout` of the request, from its `route` or else its connector.
+    request_timeout: Option<Duration>,
+    /// An upgrade request its connector lets through. After the handshake its body is a
+    /// tunnel that only ends when the connection does, so it's never held back.
+    upgrade: bool,
+    /// Name of the header a request was answered with `431` for, for the access log.
+    rejected_header: Option<HeaderName>,
+}
+
+impl MotyaContext {
+    fn new(router: Arc<UpstreamRouter<UpstreamContext>>) -> Self {
+        Self {
+            router,
+            _admission: None,
+            cache_key: None,
+            cache_fill: None,
+            selected_upstream: None,
+            upstream_started: None,
+            upstream_response_time: None,
+            _in_flight: None,
+            request_body: BodyBuffer::default(),
+            response_body: BodyBuffer::default(),
+            wasm_request_body: None,
+            wasm_now_millis: None,
+            started: Instant::now(),
+            latency_budget: None,
+            request_timeout: None,
+            upgrade: false,
+            rejected_header: None,
+        }
+    }
+
+    /// Milliseconds since the UNIX epoch for `clock::now-millis`, the same for every WASM
+    /// filter of the request.
+    fn wasm_now_millis(&mut self) -> u64 {
+        *self.wasm_now_millis.get_or_insert_with(unix_millis)
+    }
+
+    /// Sets the upstream `Content-Length` to the body WASM filters left behind, if any.
+    fn set_wasm_body_length(&self, header: &mut RequestHeader) -> Result<()> {
+        if let Some(body) = &self.wasm_request_body {
+            header.remove_header(&http::header::TRANSFER_ENCODING);
+            header.insert_header(http::header::CONTENT_LENGTH, body.len())?;
+        }
+        Ok(())
+    }
+
+    /// Puts the body WASM filters left behind in place of the downstream one, returning
+    /// whether there is such a body.
+    fn replay_wasm_body(&self, body: &mut Option<Bytes>, end_of_stream: bool) -> bool {
+        let Some(rewritten) = &self.wasm_request_body else {
+            return false;
+        };
+        // the downstream body was read whole already, this is its replay
+        *body = end_of_stream.then(|| rewritten.clone());
+        true
+    }
+
+    /// Ends `phase` of a request with a `latency-budget`, failing it with a 504 once the
+    /// budget is over.
+    fn budget_boundary(&mut self, phase: &'static str) -> Result<()> {
+        match &mut self.latency_budget {
+            Some(budget) if !budget.boundary(phase, Instant::now()) => {
+                Err(pingora::Error::new(pingora::ErrorType::HTTPStatus(504)))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn upstream_responded(&mut self) {
+        self.upstream_response_time = self.upstream_started.map(|started| started.elapsed());
+    }
+
+    fn outliers<'a>(&'a self, session: &Session) -> Option<&'a OutlierDetector> {
+        self.router
+            .get_upstream_by_path(session.req_header().uri.path())?
+            .balancer
+            .as_ref()?
+            .outlier_detection
+            .as_ref()
+    }
 }
 
 #[async_trait]
@@ -119,10 +369,22 @@ impl ProxyHttp for MotyaProxyService {
     type CTX = MotyaContext;
 
     fn new_ctx(&self) -> Self::CTX {
-        let router = self.state.load();
-        MotyaContext {
-            router: router.clone(),
+        MotyaContext::new(self.state.load_full())
+    }
+
+    async fn early_request_filter(&self, session: &mut Session, _ctx: &mut Self::CTX) -> Result<()>
+    where
+        Self::CTX: Send + Sync,
+    {
+        self.tcp_nodelay.apply(session);
+        // the socket digest lives as long as the connection, telling reused ones apart
+        if let Some(socket) = session
+            .digest()
+            .and_then(|digest| digest.socket_digest.as_ref())
+        {
+            connection_reuse().on_request(socket);
         }
+        Ok(())
     }
 
     /// Handle the "Request filter" stage
@@ -130,42 +392,88 @@ impl ProxyHttp for MotyaProxyService {
     where
         Self::CTX: Send + Sync,
     {
+        if self.uri_limits.exceeded(session) {
+            tracing::trace!("Rejecting a request over the listener's max-uri-length");
+            self.respond_error(session, 414).await?;
+            return Ok(true);
+        }
+        if let Some(name) = self.header_limits.exceeded(session) {
+            tracing::debug!(
+                "Rejecting a request whose '{name}' header is over the listener's max-header-size"
+            );
+            self.respond_error_with(session, 431, Some((REJECTED_HEADER, name.as_str())))
+                .await?;
+            ctx.rejected_header = Some(name);
+            return Ok(true);
+        }
+
+        let local_addr = session.server_addr().and_then(|addr| addr.as_inet());
+
+        match self.concurrency.try_admit(local_addr) {
+            Admission::Rejected => {
+                tracing::trace!("Rejecting due to listener concurrency limit");
+                self.respond_error(session, 503).await?;
+                return Ok(true);
+            }
+            admission => ctx._admission = Some(admission),
+        }
+
+        // the split is picked once, every later phase uses the chosen router
+        let (router, request_timeout) = ctx.router.route(session.req_header());
+        ctx.router = router;
+        ctx.request_timeout = request_timeout;
+
         let router = ctx.router.clone();
         let path = session.req_header().uri.path();
 
         if let Some(upstream_ctx) = router.get_upstream_by_path(path) {
-            // let multis = self
-            //     .rate_limiters
-            //     .request_filter_stage_multi
-            //     .iter()
-            //     .filter_map(|l| l.get_ticket(session));
-
-            // let singles = self
-            //     .rate_limiters
-            //     .request_filter_stage_single
-            //     .iter()
-            //     .filter_map(|l| l.get_ticket(session));
-
-            // // Attempt to get all tokens
-            // //
-            // // TODO: If https://github.com/udoprog/leaky-bucket/issues/17 is resolved we could
-            // // remember the buckets that we did get approved for, and "return" the unused tokens.
-            // //
-            // // For now, if some tickets succeed but subsequent tickets fail, the preceeding
-            // // approved tokens are just "burned".
-            // //
-            // // TODO: If https://github.com/udoprog/leaky-bucket/issues/34 is resolved we could
-            // // support a "max debt" number, allowing us to delay if acquisition of the token
-            // // would happen soon-ish, instead of immediately 429-ing if the token we need is
-            // // about to become available.
-            // if singles
-            //     .chain(multis)
-            //     .any(|t| t.now_or_never() == Outcome::Declined)
-            // {
-            //     tracing::trace!("Rejecting due to rate limiting failure");
-            //     session.downstream_session.respond_error(429).await?;
-            //     return Ok(true);
-            // }
+            ctx.latency_budget = upstream_ctx
+                .latency_budget
+                .map(|budget| LatencyBudget::new(budget, ctx.started));
+            ctx.upgrade =
+                upstream_ctx.allow_upgrades && upgrade::is_upgrade_request(session.req_header());
+
+            let tls = require_tls::is_tls(session);
+            match require_tls::check(upstream_ctx.require_tls, tls, session.req_header()) {
+                TlsCheck::Pass => {}
+                TlsCheck::Reject => {
+                    tracing::trace!("Rejecting a plaintext request to a 'require-tls' section");
+                    self.respond_error(session, 403).await?;
+                    return Ok(true);
+                }
+                TlsCheck::Redirect(location) => {
+                    let mut header = ResponseHeader::build(308, Some(2))?;
+                    header.insert_header(http::header::LOCATION, location)?;
+                    header.insert_header(http::header::CONTENT_LENGTH, 0)?;
+                    session
+                        .downstream_session
+                        .write_response_header(Box::new(header))
+                        .await?;
+                    return Ok(true);
+                }
+            }
+
+            if let Some(limiter) = &upstream_ctx.rate_limit {
+                static DEFAULT: PathAndQuery = PathAndQuery::from_static("/");
+
+                let admitted = limiter
+                    .try_acquire(&SessionInfo {
+                        headers: session.req_header(),
+                        client_addr: session.client_addr(),
+                        path: session
+                            .req_header()
+                            .uri
+                            .path_and_query()
+                            .unwrap_or(&DEFAULT),
+                    })
+                    .await;
+
+                if !admitted {
+                    tracing::trace!("Rejecting due to the connector's rate-limit");
+                    self.respond_error(session, 429).await?;
+                    return Ok(true);
+                }
+            }
 
             for chain in &upstream_ctx.chains {
                 for filter in &chain.actions {
@@ -179,6 +487,47 @@ impl ProxyHttp for MotyaProxyService {
                     }
                 }
             }
+            ctx.budget_boundary("request filters")?;
+
+            // the handshake must reach the upstream, whatever a cache holds for the path
+            if let Some(cache) = upstream_ctx.cache.as_ref().filter(|_| !ctx.upgrade) {
+                static DEFAULT: PathAndQuery = PathAndQuery::from_static("/");
+
+                let key = cache.key(
+                    &session.req_header().method,
+                    &SessionInfo {
+                        headers: session.req_header(),
+                        client_addr: session.client_addr(),
+                        path: session
+                            .req_header()
+                            .uri
+                            .path_and_query()
+                            .unwrap_or(&DEFAULT),
+                    },
+                );
+
+                if let Some(key) = key {
+                    match cache.lookup(&key) {
+                        Lookup::Fresh(hit) => {
+                            write_cached(session, &hit).await?;
+                            return Ok(true);
+                        }
+                        // a refresh is fetched as a GET, a stale HEAD just goes upstream
+                        Lookup::Stale(hit) if session.req_header().method == http::Method::GET => {
+                            if let Some(revalidation) = cache.claim_revalidation(&key) {
+                                spawn_revalidation(session, &router, upstream_ctx, revalidation)
+                                    .await?;
+                            }
+
+                            write_cached(session, &hit).await?;
+                            return Ok(true);
+                        }
+                        Lookup::Stale(_) | Lookup::Miss => {}
+                    }
+
+                    ctx.cache_key = Some(key);
+                }
+            }
 
             if let UpstreamConfig::Static(response) = upstream_ctx.upstream.clone() {
                 let _ = std::convert::Into::<SimpleResponse>::into(response)
@@ -211,7 +560,25 @@ impl ProxyHttp for MotyaProxyService {
                     .unwrap_or(&DEFAULT),
             },
         ) {
-            Ok(Some(peer)) => Ok(Box::new(peer)),
+            Ok(Some(mut peer)) => {
+                ctx.selected_upstream = Some(peer.address().to_string());
+                ctx.upstream_started = Some(Instant::now());
+                // a retry moves the request over to the backend it's retried on
+                ctx._in_flight = ctx
+                    .router
+                    .get_upstream_by_path(session.req_header().uri.path())
+                    .and_then(|upstream_ctx| {
+                        upstream_ctx.in_flight.enter(&peer.address().to_string())
+                    });
+                // the read timeout would also cut an idle tunnel once the upgrade succeeds
+                if let Some(timeout) = ctx.request_timeout.filter(|_| !ctx.upgrade) {
+                    peer.options.read_timeout = Some(timeout);
+                }
+                if let Some(budget) = ctx.latency_budget.as_ref().filter(|_| !ctx.upgrade) {
+                    budget.bound_peer(&mut peer, Instant::now());
+                }
+                Ok(Box::new(peer))
+            }
             Ok(None) => Err(pingora::Error::new(pingora::ErrorType::HTTPStatus(404))),
             Err(err) => {
                 let id = Uuid::new_v4();
@@ -235,14 +602,16 @@ impl ProxyHttp for MotyaProxyService {
         header: &mut RequestHeader,
         ctx: &mut Self::CTX,
     ) -> Result<()> {
+        ctx.budget_boundary("connect")?;
+
         let router = ctx.router.clone();
         let path = session.req_header().uri.path();
 
         if let Some(upstream_ctx) = router.get_upstream_by_path(path) {
-            for chain in &upstream_ctx.chains {
-                for filter in &chain.req_mods {
-                    filter.upstream_request_filter(session, header, ctx).await?;
-                }
+            prepare_upstream_request(session, header, ctx, upstream_ctx).await?;
+            ctx.set_wasm_body_length(header)?;
+            if !upstream_ctx.allow_upgrades && upgrade::is_upgrade_request(header) {
+                upgrade::refuse_upgrade(header);
             }
         }
 
@@ -260,6 +629,9 @@ impl ProxyHttp for MotyaProxyService {
         upstream_response: &mut ResponseHeader,
         ctx: &mut Self::CTX,
     ) -> Result<()> {
+        ctx.upstream_responded();
+        ctx.budget_boundary("upstream response")?;
+
         let router = ctx.router.clone();
         let path = session.req_header().uri.path();
 
@@ -269,7 +641,367 @@ impl ProxyHttp for MotyaProxyService {
                     filter.upstream_response_filter(session, upstream_response, ctx);
                 }
             }
+            apply_response_rules(&upstream_ctx.response_headers, upstream_response);
+
+            if let (Some(outliers), Some(addr)) = (ctx.outliers(session), &ctx.selected_upstream) {
+                if upstream_response.status.is_server_error() {
+                    let error = format!("status {}", upstream_response.status.as_u16());
+                    outliers.record_failure(addr, &error);
+                } else {
+                    outliers.record_success(addr);
+                }
+            }
+
+            // after the filters and the outlier check, so logs, caches and clients agree
+            if let Some(status) = upstream_ctx
+                .response_status_map
+                .get(upstream_response.status.as_u16())
+            {
+                upstream_response.set_status(status)?;
+            }
+
+            if let (Some(cache), Some(key)) = (&upstream_ctx.cache, ctx.cache_key.take()) {
+                ctx.cache_fill = cache.begin_fill(key, session.req_header(), upstream_response);
+            }
+        }
+        Ok(())
+    }
+
+    /// Counts connect errors towards ejecting the backend.
+    ///
+    /// A connector with a `latency-budget` retries the connect while the budget lasts.
+    fn fail_to_connect(
+        &self,
+        session: &mut Session,
+        peer: &HttpPeer,
+        ctx: &mut Self::CTX,
+        mut e: Box<pingora::Error>,
+    ) -> Box<pingora::Error> {
+        if let Some(outliers) = ctx.outliers(session) {
+            outliers.record_failure(&peer.address().to_string(), &e.to_string());
+        }
+        if ctx.latency_budget.is_some() {
+            match ctx.budget_boundary("connect failed") {
+                Ok(()) => e.set_retry(true),
+                Err(over_budget) => return over_budget,
+            }
+        }
+        e
+    }
+
+    /// Handle errors raised while proxying, answering with the configured error pages.
+    ///
+    /// Status codes are picked the same way as pingora's default implementation.
+    async fn fail_to_proxy(
+        &self,
+        session: &mut Session,
+        e: &pingora::Error,
+        ctx: &mut Self::CTX,
+    ) -> FailToProxy
+    where
+        Self::CTX: Send + Sync,
+    {
+        use pingora::{ErrorSource, ErrorType};
+
+        let code = match e.etype() {
+            ErrorType::HTTPStatus(code) => *code,
+            _ => match e.esource() {
+                ErrorSource::Upstream => 502,
+                ErrorSource::Downstream => match e.etype() {
+                    // the connection is already gone
+                    ErrorType::WriteError | ErrorType::ReadError | ErrorType::ConnectionClosed => 0,
+                    _ => 400,
+                },
+                ErrorSource::Internal | ErrorSource::Unset => 500,
+            },
+        };
+
+        // an upstream that didn't answer within the `request-timeout`
+        let code = match e.etype() {
+            ErrorType::ReadTimedout if code == 502 && ctx.request_timeout.is_some() => 504,
+            _ => code,
+        };
+
+        // whatever failed last, a request out of time says so
+        let now = Instant::now();
+        let code = match &ctx.latency_budget {
+            Some(budget) if code > 0 && budget.exceeded(now) => {
+                let req = session.req_header();
+                tracing::warn!(
+                    "{} {} exceeded its latency budget, {}",
+                    req.method,
+                    req.uri,
+                    budget.breakdown(now)
+                );
+                504
+            }
+            _ => code,
+        };
+
+        if code > 0 {
+            if let Err(err) = self.respond_error(session, code).await {
+                tracing::error!("Failed to send error response to downstream: {err}");
+            }
+        }
+
+        FailToProxy {
+            error_code: code,
+            can_reuse_downstream: false,
+        }
+    }
+
+    /// Holds back the request body until it's complete, for `buffer-request-body` connectors.
+    async fn request_body_filter(
+        &self,
+        session: &mut Session,
+        body: &mut Option<Bytes>,
+        end_of_stream: bool,
+        ctx: &mut Self::CTX,
+    ) -> Result<()>
+    where
+        Self::CTX: Send + Sync,
+    {
+        if ctx.replay_wasm_body(body, end_of_stream) {
+            return Ok(());
+        }
+
+        let limit = ctx
+            .router
+            .get_upstream_by_path(session.req_header().uri.path())
+            .filter(|upstream_ctx| upstream_ctx.buffer_request_body)
+            .map(|upstream_ctx| upstream_ctx.buffer_body_limit);
+
+        if let Some(limit) = limit.filter(|_| !ctx.upgrade) {
+            if ctx.request_body.hold(body, end_of_stream, limit).is_err() {
+                return Err(pingora::Error::explain(
+                    pingora::ErrorType::HTTPStatus(413),
+                    "request body is over the 'buffer-body-limit'",
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Holds back the response body until it's complete, for `buffer-response-body` connectors.
+    fn response_body_filter(
+        &self,
+        session: &mut Session,
+        body: &mut Option<Bytes>,
+        end_of_stream: bool,
+        ctx: &mut Self::CTX,
+    ) -> Result<Option<std::time::Duration>>
+    where
+        Self::CTX: Send + Sync,
+    {
+        let limit = ctx
+            .router
+            .get_upstream_by_path(session.req_header().uri.path())
+            .filter(|upstream_ctx| upstream_ctx.buffer_response_body)
+            .map(|upstream_ctx| upstream_ctx.buffer_body_limit);
+
+        if let Some(limit) = limit.filter(|_| !ctx.upgrade) {
+            // the header is already on its way, so the client sees the connection cut
+            if ctx.response_body.hold(body, end_of_stream, limit).is_err() {
+                return Err(pingora::Error::explain(
+                    pingora::ErrorType::HTTPStatus(502),
+                    "response body is over the 'buffer-body-limit'",
+                ));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Passes the trailers of `protocol "grpc"` responses on, carrying `grpc-status`.
+    async fn response_trailer_filter(
+        &self,
+        session: &mut Session,
+        upstream_trailers: &mut http::HeaderMap,
+        ctx: &mut Self::CTX,
+    ) -> Result<Option<Bytes>>
+    where
+        Self::CTX: Send + Sync,
+    {
+        let grpc = ctx
+            .router
+            .get_upstream_by_path(session.req_header().uri.path())
+            .is_some_and(|upstream_ctx| upstream_ctx.protocol == UpstreamProtocol::Grpc);
+
+        if grpc {
+            grpc::forward_trailers(upstream_trailers);
+        }
+        Ok(None)
+    }
+
+    /// Collects the body of a cacheable response and stores it once complete.
+    fn upstream_response_body_filter(
+        &self,
+        session: &mut Session,
+        body: &mut Option<Bytes>,
+        end_of_stream: bool,
+        ctx: &mut Self::CTX,
+    ) -> Result<()> {
+        if end_of_stream {
+            ctx.upstream_responded();
+        }
+        if ctx.cache_fill.is_none() {
+            return Ok(());
+        }
+
+        let router = ctx.router.clone();
+        let path = session.req_header().uri.path();
+
+        if let Some(cache) = router
+            .get_upstream_by_path(path)
+            .and_then(|upstream_ctx| upstream_ctx.cache.as_ref())
+        {
+            if let Some(chunk) = body {
+                cache.extend_fill(&mut ctx.cache_fill, chunk);
+            }
+            if end_of_stream {
+                if let Some(fill) = ctx.cache_fill.take() {
+                    cache.finish_fill(fill);
+                }
+            }
         }
+
         Ok(())
     }
+
+    /// Emits the access record of the finished request to the log sinks.
+    async fn logging(&self, session: &mut Session, e: Option<&pingora::Error>, ctx: &mut Self::CTX)
+    where
+        Self::CTX: Send + Sync,
+    {
+        let status = session
+            .response_written()
+            .map(|header| header.status.as_u16())
+            .unwrap_or_default();
+        let req = session.req_header();
+
+        let record = AccessRecord {
+            method: req.method.as_str(),
+            uri: req.uri.to_string(),
+            status,
+            client_ip: session
+                .client_addr()
+                .and_then(|addr| addr.as_inet())
+                .map(|addr| addr.ip()),
+            request_time: ctx.started.elapsed(),
+            upstream_addr: ctx.selected_upstream.as_deref(),
+            upstream_response_time: ctx.upstream_response_time,
+            rejected_header: ctx.rejected_header.as_ref().map(HeaderName::as_str),
+        };
+        let mut message = record.render(&self.access_log);
+        if let Some(e) = e {
+            message.push_str(&format!(" ({e})"));
+        }
+
+        log_sink::emit(LogRecord {
+            level: tracing::Level::DEBUG,
+            source: LogSource::Access,
+            message,
+            fields: vec![],
+        });
+    }
+}
+
+/// Turns the downstream request into the one sent to `upstream_ctx`: runs its filter chains,
+/// then its forwarding and header rules.
+async fn prepare_upstream_request(
+    session: &mut Session,
+    header: &mut RequestHeader,
+    ctx: &mut MotyaContext,
+    upstream_ctx: &UpstreamContext,
+) -> Result<()> {
+    for chain in &upstream_ctx.chains {
+        for filter in &chain.req_mods {
+            filter.upstream_request_filter(session, header, ctx).await?;
+        }
+    }
+    shape_upstream_request(session, header, upstream_ctx);
+
+    Ok(())
+}
+
+/// Applies the forwarding and header rules of `upstream_ctx` to a request sent to it, the
+/// steps that only depend on the configuration and the downstream request.
+fn shape_upstream_request(
+    session: &Session,
+    header: &mut RequestHeader,
+    upstream_ctx: &UpstreamContext,
+) {
+    let peer = session
+        .client_addr()
+        .and_then(|addr| addr.as_inet())
+        .map(|addr| addr.ip());
+    let tls = require_tls::is_tls(session);
+    apply_forwarded_headers(&upstream_ctx.forwarded_headers, peer, tls, header);
+    if !upstream_ctx.request_headers.is_empty() {
+        let vars = RequestVariables::from_session(session);
+        apply_request_rules(&upstream_ctx.request_headers, &vars, header);
+    }
+    apply_accept_encoding(&upstream_ctx.upstream_accept_encoding, header);
+    apply_upstream_host(&upstream_ctx.host_header, header);
+    apply_upstream_method(upstream_ctx.upstream_method.as_ref(), header);
+    if upstream_ctx.protocol == UpstreamProtocol::Grpc {
+        grpc::prepare_request(header);
+    }
+}
+
+/// Refreshes a stale cache entry in the background, from the peer the request would go to.
+///
+/// The refresh gets the forwarding and header rules of `upstream_ctx`, but not its filter
+/// chains: no client sent it, so filters counting, logging or limiting requests must not see
+/// it. It's sent with the TLS settings of the peer.
+async fn spawn_revalidation(
+    session: &mut Session,
+    router: &UpstreamRouter<UpstreamContext>,
+    upstream_ctx: &UpstreamContext,
+    revalidation: Revalidation,
+) -> Result<()> {
+    static DEFAULT: PathAndQuery = PathAndQuery::from_static("/");
+
+    let peer = router.pick_peer(
+        &mut ContextInfo {},
+        &mut SessionInfo {
+            headers: session.req_header(),
+            client_addr: session.client_addr(),
+            path: session
+                .req_header()
+                .uri
+                .path_and_query()
+                .unwrap_or(&DEFAULT),
+        },
+    );
+
+    let Ok(Some(peer)) = peer else {
+        tracing::warn!("No peer to revalidate {} with", session.req_header().uri);
+        return Ok(());
+    };
+
+    let mut request = session.req_header().clone();
+    shape_upstream_request(session, &mut request, upstream_ctx);
+
+    tokio::spawn(revalidation.run(peer, request));
+    Ok(())
+}
+
+/// Answers a request with a response from the cache.
+async fn write_cached(session: &mut Session, hit: &CachedResponse) -> Result<()> {
+    let mut header = hit.header.clone();
+    header.insert_header(http::header::AGE, hit.age().as_secs())?;
+    let is_head = session.req_header().method == http::Method::HEAD;
+
+    session
+        .downstream_session
+        .write_response_header(Box::new(header))
+        .await?;
+    if !is_head {
+        session
+            .downstream_session
+            .write_response_body(hit.body.clone(), true)
+            .await?;
+    }
+
+    Ok(())
 }
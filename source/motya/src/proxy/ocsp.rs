@@ -0,0 +1,311 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, LazyLock, Mutex},
+    time::Duration,
+};
+
+use arc_swap::ArcSwapOption;
+use async_trait::async_trait;
+use pingora::{
+    server::ShutdownWatch,
+    services::background::BackgroundService,
+    tls::{
+        error::ErrorStack,
+        hash::MessageDigest,
+        ocsp::{OcspCertId, OcspCertStatus, OcspRequest, OcspResponse, OcspResponseStatus},
+        ssl::{SslAcceptorBuilder, SslRef},
+        x509::{X509VerifyResult, X509},
+    },
+};
+use url::Url;
+
+use motya_config::common_types::listeners::{Listeners, OcspConfig};
+
+/// How often a staple is fetched again. Responders sign responses valid for days, so
+/// this renews them well before they expire.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// How soon a failed fetch is tried again.
+const RETRY_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Longest wait for a responder to answer.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Staples by certificate path, so the listeners serving the same certificate share
+/// one, and the TLS settings built by `populate_listners` find the one kept fresh by
+/// the certificate's [`OcspStapler`].
+static STAPLES: LazyLock<Mutex<HashMap<PathBuf, Arc<Staple>>>> = LazyLock::new(Default::default);
+
+/// The DER encoded OCSP response stapled to handshakes, if one could be fetched.
+#[derive(Default)]
+pub struct Staple(ArcSwapOption<Vec<u8>>);
+
+/// The staple of the certificate at `cert_path`.
+pub fn staple_for(cert_path: &Path) -> Arc<Staple> {
+    STAPLES
+        .lock()
+        .expect("staples lock poisoned")
+        .entry(cert_path.to_path_buf())
+        .or_default()
+        .clone()
+}
+
+/// Answers the clients asking for the certificate status with `staple`. Until a
+/// response has been fetched, handshakes go on without one.
+pub fn set_status_callback(builder: &mut SslAcceptorBuilder, staple: Arc<Staple>) {
+    builder
+        .set_status_callback(move |ssl| staple_to(&staple, ssl))
+        .expect("setting the OCSP status callback shouldn't fail");
+}
+
+fn staple_to(staple: &Staple, ssl: &mut SslRef) -> Result<bool, ErrorStack> {
+    match staple.0.load_full() {
+        Some(response) => {
+            ssl.set_ocsp_status(&response)?;
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+/// Keeps the staple of a certificate served with `ocsp-staple` fresh.
+#[derive(Clone)]
+pub struct OcspStapler {
+    cert_path: PathBuf,
+    responder: Option<Url>,
+    staple: Arc<Staple>,
+}
+
+/// A stapler for every certificate served with `ocsp-staple`, to run next to the
+/// service of `listeners`.
+pub fn ocsp_staplers(listeners: &Listeners) -> Vec<OcspStapler> {
+    let mut staplers: Vec<OcspStapler> = vec![];
+
+    for cfg in &listeners.list_cfgs {
+        let Some(tls) = cfg.source.tls() else {
+            continue;
+        };
+        let Some(OcspConfig { responder }) = &tls.ocsp else {
+            continue;
+        };
+        if staplers.iter().any(|s| s.cert_path == tls.cert_path) {
+            continue;
+        }
+
+        staplers.push(OcspStapler {
+            cert_path: tls.cert_path.clone(),
+            responder: responder.clone(),
+            staple: staple_for(&tls.cert_path),
+        });
+    }
+
+    staplers
+}
+
+#[async_trait]
+impl BackgroundService for OcspStapler {
+    async fn start(&self, mut shutdown: ShutdownWatch) {
+        let client = match reqwest::Client::builder().timeout(FETCH_TIMEOUT).build() {
+            Ok(client) => client,
+            Err(err) => {
+                tracing::error!(
+                    "Failed to set up OCSP stapling for {:?}: {err}",
+                    self.cert_path
+                );
+                return;
+            }
+        };
+
+        loop {
+            let wait = match self.refresh(&client).await {
+                Ok(()) => REFRESH_INTERVAL,
+                Err(err) => {
+                    self.drop_stale();
+                    tracing::warn!(
+                        "Failed to fetch the OCSP response for {:?}, serving it without a fresh staple: {err}",
+                        self.cert_path
+                    );
+                    RETRY_INTERVAL
+                }
+            };
+
+            tokio::select! {
+                _ = shutdown.changed() => return,
+                _ = tokio::time::sleep(wait) => {}
+            }
+        }
+    }
+}
+
+impl OcspStapler {
+    /// Fetches a new response and staples it once it checks out.
+    async fn refresh(&self, client: &reqwest::Client) -> Result<(), String> {
+        let (responder, request) = self.request()?;
+
+        let response = client
+            .post(responder.clone())
+            .header(reqwest::header::CONTENT_TYPE, "application/ocsp-request")
+            .body(request)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| format!("Failed to reach the OCSP responder {responder}: {e}"))?;
+        let body = response
+            .bytes()
+            .await
+            .map_err(|e| format!("Failed to read the response of {responder}: {e}"))?;
+
+        self.check(&body)?;
+        self.staple.0.store(Some(Arc::new(body.to_vec())));
+        Ok(())
+    }
+
+    /// The responder to ask and the DER encoded request for the certificate.
+    fn request(&self) -> Result<(Url, Vec<u8>), String> {
+        let chain = self.chain()?;
+
+        let responder = match &self.responder {
+            Some(responder) => responder.clone(),
+            None => chain[0]
+                .ocsp_responders()
+                .map_err(|e| e.to_string())?
+                .iter()
+                .find_map(|responder| Url::parse(responder).ok())
+                .ok_or_else(|| {
+                    format!(
+                        "{:?} names no OCSP responder, set 'ocsp-responder'",
+                        self.cert_path
+                    )
+                })?,
+        };
+
+        let mut request = OcspRequest::new().map_err(|e| e.to_string())?;
+        request
+            .add_id(self.cert_id(&chain)?)
+            .map_err(|e| e.to_string())?;
+        let request = request.to_der().map_err(|e| e.to_string())?;
+
+        Ok((responder, request))
+    }
+
+    /// Checks that `der` is a response vouching for the certificate, and still valid.
+    fn check(&self, der: &[u8]) -> Result<(), String> {
+        let chain = self.chain()?;
+        let id = self.cert_id(&chain)?;
+
+        let response = OcspResponse::from_der(der).map_err(|e| e.to_string())?;
+        if response.status() != OcspResponseStatus::SUCCESSFUL {
+            return Err(format!(
+                "the responder refused the request with status {}",
+                response.status().as_raw()
+            ));
+        }
+        let basic = response.basic().map_err(|e| e.to_string())?;
+        let status = basic
+            .find_status(&id)
+            .ok_or("the response is not about the certificate")?;
+        if status.status != OcspCertStatus::GOOD {
+            return Err(format!(
+                "the certificate status is {}, not good",
+                status.status.as_raw()
+            ));
+        }
+        status
+            .check_validity(0, None)
+            .map_err(|_| "the response has expired".to_string())
+    }
+
+    /// Stops stapling a response that expired while it couldn't be renewed.
+    fn drop_stale(&self) {
+        let stale = match self.staple.0.load_full() {
+            Some(response) => self.check(&response).is_err(),
+            None => false,
+        };
+        if stale {
+            self.staple.0.store(None);
+        }
+    }
+
+    /// The certificate followed by its chain, read again for every fetch so a renewed
+    /// certificate is picked up.
+    fn chain(&self) -> Result<Vec<X509>, String> {
+        let pem = std::fs::read(&self.cert_path)
+            .map_err(|e| format!("Failed to read {:?}: {e}", self.cert_path))?;
+        let chain = X509::stack_from_pem(&pem).map_err(|e| e.to_string())?;
+        if chain.is_empty() {
+            return Err(format!("{:?} holds no certificate", self.cert_path));
+        }
+        Ok(chain)
+    }
+
+    fn cert_id(&self, chain: &[X509]) -> Result<OcspCertId, String> {
+        let cert = &chain[0];
+        // a self-signed certificate is its own issuer
+        let issuer = match chain.get(1) {
+            Some(issuer) => issuer,
+            None if cert.issued(cert) == X509VerifyResult::OK => cert,
+            None => {
+                return Err(format!(
+                    "{:?} holds no issuer certificate, append it after the certificate",
+                    self.cert_path
+                ))
+            }
+        };
+
+        OcspCertId::from_cert(MessageDigest::sha1(), cert, issuer).map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pingora::tls::ssl::{Ssl, SslContext, SslMethod};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_unreachable_responder_leaves_no_staple() {
+        let stapler = OcspStapler {
+            cert_path: "./assets/test.crt".into(),
+            // nothing listens on port 1
+            responder: Some(Url::parse("http://127.0.0.1:1").unwrap()),
+            staple: Arc::new(Staple::default()),
+        };
+
+        let client = reqwest::Client::new();
+        let err = stapler.refresh(&client).await.unwrap_err();
+        assert!(err.contains("Failed to reach the OCSP responder"), "{err}");
+        stapler.drop_stale();
+        assert!(stapler.staple.0.load().is_none());
+
+        // the handshake goes on, without a staple
+        let context = SslContext::builder(SslMethod::tls()).unwrap().build();
+        let mut ssl = Ssl::new(&context).unwrap();
+        assert!(!staple_to(&stapler.staple, &mut ssl).unwrap());
+    }
+
+    #[test]
+    fn test_invalid_response_is_not_stapled() {
+        let stapler = OcspStapler {
+            cert_path: "./assets/test.crt".into(),
+            responder: None,
+            staple: Arc::new(Staple::default()),
+        };
+
+        assert!(stapler.check(b"not a response").is_err());
+
+        // the test certificate is self-signed and names no responder
+        let err = stapler.request().unwrap_err();
+        assert!(err.contains("names no OCSP responder"), "{err}");
+    }
+
+    #[test]
+    fn test_listeners_share_a_staple() {
+        let a = staple_for(Path::new("shared.crt"));
+        let b = staple_for(Path::new("shared.crt"));
+        let other = staple_for(Path::new("other.crt"));
+
+        assert!(Arc::ptr_eq(&a, &b));
+        assert!(!Arc::ptr_eq(&a, &other));
+    }
+}
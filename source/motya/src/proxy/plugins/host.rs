@@ -1,11 +1,32 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use tracing::Level;
 use wasmtime::component::{Linker, LinkerInstance};
 use wasmtime_wasi::WasiView;
 use wasmtime_wasi_io::IoView;
 
-use crate::proxy::plugins::{module::TraitModuleState, store::ModuleState};
+use crate::proxy::{
+    log_sink::{self, LogRecord, LogSource},
+    plugins::{module::TraitModuleState, store::ModuleState},
+};
 
 pub trait HostFunctions {
     fn get_path(&self) -> String;
+
+    /// Address of the backend picked for the request, empty until one is picked.
+    fn selected_upstream(&self) -> String;
+
+    /// The buffered request body, `None` unless the filter has access to it.
+    fn read_body(&self) -> Option<Vec<u8>>;
+
+    /// Replaces the request body, which must fit the filter's `max-body-size`.
+    fn set_body(&mut self, body: Vec<u8>) -> Result<(), String>;
+
+    /// Wall-clock milliseconds since the UNIX epoch.
+    ///
+    /// Implementations are expected to sample the clock once per request and
+    /// hand out the cached value on subsequent calls.
+    fn now_millis(&mut self) -> u64;
 }
 
 pub struct PluginHost;
@@ -18,6 +39,8 @@ impl PluginHost {
 
         Self::register_logger(linker.root().instance("motya:proxy/logger")?)?;
         Self::register_context(linker.root().instance("motya:proxy/context")?)?;
+        Self::register_request(linker.root().instance("motya:proxy/request")?)?;
+        Self::register_clock(linker.root().instance("motya:proxy/clock")?)?;
 
         Ok(())
     }
@@ -32,23 +55,58 @@ impl PluginHost {
         Ok(())
     }
 
-    fn register_logger<T: WasiView + IoView>(
-        mut logger: LinkerInstance<'_, T>,
+    fn register_request<T: TraitModuleState>(
+        mut request: LinkerInstance<'_, T>,
     ) -> wasmtime::Result<()> {
-        logger.func_wrap("info", |_, (message,): (String,)| {
-            tracing::info!("WASM LOG: {}", message);
-            Ok(())
+        request.func_wrap(
+            "selected-upstream",
+            |ctx, (): ()| -> wasmtime::Result<(String,)> { Ok((ctx.data().selected_upstream(),)) },
+        )?;
+        request.func_wrap("read-body", |ctx, (): ()| -> wasmtime::Result<(Vec<u8>,)> {
+            ctx.data()
+                .read_body()
+                .map(|body| (body,))
+                .ok_or_else(|| wasmtime::Error::msg("the filter has no access to the request body"))
         })?;
+        request.func_wrap(
+            "set-body",
+            |mut ctx, (body,): (Vec<u8>,)| -> wasmtime::Result<()> {
+                ctx.data_mut().set_body(body).map_err(wasmtime::Error::msg)
+            },
+        )?;
 
-        logger.func_wrap("error", |_, (message,): (String,)| {
-            tracing::error!("WASM LOG: {}", message);
-            Ok(())
-        })?;
+        Ok(())
+    }
 
-        logger.func_wrap("debug", |_, (message,): (String,)| {
-            tracing::debug!("WASM LOG: {}", message);
-            Ok(())
-        })?;
+    fn register_clock<T: TraitModuleState>(
+        mut clock: LinkerInstance<'_, T>,
+    ) -> wasmtime::Result<()> {
+        clock.func_wrap(
+            "now-millis",
+            |mut ctx, (): ()| -> wasmtime::Result<(u64,)> { Ok((ctx.data_mut().now_millis(),)) },
+        )?;
+
+        Ok(())
+    }
+
+    fn register_logger<T: WasiView + IoView>(
+        mut logger: LinkerInstance<'_, T>,
+    ) -> wasmtime::Result<()> {
+        for (name, level) in [
+            ("info", Level::INFO),
+            ("error", Level::ERROR),
+            ("debug", Level::DEBUG),
+        ] {
+            logger.func_wrap(name, move |_, (message,): (String,)| {
+                log_sink::emit(LogRecord {
+                    level,
+                    source: LogSource::Plugin,
+                    message,
+                    fields: vec![],
+                });
+                Ok(())
+            })?;
+        }
 
         Ok(())
     }
@@ -63,4 +121,38 @@ impl HostFunctions for ModuleState {
             panic!("invariant violated: session was null on filter phase");
         }
     }
+
+    fn selected_upstream(&self) -> String {
+        self.selected_upstream.clone().unwrap_or_default()
+    }
+
+    fn read_body(&self) -> Option<Vec<u8>> {
+        self.request_body.clone()
+    }
+
+    fn set_body(&mut self, body: Vec<u8>) -> Result<(), String> {
+        let Some(current) = &mut self.request_body else {
+            return Err("the filter has no access to the request body".to_string());
+        };
+        if body.len() > self.max_body_size {
+            return Err(format!(
+                "body of {} bytes is over the filter's max-body-size of {}",
+                body.len(),
+                self.max_body_size
+            ));
+        }
+        *current = body;
+        Ok(())
+    }
+
+    fn now_millis(&mut self) -> u64 {
+        *self.now_millis.get_or_insert_with(unix_millis)
+    }
+}
+
+pub fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
 }
@@ -1,16 +1,19 @@
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, ptr::NonNull};
 
 use async_trait::async_trait;
+use bytes::BytesMut;
 use miette::miette;
 use pingora_http::{RequestHeader, ResponseHeader};
 use pingora_proxy::Session;
 use wasmtime::{
     component::{Linker, ResourceAny},
-    Store,
+    Store, StoreLimits, StoreLimitsBuilder,
 };
 use wasmtime_wasi::WasiView;
 use wasmtime_wasi_io::IoView;
 
+use motya_config::common_types::definitions::FilterErrorPolicy;
+
 use crate::proxy::{
     filters::types::{RequestFilterMod, RequestModifyMod, ResponseModifyMod},
     plugins::{
@@ -21,9 +24,20 @@ use crate::proxy::{
     MotyaContext,
 };
 
-pub trait TraitModuleState: WasiView + IoView + HostFunctions + Default + 'static {}
+pub trait TraitModuleState:
+    WasiView + IoView + HostFunctions + ModuleLimits + Default + 'static
+{
+}
 
-impl<T> TraitModuleState for T where T: WasiView + IoView + HostFunctions + Default + 'static {}
+impl<T> TraitModuleState for T where
+    T: WasiView + IoView + HostFunctions + ModuleLimits + Default + 'static
+{
+}
+
+/// Resource limits wasmtime enforces on the instance owning the state.
+pub trait ModuleLimits {
+    fn limits(&mut self) -> &mut StoreLimits;
+}
 
 pub struct WasmModule<T: 'static = ModuleState> {
     artifact: WasmArtifact,
@@ -51,6 +65,7 @@ impl<T: TraitModuleState> WasmModule<T> {
         state: T,
     ) -> miette::Result<Option<WasmFilterState<T>>> {
         let mut store = Store::new(&self.artifact.engine, state);
+        store.limiter(|state| state.limits());
 
         let instance = g::App::instantiate(&mut store, &self.artifact.component, &self.linker)
             .map_err(|err| miette!("{err}"))?;
@@ -95,6 +110,12 @@ pub struct WasmInvoker<T: 'static = ModuleState> {
     pub module: WasmModule<T>,
     pub filter_name: String,
     pub config: BTreeMap<String, String>,
+    /// Applied when the module traps or the filter returns an error.
+    pub on_error: FilterErrorPolicy,
+    /// Cap on the linear memory of each instance, in bytes.
+    pub max_memory: Option<usize>,
+    /// `request-body`: the filter gets the whole request body, up to this many bytes.
+    pub max_body_size: Option<usize>,
 }
 
 impl<T> Clone for WasmInvoker<T> {
@@ -103,6 +124,9 @@ impl<T> Clone for WasmInvoker<T> {
             module: self.module.clone(),
             filter_name: self.filter_name.clone(),
             config: self.config.clone(),
+            on_error: self.on_error,
+            max_memory: self.max_memory,
+            max_body_size: self.max_body_size,
         }
     }
 }
@@ -117,9 +141,27 @@ impl<T: TraitModuleState> WasmInvoker<T> {
             config,
             filter_name,
             module,
+            on_error: FilterErrorPolicy::default(),
+            max_memory: None,
+            max_body_size: None,
         }
     }
 
+    pub fn with_on_error(mut self, on_error: FilterErrorPolicy) -> Self {
+        self.on_error = on_error;
+        self
+    }
+
+    pub fn with_max_memory(mut self, max_memory: Option<usize>) -> Self {
+        self.max_memory = max_memory;
+        self
+    }
+
+    pub fn with_max_body_size(mut self, max_body_size: Option<usize>) -> Self {
+        self.max_body_size = max_body_size;
+        self
+    }
+
     pub fn get_filter_type(&self) -> miette::Result<FilterType> {
         let state = T::default();
 
@@ -132,7 +174,8 @@ impl<T: TraitModuleState> WasmInvoker<T> {
         Ok(filter_state.self_type)
     }
 
-    fn execute<F, R>(&self, state: T, func: F) -> pingora::Result<R>
+    /// Runs `func` on a fresh instance, handing back the state it left behind.
+    fn execute<F, R>(&self, mut state: T, func: F) -> pingora::Result<(R, T)>
     where
         F: FnOnce(
             &GuestFilterInstance,
@@ -140,6 +183,14 @@ impl<T: TraitModuleState> WasmInvoker<T> {
             ResourceAny,
         ) -> wasmtime::Result<std::result::Result<R, String>>,
     {
+        if let Some(max_memory) = self.max_memory {
+            // growing past the cap traps the instance instead of failing `memory.grow`
+            *state.limits() = StoreLimitsBuilder::new()
+                .memory_size(max_memory)
+                .trap_on_grow_failure(true)
+                .build();
+        }
+
         let mut filter_state = self
             .module
             .pick(&self.filter_name, &self.config, state)
@@ -152,21 +203,39 @@ impl<T: TraitModuleState> WasmInvoker<T> {
         let wasm_result = func(&filter, &mut filter_state.store, filter_state.resource)
             .map_err(|e| Self::make_err("Wasm runtime trap/error", e))?;
 
-        wasm_result.map_err(|e| Self::make_err("Filter execution error", e))
+        let result = wasm_result.map_err(|e| Self::make_err("Filter execution error", e))?;
+
+        Ok((result, filter_state.store.into_data()))
     }
 
-    fn on_request(&self, state: T) -> pingora::Result<()> {
+    fn on_request(&self, state: T) -> pingora::Result<T> {
         self.execute(state, |f, s, r| f.call_on_request(s, r))
+            .map(|((), state)| state)
     }
 
-    #[allow(unused)]
-    fn filter(&self, state: T) -> pingora::Result<bool> {
+    fn filter(&self, state: T) -> pingora::Result<(bool, T)> {
         self.execute(state, |f, s, r| f.call_filter(s, r))
     }
 
     #[allow(unused)]
     fn on_response(&self, state: T) -> pingora::Result<()> {
         self.execute(state, |f, s, r| f.call_on_response(s, r))
+            .map(|((), _)| ())
+    }
+
+    /// Applies the `on-filter-error` policy to a call that trapped or returned an error.
+    fn recover(&self, err: pingora::BError) -> pingora::Result<()> {
+        tracing::error!("WASM filter '{}' failed: {err}", self.filter_name);
+
+        match self.on_error {
+            FilterErrorPolicy::Allow => Ok(()),
+            FilterErrorPolicy::Block => {
+                Err(pingora::Error::new(pingora::ErrorType::HTTPStatus(403)))
+            }
+            FilterErrorPolicy::InternalError => {
+                Err(pingora::Error::new(pingora::ErrorType::HTTPStatus(500)))
+            }
+        }
     }
 
     fn make_err(msg: &'static str, context: impl std::fmt::Display) -> pingora::BError {
@@ -174,25 +243,94 @@ impl<T: TraitModuleState> WasmInvoker<T> {
     }
 }
 
+impl WasmInvoker {
+    /// The request body for a filter with `request-body`, read whole by the first such
+    /// filter of the request and then passed along the chain, rewrites included.
+    async fn request_body(
+        &self,
+        session: &mut Session,
+        ctx: &mut MotyaContext,
+    ) -> pingora::Result<Option<Vec<u8>>> {
+        let Some(max_body_size) = self.max_body_size else {
+            return Ok(None);
+        };
+        let too_large = || pingora::Error::new(pingora::ErrorType::HTTPStatus(413));
+
+        if let Some(body) = &ctx.wasm_request_body {
+            if body.len() > max_body_size {
+                return Err(too_large());
+            }
+            return Ok(Some(body.to_vec()));
+        }
+
+        // pingora replays the retained body towards the upstream, which is where the
+        // proxy swaps in the filtered one
+        session.enable_retry_buffering();
+        let mut body = BytesMut::new();
+        while let Some(chunk) = session.read_request_body().await? {
+            if body.len() + chunk.len() > max_body_size {
+                return Err(too_large());
+            }
+            body.extend_from_slice(&chunk);
+        }
+
+        let body = body.freeze();
+        ctx.wasm_request_body = Some(body.clone());
+        Ok(Some(body.to_vec()))
+    }
+
+    /// State of an `on-request` call, made once the backend is picked, so that
+    /// `selected-upstream` knows it.
+    fn upstream_state(&self, request_body: Option<Vec<u8>>, ctx: &mut MotyaContext) -> ModuleState {
+        ModuleState {
+            now_millis: Some(ctx.wasm_now_millis()),
+            selected_upstream: ctx.selected_upstream.clone(),
+            request_body,
+            max_body_size: self.max_body_size.unwrap_or_default(),
+            ..Default::default()
+        }
+    }
+
+    /// Keeps the body the filter left behind for the upstream and the next filters.
+    fn keep_request_body(state: ModuleState, ctx: &mut MotyaContext) {
+        if let Some(body) = state.request_body {
+            ctx.wasm_request_body = Some(body.into());
+        }
+    }
+}
+
 #[async_trait]
 impl RequestFilterMod for WasmInvoker {
     async fn request_filter(
         &self,
         session: &mut Session,
-        _: &mut MotyaContext,
+        ctx: &mut MotyaContext,
     ) -> pingora::Result<bool> {
+        let request_body = self.request_body(session, ctx).await?;
+
+        let req_header = NonNull::from(session.req_header());
         let session_state = SessionCtx {
-            req_header: None,
+            req_header: Some(req_header),
             _res_headers: None,
             _session: session.into(),
         };
 
-        let _state = ModuleState {
+        let state = ModuleState {
             session: Some(session_state),
+            now_millis: Some(ctx.wasm_now_millis()),
+            request_body,
+            max_body_size: self.max_body_size.unwrap_or_default(),
             ..Default::default()
         };
 
-        Ok(false)
+        match self.filter(state) {
+            Ok((false, state)) => {
+                Self::keep_request_body(state, ctx);
+                Ok(false)
+            }
+            Ok((true, _)) => Err(pingora::Error::new(pingora::ErrorType::HTTPStatus(403))),
+            Err(err) => self.recover(err).map(|()| false),
+        }
     }
 }
 
@@ -202,7 +340,7 @@ impl ResponseModifyMod for WasmInvoker {
         &self,
         session: &mut Session,
         header: &mut ResponseHeader,
-        _: &mut MotyaContext,
+        ctx: &mut MotyaContext,
     ) {
         let session_state = SessionCtx {
             req_header: None,
@@ -212,6 +350,8 @@ impl ResponseModifyMod for WasmInvoker {
 
         let _state = ModuleState {
             session: Some(session_state),
+            now_millis: Some(ctx.wasm_now_millis()),
+            selected_upstream: ctx.selected_upstream.clone(),
             ..Default::default()
         };
     }
@@ -223,8 +363,10 @@ impl RequestModifyMod for WasmInvoker {
         &self,
         session: &mut Session,
         header: &mut RequestHeader,
-        _: &mut MotyaContext,
+        ctx: &mut MotyaContext,
     ) -> pingora::Result<()> {
+        let request_body = self.request_body(session, ctx).await?;
+
         let session_state = SessionCtx {
             req_header: Some(header.into()),
             _res_headers: None,
@@ -233,10 +375,16 @@ impl RequestModifyMod for WasmInvoker {
 
         let state = ModuleState {
             session: Some(session_state),
-            ..Default::default()
+            ..self.upstream_state(request_body, ctx)
         };
 
-        self.on_request(state)
+        match self.on_request(state) {
+            Ok(state) => {
+                Self::keep_request_body(state, ctx);
+                Ok(())
+            }
+            Err(err) => self.recover(err),
+        }
     }
 }
 
@@ -253,19 +401,21 @@ impl From<g::exports::motya::proxy::filter_factory::FilterType> for FilterType {
 #[cfg(test)]
 mod tests {
 
-    use std::str::FromStr;
+    use std::{str::FromStr, sync::Arc};
 
+    use bytes::Bytes;
     use fqdn::FQDN;
     use wasmtime::Engine;
     use wasmtime_wasi::{ResourceTable, WasiCtx, WasiCtxView};
 
-    use crate::proxy::plugins::store::WasmPluginStore;
+    use crate::proxy::{plugins::store::WasmPluginStore, upstream_router::UpstreamRouter};
     use motya_config::common_types::definitions::PluginSource;
 
     #[derive(Default)]
     pub struct MockState {
         pub ctx: WasiCtx,
         pub table: ResourceTable,
+        pub limits: StoreLimits,
     }
 
     impl WasiView for MockState {
@@ -283,10 +433,32 @@ mod tests {
         }
     }
 
+    impl ModuleLimits for MockState {
+        fn limits(&mut self) -> &mut StoreLimits {
+            &mut self.limits
+        }
+    }
+
     impl HostFunctions for MockState {
         fn get_path(&self) -> String {
             "/hubabuba".to_string()
         }
+
+        fn selected_upstream(&self) -> String {
+            String::new()
+        }
+
+        fn read_body(&self) -> Option<Vec<u8>> {
+            None
+        }
+
+        fn set_body(&mut self, _body: Vec<u8>) -> Result<(), String> {
+            Err("no body".to_string())
+        }
+
+        fn now_millis(&mut self) -> u64 {
+            0
+        }
     }
 
     use super::*;
@@ -312,7 +484,7 @@ mod tests {
 
             let invoker = WasmInvoker::new(module, filter_name.clone(), config);
 
-            assert!(invoker.filter(state).unwrap());
+            assert!(invoker.filter(state).unwrap().0);
         }
 
         {
@@ -324,7 +496,7 @@ mod tests {
 
             let invoker = WasmInvoker::new(module, filter_name.clone(), config);
 
-            assert!(!invoker.filter(state).unwrap());
+            assert!(!invoker.filter(state).unwrap().0);
         }
 
         let filter_name = "response_logger".to_string();
@@ -341,4 +513,183 @@ mod tests {
             invoker.on_response(state).unwrap();
         }
     }
+
+    #[tokio::test]
+    async fn test_on_request_sees_selected_upstream() {
+        let artifact = WasmPluginStore::create_artifact(
+            FQDN::from_str("example").unwrap(),
+            &PluginSource::File("./assets/request_filter.wasm".into()),
+            &Engine::default(),
+        )
+        .await
+        .unwrap();
+        let module = WasmPluginStore::create_module::<ModuleState>(&artifact).unwrap();
+        let invoker = WasmInvoker::new(module, "my_filter".to_string(), BTreeMap::new());
+
+        let mut ctx = MotyaContext::new(Arc::new(UpstreamRouter::build(vec![]).unwrap()));
+        ctx.selected_upstream = Some("127.0.0.1:8080".to_string());
+
+        let state = invoker.upstream_state(None, &mut ctx);
+        assert_eq!(state.selected_upstream(), "127.0.0.1:8080");
+    }
+
+    #[tokio::test]
+    async fn test_rewritten_body_sent_upstream() {
+        let artifact = WasmPluginStore::create_artifact(
+            FQDN::from_str("example").unwrap(),
+            &PluginSource::File("./assets/request_filter.wasm".into()),
+            &Engine::default(),
+        )
+        .await
+        .unwrap();
+        let module = WasmPluginStore::create_module::<ModuleState>(&artifact).unwrap();
+        let invoker = WasmInvoker::new(module, "my_filter".to_string(), BTreeMap::new())
+            .with_max_body_size(Some(64));
+
+        let downstream = br#"{"name": "motya"}"#;
+        let mut ctx = MotyaContext::new(Arc::new(UpstreamRouter::build(vec![]).unwrap()));
+        let mut state = invoker.upstream_state(Some(downstream.to_vec()), &mut ctx);
+
+        // the calls examples/upper-json makes
+        assert_eq!(state.read_body().unwrap(), downstream);
+        state.set_body(br#"{"name": "MOTYA"}"#.to_vec()).unwrap();
+        assert!(state.set_body(vec![b' '; 65]).is_err());
+        WasmInvoker::keep_request_body(state, &mut ctx);
+
+        let mut header = RequestHeader::build("POST", b"/", None).unwrap();
+        header
+            .insert_header(http::header::TRANSFER_ENCODING, "chunked")
+            .unwrap();
+        ctx.set_wasm_body_length(&mut header).unwrap();
+        assert!(header
+            .headers
+            .get(http::header::TRANSFER_ENCODING)
+            .is_none());
+        assert_eq!(header.headers[http::header::CONTENT_LENGTH], "17");
+
+        // pingora replays the downstream body, which the filter's replaces
+        let mut body = Some(Bytes::from_static(downstream));
+        assert!(ctx.replay_wasm_body(&mut body, true));
+        assert_eq!(body.as_deref(), Some(&br#"{"name": "MOTYA"}"#[..]));
+    }
+
+    #[tokio::test]
+    async fn test_on_filter_error_policy() {
+        let artifact = WasmPluginStore::create_artifact(
+            FQDN::from_str("example").unwrap(),
+            &PluginSource::File("./assets/request_filter.wasm".into()),
+            &Engine::default(),
+        )
+        .await
+        .unwrap();
+        let module = WasmPluginStore::create_module::<MockState>(&artifact).unwrap();
+
+        let status_for = |on_error| {
+            // the module has no such filter, so every call fails
+            let invoker = WasmInvoker::new(module.clone(), "missing".to_string(), BTreeMap::new())
+                .with_on_error(on_error);
+
+            let err = invoker.filter(MockState::default()).unwrap_err();
+            match invoker.recover(err) {
+                Ok(()) => None,
+                Err(err) => match err.etype() {
+                    pingora::ErrorType::HTTPStatus(code) => Some(*code),
+                    other => panic!("unexpected error type {other:?}"),
+                },
+            }
+        };
+
+        assert_eq!(status_for(FilterErrorPolicy::Block), Some(403));
+        assert_eq!(status_for(FilterErrorPolicy::Allow), None);
+        assert_eq!(status_for(FilterErrorPolicy::InternalError), Some(500));
+    }
+
+    #[tokio::test]
+    async fn test_max_memory_contained() {
+        let artifact = WasmPluginStore::create_artifact(
+            FQDN::from_str("example").unwrap(),
+            &PluginSource::File("./assets/request_filter.wasm".into()),
+            &Engine::default(),
+        )
+        .await
+        .unwrap();
+        let module = WasmPluginStore::create_module::<MockState>(&artifact).unwrap();
+
+        let config = BTreeMap::from([("forbidden".to_string(), "hubabuba".to_string())]);
+        let invoker = WasmInvoker::new(module, "my_filter".to_string(), config);
+
+        assert!(invoker.filter(MockState::default()).unwrap().0);
+
+        // a single page is less than the module needs, so the instance fails
+        // while the host carries on and applies the error policy
+        let limited = invoker.clone().with_max_memory(Some(64 << 10));
+        let err = limited.filter(MockState::default()).unwrap_err();
+        assert!(limited.recover(err).is_err());
+
+        assert!(invoker.filter(MockState::default()).unwrap().0);
+    }
+
+    #[test]
+    fn test_selected_upstream() {
+        assert_eq!(ModuleState::default().selected_upstream(), "");
+
+        let state = ModuleState {
+            selected_upstream: Some("127.0.0.1:8080".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(state.selected_upstream(), "127.0.0.1:8080");
+    }
+
+    #[test]
+    fn test_request_body_access() {
+        let mut no_access = ModuleState::default();
+        assert_eq!(no_access.read_body(), None);
+        assert!(no_access.set_body(b"{}".to_vec()).is_err());
+
+        let mut state = ModuleState {
+            request_body: Some(br#"{"name":"motya"}"#.to_vec()),
+            max_body_size: 20,
+            ..Default::default()
+        };
+        let upper = state.read_body().unwrap().to_ascii_uppercase();
+        state.set_body(upper).unwrap();
+        assert_eq!(state.read_body().unwrap(), br#"{"NAME":"MOTYA"}"#);
+
+        // a body over the cap is refused and the last one kept
+        let err = state.set_body(vec![b' '; 21]).unwrap_err();
+        assert!(err.contains("over the filter's max-body-size of 20"));
+        assert_eq!(state.request_body.unwrap(), br#"{"NAME":"MOTYA"}"#);
+    }
+
+    #[test]
+    fn test_now_millis_cached_per_state() {
+        let mut state = ModuleState::default();
+
+        let first = state.now_millis();
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        let second = state.now_millis();
+
+        assert_eq!(first, second);
+        assert!(first > 0);
+
+        let mut next_request = ModuleState::default();
+        assert!(next_request.now_millis() >= first);
+    }
+
+    #[test]
+    fn test_now_millis_shared_per_request() {
+        let mut ctx = MotyaContext::new(Arc::new(UpstreamRouter::build(vec![]).unwrap()));
+        let first = ctx.wasm_now_millis();
+        std::thread::sleep(std::time::Duration::from_millis(5));
+
+        // every filter of the request gets a store of its own, and the same instant
+        let mut state = ModuleState {
+            now_millis: Some(ctx.wasm_now_millis()),
+            ..Default::default()
+        };
+        assert_eq!(state.now_millis(), first);
+
+        let mut next_request = MotyaContext::new(Arc::new(UpstreamRouter::build(vec![]).unwrap()));
+        assert!(next_request.wasm_now_millis() >= first);
+    }
 }
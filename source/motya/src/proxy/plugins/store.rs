@@ -6,7 +6,7 @@ use pingora_proxy::Session;
 use std::{collections::HashMap, ptr::NonNull, sync::Arc};
 use wasmtime::{
     component::{Component, Linker},
-    Engine,
+    Engine, StoreLimits,
 };
 use wasmtime_wasi::{ResourceTable, WasiCtx, WasiCtxView, WasiView};
 use wasmtime_wasi_io::IoView;
@@ -15,7 +15,7 @@ use crate::proxy::{
     filters::registry::{FilterRegistry, RegistryFilterContainer},
     plugins::{
         host::PluginHost,
-        module::{TraitModuleState, WasmModule},
+        module::{ModuleLimits, TraitModuleState, WasmModule},
     },
 };
 use motya_config::common_types::{definitions::PluginSource, definitions_table::DefinitionsTable};
@@ -134,6 +134,16 @@ pub struct ModuleState {
     pub ctx: WasiCtx,
     pub table: ResourceTable,
     pub session: Option<SessionCtx>,
+    /// Clock sample shared by every `clock::now-millis` call within this request, taken
+    /// from the request context so that all filters of the request agree on it.
+    pub now_millis: Option<u64>,
+    /// Backend the request is proxied to, once the balancer has picked one.
+    pub selected_upstream: Option<String>,
+    /// The request body as the filter sees it, for filters with `request-body`.
+    pub request_body: Option<Vec<u8>>,
+    /// Largest body the filter may set.
+    pub max_body_size: usize,
+    pub limits: StoreLimits,
 }
 
 unsafe impl Send for ModuleState {}
@@ -154,6 +164,12 @@ impl WasiView for ModuleState {
     }
 }
 
+impl ModuleLimits for ModuleState {
+    fn limits(&mut self) -> &mut StoreLimits {
+        &mut self.limits
+    }
+}
+
 impl IoView for ModuleState {
     fn table(&mut self) -> &mut ResourceTable {
         &mut self.table
@@ -180,6 +196,7 @@ mod tests {
             PluginDefinition {
                 name: FQDN::from_str(plugin_name).unwrap(),
                 source,
+                on_filter_error: None,
             },
         );
 
@@ -296,6 +313,7 @@ mod tests {
             PluginDefinition {
                 name: FQDN::from_str("remote").unwrap(),
                 source: PluginSource::Url(url),
+                on_filter_error: None,
             },
         );
 
@@ -304,6 +322,7 @@ mod tests {
             PluginDefinition {
                 name: FQDN::from_str("local").unwrap(),
                 source: PluginSource::File(file_path),
+                on_filter_error: None,
             },
         );
 
@@ -1,12 +1,87 @@
-use pingora::listeners::tls::TlsSettings;
+use std::{
+    ffi::{c_int, c_long},
+    io,
+    net::{SocketAddr, TcpListener},
+};
 
-use motya_config::common_types::listeners::{ListenerKind, Listeners};
+use miette::{miette, IntoDiagnostic};
+use pingora::{
+    listeners::{tls::TlsSettings, TcpSocketOptions},
+    protocols::ALPN,
+    tls::ssl::{
+        select_next_proto, AlpnError, SslAcceptor, SslAcceptorBuilder, SslFiletype, SslMethod,
+        SslOptions,
+    },
+};
+
+use motya_config::common_types::listeners::{
+    ListenerConfig, ListenerKind, Listeners, TicketKey, TlsConfig,
+};
+
+use crate::proxy::ocsp;
+
+/// Ports below this one need elevated privileges to bind on most unix systems.
+const FIRST_UNPRIVILEGED_PORT: u16 = 1024;
+
+/// `SSL_CTRL_SET_TLSEXT_TICKET_KEYS`, which the openssl crate has no wrapper for.
+const SSL_CTRL_SET_TLSEXT_TICKET_KEYS: c_int = 59;
+
+/// ALPN protocol lists in wire format, in order of preference.
+const ALPN_H2: &[u8] = b"\x02h2";
+const ALPN_H2_H1: &[u8] = b"\x02h2\x08http/1.1";
+
+/// Tries binding every privileged TCP port up front, `auto-tls` listeners included, so that
+/// missing permissions are reported with guidance at startup instead of as a bare
+/// `Permission denied` once the server runs.
+///
+/// Any other bind failure, such as a port already taken by the process being upgraded, is left
+/// for the listener itself to report.
+pub fn check_privileged_ports(listeners: &Listeners) -> miette::Result<()> {
+    for list_cfg in listeners.list_cfgs.iter() {
+        let ListenerKind::Tcp { addr, .. } = &list_cfg.source else {
+            continue;
+        };
+        let Ok(addr) = addr.parse::<SocketAddr>() else {
+            continue;
+        };
+        if addr.port() == 0 || addr.port() >= FIRST_UNPRIVILEGED_PORT {
+            continue;
+        }
+
+        if let Err(err) = TcpListener::bind(addr) {
+            if let Some(report) = privileged_bind_error(addr, &err) {
+                return Err(report);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Explains a failure to bind a privileged port, keeping the original error in the message.
+pub fn privileged_bind_error(addr: SocketAddr, err: &io::Error) -> Option<miette::Report> {
+    if addr.port() >= FIRST_UNPRIVILEGED_PORT || err.kind() != io::ErrorKind::PermissionDenied {
+        return None;
+    }
+
+    Some(miette!(
+        help = format!(
+            "ports below {FIRST_UNPRIVILEGED_PORT} need elevated privileges: run motya as root, \
+             grant the binary the capability with `setcap 'cap_net_bind_service=+ep' <path>`, \
+             or listen on a higher port behind a port forward"
+        ),
+        "Failed to bind listener {addr}: {err}"
+    ))
+}
 
 pub fn populate_listners<T>(
     listeners: &Listeners,
     service: &mut pingora::services::listening::Service<T>,
 ) {
     for list_cfg in listeners.list_cfgs.iter() {
+        if list_cfg.auto_tls {
+            // bound by the service's `AutoTlsListener` instead
+            continue;
+        }
         // NOTE: See https://github.com/cloudflare/pingora/issues/182 for tracking "paths aren't
         // always UTF-8 strings".
         //
@@ -27,11 +102,14 @@ pub fn populate_listners<T>(
                 // TODO: Make conditional!
                 let mut settings = TlsSettings::intermediate(cert_path, key_path)
                     .expect("adding TLS listener shouldn't fail");
-                if *offer_h2 {
+                if tls_cfg.h2_only {
+                    settings.set_alpn(ALPN::H2);
+                } else if *offer_h2 {
                     settings.enable_h2();
                 }
+                configure_tls(&mut settings, tls_cfg);
 
-                service.add_tls_with_settings(addr, None, settings);
+                service.add_tls_with_settings(addr, socket_options(list_cfg), settings);
             }
             ListenerKind::Tcp {
                 addr,
@@ -41,12 +119,123 @@ pub fn populate_listners<T>(
                 if *offer_h2 {
                     panic!("Unsupported configuration: {addr:?} configured without TLS, but H2 enabled which requires TLS");
                 }
-                service.add_tcp(addr);
+                match socket_options(list_cfg) {
+                    Some(options) => service.add_tcp_with_settings(addr, options),
+                    None => service.add_tcp(addr),
+                }
             }
             ListenerKind::Uds(path) => {
                 let path = path.to_str().unwrap();
                 service.add_uds(path, None); // todo
             }
+            ListenerKind::InheritedFd { .. } => {
+                // accepted on by the service's `InheritedFdListener` instead
+            }
         }
     }
 }
+
+/// Builds the TLS settings of a listener accepting its own connections, the same as
+/// those `populate_listners` hands to pingora.
+pub fn tls_acceptor(tls_cfg: &TlsConfig, offer_h2: bool) -> miette::Result<SslAcceptor> {
+    let mut builder = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls()).into_diagnostic()?;
+    builder
+        .set_private_key_file(&tls_cfg.key_path, SslFiletype::PEM)
+        .and_then(|()| builder.set_certificate_chain_file(&tls_cfg.cert_path))
+        .and_then(|()| builder.check_private_key())
+        .map_err(|err| {
+            miette!(
+                "Failed to load the certificate {:?} and key {:?}: {err}",
+                tls_cfg.cert_path,
+                tls_cfg.key_path
+            )
+        })?;
+
+    if tls_cfg.h2_only {
+        builder.set_alpn_select_callback(|_, client| {
+            select_next_proto(ALPN_H2, client).ok_or(AlpnError::ALERT_FATAL)
+        });
+    } else if offer_h2 {
+        builder.set_alpn_select_callback(|_, client| {
+            select_next_proto(ALPN_H2_H1, client).ok_or(AlpnError::NOACK)
+        });
+    }
+    configure_tls(&mut builder, tls_cfg);
+
+    Ok(builder.build())
+}
+
+/// The settings of `tls_cfg` besides the certificate and ALPN, which pingora's
+/// [`TlsSettings`] set up on their own.
+fn configure_tls(builder: &mut SslAcceptorBuilder, tls_cfg: &TlsConfig) {
+    if !tls_cfg.session_tickets {
+        builder.set_options(SslOptions::NO_TICKET);
+    }
+    if let Some(key) = &tls_cfg.ticket_key {
+        set_ticket_key(builder, key);
+    }
+    if tls_cfg.ocsp.is_some() {
+        ocsp::set_status_callback(builder, ocsp::staple_for(&tls_cfg.cert_path));
+    }
+}
+
+/// Encrypts the session tickets of a listener with its `ticket-key-file`, so a ticket issued
+/// by one instance sharing the file is accepted by the others.
+fn set_ticket_key(builder: &mut SslAcceptorBuilder, key: &TicketKey) {
+    let mut material = key.material;
+
+    // SAFETY: the context is alive for the call, and OpenSSL copies the key out of
+    // `material` after checking its length.
+    let set = unsafe {
+        openssl_sys::SSL_CTX_ctrl(
+            builder.as_ptr(),
+            SSL_CTRL_SET_TLSEXT_TICKET_KEYS,
+            material.len() as c_long,
+            material.as_mut_ptr().cast(),
+        )
+    };
+    assert_eq!(
+        set, 1,
+        "setting the ticket key from {:?} shouldn't fail",
+        key.path
+    );
+}
+
+/// Options applied to the listening socket itself, `None` when there are none to set.
+///
+/// `tcp-nodelay` is not one of them, it's set on each accepted connection instead.
+fn socket_options(list_cfg: &ListenerConfig) -> Option<TcpSocketOptions> {
+    let backlog = list_cfg.tcp_fastopen?;
+
+    if cfg!(not(target_os = "linux")) {
+        tracing::warn!("'tcp-fastopen' is only supported on Linux, ignoring it");
+        return None;
+    }
+
+    Some(TcpSocketOptions {
+        tcp_fastopen: Some(backlog),
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_privileged_bind_error() {
+        let denied = io::Error::from(io::ErrorKind::PermissionDenied);
+
+        let report = privileged_bind_error("0.0.0.0:443".parse().unwrap(), &denied)
+            .expect("a denied privileged port is explained");
+        assert!(report.to_string().contains("0.0.0.0:443"));
+        assert!(report.to_string().contains(&denied.to_string()));
+        let help = report.help().unwrap().to_string();
+        assert!(help.contains("cap_net_bind_service"));
+
+        // unprivileged ports and other failures are left alone
+        assert!(privileged_bind_error("0.0.0.0:8080".parse().unwrap(), &denied).is_none());
+        let in_use = io::Error::from(io::ErrorKind::AddrInUse);
+        assert!(privileged_bind_error("0.0.0.0:80".parse().unwrap(), &in_use).is_none());
+    }
+}
@@ -0,0 +1,380 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use motya_config::common_types::rate_limit::{RateLimitBackend, RateLimitConfig};
+
+use crate::proxy::{
+    balancer::key_selector::{KeySelector, KeySourceContext},
+    redis_rate_limit::{BucketLimits, RedisBuckets},
+};
+
+type Buckets = HashMap<Option<u64>, Bucket>;
+
+/// Token buckets of a `rate-limit` block, one per value of its key profile.
+///
+/// The key is the first of the profile's `key` and `fallback` templates that yields a
+/// value, so a header-based key falls back to the client address for requests without
+/// the header. Requests that yield no key at all share a single bucket.
+///
+/// The buckets are split by key into `shards`, each behind its own lock, so requests
+/// for different keys rarely wait on each other. `max-buckets` is divided between the
+/// shards, and eviction only looks at the shard the new key falls into.
+///
+/// With the Redis backend the buckets live in Redis instead, and the local ones only
+/// stand in while Redis is unreachable, if the backend fails open.
+pub struct RateLimiter {
+    selector: KeySelector,
+    tokens_per_bucket: usize,
+    refill_qty: usize,
+    refill_interval: Duration,
+    /// Keys remembered by each shard.
+    max_buckets: usize,
+    shards: Box<[Mutex<Buckets>]>,
+    redis: Option<RedisBuckets>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: usize,
+    /// Start of the refill interval in progress.
+    refilled_at: Instant,
+    last_used: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Result<Self, String> {
+        Ok(Self {
+            selector: KeySelector::try_from(config.key)?,
+            tokens_per_bucket: config.tokens_per_bucket.get(),
+            refill_qty: config.refill_qty.get(),
+            refill_interval: config.refill_interval,
+            max_buckets: config.max_buckets.get().div_ceil(config.shards.get()),
+            shards: (0..config.shards.get()).map(|_| Mutex::default()).collect(),
+            redis: match config.backend {
+                RateLimitBackend::Local => None,
+                RateLimitBackend::Redis(redis) => Some(RedisBuckets::new(redis)?),
+            },
+        })
+    }
+
+    /// Takes a token for the request, `false` if its bucket is empty.
+    pub async fn try_acquire<C: KeySourceContext + Sync>(&self, ctx: &C) -> bool {
+        let key = self.selector.evaluate(ctx).map(|key| key.hash);
+
+        if let Some(redis) = &self.redis {
+            let limits = BucketLimits {
+                tokens_per_bucket: self.tokens_per_bucket,
+                refill_qty: self.refill_qty,
+                refill_interval: self.refill_interval,
+            };
+            match redis.try_acquire(key, limits).await {
+                Ok(admitted) => return admitted,
+                Err(_) if !redis.fail_open => return false,
+                Err(_) => {}
+            }
+        }
+
+        self.try_acquire_at(key, Instant::now())
+    }
+
+    /// The shard holding the bucket of `key`.
+    fn shard(&self, key: Option<u64>) -> &Mutex<Buckets> {
+        // the key is already a hash, folding in the high half evens out weak ones like FNV
+        let hash = key.map_or(0, |key| key ^ (key >> 32)) as usize;
+        &self.shards[hash & (self.shards.len() - 1)]
+    }
+
+    fn try_acquire_at(&self, key: Option<u64>, now: Instant) -> bool {
+        let mut buckets = self.shard(key).lock().unwrap_or_else(|e| e.into_inner());
+
+        if !buckets.contains_key(&key) && buckets.len() >= self.max_buckets {
+            self.evict(&mut buckets, now);
+        }
+
+        let bucket = buckets.entry(key).or_insert_with(|| Bucket {
+            tokens: self.tokens_per_bucket,
+            refilled_at: now,
+            last_used: now,
+        });
+        self.refill(bucket, now);
+        bucket.last_used = now;
+
+        match bucket.tokens {
+            0 => false,
+            _ => {
+                bucket.tokens -= 1;
+                true
+            }
+        }
+    }
+
+    fn refill(&self, bucket: &mut Bucket, now: Instant) {
+        let intervals = now.saturating_duration_since(bucket.refilled_at).as_nanos()
+            / self.refill_interval.as_nanos();
+        if intervals == 0 {
+            return;
+        }
+
+        let added = (intervals as usize).saturating_mul(self.refill_qty);
+        bucket.tokens = bucket
+            .tokens
+            .saturating_add(added)
+            .min(self.tokens_per_bucket);
+        bucket.refilled_at += self.refill_interval * intervals as u32;
+    }
+
+    /// Makes room for a new key: buckets that have filled up again are no different from
+    /// new ones and go first, otherwise the least recently used one does.
+    fn evict(&self, buckets: &mut Buckets, now: Instant) {
+        buckets.retain(|_, bucket| {
+            self.refill(bucket, now);
+            bucket.tokens < self.tokens_per_bucket
+        });
+        if buckets.len() < self.max_buckets {
+            return;
+        }
+
+        if let Some(oldest) = buckets
+            .iter()
+            .min_by_key(|(_, bucket)| bucket.last_used)
+            .map(|(key, _)| *key)
+        {
+            buckets.remove(&oldest);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, net::IpAddr, num::NonZeroUsize};
+
+    use http::uri::PathAndQuery;
+    use motya_config::common_types::definitions::{HashAlgorithm, KeyTemplateConfig};
+
+    use super::*;
+    use crate::proxy::redis_rate_limit::tests::{backend, mock_redis};
+
+    struct Request {
+        headers: HashMap<&'static str, &'static str>,
+        ip: IpAddr,
+        path: PathAndQuery,
+    }
+
+    impl Request {
+        fn from(ip: &str) -> Self {
+            Self {
+                headers: HashMap::new(),
+                ip: ip.parse().unwrap(),
+                path: PathAndQuery::from_static("/"),
+            }
+        }
+
+        fn with_api_key(mut self, key: &'static str) -> Self {
+            self.headers.insert("x-api-key", key);
+            self
+        }
+    }
+
+    impl KeySourceContext for Request {
+        fn get_header(&self, name: &str) -> Option<&str> {
+            self.headers.get(name).copied()
+        }
+        fn get_cookie(&self, _name: &str) -> Option<&str> {
+            None
+        }
+        fn get_ip(&self) -> Option<IpAddr> {
+            Some(self.ip)
+        }
+        fn get_path(&self) -> &PathAndQuery {
+            &self.path
+        }
+    }
+
+    fn limiter(tokens: usize, max_buckets: usize) -> RateLimiter {
+        sharded_limiter(tokens, max_buckets, 1)
+    }
+
+    fn sharded_limiter(tokens: usize, max_buckets: usize, shards: usize) -> RateLimiter {
+        RateLimiter::new(config(tokens, max_buckets, shards)).unwrap()
+    }
+
+    fn config(tokens: usize, max_buckets: usize, shards: usize) -> RateLimitConfig {
+        RateLimitConfig {
+            key: KeyTemplateConfig {
+                source: "${header-x-api-key}".to_string(),
+                fallback: Some("${client-ip}".to_string()),
+                transforms: vec![],
+                algorithm: HashAlgorithm {
+                    name: "xxhash64".to_string(),
+                    seed: None,
+                },
+            },
+            tokens_per_bucket: NonZeroUsize::new(tokens).unwrap(),
+            refill_qty: NonZeroUsize::new(1).unwrap(),
+            refill_interval: Duration::from_secs(1),
+            max_buckets: NonZeroUsize::new(max_buckets).unwrap(),
+            shards: NonZeroUsize::new(shards).unwrap(),
+            backend: RateLimitBackend::Local,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_missing_header_falls_back_to_ip() {
+        let limiter = limiter(2, 100);
+
+        // the same client without a key is limited by its address
+        assert!(limiter.try_acquire(&Request::from("10.0.0.1")).await);
+        assert!(limiter.try_acquire(&Request::from("10.0.0.1")).await);
+        assert!(!limiter.try_acquire(&Request::from("10.0.0.1")).await);
+
+        // other addresses, and keyed requests from the same one, have their own buckets
+        assert!(limiter.try_acquire(&Request::from("10.0.0.2")).await);
+        let keyed = || Request::from("10.0.0.1").with_api_key("team-a");
+        assert!(limiter.try_acquire(&keyed()).await);
+        assert!(limiter.try_acquire(&keyed()).await);
+        assert!(!limiter.try_acquire(&keyed()).await);
+
+        // a key is limited wherever it comes from
+        assert!(
+            !limiter
+                .try_acquire(&Request::from("10.0.0.9").with_api_key("team-a"))
+                .await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_redis_backend_shared_between_instances() {
+        let addr = mock_redis().await;
+        let instance = || {
+            let mut config = config(3, 100, 1);
+            config.backend = RateLimitBackend::Redis(backend(addr, true));
+            RateLimiter::new(config).unwrap()
+        };
+        let (first, second) = (instance(), instance());
+
+        let client = || Request::from("10.0.0.1");
+        assert!(first.try_acquire(&client()).await);
+        assert!(second.try_acquire(&client()).await);
+        assert!(second.try_acquire(&client()).await);
+        assert!(!first.try_acquire(&client()).await);
+        assert!(!second.try_acquire(&client()).await);
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_redis_fails_open_or_closed() {
+        // bound and dropped, so nothing listens there
+        let addr = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let with_redis = |fail_open| {
+            let mut config = config(2, 100, 1);
+            config.backend = RateLimitBackend::Redis(backend(addr, fail_open));
+            RateLimiter::new(config).unwrap()
+        };
+
+        // falls back to the local buckets
+        let open = with_redis(true);
+        assert!(open.try_acquire(&Request::from("10.0.0.1")).await);
+        assert!(open.try_acquire(&Request::from("10.0.0.1")).await);
+        assert!(!open.try_acquire(&Request::from("10.0.0.1")).await);
+
+        let closed = with_redis(false);
+        assert!(!closed.try_acquire(&Request::from("10.0.0.1")).await);
+    }
+
+    #[test]
+    fn test_bucket_refills() {
+        let limiter = limiter(2, 100);
+        let start = Instant::now();
+
+        assert!(limiter.try_acquire_at(Some(1), start));
+        assert!(limiter.try_acquire_at(Some(1), start));
+        assert!(!limiter.try_acquire_at(Some(1), start + Duration::from_millis(999)));
+
+        assert!(limiter.try_acquire_at(Some(1), start + Duration::from_secs(1)));
+        assert!(!limiter.try_acquire_at(Some(1), start + Duration::from_secs(1)));
+
+        // never more than the bucket holds, however long it was idle
+        let later = start + Duration::from_secs(60);
+        assert!(limiter.try_acquire_at(Some(1), later));
+        assert!(limiter.try_acquire_at(Some(1), later));
+        assert!(!limiter.try_acquire_at(Some(1), later));
+    }
+
+    #[test]
+    fn test_eviction_keeps_busy_buckets() {
+        let limiter = limiter(1, 2);
+        let start = Instant::now();
+
+        assert!(limiter.try_acquire_at(Some(1), start));
+        assert!(limiter.try_acquire_at(Some(2), start + Duration::from_millis(10)));
+        // both buckets are empty, the least recently used one is dropped
+        assert!(limiter.try_acquire_at(Some(3), start + Duration::from_millis(20)));
+
+        assert!(limiter.try_acquire_at(Some(1), start + Duration::from_millis(30)));
+        assert!(!limiter.try_acquire_at(Some(3), start + Duration::from_millis(40)));
+    }
+
+    #[test]
+    fn test_shards_split_max_buckets() {
+        let limiter = sharded_limiter(1, 100, 8);
+        assert_eq!(limiter.shards.len(), 8);
+        assert_eq!(limiter.max_buckets, 13);
+
+        // every shard gets keys, and a key always lands in the same one
+        let mut used = [false; 8];
+        for key in 0..64u64 {
+            let shard = limiter.shard(Some(key)) as *const _;
+            assert_eq!(shard, limiter.shard(Some(key)) as *const _);
+            let index = limiter.shards.iter().position(|s| std::ptr::eq(s, shard));
+            used[index.unwrap()] = true;
+        }
+        assert!(used.iter().all(|used| *used));
+    }
+
+    #[test]
+    fn test_shards_dont_contend() {
+        use std::{sync::mpsc, thread};
+
+        let sharded = sharded_limiter(10, 100, 64);
+        let limiter = &sharded;
+        let start = Instant::now();
+        let (busy, other) = (Some(0), Some(1));
+        assert!(!std::ptr::eq(limiter.shard(busy), limiter.shard(other)));
+
+        thread::scope(|scope| {
+            // holds one shard's lock, as a slow request for that key would
+            let held = limiter.shard(busy).lock().unwrap();
+
+            let (done, finished) = mpsc::channel();
+            scope.spawn(move || {
+                done.send(limiter.try_acquire_at(other, start)).unwrap();
+            });
+            // a single mutex would keep the other key waiting until the lock is released
+            assert_eq!(finished.recv_timeout(Duration::from_secs(5)), Ok(true));
+
+            drop(held);
+        });
+
+        // with one shard, the same request waits for the lock
+        let unsharded = sharded_limiter(10, 100, 1);
+        let single = &unsharded;
+        thread::scope(|scope| {
+            let held = single.shard(busy).lock().unwrap();
+
+            let (done, finished) = mpsc::channel();
+            scope.spawn(move || {
+                done.send(single.try_acquire_at(other, start)).unwrap();
+            });
+            assert!(finished.recv_timeout(Duration::from_millis(100)).is_err());
+
+            drop(held);
+            assert_eq!(finished.recv_timeout(Duration::from_secs(5)), Ok(true));
+        });
+    }
+}
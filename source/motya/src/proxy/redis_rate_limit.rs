@@ -0,0 +1,358 @@
+use std::{
+    io,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use motya_config::common_types::rate_limit::RedisBackend;
+use redis::{aio::ConnectionManager, Client, Script};
+use tokio::sync::OnceCell;
+
+/// How long a single exchange with Redis, connecting included, may take.
+const REDIS_TIMEOUT: Duration = Duration::from_millis(250);
+
+/// How long Redis is left alone after a failed exchange.
+const RETRY_AFTER: Duration = Duration::from_secs(1);
+
+/// Takes a token from the bucket in `KEYS[1]`, with `ARGV` being the bucket size, the
+/// refill quantity and the refill interval in milliseconds. Same arithmetic as the local
+/// buckets, on the clock of the Redis server so that every instance agrees on it.
+const TOKEN_BUCKET_SCRIPT: &str = r#"
+local tokens_per_bucket = tonumber(ARGV[1])
+local refill_qty = tonumber(ARGV[2])
+local interval = tonumber(ARGV[3])
+
+local time = redis.call('TIME')
+local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+
+local bucket = redis.call('HMGET', KEYS[1], 'tokens', 'refilled_at')
+local tokens = tonumber(bucket[1])
+local refilled_at = tonumber(bucket[2])
+
+if tokens == nil or refilled_at == nil then
+    tokens = tokens_per_bucket
+    refilled_at = now
+else
+    local intervals = math.floor((now - refilled_at) / interval)
+    if intervals > 0 then
+        tokens = math.min(tokens_per_bucket, tokens + intervals * refill_qty)
+        refilled_at = refilled_at + intervals * interval
+    end
+end
+
+local admitted = 0
+if tokens > 0 then
+    tokens = tokens - 1
+    admitted = 1
+end
+
+redis.call('HSET', KEYS[1], 'tokens', tokens, 'refilled_at', refilled_at)
+-- a bucket that would be full again is no different from a missing one
+local refills = math.ceil((tokens_per_bucket - tokens) / refill_qty)
+redis.call('PEXPIRE', KEYS[1], (refills + 1) * interval)
+
+return admitted
+"#;
+
+/// Token buckets kept in Redis, shared by every instance pointed at the same server and
+/// prefix. Each acquisition is one run of [`TOKEN_BUCKET_SCRIPT`], so concurrent
+/// requests from different instances can't both take the last token.
+///
+/// The script is run with `EVALSHA`, and only sent again when Redis answers that it
+/// doesn't know it. Requests share a multiplexed connection, opened on first use and
+/// reopened by the connection manager after an error. Once an exchange fails, Redis is
+/// considered unreachable for [`RETRY_AFTER`] and every acquisition fails right away,
+/// leaving it to the caller to fall back.
+pub struct RedisBuckets {
+    /// `host:port` of the server, for the logs.
+    addr: String,
+    client: Client,
+    script: Script,
+    prefix: String,
+    pub fail_open: bool,
+    connection: OnceCell<ConnectionManager>,
+    retry_at: Mutex<Option<Instant>>,
+    /// Set while Redis is unreachable and that has been logged.
+    warned: AtomicBool,
+}
+
+/// The limits of the bucket, passed to the script with every acquisition.
+#[derive(Debug, Clone, Copy)]
+pub struct BucketLimits {
+    pub tokens_per_bucket: usize,
+    pub refill_qty: usize,
+    pub refill_interval: Duration,
+}
+
+impl RedisBuckets {
+    pub fn new(config: RedisBackend) -> Result<Self, String> {
+        let host = config
+            .url
+            .host_str()
+            .ok_or_else(|| format!("Redis url '{}' has no host", config.url))?;
+        let port = config.url.port().unwrap_or(6379);
+
+        // the user and password are percent-decoded by the client, before `AUTH`
+        let client = Client::open(config.url.as_str())
+            .map_err(|err| format!("Invalid Redis url '{}': {err}", config.url))?;
+
+        Ok(Self {
+            addr: format!("{host}:{port}"),
+            client,
+            script: Script::new(TOKEN_BUCKET_SCRIPT),
+            prefix: config.prefix,
+            fail_open: config.fail_open,
+            connection: OnceCell::new(),
+            retry_at: Mutex::new(None),
+            warned: AtomicBool::new(false),
+        })
+    }
+
+    /// Takes a token from the shared bucket of `key`, `Ok(false)` if it's empty.
+    pub async fn try_acquire(&self, key: Option<u64>, limits: BucketLimits) -> io::Result<bool> {
+        let retry_at = *self.retry_at.lock().unwrap_or_else(|e| e.into_inner());
+        if retry_at.is_some_and(|retry_at| Instant::now() < retry_at) {
+            return Err(io::Error::other("Redis is unreachable"));
+        }
+
+        let key = match key {
+            Some(key) => format!("{}:rate-limit:{key:016x}", self.prefix),
+            None => format!("{}:rate-limit:none", self.prefix),
+        };
+
+        let result = tokio::time::timeout(REDIS_TIMEOUT, self.take_token(&key, limits))
+            .await
+            .unwrap_or_else(|_| Err(io::Error::new(io::ErrorKind::TimedOut, "Redis timed out")));
+
+        match result {
+            Ok(admitted) => {
+                if self.warned.swap(false, Ordering::Relaxed) {
+                    tracing::info!("Redis at {} is reachable again", self.addr);
+                }
+                Ok(admitted == 1)
+            }
+            Err(err) => Err(self.failed(err)),
+        }
+    }
+
+    async fn take_token(&self, key: &str, limits: BucketLimits) -> io::Result<i64> {
+        let mut connection = self
+            .connection
+            .get_or_try_init(|| ConnectionManager::new(self.client.clone()))
+            .await
+            .map_err(io::Error::other)?
+            .clone();
+
+        self.script
+            .key(key)
+            .arg(limits.tokens_per_bucket)
+            .arg(limits.refill_qty)
+            .arg(limits.refill_interval.as_millis().max(1) as u64)
+            .invoke_async(&mut connection)
+            .await
+            .map_err(io::Error::other)
+    }
+
+    /// Keeps away from Redis for a while.
+    fn failed(&self, err: io::Error) -> io::Error {
+        *self.retry_at.lock().unwrap_or_else(|e| e.into_inner()) =
+            Some(Instant::now() + RETRY_AFTER);
+
+        if !self.warned.swap(true, Ordering::Relaxed) {
+            let fallback = if self.fail_open {
+                "limiting locally"
+            } else {
+                "rejecting rate-limited requests"
+            };
+            tracing::warn!("Redis at {} is unreachable, {fallback}: {err}", self.addr);
+        }
+        err
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{collections::HashMap, net::SocketAddr, sync::Arc};
+
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+        net::{TcpListener, TcpStream},
+    };
+
+    use super::*;
+
+    /// What a [`mock_redis`] was sent, shared by all its connections.
+    #[derive(Default)]
+    pub(crate) struct MockRedis {
+        buckets: HashMap<String, i64>,
+        script_loaded: bool,
+        /// How many times the script was sent.
+        loads: usize,
+        /// The arguments of every `AUTH`.
+        auths: Vec<Vec<String>>,
+    }
+
+    /// A stand-in for Redis that runs the bucket script as a plain decrement, without
+    /// refills, keeping the buckets of every connection in one map.
+    pub(crate) async fn mock_redis() -> SocketAddr {
+        mock_redis_with_state().await.0
+    }
+
+    pub(crate) async fn mock_redis_with_state() -> (SocketAddr, Arc<Mutex<MockRedis>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let state = Arc::new(Mutex::new(MockRedis::default()));
+
+        let shared = state.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let state = shared.clone();
+                tokio::spawn(async move {
+                    let mut stream = BufReader::new(stream);
+                    while let Some(command) = read_command(&mut stream).await {
+                        let reply = reply(&mut state.lock().unwrap(), &command);
+                        if stream.get_mut().write_all(reply.as_bytes()).await.is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+
+        (addr, state)
+    }
+
+    fn reply(state: &mut MockRedis, command: &[String]) -> String {
+        match command[0].to_ascii_uppercase().as_str() {
+            "EVALSHA" if !state.script_loaded => {
+                "-NOSCRIPT No matching script. Please use EVAL.\r\n".to_string()
+            }
+            "EVALSHA" => {
+                let capacity: i64 = command[4].parse().unwrap();
+                let tokens = state.buckets.entry(command[3].clone()).or_insert(capacity);
+                let admitted = *tokens > 0;
+                if admitted {
+                    *tokens -= 1;
+                }
+                format!(":{}\r\n", admitted as i64)
+            }
+            "SCRIPT" => {
+                state.script_loaded = true;
+                state.loads += 1;
+                let sha = "0".repeat(40);
+                format!("${}\r\n{sha}\r\n", sha.len())
+            }
+            "AUTH" => {
+                state.auths.push(command[1..].to_vec());
+                "+OK\r\n".to_string()
+            }
+            "SELECT" | "CLIENT" => "+OK\r\n".to_string(),
+            "PING" => "+PONG\r\n".to_string(),
+            other => format!("-ERR unknown command '{other}'\r\n"),
+        }
+    }
+
+    async fn read_command(stream: &mut BufReader<TcpStream>) -> Option<Vec<String>> {
+        let mut line = String::new();
+        stream
+            .read_line(&mut line)
+            .await
+            .ok()
+            .filter(|read| *read > 0)?;
+        let count: usize = line.trim_end().strip_prefix('*')?.parse().ok()?;
+
+        let mut command = Vec::with_capacity(count);
+        for _ in 0..count {
+            line.clear();
+            stream.read_line(&mut line).await.ok()?;
+            let len: usize = line.trim_end().strip_prefix('$')?.parse().ok()?;
+            let mut arg = vec![0; len + 2];
+            stream.read_exact(&mut arg).await.ok()?;
+            arg.truncate(len);
+            command.push(String::from_utf8(arg).ok()?);
+        }
+        Some(command)
+    }
+
+    pub(crate) fn backend(addr: SocketAddr, fail_open: bool) -> RedisBackend {
+        RedisBackend {
+            url: format!("redis://{addr}/0").parse().unwrap(),
+            prefix: "test".to_string(),
+            fail_open,
+        }
+    }
+
+    const LIMITS: BucketLimits = BucketLimits {
+        tokens_per_bucket: 3,
+        refill_qty: 1,
+        refill_interval: Duration::from_secs(60),
+    };
+
+    #[tokio::test]
+    async fn test_instances_share_buckets() {
+        let addr = mock_redis().await;
+        let first = RedisBuckets::new(backend(addr, true)).unwrap();
+        let second = RedisBuckets::new(backend(addr, true)).unwrap();
+
+        assert!(first.try_acquire(Some(7), LIMITS).await.unwrap());
+        assert!(second.try_acquire(Some(7), LIMITS).await.unwrap());
+        assert!(first.try_acquire(Some(7), LIMITS).await.unwrap());
+        // the bucket is empty for every instance, whichever took the tokens
+        assert!(!second.try_acquire(Some(7), LIMITS).await.unwrap());
+        assert!(!first.try_acquire(Some(7), LIMITS).await.unwrap());
+
+        // other keys have their own buckets
+        assert!(second.try_acquire(Some(8), LIMITS).await.unwrap());
+        assert!(second.try_acquire(None, LIMITS).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_script_sent_once() {
+        let (addr, state) = mock_redis_with_state().await;
+        let buckets = RedisBuckets::new(backend(addr, true)).unwrap();
+
+        for _ in 0..5 {
+            buckets.try_acquire(Some(7), LIMITS).await.unwrap();
+        }
+
+        // run by its hash, after loading it when Redis didn't know it yet
+        assert_eq!(state.lock().unwrap().loads, 1);
+    }
+
+    #[tokio::test]
+    async fn test_credentials_percent_decoded() {
+        let (addr, state) = mock_redis_with_state().await;
+        let buckets = RedisBuckets::new(RedisBackend {
+            url: format!("redis://us%40er:p%40ss%3Aword@{addr}/0")
+                .parse()
+                .unwrap(),
+            ..backend(addr, true)
+        })
+        .unwrap();
+
+        assert!(buckets.try_acquire(Some(7), LIMITS).await.unwrap());
+        assert_eq!(
+            state.lock().unwrap().auths,
+            vec![vec!["us@er".to_string(), "p@ss:word".to_string()]]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_backs_off() {
+        // bound and dropped, so nothing listens there
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let buckets = RedisBuckets::new(backend(addr, true)).unwrap();
+
+        assert!(buckets.try_acquire(Some(1), LIMITS).await.is_err());
+        assert!(buckets.retry_at.lock().unwrap().is_some());
+        assert!(buckets.warned.load(Ordering::Relaxed));
+        assert!(buckets.try_acquire(Some(1), LIMITS).await.is_err());
+    }
+}
@@ -0,0 +1,98 @@
+use pingora_http::RequestHeader;
+use pingora_proxy::Session;
+
+use motya_config::common_types::connectors::RequireTls;
+
+/// What a connector under a `require-tls` section does with a request.
+#[derive(Debug, PartialEq)]
+pub enum TlsCheck {
+    Pass,
+    Reject,
+    /// Redirect to this https URL.
+    Redirect(String),
+}
+
+/// Whether the request came over a TLS connection. With `auto-tls` the handshake is
+/// done before the connection reaches the proxy, so this holds for those connections too.
+pub fn is_tls(session: &Session) -> bool {
+    session
+        .digest()
+        .is_some_and(|digest| digest.ssl_digest.is_some())
+}
+
+pub fn check(require: RequireTls, tls: bool, req: &RequestHeader) -> TlsCheck {
+    if tls {
+        return TlsCheck::Pass;
+    }
+
+    match require {
+        RequireTls::No => TlsCheck::Pass,
+        RequireTls::Reject => TlsCheck::Reject,
+        // without a host there is nothing to redirect to
+        RequireTls::Redirect => https_location(req).map_or(TlsCheck::Reject, TlsCheck::Redirect),
+    }
+}
+
+/// The URL of the request over https, on the default port.
+fn https_location(req: &RequestHeader) -> Option<String> {
+    let host = req
+        .headers
+        .get(http::header::HOST)
+        .and_then(|host| host.to_str().ok())
+        .or_else(|| req.uri.host())?;
+    // the plaintext port says nothing about where https is served
+    let host = match host.rsplit_once(':') {
+        Some((name, port)) if port.bytes().all(|b| b.is_ascii_digit()) => name,
+        _ => host,
+    };
+    if host.is_empty() {
+        return None;
+    }
+
+    let path = req.uri.path_and_query().map_or("/", |path| path.as_str());
+    Some(format!("https://{host}{path}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(uri: &str, host: Option<&str>) -> RequestHeader {
+        let mut req = RequestHeader::build("GET", uri.as_bytes(), None).unwrap();
+        if let Some(host) = host {
+            req.insert_header(http::header::HOST, host).unwrap();
+        }
+        req
+    }
+
+    #[test]
+    fn test_plaintext_rejected_tls_passes() {
+        let req = request("/admin/users", Some("example.com"));
+
+        assert_eq!(check(RequireTls::Reject, false, &req), TlsCheck::Reject);
+        assert_eq!(check(RequireTls::Reject, true, &req), TlsCheck::Pass);
+        assert_eq!(check(RequireTls::No, false, &req), TlsCheck::Pass);
+    }
+
+    #[test]
+    fn test_redirect_location() {
+        let req = request("/admin/login?next=%2F", Some("example.com:8080"));
+        assert_eq!(
+            check(RequireTls::Redirect, false, &req),
+            TlsCheck::Redirect("https://example.com/admin/login?next=%2F".to_string())
+        );
+        assert_eq!(check(RequireTls::Redirect, true, &req), TlsCheck::Pass);
+
+        let ipv6 = request("/", Some("[::1]:8080"));
+        assert_eq!(
+            check(RequireTls::Redirect, false, &ipv6),
+            TlsCheck::Redirect("https://[::1]/".to_string())
+        );
+
+        let hostless = request("/admin", None);
+        assert_eq!(
+            check(RequireTls::Redirect, false, &hostless),
+            TlsCheck::Reject
+        );
+    }
+}
@@ -0,0 +1,403 @@
+use std::{collections::BTreeMap, net::IpAddr, sync::Arc};
+
+use fqdn::FQDN;
+use http::uri::PathAndQuery;
+use miette::{miette, IntoDiagnostic, Result};
+use pingora::prelude::HttpPeer;
+use pingora_http::RequestHeader;
+use regex::Regex;
+
+use motya_config::{
+    common_types::{
+        condition::RequestFacts,
+        connectors::{RouteMatcher, UpstreamConfig, UpstreamContextConfig},
+        definitions::{ConfiguredFilter, Modificator},
+        routes::{RouteSelector, SplitRouteConfig},
+    },
+    define_builtin_filters,
+    internal::ProxyConfig,
+};
+
+use crate::proxy::{
+    balancer::key_selector::Balancer,
+    filters::builtin::cidr_range::CidrRangeFilter,
+    split::SplitRoute,
+    upstream_router::{UpstreamContextTrait, UpstreamRouter},
+};
+
+const BLOCK_CIDR_RANGE: &str = "motya.filters.block-cidr-range";
+
+macro_rules! modifier_names {
+    (
+        actions: { $($act_key:literal => $act_type:ty),* $(,)? }
+
+        requests: { $($req_key:literal => $req_type:ty),* $(,)? }
+
+        responses: { $($res_key:literal => $res_type:ty),* $(,)? }
+    ) => {
+        /// Built-in filters that only change requests and responses, and so never stop one.
+        const MODIFIERS: &[&str] = &[$($req_key,)* $($res_key,)*];
+    };
+}
+
+define_builtin_filters!(modifier_names);
+
+/// Where a request would be sent and whether the filters would let it through.
+#[derive(Debug, Clone)]
+pub struct SimResult {
+    /// The `route` the request falls under, with the group it is handed to.
+    pub route: Option<SimRoute>,
+    /// The connector handling the request, `None` when no connector matches its path.
+    pub connector: Option<UpstreamContextConfig>,
+    pub decision: FilterDecision,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimRoute {
+    pub path: String,
+    pub group: String,
+}
+
+/// What the request filters of the chosen connector do with a request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FilterDecision {
+    /// No filter stops the request.
+    Pass,
+    /// The named filter answers the request instead of letting it through.
+    Blocked(String),
+    /// The named filter can't be decided without running it, such as `geo-block` or a
+    /// WASM filter. The filters after it are not looked at.
+    Undecided(String),
+}
+
+/// Works out how a service would handle a request, without sending anything upstream.
+///
+/// The request is routed as the service routes it, `route` splits first and then the
+/// connectors by path. The enabled filters of the chosen connector are then tried in
+/// order, skipping those whose `when` clause doesn't match, with `block-cidr-range`
+/// checked against `client`.
+///
+/// A `split` without a sticky key picks its group as it does for the first request after
+/// a start.
+pub fn simulate(
+    proxy: &ProxyConfig,
+    method: &str,
+    host: Option<&str>,
+    path: &str,
+    headers: &[(&str, &str)],
+    client: IpAddr,
+) -> Result<SimResult> {
+    let mut req =
+        RequestHeader::build(method, path.as_bytes(), None).map_err(|err| miette!("{err}"))?;
+    if let Some(host) = host {
+        req.insert_header(http::header::HOST, host)
+            .map_err(|err| miette!("{err}"))?;
+    }
+    for (name, value) in headers {
+        req.append_header(name.to_string(), *value)
+            .map_err(|err| miette!("{err}"))?;
+    }
+
+    let mut groups = vec![];
+    let splits = proxy
+        .routes
+        .iter()
+        .map(|route| build_split(route, &mut groups))
+        .collect::<Result<Vec<_>>>()?;
+    let root = build_router(&proxy.connectors.upstreams)?.with_splits(splits);
+
+    let path = req.uri.path();
+    let picked = root
+        .split_for(&req)
+        .map(|split| (split.prefix().to_string(), split.pick(&req).clone()));
+    let (route, router) = match picked {
+        Some((route_path, router)) => {
+            let group = groups
+                .iter()
+                .find(|(candidate, _)| Arc::ptr_eq(candidate, &router))
+                .map(|(_, group)| group.clone())
+                .expect("every split router belongs to a group");
+            let route = SimRoute {
+                path: route_path,
+                group,
+            };
+            (Some(route), router)
+        }
+        None => (None, Arc::new(root)),
+    };
+
+    let Some(upstream) = router.get_upstream_by_path(path) else {
+        return Ok(SimResult {
+            route,
+            connector: None,
+            decision: FilterDecision::Pass,
+        });
+    };
+
+    let facts = RequestFacts {
+        method: req.method.as_str(),
+        path,
+        host,
+        headers: &req.headers,
+    };
+    let decision = decide(&upstream.config, &facts, client)?;
+
+    Ok(SimResult {
+        route,
+        connector: Some(upstream.config.clone()),
+        decision,
+    })
+}
+
+fn decide(
+    config: &UpstreamContextConfig,
+    facts: &RequestFacts,
+    client: IpAddr,
+) -> Result<FilterDecision> {
+    let filters = config
+        .chains
+        .iter()
+        .flat_map(|Modificator::Chain(named)| &named.chain.filters)
+        .filter(|filter| filter.enabled);
+
+    for filter in filters {
+        if filter
+            .when
+            .as_ref()
+            .is_some_and(|when| !when.matches(facts))
+        {
+            continue;
+        }
+
+        if is_named(filter, BLOCK_CIDR_RANGE) {
+            let settings = filter.args.clone().into_iter().collect::<BTreeMap<_, _>>();
+            let cidr = CidrRangeFilter::from_settings(settings).map_err(|err| miette!("{err}"))?;
+            if cidr.is_blocked(&client) {
+                return Ok(FilterDecision::Blocked(filter.name.to_string()));
+            }
+        } else if !MODIFIERS.iter().any(|name| is_named(filter, name)) {
+            return Ok(FilterDecision::Undecided(filter.name.to_string()));
+        }
+    }
+
+    Ok(FilterDecision::Pass)
+}
+
+fn is_named(filter: &ConfiguredFilter, name: &str) -> bool {
+    name.parse::<FQDN>().is_ok_and(|name| name == filter.name)
+}
+
+type SimRouter = UpstreamRouter<SimUpstream>;
+
+fn build_router(upstreams: &[UpstreamContextConfig]) -> Result<SimRouter> {
+    let upstreams = upstreams
+        .iter()
+        .map(|config| SimUpstream {
+            config: config.clone(),
+        })
+        .collect();
+
+    UpstreamRouter::build(upstreams).into_diagnostic()
+}
+
+fn build_split(
+    route: &SplitRouteConfig,
+    groups: &mut Vec<(Arc<SimRouter>, String)>,
+) -> Result<SplitRoute<SimUpstream>> {
+    let mut group_router = |group: &str, upstreams: &[UpstreamContextConfig]| {
+        let router = Arc::new(build_router(upstreams)?);
+        groups.push((router.clone(), group.to_string()));
+        Ok::<_, miette::Error>(router)
+    };
+
+    let split = match &route.selector {
+        RouteSelector::Split { targets, sticky } => {
+            let routers = targets
+                .iter()
+                .map(|target| {
+                    let router = group_router(&target.group, &target.connectors.upstreams)?;
+                    Ok((target.weight, router))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            SplitRoute::new(route.path.clone(), sticky.clone(), routers)
+        }
+        RouteSelector::MatchHeader(header_match) => {
+            let cases = header_match
+                .cases
+                .iter()
+                .map(|(value, target)| {
+                    let router = group_router(&target.group, &target.connectors.upstreams)?;
+                    Ok((value.clone(), router))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let default = group_router(
+                &header_match.default.group,
+                &header_match.default.connectors.upstreams,
+            )?;
+            SplitRoute::by_header(
+                route.path.clone(),
+                header_match.header.clone(),
+                cases,
+                default,
+            )
+        }
+    };
+
+    Ok(split.with_query(route.query.clone()))
+}
+
+/// A connector as the router sees it, without the balancer and chains a running service
+/// builds for it.
+struct SimUpstream {
+    config: UpstreamContextConfig,
+}
+
+impl UpstreamContextTrait for SimUpstream {
+    fn get_prefix_path(&self) -> &PathAndQuery {
+        match &self.config.upstream {
+            UpstreamConfig::Service(peer_options) => &peer_options.prefix_path,
+            UpstreamConfig::Static(peer_options) => &peer_options.prefix_path,
+            UpstreamConfig::MultiServer(m) => &m.prefix_path,
+        }
+    }
+
+    fn get_route_type(&self) -> RouteMatcher {
+        match &self.config.upstream {
+            UpstreamConfig::Service(peer_options) => peer_options.matcher,
+            UpstreamConfig::Static(_) => RouteMatcher::Exact,
+            UpstreamConfig::MultiServer(m) => m.matcher,
+        }
+    }
+
+    fn get_balancer(&self) -> Option<&Balancer> {
+        None
+    }
+
+    fn get_peer(&self) -> Option<HttpPeer> {
+        None
+    }
+
+    fn get_pinned_peer(&self, _req: &RequestHeader) -> Option<HttpPeer> {
+        None
+    }
+
+    fn get_path_regex(&self) -> Option<&Regex> {
+        self.config.path_regex.as_ref().map(|regex| &regex.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use motya_config::{
+        common_types::definitions_table::DefinitionsTable,
+        kdl::{compiler::ConfigCompiler, fs_loader::read_piped},
+    };
+
+    use super::*;
+
+    fn compile(kdl_input: &str) -> ProxyConfig {
+        let docs = read_piped(kdl_input.as_bytes(), None).unwrap();
+        let mut config = ConfigCompiler::new(docs)
+            .compile(&mut DefinitionsTable::new_with_global())
+            .expect("Should compile");
+        config.basic_proxies.remove(0)
+    }
+
+    fn peer(result: &SimResult) -> String {
+        match &result
+            .connector
+            .as_ref()
+            .expect("Should match a connector")
+            .upstream
+        {
+            UpstreamConfig::Service(peer) => peer.peer_address.to_string(),
+            other => panic!("Expected a proxy connector, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_routes_to_connector() {
+        let proxy = compile(
+            r#"
+            system { }
+            services {
+                Api {
+                    listeners {
+                        "0.0.0.0:8080"
+                    }
+                    connectors {
+                        section "/api" as="prefix" {
+                            proxy "127.0.0.1:3001"
+                        }
+                        proxy "127.0.0.1:3000"
+                    }
+                }
+            }
+            "#,
+        );
+        let client = "192.168.1.10".parse().unwrap();
+
+        let result = simulate(
+            &proxy,
+            "GET",
+            Some("example.com"),
+            "/api/users",
+            &[],
+            client,
+        )
+        .unwrap();
+        assert_eq!(peer(&result), "127.0.0.1:3001");
+        assert_eq!(result.route, None);
+        assert_eq!(result.decision, FilterDecision::Pass);
+
+        let result = simulate(&proxy, "GET", None, "/index.html", &[], client).unwrap();
+        assert_eq!(peer(&result), "127.0.0.1:3000");
+    }
+
+    #[test]
+    fn test_cidr_filter_blocks() {
+        let proxy = compile(
+            r#"
+            system { }
+            definitions {
+                modifiers {
+                    chain-filters "internal-only" {
+                        filter name="motya.request.upsert-header" key="X-Seen" value="1"
+                        filter name="motya.filters.block-cidr-range" addrs="10.0.0.0/8"
+                    }
+                }
+            }
+            services {
+                Api {
+                    listeners {
+                        "0.0.0.0:8080"
+                    }
+                    connectors {
+                        section "/api" as="prefix" {
+                            use-chain "internal-only"
+                            proxy "127.0.0.1:3001"
+                        }
+                        proxy "127.0.0.1:3000"
+                    }
+                }
+            }
+            "#,
+        );
+
+        let blocked = "10.1.2.3".parse().unwrap();
+        let result = simulate(&proxy, "GET", None, "/api/users", &[], blocked).unwrap();
+        assert!(
+            matches!(&result.decision, FilterDecision::Blocked(name) if name.contains("block-cidr-range")),
+            "{:?}",
+            result.decision
+        );
+
+        let allowed = "192.168.1.10".parse().unwrap();
+        let result = simulate(&proxy, "GET", None, "/api/users", &[], allowed).unwrap();
+        assert_eq!(result.decision, FilterDecision::Pass);
+
+        // the chain only applies under /api
+        let result = simulate(&proxy, "GET", None, "/index.html", &[], blocked).unwrap();
+        assert_eq!(result.decision, FilterDecision::Pass);
+    }
+}
@@ -0,0 +1,266 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use http::HeaderName;
+use pingora_http::RequestHeader;
+use xxhash_rust::xxh64::xxh64;
+
+use motya_config::common_types::routes::{QueryMatch, StickyKey};
+
+use crate::proxy::upstream_router::{UpstreamContextTrait, UpstreamRouter};
+
+/// A `route` that hands the requests under its path prefix to one of several routers.
+pub struct SplitRoute<TUpstream: UpstreamContextTrait> {
+    prefix: String,
+    selector: Selector<TUpstream>,
+    /// Query parameter a request must carry for the route to apply.
+    query: Option<QueryMatch>,
+    /// `request-timeout` of the requests it routes, over the one of their connector.
+    request_timeout: Option<Duration>,
+}
+
+enum Selector<TUpstream: UpstreamContextTrait> {
+    /// `split`: a share of the requests to each router.
+    Weighted {
+        sticky: Option<StickyKey>,
+        /// Routers with their percentage of the traffic; the weights sum to 100.
+        targets: Vec<(u8, Arc<UpstreamRouter<TUpstream>>)>,
+        counter: AtomicU64,
+    },
+    /// `match-header`: the router listed for the value of a header.
+    Header {
+        name: HeaderName,
+        cases: Vec<(String, Arc<UpstreamRouter<TUpstream>>)>,
+        default: Arc<UpstreamRouter<TUpstream>>,
+    },
+}
+
+impl<TUpstream: UpstreamContextTrait> SplitRoute<TUpstream> {
+    pub fn new(
+        prefix: String,
+        sticky: Option<StickyKey>,
+        targets: Vec<(u8, Arc<UpstreamRouter<TUpstream>>)>,
+    ) -> Self {
+        Self {
+            prefix,
+            selector: Selector::Weighted {
+                sticky,
+                targets,
+                counter: AtomicU64::new(0),
+            },
+            query: None,
+            request_timeout: None,
+        }
+    }
+
+    /// A route picking the router by the value of the `name` header, or `default` when
+    /// the header is missing or its value isn't listed.
+    pub fn by_header(
+        prefix: String,
+        name: HeaderName,
+        cases: Vec<(String, Arc<UpstreamRouter<TUpstream>>)>,
+        default: Arc<UpstreamRouter<TUpstream>>,
+    ) -> Self {
+        Self {
+            prefix,
+            selector: Selector::Header {
+                name,
+                cases,
+                default,
+            },
+            query: None,
+            request_timeout: None,
+        }
+    }
+
+    pub fn with_query(mut self, query: Option<QueryMatch>) -> Self {
+        self.query = query;
+        self
+    }
+
+    pub fn with_request_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.request_timeout = timeout;
+        self
+    }
+
+    /// Whether `path` is the prefix itself or lies below it.
+    pub fn matches(&self, path: &str) -> bool {
+        path.strip_prefix(self.prefix.as_str()).is_some_and(|rest| {
+            self.prefix.ends_with('/') || rest.is_empty() || rest.starts_with('/')
+        })
+    }
+
+    /// Whether the request `query` carries the parameter the route requires, if any.
+    pub fn matches_query(&self, query: Option<&str>) -> bool {
+        self.query
+            .as_ref()
+            .is_none_or(|expected| expected.matches(query))
+    }
+
+    pub fn query(&self) -> Option<&QueryMatch> {
+        self.query.as_ref()
+    }
+
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    pub fn request_timeout(&self) -> Option<Duration> {
+        self.request_timeout
+    }
+
+    /// Picks the router for one request.
+    ///
+    /// For a split, requests carrying the same sticky value always get the same router.
+    /// The others are spread over a fixed, evenly mixed sequence.
+    pub fn pick(&self, req: &RequestHeader) -> &Arc<UpstreamRouter<TUpstream>> {
+        match &self.selector {
+            Selector::Weighted {
+                sticky,
+                targets,
+                counter,
+            } => {
+                let hash = match sticky.as_ref().and_then(|key| sticky_value(req, key)) {
+                    Some(value) => xxh64(value.as_bytes(), 0),
+                    None => xxh64(&counter.fetch_add(1, Ordering::Relaxed).to_le_bytes(), 0),
+                };
+
+                pick_bucket(targets, (hash % 100) as u8)
+            }
+            Selector::Header {
+                name,
+                cases,
+                default,
+            } => {
+                let value = req.headers.get(name).and_then(|value| value.to_str().ok());
+                value
+                    .and_then(|value| cases.iter().find(|(case, _)| case == value))
+                    .map(|(_, router)| router)
+                    .unwrap_or(default)
+            }
+        }
+    }
+}
+
+fn pick_bucket<TUpstream: UpstreamContextTrait>(
+    targets: &[(u8, Arc<UpstreamRouter<TUpstream>>)],
+    bucket: u8,
+) -> &Arc<UpstreamRouter<TUpstream>> {
+    let mut upper = 0;
+
+    for (weight, router) in targets {
+        upper += *weight;
+        if bucket < upper {
+            return router;
+        }
+    }
+
+    &targets.last().expect("a split has at least one target").1
+}
+
+fn sticky_value<'a>(req: &'a RequestHeader, key: &StickyKey) -> Option<&'a str> {
+    match key {
+        StickyKey::Header(name) => req.headers.get(name)?.to_str().ok(),
+        StickyKey::Cookie(name) => req
+            .headers
+            .get_all(http::header::COOKIE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(';'))
+            .find_map(|pair| {
+                let (key, value) = pair.trim().split_once('=')?;
+                (key == name).then_some(value)
+            }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::upstream_router::tests::MockUpstreamContext;
+
+    fn split(sticky: Option<StickyKey>) -> SplitRoute<MockUpstreamContext> {
+        let stable = Arc::new(UpstreamRouter::build(vec![]).unwrap());
+        let canary = Arc::new(UpstreamRouter::build(vec![]).unwrap());
+
+        SplitRoute::new("/".to_string(), sticky, vec![(90, stable), (10, canary)])
+    }
+
+    fn is_canary(split: &SplitRoute<MockUpstreamContext>, req: &RequestHeader) -> bool {
+        let Selector::Weighted { targets, .. } = &split.selector else {
+            panic!("expected a split");
+        };
+        Arc::ptr_eq(split.pick(req), &targets[1].1)
+    }
+
+    #[test]
+    fn test_split_approximates_ratio() {
+        let split = split(None);
+        let req = RequestHeader::build("GET", b"/", None).unwrap();
+
+        let canary = (0..10_000).filter(|_| is_canary(&split, &req)).count();
+
+        assert!(
+            (800..=1200).contains(&canary),
+            "{canary} of 10000 went to canary"
+        );
+    }
+
+    #[test]
+    fn test_sticky_cookie_pins_client() {
+        let split = split(Some(StickyKey::Cookie("uid".to_string())));
+
+        let mut canary = 0;
+        for user in 0..1_000 {
+            let mut req = RequestHeader::build("GET", b"/", None).unwrap();
+            req.append_header("Cookie", format!("theme=dark; uid=user-{user}"))
+                .unwrap();
+
+            let first = is_canary(&split, &req);
+            assert!((0..5).all(|_| is_canary(&split, &req) == first));
+            canary += first as usize;
+        }
+
+        assert!(
+            (50..=150).contains(&canary),
+            "{canary} of 1000 went to canary"
+        );
+    }
+
+    #[test]
+    fn test_prefix_matching() {
+        let split = SplitRoute::<MockUpstreamContext>::new("/api".to_string(), None, vec![]);
+
+        assert!(split.matches("/api"));
+        assert!(split.matches("/api/users"));
+        assert!(!split.matches("/apix"));
+    }
+
+    #[test]
+    fn test_match_header() {
+        let beta = Arc::new(UpstreamRouter::build(vec![]).unwrap());
+        let stable = Arc::new(UpstreamRouter::build(vec![]).unwrap());
+        let route = SplitRoute::<MockUpstreamContext>::by_header(
+            "/".to_string(),
+            HeaderName::from_static("x-variant"),
+            vec![("beta".to_string(), beta.clone())],
+            stable.clone(),
+        );
+
+        let mut matched = RequestHeader::build("GET", b"/", None).unwrap();
+        matched.append_header("X-Variant", "beta").unwrap();
+        assert!(Arc::ptr_eq(route.pick(&matched), &beta));
+
+        let mut unmatched = RequestHeader::build("GET", b"/", None).unwrap();
+        unmatched.append_header("X-Variant", "gamma").unwrap();
+        assert!(Arc::ptr_eq(route.pick(&unmatched), &stable));
+
+        let missing = RequestHeader::build("GET", b"/", None).unwrap();
+        assert!(Arc::ptr_eq(route.pick(&missing), &stable));
+    }
+}
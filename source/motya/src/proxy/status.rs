@@ -0,0 +1,235 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use async_trait::async_trait;
+use http::{header, Response, StatusCode};
+use pingora::{
+    apps::http_app::{HttpServer, ServeHttp},
+    protocols::http::ServerSession,
+    services::listening::Service,
+};
+use serde_json::{json, Value};
+
+use motya_config::common_types::{connectors::UpstreamConfig, system_data::StatusConfig};
+
+use crate::proxy::{
+    connection_reuse::connection_reuse,
+    upstream_router::{UpstreamContext, UpstreamContextTrait},
+    SharedProxyState,
+};
+
+/// Requests in flight to each backend of a connector.
+#[derive(Debug, Default)]
+pub struct InFlight {
+    counts: Vec<(String, AtomicUsize)>,
+}
+
+/// Counts a request against its backend until it's dropped along with the request context.
+#[derive(Debug)]
+pub struct InFlightGuard {
+    in_flight: Arc<InFlight>,
+    index: usize,
+}
+
+impl InFlight {
+    /// Counters for the backends `upstream` may send requests to.
+    pub fn for_upstream(upstream: &UpstreamConfig) -> Self {
+        let addrs = match upstream {
+            UpstreamConfig::Service(peer) => vec![peer.peer_address],
+            UpstreamConfig::MultiServer(m) => m
+                .servers
+                .iter()
+                .map(|s| s.address)
+                .chain(m.pinned.iter().map(|s| s.address))
+                .collect(),
+            UpstreamConfig::Static(_) => vec![],
+        };
+
+        Self {
+            counts: addrs
+                .into_iter()
+                .map(|addr| (addr.to_string(), AtomicUsize::new(0)))
+                .collect(),
+        }
+    }
+
+    /// Starts counting a request to the backend at `addr`, `None` if it isn't one of ours.
+    pub fn enter(self: &Arc<Self>, addr: &str) -> Option<InFlightGuard> {
+        let index = self.counts.iter().position(|(known, _)| known == addr)?;
+        self.counts[index].1.fetch_add(1, Ordering::Relaxed);
+
+        Some(InFlightGuard {
+            in_flight: self.clone(),
+            index,
+        })
+    }
+
+    pub fn get(&self, addr: &str) -> usize {
+        self.counts
+            .iter()
+            .find(|(known, _)| known == addr)
+            .map_or(0, |(_, count)| count.load(Ordering::Relaxed))
+    }
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.in_flight.counts[self.index]
+            .1
+            .fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Serves the `status` endpoint: the backends of every proxy service as JSON, with their
+/// health, requests in flight and whether outlier detection has opened their circuit.
+///
+/// The connection reuse metrics are served next to it, in the Prometheus text format.
+pub struct StatusApp {
+    path: String,
+    metrics_path: String,
+    services: Vec<(String, SharedProxyState)>,
+}
+
+pub fn status_service(
+    config: &StatusConfig,
+    services: Vec<(String, SharedProxyState)>,
+) -> Box<dyn pingora::services::Service> {
+    let app = StatusApp {
+        path: config.path.path().to_string(),
+        metrics_path: config.metrics_path.path().to_string(),
+        services,
+    };
+
+    let mut service = Service::new("motya-status".to_string(), HttpServer::new_app(app));
+    service.add_tcp(&config.addr.to_string());
+    Box::new(service)
+}
+
+impl StatusApp {
+    fn report(&self) -> Value {
+        let services = self
+            .services
+            .iter()
+            .map(|(name, state)| {
+                // the current router, so the report follows configuration reloads
+                let router = state.load();
+                let connectors = router
+                    .all_upstreams()
+                    .filter(|upstream| !matches!(upstream.upstream, UpstreamConfig::Static(_)))
+                    .map(connector_report)
+                    .collect::<Vec<_>>();
+
+                json!({ "name": name, "connectors": connectors })
+            })
+            .collect::<Vec<_>>();
+
+        json!({ "services": services })
+    }
+}
+
+fn connector_report(upstream: &UpstreamContext) -> Value {
+    let backend = |addr: String, healthy: bool| {
+        let circuit_breaker = match upstream
+            .balancer
+            .as_ref()
+            .and_then(|b| b.outlier_detection.as_ref())
+        {
+            Some(outliers) if outliers.is_ejected(&addr) => "open",
+            Some(_) => "closed",
+            None => "disabled",
+        };
+
+        json!({
+            "address": addr,
+            "healthy": healthy,
+            "in_flight": upstream.in_flight.get(&addr),
+            "circuit_breaker": circuit_breaker,
+        })
+    };
+
+    let backends = match (&upstream.balancer, &upstream.upstream) {
+        (Some(balancer), _) => {
+            let backends = balancer.balancer_type.backends();
+            backends
+                .get_backend()
+                .iter()
+                .map(|b| backend(b.addr.to_string(), backends.ready(b)))
+                .collect::<Vec<_>>()
+        }
+        // without a balancer there are no health checks, the backend is always used
+        (None, UpstreamConfig::Service(peer)) => vec![backend(peer.peer_address.to_string(), true)],
+        (None, _) => vec![],
+    };
+
+    json!({
+        "path": upstream.get_prefix_path().path(),
+        "path_regex": upstream.get_path_regex().map(|regex| regex.as_str()),
+        "backends": backends,
+    })
+}
+
+#[async_trait]
+impl ServeHttp for StatusApp {
+    async fn response(&self, session: &mut ServerSession) -> Response<Vec<u8>> {
+        let path = session.req_header().uri.path();
+        let (status, content_type, body) = if path == self.path {
+            let report = self.report().to_string().into_bytes();
+            (StatusCode::OK, "application/json", report)
+        } else if path == self.metrics_path {
+            let metrics = connection_reuse().render_prometheus().into_bytes();
+            (StatusCode::OK, "text/plain; version=0.0.4", metrics)
+        } else {
+            (StatusCode::NOT_FOUND, "application/json", vec![])
+        };
+
+        Response::builder()
+            .status(status)
+            .header(header::CONTENT_TYPE, content_type)
+            .header(header::CONTENT_LENGTH, body.len())
+            .body(body)
+            .expect("status response is valid")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use motya_config::common_types::connectors::{
+        MultiServerUpstreamConfig, RouteMatcher, UpstreamServer, ALPN,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_in_flight_released_with_guard() {
+        let upstream = UpstreamConfig::MultiServer(MultiServerUpstreamConfig {
+            servers: ["10.0.0.1:80", "10.0.0.2:80"]
+                .into_iter()
+                .map(|addr| UpstreamServer {
+                    address: addr.parse().unwrap(),
+                    weight: 1,
+                })
+                .collect(),
+            pinned: vec![],
+            tls_sni: None,
+            alpn: ALPN::H1,
+            prefix_path: "/".parse().unwrap(),
+            target_path: "/".parse().unwrap(),
+            matcher: RouteMatcher::Prefix,
+            connect_timeout: None,
+        });
+        let in_flight = Arc::new(InFlight::for_upstream(&upstream));
+
+        let first = in_flight.enter("10.0.0.1:80").unwrap();
+        let second = in_flight.enter("10.0.0.1:80").unwrap();
+        assert_eq!(in_flight.get("10.0.0.1:80"), 2);
+        assert_eq!(in_flight.get("10.0.0.2:80"), 0);
+        assert!(in_flight.enter("10.0.0.9:80").is_none());
+
+        drop(first);
+        assert_eq!(in_flight.get("10.0.0.1:80"), 1);
+        drop(second);
+        assert_eq!(in_flight.get("10.0.0.1:80"), 0);
+    }
+}
@@ -0,0 +1,110 @@
+use std::net::SocketAddr;
+
+use pingora_proxy::Session;
+
+use motya_config::common_types::listeners::{ListenerKind, Listeners};
+
+use crate::proxy::concurrency::find_listener;
+
+/// Listeners with `tcp-nodelay`, whose accepted connections get Nagle's algorithm turned off.
+#[derive(Debug, Clone, Default)]
+pub struct TcpNoDelay {
+    listeners: Vec<(SocketAddr, ())>,
+}
+
+impl TcpNoDelay {
+    pub fn from_listeners(listeners: &Listeners) -> Self {
+        let listeners = listeners
+            .list_cfgs
+            .iter()
+            .filter(|cfg| cfg.tcp_nodelay)
+            .filter_map(|cfg| match &cfg.source {
+                ListenerKind::Tcp { addr, .. } => {
+                    let addr = addr
+                        .parse::<SocketAddr>()
+                        .expect("Listener address must be valid after parsing the configuration");
+                    Some((addr, ()))
+                }
+                ListenerKind::Uds(_) | ListenerKind::InheritedFd { .. } => None,
+            })
+            .collect();
+
+        Self { listeners }
+    }
+
+    /// Sets `TCP_NODELAY` on the connection, if the listener that accepted it asks for it.
+    pub fn apply(&self, session: &Session) {
+        let local_addr = session
+            .server_addr()
+            .and_then(|addr| addr.as_inet())
+            .copied();
+        if !local_addr.is_some_and(|addr| self.enabled_for(&addr)) {
+            return;
+        }
+
+        let Some(socket) = session
+            .digest()
+            .and_then(|digest| digest.socket_digest.as_ref())
+        else {
+            return;
+        };
+
+        if let Err(err) = set_nodelay(socket) {
+            tracing::debug!("Failed to set TCP_NODELAY on a downstream connection: {err}");
+        }
+    }
+
+    fn enabled_for(&self, local_addr: &SocketAddr) -> bool {
+        find_listener(&self.listeners, local_addr).is_some()
+    }
+}
+
+#[cfg(unix)]
+fn set_nodelay(socket: &pingora::protocols::SocketDigest) -> std::io::Result<()> {
+    use std::{
+        mem::ManuallyDrop,
+        net::TcpStream,
+        os::fd::{AsRawFd, FromRawFd},
+    };
+
+    // SAFETY: the descriptor belongs to the live downstream connection, and `ManuallyDrop`
+    // keeps this borrowed handle from closing it.
+    let stream = ManuallyDrop::new(unsafe { TcpStream::from_raw_fd(socket.as_raw_fd()) });
+    stream.set_nodelay(true)
+}
+
+#[cfg(not(unix))]
+fn set_nodelay(_socket: &pingora::protocols::SocketDigest) -> std::io::Result<()> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use motya_config::common_types::listeners::ListenerConfig;
+
+    use super::*;
+
+    fn listener(addr: &str, tcp_nodelay: bool) -> ListenerConfig {
+        ListenerConfig {
+            tcp_nodelay,
+            ..ListenerConfig::new(ListenerKind::Tcp {
+                addr: addr.to_string(),
+                tls: None,
+                offer_h2: false,
+            })
+        }
+    }
+
+    #[test]
+    fn test_nodelay_follows_listener() {
+        let nodelay = TcpNoDelay::from_listeners(&Listeners {
+            list_cfgs: vec![
+                listener("0.0.0.0:8080", true),
+                listener("0.0.0.0:9090", false),
+            ],
+        });
+
+        assert!(nodelay.enabled_for(&"10.0.0.5:8080".parse().unwrap()));
+        assert!(!nodelay.enabled_for(&"10.0.0.5:9090".parse().unwrap()));
+    }
+}
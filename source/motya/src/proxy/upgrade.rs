@@ -0,0 +1,100 @@
+use http::header::{CONNECTION, UPGRADE};
+use pingora_http::RequestHeader;
+
+/// Whether the request asks to switch protocols, like a WebSocket handshake: it names the
+/// new protocol in `Upgrade` and lists `upgrade` among its `Connection` options.
+///
+/// Once the upstream answers with a `101`, the connection carries that protocol in both
+/// directions until either side closes it.
+pub fn is_upgrade_request(header: &RequestHeader) -> bool {
+    header.headers.contains_key(UPGRADE) && connection_options(header).any(is_upgrade_option)
+}
+
+/// Forwards an upgrade request of an `allow-upgrades #false` connector as a plain one, so
+/// the upstream answers it without switching protocols.
+pub fn refuse_upgrade(header: &mut RequestHeader) {
+    let options = connection_options(header)
+        .filter(|option| !is_upgrade_option(option))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    header.remove_header(&UPGRADE);
+    header.remove_header(&CONNECTION);
+    if !options.is_empty() {
+        if let Err(e) = header.insert_header(CONNECTION, options) {
+            tracing::warn!("Failed to restore Connection on a refused upgrade: {e}");
+        }
+    }
+}
+
+fn connection_options(header: &RequestHeader) -> impl Iterator<Item = &str> {
+    header
+        .headers
+        .get_all(CONNECTION)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|option| !option.is_empty())
+}
+
+fn is_upgrade_option(option: &str) -> bool {
+    option.eq_ignore_ascii_case("upgrade")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(headers: &[(&'static str, &'static str)]) -> RequestHeader {
+        let mut req = RequestHeader::build("GET", b"/chat", None).unwrap();
+        for (name, value) in headers {
+            req.append_header(*name, *value).unwrap();
+        }
+        req
+    }
+
+    #[test]
+    fn test_upgrade_detected() {
+        let websocket = request(&[("Connection", "Upgrade"), ("Upgrade", "websocket")]);
+        assert!(is_upgrade_request(&websocket));
+
+        // browsers send both options, in any case and over several headers
+        let firefox = request(&[
+            ("Connection", "keep-alive, Upgrade"),
+            ("Upgrade", "websocket"),
+        ]);
+        assert!(is_upgrade_request(&firefox));
+        let split = request(&[
+            ("Connection", "keep-alive"),
+            ("Connection", "upgrade"),
+            ("Upgrade", "websocket"),
+        ]);
+        assert!(is_upgrade_request(&split));
+
+        assert!(!is_upgrade_request(&request(&[("Upgrade", "websocket")])));
+        assert!(!is_upgrade_request(&request(&[("Connection", "Upgrade")])));
+        assert!(!is_upgrade_request(&request(&[
+            ("Connection", "keep-alive"),
+            ("Upgrade", "websocket"),
+        ])));
+    }
+
+    #[test]
+    fn test_refused_upgrade_is_plain() {
+        let mut req = request(&[
+            ("Connection", "keep-alive, Upgrade"),
+            ("Upgrade", "websocket"),
+        ]);
+
+        refuse_upgrade(&mut req);
+
+        assert!(!is_upgrade_request(&req));
+        assert_eq!(req.headers.get(UPGRADE), None);
+        assert_eq!(req.headers[CONNECTION], "keep-alive");
+
+        let mut req = request(&[("Connection", "Upgrade"), ("Upgrade", "websocket")]);
+        refuse_upgrade(&mut req);
+        assert_eq!(req.headers.get(CONNECTION), None);
+    }
+}
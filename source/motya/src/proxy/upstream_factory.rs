@@ -1,10 +1,16 @@
-use std::collections::BTreeSet;
+use std::{
+    collections::BTreeSet,
+    net::SocketAddr,
+    sync::{Arc, Weak},
+    time::Duration,
+};
 
-use futures_util::FutureExt;
-use miette::{miette, Result};
+use futures_util::{future::try_join_all, FutureExt};
+use miette::{miette, IntoDiagnostic, Result};
 use pingora::prelude::HttpPeer;
 use pingora_load_balancing::{
     discovery,
+    health_check::{HealthObserveCallback, TcpHealthCheck},
     prelude::RoundRobin,
     selection::{consistent::KetamaHashing, FNVHash, Random},
     Backend, Backends, LoadBalancer,
@@ -12,16 +18,31 @@ use pingora_load_balancing::{
 
 use motya_config::{
     common_types::{
-        connectors::{MultiServerUpstreamConfig, UpstreamConfig, UpstreamContextConfig},
+        connectors::{
+            MultiServerUpstreamConfig, UpstreamConfig, UpstreamContextConfig, UpstreamHttpVersion,
+            UpstreamProtocol,
+        },
         definitions::Modificator,
+        routes::{RouteSelector, SplitRouteConfig},
     },
-    internal::{SelectionKind, UpstreamOptions},
+    internal::{HealthCheckKind, SelectionKind, UpstreamOptions},
 };
 
 use crate::proxy::{
-    balancer::key_selector::{Balancer, BalancerType, KeySelector},
+    balancer::{
+        key_selector::{Balancer, BalancerType, KeySelector},
+        outlier::OutlierDetector,
+        slow_start::{SlowStart, SlowStartObserver},
+        weighted_random::WeightedRandom,
+    },
+    cache::ResponseCache,
     filters::chain_resolver::ChainResolver,
-    upstream_router::UpstreamContext,
+    grpc::negotiate_h2,
+    http_version::negotiate_http_version,
+    rate_limit::RateLimiter,
+    split::SplitRoute,
+    status::InFlight,
+    upstream_router::{UpstreamContext, UpstreamRouter},
 };
 
 #[derive(Clone)]
@@ -39,12 +60,28 @@ impl UpstreamFactory {
             UpstreamConfig::Static(_) | UpstreamConfig::Service(_) => None,
             UpstreamConfig::MultiServer(m) => {
                 if let Some(lb_options) = config.lb_options {
-                    setup_balancer(lb_options, m)?
+                    setup_balancer(lb_options, m, config.protocol, config.upstream_http_version)?
                 } else {
                     None
                 }
             }
         };
+        let pinned = match &config.upstream {
+            UpstreamConfig::MultiServer(m) => m
+                .pinned
+                .iter()
+                .map(|server| {
+                    let peer = pool_peer(
+                        &server.address,
+                        m,
+                        config.protocol,
+                        config.upstream_http_version,
+                    );
+                    (server.when.clone(), peer)
+                })
+                .collect(),
+            _ => vec![],
+        };
 
         let mut chains = Vec::new();
 
@@ -58,18 +95,130 @@ impl UpstreamFactory {
         }
 
         let ctx = UpstreamContext {
+            in_flight: Arc::new(InFlight::for_upstream(&config.upstream)),
             balancer,
+            pinned,
             upstream: config.upstream,
             chains,
+            request_headers: config.request_headers,
+            response_headers: config.response_headers,
+            cache: config
+                .cache
+                .map(|cache| ResponseCache::new(cache).map(Arc::new))
+                .transpose()
+                .map_err(|err| miette!("{err}"))?,
+            path_regex: config.path_regex.map(|regex| regex.0),
+            buffer_request_body: config.buffer_request_body,
+            buffer_response_body: config.buffer_response_body,
+            buffer_body_limit: config.buffer_body_limit,
+            allow_upgrades: config.allow_upgrades,
+            upstream_accept_encoding: config.upstream_accept_encoding,
+            host_header: config.host_header,
+            upstream_method: config.upstream_method,
+            response_status_map: config.response_status_map,
+            protocol: config.protocol,
+            upstream_http_version: config.upstream_http_version,
+            latency_budget: config.latency_budget,
+            request_timeout: config.request_timeout,
+            rate_limit: config
+                .rate_limit
+                .map(RateLimiter::new)
+                .transpose()
+                .map_err(|err| miette!("{err}"))?,
+            require_tls: config.require_tls,
+            forwarded_headers: config.forwarded_headers,
         };
 
         Ok(ctx)
     }
+
+    /// Builds the router of a service, along with the routers its `route` splits pick from.
+    pub async fn create_router(
+        &self,
+        upstreams: Vec<UpstreamContextConfig>,
+        routes: Vec<SplitRouteConfig>,
+    ) -> Result<UpstreamRouter<UpstreamContext>> {
+        let mut splits = Vec::with_capacity(routes.len());
+
+        for route in routes {
+            let split = match route.selector {
+                RouteSelector::Split { targets, sticky } => {
+                    let mut routers = Vec::with_capacity(targets.len());
+                    for target in targets {
+                        let router = self
+                            .create_plain_router(target.connectors.upstreams)
+                            .await?;
+                        routers.push((target.weight, Arc::new(router)));
+                    }
+                    SplitRoute::new(route.path, sticky, routers)
+                }
+                RouteSelector::MatchHeader(header_match) => {
+                    let mut cases = Vec::with_capacity(header_match.cases.len());
+                    for (value, target) in header_match.cases {
+                        let router = self
+                            .create_plain_router(target.connectors.upstreams)
+                            .await?;
+                        cases.push((value, Arc::new(router)));
+                    }
+                    let default = self
+                        .create_plain_router(header_match.default.connectors.upstreams)
+                        .await?;
+                    SplitRoute::by_header(route.path, header_match.header, cases, Arc::new(default))
+                }
+            };
+            splits.push(
+                split
+                    .with_query(route.query)
+                    .with_request_timeout(route.request_timeout),
+            );
+        }
+
+        Ok(self
+            .create_plain_router(upstreams)
+            .await?
+            .with_splits(splits))
+    }
+
+    async fn create_plain_router(
+        &self,
+        upstreams: Vec<UpstreamContextConfig>,
+    ) -> Result<UpstreamRouter<UpstreamContext>> {
+        let contexts =
+            try_join_all(upstreams.into_iter().map(|cfg| self.create_context(cfg))).await?;
+
+        UpstreamRouter::build(contexts).into_diagnostic()
+    }
+}
+
+/// The peer for a server of the pool `m`, with the pool's TLS and timeout settings.
+fn pool_peer(
+    addr: &SocketAddr,
+    m: &MultiServerUpstreamConfig,
+    protocol: UpstreamProtocol,
+    http_version: Option<UpstreamHttpVersion>,
+) -> HttpPeer {
+    let mut peer = HttpPeer::new(
+        addr,
+        //sni is https only
+        //https://github.com/cloudflare/pingora/blob/main/docs/user_guide/peer.md
+        m.tls_sni.is_some(),
+        m.tls_sni.clone().unwrap_or("".to_string()),
+    );
+    if let Some(version) = http_version {
+        negotiate_http_version(&mut peer, version);
+    }
+    if protocol == UpstreamProtocol::Grpc {
+        negotiate_h2(&mut peer);
+    }
+    peer.options.connection_timeout = m.connect_timeout;
+    peer
 }
 
 fn setup_balancer(
     lb_options: UpstreamOptions,
     m: &MultiServerUpstreamConfig,
+    protocol: UpstreamProtocol,
+    http_version: Option<UpstreamHttpVersion>,
 ) -> Result<Option<Balancer>, miette::Error> {
     let addrs = m.servers.iter().map(|s| (&s.address, s.weight));
     let mut backends = addrs
@@ -80,41 +229,53 @@ fn setup_balancer(
         })
         .collect::<Vec<_>>();
     for (backend, (addr, _)) in backends.iter_mut().zip(addrs) {
-        assert!(backend
-            .ext
-            .insert(HttpPeer::new(
-                addr,
-                //sni is https only
-                //https://github.com/cloudflare/pingora/blob/main/docs/user_guide/peer.md
-                m.tls_sni.is_some(),
-                m.tls_sni.clone().unwrap_or("".to_string())
-            ))
-            .is_none());
+        let peer = pool_peer(addr, m, protocol, http_version);
+        assert!(backend.ext.insert(peer).is_none());
     }
     let disco = discovery::Static::new(BTreeSet::from_iter(backends));
+    let slow_start = lb_options
+        .slow_start
+        .map(|window| Arc::new(SlowStart::new(window)));
+
+    let mut pool = Backends::new(disco);
+    if let HealthCheckKind::Tcp { .. } = lb_options.health_checks {
+        let mut health_check = TcpHealthCheck::new();
+        health_check.health_changed_callback = slow_start
+            .clone()
+            .map(|slow_start| Box::new(SlowStartObserver(slow_start)) as HealthObserveCallback);
+        pool.set_health_check(health_check);
+    }
+
     let balancer_type = match lb_options.selection {
         SelectionKind::FvnHash => {
-            BalancerType::FNVHash(LoadBalancer::<FNVHash>::from_backends(Backends::new(disco)))
+            BalancerType::FNVHash(LoadBalancer::<FNVHash>::from_backends(pool))
+        }
+        SelectionKind::RoundRobin => {
+            BalancerType::RoundRobin(LoadBalancer::<RoundRobin>::from_backends(pool))
+        }
+        SelectionKind::Random => BalancerType::Random(LoadBalancer::<Random>::from_backends(pool)),
+        SelectionKind::KetamaHashing => {
+            BalancerType::KetamaHashing(LoadBalancer::<KetamaHashing>::from_backends(pool))
         }
-        SelectionKind::RoundRobin => BalancerType::RoundRobin(
-            LoadBalancer::<RoundRobin>::from_backends(Backends::new(disco)),
-        ),
-        SelectionKind::Random => {
-            BalancerType::Random(LoadBalancer::<Random>::from_backends(Backends::new(disco)))
+        SelectionKind::WeightedRandom => {
+            BalancerType::WeightedRandom(LoadBalancer::<WeightedRandom>::from_backends(pool))
         }
-        SelectionKind::KetamaHashing => BalancerType::KetamaHashing(
-            LoadBalancer::<KetamaHashing>::from_backends(Backends::new(disco)),
-        ),
     };
     match &balancer_type {
         BalancerType::FNVHash(b) => b.update().now_or_never(),
         BalancerType::KetamaHashing(b) => b.update().now_or_never(),
         BalancerType::Random(b) => b.update().now_or_never(),
         BalancerType::RoundRobin(b) => b.update().now_or_never(),
+        BalancerType::WeightedRandom(b) => b.update().now_or_never(),
     }
     .expect("static should not block")
     .expect("static should not error");
 
+    let balancer_type = Arc::new(balancer_type);
+    if let HealthCheckKind::Tcp { interval } = lb_options.health_checks {
+        spawn_health_checks(Arc::downgrade(&balancer_type), interval);
+    }
+
     Ok(Some(Balancer {
         selector: lb_options
             .template
@@ -122,5 +283,165 @@ fn setup_balancer(
             .transpose()
             .map_err(|err| miette!("{err}"))?,
         balancer_type,
+        slow_start,
+        outlier_detection: lb_options.outlier_detection.map(OutlierDetector::new),
     }))
 }
+
+/// Probes the servers of a pool every `interval`, until the balancer is dropped by a reload.
+///
+/// Each transition is reported to the health check's observer, which starts the slow-start
+/// ramp of a recovered server.
+fn spawn_health_checks(balancer_type: Weak<BalancerType>, interval: Duration) {
+    tokio::spawn(async move {
+        // the servers start out healthy, the first probe is one interval away
+        let start = tokio::time::Instant::now() + interval;
+        let mut ticker = tokio::time::interval_at(start, interval);
+        loop {
+            ticker.tick().await;
+            let Some(balancer_type) = balancer_type.upgrade() else {
+                return;
+            };
+            balancer_type.backends().run_health_check(true).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use pingora_http::RequestHeader;
+    use tokio::sync::Mutex;
+
+    use motya_config::{
+        common_types::definitions_table::DefinitionsTable,
+        kdl::{compiler::ConfigCompiler, fs_loader::read_piped},
+    };
+
+    use super::*;
+    use crate::proxy::{
+        context::{ContextInfo, SessionInfo},
+        filters::registry::FilterRegistry,
+    };
+
+    #[tokio::test]
+    async fn test_pinned_server_takes_matching_requests() {
+        let kdl_input = r#"
+            system { }
+            services {
+                Api {
+                    listeners { "0.0.0.0:8080"; }
+                    connectors {
+                        load-balance { selection "RoundRobin"; }
+                        proxy {
+                            server "127.0.0.1:3000"
+                            server "127.0.0.1:3001"
+                            server "127.0.0.1:3009" when="header X-Debug exists"
+                        }
+                    }
+                }
+            }
+        "#;
+        let docs = read_piped(kdl_input.as_bytes(), None).unwrap();
+        let mut config = ConfigCompiler::new(docs)
+            .compile(&mut DefinitionsTable::new_with_global())
+            .expect("Should compile");
+        let proxy = config.basic_proxies.remove(0);
+
+        let registry = Arc::new(Mutex::new(FilterRegistry::default()));
+        let resolver = ChainResolver::new(DefinitionsTable::default(), registry)
+            .await
+            .unwrap();
+        let router = UpstreamFactory::new(resolver)
+            .create_router(proxy.connectors.upstreams, vec![])
+            .await
+            .unwrap();
+
+        let pick = |req: &RequestHeader| {
+            let peer = router
+                .pick_peer(
+                    &mut ContextInfo {},
+                    &mut SessionInfo {
+                        headers: req,
+                        client_addr: None,
+                        path: req.uri.path_and_query().unwrap(),
+                    },
+                )
+                .unwrap()
+                .expect("Should pick a peer");
+            peer.address().to_string()
+        };
+
+        let mut debug = RequestHeader::build("GET", b"/api/users", None).unwrap();
+        debug.insert_header("X-Debug", "1").unwrap();
+        assert_eq!(pick(&debug), "127.0.0.1:3009");
+
+        let plain = RequestHeader::build("GET", b"/api/users", None).unwrap();
+        for _ in 0..4 {
+            let picked = pick(&plain);
+            assert!(
+                picked == "127.0.0.1:3000" || picked == "127.0.0.1:3001",
+                "{picked}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_health_flip_starts_slow_start() {
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let kdl_input = format!(
+            r#"
+            system {{ }}
+            services {{
+                Api {{
+                    listeners {{ "0.0.0.0:8080"; }}
+                    connectors {{
+                        load-balance {{
+                            selection "RoundRobin"
+                            health-check "TCP" interval="1h"
+                            slow-start "30s"
+                        }}
+                        proxy {{
+                            server "{addr}"
+                        }}
+                    }}
+                }}
+            }}
+        "#
+        );
+        let docs = read_piped(kdl_input.as_bytes(), None).unwrap();
+        let mut config = ConfigCompiler::new(docs)
+            .compile(&mut DefinitionsTable::new_with_global())
+            .expect("Should compile");
+        let proxy = config.basic_proxies.remove(0);
+
+        let registry = Arc::new(Mutex::new(FilterRegistry::default()));
+        let resolver = ChainResolver::new(DefinitionsTable::default(), registry)
+            .await
+            .unwrap();
+        let router = UpstreamFactory::new(resolver)
+            .create_router(proxy.connectors.upstreams, vec![])
+            .await
+            .unwrap();
+
+        let balancer = router.all_upstreams().next().unwrap().balancer.as_ref();
+        let balancer = balancer.expect("Should have a balancer");
+        let slow_start = balancer.slow_start.as_ref().expect("Should slow-start");
+        let backends = balancer.balancer_type.backends();
+        let backend = Backend::new(&addr.to_string()).unwrap();
+
+        // nothing listens on the server yet
+        backends.run_health_check(false).await;
+        assert!(!backends.ready(&backend));
+        assert!((0..100).all(|_| slow_start.accept(&backend)));
+
+        let _listener = tokio::net::TcpListener::bind(addr).await.unwrap();
+        backends.run_health_check(false).await;
+        assert!(backends.ready(&backend));
+
+        let accepted = (0..1000).filter(|_| slow_start.accept(&backend)).count();
+        assert!(accepted < 10, "{accepted}");
+    }
+}
@@ -1,18 +1,56 @@
-use http::uri::PathAndQuery;
+use std::{sync::Arc, time::Duration};
+
+use http::{uri::PathAndQuery, Method};
 use matchit::{InsertError, Router};
 use pingora::{prelude::HttpPeer, ErrorType};
+use pingora_http::RequestHeader;
+use regex::Regex;
 
 use crate::proxy::{
     balancer::key_selector::Balancer,
+    cache::ResponseCache,
     context::{ContextInfo, SessionInfo},
     filters::chain_resolver::RuntimeChain,
+    grpc::negotiate_h2,
+    headers::request_host,
+    http_version::negotiate_http_version,
+    rate_limit::RateLimiter,
+    split::SplitRoute,
+    status::InFlight,
+};
+use motya_config::common_types::{
+    condition::{Condition, RequestFacts},
+    connectors::{RequireTls, RouteMatcher, UpstreamConfig, UpstreamHttpVersion, UpstreamProtocol},
+    headers::{ForwardedHeaders, HeaderRule, HeaderTemplate, UpstreamAcceptEncoding, UpstreamHost},
+    status_map::StatusMap,
 };
-use motya_config::common_types::connectors::{RouteMatcher, UpstreamConfig};
 
 pub struct UpstreamContext {
     pub upstream: UpstreamConfig,
     pub chains: Vec<RuntimeChain>,
     pub balancer: Option<Balancer>,
+    /// Servers of the pool with a `when` clause, each with the peer it's reached at.
+    pub pinned: Vec<(Condition, HttpPeer)>,
+    pub request_headers: Vec<HeaderRule<HeaderTemplate>>,
+    pub response_headers: Vec<HeaderRule>,
+    pub cache: Option<Arc<ResponseCache>>,
+    pub path_regex: Option<Regex>,
+    pub buffer_request_body: bool,
+    pub buffer_response_body: bool,
+    pub buffer_body_limit: usize,
+    pub allow_upgrades: bool,
+    pub upstream_accept_encoding: UpstreamAcceptEncoding,
+    pub host_header: UpstreamHost,
+    pub upstream_method: Option<Method>,
+    pub response_status_map: StatusMap,
+    pub protocol: UpstreamProtocol,
+    pub upstream_http_version: Option<UpstreamHttpVersion>,
+    pub latency_budget: Option<Duration>,
+    pub request_timeout: Option<Duration>,
+    pub rate_limit: Option<RateLimiter>,
+    pub require_tls: RequireTls,
+    pub forwarded_headers: ForwardedHeaders,
+    pub in_flight: Arc<InFlight>,
 }
 
 pub trait UpstreamContextTrait {
@@ -20,22 +58,42 @@ pub trait UpstreamContextTrait {
     fn get_route_type(&self) -> RouteMatcher;
     fn get_balancer(&self) -> Option<&Balancer>;
     fn get_peer(&self) -> Option<HttpPeer>;
+    /// The first server whose `when` clause matches `req`, taking it ahead of the balancer.
+    fn get_pinned_peer(&self, req: &RequestHeader) -> Option<HttpPeer>;
+    fn get_path_regex(&self) -> Option<&Regex>;
+    fn get_request_timeout(&self) -> Option<Duration>;
 }
 
+/// Routes requests by path.
+///
+/// Exact routes take precedence, then `path-regex` routes in declaration order,
+/// then prefix routes.
 pub struct UpstreamRouter<TUpstream: UpstreamContextTrait> {
-    pub router: Router<TUpstream>,
+    /// Index into `upstreams` of each path route.
+    pub router: Router<usize>,
+    pub upstreams: Vec<TUpstream>,
+    pub regex_routes: Vec<TUpstream>,
+    /// `route` splits, each choosing the router a whole request is handled by.
+    pub splits: Vec<SplitRoute<TUpstream>>,
 }
 
 impl<TUpstream: UpstreamContextTrait> UpstreamRouter<TUpstream> {
     pub fn build(paths: Vec<TUpstream>) -> Result<Self, InsertError> {
         let mut router = Router::new();
+        let mut upstreams = Vec::new();
+        let mut regex_routes = Vec::new();
 
         for item in paths {
+            if item.get_path_regex().is_some() {
+                regex_routes.push(item);
+                continue;
+            }
+
             let raw_path = item.get_prefix_path().path().to_string();
 
             match item.get_route_type() {
                 RouteMatcher::Exact => {
-                    router.insert(raw_path, item)?;
+                    router.insert(raw_path, upstreams.len())?;
                 }
                 RouteMatcher::Prefix => {
                     let clean_path = raw_path.trim_end_matches('/');
@@ -46,12 +104,55 @@ impl<TUpstream: UpstreamContextTrait> UpstreamRouter<TUpstream> {
                         format!("{}/{{*catch_all}}", clean_path)
                     };
 
-                    router.insert(wildcard_path, item)?;
+                    router.insert(wildcard_path, upstreams.len())?;
                 }
             }
+            upstreams.push(item);
         }
 
-        Ok(Self { router })
+        Ok(Self {
+            router,
+            upstreams,
+            regex_routes,
+            splits: Vec::new(),
+        })
+    }
+
+    pub fn with_splits(mut self, splits: Vec<SplitRoute<TUpstream>>) -> Self {
+        self.splits = splits;
+        self
+    }
+
+    /// The router handling `req`, the one picked by the `route` covering its path if there
+    /// is one, along with the `request-timeout` the request gets: the route's own, or else
+    /// the one of its connector.
+    pub fn route(self: &Arc<Self>, req: &RequestHeader) -> (Arc<Self>, Option<Duration>) {
+        let path = req.uri.path();
+        let (router, route_timeout) = match self.split_for(req) {
+            Some(split) => (split.pick(req).clone(), split.request_timeout()),
+            None => (self.clone(), None),
+        };
+
+        let timeout =
+            route_timeout.or_else(|| router.get_upstream_by_path(path)?.get_request_timeout());
+        (router, timeout)
+    }
+
+    /// The split with the longest prefix covering the path of `req` whose `query`, if it
+    /// has one, the request satisfies. Between splits of the same prefix, one with a
+    /// `query` is preferred.
+    pub fn split_for(&self, req: &RequestHeader) -> Option<&SplitRoute<TUpstream>> {
+        let (path, query) = (req.uri.path(), req.uri.query());
+
+        self.splits
+            .iter()
+            .filter(|split| split.matches(path) && split.matches_query(query))
+            .max_by_key(|split| (split.prefix().len(), split.query().is_some()))
+    }
+
+    /// Every upstream of this router, path routes first, leaving out those of `route` splits.
+    pub fn all_upstreams(&self) -> impl Iterator<Item = &TUpstream> {
+        self.upstreams.iter().chain(&self.regex_routes)
     }
 
     pub fn pick_peer(
@@ -63,6 +164,10 @@ impl<TUpstream: UpstreamContextTrait> UpstreamRouter<TUpstream> {
             return Ok(None);
         };
 
+        if let Some(peer) = upstream.get_pinned_peer(session.headers) {
+            return Ok(Some(peer));
+        }
+
         if let Some(balancer) = upstream.get_balancer() {
             let backend = balancer.select_backend(session);
 
@@ -86,7 +191,22 @@ impl<TUpstream: UpstreamContextTrait> UpstreamRouter<TUpstream> {
     }
 
     pub fn get_upstream_by_path(&self, path: &str) -> Option<&TUpstream> {
-        self.router.at(path).ok().map(|v| v.value)
+        let matched = self.router.at(path).ok().map(|v| &self.upstreams[*v.value]);
+
+        if let Some(upstream) = matched {
+            if upstream.get_route_type() == RouteMatcher::Exact {
+                return Some(upstream);
+            }
+        }
+
+        self.regex_routes
+            .iter()
+            .find(|upstream| {
+                upstream
+                    .get_path_regex()
+                    .is_some_and(|regex| regex.is_match(path))
+            })
+            .or(matched)
     }
 }
 
@@ -116,12 +236,49 @@ impl UpstreamContextTrait for UpstreamContext {
     // MultiServer - processing is delegated to the load balancer.
     fn get_peer(&self) -> Option<HttpPeer> {
         match &self.upstream {
+            UpstreamConfig::Service(s) if self.protocol == UpstreamProtocol::Grpc => {
+                let mut peer = HttpPeer::new(s.peer_address, true, s.sni.clone());
+                negotiate_h2(&mut peer);
+                peer.options.connection_timeout = s.connect_timeout;
+                Some(peer)
+            }
             UpstreamConfig::Service(s) => {
-                Some(HttpPeer::new(s.peer_address, false, "".to_string()))
+                let mut peer = HttpPeer::new(s.peer_address, s.tls, s.sni.clone());
+                if let Some(version) = self.upstream_http_version {
+                    negotiate_http_version(&mut peer, version);
+                }
+                peer.options.connection_timeout = s.connect_timeout;
+                Some(peer)
             }
             _ => None,
         }
     }
+
+    fn get_pinned_peer(&self, req: &RequestHeader) -> Option<HttpPeer> {
+        if self.pinned.is_empty() {
+            return None;
+        }
+
+        let host = request_host(req);
+        let facts = RequestFacts {
+            method: req.method.as_str(),
+            path: req.uri.path(),
+            host: host.as_deref(),
+            headers: &req.headers,
+        };
+        self.pinned
+            .iter()
+            .find(|(when, _)| when.matches(&facts))
+            .map(|(_, peer)| peer.clone())
+    }
+
+    fn get_path_regex(&self) -> Option<&Regex> {
+        self.path_regex.as_ref()
+    }
+
+    fn get_request_timeout(&self) -> Option<Duration> {
+        self.request_timeout
+    }
 }
 
 #[cfg(test)]
@@ -132,6 +289,8 @@ pub mod tests {
         pub prefix: PathAndQuery,
         pub matcher: RouteMatcher,
         pub peer: HttpPeer,
+        pub path_regex: Option<Regex>,
+        pub request_timeout: Option<Duration>,
     }
 
     impl UpstreamContextTrait for MockUpstreamContext {
@@ -149,6 +308,15 @@ pub mod tests {
         fn get_peer(&self) -> Option<HttpPeer> {
             Some(self.peer.clone())
         }
+        fn get_pinned_peer(&self, _req: &RequestHeader) -> Option<HttpPeer> {
+            None
+        }
+        fn get_path_regex(&self) -> Option<&Regex> {
+            self.path_regex.as_ref()
+        }
+        fn get_request_timeout(&self) -> Option<Duration> {
+            self.request_timeout
+        }
     }
 
     fn mock_context(path: &str, matcher: RouteMatcher) -> MockUpstreamContext {
@@ -156,6 +324,15 @@ pub mod tests {
             prefix: path.parse().unwrap(),
             matcher,
             peer: HttpPeer::new("0.0.0.0:0", false, "".to_string()),
+            path_regex: None,
+            request_timeout: None,
+        }
+    }
+
+    fn regex_context(pattern: &str) -> MockUpstreamContext {
+        MockUpstreamContext {
+            path_regex: Some(Regex::new(pattern).unwrap()),
+            ..mock_context("/", RouteMatcher::Prefix)
         }
     }
 
@@ -197,4 +374,92 @@ pub mod tests {
         let elem = router.get_upstream_by_path("/custom/bar").unwrap();
         assert_eq!(elem.get_prefix_path(), "/custom/{*foo}");
     }
+
+    #[test]
+    fn test_regex_routes_precedence() {
+        let paths = vec![
+            mock_context("/users/admin", RouteMatcher::Exact),
+            regex_context(r"^/users/\d+$"),
+            mock_context("/", RouteMatcher::Prefix),
+        ];
+        let router = UpstreamRouter::build(paths).expect("Router build failed");
+
+        let elem = router.get_upstream_by_path("/users/42").unwrap();
+        assert!(elem.get_path_regex().is_some());
+
+        let elem = router.get_upstream_by_path("/users/admin").unwrap();
+        assert_eq!(elem.get_prefix_path(), "/users/admin");
+
+        let elem = router.get_upstream_by_path("/users/me").unwrap();
+        assert_eq!(elem.get_prefix_path(), "/");
+    }
+
+    #[test]
+    fn test_route_request_timeout() {
+        let slow = UpstreamRouter::build(vec![MockUpstreamContext {
+            request_timeout: Some(Duration::from_secs(5)),
+            ..mock_context("/reports", RouteMatcher::Prefix)
+        }])
+        .unwrap();
+        let split = SplitRoute::new("/reports".to_string(), None, vec![(100, Arc::new(slow))])
+            .with_request_timeout(Some(Duration::from_secs(60)));
+
+        let router = Arc::new(
+            UpstreamRouter::build(vec![MockUpstreamContext {
+                request_timeout: Some(Duration::from_secs(5)),
+                ..mock_context("/", RouteMatcher::Prefix)
+            }])
+            .unwrap()
+            .with_splits(vec![split]),
+        );
+
+        // the route's timeout wins over the connector default
+        let req = RequestHeader::build("GET", b"/reports/yearly", None).unwrap();
+        let (picked, timeout) = router.route(&req);
+        assert!(!Arc::ptr_eq(&picked, &router));
+        assert_eq!(timeout, Some(Duration::from_secs(60)));
+
+        let req = RequestHeader::build("GET", b"/api/users", None).unwrap();
+        let (picked, timeout) = router.route(&req);
+        assert!(Arc::ptr_eq(&picked, &router));
+        assert_eq!(timeout, Some(Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn test_split_query_match() {
+        let split = |query: Option<&str>| {
+            let router = Arc::new(UpstreamRouter::build(vec![]).unwrap());
+            SplitRoute::new("/api".to_string(), None, vec![(100, router)])
+                .with_query(query.map(|query| query.parse().unwrap()))
+        };
+        let router = UpstreamRouter::<MockUpstreamContext>::build(vec![])
+            .unwrap()
+            .with_splits(vec![
+                split(None),
+                split(Some("version=2")),
+                split(Some("beta")),
+            ]);
+        let picked = |uri: &str| {
+            let req = RequestHeader::build("GET", uri.as_bytes(), None).unwrap();
+            router
+                .split_for(&req)
+                .map(|split| split.query().map(|query| query.name.clone()))
+        };
+
+        // presence only
+        assert_eq!(picked("/api/users?beta"), Some(Some("beta".to_string())));
+        // value, compared decoded
+        assert_eq!(
+            picked("/api/users?version=2"),
+            Some(Some("version".to_string()))
+        );
+        assert_eq!(
+            picked("/api/users?version=%32"),
+            Some(Some("version".to_string()))
+        );
+        // another value falls through to the route without a query
+        assert_eq!(picked("/api/users?version=3"), Some(None));
+        assert_eq!(picked("/api/users"), Some(None));
+        assert_eq!(picked("/other?beta"), None);
+    }
 }
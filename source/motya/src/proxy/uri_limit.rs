@@ -0,0 +1,102 @@
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
+
+use pingora_proxy::Session;
+
+use motya_config::common_types::listeners::{ListenerKind, Listeners, DEFAULT_MAX_URI_LENGTH};
+
+use crate::proxy::concurrency::find_listener;
+
+/// Per-listener `max-uri-length` settings.
+#[derive(Debug, Clone, Default)]
+pub struct UriLimits {
+    tcp: Vec<(SocketAddr, usize)>,
+    uds: Vec<(PathBuf, usize)>,
+}
+
+impl UriLimits {
+    pub fn from_listeners(listeners: &Listeners) -> Self {
+        let mut limits = Self::default();
+
+        for cfg in &listeners.list_cfgs {
+            match &cfg.source {
+                ListenerKind::Tcp { addr, .. } => {
+                    let addr = addr
+                        .parse::<SocketAddr>()
+                        .expect("Listener address must be valid after parsing the configuration");
+                    limits.tcp.push((addr, cfg.max_uri_length));
+                }
+                ListenerKind::Uds(path) => limits.uds.push((path.clone(), cfg.max_uri_length)),
+                // given the address of its socket by `with_inherited_addrs` beforehand
+                ListenerKind::InheritedFd { .. } => {}
+            }
+        }
+
+        limits
+    }
+
+    /// Whether the request target is longer than the listener that accepted it allows.
+    pub fn exceeded(&self, session: &Session) -> bool {
+        let limit = session
+            .server_addr()
+            .and_then(|addr| match (addr.as_inet(), addr.as_unix()) {
+                (Some(inet), _) => self.for_tcp(inet),
+                (None, Some(unix)) => self.for_uds(unix.as_pathname()?),
+                (None, None) => None,
+            })
+            .unwrap_or(DEFAULT_MAX_URI_LENGTH);
+
+        session.req_header().raw_path().len() > limit
+    }
+
+    fn for_tcp(&self, local_addr: &SocketAddr) -> Option<usize> {
+        find_listener(&self.tcp, local_addr).copied()
+    }
+
+    fn for_uds(&self, path: &Path) -> Option<usize> {
+        self.uds
+            .iter()
+            .find(|(listener, _)| listener == path)
+            .map(|(_, limit)| *limit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use motya_config::common_types::listeners::ListenerConfig;
+
+    use super::*;
+
+    fn listener(source: ListenerKind, max_uri_length: usize) -> ListenerConfig {
+        ListenerConfig {
+            max_uri_length,
+            ..ListenerConfig::new(source)
+        }
+    }
+
+    #[test]
+    fn test_limits_follow_listener() {
+        let limits = UriLimits::from_listeners(&Listeners {
+            list_cfgs: vec![
+                listener(
+                    ListenerKind::Tcp {
+                        addr: "0.0.0.0:8080".to_string(),
+                        tls: None,
+                        offer_h2: false,
+                    },
+                    1024,
+                ),
+                listener(ListenerKind::Uds("/run/motya.sock".into()), 2048),
+            ],
+        });
+
+        assert_eq!(
+            limits.for_tcp(&"10.0.0.5:8080".parse().unwrap()),
+            Some(1024)
+        );
+        assert_eq!(limits.for_tcp(&"10.0.0.5:9090".parse().unwrap()), None);
+        assert_eq!(limits.for_uds(Path::new("/run/motya.sock")), Some(2048));
+    }
+}
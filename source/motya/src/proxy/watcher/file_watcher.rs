@@ -2,23 +2,47 @@ use std::{
     collections::HashMap, convert::Infallible, marker::PhantomData, path::PathBuf, time::Duration,
 };
 
-use futures_util::future::try_join_all;
-use miette::IntoDiagnostic;
+use miette::miette;
 use notify::{Event, RecursiveMode, Watcher};
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, oneshot};
 
 use crate::{
     fs_adapter::TokioFs,
-    proxy::{upstream_factory::UpstreamFactory, upstream_router::UpstreamRouter, SharedProxyState},
+    proxy::{upstream_factory::UpstreamFactory, SharedProxyState},
 };
 use motya_config::{
     common_types::definitions_table::DefinitionsTable,
     config_source::ConfigSource,
     internal::{Config, ProxyConfig},
-    kdl::fs_loader::FileCollector,
+    kdl::fs_loader::{watched_path, FileCollector, STDIN_ENTRY},
     loader::{ConfigLoader, FileConfigLoaderProvider},
 };
 
+/// Where the outcome of a requested reload is sent.
+type ReloadReply = oneshot::Sender<miette::Result<()>>;
+
+/// Asks a running [`ConfigWatcher`] to reload the configuration, as the `admin` endpoint does.
+#[derive(Clone)]
+pub struct ReloadHandle {
+    requests: mpsc::Sender<ReloadReply>,
+}
+
+impl ReloadHandle {
+    /// Reloads the configuration, the error holds the diagnostics when it's refused and the
+    /// running configuration is kept.
+    pub async fn reload(&self) -> miette::Result<()> {
+        let (reply, outcome) = oneshot::channel();
+        self.requests
+            .send(reply)
+            .await
+            .map_err(|_| miette!("The configuration watcher is not running"))?;
+
+        outcome
+            .await
+            .map_err(|_| miette!("The configuration watcher stopped before reloading"))?
+    }
+}
+
 pub struct ConfigWatcher<
     Cs: ConfigSource = FileCollector<TokioFs>,
     TConfigLoader: FileConfigLoaderProvider + Clone = ConfigLoader<Cs>,
@@ -29,6 +53,7 @@ pub struct ConfigWatcher<
     watch_entry_path: PathBuf,
     upstream_factory: UpstreamFactory,
     config_loader: TConfigLoader,
+    reload_requests: Option<mpsc::Receiver<ReloadReply>>,
     phantom: PhantomData<Cs>,
 }
 
@@ -47,6 +72,7 @@ impl<Cs: ConfigSource, T: FileConfigLoaderProvider + Clone> ConfigWatcher<Cs, T>
             upstream_factory,
             config_loader,
             active_proxies: HashMap::default(),
+            reload_requests: None,
             phantom: PhantomData,
         }
     }
@@ -55,7 +81,26 @@ impl<Cs: ConfigSource, T: FileConfigLoaderProvider + Clone> ConfigWatcher<Cs, T>
         self.active_proxies.insert(name, state);
     }
 
+    /// A handle requesting reloads from [`ConfigWatcher::watch`], next to the file changes.
+    pub fn reload_handle(&mut self) -> ReloadHandle {
+        let (requests, receiver) = mpsc::channel(8);
+        self.reload_requests = Some(receiver);
+        ReloadHandle { requests }
+    }
+
     pub async fn watch(&mut self) -> Result<Infallible, Box<dyn std::error::Error + Send + Sync>> {
+        let mut requests = self.reload_requests.take();
+
+        if self.watch_entry_path.as_os_str() == STDIN_ENTRY {
+            tracing::info!("Configuration read from stdin, reloading is disabled");
+            while let Some(reply) = next_request(&mut requests).await {
+                let _ = reply.send(Err(miette!(
+                    "The configuration was read from stdin and can't be read again"
+                )));
+            }
+            return std::future::pending().await;
+        }
+
         tracing::info!("Starting watcher on: {:?}", &self.watch_entry_path);
 
         let (tx, mut rx) = mpsc::channel(100);
@@ -68,84 +113,93 @@ impl<Cs: ConfigSource, T: FileConfigLoaderProvider + Clone> ConfigWatcher<Cs, T>
             }
         })?;
 
-        watcher.watch(&self.watch_entry_path, RecursiveMode::Recursive)?;
+        watcher.watch(
+            watched_path(&self.watch_entry_path),
+            RecursiveMode::Recursive,
+        )?;
 
         loop {
-            if let Some(_event) = rx.recv().await {
-                tokio::time::sleep(Duration::from_millis(100)).await;
+            tokio::select! {
+                Some(_event) = rx.recv() => {
+                    tokio::time::sleep(Duration::from_millis(100)).await;
 
-                while rx.try_recv().is_ok() {}
+                    while rx.try_recv().is_ok() {}
 
-                match self.reload().await {
-                    Ok(_) => {}
-                    Err(err) => tracing::error!("fail on reload: {err}"),
+                    if let Err(err) = self.reload().await {
+                        tracing::warn!("Failed to reload config: {err:?}. Keeping old configuration.");
+                    }
+                }
+                Some(reply) = next_request(&mut requests) => {
+                    let outcome = self.reload().await;
+                    if let Err(err) = &outcome {
+                        tracing::warn!("Failed to reload config: {err:?}. Keeping old configuration.");
+                    }
+                    let _ = reply.send(outcome);
                 }
             }
         }
     }
 
+    /// Loads the configuration again and swaps the routers of the services it changed.
+    ///
+    /// Nothing is swapped unless every changed service could be built.
     async fn reload(&mut self) -> miette::Result<()> {
         tracing::info!("Reloading configuration...");
 
         let mut new_definitions = DefinitionsTable::new_with_global();
 
-        match self
+        let cfg = self
             .config_loader
             .clone()
             .load_entry_point(Some(self.watch_entry_path.clone()), &mut new_definitions)
-            .await
-        {
-            Ok(Some(cfg)) => {
-                self.table = new_definitions;
-
-                let old_proxies: HashMap<&String, &ProxyConfig> = self
-                    .config
-                    .basic_proxies
-                    .iter()
-                    .map(|p| (&p.name, p))
-                    .collect();
-
-                let new_proxies: HashMap<&String, &ProxyConfig> =
-                    cfg.basic_proxies.iter().map(|p| (&p.name, p)).collect();
-
-                for (name, new) in new_proxies.iter() {
-                    if let Some(old) = old_proxies.get(name) {
-                        if old.connectors != new.connectors {
-                            if let Some(active_config) = self.active_proxies.get(*name) {
-                                println!("Connectors changed for proxy '{}'", new.name);
-                                let upstreams = try_join_all(
-                                    new.connectors
-                                        .upstreams
-                                        .clone()
-                                        .into_iter()
-                                        .map(|cfg| self.upstream_factory.create_context(cfg))
-                                        .collect::<Vec<_>>(),
-                                )
-                                .await?;
-
-                                let router = UpstreamRouter::build(upstreams).into_diagnostic()?;
-
-                                active_config.swap(router.into());
-                            }
-                            // logic...
-                        }
-                    } else {
-                        // println!("New proxy detected: '{}'", new.name);
+            .await?
+            .ok_or_else(|| miette!("invariant violated: path not exist"))?;
+
+        let old_proxies: HashMap<&String, &ProxyConfig> = self
+            .config
+            .basic_proxies
+            .iter()
+            .map(|p| (&p.name, p))
+            .collect();
+
+        let mut swaps = vec![];
+        for new in &cfg.basic_proxies {
+            if let Some(old) = old_proxies.get(&new.name) {
+                if old.connectors != new.connectors || old.routes != new.routes {
+                    if let Some(active_config) = self.active_proxies.get(&new.name) {
+                        println!("Connectors changed for proxy '{}'", new.name);
+                        let router = self
+                            .upstream_factory
+                            .create_router(new.connectors.upstreams.clone(), new.routes.clone())
+                            .await?;
+
+                        swaps.push((active_config, router));
                     }
+                    // logic...
                 }
+            } else {
+                // println!("New proxy detected: '{}'", new.name);
             }
-            Ok(None) => {
-                tracing::warn!("Failed to load config: invariant violated: path not exist. Keeping old configuration.");
-            }
-            Err(e) => {
-                tracing::warn!("Failed to reload config: {}. Keeping old configuration.", e);
-            }
         }
 
+        for (active_config, router) in swaps {
+            active_config.swap(router.into());
+        }
+        self.table = new_definitions;
+        self.config = cfg;
+
         Ok(())
     }
 }
 
+/// The next reload request, never resolving when nothing can request one.
+async fn next_request(requests: &mut Option<mpsc::Receiver<ReloadReply>>) -> Option<ReloadReply> {
+    match requests {
+        Some(requests) => requests.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::sync::Arc;
@@ -158,6 +212,7 @@ mod tests {
     use super::*;
     use crate::proxy::{
         filters::{chain_resolver::ChainResolver, registry::FilterRegistry},
+        upstream_router::UpstreamRouter,
         ArcSwap,
     };
     use motya_config::common_types::{
@@ -170,12 +225,15 @@ mod tests {
     #[derive(Clone)]
     struct MockConfigLoader {
         pub config_to_return: Arc<Mutex<Option<Config>>>,
+        /// When set, loading fails with this message, as an invalid configuration does.
+        pub error_to_return: Arc<Mutex<Option<String>>>,
     }
 
     impl MockConfigLoader {
         fn new(cfg: Config) -> Self {
             Self {
                 config_to_return: Arc::new(Mutex::new(Some(cfg))),
+                error_to_return: Arc::new(Mutex::new(None)),
             }
         }
     }
@@ -186,32 +244,51 @@ mod tests {
             _path: Option<PathBuf>,
             _defs: &mut DefinitionsTable,
         ) -> Result<Option<Config>> {
+            if let Some(err) = self.error_to_return.lock().await.clone() {
+                return Err(miette!("{err}"));
+            }
             let cfg = self.config_to_return.lock().await.clone();
             Ok(cfg)
         }
     }
 
-    #[tokio::test]
-    async fn test_watcher_updates_proxies_using_mock() {
-        let new_proxy_config = Config {
+    /// A configuration with one service answering `body` on every path.
+    fn static_config(body: &str) -> Config {
+        Config {
             basic_proxies: vec![ProxyConfig {
                 listeners: Listeners { list_cfgs: vec![] },
                 connectors: Connectors {
                     anonymous_definitions: Default::default(),
-                    upstreams: vec![UpstreamContextConfig {
-                        chains: vec![],
-                        lb_options: Default::default(),
-                        upstream: UpstreamConfig::Static(SimpleResponseConfig {
+                    upstreams: vec![UpstreamContextConfig::new(UpstreamConfig::Static(
+                        SimpleResponseConfig {
                             http_code: StatusCode::OK,
-                            response_body: "ver 1".to_string(),
+                            response_body: body.to_string(),
                             prefix_path: PathAndQuery::from_static("/"),
-                        }),
-                    }],
+                        },
+                    ))],
                 },
+                error_pages: Default::default(),
+                access_log: Default::default(),
+                routes: vec![],
                 name: "Test".to_string(),
             }],
             ..Config::default()
+        }
+    }
+
+    /// The body the router of `state` answers `/` with.
+    fn served_body(state: &SharedProxyState) -> String {
+        let router = state.load();
+        let upstream = router.get_upstream_by_path("/").unwrap();
+        let UpstreamConfig::Static(response) = &upstream.upstream else {
+            unreachable!()
         };
+        response.response_body.clone()
+    }
+
+    #[tokio::test]
+    async fn test_watcher_updates_proxies_using_mock() {
+        let new_proxy_config = static_config("ver 1");
 
         let mock_loader = MockConfigLoader::new(new_proxy_config.clone());
         let table = DefinitionsTable::default();
@@ -277,4 +354,49 @@ mod tests {
 
         assert_eq!(response.response_body, "ver 2");
     }
+
+    #[tokio::test]
+    async fn test_requested_reload_swaps_only_valid_config() {
+        let config = static_config("ver 1");
+        let mock_loader = MockConfigLoader::new(config.clone());
+        let table = DefinitionsTable::default();
+        let registry = Arc::new(Mutex::new(FilterRegistry::default()));
+        let resolver = ChainResolver::new(table.clone(), registry).await.unwrap();
+        let factory = UpstreamFactory::new(resolver);
+        let config_dir = tempfile::tempdir().unwrap();
+
+        let mut watcher: ConfigWatcher<FileCollector<TokioFs>, MockConfigLoader> =
+            ConfigWatcher::new(
+                config.clone(),
+                table,
+                config_dir.path().to_path_buf(),
+                factory.clone(),
+                mock_loader.clone(),
+            );
+        let upstream = factory
+            .create_context(config.basic_proxies[0].connectors.upstreams[0].clone())
+            .await
+            .unwrap();
+        let tracked_router = Arc::new(ArcSwap::from_pointee(
+            UpstreamRouter::build(vec![upstream]).unwrap(),
+        ));
+        watcher.insert_proxy_state(config.basic_proxies[0].name.clone(), tracked_router.clone());
+
+        let handle = watcher.reload_handle();
+        tokio::spawn(async move { watcher.watch().await });
+
+        *mock_loader.config_to_return.lock().await = Some(static_config("ver 2"));
+        handle
+            .reload()
+            .await
+            .expect("a valid configuration is applied");
+        assert_eq!(served_body(&tracked_router), "ver 2");
+
+        *mock_loader.config_to_return.lock().await = Some(static_config("ver 3"));
+        *mock_loader.error_to_return.lock().await =
+            Some("Missing required directive 'addr'".to_string());
+        let err = handle.reload().await.unwrap_err();
+        assert!(format!("{err:?}").contains("Missing required directive 'addr'"));
+        assert_eq!(served_body(&tracked_router), "ver 2");
+    }
 }
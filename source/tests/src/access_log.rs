@@ -0,0 +1,147 @@
+use std::{
+    io::Write,
+    net::TcpListener,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use reqwest::Client;
+use tempfile::NamedTempFile;
+use wiremock::{matchers::method, Mock, MockServer, ResponseTemplate};
+
+use motya::{
+    app_context::AppContext,
+    proxy::log_sink::{register_sink, LogRecord, LogSink, LogSource},
+};
+use motya_config::cli::cli_struct::Cli;
+
+const ACCESS_LOG_CONFIG_TEMPLATE: &str = r#"
+system { }
+services {
+    AccessLogTest {
+        listeners {
+            "127.0.0.1:__PROXY_PORT__"
+        }
+        access-log {
+            format "$uri $status $upstream_addr $upstream_response_time"
+        }
+        connectors {
+            proxy "__UPSTREAM__"
+        }
+    }
+}
+"#;
+
+#[derive(Default)]
+struct AccessLog(Mutex<Vec<String>>);
+
+impl LogSink for AccessLog {
+    fn emit(&self, record: LogRecord) {
+        if record.source == LogSource::Access {
+            self.0.lock().unwrap().push(record.message);
+        }
+    }
+}
+
+fn get_free_port() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind to random port");
+    let port = listener.local_addr().unwrap().port();
+    drop(listener);
+    port
+}
+
+#[tokio::test]
+async fn test_upstream_timing_is_logged() {
+    let upstream = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_string("slow")
+                .set_delay(Duration::from_millis(200)),
+        )
+        .mount(&upstream)
+        .await;
+
+    let access_log = Arc::new(AccessLog::default());
+    register_sink(access_log.clone());
+
+    let proxy_port = get_free_port();
+    let config_content = ACCESS_LOG_CONFIG_TEMPLATE
+        .replace("__PROXY_PORT__", &proxy_port.to_string())
+        .replace("__UPSTREAM__", &upstream.uri());
+
+    let mut config_file = NamedTempFile::new().expect("Failed to create temp config file");
+    write!(config_file, "{}", config_content).expect("Failed to write config content");
+
+    let cli = Cli {
+        validate_configs: false,
+        dump_config: false,
+        threads_per_service: None,
+        config_entry: Some(config_file.path().to_path_buf()),
+        profile: None,
+        max_config_size: None,
+        daemonize: false,
+        upgrade: false,
+        pidfile: None,
+        upgrade_socket: None,
+        command: None,
+    };
+
+    let mut app_ctx = AppContext::bootstrap(cli)
+        .await
+        .expect("Failed to bootstrap AppContext");
+    let services = app_ctx
+        .build_services()
+        .await
+        .expect("Failed to build services");
+
+    let (mut server, _watcher) = app_ctx.ready();
+    server.add_services(services);
+    server.bootstrap();
+
+    thread::spawn(move || {
+        server.run_forever();
+    });
+
+    let url = format!("http://127.0.0.1:{proxy_port}/upstream-timing");
+    let client = Client::new();
+    let mut response = None;
+    for _ in 0..50 {
+        if let Ok(resp) = client.get(&url).send().await {
+            response = Some(resp);
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    let response = response.expect("Proxy did not start within timeout");
+    assert_eq!(response.text().await.unwrap(), "slow");
+
+    // the access record is emitted once the response is sent
+    let logged = format!("/upstream-timing 200 {} ", upstream.address());
+    for _ in 0..50 {
+        let record = access_log
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .find_map(|m| m.strip_prefix(&logged).map(str::to_string));
+
+        if let Some(upstream_response_time) = record {
+            let seconds: f64 = upstream_response_time
+                .parse()
+                .expect("upstream response time should be in seconds");
+            // at least the delay of the upstream, but not the whole test run
+            assert!(
+                (0.2..5.0).contains(&seconds),
+                "implausible upstream response time '{upstream_response_time}'"
+            );
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!(
+        "no access record starting with '{logged}' in {:?}",
+        access_log.0.lock().unwrap()
+    );
+}
@@ -0,0 +1,135 @@
+use std::{io::Write, net::TcpListener, thread, time::Duration};
+
+use reqwest::Client;
+use tempfile::NamedTempFile;
+use wiremock::{matchers::method, Mock, MockServer, ResponseTemplate};
+
+use motya::app_context::AppContext;
+use motya_config::cli::cli_struct::Cli;
+
+const CACHE_CONFIG_TEMPLATE: &str = r#"
+system { }
+definitions {
+    key-profiles {
+        template "page" {
+            key "${uri-path}"
+        }
+    }
+    modifiers {
+        chain-filters "tag" {
+            filter name="motya.request.upsert-header" key="X-Filtered" value="yes"
+        }
+    }
+}
+services {
+    CacheTest {
+        listeners {
+            "127.0.0.1:__PROXY_PORT__"
+        }
+        connectors {
+            section "/" {
+                use-chain "tag"
+                cache {
+                    key-profile "page"
+                    ttl "1s"
+                    stale-while-revalidate "1s"
+                }
+                request-headers {
+                    set "X-Shaped" "yes"
+                }
+                proxy "__UPSTREAM__"
+            }
+        }
+    }
+}
+"#;
+
+fn get_free_port() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind to random port");
+    let port = listener.local_addr().unwrap().port();
+    drop(listener);
+    port
+}
+
+#[tokio::test]
+async fn test_revalidation_skips_filter_chains() {
+    let upstream = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("page"))
+        .mount(&upstream)
+        .await;
+
+    let proxy_port = get_free_port();
+    let config_content = CACHE_CONFIG_TEMPLATE
+        .replace("__PROXY_PORT__", &proxy_port.to_string())
+        .replace("__UPSTREAM__", &upstream.uri());
+
+    let mut config_file = NamedTempFile::new().expect("Failed to create temp config file");
+    write!(config_file, "{}", config_content).expect("Failed to write config content");
+
+    let cli = Cli {
+        validate_configs: false,
+        dump_config: false,
+        threads_per_service: None,
+        config_entry: Some(config_file.path().to_path_buf()),
+        profile: None,
+        max_config_size: None,
+        daemonize: false,
+        upgrade: false,
+        pidfile: None,
+        upgrade_socket: None,
+        command: None,
+    };
+
+    let mut app_ctx = AppContext::bootstrap(cli)
+        .await
+        .expect("Failed to bootstrap AppContext");
+    let services = app_ctx
+        .build_services()
+        .await
+        .expect("Failed to build services");
+
+    let (mut server, _watcher) = app_ctx.ready();
+    server.add_services(services);
+    server.bootstrap();
+
+    thread::spawn(move || {
+        server.run_forever();
+    });
+
+    let url = format!("http://127.0.0.1:{proxy_port}/page");
+    let client = Client::new();
+    let mut response = None;
+    for _ in 0..50 {
+        if let Ok(resp) = client.get(&url).send().await {
+            response = Some(resp);
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    let response = response.expect("Proxy did not start within timeout");
+    assert_eq!(response.text().await.unwrap(), "page");
+
+    // served stale, while the entry is refreshed in the background
+    tokio::time::sleep(Duration::from_millis(1200)).await;
+    let response = client.get(&url).send().await.unwrap();
+    assert_eq!(response.text().await.unwrap(), "page");
+
+    for _ in 0..50 {
+        let requests = upstream.received_requests().await.unwrap();
+        if let [first, refresh] = requests.as_slice() {
+            assert!(first.headers.contains_key("x-filtered"));
+            assert!(first.headers.contains_key("x-shaped"));
+
+            // the header rules apply to the refresh, the filter chain doesn't run for it
+            assert!(!refresh.headers.contains_key("x-filtered"));
+            assert!(refresh.headers.contains_key("x-shaped"));
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!(
+        "expected the request and its refresh upstream, got {:?}",
+        upstream.received_requests().await
+    );
+}
@@ -33,8 +33,11 @@ mod tests {
 
         let cli = Cli {
             validate_configs: false,
+            dump_config: false,
             threads_per_service: None,
             config_entry: None,
+            profile: None,
+            max_config_size: None,
             daemonize: false,
             upgrade: false,
             pidfile: None,
@@ -81,8 +84,11 @@ mod tests {
 
         let cli = Cli {
             validate_configs: false,
+            dump_config: false,
             threads_per_service: None,
             config_entry: None,
+            profile: None,
+            max_config_size: None,
             daemonize: false,
             upgrade: false,
             pidfile: None,
@@ -147,8 +153,11 @@ mod tests {
 
         let cli = Cli {
             validate_configs: false,
+            dump_config: false,
             threads_per_service: None,
             config_entry: None,
+            profile: None,
+            max_config_size: None,
             daemonize: false,
             upgrade: false,
             pidfile: None,
@@ -9,6 +9,7 @@ mod tests {
 
     use motya::app_context::{pingora_opt, pingora_server_conf};
     use motya::fs_adapter::TokioFs;
+    use motya::proxy::connection_limit::ConnectionLimit;
     use motya::proxy::filters::{chain_resolver::ChainResolver, registry::FilterRegistry};
     use motya::proxy::motya_proxy_service;
     use motya::proxy::upstream_factory::UpstreamFactory;
@@ -149,11 +150,16 @@ mod tests {
         app_server.bootstrap();
 
         let proxy_config = config.basic_proxies[0].clone();
-        let (service, shared_state) = motya_proxy_service(proxy_config, resolver, &app_server)
-            .await
-            .unwrap();
-
-        app_server.add_services(vec![service]);
+        let (services, shared_state) = motya_proxy_service(
+            proxy_config,
+            resolver,
+            ConnectionLimit::default(),
+            &app_server,
+        )
+        .await
+        .unwrap();
+
+        app_server.add_services(services);
         thread::spawn(move || {
             app_server.run_forever();
         });
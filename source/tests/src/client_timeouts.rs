@@ -0,0 +1,107 @@
+use std::{io::Write, net::TcpListener, thread, time::Duration};
+
+use reqwest::Client;
+use tempfile::NamedTempFile;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+use wiremock::{matchers::method, Mock, MockServer, ResponseTemplate};
+
+use motya::app_context::AppContext;
+use motya_config::cli::cli_struct::Cli;
+
+const CLIENT_TIMEOUTS_CONFIG_TEMPLATE: &str = r#"
+system { }
+services {
+    ClientTimeoutsTest {
+        listeners {
+            "127.0.0.1:__PROXY_PORT__" client-read-timeout="500ms"
+        }
+        connectors {
+            section "/" {
+                proxy "__UPSTREAM__"
+            }
+        }
+    }
+}
+"#;
+
+fn get_free_port() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind to random port");
+    let port = listener.local_addr().unwrap().port();
+    drop(listener);
+    port
+}
+
+#[tokio::test]
+async fn test_read_timeout_covers_request_header() {
+    let upstream = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("ok"))
+        .mount(&upstream)
+        .await;
+
+    let proxy_port = get_free_port();
+    let config_content = CLIENT_TIMEOUTS_CONFIG_TEMPLATE
+        .replace("__PROXY_PORT__", &proxy_port.to_string())
+        .replace("__UPSTREAM__", &upstream.address().to_string());
+
+    let mut config_file = NamedTempFile::new().expect("Failed to create temp config file");
+    write!(config_file, "{}", config_content).expect("Failed to write config content");
+
+    let cli = Cli {
+        validate_configs: false,
+        dump_config: false,
+        threads_per_service: None,
+        config_entry: Some(config_file.path().to_path_buf()),
+        profile: None,
+        max_config_size: None,
+        daemonize: false,
+        upgrade: false,
+        pidfile: None,
+        upgrade_socket: None,
+        command: None,
+    };
+
+    let mut app_ctx = AppContext::bootstrap(cli)
+        .await
+        .expect("Failed to bootstrap AppContext");
+    let services = app_ctx
+        .build_services()
+        .await
+        .expect("Failed to build services");
+
+    let (mut server, _watcher) = app_ctx.ready();
+    server.add_services(services);
+    server.bootstrap();
+
+    thread::spawn(move || {
+        server.run_forever();
+    });
+
+    let url = format!("http://127.0.0.1:{proxy_port}/");
+    let client = Client::new();
+    for _ in 0..50 {
+        if client.get(&url).send().await.is_ok() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    // starts a request header and never finishes it
+    let mut stream = TcpStream::connect(("127.0.0.1", proxy_port))
+        .await
+        .expect("Proxy should accept");
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n")
+        .await
+        .unwrap();
+
+    let mut buf = Vec::new();
+    let closed = tokio::time::timeout(Duration::from_secs(3), stream.read_to_end(&mut buf)).await;
+    assert!(
+        closed.is_ok(),
+        "the connection outlived its read timeout while the header was incomplete"
+    );
+}
@@ -8,6 +8,7 @@ use http::uri::PathAndQuery;
 use motya::{
     app_context::{pingora_opt, pingora_server_conf},
     proxy::{
+        connection_limit::ConnectionLimit,
         filters::{chain_resolver::ChainResolver, generate_registry::load_registry},
         motya_proxy_service,
     },
@@ -42,6 +43,12 @@ pub async fn setup_check_cidr() -> thread::JoinHandle<()> {
         filters: vec![ConfiguredFilter {
             name: fqdn!("motya.filters.block-cidr-range"),
             args: HashMap::from([("addrs".to_string(), "127.0.0.0/8".to_string())]),
+            enabled: true,
+            on_error: None,
+            max_memory: None,
+            max_body_size: None,
+            when: None,
+            priority: 0,
         }],
     };
 
@@ -58,12 +65,11 @@ pub async fn setup_check_cidr() -> thread::JoinHandle<()> {
     let proxy = ProxyConfig {
         connectors: Connectors {
             upstreams: vec![UpstreamContextConfig {
-                lb_options: Default::default(),
                 chains: vec![Modificator::Chain(NamedFilterChain {
                     name: "block-noob".to_string(),
                     chain: chain.clone(),
                 })],
-                upstream: UpstreamConfig::Service(HttpPeerConfig {
+                ..UpstreamContextConfig::new(UpstreamConfig::Service(HttpPeerConfig {
                     peer_address: *mock_server.address(),
                     alpn: ALPN::H1,
                     sni: String::new(),
@@ -71,31 +77,34 @@ pub async fn setup_check_cidr() -> thread::JoinHandle<()> {
                     prefix_path: PathAndQuery::from_static("/"),
                     target_path: PathAndQuery::from_static("/"),
                     matcher: Default::default(),
-                }),
+                    connect_timeout: None,
+                }))
             }],
             anonymous_definitions: Default::default(),
         },
         listeners: Listeners {
-            list_cfgs: vec![ListenerConfig {
-                source: ListenerKind::Tcp {
-                    addr: proxy_addr.to_string(),
-                    offer_h2: false,
-                    tls: None,
-                },
-            }],
+            list_cfgs: vec![ListenerConfig::new(ListenerKind::Tcp {
+                addr: proxy_addr.to_string(),
+                offer_h2: false,
+                tls: None,
+            })],
         },
+        error_pages: Default::default(),
+        access_log: Default::default(),
+        routes: vec![],
         name: "TestServer".to_string(),
     };
 
     let mut app_server =
         Server::new_with_opt_and_conf(pingora_opt(&config), pingora_server_conf(&config));
 
-    let (proxy_service, _) = motya_proxy_service(proxy, resolver, &app_server)
-        .await
-        .unwrap();
+    let (proxy_services, _) =
+        motya_proxy_service(proxy, resolver, ConnectionLimit::default(), &app_server)
+            .await
+            .unwrap();
 
     app_server.bootstrap();
-    app_server.add_services(vec![proxy_service]);
+    app_server.add_services(proxy_services);
 
     let (tx, rx) = mpsc::channel();
 
@@ -126,6 +135,12 @@ pub async fn setup_check_cidr_accept() -> thread::JoinHandle<()> {
         filters: vec![ConfiguredFilter {
             name: fqdn!("motya.filters.block-cidr-range"),
             args: HashMap::from([("addrs".to_string(), "10.0.0.0/8".to_string())]),
+            enabled: true,
+            on_error: None,
+            max_memory: None,
+            max_body_size: None,
+            when: None,
+            priority: 0,
         }],
     };
 
@@ -142,12 +157,11 @@ pub async fn setup_check_cidr_accept() -> thread::JoinHandle<()> {
     let proxy = ProxyConfig {
         connectors: Connectors {
             upstreams: vec![UpstreamContextConfig {
-                lb_options: Default::default(),
                 chains: vec![Modificator::Chain(NamedFilterChain {
                     name: "block-noob".to_string(),
                     chain: chain.clone(),
                 })],
-                upstream: UpstreamConfig::Service(HttpPeerConfig {
+                ..UpstreamContextConfig::new(UpstreamConfig::Service(HttpPeerConfig {
                     peer_address: *mock_server.address(),
                     alpn: ALPN::H1,
                     sni: String::new(),
@@ -155,31 +169,34 @@ pub async fn setup_check_cidr_accept() -> thread::JoinHandle<()> {
                     prefix_path: PathAndQuery::from_static("/"),
                     target_path: PathAndQuery::from_static("/"),
                     matcher: Default::default(),
-                }),
+                    connect_timeout: None,
+                }))
             }],
             anonymous_definitions: Default::default(),
         },
         listeners: Listeners {
-            list_cfgs: vec![ListenerConfig {
-                source: ListenerKind::Tcp {
-                    addr: proxy_addr.to_string(),
-                    offer_h2: false,
-                    tls: None,
-                },
-            }],
+            list_cfgs: vec![ListenerConfig::new(ListenerKind::Tcp {
+                addr: proxy_addr.to_string(),
+                offer_h2: false,
+                tls: None,
+            })],
         },
+        error_pages: Default::default(),
+        access_log: Default::default(),
+        routes: vec![],
         name: "TestServer".to_string(),
     };
 
     let mut app_server =
         Server::new_with_opt_and_conf(pingora_opt(&config), pingora_server_conf(&config));
 
-    let (proxy_service, _) = motya_proxy_service(proxy, resolver, &app_server)
-        .await
-        .unwrap();
+    let (proxy_services, _) =
+        motya_proxy_service(proxy, resolver, ConnectionLimit::default(), &app_server)
+            .await
+            .unwrap();
 
     app_server.bootstrap();
-    app_server.add_services(vec![proxy_service]);
+    app_server.add_services(proxy_services);
 
     let (tx, rx) = mpsc::channel();
 
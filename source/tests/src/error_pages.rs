@@ -0,0 +1,117 @@
+use std::{io::Write, net::TcpListener, thread, time::Duration};
+
+use reqwest::Client;
+use tempfile::NamedTempFile;
+
+use motya::app_context::AppContext;
+use motya_config::cli::cli_struct::Cli;
+
+const ERROR_PAGES_CONFIG_TEMPLATE: &str = r#"
+system { }
+services {
+    ErrorPagesTest {
+        listeners {
+            "127.0.0.1:__PROXY_PORT__"
+        }
+        error-pages {
+            "502" "__BAD_GATEWAY_PAGE__"
+        }
+        connectors {
+            proxy "http://127.0.0.1:__DEAD_PORT__"
+        }
+    }
+}
+"#;
+
+const BAD_GATEWAY_PAGE: &str = "<h1>We'll be right back</h1>";
+
+fn get_free_port() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind to random port");
+    let port = listener.local_addr().unwrap().port();
+    drop(listener);
+    port
+}
+
+async fn wait_for_proxy(url: &str) {
+    let client = Client::new();
+    let start = std::time::Instant::now();
+
+    while start.elapsed() < Duration::from_secs(5) {
+        if client.get(url).send().await.is_ok() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("Proxy did not start at {} within timeout", url);
+}
+
+#[tokio::test]
+async fn test_connect_failure_serves_error_page() {
+    let proxy_port = get_free_port();
+    // nothing listens here, so connecting to the upstream fails
+    let dead_port = get_free_port();
+
+    let mut page_file = tempfile::Builder::new()
+        .suffix(".html")
+        .tempfile()
+        .expect("Failed to create temp error page");
+    write!(page_file, "{}", BAD_GATEWAY_PAGE).expect("Failed to write error page");
+
+    let config_content = ERROR_PAGES_CONFIG_TEMPLATE
+        .replace("__PROXY_PORT__", &proxy_port.to_string())
+        .replace("__DEAD_PORT__", &dead_port.to_string())
+        .replace(
+            "__BAD_GATEWAY_PAGE__",
+            &page_file.path().display().to_string(),
+        );
+
+    let mut config_file = NamedTempFile::new().expect("Failed to create temp config file");
+    write!(config_file, "{}", config_content).expect("Failed to write config content");
+    let config_path = config_file.path().to_path_buf();
+
+    let cli = Cli {
+        validate_configs: false,
+        dump_config: false,
+        threads_per_service: None,
+        config_entry: Some(config_path),
+        profile: None,
+        max_config_size: None,
+        daemonize: false,
+        upgrade: false,
+        pidfile: None,
+        upgrade_socket: None,
+        command: None,
+    };
+
+    let mut app_ctx = AppContext::bootstrap(cli)
+        .await
+        .expect("Failed to bootstrap AppContext");
+    let services = app_ctx
+        .build_services()
+        .await
+        .expect("Failed to build services");
+
+    let (mut server, _watcher) = app_ctx.ready();
+    server.add_services(services);
+    server.bootstrap();
+
+    thread::spawn(move || {
+        server.run_forever();
+    });
+
+    let proxy_url = format!("http://127.0.0.1:{}", proxy_port);
+    wait_for_proxy(&proxy_url).await;
+
+    let response = Client::new()
+        .get(&proxy_url)
+        .send()
+        .await
+        .expect("Failed to send request");
+
+    assert_eq!(response.status(), 502);
+    assert_eq!(
+        response.headers().get("content-type").unwrap(),
+        "text/html; charset=utf-8"
+    );
+    assert_eq!(response.text().await.unwrap(), BAD_GATEWAY_PAGE);
+}
@@ -1,5 +1,6 @@
 use motya::app_context::{pingora_opt, pingora_server_conf};
 use motya::fs_adapter::TokioFs;
+use motya::proxy::connection_limit::ConnectionLimit;
 use motya::proxy::filters::chain_resolver::ChainResolver;
 use motya::proxy::filters::generate_registry::load_registry;
 use motya::proxy::motya_proxy_service;
@@ -91,11 +92,12 @@ async fn start_server_from_config_path(config_path: &std::path::Path) -> thread:
 
     let mut app_server =
         Server::new_with_opt_and_conf(pingora_opt(&conf), pingora_server_conf(&conf));
-    let (proxy_service, _) = motya_proxy_service(proxy, resolver, &app_server)
-        .await
-        .unwrap();
+    let (proxy_services, _) =
+        motya_proxy_service(proxy, resolver, ConnectionLimit::default(), &app_server)
+            .await
+            .unwrap();
     app_server.bootstrap();
-    app_server.add_services(vec![proxy_service]);
+    app_server.add_services(proxy_services);
 
     let (tx, rx) = mpsc::channel();
     let handle = thread::spawn(move || {
@@ -156,3 +158,64 @@ async fn test_routes_apply_different_filters_internal_file() {
 
     handle.thread().unpark();
 }
+
+const PRIORITY_CONFIG: &str = r#"
+    system { }
+    definitions {
+        modifiers {
+            chain-filters "ordered" {
+                filter name="motya.request.upsert-header" key="X-Order" value="last" priority=10
+                filter name="motya.request.upsert-header" key="X-Order" value="first" priority=-5
+                filter name="motya.request.upsert-header" key="X-Order" value="middle"
+            }
+        }
+    }
+
+    services {
+        TestService {
+            connectors {
+                use-chain "ordered"
+                proxy "__SERVICE__"
+            }
+            listeners {
+                "127.0.0.1:__PORT__"
+            }
+        }
+    }
+"#;
+
+#[tokio::test]
+async fn test_filters_run_in_priority_order() {
+    let mock_server = MockServer::start().await;
+
+    // each filter overwrites the header, the value that arrives is the one set last
+    Mock::given(method("GET"))
+        .and(header("X-Order", "last"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("ordered"))
+        .mount(&mock_server)
+        .await;
+
+    let proxy_port = get_free_port();
+
+    let config_content = PRIORITY_CONFIG
+        .replace("__SERVICE__", &mock_server.uri().to_string())
+        .replace("__PORT__", &proxy_port.to_string());
+
+    let mut config_file = NamedTempFile::new().expect("Failed to create temp file");
+    write!(config_file, "{}", config_content).expect("Failed to write config");
+
+    let handle = start_server_from_config_path(config_file.path()).await;
+
+    let url = format!("http://127.0.0.1:{}/", proxy_port);
+    wait_for_proxy_start(&url).await;
+
+    let resp = Client::new()
+        .get(&url)
+        .send()
+        .await
+        .expect("Request failed");
+    assert_eq!(resp.status(), 200, "Filters ran out of priority order");
+    assert_eq!(resp.text().await.unwrap(), "ordered");
+
+    handle.thread().unpark();
+}
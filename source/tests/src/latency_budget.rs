@@ -0,0 +1,122 @@
+use std::{
+    io::Write,
+    net::TcpListener,
+    thread,
+    time::{Duration, Instant},
+};
+
+use reqwest::Client;
+use tempfile::NamedTempFile;
+use wiremock::{matchers::method, Mock, MockServer, ResponseTemplate};
+
+use motya::app_context::AppContext;
+use motya_config::cli::cli_struct::Cli;
+
+const LATENCY_BUDGET_CONFIG_TEMPLATE: &str = r#"
+system { }
+services {
+    LatencyBudgetTest {
+        listeners {
+            "127.0.0.1:__PROXY_PORT__"
+        }
+        connectors {
+            latency-budget "500ms"
+            section "/" {
+                load-balance {
+                    selection "RoundRobin"
+                }
+                proxy {
+                    server "__REFUSING_1__"
+                    server "__REFUSING_2__"
+                    server "__SLOW__"
+                }
+            }
+        }
+    }
+}
+"#;
+
+fn get_free_port() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind to random port");
+    let port = listener.local_addr().unwrap().port();
+    drop(listener);
+    port
+}
+
+#[tokio::test]
+async fn test_budget_exceeded_across_retries() {
+    // answers long after the budget is over
+    let slow = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_string("too late")
+                .set_delay(Duration::from_secs(3)),
+        )
+        .mount(&slow)
+        .await;
+
+    let proxy_port = get_free_port();
+    let config_content = LATENCY_BUDGET_CONFIG_TEMPLATE
+        .replace("__PROXY_PORT__", &proxy_port.to_string())
+        // nothing listens there, so each connect fails and is retried on the next server
+        .replace("__REFUSING_1__", &format!("127.0.0.1:{}", get_free_port()))
+        .replace("__REFUSING_2__", &format!("127.0.0.1:{}", get_free_port()))
+        .replace("__SLOW__", &slow.address().to_string());
+
+    let mut config_file = NamedTempFile::new().expect("Failed to create temp config file");
+    write!(config_file, "{}", config_content).expect("Failed to write config content");
+
+    let cli = Cli {
+        validate_configs: false,
+        dump_config: false,
+        threads_per_service: None,
+        config_entry: Some(config_file.path().to_path_buf()),
+        profile: None,
+        max_config_size: None,
+        daemonize: false,
+        upgrade: false,
+        pidfile: None,
+        upgrade_socket: None,
+        command: None,
+    };
+
+    let mut app_ctx = AppContext::bootstrap(cli)
+        .await
+        .expect("Failed to bootstrap AppContext");
+    let services = app_ctx
+        .build_services()
+        .await
+        .expect("Failed to build services");
+
+    let (mut server, _watcher) = app_ctx.ready();
+    server.add_services(services);
+    server.bootstrap();
+
+    thread::spawn(move || {
+        server.run_forever();
+    });
+
+    let url = format!("http://127.0.0.1:{proxy_port}/");
+    let client = Client::new();
+    for _ in 0..50 {
+        if client.get(&url).send().await.is_ok() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    // the round robin has moved on while waiting, so send one request per server to
+    // have one go through both refusing servers before reaching the slow one
+    for _ in 0..3 {
+        let sent = Instant::now();
+        let response = client.get(&url).send().await.expect("Proxy should answer");
+
+        assert_eq!(response.status(), 504);
+        assert!(
+            sent.elapsed() < Duration::from_secs(2),
+            "the request took {:?}, past its budget",
+            sent.elapsed()
+        );
+    }
+}
@@ -1,8 +1,16 @@
 #![cfg(test)]
+mod access_log;
+mod cache_revalidation;
 mod check_cidr;
 mod check_cli_serve_and_hello;
 mod check_diff_filewatcher;
+mod client_timeouts;
 mod common;
+mod error_pages;
 mod integration_filters;
+mod latency_budget;
 mod load_balancer;
 mod load_balancer_ketama;
+mod status_endpoint;
+mod status_map;
+mod websocket;
@@ -93,8 +93,11 @@ async fn test_load_balancer_round_robin_distribution() {
 
     let cli = Cli {
         validate_configs: false,
+        dump_config: false,
         threads_per_service: None,
         config_entry: Some(config_path),
+        profile: None,
+        max_config_size: None,
         daemonize: false,
         upgrade: false,
         pidfile: None,
@@ -130,8 +130,11 @@ async fn test_ketama_hashing_with_transforms() {
 
     let cli = Cli {
         validate_configs: false,
+        dump_config: false,
         threads_per_service: None,
         config_entry: Some(config_path),
+        profile: None,
+        max_config_size: None,
         daemonize: false,
         upgrade: false,
         pidfile: None,
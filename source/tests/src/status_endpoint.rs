@@ -0,0 +1,183 @@
+use std::{io::Write, net::TcpListener, thread, time::Duration};
+
+use reqwest::{Client, StatusCode};
+use serde_json::Value;
+use tempfile::NamedTempFile;
+use wiremock::{matchers::method, Mock, MockServer, ResponseTemplate};
+
+use motya::app_context::AppContext;
+use motya_config::cli::cli_struct::Cli;
+
+const STATUS_CONFIG_TEMPLATE: &str = r#"
+system {
+    status {
+        addr "127.0.0.1:__STATUS_PORT__"
+        path "/status"
+    }
+}
+services {
+    StatusTest {
+        listeners {
+            "127.0.0.1:__PROXY_PORT__"
+        }
+        connectors {
+            section "/api" {
+                load-balance {
+                    selection "RoundRobin"
+                    outlier-detection {
+                        consecutive-errors 1
+                        ejection-time "60s"
+                    }
+                }
+                proxy {
+                    server "__HEALTHY__"
+                    server "__FAILING__"
+                }
+            }
+            section "/single" {
+                proxy "__HEALTHY__"
+            }
+        }
+    }
+}
+"#;
+
+fn get_free_port() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind to random port");
+    let port = listener.local_addr().unwrap().port();
+    drop(listener);
+    port
+}
+
+async fn wait_for(url: &str) {
+    let client = Client::new();
+    let start = std::time::Instant::now();
+
+    while start.elapsed() < Duration::from_secs(5) {
+        if client.get(url).send().await.is_ok() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("Nothing listened at {} within timeout", url);
+}
+
+fn connector<'a>(report: &'a Value, path: &str) -> &'a Value {
+    report["services"][0]["connectors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|c| c["path"] == path)
+        .unwrap_or_else(|| panic!("No connector for '{path}' in {report}"))
+}
+
+fn backend<'a>(connector: &'a Value, addr: &str) -> &'a Value {
+    connector["backends"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|b| b["address"] == addr)
+        .unwrap_or_else(|| panic!("No backend '{addr}' in {connector}"))
+}
+
+#[tokio::test]
+async fn test_status_endpoint_reports_backends() {
+    let healthy = MockServer::start().await;
+    let failing = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("OK"))
+        .mount(&healthy)
+        .await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(503))
+        .mount(&failing)
+        .await;
+
+    let proxy_port = get_free_port();
+    let status_port = get_free_port();
+    let healthy_addr = healthy.address().to_string();
+    let failing_addr = failing.address().to_string();
+
+    let config_content = STATUS_CONFIG_TEMPLATE
+        .replace("__PROXY_PORT__", &proxy_port.to_string())
+        .replace("__STATUS_PORT__", &status_port.to_string())
+        .replace("__HEALTHY__", &healthy_addr)
+        .replace("__FAILING__", &failing_addr);
+
+    let mut config_file = NamedTempFile::new().expect("Failed to create temp config file");
+    write!(config_file, "{}", config_content).expect("Failed to write config content");
+
+    let cli = Cli {
+        validate_configs: false,
+        dump_config: false,
+        threads_per_service: None,
+        config_entry: Some(config_file.path().to_path_buf()),
+        profile: None,
+        max_config_size: None,
+        daemonize: false,
+        upgrade: false,
+        pidfile: None,
+        upgrade_socket: None,
+        command: None,
+    };
+
+    let mut app_ctx = AppContext::bootstrap(cli)
+        .await
+        .expect("Failed to bootstrap AppContext");
+    let services = app_ctx
+        .build_services()
+        .await
+        .expect("Failed to build services");
+
+    let (mut server, _watcher) = app_ctx.ready();
+    server.add_services(services);
+    server.bootstrap();
+
+    thread::spawn(move || {
+        server.run_forever();
+    });
+
+    let proxy_url = format!("http://127.0.0.1:{}", proxy_port);
+    let status_url = format!("http://127.0.0.1:{}/status", status_port);
+    wait_for(&proxy_url).await;
+    wait_for(&status_url).await;
+
+    // round robin sends one of these to the failing backend, which ejects it
+    let client = Client::new();
+    for _ in 0..4 {
+        client
+            .get(format!("{proxy_url}/api"))
+            .send()
+            .await
+            .expect("Failed to send request");
+    }
+
+    let response = client.get(&status_url).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "application/json");
+    let body = response.text().await.unwrap();
+    let report: Value = serde_json::from_str(&body).expect("Status isn't JSON");
+
+    assert_eq!(report["services"][0]["name"], "StatusTest");
+
+    let api = connector(&report, "/api");
+    assert_eq!(api["backends"].as_array().unwrap().len(), 2);
+
+    let up = backend(api, &healthy_addr);
+    assert_eq!(up["healthy"], true);
+    assert_eq!(up["in_flight"], 0);
+    assert_eq!(up["circuit_breaker"], "closed");
+    assert_eq!(backend(api, &failing_addr)["circuit_breaker"], "open");
+
+    let single = backend(connector(&report, "/single"), &healthy_addr);
+    assert_eq!(single["healthy"], true);
+    assert_eq!(single["circuit_breaker"], "disabled");
+
+    let missing = client
+        .get(format!("http://127.0.0.1:{}/other", status_port))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+}
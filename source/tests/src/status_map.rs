@@ -0,0 +1,136 @@
+use std::{
+    io::Write,
+    net::TcpListener,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use reqwest::Client;
+use tempfile::NamedTempFile;
+use wiremock::{matchers::method, Mock, MockServer, ResponseTemplate};
+
+use motya::{
+    app_context::AppContext,
+    proxy::log_sink::{register_sink, LogRecord, LogSink, LogSource},
+};
+use motya_config::cli::cli_struct::Cli;
+
+const STATUS_MAP_CONFIG_TEMPLATE: &str = r#"
+system { }
+services {
+    StatusMapTest {
+        listeners {
+            "127.0.0.1:__PROXY_PORT__"
+        }
+        connectors {
+            response-status-map {
+                "418" -> "400"
+            }
+            proxy "__UPSTREAM__"
+        }
+    }
+}
+"#;
+
+#[derive(Default)]
+struct AccessLog(Mutex<Vec<String>>);
+
+impl LogSink for AccessLog {
+    fn emit(&self, record: LogRecord) {
+        if record.source == LogSource::Access {
+            self.0.lock().unwrap().push(record.message);
+        }
+    }
+}
+
+fn get_free_port() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind to random port");
+    let port = listener.local_addr().unwrap().port();
+    drop(listener);
+    port
+}
+
+#[tokio::test]
+async fn test_upstream_status_is_rewritten() {
+    let upstream = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(418).set_body_string("I'm a teapot"))
+        .mount(&upstream)
+        .await;
+
+    let access_log = Arc::new(AccessLog::default());
+    register_sink(access_log.clone());
+
+    let proxy_port = get_free_port();
+    let config_content = STATUS_MAP_CONFIG_TEMPLATE
+        .replace("__PROXY_PORT__", &proxy_port.to_string())
+        .replace("__UPSTREAM__", &upstream.uri());
+
+    let mut config_file = NamedTempFile::new().expect("Failed to create temp config file");
+    write!(config_file, "{}", config_content).expect("Failed to write config content");
+
+    let cli = Cli {
+        validate_configs: false,
+        dump_config: false,
+        threads_per_service: None,
+        config_entry: Some(config_file.path().to_path_buf()),
+        profile: None,
+        max_config_size: None,
+        daemonize: false,
+        upgrade: false,
+        pidfile: None,
+        upgrade_socket: None,
+        command: None,
+    };
+
+    let mut app_ctx = AppContext::bootstrap(cli)
+        .await
+        .expect("Failed to bootstrap AppContext");
+    let services = app_ctx
+        .build_services()
+        .await
+        .expect("Failed to build services");
+
+    let (mut server, _watcher) = app_ctx.ready();
+    server.add_services(services);
+    server.bootstrap();
+
+    thread::spawn(move || {
+        server.run_forever();
+    });
+
+    let url = format!("http://127.0.0.1:{proxy_port}/teapot");
+    let client = Client::new();
+    let mut response = None;
+    for _ in 0..50 {
+        if let Ok(resp) = client.get(&url).send().await {
+            response = Some(resp);
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    let response = response.expect("Proxy did not start within timeout");
+
+    assert_eq!(response.status(), 400);
+    assert_eq!(response.text().await.unwrap(), "I'm a teapot");
+
+    // the access record is emitted once the response is sent
+    let logged = "GET /teapot 400";
+    for _ in 0..50 {
+        if access_log
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|m| m.starts_with(logged))
+        {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!(
+        "no access record starting with '{logged}' in {:?}",
+        access_log.0.lock().unwrap()
+    );
+}
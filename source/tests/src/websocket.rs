@@ -0,0 +1,210 @@
+use std::{io::Write, net::TcpListener as StdTcpListener, thread, time::Duration};
+
+use tempfile::NamedTempFile;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    time::timeout,
+};
+
+use motya::app_context::AppContext;
+use motya_config::cli::cli_struct::Cli;
+
+const WEBSOCKET_CONFIG_TEMPLATE: &str = r#"
+system { }
+definitions {
+    modifiers {
+        chain-filters "handshake" {
+            filter name="motya.request.upsert-header" key="X-Handshake" value="checked"
+        }
+    }
+}
+services {
+    WebSocketTest {
+        listeners {
+            "127.0.0.1:__PROXY_PORT__"
+        }
+        connectors {
+            use-chain "handshake"
+            buffer-request-body #true
+            buffer-response-body #true
+            section "/ws" {
+                proxy "__UPSTREAM__"
+            }
+            section "/plain" {
+                allow-upgrades #false
+                proxy "__UPSTREAM__"
+            }
+        }
+    }
+}
+"#;
+
+const HANDSHAKE: &str = "Host: 127.0.0.1\r\n\
+    Connection: Upgrade\r\n\
+    Upgrade: websocket\r\n\
+    Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+    Sec-WebSocket-Version: 13\r\n\r\n";
+
+fn get_free_port() -> u16 {
+    let listener = StdTcpListener::bind("127.0.0.1:0").expect("Failed to bind to random port");
+    let port = listener.local_addr().unwrap().port();
+    drop(listener);
+    port
+}
+
+async fn read_head(stream: &mut TcpStream) -> String {
+    let mut head = Vec::new();
+    let mut byte = [0u8; 1];
+    while !head.ends_with(b"\r\n\r\n") {
+        if stream.read(&mut byte).await.expect("Failed to read head") == 0 {
+            break;
+        }
+        head.push(byte[0]);
+    }
+    String::from_utf8_lossy(&head).to_lowercase()
+}
+
+/// Switches protocols for WebSocket handshakes that went through the filters, then echoes
+/// whatever it receives. Anything else is told an upgrade is required.
+async fn echo_upstream() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+
+    tokio::spawn(async move {
+        loop {
+            let Ok((mut stream, _)) = listener.accept().await else {
+                return;
+            };
+            tokio::spawn(async move {
+                let head = read_head(&mut stream).await;
+
+                if !head.contains("upgrade: websocket") || !head.contains("x-handshake: checked") {
+                    let _ = stream
+                        .write_all(
+                            b"HTTP/1.1 426 Upgrade Required\r\n\
+                              Content-Length: 0\r\nConnection: close\r\n\r\n",
+                        )
+                        .await;
+                    return;
+                }
+
+                let _ = stream
+                    .write_all(
+                        b"HTTP/1.1 101 Switching Protocols\r\n\
+                          Upgrade: websocket\r\nConnection: Upgrade\r\n\r\n",
+                    )
+                    .await;
+
+                let mut buf = [0u8; 1024];
+                while let Ok(n @ 1..) = stream.read(&mut buf).await {
+                    if stream.write_all(&buf[..n]).await.is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+    });
+
+    addr
+}
+
+/// Starts the proxy, returning its port and the config file, which must outlive the test.
+async fn start_proxy() -> (u16, NamedTempFile) {
+    let upstream = echo_upstream().await;
+    let proxy_port = get_free_port();
+    let config_content = WEBSOCKET_CONFIG_TEMPLATE
+        .replace("__PROXY_PORT__", &proxy_port.to_string())
+        .replace("__UPSTREAM__", &upstream);
+
+    let mut config_file = NamedTempFile::new().expect("Failed to create temp config file");
+    write!(config_file, "{}", config_content).expect("Failed to write config content");
+
+    let cli = Cli {
+        validate_configs: false,
+        dump_config: false,
+        threads_per_service: None,
+        config_entry: Some(config_file.path().to_path_buf()),
+        profile: None,
+        max_config_size: None,
+        daemonize: false,
+        upgrade: false,
+        pidfile: None,
+        upgrade_socket: None,
+        command: None,
+    };
+
+    let mut app_ctx = AppContext::bootstrap(cli)
+        .await
+        .expect("Failed to bootstrap AppContext");
+    let services = app_ctx
+        .build_services()
+        .await
+        .expect("Failed to build services");
+
+    let (mut server, _watcher) = app_ctx.ready();
+    server.add_services(services);
+    server.bootstrap();
+
+    thread::spawn(move || {
+        server.run_forever();
+    });
+
+    for _ in 0..50 {
+        if TcpStream::connect(("127.0.0.1", proxy_port)).await.is_ok() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    (proxy_port, config_file)
+}
+
+async fn handshake(proxy_port: u16, path: &str) -> (TcpStream, String) {
+    let mut stream = TcpStream::connect(("127.0.0.1", proxy_port))
+        .await
+        .expect("Failed to connect to proxy");
+    stream
+        .write_all(format!("GET {path} HTTP/1.1\r\n{HANDSHAKE}").as_bytes())
+        .await
+        .unwrap();
+
+    let head = timeout(Duration::from_secs(5), read_head(&mut stream))
+        .await
+        .expect("Handshake response timed out");
+    (stream, head)
+}
+
+#[tokio::test]
+async fn test_upgrade_tunneled_past_body_buffering() {
+    let (proxy_port, _config_file) = start_proxy().await;
+
+    let (mut stream, head) = handshake(proxy_port, "/ws").await;
+    assert!(
+        head.starts_with("http/1.1 101"),
+        "unexpected response: {head}"
+    );
+
+    // both directions are buffered for plain requests, a tunnel must not wait for its end
+    for message in [&b"ping"[..], b"second message"] {
+        stream.write_all(message).await.unwrap();
+
+        let mut echoed = vec![0u8; message.len()];
+        timeout(Duration::from_secs(5), stream.read_exact(&mut echoed))
+            .await
+            .expect("Echo timed out, the tunnel is held back")
+            .unwrap();
+        assert_eq!(echoed, message);
+    }
+}
+
+#[tokio::test]
+async fn test_upgrade_refused_when_not_allowed() {
+    let (proxy_port, _config_file) = start_proxy().await;
+
+    let (_stream, head) = handshake(proxy_port, "/plain").await;
+    assert!(
+        head.starts_with("http/1.1 426"),
+        "unexpected response: {head}"
+    );
+}